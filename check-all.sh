@@ -66,6 +66,12 @@ echo "==================== ./ferros-test/test-macro-impl =======================
     cargo test
 )
 
+echo "============================ ./ferros-derive ================================="
+(
+    cd ferros-derive
+    cargo test
+)
+
 echo "==================== ./ferros-test/examples/minimal =========================="
 (
     export PATH="${armv7_toolchain_dir}/bin:${PATH}"
@@ -29,11 +29,18 @@
 use core::cell::{Cell, UnsafeCell};
 use core::fmt;
 use core::marker::PhantomData;
+use core::mem::{align_of, size_of};
 use core::ops::{Deref, DerefMut};
 use core::ptr;
-use core::sync::atomic::{self, AtomicUsize, Ordering};
+use core::sync::atomic::{self, AtomicBool, AtomicUsize, Ordering};
 
 /// A slot in a queue.
+///
+/// A slot is aligned to at least the alignment of `T`. To give slots
+/// a larger alignment, e.g. to keep elements that hold atomics on
+/// 8-byte boundaries or to keep each element on its own cache line,
+/// queue the element wrapped in `Align8`, `Align16` or `CachePadded`.
+#[repr(C)]
 pub struct Slot<T> {
     /// The current stamp.
     ///
@@ -48,7 +55,8 @@ pub struct Slot<T> {
 unsafe impl<T: Send> Send for ArrayQueue<T> {}
 unsafe impl<T: Send> Sync for ArrayQueue<T> {}
 
-#[repr(align(64))]
+/// Pads and aligns a value to the length of a cache line.
+#[repr(C, align(64))]
 pub struct CachePadded<T> {
     value: T,
 }
@@ -86,11 +94,114 @@ impl<T> DerefMut for CachePadded<T> {
     }
 }
 
+macro_rules! aligned_wrapper {
+    ($name:ident, $align:literal) => {
+        #[doc = concat!("Aligns a value to ", stringify!($align), " bytes.")]
+        #[repr(C, align($align))]
+        #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+        pub struct $name<T> {
+            value: T,
+        }
+
+        impl<T> $name<T> {
+            pub const fn new(t: T) -> $name<T> {
+                $name { value: t }
+            }
+
+            pub fn into_inner(self) -> T {
+                self.value
+            }
+        }
+
+        impl<T> Deref for $name<T> {
+            type Target = T;
+
+            fn deref(&self) -> &T {
+                &self.value
+            }
+        }
+
+        impl<T> DerefMut for $name<T> {
+            fn deref_mut(&mut self) -> &mut T {
+                &mut self.value
+            }
+        }
+    };
+}
+
+aligned_wrapper!(Align8, 8);
+aligned_wrapper!(Align16, 16);
+
+/// The size and alignment of a queue's slots, as the build that
+/// created the queue saw them.
+///
+/// A queue shared between processes is created by one build and used
+/// by others. If those builds disagree on the layout of the element
+/// type, elements are silently garbled on their way through, so each
+/// queue records the layout it was created with and `ArrayQueue`
+/// checks it against its own view with `check_layout`. In debug
+/// builds, `push` and `pop` check it as well.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SlotLayout {
+    pub size: usize,
+    pub align: usize,
+}
+
+impl SlotLayout {
+    pub const fn of<T>() -> SlotLayout {
+        SlotLayout {
+            size: size_of::<Slot<T>>(),
+            align: align_of::<Slot<T>>(),
+        }
+    }
+}
+
+/// Fails the build unless a queue element type has the given size and
+/// alignment.
+///
+/// Put it next to the definition of a type that crosses a process
+/// boundary, in a crate shared by both sides, so that a change to the
+/// type's layout, or a difference in how two builds lay it out, is a
+/// compile error rather than corruption at runtime. The type should be
+/// `#[repr(C)]` (or a primitive) for its layout to mean the same thing
+/// in every build.
+///
+/// ```
+/// use cross_queue::{assert_layout, Align8};
+///
+/// #[repr(C)]
+/// struct Frame {
+///     len: u16,
+///     buf: [u8; 14],
+/// }
+///
+/// assert_layout!(Frame, size = 16, align = 2);
+/// assert_layout!(Align8<Frame>, size = 16, align = 8);
+/// ```
+#[macro_export]
+macro_rules! assert_layout {
+    ($t:ty, size = $size:expr, align = $align:expr) => {
+        const _: () = {
+            assert!(
+                ::core::mem::size_of::<$t>() == $size,
+                concat!("unexpected size for ", stringify!($t))
+            );
+            assert!(
+                ::core::mem::align_of::<$t>() == $align,
+                concat!("unexpected alignment for ", stringify!($t))
+            );
+        };
+    };
+}
+
+#[repr(C, usize)]
 enum BufferAddress<T> {
     Direct(*mut Slot<T>),
     Offset(usize),
 }
 
+#[repr(C)]
 pub struct ArrayQueue<T> {
     /// The head of the queue.
     ///
@@ -122,6 +233,12 @@ pub struct ArrayQueue<T> {
     /// A stamp with the value of `{ lap: 1, index: 0 }`.
     one_lap: usize,
 
+    /// The slot layout of the build that created the queue.
+    slot_layout: SlotLayout,
+
+    /// Set once the queue's consumer has gone, see `close`.
+    closed: AtomicBool,
+
     /// Indicates that dropping an `ArrayQueue<T>` may drop elements
     /// of type `T`.
     _marker: PhantomData<T>,
@@ -156,6 +273,8 @@ impl<T> ArrayQueue<T> {
             one_lap,
             head: CachePadded::new(AtomicUsize::new(head)),
             tail: CachePadded::new(AtomicUsize::new(tail)),
+            slot_layout: SlotLayout::of::<T>(),
+            closed: AtomicBool::new(false),
             _marker: PhantomData,
         };
 
@@ -163,7 +282,30 @@ impl<T> ArrayQueue<T> {
         aq
     }
 
+    /// How far past the start of a queue its slots can start, when
+    /// they're laid out right after it with `new_at_ptr`: the size of
+    /// the queue itself, rounded up to the alignment of a slot.
+    pub const fn buffer_offset() -> usize {
+        let align = align_of::<Slot<T>>();
+        (size_of::<ArrayQueue<T>>() + align - 1) & !(align - 1)
+    }
+
+    /// How many bytes a queue of `cap` elements takes up when its
+    /// slots are laid out right after it with `new_at_ptr`.
+    pub const fn region_size(cap: usize) -> usize {
+        Self::buffer_offset() + cap * size_of::<Slot<T>>()
+    }
+
+    /// Initializes a queue at `ptr` whose slots start `buffer_offset`
+    /// bytes past it. Both must be suitably aligned, for the queue
+    /// and for a slot respectively.
     pub unsafe fn new_at_ptr(ptr: *mut ArrayQueue<T>, cap: usize, buffer_offset: usize) {
+        assert!(cap > 0, "capacity must be non-zero");
+        assert_eq!(
+            (ptr as usize + buffer_offset) % align_of::<Slot<T>>(),
+            0,
+            "queue buffer must be aligned for its slots"
+        );
         let q: &mut ArrayQueue<T> = &mut *ptr;
 
         q.cap = cap;
@@ -171,10 +313,44 @@ impl<T> ArrayQueue<T> {
         q.tail = CachePadded::new(AtomicUsize::new(0));
         q.buffer = BufferAddress::Offset(buffer_offset);
         q.one_lap = (cap + 1).next_power_of_two();
+        q.slot_layout = SlotLayout::of::<T>();
+        q.closed = AtomicBool::new(false);
 
         q.inititialize_stamps();
     }
 
+    /// Checks that the queue was created with the same slot layout as
+    /// this build has for `T`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cross_queue::{ArrayQueue, Slot, SlotLayout};
+    /// use core::mem::MaybeUninit;
+    ///
+    /// let mut buff = unsafe { MaybeUninit::<[Slot::<u32>;4]>::uninit().assume_init() };
+    /// let q = unsafe { ArrayQueue::new(4, &mut buff[0]) };
+    ///
+    /// assert_eq!(q.check_layout(), Ok(()));
+    /// assert_eq!(q.slot_layout(), SlotLayout::of::<u32>());
+    /// ```
+    pub fn check_layout(&self) -> Result<(), LayoutMismatch> {
+        let expected = SlotLayout::of::<T>();
+        if self.slot_layout == expected {
+            Ok(())
+        } else {
+            Err(LayoutMismatch {
+                expected,
+                found: self.slot_layout,
+            })
+        }
+    }
+
+    /// The slot layout the queue was created with
+    pub fn slot_layout(&self) -> SlotLayout {
+        self.slot_layout
+    }
+
     unsafe fn buffer(&self) -> *mut Slot<T> {
         match self.buffer {
             BufferAddress::Direct(p) => p,
@@ -210,6 +386,7 @@ impl<T> ArrayQueue<T> {
     /// assert_eq!(q.push(20), Err(PushError(20)));
     /// ```
     pub fn push(&self, value: T) -> Result<(), PushError<T>> {
+        debug_assert_eq!(self.check_layout(), Ok(()));
         let backoff = Backoff::new();
         let mut tail = self.tail.load(Ordering::Relaxed);
 
@@ -293,6 +470,7 @@ impl<T> ArrayQueue<T> {
     /// assert_eq!(q.pop(), Err(PopError));
     /// ```
     pub fn pop(&self) -> Result<T, PopError> {
+        debug_assert_eq!(self.check_layout(), Ok(()));
         let backoff = Backoff::new();
         let mut head = self.head.load(Ordering::Relaxed);
 
@@ -429,6 +607,32 @@ impl<T> ArrayQueue<T> {
         head.wrapping_add(self.one_lap) == tail
     }
 
+    /// Marks the queue as having no consumer any more, for its producers
+    /// to tell apart from one that's just full. Nothing stops elements
+    /// being pushed or popped after it's closed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cross_queue::{ArrayQueue, Slot};
+    /// use core::mem::MaybeUninit;
+    ///
+    /// let mut buff = unsafe { MaybeUninit::<[Slot::<usize>;1]>::uninit().assume_init() };
+    /// let q = unsafe { ArrayQueue::new(1, &mut buff[0]) };
+    ///
+    /// assert!(!q.is_closed());
+    /// q.close();
+    /// assert!(q.is_closed());
+    /// ```
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+    }
+
+    /// Returns `true` if the queue has been closed.
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
     /// Returns the number of elements in the queue.
     ///
     /// # Examples
@@ -508,6 +712,24 @@ impl fmt::Display for PopError {
     }
 }
 
+/// Error which occurs when a queue was created with a different slot
+/// layout than the one in use.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct LayoutMismatch {
+    pub expected: SlotLayout,
+    pub found: SlotLayout,
+}
+
+impl fmt::Display for LayoutMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "queue slots are {} bytes aligned to {}, expected {} bytes aligned to {}",
+            self.found.size, self.found.align, self.expected.size, self.expected.align
+        )
+    }
+}
+
 /// Error which occurs when pushing into a full queue.
 #[derive(Clone, Copy, Eq, PartialEq)]
 pub struct PushError<T>(pub T);
@@ -3,7 +3,7 @@ extern crate crossbeam_utils;
 
 use core::sync::atomic::{AtomicUsize, Ordering};
 
-use cross_queue::{ArrayQueue, Slot};
+use cross_queue::{Align16, Align8, ArrayQueue, CachePadded, Slot, SlotLayout};
 use crossbeam_utils::thread::scope;
 use rand::{thread_rng, Rng};
 use core::mem::MaybeUninit;
@@ -282,3 +282,78 @@ fn linearizable() {
     })
     .unwrap();
 }
+
+#[test]
+fn aligned_slots() {
+    assert_eq!(core::mem::align_of::<Slot<Align16<u8>>>(), 16);
+    assert_eq!(core::mem::align_of::<Slot<CachePadded<u8>>>(), 64);
+    assert_eq!(core::mem::size_of::<Slot<CachePadded<u8>>>(), 128);
+
+    let mut buff = unsafe { MaybeUninit::<[Slot::<Align16<u8>>;4]>::uninit().assume_init() };
+    let q = unsafe { ArrayQueue::new(4, &mut buff[0]) };
+    for i in 0..4 {
+        q.push(Align16::new(i)).unwrap();
+    }
+    for i in 0..4 {
+        let e = q.pop().unwrap();
+        assert_eq!(&*e as *const u8 as usize % 16, 0);
+        assert_eq!(e.into_inner(), i);
+    }
+}
+
+#[test]
+fn layout_mismatch() {
+    let mut buff = unsafe { MaybeUninit::<[Slot::<u8>;4]>::uninit().assume_init() };
+    let q = unsafe { ArrayQueue::new(4, &mut buff[0]) };
+    assert_eq!(q.check_layout(), Ok(()));
+
+    // The same queue, as seen by a build with a different element type
+    let other: &ArrayQueue<[u64; 2]> = unsafe { core::mem::transmute(&q) };
+    let mismatch = other.check_layout().unwrap_err();
+    assert_eq!(mismatch.expected, SlotLayout::of::<[u64; 2]>());
+    assert_eq!(mismatch.found, SlotLayout::of::<u8>());
+}
+
+#[test]
+fn new_at_ptr() {
+    const CAP: usize = 10;
+    type Element = Align8<u32>;
+
+    let mut region = Box::new(CachePadded::new([0u8; 4096]));
+    assert!(ArrayQueue::<Element>::region_size(CAP) <= region.len());
+    assert_eq!(
+        ArrayQueue::<Element>::buffer_offset() % core::mem::align_of::<Slot<Element>>(),
+        0
+    );
+
+    let q: &ArrayQueue<Element> = unsafe {
+        let ptr = region.as_mut_ptr() as *mut ArrayQueue<Element>;
+        ArrayQueue::new_at_ptr(ptr, CAP, ArrayQueue::<Element>::buffer_offset());
+        &*ptr
+    };
+    assert_eq!(q.capacity(), CAP);
+    for i in 0..CAP as u32 {
+        q.push(Align8::new(i)).unwrap();
+    }
+    assert!(q.is_full());
+    for i in 0..CAP as u32 {
+        assert_eq!(q.pop().map(Align8::into_inner), Ok(i));
+    }
+}
+
+#[test]
+fn close() {
+    let mut buff = unsafe { MaybeUninit::<[Slot::<usize>;2]>::uninit().assume_init() };
+    let q = unsafe { ArrayQueue::new(2, &mut buff[0]) };
+    assert!(!q.is_closed());
+
+    q.push(1).unwrap();
+    q.close();
+    assert!(q.is_closed());
+
+    // Closing only marks the queue, what's in it is still there
+    q.push(2).unwrap();
+    assert_eq!(q.pop(), Ok(1));
+    assert_eq!(q.pop(), Ok(2));
+    assert!(q.is_closed());
+}
@@ -1,7 +1,7 @@
 use ferros_build::*;
 use std::path::Path;
 
-fn main() {
+fn main() -> Result<(), CodegenError> {
     let out_dir = Path::new(&std::env::var_os("OUT_DIR").unwrap()).to_owned();
     let bin_dir = out_dir.join("..").join("..").join("..");
     let resources = out_dir.join("resources.rs");
@@ -11,7 +11,9 @@ fn main() {
         image_name: "hello-printer".to_owned(),
         type_name: "HelloPrinter".to_owned(),
         stack_size_bits: None,
+        reject_shared_segment_pages: false,
     };
 
-    embed_resources(&resources, vec![&hello as &dyn Resource]);
+    embed_resources(&resources, vec![&hello as &dyn Resource])?;
+    Ok(())
 }
@@ -1,16 +1,21 @@
 #![no_std]
 
+use config_service::{ConfigCaller, FeatureFlagsSizeBits};
 use ferros::cap::{role, CNodeRole};
-use ferros::userland::{Caller, InterruptConsumer, Producer, RetypeForSetup};
+use ferros::userland::{
+    BuildMetadata, Caller, Consumer1, Correlated, CpuStatsSizeBits, Producer, RetypeForSetup,
+    SequenceCounterSizeBits,
+};
 use ferros::vspace::{shared_status, MappedMemoryRegion};
 use imx6_hal::pac::{
     typenum::{op, U1, U12},
     uart1::{self, UART1},
+    wdog::wdog1::WDOG1,
 };
-use net_types::IpcUdpTransmitBuffer;
+use liveness::LivenessSizeBits;
+use net_types::{ControlRequest, ControlResponse, IpcUdpTransmitBuffer};
 
-/// Expected badge value on IRQ notifications
-pub type IrqBadgeBits = uart1::Irq;
+pub use uart1::IrqBadgeBits;
 
 /// 4K console buffer
 pub type ConsoleBufferSizeBits = U12;
@@ -21,8 +26,12 @@ pub struct ProcParams<Role: CNodeRole> {
     /// Console UART/serial
     pub uart: UART1,
 
-    /// Interrupt consumer for the console UART
-    pub int_consumer: InterruptConsumer<uart1::Irq, Role>,
+    /// Watchdog, for the reboot command
+    pub wdog: WDOG1,
+
+    /// Consumer of the console UART interrupts, in addition to the
+    /// TCP/IP driver's responses to management requests
+    pub int_consumer: Consumer1<Role, ControlResponse, uart1::Irq>,
 
     /// IPC to the storage driver
     pub storage_caller: Caller<
@@ -31,11 +40,32 @@ pub struct ProcParams<Role: CNodeRole> {
         Role,
     >,
 
+    /// IPC to the config service
+    pub config_caller: ConfigCaller<Role>,
+
     /// Producer of UDP messages destined to the TCP/IP driver
-    pub udp_producer: Producer<Role, IpcUdpTransmitBuffer>,
+    pub udp_producer: Producer<Role, Correlated<IpcUdpTransmitBuffer>>,
+
+    /// Producer of management requests destined to the TCP/IP driver
+    pub net_control_producer: Producer<Role, ControlRequest>,
 
     /// Console buffer memory
     pub console_buffer: MappedMemoryRegion<ConsoleBufferSizeBits, shared_status::Exclusive>,
+
+    /// The system-wide event sequence counter page, stamps log records
+    pub sequence_counter_mem: MappedMemoryRegion<SequenceCounterSizeBits, shared_status::Shared>,
+
+    /// The feature flags page, mapped read-only
+    pub feature_flags_mem: MappedMemoryRegion<FeatureFlagsSizeBits, shared_status::Shared>,
+
+    /// The per-core utilization stats page, mapped read-only
+    pub cpu_stats_mem: MappedMemoryRegion<CpuStatsSizeBits, shared_status::Shared>,
+
+    /// The liveness page, mapped read-only
+    pub liveness_mem: MappedMemoryRegion<LivenessSizeBits, shared_status::Shared>,
+
+    /// The root task's build metadata, checked against this process' own
+    pub root_build: BuildMetadata,
 }
 
 impl RetypeForSetup for ProcParams<role::Local> {
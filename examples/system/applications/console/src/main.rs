@@ -3,35 +3,59 @@
 
 use selfe_runtime as _;
 
+use ::liveness::LivenessPage;
+use config_service::{entry, ConfigCaller, FeatureFlags};
 use console::ProcParams;
 use core::fmt::{self, Write as WriteFmt};
 use debug_logger::DebugLogger;
 use ferros::{
     cap::role,
-    userland::{Caller, Producer},
+    userland::{
+        with_correlation_id, Caller, Correlated, CorrelationId, CpuStats, Producer, SequenceCounter,
+    },
 };
 use imx6_hal::embedded_hal::serial::Read;
-use imx6_hal::{pac::uart1::UART1, serial::Serial};
+use imx6_hal::{pac::uart1::UART1, serial::Serial, watchdog::Watchdog};
 use menu::*;
-use net_types::{EthernetFrameBuffer, IpcUdpTransmitBuffer};
+use net_types::{
+    CaptureConfig, CaptureFilter, ControlRequest, EthernetAddress, IpcUdpTransmitBuffer,
+    Ipv4Address,
+};
 
 static LOGGER: DebugLogger = DebugLogger;
 
+mod build_metadata {
+    include!(concat!(env!("OUT_DIR"), "/build_metadata.rs"));
+}
+
 #[allow(improper_ctypes_definitions)]
 #[no_mangle]
 pub extern "C" fn _start(params: ProcParams<role::Local>) -> ! {
     log::set_logger(&LOGGER)
         .map(|()| log::set_max_level(DebugLogger::max_log_level_from_env()))
         .unwrap();
+    DebugLogger::set_sequence_counter(SequenceCounter::from_region(params.sequence_counter_mem));
+    let feature_flags = FeatureFlags::from_region(params.feature_flags_mem);
+    DebugLogger::set_verbose_flag(feature_flags.flag::<entry::FlagVerboseTracing>());
 
     log::debug!("[console] Process started");
 
+    if let Err(skew) = build_metadata::BUILD_METADATA.check_skew(&params.root_build) {
+        log::warn!("[console] Built differently than the root task: {:?}", skew);
+    }
+
     let int_consumer = params.int_consumer;
     let serial = Serial::new(params.uart);
     let context = Context {
         serial,
+        watchdog: Watchdog::new(params.wdog),
         storage_caller: params.storage_caller,
+        config_caller: params.config_caller,
+        feature_flags,
+        cpu_stats: CpuStats::from_region(params.cpu_stats_mem),
+        liveness: LivenessPage::from_region(params.liveness_mem),
         udp_producer: params.udp_producer,
+        net_control_producer: params.net_control_producer,
     };
 
     let mut console_buffer_mem = params.console_buffer;
@@ -43,22 +67,41 @@ pub extern "C" fn _start(params: ProcParams<role::Local>) -> ! {
     // TODO - this info is only if running on QEMU, otherwise it's the UART1 serial
     // port
     log::info!("[console] Run 'telnet 0.0.0.0 8888' to connect to the console interface (QEMU)");
-    int_consumer.consume(state, move |mut state| {
-        if let Ok(b) = state.context.serial.read() {
-            state.input_byte(b);
-        }
-        state
-    })
+    int_consumer.consume(
+        state,
+        move |mut state| {
+            if let Ok(b) = state.context.serial.read() {
+                // Each command entered is a new action to correlate across processes
+                let correlation_id = match b {
+                    b'\r' | b'\n' => Some(CorrelationId::next()),
+                    _ => None,
+                };
+                with_correlation_id(correlation_id, || state.input_byte(b));
+            }
+            state
+        },
+        move |control_response, mut state| {
+            // Responses to the net sub-menu's management requests
+            writeln!(state.context.serial, "{}", control_response).unwrap();
+            state
+        },
+    )
 }
 
 pub struct Context {
     serial: Serial<UART1>,
+    watchdog: Watchdog,
     storage_caller: Caller<
         persistent_storage::Request,
         Result<persistent_storage::Response, persistent_storage::ErrorCode>,
         role::Local,
     >,
-    udp_producer: Producer<role::Local, IpcUdpTransmitBuffer>,
+    config_caller: ConfigCaller<role::Local>,
+    feature_flags: &'static FeatureFlags,
+    cpu_stats: &'static CpuStats,
+    liveness: &'static LivenessPage,
+    udp_producer: Producer<role::Local, Correlated<IpcUdpTransmitBuffer>>,
+    net_control_producer: Producer<role::Local, ControlRequest>,
 }
 
 impl fmt::Write for Context {
@@ -128,36 +171,196 @@ const ROOT_MENU: Menu<Context> = Menu {
                 exit: None,
             }),
         },
+        &Item {
+            command: "config",
+            help: Some("Enter the configuration sub-menu."),
+            item_type: ItemType::Menu(&Menu {
+                label: "config",
+                items: &[
+                    &Item {
+                        command: "list",
+                        help: Some(config::list::HELP),
+                        item_type: ItemType::Callback {
+                            function: config::list::cmd,
+                            parameters: &[],
+                        },
+                    },
+                    &Item {
+                        command: "get",
+                        help: Some(config::get::HELP),
+                        item_type: ItemType::Callback {
+                            function: config::get::cmd,
+                            parameters: &[Parameter::Mandatory {
+                                parameter_name: "key",
+                                help: Some("The entry's key name"),
+                            }],
+                        },
+                    },
+                    &Item {
+                        command: "set",
+                        help: Some(config::set::HELP),
+                        item_type: ItemType::Callback {
+                            function: config::set::cmd,
+                            parameters: &[
+                                Parameter::Mandatory {
+                                    parameter_name: "key",
+                                    help: Some("The entry's key name"),
+                                },
+                                Parameter::Mandatory {
+                                    parameter_name: "value",
+                                    help: Some("The entry's new value"),
+                                },
+                            ],
+                        },
+                    },
+                    &Item {
+                        command: "reset",
+                        help: Some(config::reset::HELP),
+                        item_type: ItemType::Callback {
+                            function: config::reset::cmd,
+                            parameters: &[Parameter::Mandatory {
+                                parameter_name: "key",
+                                help: Some("The entry's key name"),
+                            }],
+                        },
+                    },
+                    &Item {
+                        command: "flags",
+                        help: Some(config::flags::HELP),
+                        item_type: ItemType::Callback {
+                            function: config::flags::cmd,
+                            parameters: &[],
+                        },
+                    },
+                    &Item {
+                        command: "enable",
+                        help: Some(config::enable::HELP),
+                        item_type: ItemType::Callback {
+                            function: config::enable::cmd,
+                            parameters: &[Parameter::Mandatory {
+                                parameter_name: "flag",
+                                help: Some("The flag's key name"),
+                            }],
+                        },
+                    },
+                    &Item {
+                        command: "disable",
+                        help: Some(config::disable::HELP),
+                        item_type: ItemType::Callback {
+                            function: config::disable::cmd,
+                            parameters: &[Parameter::Mandatory {
+                                parameter_name: "flag",
+                                help: Some("The flag's key name"),
+                            }],
+                        },
+                    },
+                ],
+                entry: None,
+                exit: None,
+            }),
+        },
         &Item {
             command: "net",
             help: Some("Enter the network sub-menu."),
             item_type: ItemType::Menu(&Menu {
                 label: "net",
-                items: &[&Item {
-                    command: "sendto",
-                    help: Some(net::sendto::HELP),
-                    item_type: ItemType::Callback {
-                        function: net::sendto::cmd,
-                        parameters: &[
-                            Parameter::Mandatory {
-                                parameter_name: "addr",
-                                help: Some("The remote address"),
-                            },
-                            Parameter::Mandatory {
-                                parameter_name: "port",
-                                help: Some("The remote port number"),
-                            },
-                            Parameter::Mandatory {
-                                parameter_name: "data",
-                                help: Some("The data to send"),
-                            },
-                        ],
+                items: &[
+                    &Item {
+                        command: "sendto",
+                        help: Some(net::sendto::HELP),
+                        item_type: ItemType::Callback {
+                            function: net::sendto::cmd,
+                            parameters: &[
+                                Parameter::Mandatory {
+                                    parameter_name: "addr",
+                                    help: Some("The remote address"),
+                                },
+                                Parameter::Mandatory {
+                                    parameter_name: "port",
+                                    help: Some("The remote port number"),
+                                },
+                                Parameter::Mandatory {
+                                    parameter_name: "data",
+                                    help: Some("The data to send"),
+                                },
+                            ],
+                        },
+                    },
+                    &Item {
+                        command: "arp",
+                        help: Some(net::arp::HELP),
+                        item_type: ItemType::Callback {
+                            function: net::arp::cmd,
+                            parameters: &[],
+                        },
                     },
-                }],
+                    &Item {
+                        command: "arp-add",
+                        help: Some(net::arp_add::HELP),
+                        item_type: ItemType::Callback {
+                            function: net::arp_add::cmd,
+                            parameters: &[
+                                Parameter::Mandatory {
+                                    parameter_name: "addr",
+                                    help: Some("The neighbor's IPv4 address"),
+                                },
+                                Parameter::Mandatory {
+                                    parameter_name: "mac",
+                                    help: Some("The neighbor's MAC address"),
+                                },
+                            ],
+                        },
+                    },
+                    &Item {
+                        command: "capture",
+                        help: Some(net::capture::HELP),
+                        item_type: ItemType::Callback {
+                            function: net::capture::cmd,
+                            parameters: &[
+                                Parameter::Mandatory {
+                                    parameter_name: "filter",
+                                    help: Some("One of off, rx, tx or both"),
+                                },
+                                Parameter::Optional {
+                                    parameter_name: "every",
+                                    help: Some("Capture one out of every N frames (default 1)"),
+                                },
+                                Parameter::Optional {
+                                    parameter_name: "snaplen",
+                                    help: Some("Maximum bytes captured per frame"),
+                                },
+                            ],
+                        },
+                    },
+                ],
                 entry: None,
                 exit: None,
             }),
         },
+        &Item {
+            command: "top",
+            help: Some(top::HELP),
+            item_type: ItemType::Callback {
+                function: top::cmd,
+                parameters: &[],
+            },
+        },
+        &Item {
+            command: "liveness",
+            help: Some(liveness::HELP),
+            item_type: ItemType::Callback {
+                function: liveness::cmd,
+                parameters: &[],
+            },
+        },
+        &Item {
+            command: "reboot",
+            help: Some(reboot::HELP),
+            item_type: ItemType::Callback {
+                function: reboot::cmd,
+                parameters: &[],
+            },
+        },
     ],
     entry: Some(enter_root_menu),
     exit: None,
@@ -297,6 +500,322 @@ mod storage {
     }
 }
 
+mod config {
+    use super::*;
+    use config_service::{ConfigError, ConfigKey, ConfigValue, Request, Response};
+
+    fn print_resp(context: &mut Context, resp: &Result<Response, ConfigError>) {
+        match resp {
+            Ok(r) => writeln!(context.serial, "{}", r).unwrap(),
+            Err(e) => writeln!(context.serial, "{}", e).unwrap(),
+        }
+    }
+
+    fn call(context: &mut Context, request: Request) -> Result<Response, ConfigError> {
+        context
+            .config_caller
+            .blocking_call(&request)
+            .expect("Failed to perform a blocking_call")
+    }
+
+    fn find_key(item: &Item<Context>, args: &[&str], context: &mut Context) -> Option<ConfigKey> {
+        find_named_key(item, args, "key", context)
+    }
+
+    fn find_named_key(
+        item: &Item<Context>,
+        args: &[&str],
+        parameter_name: &str,
+        context: &mut Context,
+    ) -> Option<ConfigKey> {
+        let name = menu::argument_finder(item, args, parameter_name)
+            .unwrap()
+            .unwrap();
+        let key = ConfigKey::from_name(name);
+        if key.is_none() {
+            writeln!(context.serial, "Unknown config key '{}'", name).unwrap();
+        }
+        key
+    }
+
+    pub mod list {
+        use super::*;
+
+        pub const HELP: &str = "List every configuration entry and its value.
+
+  Example:
+  list";
+
+        pub fn cmd(
+            _menu: &Menu<Context>,
+            _item: &Item<Context>,
+            _args: &[&str],
+            context: &mut Context,
+        ) {
+            log::debug!("[console] List config entries");
+
+            for key in ConfigKey::ALL.iter().copied() {
+                let resp = call(context, Request::Get(key));
+                print_resp(context, &resp);
+            }
+        }
+    }
+
+    pub mod get {
+        use super::*;
+
+        pub const HELP: &str = "Retrieves the value of a configuration entry.
+
+  Example:
+  get net.ip-addr";
+
+        pub fn cmd(
+            _menu: &Menu<Context>,
+            item: &Item<Context>,
+            args: &[&str],
+            context: &mut Context,
+        ) {
+            let key = match find_key(item, args, context) {
+                Some(key) => key,
+                None => return,
+            };
+
+            log::debug!("[console] Get config entry key='{}'", key);
+
+            let resp = call(context, Request::Get(key));
+            print_resp(context, &resp);
+        }
+    }
+
+    pub mod set {
+        use super::*;
+
+        pub const HELP: &str = "Sets the value of a configuration entry.
+
+  The new value is stored and applied right away.
+
+  Example:
+  set net.ip-addr 192.0.2.81";
+
+        pub fn cmd(
+            _menu: &Menu<Context>,
+            item: &Item<Context>,
+            args: &[&str],
+            context: &mut Context,
+        ) {
+            let key = match find_key(item, args, context) {
+                Some(key) => key,
+                None => return,
+            };
+
+            let value = menu::argument_finder(item, args, "value").unwrap().unwrap();
+            let value = match key.parse_value(value) {
+                Ok(value) => value,
+                Err(e) => {
+                    writeln!(context.serial, "{}", e).unwrap();
+                    return;
+                }
+            };
+
+            log::debug!("[console] Set config entry key='{}' value='{}'", key, value);
+
+            let resp = call(context, Request::Set(key, value));
+            print_resp(context, &resp);
+        }
+    }
+
+    pub mod reset {
+        use super::*;
+
+        pub const HELP: &str = "Resets a configuration entry to its default value.
+
+  Example:
+  reset net.ip-addr";
+
+        pub fn cmd(
+            _menu: &Menu<Context>,
+            item: &Item<Context>,
+            args: &[&str],
+            context: &mut Context,
+        ) {
+            let key = match find_key(item, args, context) {
+                Some(key) => key,
+                None => return,
+            };
+
+            log::debug!("[console] Reset config entry key='{}'", key);
+
+            let resp = call(context, Request::Reset(key));
+            print_resp(context, &resp);
+        }
+    }
+
+    fn set_flag(item: &Item<Context>, args: &[&str], context: &mut Context, enabled: bool) {
+        let key = match find_named_key(item, args, "flag", context) {
+            Some(key) => key,
+            None => return,
+        };
+
+        log::debug!(
+            "[console] Set feature flag key='{}' enabled={}",
+            key,
+            enabled
+        );
+
+        let resp = call(context, Request::Set(key, ConfigValue::Bool(enabled)));
+        print_resp(context, &resp);
+    }
+
+    pub mod flags {
+        use super::*;
+
+        pub const HELP: &str = "List the feature flags, read from the shared flags page.
+
+  Example:
+  flags";
+
+        pub fn cmd(
+            _menu: &Menu<Context>,
+            _item: &Item<Context>,
+            _args: &[&str],
+            context: &mut Context,
+        ) {
+            for key in ConfigKey::ALL.iter().copied() {
+                if let ConfigValue::Bool(_) = key.default_value() {
+                    let enabled = context.feature_flags.get(key);
+                    writeln!(context.serial, "{} = {}", key, enabled).unwrap();
+                }
+            }
+        }
+    }
+
+    pub mod enable {
+        use super::*;
+
+        pub const HELP: &str = "Enables a feature flag.
+
+  Example:
+  enable flag.verbose-tracing";
+
+        pub fn cmd(
+            _menu: &Menu<Context>,
+            item: &Item<Context>,
+            args: &[&str],
+            context: &mut Context,
+        ) {
+            set_flag(item, args, context, true);
+        }
+    }
+
+    pub mod disable {
+        use super::*;
+
+        pub const HELP: &str = "Disables a feature flag.
+
+  Example:
+  disable flag.verbose-tracing";
+
+        pub fn cmd(
+            _menu: &Menu<Context>,
+            item: &Item<Context>,
+            args: &[&str],
+            context: &mut Context,
+        ) {
+            set_flag(item, args, context, false);
+        }
+    }
+}
+
+mod top {
+    use super::*;
+
+    pub const HELP: &str = "Show the approximate utilization of each core,
+  as last sampled by the root task's idle loop.
+
+  Example:
+  top";
+
+    pub fn cmd(
+        _menu: &Menu<Context>,
+        _item: &Item<Context>,
+        _args: &[&str],
+        context: &mut Context,
+    ) {
+        writeln!(context.serial, "core  busy    samples").unwrap();
+        for (core, stats) in context.cpu_stats.sampled_cores() {
+            let busy = stats.busy_permille();
+            writeln!(
+                context.serial,
+                "{:<5} {:>3}.{}%  {}",
+                core,
+                busy / 10,
+                busy % 10,
+                stats.samples()
+            )
+            .unwrap();
+        }
+    }
+}
+
+mod liveness {
+    use super::*;
+    use ::liveness::system::SIGNALS;
+
+    pub const HELP: &str = "Show each process' liveness signals, with how
+  many times each has been found overdue by the root task.
+
+  Example:
+  liveness";
+
+    pub fn cmd(
+        _menu: &Menu<Context>,
+        _item: &Item<Context>,
+        _args: &[&str],
+        context: &mut Context,
+    ) {
+        writeln!(
+            context.serial,
+            "process  signal       kind       period  count       pending  overdue"
+        )
+        .unwrap();
+        for signal in SIGNALS {
+            let metrics = context.liveness.metrics(*signal);
+            writeln!(
+                context.serial,
+                "{:<8} {:<12} {:<10} {:>4}ms  {:<11} {:<8} {}",
+                signal.process,
+                signal.name,
+                signal.kind,
+                signal.period_ms,
+                metrics.count,
+                metrics.pending,
+                metrics.overdue
+            )
+            .unwrap();
+        }
+    }
+}
+
+mod reboot {
+    use super::*;
+
+    pub const HELP: &str = "Restart the system.
+
+  Example:
+  reboot";
+
+    pub fn cmd(
+        _menu: &Menu<Context>,
+        _item: &Item<Context>,
+        _args: &[&str],
+        context: &mut Context,
+    ) {
+        log::info!("[console] Rebooting");
+        writeln!(context.serial, "Rebooting...").unwrap();
+        context.watchdog.reset_system();
+    }
+}
+
 mod net {
     use super::*;
 
@@ -315,25 +834,35 @@ mod net {
             context: &mut Context,
         ) {
             let addr = menu::argument_finder(item, args, "addr").unwrap().unwrap();
-            let mut addr_octets = [0_u8; 4];
-            for (idx, part) in addr.split('.').into_iter().enumerate() {
-                addr_octets[idx] = part.parse().unwrap();
-            }
+            let dst_addr = match parse_ipv4_address(addr) {
+                Some(dst_addr) => dst_addr,
+                None => {
+                    writeln!(context.serial, "Invalid IPv4 address '{}'", addr).unwrap();
+                    return;
+                }
+            };
 
             let port = menu::argument_finder(item, args, "port").unwrap().unwrap();
-            let port: u16 = port.parse().unwrap();
+            let dst_port: u16 = match port.parse() {
+                Ok(dst_port) => dst_port,
+                Err(_) => {
+                    writeln!(context.serial, "Invalid port '{}'", port).unwrap();
+                    return;
+                }
+            };
 
             let data = menu::argument_finder(item, args, "data").unwrap().unwrap();
-            let data_bytes = data.as_bytes();
-            let data_len = data_bytes.len();
-
-            let mut msg = IpcUdpTransmitBuffer {
-                dst_addr: addr_octets.into(),
-                dst_port: port.into(),
-                frame: EthernetFrameBuffer::new(),
-            };
-            msg.frame.truncate(data_len);
-            msg.frame.as_mut_slice().copy_from_slice(data_bytes);
+            let msg: IpcUdpTransmitBuffer =
+                match IpcUdpTransmitBuffer::builder(dst_addr, dst_port.into())
+                    .payload(data.as_bytes())
+                    .build()
+                {
+                    Ok(msg) => msg,
+                    Err(e) => {
+                        writeln!(context.serial, "{}", e).unwrap();
+                        return;
+                    }
+                };
 
             log::debug!(
                 "[console] Send UDP message to {}:{} data='{}'",
@@ -342,9 +871,171 @@ mod net {
                 data
             );
 
-            if context.udp_producer.send(msg).is_err() {
+            if context.udp_producer.send(Correlated::current(msg)).is_err() {
                 log::warn!("[console] Rejected sending IpcUdpTransmitBuffer data to TCP/IP driver");
             }
         }
     }
+    pub mod capture {
+        use super::*;
+
+        pub const HELP: &str = "Configure the TCP/IP driver's frame capture tap.
+
+  Captured frames are emitted in pcap format on the debug serial
+  output, see scripts/pcap-from-log.py.
+
+  Example:
+  capture both
+  capture rx 10 128
+  capture off";
+
+        pub fn cmd(
+            _menu: &Menu<Context>,
+            item: &Item<Context>,
+            args: &[&str],
+            context: &mut Context,
+        ) {
+            let filter = match menu::argument_finder(item, args, "filter")
+                .unwrap()
+                .unwrap()
+            {
+                "off" => CaptureFilter::Off,
+                "rx" => CaptureFilter::Rx,
+                "tx" => CaptureFilter::Tx,
+                "both" => CaptureFilter::Both,
+                other => {
+                    writeln!(context.serial, "Unknown capture filter '{}'", other).unwrap();
+                    return;
+                }
+            };
+
+            let mut config = CaptureConfig::off();
+            config.filter = filter;
+
+            if let Some(every) = menu::argument_finder(item, args, "every").unwrap() {
+                match every.parse() {
+                    Ok(every) => config.sample_every = every,
+                    Err(_) => {
+                        writeln!(context.serial, "Invalid sample count '{}'", every).unwrap();
+                        return;
+                    }
+                }
+            }
+
+            if let Some(snap_len) = menu::argument_finder(item, args, "snaplen").unwrap() {
+                match snap_len.parse::<u16>() {
+                    Ok(snap_len) if snap_len <= config.snap_len => config.snap_len = snap_len,
+                    _ => {
+                        writeln!(
+                            context.serial,
+                            "Invalid snap length '{}', must be at most {}",
+                            snap_len, config.snap_len
+                        )
+                        .unwrap();
+                        return;
+                    }
+                }
+            }
+
+            log::debug!("[console] Configure {}", config);
+
+            send_control_request(context, ControlRequest::Capture(config));
+        }
+    }
+
+    pub mod arp {
+        use super::*;
+
+        pub const HELP: &str = "List the TCP/IP driver's neighbor (ARP) table.
+
+  Example:
+  arp";
+
+        pub fn cmd(
+            _menu: &Menu<Context>,
+            _item: &Item<Context>,
+            _args: &[&str],
+            context: &mut Context,
+        ) {
+            log::debug!("[console] List neighbors");
+
+            send_control_request(context, ControlRequest::ListNeighbors);
+        }
+    }
+
+    pub mod arp_add {
+        use super::*;
+
+        pub const HELP: &str = "Add a static neighbor (ARP) entry to the TCP/IP driver.
+
+  Example:
+  arp-add 192.0.2.2 00:00:5e:01:23:ff";
+
+        pub fn cmd(
+            _menu: &Menu<Context>,
+            item: &Item<Context>,
+            args: &[&str],
+            context: &mut Context,
+        ) {
+            let addr = menu::argument_finder(item, args, "addr").unwrap().unwrap();
+            let ip_addr = match parse_ipv4_address(addr) {
+                Some(ip_addr) => ip_addr,
+                None => {
+                    writeln!(context.serial, "Invalid IPv4 address '{}'", addr).unwrap();
+                    return;
+                }
+            };
+
+            let mac = menu::argument_finder(item, args, "mac").unwrap().unwrap();
+            let mac_addr = match parse_ethernet_address(mac) {
+                Some(mac_addr) => mac_addr,
+                None => {
+                    writeln!(context.serial, "Invalid MAC address '{}'", mac).unwrap();
+                    return;
+                }
+            };
+
+            log::debug!("[console] Add static neighbor {} {}", ip_addr, mac_addr);
+
+            send_control_request(
+                context,
+                ControlRequest::AddStaticNeighbor(ip_addr, mac_addr),
+            );
+        }
+    }
+
+    /// The TCP/IP driver's response is printed once it arrives
+    fn send_control_request(context: &mut Context, request: ControlRequest) {
+        if context.net_control_producer.send(request).is_err() {
+            log::warn!("[console] Rejected sending ControlRequest to TCP/IP driver");
+        }
+    }
+
+    fn parse_ipv4_address(s: &str) -> Option<Ipv4Address> {
+        let mut octets = [0_u8; 4];
+        let mut parts = s.split('.');
+        for octet in octets.iter_mut() {
+            *octet = parts.next()?.parse().ok()?;
+        }
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(Ipv4Address(octets))
+    }
+
+    fn parse_ethernet_address(s: &str) -> Option<EthernetAddress> {
+        let mut octets = [0_u8; 6];
+        let mut parts = s.split(':');
+        for octet in octets.iter_mut() {
+            let part = parts.next()?;
+            if part.len() != 2 {
+                return None;
+            }
+            *octet = u8::from_str_radix(part, 16).ok()?;
+        }
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(EthernetAddress(octets))
+    }
 }
@@ -0,0 +1,56 @@
+//! Classic libpcap file format, emitted as hex encoded lines on the
+//! debug serial output
+//!
+//! Each line is `PCAP <hex>`, the first one holding the global header and
+//! every following one a record header plus the captured frame bytes.
+//! scripts/pcap-from-log.py turns a serial log back into a .pcap file.
+
+use core::fmt;
+use net_types::CapturedFrame;
+
+pub const LINE_PREFIX: &str = "PCAP";
+
+pub const MAGIC: u32 = 0xA1B2_C3D4;
+pub const VERSION_MAJOR: u16 = 2;
+pub const VERSION_MINOR: u16 = 4;
+pub const SNAP_LEN: u32 = 65535;
+pub const LINKTYPE_ETHERNET: u32 = 1;
+
+pub const GLOBAL_HEADER_SIZE: usize = 24;
+pub const RECORD_HEADER_SIZE: usize = 16;
+
+pub fn global_header() -> [u8; GLOBAL_HEADER_SIZE] {
+    let mut hdr = [0; GLOBAL_HEADER_SIZE];
+    hdr[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+    hdr[4..6].copy_from_slice(&VERSION_MAJOR.to_le_bytes());
+    hdr[6..8].copy_from_slice(&VERSION_MINOR.to_le_bytes());
+    // thiszone and sigfigs are left zero
+    hdr[16..20].copy_from_slice(&SNAP_LEN.to_le_bytes());
+    hdr[20..24].copy_from_slice(&LINKTYPE_ETHERNET.to_le_bytes());
+    hdr
+}
+
+pub fn record_header<const N: usize>(frame: &CapturedFrame<N>) -> [u8; RECORD_HEADER_SIZE] {
+    let ts_sec = (frame.timestamp_ms / 1000) as u32;
+    let ts_usec = ((frame.timestamp_ms % 1000) * 1000) as u32;
+    let mut hdr = [0; RECORD_HEADER_SIZE];
+    hdr[0..4].copy_from_slice(&ts_sec.to_le_bytes());
+    hdr[4..8].copy_from_slice(&ts_usec.to_le_bytes());
+    hdr[8..12].copy_from_slice(&(frame.frame.len() as u32).to_le_bytes());
+    hdr[12..16].copy_from_slice(&(frame.orig_len as u32).to_le_bytes());
+    hdr
+}
+
+/// Lower case hex encoding of a sequence of byte slices
+pub struct Hex<'a>(pub &'a [&'a [u8]]);
+
+impl<'a> fmt::Display for Hex<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for bytes in self.0 {
+            for b in bytes.iter() {
+                write!(f, "{:02x}", b)?;
+            }
+        }
+        Ok(())
+    }
+}
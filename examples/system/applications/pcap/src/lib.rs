@@ -0,0 +1,22 @@
+#![no_std]
+
+use ferros::cap::{role, CNodeRole};
+use ferros::userland::{BuildMetadata, Consumer1, RetypeForSetup};
+use net_types::IpcCapturedFrame;
+
+mod format;
+
+pub use crate::format::*;
+
+#[repr(C)]
+pub struct ProcParams<Role: CNodeRole> {
+    /// Consumer of the frames sampled by the TCP/IP driver's capture tap
+    pub frame_consumer: Consumer1<Role, IpcCapturedFrame>,
+
+    /// The root task's build metadata, checked against this process' own
+    pub root_build: BuildMetadata,
+}
+
+impl RetypeForSetup for ProcParams<role::Local> {
+    type Output = ProcParams<role::Child>;
+}
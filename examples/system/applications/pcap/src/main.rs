@@ -0,0 +1,49 @@
+#![no_std]
+#![no_main]
+
+use selfe_runtime as _;
+
+use debug_logger::DebugLogger;
+use ferros::cap::role;
+use ferros::debug_println;
+use pcap::{global_header, record_header, Hex, ProcParams, LINE_PREFIX};
+
+static LOGGER: DebugLogger = DebugLogger;
+
+mod build_metadata {
+    include!(concat!(env!("OUT_DIR"), "/build_metadata.rs"));
+}
+
+#[allow(improper_ctypes_definitions)]
+#[no_mangle]
+pub extern "C" fn _start(params: ProcParams<role::Local>) -> ! {
+    log::set_logger(&LOGGER)
+        .map(|()| log::set_max_level(DebugLogger::max_log_level_from_env()))
+        .unwrap();
+
+    log::debug!("[pcap] Process started");
+
+    if let Err(skew) = build_metadata::BUILD_METADATA.check_skew(&params.root_build) {
+        log::warn!("[pcap] Built differently than the root task: {:?}", skew);
+    }
+
+    // The capture output bypasses the log level filtering
+    debug_println!("{} {}", LINE_PREFIX, Hex(&[&global_header()]));
+
+    params.frame_consumer.consume(
+        (),
+        |state| {
+            // No IRQ or waker associated with this consumer
+            state
+        },
+        |captured, state| {
+            log::trace!("[pcap] Processing {}", captured);
+            debug_println!(
+                "{} {}",
+                LINE_PREFIX,
+                Hex(&[&record_header(&captured), captured.frame.as_slice()])
+            );
+            state
+        },
+    );
+}
@@ -0,0 +1,25 @@
+#![no_std]
+
+use ferros::cap::{role, CNodeRole};
+use ferros::userland::{BuildMetadata, Consumer1, Producer, RetypeForSetup};
+use net_types::{IpcUdpReceiveBuffer, IpcUdpTransmitBuffer};
+
+mod protocol;
+
+pub use crate::protocol::*;
+
+#[repr(C)]
+pub struct ProcParams<Role: CNodeRole> {
+    /// Consumer of UDP datagrams received on the TCP/IP driver's service port
+    pub udp_consumer: Consumer1<Role, IpcUdpReceiveBuffer>,
+
+    /// Producer of UDP datagrams sent from the TCP/IP driver's service port
+    pub udp_producer: Producer<Role, IpcUdpTransmitBuffer>,
+
+    /// The root task's build metadata, checked against this process' own
+    pub root_build: BuildMetadata,
+}
+
+impl RetypeForSetup for ProcParams<role::Local> {
+    type Output = ProcParams<role::Child>;
+}
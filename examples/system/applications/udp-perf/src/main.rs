@@ -0,0 +1,120 @@
+#![no_std]
+#![no_main]
+
+use selfe_runtime as _;
+
+use debug_logger::DebugLogger;
+use ferros::{cap::role, userland::Producer};
+use net_types::{IpcUdpReceiveBuffer, IpcUdpTransmitBuffer};
+use udp_perf::{Header, Kind, ProcParams, Session, Stats, HEADER_SIZE, REPORT_SIZE};
+
+static LOGGER: DebugLogger = DebugLogger;
+
+mod build_metadata {
+    include!(concat!(env!("OUT_DIR"), "/build_metadata.rs"));
+}
+
+#[allow(improper_ctypes_definitions)]
+#[no_mangle]
+pub extern "C" fn _start(params: ProcParams<role::Local>) -> ! {
+    log::set_logger(&LOGGER)
+        .map(|()| log::set_max_level(DebugLogger::max_log_level_from_env()))
+        .unwrap();
+
+    log::debug!("[udp-perf] Process started");
+
+    if let Err(skew) = build_metadata::BUILD_METADATA.check_skew(&params.root_build) {
+        log::warn!("[udp-perf] Built differently than the root task: {:?}", skew);
+    }
+
+    let initial_state = State {
+        session: Session::default(),
+        udp_producer: params.udp_producer,
+    };
+
+    params.udp_consumer.consume(
+        initial_state,
+        |state| {
+            // No IRQ or waker associated with this consumer
+            state
+        },
+        |udp_rx, mut state| {
+            log::trace!("[udp-perf] Processing {}", udp_rx);
+            state.handle_udp_rx_buffer(udp_rx);
+            state
+        },
+    );
+}
+
+struct State {
+    session: Session,
+    udp_producer: Producer<role::Local, IpcUdpTransmitBuffer>,
+}
+
+impl State {
+    fn handle_udp_rx_buffer(&mut self, udp_rx: IpcUdpReceiveBuffer) {
+        match Header::parse(udp_rx.frame.as_slice()) {
+            Some(Header {
+                kind: Kind::Data,
+                seq,
+            }) => self.session.record(seq, udp_rx.frame.len()),
+            Some(Header {
+                kind: Kind::ReportRequest,
+                seq,
+            }) => {
+                let stats = self.session.take_stats();
+                log::info!(
+                    "[udp-perf] Report for {}:{} {}",
+                    udp_rx.src_addr,
+                    udp_rx.src_port,
+                    stats
+                );
+                self.send_report(&udp_rx, seq, &stats);
+            }
+            Some(Header {
+                kind: Kind::Report,
+                ..
+            }) => log::warn!("[udp-perf] Ignoring unexpected report message"),
+            None => self.echo(udp_rx),
+        }
+    }
+
+    fn send_report(&self, udp_rx: &IpcUdpReceiveBuffer, seq: u32, stats: &Stats) {
+        let mut buf = [0_u8; REPORT_SIZE];
+        Header {
+            kind: Kind::Report,
+            seq,
+        }
+        .emit(&mut buf[..HEADER_SIZE]);
+        stats.emit(&mut buf[HEADER_SIZE..]);
+
+        let msg: IpcUdpTransmitBuffer =
+            match IpcUdpTransmitBuffer::builder(udp_rx.src_addr, udp_rx.src_port)
+                .payload(&buf)
+                .build()
+            {
+                Ok(msg) => msg,
+                Err(e) => {
+                    log::warn!("[udp-perf] Failed to build report, {}", e);
+                    return;
+                }
+            };
+
+        if self.udp_producer.send(msg).is_err() {
+            log::warn!("[udp-perf] Rejected sending report to TCP/IP driver");
+        }
+    }
+
+    fn echo(&self, udp_rx: IpcUdpReceiveBuffer) {
+        let msg = IpcUdpTransmitBuffer {
+            dst_addr: udp_rx.src_addr,
+            dst_port: udp_rx.src_port,
+            checksum: None,
+            frame: udp_rx.frame,
+        };
+
+        if self.udp_producer.send(msg).is_err() {
+            log::warn!("[udp-perf] Rejected sending echo reply to TCP/IP driver");
+        }
+    }
+}
@@ -0,0 +1,145 @@
+//! The wire format shared with the host peer (scripts/udp-perf.py)
+//!
+//! Every perf message starts with a fixed size header, all fields big-endian:
+//!
+//! ```text
+//! | magic: u32 | kind: u8 | reserved: [u8; 3] | seq: u32 |
+//! ```
+//!
+//! Datagrams that don't start with a valid header are echoed back verbatim.
+
+use core::convert::TryInto;
+use core::fmt;
+
+pub const MAGIC: u32 = 0x4645_5250;
+
+pub const HEADER_SIZE: usize = 12;
+
+/// Size of a `Report` message, the header followed by the `Stats` fields
+pub const REPORT_SIZE: usize = HEADER_SIZE + Stats::SIZE;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[repr(u8)]
+pub enum Kind {
+    /// Throughput payload, accounted for but not replied to
+    Data = 1,
+    /// Request a `Report` of the stats since the previous report
+    ReportRequest = 2,
+    /// The stats, sent in response to a `ReportRequest`
+    Report = 3,
+}
+
+impl Kind {
+    fn from_u8(kind: u8) -> Option<Self> {
+        match kind {
+            1 => Some(Kind::Data),
+            2 => Some(Kind::ReportRequest),
+            3 => Some(Kind::Report),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Header {
+    pub kind: Kind,
+    pub seq: u32,
+}
+
+impl Header {
+    /// Returns `None` if the data isn't a perf message
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < HEADER_SIZE {
+            return None;
+        }
+
+        if u32::from_be_bytes(data[0..4].try_into().ok()?) != MAGIC {
+            return None;
+        }
+
+        Some(Header {
+            kind: Kind::from_u8(data[4])?,
+            seq: u32::from_be_bytes(data[8..12].try_into().ok()?),
+        })
+    }
+
+    /// Panics if `buf` is smaller than `HEADER_SIZE`
+    pub fn emit(&self, buf: &mut [u8]) {
+        buf[0..4].copy_from_slice(&MAGIC.to_be_bytes());
+        buf[4] = self.kind as u8;
+        buf[5..8].fill(0);
+        buf[8..12].copy_from_slice(&self.seq.to_be_bytes());
+    }
+}
+
+/// Receive side accounting of a throughput run
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Stats {
+    /// Number of `Data` datagrams received
+    pub datagrams: u32,
+    /// Number of UDP payload bytes received, including headers
+    pub bytes: u32,
+    /// Number of sequence numbers skipped and not (yet) received
+    pub lost: u32,
+    /// Number of datagrams that arrived after a later sequence number
+    pub reordered: u32,
+}
+
+impl Stats {
+    pub const SIZE: usize = 16;
+
+    /// Panics if `buf` is smaller than `Stats::SIZE`
+    pub fn emit(&self, buf: &mut [u8]) {
+        buf[0..4].copy_from_slice(&self.datagrams.to_be_bytes());
+        buf[4..8].copy_from_slice(&self.bytes.to_be_bytes());
+        buf[8..12].copy_from_slice(&self.lost.to_be_bytes());
+        buf[12..16].copy_from_slice(&self.reordered.to_be_bytes());
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "datagrams={} bytes={} lost={} reordered={}",
+            self.datagrams, self.bytes, self.lost, self.reordered
+        )
+    }
+}
+
+/// Tracks the sequence numbers of a throughput run
+#[derive(Debug, Default)]
+pub struct Session {
+    next_seq: u32,
+    stats: Stats,
+}
+
+impl Session {
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    /// Account for a `Data` datagram, a sequence number of zero starts a new run
+    pub fn record(&mut self, seq: u32, len: usize) {
+        if seq == 0 {
+            *self = Session::default();
+        }
+
+        self.stats.datagrams = self.stats.datagrams.wrapping_add(1);
+        self.stats.bytes = self.stats.bytes.wrapping_add(len as u32);
+
+        if seq >= self.next_seq {
+            self.stats.lost = self.stats.lost.saturating_add(seq - self.next_seq);
+            self.next_seq = seq.wrapping_add(1);
+        } else {
+            // Late arrival of a sequence number previously counted as lost
+            self.stats.reordered = self.stats.reordered.saturating_add(1);
+            self.stats.lost = self.stats.lost.saturating_sub(1);
+        }
+    }
+
+    /// Take the stats accumulated since the previous report
+    pub fn take_stats(&mut self) -> Stats {
+        core::mem::take(&mut self.stats)
+    }
+}
@@ -0,0 +1,44 @@
+#![no_std]
+
+use ferros::cap::{role, CNodeRole};
+use ferros::userland::{Consumer2, Producer, RetypeForSetup};
+use ferros::vspace::{shared_status, MappedMemoryRegion};
+use net_types::{FrameHandle, FramePoolMemSizeBits};
+use static_assertions::const_assert;
+use typenum::{op, Unsigned, U1, U12};
+
+mod mac_table;
+
+pub use crate::mac_table::*;
+
+/// A single 4K page holds the MAC table
+pub type MacTableSizeBits = U12;
+pub type MacTableSize = op!(U1 << MacTableSizeBits);
+const_assert!(core::mem::size_of::<MacTable>() <= MacTableSize::USIZE);
+
+#[repr(C)]
+pub struct ProcParams<Role: CNodeRole> {
+    /// Consumer of Ethernet frames received by the L2 drivers of
+    /// port A and port B respectively
+    pub consumer: Consumer2<Role, FrameHandle, FrameHandle>,
+
+    /// Producer of Ethernet frames destined to the port A L2 driver
+    pub port_a_producer: Producer<Role, FrameHandle>,
+
+    /// Producer of Ethernet frames destined to the port B L2 driver
+    pub port_b_producer: Producer<Role, FrameHandle>,
+
+    /// The Ethernet frame pool shared with both L2 drivers, forwarded
+    /// frames stay in place
+    pub frame_pool_mem: MappedMemoryRegion<FramePoolMemSizeBits, shared_status::Shared>,
+
+    /// Memory for the learning MAC table.
+    ///
+    /// The table is laid out in place (`#[repr(C)]`) so a monitoring
+    /// process can be given a mapping of the same region.
+    pub mac_table_mem: MappedMemoryRegion<MacTableSizeBits, shared_status::Exclusive>,
+}
+
+impl RetypeForSetup for ProcParams<role::Local> {
+    type Output = ProcParams<role::Child>;
+}
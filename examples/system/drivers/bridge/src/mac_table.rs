@@ -0,0 +1,200 @@
+use core::fmt;
+use net_types::EthernetAddress;
+
+/// Maximum number of MAC addresses the bridge can learn
+pub const MAX_MAC_ENTRIES: usize = 128;
+
+/// Entries not refreshed within this many forwarded frames are aged out.
+///
+/// The bridge has no time domain of its own, the frames it processes
+/// serve as its clock.
+pub const MAC_ENTRY_MAX_AGE: u64 = 1 << 16;
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+#[repr(u8)]
+pub enum PortId {
+    A = 0,
+    B = 1,
+}
+
+impl PortId {
+    pub fn other(self) -> Self {
+        match self {
+            PortId::A => PortId::B,
+            PortId::B => PortId::A,
+        }
+    }
+}
+
+impl fmt::Display for PortId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PortId::A => f.write_str("A"),
+            PortId::B => f.write_str("B"),
+        }
+    }
+}
+
+/// What to do with a frame received on a port
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Forwarding {
+    /// The destination was learned on the given (other) port
+    Forward(PortId),
+    /// Broadcast, multicast or not yet learned destination
+    Flood,
+    /// The destination was learned on the receiving port, it's
+    /// already been seen by its segment
+    Filter,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[repr(C)]
+pub struct MacEntry {
+    pub last_seen: u64,
+    pub addr: EthernetAddress,
+    pub port: PortId,
+    pub in_use: bool,
+}
+
+impl MacEntry {
+    const EMPTY: MacEntry = MacEntry {
+        last_seen: 0,
+        addr: EthernetAddress([0; 6]),
+        port: PortId::A,
+        in_use: false,
+    };
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[repr(C)]
+pub struct BridgeStats {
+    pub forwarded: u64,
+    pub flooded: u64,
+    pub filtered: u64,
+    /// Frames rejected by a full egress queue
+    pub dropped: u64,
+}
+
+impl fmt::Display for BridgeStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "forwarded={} flooded={} filtered={} dropped={}",
+            self.forwarded, self.flooded, self.filtered, self.dropped
+        )
+    }
+}
+
+/// A learning MAC address table, laid out to live in a memory region
+#[repr(C)]
+pub struct MacTable {
+    /// Number of frames processed, the table's clock
+    pub clock: u64,
+    pub stats: BridgeStats,
+    pub entries: [MacEntry; MAX_MAC_ENTRIES],
+}
+
+impl MacTable {
+    /// Initialize a table at the start of the given memory
+    ///
+    /// Panics if `mem` is too small or not suitably aligned for a `MacTable`.
+    pub fn init_in(mem: &mut [u8]) -> &mut MacTable {
+        assert!(mem.len() >= core::mem::size_of::<MacTable>());
+        let table = mem.as_mut_ptr() as *mut MacTable;
+        assert_eq!(table as usize % core::mem::align_of::<MacTable>(), 0);
+        unsafe {
+            table.write(MacTable {
+                clock: 0,
+                stats: BridgeStats::default(),
+                entries: [MacEntry::EMPTY; MAX_MAC_ENTRIES],
+            });
+            &mut *table
+        }
+    }
+
+    /// Learn the frame's source address and decide where it goes
+    pub fn process(
+        &mut self,
+        ingress: PortId,
+        src: EthernetAddress,
+        dst: EthernetAddress,
+    ) -> Forwarding {
+        self.clock = self.clock.wrapping_add(1);
+
+        if is_unicast(&src) {
+            self.learn(ingress, src);
+        }
+
+        let forwarding = if !is_unicast(&dst) {
+            Forwarding::Flood
+        } else {
+            match self.lookup(&dst) {
+                Some(port) if port == ingress => Forwarding::Filter,
+                Some(port) => Forwarding::Forward(port),
+                None => Forwarding::Flood,
+            }
+        };
+
+        match forwarding {
+            Forwarding::Forward(_) => self.stats.forwarded += 1,
+            Forwarding::Flood => self.stats.flooded += 1,
+            Forwarding::Filter => self.stats.filtered += 1,
+        }
+
+        forwarding
+    }
+
+    pub fn lookup(&self, addr: &EthernetAddress) -> Option<PortId> {
+        self.entries
+            .iter()
+            .find(|e| e.in_use && e.addr == *addr && !self.is_expired(e))
+            .map(|e| e.port)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &MacEntry> {
+        self.entries
+            .iter()
+            .filter(move |e| e.in_use && !self.is_expired(e))
+    }
+
+    fn learn(&mut self, port: PortId, addr: EthernetAddress) {
+        let clock = self.clock;
+
+        // Refresh an existing entry (the station may have moved ports),
+        // otherwise take a free or expired slot, otherwise the stalest one
+        let idx = self
+            .entries
+            .iter()
+            .position(|e| e.in_use && e.addr == addr)
+            .or_else(|| {
+                self.entries
+                    .iter()
+                    .position(|e| !e.in_use || self.is_expired(e))
+            })
+            .or_else(|| {
+                self.entries
+                    .iter()
+                    .enumerate()
+                    .min_by_key(|(_, e)| e.last_seen)
+                    .map(|(idx, _)| idx)
+            });
+
+        if let Some(idx) = idx {
+            self.entries[idx] = MacEntry {
+                last_seen: clock,
+                addr,
+                port,
+                in_use: true,
+            };
+        }
+    }
+
+    fn is_expired(&self, entry: &MacEntry) -> bool {
+        self.clock.wrapping_sub(entry.last_seen) > MAC_ENTRY_MAX_AGE
+    }
+}
+
+/// Group bit clear, the broadcast address has it set
+fn is_unicast(addr: &EthernetAddress) -> bool {
+    addr.0[0] & 0x01 == 0
+}
@@ -0,0 +1,108 @@
+#![no_std]
+#![no_main]
+
+use selfe_runtime as _;
+
+use bridge::{Forwarding, MacTable, PortId, ProcParams};
+use debug_logger::DebugLogger;
+use ferros::cap::role;
+use ferros::userland::Producer;
+use net_types::{EthernetAddress, FrameHandle, FramePool};
+
+/// Source and destination MAC addresses
+const ETHERNET_ADDRS_LEN: usize = 12;
+
+static LOGGER: DebugLogger = DebugLogger;
+
+#[allow(improper_ctypes_definitions)]
+#[no_mangle]
+pub extern "C" fn _start(params: ProcParams<role::Local>) -> ! {
+    log::set_logger(&LOGGER)
+        .map(|()| log::set_max_level(DebugLogger::max_log_level_from_env()))
+        .unwrap();
+
+    log::debug!("[bridge] Process started");
+
+    let mut mac_table_mem = params.mac_table_mem;
+    mac_table_mem.flush().unwrap();
+    let mac_table = MacTable::init_in(mac_table_mem.as_mut_slice());
+
+    let mut frame_pool_mem = params.frame_pool_mem;
+    let frame_pool = unsafe { FramePool::attach(frame_pool_mem.as_mut_slice()) };
+
+    let initial_state = Bridge {
+        mac_table,
+        frame_pool,
+        port_a: params.port_a_producer,
+        port_b: params.port_b_producer,
+    };
+
+    params.consumer.consume(
+        initial_state,
+        |state| {
+            // No IRQ or waker associated with this consumer
+            state
+        },
+        |frame, mut state| {
+            state.handle_frame(PortId::A, frame);
+            state
+        },
+        |frame, mut state| {
+            state.handle_frame(PortId::B, frame);
+            state
+        },
+    );
+}
+
+struct Bridge<'a> {
+    mac_table: &'a mut MacTable,
+    frame_pool: FramePool<'a>,
+    port_a: Producer<role::Local, FrameHandle>,
+    port_b: Producer<role::Local, FrameHandle>,
+}
+
+impl<'a> Bridge<'a> {
+    fn handle_frame(&mut self, ingress: PortId, frame: FrameHandle) {
+        let data = self.frame_pool.frame(&frame);
+        if data.len() < ETHERNET_ADDRS_LEN {
+            log::warn!("[bridge] Dropping runt frame from port {}", ingress);
+            self.frame_pool.free(frame);
+            return;
+        }
+
+        let mut dst = EthernetAddress::default();
+        let mut src = EthernetAddress::default();
+        dst.0.copy_from_slice(&data[0..6]);
+        src.0.copy_from_slice(&data[6..12]);
+
+        let forwarding = self.mac_table.process(ingress, src, dst);
+        log::trace!(
+            "[bridge] {} src={} dst={} from port {} -> {:?}",
+            frame,
+            src,
+            dst,
+            ingress,
+            forwarding
+        );
+
+        // With two ports, flooding is forwarding to the other one
+        let egress = match forwarding {
+            Forwarding::Forward(port) => port,
+            Forwarding::Flood => ingress.other(),
+            Forwarding::Filter => {
+                self.frame_pool.free(frame);
+                return;
+            }
+        };
+
+        let producer = match egress {
+            PortId::A => &self.port_a,
+            PortId::B => &self.port_b,
+        };
+        if let Err(e) = producer.send(frame) {
+            self.mac_table.stats.dropped += 1;
+            log::warn!("[bridge] Rejected sending FrameHandle to port {}", egress);
+            self.frame_pool.free(e.into_inner());
+        }
+    }
+}
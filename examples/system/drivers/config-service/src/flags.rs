@@ -0,0 +1,51 @@
+//! Feature flags, the boolean config entries, published in a page of
+//! memory shared with the processes that act on them.
+//!
+//! The config service writes a flag whenever its entry changes, readers
+//! check it in place without making a request.
+
+use crate::{ConfigEntry, ConfigKey};
+use core::sync::atomic::{AtomicBool, Ordering};
+use ferros::arch::PageBits;
+use ferros::vspace::{shared_status, MappedMemoryRegion};
+
+/// Size of the shared region backing `FeatureFlags`
+pub type FeatureFlagsSizeBits = PageBits;
+
+/// A flag for every entry by key index, only the boolean entries are used
+#[repr(C)]
+pub struct FeatureFlags {
+    flags: [AtomicBool; ConfigKey::COUNT],
+}
+
+impl FeatureFlags {
+    /// Use the flags in a mapping of the shared page.
+    ///
+    /// Readers map the page read-only. Retyped memory starts out zeroed, so
+    /// every flag reads as disabled until the config service publishes it.
+    pub fn from_region(
+        region: MappedMemoryRegion<FeatureFlagsSizeBits, shared_status::Shared>,
+    ) -> &'static FeatureFlags {
+        unsafe { &*(region.vaddr() as *const FeatureFlags) }
+    }
+
+    pub fn is_enabled<E: ConfigEntry<Value = bool>>(&self) -> bool {
+        self.flag::<E>().load(Ordering::Relaxed)
+    }
+
+    /// Untyped read of a flag by its key, false for entries that aren't flags
+    pub fn get(&self, key: ConfigKey) -> bool {
+        self.flags[key.index()].load(Ordering::Relaxed)
+    }
+
+    /// The flag itself, for code that can't depend on the schema
+    pub fn flag<E: ConfigEntry<Value = bool>>(&self) -> &AtomicBool {
+        &self.flags[E::KEY.index()]
+    }
+
+    /// Publish a flag's value, only the config service's writable mapping
+    /// allows this
+    pub fn publish(&self, key: ConfigKey, enabled: bool) {
+        self.flags[key.index()].store(enabled, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,182 @@
+#![no_std]
+
+use core::fmt;
+use ferros::cap::{role, CNodeRole};
+use ferros::userland::{BuildMetadata, Caller, Producer, Responder, RetypeForSetup};
+use ferros::vspace::{shared_status, MappedMemoryRegion};
+use net_types::Ipv4Address;
+
+#[macro_use]
+mod schema;
+mod flags;
+
+pub use crate::flags::*;
+pub use crate::schema::*;
+
+config_schema! {
+    /// IPv4 address of the TCP/IP driver's interface
+    NetIpAddr("net.ip-addr"): Ipv4Address = Ipv4Address([192, 0, 2, 80]),
+        validate = is_unicast;
+
+    /// Prefix length of the TCP/IP driver's IPv4 subnet
+    NetPrefixLen("net.prefix-len"): u8 = 24, validate = |len: &u8| (1..=30).contains(len);
+
+    /// Log every record, trace level included, in the processes reading the
+    /// feature flags
+    FlagVerboseTracing("flag.verbose-tracing"): bool = false;
+
+    /// Capture every frame through the TCP/IP driver's capture tap while
+    /// it's otherwise off
+    FlagPcapCapture("flag.pcap-capture"): bool = false;
+
+    /// Opt in to code paths still under development
+    FlagExperimental("flag.experimental"): bool = false;
+
+    /// Timestamp frames as they're handed between the ENET and TCP/IP
+    /// drivers, and log how long each hop took
+    FlagFrameTimestamps("flag.frame-timestamps"): bool = false;
+}
+
+fn is_unicast(addr: &Ipv4Address) -> bool {
+    let [first_octet, ..] = addr.0;
+    addr.0 != [0; 4]
+        && addr.0 != [255; 4]
+        && first_octet != 127
+        && !(224..240).contains(&first_octet)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Request {
+    Get(ConfigKey),
+    /// Validate and store a new value, notifying the subscribers if it changed
+    Set(ConfigKey, ConfigValue),
+    /// Go back to the entry's default value
+    Reset(ConfigKey),
+}
+
+impl fmt::Display for Request {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Request::Get(k) => write!(f, "Get({})", k),
+            Request::Set(k, v) => write!(f, "Set({}, {})", k, v),
+            Request::Reset(k) => write!(f, "Reset({})", k),
+        }
+    }
+}
+
+/// Every request is answered with the entry's current value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Response {
+    Value(ConfigKey, ConfigValue),
+}
+
+impl fmt::Display for Response {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Response::Value(k, v) => write!(f, "{} = {}", k, v),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigError {
+    /// The value couldn't be parsed as the entry's type
+    Malformed(ConfigKey),
+    /// The value isn't of the entry's type
+    WrongType(ConfigKey),
+    /// The value didn't pass the entry's validation
+    InvalidValue(ConfigKey),
+    /// persistent-storage failed to store the value
+    Storage(persistent_storage::ErrorCode),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Malformed(k) => write!(f, "Malformed value for {}", k),
+            ConfigError::WrongType(k) => write!(f, "Wrong value type for {}", k),
+            ConfigError::InvalidValue(k) => write!(f, "Invalid value for {}", k),
+            ConfigError::Storage(ec) => write!(f, "Storage error {:?}", ec),
+        }
+    }
+}
+
+/// Notification of an entry's new value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConfigChange {
+    pub key: ConfigKey,
+    pub value: ConfigValue,
+}
+
+impl ConfigChange {
+    /// The new value, if the change is to the entry `E`
+    pub fn get<E: ConfigEntry>(&self) -> Option<E::Value> {
+        if self.key == E::KEY {
+            E::Value::from_value(self.value)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for ConfigChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ConfigChange {} = {}", self.key, self.value)
+    }
+}
+
+pub type ConfigCaller<Role> = Caller<Request, Result<Response, ConfigError>, Role>;
+
+/// Typed read of an entry through the config service
+pub fn get<E: ConfigEntry>(caller: &ConfigCaller<role::Local>) -> Result<E::Value, ConfigError> {
+    let Response::Value(_, value) = caller
+        .blocking_call(&Request::Get(E::KEY))
+        .expect("Failed to perform a blocking_call")?;
+    E::Value::from_value(value).ok_or(ConfigError::WrongType(E::KEY))
+}
+
+/// Typed write of an entry through the config service
+pub fn set<E: ConfigEntry>(
+    caller: &ConfigCaller<role::Local>,
+    value: E::Value,
+) -> Result<(), ConfigError> {
+    caller
+        .blocking_call(&Request::Set(E::KEY, value.into_value()))
+        .expect("Failed to perform a blocking_call")
+        .map(|_| ())
+}
+
+/// A subscriber to changes of a set of entries.
+///
+/// At startup, the subscriber is also sent the entries with a stored value
+/// other than their default.
+#[repr(C)]
+pub struct Subscription<Role: CNodeRole> {
+    pub producer: Producer<Role, ConfigChange>,
+    pub keys: ConfigKeySet,
+}
+
+#[repr(C)]
+pub struct ProcParams<Role: CNodeRole> {
+    pub responder: Responder<Request, Result<Response, ConfigError>, Role>,
+
+    /// IPC to the storage driver, where the entries are kept
+    pub storage_caller: Caller<
+        persistent_storage::Request,
+        Result<persistent_storage::Response, persistent_storage::ErrorCode>,
+        Role,
+    >,
+
+    /// The TCP/IP driver's subscription to the net entries
+    pub tcpip_subscription: Subscription<Role>,
+
+    /// The feature flags page, mapped writable for the service alone
+    pub feature_flags_mem: MappedMemoryRegion<FeatureFlagsSizeBits, shared_status::Shared>,
+
+    /// The root task's build metadata, checked against this process' own
+    pub root_build: BuildMetadata,
+}
+
+impl RetypeForSetup for ProcParams<role::Local> {
+    type Output = ProcParams<role::Child>;
+}
@@ -0,0 +1,196 @@
+#![no_std]
+#![no_main]
+
+use selfe_runtime as _;
+
+use config_service::{
+    ConfigChange, ConfigError, ConfigKey, ConfigValue, FeatureFlags, ProcParams, Request, Response,
+    Subscription,
+};
+use core::fmt::Write;
+use debug_logger::DebugLogger;
+use ferros::cap::role;
+use ferros::userland::Caller;
+use persistent_storage::{ErrorCode, Key, Value};
+
+static LOGGER: DebugLogger = DebugLogger;
+
+mod build_metadata {
+    include!(concat!(env!("OUT_DIR"), "/build_metadata.rs"));
+}
+
+/// Entries are stored in persistent-storage under their key name with
+/// this prefix
+const STORAGE_KEY_PREFIX: &str = "cfg/";
+
+#[allow(improper_ctypes_definitions)]
+#[no_mangle]
+pub extern "C" fn _start(params: ProcParams<role::Local>) -> ! {
+    log::set_logger(&LOGGER)
+        .map(|()| log::set_max_level(DebugLogger::max_log_level_from_env()))
+        .unwrap();
+
+    log::debug!("[config-service] Process started");
+
+    if let Err(skew) = build_metadata::BUILD_METADATA.check_skew(&params.root_build) {
+        log::warn!("[config-service] Built differently than the root task: {:?}", skew);
+    }
+
+    let mut service = Service {
+        storage_caller: params.storage_caller,
+        tcpip_subscription: params.tcpip_subscription,
+        feature_flags: FeatureFlags::from_region(params.feature_flags_mem),
+        values: [ConfigValue::Bool(false); ConfigKey::COUNT],
+    };
+    service.load();
+
+    params
+        .responder
+        .reply_recv(move |req| {
+            log::debug!("[config-service] Processing request {}", req);
+            let resp = service.handle_request(req);
+            match &resp {
+                Ok(r) => log::debug!("[config-service] Response {}", r),
+                Err(e) => log::debug!("[config-service] Response {}", e),
+            }
+            resp
+        })
+        .expect("Could not set up a reply_recv")
+        .expect("Failure on reply_recv");
+
+    unsafe {
+        loop {
+            selfe_sys::seL4_Yield();
+        }
+    }
+}
+
+struct Service {
+    storage_caller: Caller<
+        persistent_storage::Request,
+        Result<persistent_storage::Response, ErrorCode>,
+        role::Local,
+    >,
+    tcpip_subscription: Subscription<role::Local>,
+    feature_flags: &'static FeatureFlags,
+    /// Current value of each entry, by key index
+    values: [ConfigValue; ConfigKey::COUNT],
+}
+
+impl Service {
+    /// Read back the stored entries, falling back to the defaults, and let
+    /// the subscribers know about the ones that differ from their default
+    fn load(&mut self) {
+        for key in ConfigKey::ALL.iter().copied() {
+            let value = match self.load_entry(key) {
+                Ok(Some(value)) => value,
+                Ok(None) => key.default_value(),
+                Err(e) => {
+                    log::warn!("[config-service] Using the default for {}, {}", key, e);
+                    key.default_value()
+                }
+            };
+            self.update(key, value);
+            log::debug!("[config-service] {} = {}", key, value);
+
+            if value != key.default_value() {
+                self.notify(ConfigChange { key, value });
+            }
+        }
+    }
+
+    fn load_entry(&self, key: ConfigKey) -> Result<Option<ConfigValue>, ConfigError> {
+        let resp = self
+            .storage_caller
+            .blocking_call(&persistent_storage::Request::Get(storage_key(key)))
+            .expect("Failed to perform a blocking_call");
+        match resp {
+            Ok(persistent_storage::Response::Value(stored)) => {
+                // Values come back padded out with NULs
+                let value = key.parse_value(stored.as_str().trim_end_matches('\0'))?;
+                key.check(value)?;
+                Ok(Some(value))
+            }
+            Ok(_) => Err(ConfigError::Storage(ErrorCode::CorruptData)),
+            Err(ErrorCode::KeyNotFound) => Ok(None),
+            Err(ec) => Err(ConfigError::Storage(ec)),
+        }
+    }
+
+    fn handle_request(&mut self, req: Request) -> Result<Response, ConfigError> {
+        let (key, value) = match req {
+            Request::Get(key) => return Ok(Response::Value(key, self.values[key.index()])),
+            Request::Set(key, value) => {
+                key.check(value)?;
+                self.invalidate_entry(key)?;
+                self.store_entry(key, value)?;
+                (key, value)
+            }
+            Request::Reset(key) => {
+                self.invalidate_entry(key)?;
+                (key, key.default_value())
+            }
+        };
+
+        if self.values[key.index()] != value {
+            self.update(key, value);
+            self.notify(ConfigChange { key, value });
+        }
+
+        Ok(Response::Value(key, value))
+    }
+
+    /// Take on a new value, publishing it to the feature flags page if
+    /// it's a flag
+    fn update(&mut self, key: ConfigKey, value: ConfigValue) {
+        self.values[key.index()] = value;
+        if let ConfigValue::Bool(enabled) = value {
+            self.feature_flags.publish(key, enabled);
+        }
+    }
+
+    fn store_entry(&self, key: ConfigKey, value: ConfigValue) -> Result<(), ConfigError> {
+        let mut stored = Value::new();
+        write!(stored, "{}", value).expect("Config values fit a storage value");
+        self.storage_caller
+            .blocking_call(&persistent_storage::Request::AppendKey(
+                storage_key(key),
+                stored,
+            ))
+            .expect("Failed to perform a blocking_call")
+            .map(|_| ())
+            .map_err(ConfigError::Storage)
+    }
+
+    /// TickV won't append a key that already exists, so the old value is
+    /// invalidated first
+    fn invalidate_entry(&self, key: ConfigKey) -> Result<(), ConfigError> {
+        let req = persistent_storage::Request::InvalidateKey(storage_key(key));
+        match self
+            .storage_caller
+            .blocking_call(&req)
+            .expect("Failed to perform a blocking_call")
+        {
+            Ok(_) | Err(ErrorCode::KeyNotFound) => Ok(()),
+            Err(ec) => Err(ConfigError::Storage(ec)),
+        }
+    }
+
+    fn notify(&self, change: ConfigChange) {
+        let subscription = &self.tcpip_subscription;
+        if !subscription.keys.contains(change.key) {
+            return;
+        }
+        log::trace!("[config-service] Notifying tcpip of {}", change);
+        if subscription.producer.send(change).is_err() {
+            log::warn!("[config-service] Rejected sending ConfigChange to tcpip");
+        }
+    }
+}
+
+fn storage_key(key: ConfigKey) -> Key {
+    let mut storage_key = Key::new();
+    write!(storage_key, "{}{}", STORAGE_KEY_PREFIX, key)
+        .expect("Config key names fit a storage key");
+    storage_key
+}
@@ -0,0 +1,257 @@
+//! Typed configuration entries.
+//!
+//! `config_schema!` declares each entry's key name, value type, default and
+//! an optional validation. It generates a marker type in `entry` for typed
+//! access to each entry, and the `ConfigKey` enumeration of all of them
+//! that's carried in requests and stored in persistent-storage.
+
+use crate::{ConfigError, ConfigKey};
+use core::fmt;
+use net_types::{Ipv4Address, Port};
+
+/// An entry's value, as carried in requests and change notifications
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ConfigValue {
+    Bool(bool),
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    Ipv4Address(Ipv4Address),
+    Port(Port),
+}
+
+impl fmt::Display for ConfigValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigValue::Bool(v) => write!(f, "{}", v),
+            ConfigValue::U8(v) => write!(f, "{}", v),
+            ConfigValue::U16(v) => write!(f, "{}", v),
+            ConfigValue::U32(v) => write!(f, "{}", v),
+            ConfigValue::Ipv4Address(v) => write!(f, "{}", v),
+            ConfigValue::Port(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+/// The types an entry's value can have
+pub trait ConfigType: Sized + Copy {
+    fn into_value(self) -> ConfigValue;
+
+    fn from_value(value: ConfigValue) -> Option<Self>;
+
+    /// Parse the value from the form it's displayed, and stored, in
+    fn parse(s: &str) -> Option<Self>;
+}
+
+macro_rules! impl_config_type {
+    ($ty:ty, $variant:ident) => {
+        impl ConfigType for $ty {
+            fn into_value(self) -> ConfigValue {
+                ConfigValue::$variant(self)
+            }
+
+            fn from_value(value: ConfigValue) -> Option<Self> {
+                match value {
+                    ConfigValue::$variant(v) => Some(v),
+                    _ => None,
+                }
+            }
+
+            fn parse(s: &str) -> Option<Self> {
+                s.parse().ok()
+            }
+        }
+    };
+}
+
+impl_config_type!(bool, Bool);
+impl_config_type!(u8, U8);
+impl_config_type!(u16, U16);
+impl_config_type!(u32, U32);
+
+impl ConfigType for Port {
+    fn into_value(self) -> ConfigValue {
+        ConfigValue::Port(self)
+    }
+
+    fn from_value(value: ConfigValue) -> Option<Self> {
+        match value {
+            ConfigValue::Port(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        s.parse().ok().map(Port)
+    }
+}
+
+impl ConfigType for Ipv4Address {
+    fn into_value(self) -> ConfigValue {
+        ConfigValue::Ipv4Address(self)
+    }
+
+    fn from_value(value: ConfigValue) -> Option<Self> {
+        match value {
+            ConfigValue::Ipv4Address(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        let mut octets = [0_u8; 4];
+        let mut parts = s.split('.');
+        for octet in octets.iter_mut() {
+            *octet = parts.next()?.parse().ok()?;
+        }
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(Ipv4Address(octets))
+    }
+}
+
+/// A configuration entry declared by `config_schema!`
+pub trait ConfigEntry {
+    type Value: ConfigType;
+
+    const KEY: ConfigKey;
+
+    /// The value used until one is set, or after it's reset
+    const DEFAULT: Self::Value;
+
+    fn is_valid(_value: &Self::Value) -> bool {
+        true
+    }
+}
+
+/// Check that a value has the entry's type and passes its validation
+pub(crate) fn check<E: ConfigEntry>(value: ConfigValue) -> Result<(), ConfigError> {
+    match E::Value::from_value(value) {
+        Some(v) if E::is_valid(&v) => Ok(()),
+        Some(_) => Err(ConfigError::InvalidValue(E::KEY)),
+        None => Err(ConfigError::WrongType(E::KEY)),
+    }
+}
+
+/// A set of keys, such as the ones a subscriber is notified of changes to
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct ConfigKeySet(u32);
+
+impl ConfigKeySet {
+    pub const fn empty() -> Self {
+        ConfigKeySet(0)
+    }
+
+    pub const fn all() -> Self {
+        ConfigKeySet(u32::MAX >> (32 - ConfigKey::COUNT))
+    }
+
+    pub const fn with(self, key: ConfigKey) -> Self {
+        ConfigKeySet(self.0 | (1 << key as u32))
+    }
+
+    pub fn contains(&self, key: ConfigKey) -> bool {
+        (self.0 & (1 << key as u32)) != 0
+    }
+}
+
+/// Declare the configuration entries.
+///
+/// Each entry is a marker type name, its key name, value type, default
+/// and an optional validation function of the value:
+///
+/// ```ignore
+/// config_schema! {
+///     /// Prefix length of the TCP/IP driver's IPv4 subnet
+///     NetPrefixLen("net.prefix-len"): u8 = 24, validate = |len: &u8| *len <= 30;
+/// }
+/// ```
+macro_rules! config_schema {
+    ($(
+        $(#[$meta:meta])*
+        $entry:ident($name:literal): $ty:ty = $default:expr $(, validate = $validate:expr)?;
+    )*) => {
+        /// Marker types for typed access to each entry
+        pub mod entry {
+            use super::*;
+
+            $(
+                $(#[$meta])*
+                pub struct $entry;
+
+                impl ConfigEntry for $entry {
+                    type Value = $ty;
+                    const KEY: ConfigKey = ConfigKey::$entry;
+                    const DEFAULT: $ty = $default;
+
+                    $(
+                        fn is_valid(value: &$ty) -> bool {
+                            ($validate)(value)
+                        }
+                    )?
+                }
+            )*
+        }
+
+        #[derive(Debug, Hash, PartialEq, Eq, Clone, Copy)]
+        pub enum ConfigKey {
+            $($entry,)*
+        }
+
+        // Key sets are a bitmask of the keys
+        static_assertions::const_assert!(ConfigKey::COUNT > 0 && ConfigKey::COUNT <= 32);
+
+        impl ConfigKey {
+            pub const ALL: &'static [ConfigKey] = &[$(ConfigKey::$entry,)*];
+
+            pub const COUNT: usize = Self::ALL.len();
+
+            pub fn index(self) -> usize {
+                self as usize
+            }
+
+            pub fn name(self) -> &'static str {
+                match self {
+                    $(ConfigKey::$entry => $name,)*
+                }
+            }
+
+            pub fn from_name(name: &str) -> Option<Self> {
+                match name {
+                    $($name => Some(ConfigKey::$entry),)*
+                    _ => None,
+                }
+            }
+
+            pub fn default_value(self) -> ConfigValue {
+                match self {
+                    $(ConfigKey::$entry => {
+                        <entry::$entry as ConfigEntry>::DEFAULT.into_value()
+                    })*
+                }
+            }
+
+            pub fn parse_value(self, s: &str) -> Result<ConfigValue, ConfigError> {
+                match self {
+                    $(ConfigKey::$entry => <$ty as ConfigType>::parse(s)
+                        .map(ConfigType::into_value)
+                        .ok_or(ConfigError::Malformed(self)),)*
+                }
+            }
+
+            /// Check that a value has the entry's type and passes its validation
+            pub fn check(self, value: ConfigValue) -> Result<(), ConfigError> {
+                match self {
+                    $(ConfigKey::$entry => $crate::schema::check::<entry::$entry>(value),)*
+                }
+            }
+        }
+
+        impl core::fmt::Display for ConfigKey {
+            fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+                f.write_str(self.name())
+            }
+        }
+    };
+}
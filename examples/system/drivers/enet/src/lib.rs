@@ -1,16 +1,19 @@
 #![no_std]
 
+use config_service::FeatureFlagsSizeBits;
 use ferros::cap::{role, CNodeRole};
-use ferros::userland::{Consumer1, Producer, RetypeForSetup};
-use ferros::vspace::{shared_status, MappedMemoryRegion};
+use ferros::userland::{
+    BuildMetadata, Consumer1, Producer, RetypeForSetup, SequenceCounterSizeBits, Waker,
+};
+use ferros::vspace::{cache_status, shared_status, MappedMemoryRegion};
 use imx6_hal::pac::{
     enet::{self, ENET},
     typenum::{op, U1, U16},
 };
-use net_types::{EthernetAddress, IpcEthernetFrame};
+use liveness::LivenessSizeBits;
+use net_types::{EthernetAddress, FrameHandle, FramePoolMemSizeBits};
 
-/// Expected badge value on IRQ notifications
-pub type IrqBadgeBits = enet::Irq;
+pub use enet::IrqBadgeBits;
 
 /// For the ENET Ethernet driver DMA descriptors and packets
 /// 1 page for the descriptors, split in half for rx/tx, up to 256 for each
@@ -26,18 +29,44 @@ pub struct ProcParams<Role: CNodeRole> {
 
     /// Consumer of Ethernet frames to be sent out on the ENET egress, in
     /// addition to IRQ notification wakeup events
-    pub consumer: Consumer1<Role, IpcEthernetFrame, enet::Irq>,
+    pub consumer: Consumer1<Role, FrameHandle, enet::Irq>,
+
+    /// Wakes the consumer's IRQ path, for rx packets left over from a
+    /// batch
+    pub waker: Waker<Role>,
 
     /// Producer of Ethernet frames received from the ENET ingress
-    pub producer: Producer<Role, IpcEthernetFrame>,
+    pub producer: Producer<Role, FrameHandle>,
+
+    /// The Ethernet frame pool shared with the L3 driver, the frames
+    /// in both queues live here
+    pub frame_pool_mem: MappedMemoryRegion<FramePoolMemSizeBits, shared_status::Shared>,
 
     /// DMA-able memory for use by the Ethernet Rx/Tx descriptors and packets.
     ///
-    /// NOTE: currently expects to be mapped *not* cacheable
-    pub dma_mem: MappedMemoryRegion<EthDmaMemSizeInBits, shared_status::Exclusive>,
+    /// Mapped uncached, the HAL and the device share the descriptor rings
+    /// without any cache maintenance
+    pub dma_mem: MappedMemoryRegion<
+        EthDmaMemSizeInBits,
+        shared_status::Exclusive,
+        role::Local,
+        cache_status::Uncached,
+    >,
 
     /// Hardware MAC address
     pub mac_addr: EthernetAddress,
+
+    /// The system-wide event sequence counter page, stamps log records
+    pub sequence_counter_mem: MappedMemoryRegion<SequenceCounterSizeBits, shared_status::Shared>,
+
+    /// The feature flags page, mapped read-only
+    pub feature_flags_mem: MappedMemoryRegion<FeatureFlagsSizeBits, shared_status::Shared>,
+
+    /// The liveness page, counts the frames put on the tx ring
+    pub liveness_mem: MappedMemoryRegion<LivenessSizeBits, shared_status::Shared>,
+
+    /// The root task's build metadata, checked against this process' own
+    pub root_build: BuildMetadata,
 }
 
 impl RetypeForSetup for ProcParams<role::Local> {
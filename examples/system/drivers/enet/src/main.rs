@@ -3,43 +3,67 @@
 
 use selfe_runtime as _;
 
+use config_service::{entry, FeatureFlags};
 use debug_logger::DebugLogger;
 use enet::ProcParams;
 use ferros::cap::role;
-use ferros::userland::Producer;
-use imx6_hal::enet::{uncached_memory_region::UncachedMemoryRegion, Enet};
+use ferros::userland::{
+    BatchBudget, BatchStep, BudgetViolation, DeadlineMonitor, Producer, SequenceCounter,
+};
+use imx6_hal::enet::Enet;
 use imx6_hal::pac::typenum::Unsigned;
-use net_types::IpcEthernetFrame;
+use liveness::system::enet::TX_FRAMES;
+use liveness::LivenessPage;
+use net_types::{FrameHandle, FramePool, FrameTimestamp, HopLatency};
 
 static LOGGER: DebugLogger = DebugLogger;
 
+mod build_metadata {
+    include!(concat!(env!("OUT_DIR"), "/build_metadata.rs"));
+}
+
+/// The most rx packets taken off the ring per wakeup, the rest wait for
+/// another once other threads on the core have had a turn
+const RX_BATCH_FRAMES: usize = 16;
+
+/// Worst case cycles for handling an IRQ, taking a batch of rx packets
+/// off the ring
+const IRQ_BUDGET_CYCLES: usize = 400_000;
+
+/// Worst case cycles for queueing a frame on the tx ring
+const TX_BUDGET_CYCLES: usize = 50_000;
+
 #[allow(improper_ctypes_definitions)]
 #[no_mangle]
 pub extern "C" fn _start(params: ProcParams<role::Local>) -> ! {
     log::set_logger(&LOGGER)
         .map(|()| log::set_max_level(DebugLogger::max_log_level_from_env()))
         .unwrap();
+    DebugLogger::set_sequence_counter(SequenceCounter::from_region(params.sequence_counter_mem));
+    let feature_flags = FeatureFlags::from_region(params.feature_flags_mem);
+    DebugLogger::set_verbose_flag(feature_flags.flag::<entry::FlagVerboseTracing>());
 
     log::debug!("[enet-driver] Process started");
 
-    let dma_mem = params.dma_mem;
-    dma_mem.flush().unwrap();
+    if let Err(skew) = build_metadata::BUILD_METADATA.check_skew(&params.root_build) {
+        log::warn!("[enet-driver] Built differently than the root task: {:?}", skew);
+    }
+
+    // Pinned, since the device keeps the region's physical address for as
+    // long as the driver runs
+    let pinned_dma_mem = params.dma_mem.pin().unwrap();
+    pinned_dma_mem.flush().unwrap();
 
-    // Downgrade to something more easily managed by the HAL
-    let mut dma_mem = unsafe {
-        UncachedMemoryRegion::new(
-            dma_mem.vaddr(),
-            dma_mem.paddr().unwrap(),
-            dma_mem.size_bytes(),
-        )
-    };
-    log::trace!("[enet-driver] DMA memory {}", dma_mem);
+    // The region is typed uncached, so it needs no flushing as the HAL
+    // and the device share it.
+    let mut dma_mem = pinned_dma_mem.dma_region().unwrap();
+    log::trace!("[enet-driver] DMA memory {:?}", dma_mem);
 
     let pkt_mem = dma_mem.split_off(ferros::arch::PageBytes::USIZE).unwrap();
     let desc_mem = dma_mem;
 
-    log::trace!("[enet-driver] Descriptor pool {}", desc_mem);
-    log::trace!("[enet-driver] Packet pool {}", pkt_mem);
+    log::trace!("[enet-driver] Descriptor pool {:?}", desc_mem);
+    log::trace!("[enet-driver] Packet pool {:?}", pkt_mem);
 
     let mut enet = Enet::new(params.enet, params.mac_addr, desc_mem, pkt_mem).unwrap();
 
@@ -49,58 +73,136 @@ pub extern "C" fn _start(params: ProcParams<role::Local>) -> ! {
 
     enet.init();
 
-    struct State {
-        enet: Enet,
-        producer: Producer<role::Local, IpcEthernetFrame>,
+    let mut frame_pool_mem = params.frame_pool_mem;
+    let frame_pool = unsafe { FramePool::attach(frame_pool_mem.as_mut_slice()) };
+    log::trace!("[enet-driver] {}", frame_pool);
+
+    struct State<'a> {
+        enet: Enet<'a>,
+        producer: Producer<role::Local, FrameHandle>,
+        frame_pool: FramePool<'a>,
+        /// The producer's dropped count as of the last report
+        reported_drops: usize,
+        flags: &'static FeatureFlags,
+        /// Timestamped frames from the TCP/IP driver
+        tx_latency: HopLatency,
+        liveness: &'static LivenessPage,
+        /// Whether the last batch left packets on the rx ring
+        rx_backlog: bool,
     }
 
-    let producer_qlen = params.producer.capacity();
+    let monitor = unsafe { DeadlineMonitor::new(report_budget_violation) };
+
+    let rx_batch = BatchBudget::new(RX_BATCH_FRAMES, params.waker);
     let initial_state = State {
         enet,
         producer: params.producer,
+        frame_pool,
+        reported_drops: 0,
+        flags: feature_flags,
+        tx_latency: HopLatency::new("tcpip->enet"),
+        liveness: LivenessPage::from_region(params.liveness_mem),
+        rx_backlog: false,
     };
 
     params.consumer.consume(
         initial_state,
-        |mut state| {
+        monitor.waker_handler("irq", IRQ_BUDGET_CYCLES, |mut state: State| {
             // Non-queue IRQ wakeup event
             log::trace!("[enet-driver] IRQ wakeup");
 
-            let rx_ready = state.enet.ack_irqs();
+            // The IRQ has been acked already if this is a wakeup from the
+            // last batch
+            let rx_ready = state.enet.ack_irqs() || state.rx_backlog;
 
-            // Attempt to drain up to qlen worth of packets from the rx ring
+            // Take up to a batch of packets off the rx ring
             if rx_ready {
-                for _ in 0..producer_qlen {
-                    let mut rx_frame = IpcEthernetFrame::new();
+                let batch = rx_batch.run(|| {
+                    let mut rx_frame = match state.frame_pool.alloc() {
+                        Some(handle) => handle,
+                        None => {
+                            // Drop the packet rather than stall the rx ring
+                            if state.enet.receive(|_| ()) == 0 {
+                                return BatchStep::Empty;
+                            }
+                            log::warn!("[enet-driver] Frame pool exhausted, dropped rx packet");
+                            return BatchStep::Processed;
+                        }
+                    };
+
+                    let frame_pool = &state.frame_pool;
                     let bytes_recvd = state.enet.receive(|pkt| {
                         log::trace!("[enet-driver] Dequeue rx packet {} bytes", pkt.len());
                         rx_frame.truncate(pkt.len());
-                        rx_frame.as_mut_slice().copy_from_slice(pkt);
+                        frame_pool.frame_mut(&mut rx_frame).copy_from_slice(pkt);
                     });
 
                     if bytes_recvd != 0 {
-                        if state.producer.send(rx_frame).is_err() {
-                            log::warn!("[enet-driver] Rejected sending IpcEthernetFrame");
+                        if state.flags.is_enabled::<entry::FlagFrameTimestamps>() {
+                            rx_frame.stamp(frame_timestamp());
+                        }
+                        if let Err(e) = state.producer.send(rx_frame) {
+                            state.frame_pool.free(e.into_inner());
                         }
+                        BatchStep::Processed
                     } else {
-                        // Break out early if the rx ring is empty
-                        break;
+                        // The rx ring is empty
+                        state.frame_pool.free(rx_frame);
+                        BatchStep::Empty
                     }
-                }
+                });
+                state.rx_backlog = batch.deferred;
+            }
+
+            // One report per IRQ rather than one per rejected frame
+            let drops = state.producer.dropped();
+            if drops != state.reported_drops {
+                log::warn!(
+                    "[enet-driver] Consumer queue full, dropped {} rx packets ({} total)",
+                    drops - state.reported_drops,
+                    drops
+                );
+                state.reported_drops = drops;
             }
 
             state
-        },
-        |tx_frame, mut state| {
-            // Transmit request queue
+        }),
+        monitor.queue_handler(
+            "tx",
+            TX_BUDGET_CYCLES,
+            |tx_frame: FrameHandle, mut state: State| {
+                // Transmit request queue
+
+                log::trace!("[enet-driver] Enqueue {}", tx_frame);
+
+                let latency = &mut state.tx_latency;
+                if let Some(cycles) = latency.record(&tx_frame, frame_timestamp()) {
+                    log::trace!("[enet-driver] {} took {} cycles", latency.name, cycles);
+                    if cycles == latency.worst_cycles {
+                        log::debug!("[enet-driver] New worst case, {}", latency);
+                    }
+                }
 
-            log::trace!("[enet-driver] Enqueue {}", tx_frame);
+                if let Err(e) = state.enet.transmit(state.frame_pool.frame(&tx_frame)) {
+                    log::warn!("[enet-driver] Failed to transmit FrameHandle {:?}", e);
+                }
 
-            if let Err(e) = state.enet.transmit(tx_frame.as_slice()) {
-                log::warn!("[enet-driver] Failed to transmit IpcEthernetFrame {:?}", e);
-            }
+                // The frame has been copied into the tx ring
+                state.frame_pool.free(tx_frame);
+                state.liveness.completed(TX_FRAMES);
 
-            state
-        },
+                state
+            },
+        ),
     );
 }
+
+/// The cycle counter, for timestamping frames. The deadline monitor
+/// started it.
+fn frame_timestamp() -> FrameTimestamp {
+    FrameTimestamp(unsafe { ferros::arch::cycle_count() } as u32)
+}
+
+fn report_budget_violation(violation: &BudgetViolation) {
+    log::warn!("[enet-driver] {}", violation);
+}
@@ -0,0 +1,7 @@
+use ferros_build::write_build_metadata;
+use std::path::Path;
+
+fn main() {
+    let out_dir = Path::new(&std::env::var_os("OUT_DIR").unwrap()).to_owned();
+    write_build_metadata(out_dir.join("build_metadata.rs"), &[]);
+}
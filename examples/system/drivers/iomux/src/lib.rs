@@ -1,7 +1,7 @@
 #![no_std]
 
 use ferros::cap::{role, CNodeRole};
-use ferros::userland::{Responder, RetypeForSetup};
+use ferros::userland::{BuildMetadata, Responder, RetypeForSetup};
 use imx6_hal::pac::iomuxc::IOMUXC;
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
@@ -18,6 +18,7 @@ pub enum Response {
 pub struct ProcParams<Role: CNodeRole> {
     pub iomuxc: IOMUXC,
     pub responder: Responder<Request, Response, Role>,
+    pub root_build: BuildMetadata,
 }
 
 impl RetypeForSetup for ProcParams<role::Local> {
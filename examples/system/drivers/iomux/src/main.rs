@@ -10,6 +10,10 @@ use iomux::{ProcParams, Request, Response};
 
 static LOGGER: DebugLogger = DebugLogger;
 
+mod build_metadata {
+    include!(concat!(env!("OUT_DIR"), "/build_metadata.rs"));
+}
+
 #[allow(improper_ctypes_definitions)]
 #[no_mangle]
 pub extern "C" fn _start(params: ProcParams<role::Local>) -> ! {
@@ -19,6 +23,10 @@ pub extern "C" fn _start(params: ProcParams<role::Local>) -> ! {
 
     log::debug!("[iomux] Process started");
 
+    if let Err(skew) = build_metadata::BUILD_METADATA.check_skew(&params.root_build) {
+        log::warn!("[iomux] Built differently than the root task: {:?}", skew);
+    }
+
     let mut iomuxc = params.iomuxc;
 
     params
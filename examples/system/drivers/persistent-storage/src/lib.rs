@@ -2,14 +2,11 @@
 
 use core::fmt;
 use ferros::cap::{role, CNodeRole};
-use ferros::userland::{Caller, Responder, RetypeForSetup};
+use ferros::pow::Pow2Bytes;
+use ferros::userland::{BuildMetadata, Caller, Responder, RetypeForSetup};
 use ferros::vspace::{shared_status, MappedMemoryRegion};
 use heapless::String;
-use imx6_hal::pac::{
-    ecspi1::ECSPI1,
-    gpio::GPIO3,
-    typenum::{op, U1, U12},
-};
+use imx6_hal::pac::{ecspi1::ECSPI1, gpio::GPIO3, typenum::U12};
 pub use tickv::{success_codes::SuccessCode, ErrorCode};
 
 pub const MAX_KEY_SIZE: usize = 32;
@@ -59,11 +56,11 @@ impl fmt::Display for Response {
 
 /// 4K buffer for persistent storage in flash (1 sector)
 pub type StorageBufferSizeBits = U12;
-pub type StorageBufferSizeBytes = op! { U1 << StorageBufferSizeBits };
+pub type StorageBufferSizeBytes = Pow2Bytes<StorageBufferSizeBits>;
 
 /// 4K scratchpad buffer
 pub type ScratchpadBufferSizeBits = U12;
-pub type ScratchpadBufferSizeBytes = op! { U1 << ScratchpadBufferSizeBits };
+pub type ScratchpadBufferSizeBytes = Pow2Bytes<ScratchpadBufferSizeBits>;
 
 #[repr(C)]
 pub struct ProcParams<Role: CNodeRole> {
@@ -73,6 +70,7 @@ pub struct ProcParams<Role: CNodeRole> {
     pub responder: Responder<Request, Result<Response, ErrorCode>, Role>,
     pub storage_buffer: MappedMemoryRegion<StorageBufferSizeBits, shared_status::Exclusive>,
     pub scratchpad_buffer: MappedMemoryRegion<ScratchpadBufferSizeBits, shared_status::Exclusive>,
+    pub root_build: BuildMetadata,
 }
 
 impl RetypeForSetup for ProcParams<role::Local> {
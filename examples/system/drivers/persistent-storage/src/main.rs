@@ -26,6 +26,10 @@ mod flash_controller;
 
 static LOGGER: DebugLogger = DebugLogger;
 
+mod build_metadata {
+    include!(concat!(env!("OUT_DIR"), "/build_metadata.rs"));
+}
+
 const_assert_eq!(StorageBufferSizeBytes::USIZE, ERASE_SIZE_BYTES);
 
 #[allow(improper_ctypes_definitions)]
@@ -37,6 +41,10 @@ pub extern "C" fn _start(params: ProcParams<role::Local>) -> ! {
 
     log::debug!("[persistent-storage] Process started",);
 
+    if let Err(skew) = build_metadata::BUILD_METADATA.check_skew(&params.root_build) {
+        log::warn!("[persistent-storage] Built differently than the root task: {:?}", skew);
+    }
+
     log::debug!(
         "[persistent-storage] storage vaddr=0x{:X} size={}",
         params.storage_buffer.vaddr(),
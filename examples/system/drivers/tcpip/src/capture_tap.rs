@@ -0,0 +1,76 @@
+use config_service::{entry, FeatureFlags};
+use core::cell::Cell;
+use ferros::cap::role;
+use ferros::userland::Producer;
+use net_types::{
+    CaptureConfig, CaptureDirection, CaptureFilter, EthernetFrameBuffer, IpcCapturedFrame,
+};
+
+/// Copies a sample of the frames passing through the IP stack's
+/// physical device to a capture process
+pub struct CaptureTap {
+    config: Cell<CaptureConfig>,
+    sample_count: Cell<u32>,
+    dropped: Cell<u32>,
+    producer: Producer<role::Local, IpcCapturedFrame>,
+    flags: &'static FeatureFlags,
+}
+
+impl CaptureTap {
+    /// The tap starts out disabled, unless the pcap capture flag is set
+    pub fn new(
+        producer: Producer<role::Local, IpcCapturedFrame>,
+        flags: &'static FeatureFlags,
+    ) -> Self {
+        CaptureTap {
+            config: Cell::new(CaptureConfig::off()),
+            sample_count: Cell::new(0),
+            dropped: Cell::new(0),
+            producer,
+            flags,
+        }
+    }
+
+    pub fn configure(&self, config: CaptureConfig) {
+        log::info!(
+            "[capture-tap] {} (previously dropped {} frames)",
+            config,
+            self.dropped.get()
+        );
+        self.config.set(config);
+        self.sample_count.set(0);
+        self.dropped.set(0);
+    }
+
+    pub fn tap(&self, timestamp_ms: i64, direction: CaptureDirection, data: &[u8]) {
+        // The pcap capture flag passes on every frame while the tap is off
+        let mut config = self.config.get();
+        let flagged = self.flags.is_enabled::<entry::FlagPcapCapture>();
+        if config.filter == CaptureFilter::Off && flagged {
+            config.filter = CaptureFilter::Both;
+        }
+        if config.filter == CaptureFilter::Off || !config.filter.matches(direction) {
+            return;
+        }
+
+        let count = self.sample_count.get();
+        self.sample_count.set(count.wrapping_add(1));
+        if count % config.sample_every.max(1) != 0 {
+            return;
+        }
+
+        let mut captured = IpcCapturedFrame {
+            timestamp_ms: timestamp_ms as u64,
+            direction,
+            orig_len: data.len(),
+            frame: EthernetFrameBuffer::new(),
+        };
+        let len = data.len().min(usize::from(config.snap_len));
+        captured.frame.truncate(len);
+        captured.frame.as_mut_slice().copy_from_slice(&data[..len]);
+
+        if self.producer.send(captured).is_err() {
+            self.dropped.set(self.dropped.get().wrapping_add(1));
+        }
+    }
+}
@@ -1,37 +1,96 @@
+use crate::capture_tap::CaptureTap;
+use crate::neighbor_table::NeighborTable;
+use config_service::{entry, FeatureFlags};
+use core::fmt;
 use ferros::cap::role;
 use ferros::userland::{Consumer1, Producer};
-use net_types::{IpcEthernetFrame, MtuSize};
+use liveness::system::enet;
+use liveness::LivenessPage;
+use net_types::{
+    CaptureDirection, FrameHandle, FramePool, FrameTimestamp, HopLatency, IpcEthernetFrame, MtuSize,
+};
 use smoltcp::phy::{Checksum, Device, DeviceCapabilities, RxToken, TxToken};
 use smoltcp::time::Instant;
 use smoltcp::Error;
 use typenum::Unsigned;
 
 /// An interface for sending and receiving raw network frames
-/// over ferros IPC
-pub struct IpcPhyDevice {
-    pub consumer: Consumer1<role::Local, IpcEthernetFrame>,
-    pub producer: Producer<role::Local, IpcEthernetFrame>,
+/// over ferros IPC, the frames themselves live in a pool shared
+/// with the L2 driver
+pub struct IpcPhyDevice<'p> {
+    pub consumer: Consumer1<role::Local, FrameHandle>,
+    pub producer: Producer<role::Local, FrameHandle>,
+    pub frame_pool: FramePool<'p>,
+    pub tap: CaptureTap,
+    pub neighbors: NeighborTable,
+    pub flags: &'static FeatureFlags,
+    /// Timestamped frames from the L2 driver
+    pub rx_latency: HopLatency,
+    /// Counts the frames sent to the L2 driver
+    pub liveness: &'static LivenessPage,
 }
 
-impl<'a> Device<'a> for IpcPhyDevice {
-    type RxToken = IpcPhyRxToken;
+/// The cycle counter, for timestamping frames
+pub fn frame_timestamp() -> FrameTimestamp {
+    FrameTimestamp(unsafe { ferros::arch::cycle_count() } as u32)
+}
+
+impl<'a, 'p> Device<'a> for IpcPhyDevice<'p> {
+    type RxToken = IpcPhyRxToken<'a>;
     type TxToken = IpcPhyTxToken<'a>;
 
     fn receive(&'a mut self) -> Option<(Self::RxToken, Self::TxToken)> {
-        if let Some(data) = self.consumer.poll() {
-            let rx = IpcPhyRxToken(data);
-            let tx = IpcPhyTxToken {
-                producer: &mut self.producer,
-            };
-            Some((rx, tx))
-        } else {
-            None
-        }
+        let IpcPhyDevice {
+            consumer,
+            producer,
+            frame_pool,
+            tap,
+            neighbors,
+            flags,
+            rx_latency,
+            liveness,
+        } = self;
+        let frame_pool: &FramePool = frame_pool;
+        let tap: &CaptureTap = tap;
+        let flags: &FeatureFlags = flags;
+
+        // Static neighbor announcements take priority over the L2 driver's frames
+        let data = match neighbors.next_static_announcement() {
+            Some(frame) => RxFrame::Injected(frame),
+            None => {
+                let handle = consumer.poll()?;
+                if let Some(cycles) = rx_latency.record(&handle, frame_timestamp()) {
+                    log::trace!("[ipc-phy-dev] {} took {} cycles", rx_latency.name, cycles);
+                    if cycles == rx_latency.worst_cycles {
+                        log::debug!("[ipc-phy-dev] New worst case, {}", rx_latency);
+                    }
+                }
+                RxFrame::Pooled(handle)
+            }
+        };
+        let rx = IpcPhyRxToken {
+            data: Some(data),
+            frame_pool,
+            tap,
+            neighbors,
+        };
+        let tx = IpcPhyTxToken {
+            producer,
+            frame_pool,
+            tap,
+            flags,
+            liveness,
+        };
+        Some((rx, tx))
     }
 
     fn transmit(&'a mut self) -> Option<Self::TxToken> {
         Some(IpcPhyTxToken {
             producer: &mut self.producer,
+            frame_pool: &self.frame_pool,
+            tap: &self.tap,
+            flags: self.flags,
+            liveness: self.liveness,
         })
     }
 
@@ -54,25 +113,74 @@ impl<'a> Device<'a> for IpcPhyDevice {
     }
 }
 
-pub struct IpcPhyRxToken(IpcEthernetFrame);
+/// A received frame, either from the L2 driver or injected locally
+enum RxFrame {
+    Pooled(FrameHandle),
+    Injected(IpcEthernetFrame),
+}
+
+impl fmt::Display for RxFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RxFrame::Pooled(handle) => handle.fmt(f),
+            RxFrame::Injected(frame) => frame.fmt(f),
+        }
+    }
+}
+
+pub struct IpcPhyRxToken<'a> {
+    data: Option<RxFrame>,
+    frame_pool: &'a FramePool<'a>,
+    tap: &'a CaptureTap,
+    neighbors: &'a mut NeighborTable,
+}
 
-impl RxToken for IpcPhyRxToken {
+impl<'a> RxToken for IpcPhyRxToken<'a> {
     fn consume<R, F>(mut self, timestamp: Instant, f: F) -> Result<R, Error>
     where
         F: FnOnce(&mut [u8]) -> Result<R, Error>,
     {
+        let data = self.data.take().expect("Rx token is consumed once");
         log::trace!(
             "[ipc-phy-dev] [{}] Receiving {} from L2 driver",
             timestamp,
-            self.0
+            data
         );
-        let result = f(self.0.as_mut_slice());
-        result
+
+        let tap = self.tap;
+        let neighbors = &mut *self.neighbors;
+        let receive = |data: &mut [u8]| {
+            tap.tap(timestamp.total_millis(), CaptureDirection::Rx, data);
+            neighbors.observe(timestamp.total_millis() as u64, data);
+            f(data)
+        };
+
+        match data {
+            RxFrame::Pooled(mut handle) => {
+                let result = receive(self.frame_pool.frame_mut(&mut handle));
+                self.frame_pool.free(handle);
+                result
+            }
+            RxFrame::Injected(mut frame) => receive(frame.as_mut_slice()),
+        }
+    }
+}
+
+impl<'a> Drop for IpcPhyRxToken<'a> {
+    fn drop(&mut self) {
+        // Give the buffer back if smoltcp didn't consume the token
+        if let Some(RxFrame::Pooled(handle)) = self.data.take() {
+            self.frame_pool.free(handle);
+        }
     }
 }
 
 pub struct IpcPhyTxToken<'a> {
-    producer: &'a mut Producer<role::Local, IpcEthernetFrame>,
+    producer: &'a mut Producer<role::Local, FrameHandle>,
+    frame_pool: &'a FramePool<'a>,
+    tap: &'a CaptureTap,
+    flags: &'a FeatureFlags,
+    liveness: &'a LivenessPage,
 }
 
 impl<'a> TxToken for IpcPhyTxToken<'a> {
@@ -80,7 +188,16 @@ impl<'a> TxToken for IpcPhyTxToken<'a> {
     where
         F: FnOnce(&mut [u8]) -> Result<R, Error>,
     {
-        let mut data = IpcEthernetFrame::new();
+        let mut data = match self.frame_pool.alloc() {
+            Some(handle) => handle,
+            None => {
+                log::warn!(
+                    "[ipc-phy-dev] [{}] Frame pool exhausted, dropping tx frame",
+                    timestamp
+                );
+                return Err(Error::Exhausted);
+            }
+        };
         data.truncate(len);
 
         log::trace!(
@@ -89,16 +206,33 @@ impl<'a> TxToken for IpcPhyTxToken<'a> {
             data,
         );
 
-        let result = f(data.as_mut_slice());
+        let result = f(self.frame_pool.frame_mut(&mut data));
+
+        if result.is_err() {
+            self.frame_pool.free(data);
+            return result;
+        }
+
+        self.tap.tap(
+            timestamp.total_millis(),
+            CaptureDirection::Tx,
+            self.frame_pool.frame(&data),
+        );
+
+        if self.flags.is_enabled::<entry::FlagFrameTimestamps>() {
+            data.stamp(frame_timestamp());
+        }
 
-        if result.is_ok() && self.producer.send(data).is_err() {
+        if let Err(e) = self.producer.send(data) {
             // Drop the data if the queue is full
             log::warn!(
-                "[ipc-phy-dev] [{}] Rejected sending IpcEthernetFrame data to L2 driver",
+                "[ipc-phy-dev] [{}] Rejected sending FrameHandle to L2 driver",
                 timestamp
             );
+            self.frame_pool.free(e.into_inner());
             return Err(Error::Exhausted);
         }
+        self.liveness.submitted(enet::TX_FRAMES);
 
         result
     }
@@ -1,24 +1,37 @@
 #![no_std]
 
+use config_service::{ConfigChange, FeatureFlagsSizeBits};
 use ferros::cap::{role, CNodeRole};
-use ferros::userland::{Consumer1, Producer, RetypeForSetup};
+use ferros::pow::Pow2Bytes;
+use ferros::userland::{
+    BuildMetadata, Consumer1, Consumer4, Correlated, Producer, RetypeForSetup,
+    SequenceCounterSizeBits,
+};
 use ferros::vspace::{shared_status, MappedMemoryRegion};
 use imx6_hal::pac::gpt::{self, GPT};
-use net_types::{EthernetAddress, IpcEthernetFrame, IpcUdpTransmitBuffer, Ipv4Address, MtuSize};
+use liveness::LivenessSizeBits;
+use net_types::{
+    ControlRequest, ControlResponse, EthernetAddress, FrameHandle, FramePoolMemSizeBits,
+    IpcCapturedFrame, IpcUdpReceiveBuffer, IpcUdpTransmitBuffer, Ipv4Address, MtuSize, Port,
+};
 use static_assertions::const_assert;
 use typenum::{op, Unsigned, U1, U12, U2};
 
 /// Rx/Tx socket buffer size, 4K each, ~2 MTU/frames
 pub type SocketBufferSizeBits = U12;
-pub type SocketBufferSize = op!(U1 << SocketBufferSizeBits);
+pub type SocketBufferSize = Pow2Bytes<SocketBufferSizeBits>;
 pub type RxTxSocketBufferSizeBits = op!(SocketBufferSizeBits + U1);
-pub type RxTxSocketBufferSize = op!(U1 << RxTxSocketBufferSizeBits);
+pub type RxTxSocketBufferSize = Pow2Bytes<RxTxSocketBufferSizeBits>;
 
 pub type MtuSize2x = op!(MtuSize * U2);
 const_assert!(SocketBufferSize::USIZE >= MtuSize2x::USIZE);
 pub type MtuSize4x = op!(MtuSize2x * U2);
 const_assert!(RxTxSocketBufferSize::USIZE >= MtuSize4x::USIZE);
 
+/// Socket buffer memory for the two UDP sockets (ephemeral and service port),
+/// each split in half for rx and tx by the driver
+pub type SocketBufferMemSizeBits = op!(RxTxSocketBufferSizeBits + U1);
+
 #[repr(C)]
 pub struct ProcParams<Role: CNodeRole> {
     /// General purpose timer provides a time domain
@@ -26,24 +39,64 @@ pub struct ProcParams<Role: CNodeRole> {
     pub gpt: GPT,
 
     /// Consumer of Ethernet frames from a L2 driver
-    pub frame_consumer: Consumer1<Role, IpcEthernetFrame>,
+    pub frame_consumer: Consumer1<Role, FrameHandle>,
 
     /// Producer of Ethernet frames destined to a L2 driver
-    pub frame_producer: Producer<Role, IpcEthernetFrame>,
+    pub frame_producer: Producer<Role, FrameHandle>,
+
+    /// The Ethernet frame pool shared with the L2 driver, the frames
+    /// in both queues live here
+    pub frame_pool_mem: MappedMemoryRegion<FramePoolMemSizeBits, shared_status::Shared>,
 
     /// The event consumer handles:
     /// - GPT IRQ notification events (via Waker)
-    /// - UDP transmit buffers
-    pub event_consumer: Consumer1<Role, IpcUdpTransmitBuffer, gpt::Irq>,
+    /// - UDP transmit buffers, sent from an ephemeral port
+    /// - UDP transmit buffers, sent from the service port
+    /// - Management requests
+    /// - Changes to the net configuration entries
+    pub event_consumer: Consumer4<
+        Role,
+        Correlated<IpcUdpTransmitBuffer>,
+        IpcUdpTransmitBuffer,
+        ControlRequest,
+        ConfigChange,
+        gpt::Irq,
+    >,
+
+    /// Producer of responses to management requests
+    pub control_producer: Producer<Role, ControlResponse>,
+
+    /// Producer of sampled Ethernet frames destined to a capture process
+    pub capture_producer: Producer<Role, IpcCapturedFrame>,
+
+    /// Producer of UDP datagrams received on the service port
+    pub udp_rx_producer: Producer<Role, IpcUdpReceiveBuffer>,
 
-    /// Memory for the socket buffers, split in half for rx and tx by the driver
-    pub socket_buffer_mem: MappedMemoryRegion<RxTxSocketBufferSizeBits, shared_status::Exclusive>,
+    /// Local UDP port the service socket is bound to
+    pub udp_service_port: Port,
+
+    /// Memory for the socket buffers, split up for each socket's rx and tx
+    /// by the driver
+    pub socket_buffer_mem: MappedMemoryRegion<SocketBufferMemSizeBits, shared_status::Exclusive>,
 
     /// Hardware MAC address
     pub mac_addr: EthernetAddress,
 
-    /// IPv4 address
+    /// IPv4 address, until the config service says otherwise
     pub ip_addr: Ipv4Address,
+
+    /// The system-wide event sequence counter page, stamps log records
+    pub sequence_counter_mem: MappedMemoryRegion<SequenceCounterSizeBits, shared_status::Shared>,
+
+    /// The feature flags page, mapped read-only
+    pub feature_flags_mem: MappedMemoryRegion<FeatureFlagsSizeBits, shared_status::Shared>,
+
+    /// The liveness page, beats the stack's tick and counts the frames
+    /// sent to the L2 driver
+    pub liveness_mem: MappedMemoryRegion<LivenessSizeBits, shared_status::Shared>,
+
+    /// The root task's build metadata, checked against this process' own
+    pub root_build: BuildMetadata,
 }
 
 impl RetypeForSetup for ProcParams<role::Local> {
@@ -3,49 +3,90 @@
 
 use selfe_runtime as _;
 
+use crate::capture_tap::CaptureTap;
 use crate::ipc_phy_dev::IpcPhyDevice;
+use crate::neighbor_table::NeighborTable;
+use config_service::{entry, ConfigChange, ConfigEntry, FeatureFlags};
 use debug_logger::DebugLogger;
 use ferros::cap::role;
-use imx6_hal::{
-    embedded_hal::timer::CountDown,
-    timer::{Event as TimerEvent, Hertz, Timer},
+use ferros::userland::{Producer, SequenceCounter};
+use imx6_hal::{periodic::PeriodicTask, timer::Timer};
+use liveness::system::tcpip::TICK;
+use liveness::LivenessPage;
+use net_types::{
+    ControlRequest, ControlResponse, EthernetFrameBuffer, FramePool, HopLatency,
+    IpcUdpReceiveBuffer, IpcUdpTransmitBuffer, Ipv4Address, MtuSize, Port,
 };
-use net_types::IpcUdpTransmitBuffer;
 use smoltcp::iface::{EthernetInterface, EthernetInterfaceBuilder, NeighborCache, Routes};
 use smoltcp::socket::{SocketHandle, SocketSet, UdpPacketMetadata, UdpSocket, UdpSocketBuffer};
 use smoltcp::time::Instant;
-use smoltcp::wire::{IpCidr, IpEndpoint};
+use smoltcp::wire::{IpAddress, IpCidr, IpEndpoint};
 use tcpip::ProcParams;
+use typenum::Unsigned;
 
+mod capture_tap;
 mod ipc_phy_dev;
+mod neighbor_table;
 
 /// Maximum number of ARP (Neighbor) cache entries
 /// available in the storage
 const MAX_ARP_ENTRIES: usize = 8;
 
+/// Prefix length of the IPv4 subnet, until the config service says otherwise
+const IP_PREFIX_LEN: u8 = entry::NetPrefixLen::DEFAULT;
+
 const EPHEMERAL_PORT: u16 = 49152;
 
-const TIMER_RATE: Hertz = Hertz(100);
-const TIMER_MS_PER_TICK: u32 = 1000 / TIMER_RATE.0;
+/// Number of datagrams the service socket can hold in each direction
+const SERVICE_SOCKET_PACKETS: usize = 4;
 
 static LOGGER: DebugLogger = DebugLogger;
 
+mod build_metadata {
+    include!(concat!(env!("OUT_DIR"), "/build_metadata.rs"));
+}
+
 #[allow(improper_ctypes_definitions)]
 #[no_mangle]
 pub extern "C" fn _start(params: ProcParams<role::Local>) -> ! {
     log::set_logger(&LOGGER)
         .map(|()| log::set_max_level(DebugLogger::max_log_level_from_env()))
         .unwrap();
+    DebugLogger::set_sequence_counter(SequenceCounter::from_region(params.sequence_counter_mem));
+    let feature_flags = FeatureFlags::from_region(params.feature_flags_mem);
+    DebugLogger::set_verbose_flag(feature_flags.flag::<entry::FlagVerboseTracing>());
+    let liveness = LivenessPage::from_region(params.liveness_mem);
 
     log::debug!("[tcpip-driver] Process started");
 
+    if let Err(skew) = build_metadata::BUILD_METADATA.check_skew(&params.root_build) {
+        log::warn!("[tcpip-driver] Built differently than the root task: {:?}", skew);
+    }
+
+    let mut frame_pool_mem = params.frame_pool_mem;
+    let frame_pool = unsafe { FramePool::attach(frame_pool_mem.as_mut_slice()) };
+    log::trace!("[tcpip-driver] {}", frame_pool);
+
     let ipc_phy = IpcPhyDevice {
         consumer: params.frame_consumer,
         producer: params.frame_producer,
+        frame_pool,
+        tap: CaptureTap::new(params.capture_producer, feature_flags),
+        neighbors: NeighborTable::new(params.ip_addr, IP_PREFIX_LEN, params.mac_addr),
+        flags: feature_flags,
+        rx_latency: HopLatency::new("enet->tcpip"),
+        liveness,
     };
 
+    // For timestamping frames, the kernel exports the cycle counter as it
+    // does for the ENET driver's deadline monitor
+    unsafe { ferros::arch::enable_cycle_counter() };
+
     // Build the IP stack
-    let ip_addr = IpCidr::new(smoltcp::wire::Ipv4Address(params.ip_addr.into()).into(), 24);
+    let ip_addr = IpCidr::new(
+        smoltcp::wire::Ipv4Address(params.ip_addr.into()).into(),
+        IP_PREFIX_LEN,
+    );
     let mut ip_addrs = [ip_addr];
     let mut neighbor_storage = [None; MAX_ARP_ENTRIES];
     let neighbor_cache = NeighborCache::new(&mut neighbor_storage[..]);
@@ -61,14 +102,16 @@ pub extern "C" fn _start(params: ProcParams<role::Local>) -> ! {
         .routes(routes)
         .finalize();
 
-    // Only capacity for a single UDP socket
-    let mut sockets_storage = [None];
+    // Capacity for the ephemeral and service UDP sockets
+    let mut sockets_storage = [None, None];
     let mut sockets = SocketSet::new(&mut sockets_storage[..]);
 
-    // Split up the memory for socket rx/tx buffers
+    // Split up the memory for each socket's rx/tx buffers
     let socket_mem = params.socket_buffer_mem;
     socket_mem.flush().unwrap();
+    let (socket_mem, service_socket_mem) = socket_mem.split().unwrap();
     let (mut rx_mem, mut tx_mem) = socket_mem.split().unwrap();
+    let (mut service_rx_mem, mut service_tx_mem) = service_socket_mem.split().unwrap();
 
     let mut rx_meta = [UdpPacketMetadata::EMPTY];
     let mut tx_meta = [UdpPacketMetadata::EMPTY];
@@ -86,66 +129,136 @@ pub extern "C" fn _start(params: ProcParams<role::Local>) -> ! {
         .bind(EPHEMERAL_PORT)
         .unwrap();
 
-    let mut timer = Timer::new(params.gpt);
-    timer.start(TIMER_RATE);
-    timer.listen(TimerEvent::TimeOut);
+    // The service handle receives datagrams on the service port and
+    // fulfills the transmits replying to them
+    let mut service_rx_meta = [UdpPacketMetadata::EMPTY; SERVICE_SOCKET_PACKETS];
+    let mut service_tx_meta = [UdpPacketMetadata::EMPTY; SERVICE_SOCKET_PACKETS];
+    let service_socket = UdpSocket::new(
+        UdpSocketBuffer::new(&mut service_rx_meta[..], service_rx_mem.as_mut_slice()),
+        UdpSocketBuffer::new(&mut service_tx_meta[..], service_tx_mem.as_mut_slice()),
+    );
+
+    let service_handle = sockets.add(service_socket);
+
+    sockets
+        .get::<UdpSocket>(service_handle)
+        .bind(params.udp_service_port.0)
+        .unwrap();
+
+    let ticker = PeriodicTask::new(Timer::new(params.gpt), TICK.period_ms);
 
     log::debug!(
-        "[tcpip-driver] TCP/IP stack is up IP={} MAC={}",
+        "[tcpip-driver] TCP/IP stack is up IP={} MAC={} UDP service port={}",
         params.ip_addr,
-        params.mac_addr
+        params.mac_addr,
+        params.udp_service_port,
     );
 
     let initial_state = Driver {
         iface,
         sockets,
         udp_handle,
-        timer,
-        timer_ms: 0,
+        service_handle,
+        udp_rx_producer: params.udp_rx_producer,
+        control_producer: params.control_producer,
+        ticker,
+        liveness,
+        ip_addr: params.ip_addr,
+        prefix_len: IP_PREFIX_LEN,
     };
 
     params.event_consumer.consume(
         initial_state,
         |mut state| {
-            // Non-queue wakeup event
+            // Non-queue wakeup event, the timer interrupt among others
+            state.tick();
 
-            // Ack timer interrupt
-            state.ack_timer_irq();
+            state
+        },
+        |udp_transmit_buffer, mut state| {
+            // UDP transmit buffer queue, handled under the console
+            // command's correlation id
+            udp_transmit_buffer.handle(|udp_transmit_buffer| {
+                log::trace!("[tcpip-driver] Processing {}", udp_transmit_buffer);
+                state.handle_udp_tx_buffer(udp_transmit_buffer);
 
-            // Service the IP stack,
-            state.poll();
+                // Service the IP stack,
+                state.poll();
+            });
 
             state
         },
         |udp_transmit_buffer, mut state| {
-            // UDP transmit buffer queue
-            log::trace!("[tcpip-driver] Processing {}", udp_transmit_buffer);
-            state.handle_udp_tx_buffer(udp_transmit_buffer);
+            // UDP service port transmit buffer queue
+            log::trace!("[tcpip-driver] Processing service {}", udp_transmit_buffer);
+            state.handle_udp_service_tx_buffer(udp_transmit_buffer);
 
             // Service the IP stack,
             state.poll();
 
+            state
+        },
+        |control_request, mut state| {
+            // Management request queue
+            log::trace!("[tcpip-driver] Processing {:?}", control_request);
+            state.handle_control_request(control_request);
+
+            // Service the IP stack, static neighbors may need announcing
+            state.poll();
+
+            state
+        },
+        |config_change, mut state| {
+            // Configuration change queue
+            log::trace!("[tcpip-driver] Processing {}", config_change);
+            state.handle_config_change(config_change);
+
             state
         },
     );
 }
 
 struct Driver<'a> {
-    iface: EthernetInterface<'a, IpcPhyDevice>,
+    iface: EthernetInterface<'a, IpcPhyDevice<'a>>,
     sockets: SocketSet<'a>,
     udp_handle: SocketHandle,
-    timer: Timer,
-    timer_ms: i64,
+    service_handle: SocketHandle,
+    udp_rx_producer: Producer<role::Local, IpcUdpReceiveBuffer>,
+    control_producer: Producer<role::Local, ControlResponse>,
+    ticker: PeriodicTask,
+    liveness: &'static LivenessPage,
+    ip_addr: Ipv4Address,
+    prefix_len: u8,
 }
 
 impl<'a> Driver<'a> {
-    pub fn ack_timer_irq(&mut self) {
-        self.timer.wait().ok();
-        self.timer_ms = self.timer_ms.wrapping_add(TIMER_MS_PER_TICK.into());
+    pub fn tick(&mut self) {
+        let activation = match self.ticker.activate() {
+            Some(activation) => activation,
+            None => {
+                // Not the timer, but the IP stack may have work to do anyway
+                self.poll();
+                return;
+            }
+        };
+
+        self.iface.device_mut().neighbors.tick(activation.now_ms);
+        self.poll();
+        self.liveness.beat(TICK);
+
+        let report = self.ticker.finish(activation);
+        if report.overrun {
+            log::warn!(
+                "[tcpip-driver] Tick overran its {}ms period, ran for {}us, {}",
+                TICK.period_ms,
+                report.run_us,
+                self.ticker.metrics()
+            );
+        }
     }
 
     pub fn get_time(&self) -> Instant {
-        Instant::from_millis(self.timer_ms)
+        Instant::from_millis(self.ticker.now_ms() as i64)
     }
 
     pub fn poll(&mut self) {
@@ -153,6 +266,42 @@ impl<'a> Driver<'a> {
         if let Err(e) = self.iface.poll(&mut self.sockets, time) {
             log::trace!("[tcpip-driver] {:?}", e);
         }
+
+        self.forward_service_rx();
+    }
+
+    /// Hand off any datagrams received on the service port to the consumer
+    fn forward_service_rx(&mut self) {
+        let mut socket = self.sockets.get::<UdpSocket>(self.service_handle);
+        while let Ok((data, endpoint)) = socket.recv() {
+            let src_addr = match endpoint.addr {
+                IpAddress::Ipv4(addr) => addr.0,
+                _ => continue,
+            };
+
+            if data.len() > MtuSize::USIZE {
+                log::warn!(
+                    "[tcpip-driver] Dropping oversized UDP datagram from {}, {} bytes",
+                    endpoint,
+                    data.len()
+                );
+                continue;
+            }
+
+            let mut udp_rx = IpcUdpReceiveBuffer {
+                src_addr: src_addr.into(),
+                src_port: Port(endpoint.port),
+                frame: EthernetFrameBuffer::new(),
+            };
+            udp_rx.frame.truncate(data.len());
+            udp_rx.frame.as_mut_slice().copy_from_slice(data);
+
+            log::trace!("[tcpip-driver] Forwarding {}", udp_rx);
+
+            if self.udp_rx_producer.send(udp_rx).is_err() {
+                log::warn!("[tcpip-driver] Rejected sending IpcUdpReceiveBuffer to consumer");
+            }
+        }
     }
 
     pub fn handle_udp_tx_buffer(&mut self, udp_tx: IpcUdpTransmitBuffer) {
@@ -169,4 +318,70 @@ impl<'a> Driver<'a> {
             log::warn!("[tcpip-driver] Failed to send UDP transmit buffer, {}", e);
         }
     }
+
+    pub fn handle_control_request(&mut self, request: ControlRequest) {
+        let now_ms = self.ticker.now_ms();
+        let response = match request {
+            ControlRequest::Capture(config) => {
+                self.iface.device().tap.configure(config);
+                return;
+            }
+            ControlRequest::ListNeighbors => {
+                ControlResponse::Neighbors(self.iface.device().neighbors.list(now_ms))
+            }
+            ControlRequest::AddStaticNeighbor(ip_addr, mac_addr) => {
+                let result = self
+                    .iface
+                    .device_mut()
+                    .neighbors
+                    .add_static(ip_addr, mac_addr);
+                log::debug!("[tcpip-driver] Add static neighbor {:?}", result);
+                ControlResponse::StaticNeighborAdded(result)
+            }
+        };
+
+        if self.control_producer.send(response).is_err() {
+            log::warn!("[tcpip-driver] Rejected sending ControlResponse");
+        }
+    }
+
+    pub fn handle_config_change(&mut self, change: ConfigChange) {
+        if let Some(ip_addr) = change.get::<entry::NetIpAddr>() {
+            self.ip_addr = ip_addr;
+        } else if let Some(prefix_len) = change.get::<entry::NetPrefixLen>() {
+            self.prefix_len = prefix_len;
+        } else {
+            return;
+        }
+
+        let ip_cidr = IpCidr::new(
+            smoltcp::wire::Ipv4Address(self.ip_addr.0).into(),
+            self.prefix_len,
+        );
+        self.iface.update_ip_addrs(|ip_addrs| ip_addrs[0] = ip_cidr);
+        self.iface
+            .device_mut()
+            .neighbors
+            .set_ip_addr(self.ip_addr, self.prefix_len);
+
+        log::info!("[tcpip-driver] IP address is now {}", ip_cidr);
+    }
+
+    pub fn handle_udp_service_tx_buffer(&mut self, udp_tx: IpcUdpTransmitBuffer) {
+        let endpoint = IpEndpoint::new(
+            smoltcp::wire::Ipv4Address(udp_tx.dst_addr.0).into(),
+            udp_tx.dst_port.0,
+        );
+
+        if let Err(e) = self
+            .sockets
+            .get::<UdpSocket>(self.service_handle)
+            .send_slice(udp_tx.frame.as_slice(), endpoint)
+        {
+            log::warn!(
+                "[tcpip-driver] Failed to send UDP service transmit buffer, {}",
+                e
+            );
+        }
+    }
 }
@@ -0,0 +1,283 @@
+//! A mirror of the smoltcp neighbor cache, which isn't accessible through
+//! the interface, maintained from the same frames smoltcp fills it from.
+//!
+//! Static entries are kept alive in the smoltcp cache by periodically
+//! injecting an ARP reply from the static neighbor on the receive path.
+
+use net_types::{
+    EthernetAddress, IpcEthernetFrame, Ipv4Address, NeighborEntry, NeighborError, NeighborKind,
+    NeighborList, MAX_NEIGHBOR_ENTRIES, MAX_STATIC_NEIGHBOR_ENTRIES,
+};
+use smoltcp::wire::{
+    ArpOperation, ArpPacket, ArpRepr, EthernetFrame, EthernetProtocol, Ipv4Packet,
+    ETHERNET_HEADER_LEN,
+};
+
+/// Matches smoltcp's neighbor cache entry lifetime
+const ENTRY_LIFETIME_MS: u64 = 60_000;
+
+/// Static entries are re-asserted well within the entry lifetime
+const STATIC_REFRESH_INTERVAL_MS: u64 = ENTRY_LIFETIME_MS / 3;
+
+/// Size of an ARP packet for Ethernet/IPv4
+const ARP_PACKET_LEN: usize = 28;
+
+pub struct NeighborTable {
+    ip_addr: smoltcp::wire::Ipv4Address,
+    netmask: u32,
+    mac_addr: smoltcp::wire::EthernetAddress,
+    entries: [Option<NeighborEntry>; MAX_NEIGHBOR_ENTRIES],
+    /// Static entries (by index) that still need to be injected
+    refresh_pending: [bool; MAX_NEIGHBOR_ENTRIES],
+    last_refresh_ms: u64,
+}
+
+impl NeighborTable {
+    pub fn new(ip_addr: Ipv4Address, prefix_len: u8, mac_addr: EthernetAddress) -> Self {
+        NeighborTable {
+            ip_addr: smoltcp::wire::Ipv4Address(ip_addr.0),
+            netmask: netmask(prefix_len),
+            mac_addr: smoltcp::wire::EthernetAddress(mac_addr.0),
+            entries: [None; MAX_NEIGHBOR_ENTRIES],
+            refresh_pending: [false; MAX_NEIGHBOR_ENTRIES],
+            last_refresh_ms: 0,
+        }
+    }
+
+    /// Move the table to a new interface address, entries outside the new
+    /// subnet are dropped, static or not
+    pub fn set_ip_addr(&mut self, ip_addr: Ipv4Address, prefix_len: u8) {
+        self.ip_addr = smoltcp::wire::Ipv4Address(ip_addr.0);
+        self.netmask = netmask(prefix_len);
+        while let Some(idx) = self
+            .entries
+            .iter()
+            .position(|e| matches!(e, Some(e) if !self.in_subnet(e.ip_addr)))
+        {
+            self.entries[idx] = None;
+            self.refresh_pending[idx] = false;
+        }
+    }
+
+    pub fn list(&self, now_ms: u64) -> NeighborList {
+        NeighborList {
+            now_ms,
+            entries: self.entries,
+        }
+    }
+
+    pub fn add_static(
+        &mut self,
+        ip_addr: Ipv4Address,
+        mac_addr: EthernetAddress,
+    ) -> Result<NeighborEntry, NeighborError> {
+        if !smoltcp::wire::Ipv4Address(ip_addr.0).is_unicast()
+            || !smoltcp::wire::EthernetAddress(mac_addr.0).is_unicast()
+        {
+            return Err(NeighborError::NotUnicast);
+        }
+        if !self.in_subnet(ip_addr) {
+            return Err(NeighborError::NotInSubnet);
+        }
+
+        let entry = NeighborEntry {
+            ip_addr,
+            mac_addr,
+            kind: NeighborKind::Static,
+        };
+
+        // Replace an existing entry for the address, static or not
+        let existing = self.position(ip_addr);
+        let replaces_static = matches!(
+            existing.and_then(|idx| self.entries[idx]),
+            Some(NeighborEntry {
+                kind: NeighborKind::Static,
+                ..
+            })
+        );
+        if !replaces_static && self.static_count() >= MAX_STATIC_NEIGHBOR_ENTRIES {
+            return Err(NeighborError::TableFull);
+        }
+        let idx = match existing {
+            Some(idx) => idx,
+            None => self
+                .free_or_oldest_dynamic()
+                .ok_or(NeighborError::TableFull)?,
+        };
+
+        self.entries[idx] = Some(entry);
+        self.refresh_pending[idx] = true;
+        Ok(entry)
+    }
+
+    /// Expire dynamic entries and schedule the periodic refresh of static ones
+    pub fn tick(&mut self, now_ms: u64) {
+        for entry in self.entries.iter_mut() {
+            if let Some(NeighborEntry {
+                kind: NeighborKind::Dynamic { expires_at_ms },
+                ..
+            }) = entry
+            {
+                if *expires_at_ms <= now_ms {
+                    *entry = None;
+                }
+            }
+        }
+
+        if now_ms.saturating_sub(self.last_refresh_ms) >= STATIC_REFRESH_INTERVAL_MS {
+            self.last_refresh_ms = now_ms;
+            for (idx, entry) in self.entries.iter().enumerate() {
+                if let Some(NeighborEntry {
+                    kind: NeighborKind::Static,
+                    ..
+                }) = entry
+                {
+                    self.refresh_pending[idx] = true;
+                }
+            }
+        }
+    }
+
+    /// An ARP reply from a static neighbor still waiting to be handed to
+    /// smoltcp on the receive path
+    pub fn next_static_announcement(&mut self) -> Option<IpcEthernetFrame> {
+        let idx = self.refresh_pending.iter().position(|pending| *pending)?;
+        self.refresh_pending[idx] = false;
+        let entry = self.entries[idx]?;
+
+        let mut data = IpcEthernetFrame::new();
+        data.truncate(ETHERNET_HEADER_LEN + ARP_PACKET_LEN);
+
+        let source_hardware_addr = smoltcp::wire::EthernetAddress(entry.mac_addr.0);
+        let mut frame = EthernetFrame::new_unchecked(data.as_mut_slice());
+        frame.set_dst_addr(self.mac_addr);
+        frame.set_src_addr(source_hardware_addr);
+        frame.set_ethertype(EthernetProtocol::Arp);
+
+        let arp = ArpRepr::EthernetIpv4 {
+            operation: ArpOperation::Reply,
+            source_hardware_addr,
+            source_protocol_addr: smoltcp::wire::Ipv4Address(entry.ip_addr.0),
+            target_hardware_addr: self.mac_addr,
+            target_protocol_addr: self.ip_addr,
+        };
+        arp.emit(&mut ArpPacket::new_unchecked(frame.payload_mut()));
+
+        Some(data)
+    }
+
+    /// Learn from a received frame the way smoltcp fills its cache:
+    /// the sender of any ARP packet, and the source of IPv4 packets
+    /// within the subnet
+    pub fn observe(&mut self, now_ms: u64, data: &[u8]) {
+        let frame = match EthernetFrame::new_checked(data) {
+            Ok(frame) => frame,
+            Err(_) => return,
+        };
+        if !frame.src_addr().is_unicast() {
+            return;
+        }
+
+        let ip_addr = match frame.ethertype() {
+            EthernetProtocol::Arp => match ArpPacket::new_checked(frame.payload())
+                .and_then(|packet| ArpRepr::parse(&packet))
+            {
+                Ok(ArpRepr::EthernetIpv4 {
+                    source_hardware_addr,
+                    source_protocol_addr,
+                    ..
+                }) if source_hardware_addr.is_unicast() && source_protocol_addr.is_unicast() => {
+                    self.learn(now_ms, source_protocol_addr.0, source_hardware_addr.0);
+                    return;
+                }
+                _ => return,
+            },
+            EthernetProtocol::Ipv4 => match Ipv4Packet::new_checked(frame.payload()) {
+                Ok(packet) if packet.src_addr().is_unicast() => packet.src_addr(),
+                _ => return,
+            },
+            _ => return,
+        };
+
+        if self.in_subnet(Ipv4Address(ip_addr.0)) {
+            self.learn(now_ms, ip_addr.0, frame.src_addr().0);
+        }
+    }
+
+    fn learn(&mut self, now_ms: u64, ip_addr: [u8; 4], mac_addr: [u8; 6]) {
+        let ip_addr = Ipv4Address(ip_addr);
+        let entry = NeighborEntry {
+            ip_addr,
+            mac_addr: EthernetAddress(mac_addr),
+            kind: NeighborKind::Dynamic {
+                expires_at_ms: now_ms + ENTRY_LIFETIME_MS,
+            },
+        };
+
+        let idx = match self.position(ip_addr) {
+            Some(idx) => idx,
+            None => match self.free_or_oldest_dynamic() {
+                Some(idx) => idx,
+                None => return,
+            },
+        };
+
+        // Static entries are configured, never learned over
+        if let Some(NeighborEntry {
+            kind: NeighborKind::Static,
+            ..
+        }) = self.entries[idx]
+        {
+            return;
+        }
+
+        self.entries[idx] = Some(entry);
+    }
+
+    fn in_subnet(&self, ip_addr: Ipv4Address) -> bool {
+        let addr = u32::from_be_bytes(ip_addr.0);
+        let ours = u32::from_be_bytes(self.ip_addr.0);
+        (addr & self.netmask) == (ours & self.netmask)
+    }
+
+    fn position(&self, ip_addr: Ipv4Address) -> Option<usize> {
+        self.entries
+            .iter()
+            .position(|e| matches!(e, Some(e) if e.ip_addr == ip_addr))
+    }
+
+    fn static_count(&self) -> usize {
+        self.entries
+            .iter()
+            .flatten()
+            .filter(|e| e.kind == NeighborKind::Static)
+            .count()
+    }
+
+    /// A free slot, or the dynamic entry closest to expiring, like the
+    /// smoltcp cache does when it's full
+    fn free_or_oldest_dynamic(&self) -> Option<usize> {
+        if let Some(idx) = self.entries.iter().position(|e| e.is_none()) {
+            return Some(idx);
+        }
+
+        self.entries
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, e)| match e {
+                Some(NeighborEntry {
+                    kind: NeighborKind::Dynamic { expires_at_ms },
+                    ..
+                }) => Some((idx, *expires_at_ms)),
+                _ => None,
+            })
+            .min_by_key(|(_, expires_at_ms)| *expires_at_ms)
+            .map(|(idx, _)| idx)
+    }
+}
+
+fn netmask(prefix_len: u8) -> u32 {
+    u32::MAX
+        .checked_shl(32 - u32::from(prefix_len))
+        .unwrap_or(0)
+}
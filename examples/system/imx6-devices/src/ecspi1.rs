@@ -4,9 +4,9 @@
 use core::mem;
 use core::ops::{Deref, DerefMut};
 use static_assertions::const_assert_eq;
-use typenum::{Unsigned, U63};
+use typenum::Unsigned;
 
-pub type Irq = U63;
+pub use crate::interrupts::ecspi1::{Irq, IrqBadgeBits};
 
 register! {
     Rx,
@@ -4,9 +4,9 @@
 use core::mem;
 use core::ops::{Deref, DerefMut};
 use static_assertions::const_assert_eq;
-use typenum::{Unsigned, U150};
+use typenum::Unsigned;
 
-pub type Irq = U150;
+pub use crate::interrupts::enet::{Irq, IrqBadgeBits};
 
 register! {
     InterruptEvent,
@@ -4,9 +4,9 @@
 use core::mem;
 use core::ops::{Deref, DerefMut};
 use static_assertions::const_assert_eq;
-use typenum::{Unsigned, U87};
+use typenum::Unsigned;
 
-pub type Irq = U87;
+pub use crate::interrupts::gpt::{Irq, IrqBadgeBits};
 
 register! {
     Control,
@@ -0,0 +1,51 @@
+//! The interrupts of the devices in this crate.
+//! See [IMX6DQRM](http://cache.freescale.com/files/32bit/doc/ref_manual/IMX6DQRM.pdf) chapter 3.
+//!
+//! Each device module's `Irq` comes from here, so the numbers are only
+//! written down once.
+
+/// Define a module per device of a platform interrupt map, each with the
+/// device's typed interrupt number as `Irq` and the badge bits expected on
+/// its interrupt notifications as `IrqBadgeBits`, along with a table of
+/// them all as `ALL`.
+///
+/// ```ignore
+/// interrupt_map! {
+///     /// UART1
+///     uart1 = U58,
+/// }
+/// ```
+#[macro_export]
+macro_rules! interrupt_map {
+    ($($(#[$meta:meta])* $device:ident = $irq:ident,)*) => {
+        $(
+            $(#[$meta])*
+            pub mod $device {
+                pub type Irq = $crate::typenum::$irq;
+
+                /// Expected badge value on IRQ notifications
+                pub type IrqBadgeBits = Irq;
+
+                pub const IRQ: usize = <Irq as $crate::typenum::Unsigned>::USIZE;
+            }
+        )*
+
+        /// Every device's name and interrupt number
+        pub const ALL: &[(&str, usize)] = &[$((stringify!($device), $device::IRQ)),*];
+    };
+}
+
+interrupt_map! {
+    /// UART1
+    uart1 = U58,
+    /// ECSPI1
+    ecspi1 = U63,
+    /// GPT
+    gpt = U87,
+    /// WDOG1
+    wdog1 = U112,
+    /// WDOG2
+    wdog2 = U113,
+    /// ENET
+    enet = U150,
+}
@@ -14,6 +14,7 @@ pub mod ecspi1;
 pub mod enet;
 pub mod gpio;
 pub mod gpt;
+pub mod interrupts;
 pub mod iomuxc;
 pub mod ocotp;
 pub mod uart1;
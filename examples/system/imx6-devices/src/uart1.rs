@@ -4,9 +4,9 @@
 use core::mem;
 use core::ops::{Deref, DerefMut};
 use static_assertions::const_assert_eq;
-use typenum::{Unsigned, U58};
+use typenum::Unsigned;
 
-pub type Irq = U58;
+pub use crate::interrupts::uart1::{Irq, IrqBadgeBits};
 
 register! {
     Rx,
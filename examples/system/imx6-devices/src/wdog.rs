@@ -4,7 +4,7 @@
 use core::mem;
 use core::ops::{Deref, DerefMut};
 use static_assertions::const_assert_eq;
-use typenum::{Unsigned, U112, U113};
+use typenum::Unsigned;
 
 pub const SEQUENCE_A: u16 = 0x5555;
 pub const SEQUENCE_B: u16 = 0xAAAA;
@@ -89,7 +89,7 @@ pub struct RegisterBlock {
 pub mod wdog1 {
     use super::*;
 
-    pub type Irq = U112;
+    pub use crate::interrupts::wdog1::{Irq, IrqBadgeBits};
 
     pub struct WDOG1 {
         vaddr: u32,
@@ -131,7 +131,7 @@ pub mod wdog1 {
 pub mod wdog2 {
     use super::*;
 
-    pub type Irq = U113;
+    pub use crate::interrupts::wdog2::{Irq, IrqBadgeBits};
 
     pub struct WDOG2 {
         vaddr: u32,
@@ -7,16 +7,16 @@ use crate::enet::{Error, MinDescriptors, NumRxDescriptors, NumTxDescriptors};
 use crate::pac::typenum::Unsigned;
 use core::sync::atomic;
 
-pub type RxDmaRing = DmaRing<Rx, { NumRxDescriptors::USIZE }>;
-pub type TxDmaRing = DmaRing<Tx, { NumTxDescriptors::USIZE }>;
+pub type RxDmaRing<'a> = DmaRing<'a, Rx, { NumRxDescriptors::USIZE }>;
+pub type TxDmaRing<'a> = DmaRing<'a, Tx, { NumTxDescriptors::USIZE }>;
 
-pub struct DmaRing<RxTx: sealed::RxTx, const N: usize> {
+pub struct DmaRing<'a, RxTx: sealed::RxTx, const N: usize> {
     next_entry: usize,
-    pub(crate) entries: [RingEntry<RxTx>; N],
+    pub(crate) entries: [RingEntry<'a, RxTx>; N],
 }
 
-impl<RxTx: sealed::RxTx, const N: usize> DmaRing<RxTx, N> {
-    pub fn new(entries: [RingEntry<RxTx>; N]) -> Result<Self, Error> {
+impl<'a, RxTx: sealed::RxTx, const N: usize> DmaRing<'a, RxTx, N> {
+    pub fn new(entries: [RingEntry<'a, RxTx>; N]) -> Result<Self, Error> {
         log::trace!("[enet] creating DMA ring len={}", N);
         if N < MinDescriptors::USIZE {
             Err(Error::NotEnoughDescriptors)
@@ -49,7 +49,7 @@ impl<RxTx: sealed::RxTx, const N: usize> DmaRing<RxTx, N> {
     }
 }
 
-impl<const N: usize> DmaRing<Rx, N> {
+impl<'a, const N: usize> DmaRing<'a, Rx, N> {
     const ERRS: rx::Status = rx::Status::from_bits_truncate(
         rx::Status::TR.bits()
             | rx::Status::OV.bits()
@@ -96,7 +96,7 @@ impl<const N: usize> DmaRing<Rx, N> {
     }
 }
 
-impl<const N: usize> DmaRing<Tx, N> {
+impl<'a, const N: usize> DmaRing<'a, Tx, N> {
     pub(crate) unsafe fn init(&mut self) {
         self.next_entry = 0;
         for entry in self.entries.iter_mut() {
@@ -2,26 +2,26 @@ use crate::enet::dma::{
     descriptor::{rx, tx, DescriptorSize},
     sealed, Rx, Tx,
 };
-use crate::enet::uncached_memory_region::UncachedMemoryRegion;
 use crate::enet::{Error, MtuSize};
 use crate::pac::typenum::Unsigned;
 use core::{marker::PhantomData, sync::atomic};
+use ferros::vspace::DmaRegion;
 
-pub type RxRingEntry = RingEntry<Rx>;
-pub type TxRingEntry = RingEntry<Tx>;
+pub type RxRingEntry<'a> = RingEntry<'a, Rx>;
+pub type TxRingEntry<'a> = RingEntry<'a, Tx>;
 
-pub struct RingEntry<RxTx: sealed::RxTx> {
-    pub(crate) desc: UncachedMemoryRegion,
-    pub(crate) pkt: UncachedMemoryRegion,
+pub struct RingEntry<'a, RxTx: sealed::RxTx> {
+    pub(crate) desc: DmaRegion<'a>,
+    pub(crate) pkt: DmaRegion<'a>,
     _role: PhantomData<RxTx>,
 }
 
-impl<RxTx: sealed::RxTx> RingEntry<RxTx> {
-    pub fn new(desc: UncachedMemoryRegion, pkt: UncachedMemoryRegion) -> Result<Self, Error> {
-        if desc.size() < DescriptorSize::USIZE {
+impl<'a, RxTx: sealed::RxTx> RingEntry<'a, RxTx> {
+    pub fn new(desc: DmaRegion<'a>, pkt: DmaRegion<'a>) -> Result<Self, Error> {
+        if desc.size_bytes() < DescriptorSize::USIZE {
             log::error!("[enet] ring entry descriptor memory too small");
             Err(Error::ExhaustedResource)
-        } else if pkt.size() < MtuSize::USIZE {
+        } else if pkt.size_bytes() < MtuSize::USIZE {
             log::error!("[enet] ring entry packet memory too small");
             Err(Error::ExhaustedResource)
         } else {
@@ -34,22 +34,22 @@ impl<RxTx: sealed::RxTx> RingEntry<RxTx> {
     }
 
     pub(crate) unsafe fn packet(&self) -> &[u8] {
-        self.pkt.as_slice::<u8>(self.pkt.size())
+        core::slice::from_raw_parts(self.pkt.as_ptr(), self.pkt.size_bytes())
     }
 
     pub(crate) unsafe fn packet_mut(&mut self) -> &mut [u8] {
-        self.pkt.as_mut_slice::<u8>(self.pkt.size())
+        core::slice::from_raw_parts_mut(self.pkt.as_mut_ptr(), self.pkt.size_bytes())
     }
 }
 
-impl RingEntry<Rx> {
+impl<'a> RingEntry<'a, Rx> {
     pub(crate) unsafe fn init(&mut self) {
         log::trace!(
-            "[enet] init rx ring entry, descriptor={}, packet={}",
+            "[enet] init rx ring entry, descriptor={:?}, packet={:?}",
             self.desc,
             self.pkt
         );
-        let pkt = self.pkt.as_mut_slice::<u8>(self.pkt.size());
+        let pkt = self.packet_mut();
         pkt.fill(0);
 
         let desc = &mut *self.desc.as_mut_ptr::<rx::Descriptor>();
@@ -77,14 +77,14 @@ impl RingEntry<Rx> {
     }
 }
 
-impl RingEntry<Tx> {
+impl<'a> RingEntry<'a, Tx> {
     pub(crate) unsafe fn init(&mut self) {
         log::trace!(
-            "[enet] init tx ring entry, descriptor={}, packet={}",
+            "[enet] init tx ring entry, descriptor={:?}, packet={:?}",
             self.desc,
             self.pkt
         );
-        let pkt = self.pkt.as_mut_slice::<u8>(self.pkt.size());
+        let pkt = self.packet_mut();
         pkt.fill(0);
 
         let desc = &mut *self.desc.as_mut_ptr::<tx::Descriptor>();
@@ -1,14 +1,13 @@
 use self::dma::descriptor::DescriptorSize;
 use self::dma::ring::{RxDmaRing, TxDmaRing};
 use self::dma::ring_entry::{RxRingEntry, TxRingEntry};
-use self::uncached_memory_region::{Error as MemRegionError, UncachedMemoryRegion};
 use crate::asm;
+use ferros::vspace::{DmaRegion, DmaRegionError};
 use imx6_devices::{enet::*, typenum::*};
 use net_types::EthernetAddress;
 use static_assertions::const_assert_eq;
 
 pub mod dma;
-pub mod uncached_memory_region;
 
 /// Frame length is 1,518 bytes
 pub type FrameLength = Sum<U1024, U494>;
@@ -48,90 +47,92 @@ pub enum Error {
     NotEnoughDescriptors,
     DmaRingMemoryNotContiguous,
     TransmitBufferTooBig,
-    MemoryRegion(MemRegionError),
+    MemoryRegion(DmaRegionError),
 }
 
-impl From<MemRegionError> for Error {
-    fn from(e: MemRegionError) -> Self {
+impl From<DmaRegionError> for Error {
+    fn from(e: DmaRegionError) -> Self {
         Error::MemoryRegion(e)
     }
 }
 
-pub struct Enet {
+pub struct Enet<'a> {
     enet: ENET,
     mac: EthernetAddress,
-    rx_ring: RxDmaRing,
-    tx_ring: TxDmaRing,
+    rx_ring: RxDmaRing<'a>,
+    tx_ring: TxDmaRing<'a>,
 }
 
-impl Enet {
+/// Take `size_bytes` off the head of `mem`, or the whole of it if that's
+/// all there is left
+fn take_head<'a>(
+    mem: &mut Option<DmaRegion<'a>>,
+    size_bytes: usize,
+) -> Result<DmaRegion<'a>, Error> {
+    let mut rest = mem.take().ok_or(Error::ExhaustedResource)?;
+    if rest.size_bytes() > size_bytes {
+        let head = rest.split(size_bytes)?;
+        *mem = Some(rest);
+        Ok(head)
+    } else {
+        Ok(rest)
+    }
+}
+
+impl<'a> Enet<'a> {
     pub fn new(
         enet: ENET,
         mac: EthernetAddress,
-        mut desc_mem: UncachedMemoryRegion,
-        mut packet_mem: UncachedMemoryRegion,
+        mut desc_mem: DmaRegion<'a>,
+        mut packet_mem: DmaRegion<'a>,
     ) -> Result<Self, Error> {
         log::trace!("[enet] new MAC={}", mac);
 
         let rx_total_desc_size = NumRxDescriptors::USIZE * DescriptorSize::USIZE;
         let tx_total_desc_size = NumTxDescriptors::USIZE * DescriptorSize::USIZE;
-        if desc_mem.size() < (rx_total_desc_size + tx_total_desc_size) {
+        if desc_mem.size_bytes() < (rx_total_desc_size + tx_total_desc_size) {
             log::error!("[enet] Descriptor memory too small");
             return Err(Error::ExhaustedResource);
         }
 
         let rx_total_pkt_size = NumRxDescriptors::USIZE * MtuSize::USIZE;
         let tx_total_pkt_size = NumTxDescriptors::USIZE * MtuSize::USIZE;
-        if packet_mem.size() < (rx_total_pkt_size + tx_total_pkt_size) {
+        if packet_mem.size_bytes() < (rx_total_pkt_size + tx_total_pkt_size) {
             log::error!("[enet] Packet memory too small");
             return Err(Error::ExhaustedResource);
         }
 
         // Split up descriptor memory, tx off the tail end
-        let mut tx_desc_mem = desc_mem.split_off(desc_mem.size() - tx_total_desc_size)?;
-        debug_assert_eq!(tx_desc_mem.size(), tx_total_desc_size);
+        let tx_desc_mem = desc_mem.split_off(desc_mem.size_bytes() - tx_total_desc_size)?;
+        debug_assert_eq!(tx_desc_mem.size_bytes(), tx_total_desc_size);
+        let mut tx_desc_mem = Some(tx_desc_mem);
 
         desc_mem.shrink_to(rx_total_desc_size)?;
-        let mut rx_desc_mem = desc_mem;
-        debug_assert_eq!(rx_desc_mem.size(), rx_total_desc_size);
+        debug_assert_eq!(desc_mem.size_bytes(), rx_total_desc_size);
+        let mut rx_desc_mem = Some(desc_mem);
 
         // Split up the packet memory, tx off the tail end
-        let mut tx_pkt_mem = packet_mem.split_off(packet_mem.size() - tx_total_pkt_size)?;
-        debug_assert_eq!(tx_pkt_mem.size(), tx_total_pkt_size);
+        let tx_pkt_mem = packet_mem.split_off(packet_mem.size_bytes() - tx_total_pkt_size)?;
+        debug_assert_eq!(tx_pkt_mem.size_bytes(), tx_total_pkt_size);
+        let mut tx_pkt_mem = Some(tx_pkt_mem);
 
         packet_mem.shrink_to(rx_total_pkt_size)?;
-        let mut rx_pkt_mem = packet_mem;
-        debug_assert_eq!(rx_pkt_mem.size(), rx_total_pkt_size);
+        debug_assert_eq!(packet_mem.size_bytes(), rx_total_pkt_size);
+        let mut rx_pkt_mem = Some(packet_mem);
 
-        let tx_entries: [TxRingEntry; NumTxDescriptors::USIZE] =
+        let tx_entries: [TxRingEntry<'a>; NumTxDescriptors::USIZE] =
             array_init::try_array_init(|_i| {
                 // Split off the head of the region so it's contiguous
-                let desc = if tx_desc_mem.size() > DescriptorSize::USIZE {
-                    tx_desc_mem.split(DescriptorSize::USIZE)?
-                } else {
-                    tx_desc_mem
-                };
-                let pkt = if tx_pkt_mem.size() > MtuSize::USIZE {
-                    tx_pkt_mem.split(MtuSize::USIZE)?
-                } else {
-                    tx_pkt_mem
-                };
+                let desc = take_head(&mut tx_desc_mem, DescriptorSize::USIZE)?;
+                let pkt = take_head(&mut tx_pkt_mem, MtuSize::USIZE)?;
                 TxRingEntry::new(desc, pkt)
             })?;
 
-        let rx_entries: [RxRingEntry; NumRxDescriptors::USIZE] =
+        let rx_entries: [RxRingEntry<'a>; NumRxDescriptors::USIZE] =
             array_init::try_array_init(|_i| {
                 // Split off the head of the region so it's contiguous
-                let desc = if rx_desc_mem.size() > DescriptorSize::USIZE {
-                    rx_desc_mem.split(DescriptorSize::USIZE)?
-                } else {
-                    rx_desc_mem
-                };
-                let pkt = if rx_pkt_mem.size() > MtuSize::USIZE {
-                    rx_pkt_mem.split(MtuSize::USIZE)?
-                } else {
-                    rx_pkt_mem
-                };
+                let desc = take_head(&mut rx_desc_mem, DescriptorSize::USIZE)?;
+                let pkt = take_head(&mut rx_pkt_mem, MtuSize::USIZE)?;
                 RxRingEntry::new(desc, pkt)
             })?;
 
@@ -10,7 +10,9 @@ pub mod asm;
 pub mod enet;
 pub mod gpio;
 pub mod otp;
+pub mod periodic;
 pub mod serial;
 pub mod spi;
 pub mod spi_nor_flash;
 pub mod timer;
+pub mod watchdog;
@@ -0,0 +1,142 @@
+//! Running a handler every so many milliseconds off the general purpose
+//! timer, keeping track of how late each activation starts (its jitter)
+//! and whether the handler runs past the end of its period (an overrun).
+
+use core::fmt;
+
+use crate::embedded_hal::timer::CountDown;
+use crate::timer::{Event, Timer, TICKS_PER_US};
+
+pub struct PeriodicTask {
+    timer: Timer,
+    period_ms: u32,
+    now_ms: u64,
+    metrics: PeriodicMetrics,
+}
+
+/// How a `PeriodicTask` has kept to its period
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PeriodicMetrics {
+    pub activations: u64,
+    /// How many times the handler was still running when the next period
+    /// started
+    pub overruns: u32,
+    /// The latest an activation started after its period began
+    pub max_jitter_us: u32,
+    /// The longest the handler ran for
+    pub max_run_us: u32,
+}
+
+impl fmt::Display for PeriodicMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "activations={} overruns={} max_jitter={}us max_run={}us",
+            self.activations, self.overruns, self.max_jitter_us, self.max_run_us
+        )
+    }
+}
+
+/// A single run of the handler, from `PeriodicTask::activate`
+#[derive(Debug)]
+pub struct Activation {
+    /// Milliseconds since the task started, counted in whole periods
+    pub now_ms: u64,
+    /// How long after its period began this activation started
+    pub jitter_us: u32,
+    start_ticks: u32,
+}
+
+/// How an activation went, from `PeriodicTask::finish`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ActivationReport {
+    pub jitter_us: u32,
+    pub run_us: u32,
+    /// The handler was still running when the next period started
+    pub overrun: bool,
+}
+
+impl PeriodicTask {
+    /// Start `timer` timing out every `period_ms`, with its interrupt
+    /// enabled
+    pub fn new(mut timer: Timer, period_ms: u32) -> Self {
+        timer.start_period_ms(period_ms);
+        timer.listen(Event::TimeOut);
+        PeriodicTask {
+            timer,
+            period_ms,
+            now_ms: 0,
+            metrics: PeriodicMetrics::default(),
+        }
+    }
+
+    pub fn period_ms(&self) -> u32 {
+        self.period_ms
+    }
+
+    /// Milliseconds since the task started, counted in whole periods
+    pub fn now_ms(&self) -> u64 {
+        self.now_ms
+    }
+
+    pub fn metrics(&self) -> PeriodicMetrics {
+        self.metrics
+    }
+
+    /// Take the metrics so far, starting over from nothing
+    pub fn take_metrics(&mut self) -> PeriodicMetrics {
+        core::mem::take(&mut self.metrics)
+    }
+
+    /// Check whether a period has ended, e.g. on waking up for the timer's
+    /// interrupt, and if so acknowledge it and start an activation. The
+    /// handler runs between this and `finish`.
+    ///
+    /// Returns `None` for a wakeup that wasn't the timer's.
+    pub fn activate(&mut self) -> Option<Activation> {
+        let start_ticks = self.timer.count();
+        self.timer.wait().ok()?;
+        self.now_ms = self.now_ms.wrapping_add(self.period_ms.into());
+        Some(Activation {
+            now_ms: self.now_ms,
+            jitter_us: start_ticks / TICKS_PER_US,
+            start_ticks,
+        })
+    }
+
+    /// Finish an activation once its handler has run, recording how it
+    /// went in the metrics
+    pub fn finish(&mut self, activation: Activation) -> ActivationReport {
+        let end_ticks = self.timer.count();
+        let overrun = self.timer.has_timed_out();
+        // The counter restarted when the next period began
+        let run_ticks = if overrun {
+            (self.period_ms * 1000 * TICKS_PER_US - activation.start_ticks)
+                .saturating_add(end_ticks)
+        } else {
+            end_ticks.saturating_sub(activation.start_ticks)
+        };
+        let report = ActivationReport {
+            jitter_us: activation.jitter_us,
+            run_us: run_ticks / TICKS_PER_US,
+            overrun,
+        };
+
+        let metrics = &mut self.metrics;
+        metrics.activations = metrics.activations.wrapping_add(1);
+        if overrun {
+            metrics.overruns = metrics.overruns.saturating_add(1);
+        }
+        metrics.max_jitter_us = metrics.max_jitter_us.max(report.jitter_us);
+        metrics.max_run_us = metrics.max_run_us.max(report.run_us);
+        report
+    }
+
+    /// `activate`, run `handler` with the time in milliseconds, then
+    /// `finish`, if a period has ended
+    pub fn run<F: FnOnce(u64)>(&mut self, handler: F) -> Option<ActivationReport> {
+        let activation = self.activate()?;
+        handler(activation.now_ms);
+        Some(self.finish(activation))
+    }
+}
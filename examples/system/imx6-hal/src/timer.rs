@@ -5,6 +5,9 @@ use void::Void;
 // Using the 24MHz clock
 const CLOCK_FREQ: u32 = 24_000_000;
 
+/// Timer ticks in a microsecond
+pub const TICKS_PER_US: u32 = CLOCK_FREQ / 1_000_000;
+
 pub struct Hertz(pub u32);
 
 impl From<u32> for Hertz {
@@ -77,6 +80,28 @@ impl Timer {
             Event::TimeOut => self.gpt.ir.modify(Interrupt::OutputCompare1::Set),
         }
     }
+
+    /// Start timing out every `period_ms` milliseconds, for periods too
+    /// long or too precise to give as a rate in `Hertz`
+    pub fn start_period_ms(&mut self, period_ms: u32) {
+        debug_assert_ne!(period_ms, 0);
+        self.reset();
+        let cmp = period_ms * (CLOCK_FREQ / 1000) - 1;
+        unsafe { self.gpt.ocr1.write(cmp) };
+        self.gpt.cr.modify(Control::Enable::Set);
+    }
+
+    /// Ticks since the current period started, the counter restarts each
+    /// time the timer times out
+    pub fn count(&self) -> u32 {
+        self.gpt.cnt.read()
+    }
+
+    /// Whether the timer has timed out since it was last waited on,
+    /// without acknowledging it
+    pub fn has_timed_out(&self) -> bool {
+        self.gpt.sr.is_set(Status::OutputCompare1::Set)
+    }
 }
 
 impl timer::CountDown for Timer {
@@ -0,0 +1,21 @@
+use crate::{asm, pac::wdog::wdog1::WDOG1, pac::wdog::*};
+
+/// The imx6 has no firmware to ask for a restart, the watchdog's software
+/// reset signal is how the system is restarted instead
+pub struct Watchdog {
+    wdog: WDOG1,
+}
+
+impl Watchdog {
+    pub fn new(wdog: WDOG1) -> Self {
+        Watchdog { wdog }
+    }
+
+    /// Restart the system
+    pub fn reset_system(&mut self) -> ! {
+        self.wdog.wcr.modify(Control::SwResetSignal::AssertReset);
+        loop {
+            asm::nop();
+        }
+    }
+}
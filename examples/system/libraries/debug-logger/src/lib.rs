@@ -1,11 +1,41 @@
 #![no_std]
 
+use core::fmt;
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
 use ferros::debug_println;
+use ferros::userland::{current_correlation_id, CorrelationId, SequenceCounter, SequenceNumber};
 use log::{LevelFilter, Metadata, Record};
 
 pub struct DebugLogger;
 
+/// Stamps records with the system-wide event sequence, once set
+static SEQUENCE_COUNTER: AtomicPtr<SequenceCounter> = AtomicPtr::new(ptr::null_mut());
+
+/// Lets every record through while it's set, once set
+static VERBOSE_FLAG: AtomicPtr<AtomicBool> = AtomicPtr::new(ptr::null_mut());
+
 impl DebugLogger {
+    /// Stamp every record with a number from the shared event sequence, so
+    /// the records of several processes can be put back in order
+    pub fn set_sequence_counter(counter: &'static SequenceCounter) {
+        SEQUENCE_COUNTER.store(counter as *const _ as *mut _, Ordering::Release);
+    }
+
+    /// Log every record, whatever the level, while `flag` is set.
+    ///
+    /// Records are then filtered by the logger rather than the log crate's
+    /// max level, which is raised to let all of them through.
+    pub fn set_verbose_flag(flag: &'static AtomicBool) {
+        VERBOSE_FLAG.store(flag as *const _ as *mut _, Ordering::Release);
+        log::set_max_level(LevelFilter::Trace);
+    }
+
+    fn verbose() -> bool {
+        let flag = unsafe { VERBOSE_FLAG.load(Ordering::Acquire).as_ref() };
+        flag.map_or(false, |flag| flag.load(Ordering::Relaxed))
+    }
+
     /// Behaves like env-logger RUST_LOG, but at compile time
     pub fn max_log_level_from_env() -> LevelFilter {
         match option_env!("RUST_LOG") {
@@ -22,14 +52,38 @@ impl DebugLogger {
 
 impl log::Log for DebugLogger {
     fn enabled(&self, metadata: &Metadata) -> bool {
-        metadata.level().to_level_filter() <= Self::max_log_level_from_env()
+        metadata.level().to_level_filter() <= Self::max_log_level_from_env() || Self::verbose()
     }
 
     fn log(&self, record: &Record) {
         if self.enabled(record.metadata()) {
-            debug_println!("{}: {}", record.level(), record.args());
+            let counter = unsafe { SEQUENCE_COUNTER.load(Ordering::Acquire).as_ref() };
+            let tags = Tags {
+                seq: counter.map(SequenceCounter::stamp),
+                cid: current_correlation_id(),
+            };
+            debug_println!("{}: {}{}", record.level(), tags, record.args());
         }
     }
 
     fn flush(&self) {}
 }
+
+/// A record's sequence number, and the id of the correlated action it was
+/// made on behalf of, if any
+struct Tags {
+    seq: Option<SequenceNumber>,
+    cid: Option<CorrelationId>,
+}
+
+impl fmt::Display for Tags {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(seq) = self.seq {
+            write!(f, "[seq={}] ", seq)?;
+        }
+        if let Some(cid) = self.cid {
+            write!(f, "[cid={}] ", cid)?;
+        }
+        Ok(())
+    }
+}
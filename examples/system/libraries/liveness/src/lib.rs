@@ -0,0 +1,304 @@
+//! Liveness signals, declared once for the whole system.
+//!
+//! Every process' signals and the periods they're expected to keep are
+//! declared together with `signals!`, see `system`. Processes report on
+//! their signals in a page of memory shared with the root task, whose
+//! `LivenessMonitor` watches for any that go quiet for longer than their
+//! period, and with the console, which reports each signal's counts.
+//!
+//! There are two kinds of signal:
+//! * a heartbeat is beaten every so often regardless of load, e.g. by a
+//!   periodic timer handler, and is overdue if it isn't beaten within its
+//!   period
+//! * a progress signal counts the items put on a queue and those taken
+//!   off it, and is only overdue if items are waiting and none has been
+//!   taken off within its period
+
+#![no_std]
+
+use core::fmt;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use ferros::arch::PageBits;
+use ferros::vspace::{shared_status, MappedMemoryRegion};
+
+/// Size of the shared region backing `LivenessPage`
+pub type LivenessSizeBits = PageBits;
+
+/// The most signals a `LivenessPage` has room for
+pub const MAX_SIGNALS: usize = 256;
+
+/// The system's signals
+pub mod system {
+    crate::signals! {
+        tcpip {
+            /// The IP stack's timer handler, which services its timers
+            heartbeat TICK every 10 ms;
+        }
+        enet {
+            /// Frames queued by the TCP/IP driver, put on the tx ring
+            progress TX_FRAMES every 100 ms;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignalKind {
+    Heartbeat,
+    Progress,
+}
+
+impl fmt::Display for SignalKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SignalKind::Heartbeat => f.pad("heartbeat"),
+            SignalKind::Progress => f.pad("progress"),
+        }
+    }
+}
+
+/// A declared signal, see `signals!`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Signal {
+    /// Where the signal's counters are in the page
+    pub index: usize,
+    pub process: &'static str,
+    pub name: &'static str,
+    pub kind: SignalKind,
+    pub period_ms: u32,
+}
+
+/// Declare the liveness signals of each process, with the period each is
+/// expected to keep.
+///
+/// Each process gets a module of its signals as constants, and `SIGNALS`
+/// lists them all, for the monitor and metrics to go through.
+///
+/// ```ignore
+/// signals! {
+///     tcpip {
+///         /// The IP stack's timer handler
+///         heartbeat TICK every 10 ms;
+///     }
+///     enet {
+///         progress TX_FRAMES every 100 ms;
+///     }
+/// }
+///
+/// let ticker = PeriodicTask::new(timer, tcpip::TICK.period_ms);
+/// liveness.beat(tcpip::TICK);
+/// ```
+#[macro_export]
+macro_rules! signals {
+    ($(
+        $process:ident {
+            $($(#[$attr:meta])* $kind:ident $signal:ident every $period:literal ms;)*
+        }
+    )*) => {
+        $crate::signals!(@processes 0usize; $($process { $({
+            $(#[$attr])* $kind $signal $period
+        })* })*);
+
+        /// Every declared signal, in the order of their index
+        pub static SIGNALS: &[$crate::Signal] = &[$($($process::$signal,)*)*];
+    };
+
+    (@processes $index:expr; $process:ident { $($signal:tt)* } $($rest:tt)*) => {
+        pub mod $process {
+            $crate::signals!(@signals $process $index; $($signal)*);
+        }
+        $crate::signals!(@processes $index + $crate::signals!(@count $($signal)*); $($rest)*);
+    };
+    (@processes $index:expr;) => {};
+
+    (@signals $process:ident $index:expr; {
+        $(#[$attr:meta])* $kind:ident $signal:ident $period:literal
+    } $($rest:tt)*) => {
+        $(#[$attr])*
+        pub const $signal: $crate::Signal = $crate::Signal {
+            index: $index,
+            process: stringify!($process),
+            name: stringify!($signal),
+            kind: $crate::signals!(@kind $kind),
+            period_ms: $period,
+        };
+        $crate::signals!(@signals $process $index + 1; $($rest)*);
+    };
+    (@signals $process:ident $index:expr;) => {};
+
+    (@count) => { 0usize };
+    (@count $head:tt $($tail:tt)*) => { 1usize + $crate::signals!(@count $($tail)*) };
+
+    (@kind heartbeat) => { $crate::SignalKind::Heartbeat };
+    (@kind progress) => { $crate::SignalKind::Progress };
+}
+
+#[repr(C)]
+struct SignalCounters {
+    /// Items put on a progress signal's queue
+    submitted: AtomicU32,
+    /// Heartbeats, or items taken off a progress signal's queue
+    completed: AtomicU32,
+    /// Monitor checks that found the signal overdue
+    overdue: AtomicU32,
+    _reserved: AtomicU32,
+}
+
+/// The counters of each signal by index, laid out at the start of the
+/// shared page
+#[repr(C)]
+pub struct LivenessPage {
+    signals: [SignalCounters; MAX_SIGNALS],
+}
+
+impl LivenessPage {
+    /// Use the counters in a mapping of the shared page.
+    ///
+    /// Retyped memory starts out zeroed, so every signal starts with
+    /// nothing counted.
+    pub fn from_region(
+        region: MappedMemoryRegion<LivenessSizeBits, shared_status::Shared>,
+    ) -> &'static LivenessPage {
+        unsafe { &*(region.vaddr() as *const LivenessPage) }
+    }
+
+    fn counters(&self, signal: Signal) -> &SignalCounters {
+        &self.signals[signal.index]
+    }
+
+    /// Beat a heartbeat
+    pub fn beat(&self, signal: Signal) {
+        debug_assert_eq!(signal.kind, SignalKind::Heartbeat);
+        self.counters(signal)
+            .completed
+            .fetch_add(1, Ordering::Release);
+    }
+
+    /// Count an item put on a progress signal's queue
+    pub fn submitted(&self, signal: Signal) {
+        debug_assert_eq!(signal.kind, SignalKind::Progress);
+        self.counters(signal)
+            .submitted
+            .fetch_add(1, Ordering::Release);
+    }
+
+    /// Count an item taken off a progress signal's queue
+    pub fn completed(&self, signal: Signal) {
+        debug_assert_eq!(signal.kind, SignalKind::Progress);
+        self.counters(signal)
+            .completed
+            .fetch_add(1, Ordering::Release);
+    }
+
+    pub fn metrics(&self, signal: Signal) -> SignalMetrics {
+        let counters = self.counters(signal);
+        let completed = counters.completed.load(Ordering::Acquire);
+        let pending = match signal.kind {
+            SignalKind::Heartbeat => 0,
+            SignalKind::Progress => counters
+                .submitted
+                .load(Ordering::Acquire)
+                .wrapping_sub(completed),
+        };
+        SignalMetrics {
+            signal,
+            count: completed,
+            pending,
+            overdue: counters.overdue.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// How a signal has been doing, from `LivenessPage::metrics`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SignalMetrics {
+    pub signal: Signal,
+    /// Heartbeats, or items taken off the queue
+    pub count: u32,
+    /// Items waiting on the queue, always zero for a heartbeat
+    pub pending: u32,
+    /// Monitor checks that found the signal overdue
+    pub overdue: u32,
+}
+
+impl fmt::Display for SignalMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}::{} {} period={}ms count={} pending={} overdue={}",
+            self.signal.process,
+            self.signal.name,
+            self.signal.kind,
+            self.signal.period_ms,
+            self.count,
+            self.pending,
+            self.overdue
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct LastSeen {
+    completed: u32,
+    /// When `completed` last changed, or the signal was last idle
+    at_ms: u64,
+    overdue: bool,
+}
+
+/// Watches for signals that go quiet for longer than their period
+pub struct LivenessMonitor {
+    page: &'static LivenessPage,
+    signals: &'static [Signal],
+    last_seen: [LastSeen; MAX_SIGNALS],
+}
+
+impl LivenessMonitor {
+    /// Watch `signals` in `page`, starting from `now_ms`
+    pub fn new(page: &'static LivenessPage, signals: &'static [Signal], now_ms: u64) -> Self {
+        assert!(signals.len() <= MAX_SIGNALS);
+        LivenessMonitor {
+            page,
+            signals,
+            last_seen: [LastSeen {
+                at_ms: now_ms,
+                ..Default::default()
+            }; MAX_SIGNALS],
+        }
+    }
+
+    /// Check every signal as of `now_ms`, counting it overdue if it's
+    /// gone longer than its period without a heartbeat or, with items
+    /// waiting, progress. `on_overdue` is called with each signal that's
+    /// just become overdue and how long it's been quiet.
+    pub fn check<F>(&mut self, now_ms: u64, mut on_overdue: F)
+    where
+        F: FnMut(&Signal, u64),
+    {
+        for signal in self.signals {
+            let metrics = self.page.metrics(*signal);
+            let last_seen = &mut self.last_seen[signal.index];
+            if metrics.count != last_seen.completed
+                || (signal.kind == SignalKind::Progress && metrics.pending == 0)
+            {
+                *last_seen = LastSeen {
+                    completed: metrics.count,
+                    at_ms: now_ms,
+                    overdue: false,
+                };
+                continue;
+            }
+
+            let quiet_ms = now_ms.saturating_sub(last_seen.at_ms);
+            if quiet_ms > u64::from(signal.period_ms) {
+                self.page
+                    .counters(*signal)
+                    .overdue
+                    .fetch_add(1, Ordering::Relaxed);
+                if !last_seen.overdue {
+                    last_seen.overdue = true;
+                    on_overdue(signal, quiet_ms);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use crate::{EthernetFrameBuffer, MtuSize};
+use core::fmt;
+use typenum::Unsigned;
+
+pub type IpcCapturedFrame = CapturedFrame<{ MtuSize::USIZE }>;
+
+/// Which way a frame was travelling when it was captured
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum CaptureDirection {
+    Rx,
+    Tx,
+}
+
+impl fmt::Display for CaptureDirection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CaptureDirection::Rx => f.write_str("rx"),
+            CaptureDirection::Tx => f.write_str("tx"),
+        }
+    }
+}
+
+/// Which frames a capture tap passes on
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum CaptureFilter {
+    Off,
+    Rx,
+    Tx,
+    Both,
+}
+
+impl CaptureFilter {
+    pub fn matches(self, direction: CaptureDirection) -> bool {
+        matches!(
+            (self, direction),
+            (CaptureFilter::Both, _)
+                | (CaptureFilter::Rx, CaptureDirection::Rx)
+                | (CaptureFilter::Tx, CaptureDirection::Tx)
+        )
+    }
+}
+
+impl fmt::Display for CaptureFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CaptureFilter::Off => f.write_str("off"),
+            CaptureFilter::Rx => f.write_str("rx"),
+            CaptureFilter::Tx => f.write_str("tx"),
+            CaptureFilter::Both => f.write_str("both"),
+        }
+    }
+}
+
+/// Capture tap configuration
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct CaptureConfig {
+    pub filter: CaptureFilter,
+
+    /// Capture one out of every `sample_every` matching frames,
+    /// zero is treated as one
+    pub sample_every: u32,
+
+    /// Maximum number of bytes captured per frame, the rest is truncated
+    pub snap_len: u16,
+}
+
+impl CaptureConfig {
+    pub const fn off() -> Self {
+        CaptureConfig {
+            filter: CaptureFilter::Off,
+            sample_every: 1,
+            snap_len: MtuSize::U16,
+        }
+    }
+}
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        Self::off()
+    }
+}
+
+impl fmt::Display for CaptureConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "CaptureConfig filter={} sample_every={} snap_len={}",
+            self.filter, self.sample_every, self.snap_len
+        )
+    }
+}
+
+/// A copy of a (possibly truncated) frame seen by a capture tap
+pub struct CapturedFrame<const N: usize> {
+    /// Capture time in milliseconds, in the capturing driver's time domain
+    pub timestamp_ms: u64,
+    pub direction: CaptureDirection,
+    /// Length of the frame on the wire, before truncation to the snap length
+    pub orig_len: usize,
+    pub frame: EthernetFrameBuffer<N>,
+}
+
+impl<const N: usize> fmt::Display for CapturedFrame<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "CapturedFrame timestamp_ms={} direction={} orig_len={} len={}",
+            self.timestamp_ms,
+            self.direction,
+            self.orig_len,
+            self.frame.len()
+        )
+    }
+}
@@ -0,0 +1,108 @@
+use crate::{CaptureConfig, EthernetAddress, Ipv4Address};
+use core::fmt;
+
+/// Maximum number of neighbor (ARP) entries tracked by the TCP/IP driver
+pub const MAX_NEIGHBOR_ENTRIES: usize = 8;
+
+/// Maximum number of static neighbor entries, a subset of `MAX_NEIGHBOR_ENTRIES`
+pub const MAX_STATIC_NEIGHBOR_ENTRIES: usize = 4;
+
+/// Management requests to the TCP/IP driver
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ControlRequest {
+    /// Reconfigure the frame capture tap
+    Capture(CaptureConfig),
+    /// Report the neighbor table, answered with `ControlResponse::Neighbors`
+    ListNeighbors,
+    /// Add a static neighbor entry, answered with `ControlResponse::StaticNeighborAdded`
+    AddStaticNeighbor(Ipv4Address, EthernetAddress),
+}
+
+/// Responses to `ControlRequest`s
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ControlResponse {
+    Neighbors(NeighborList),
+    StaticNeighborAdded(Result<NeighborEntry, NeighborError>),
+}
+
+impl fmt::Display for ControlResponse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ControlResponse::Neighbors(list) => write!(f, "{}", list),
+            ControlResponse::StaticNeighborAdded(Ok(entry)) => write!(f, "Added {}", entry),
+            ControlResponse::StaticNeighborAdded(Err(e)) => write!(f, "{}", e),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum NeighborKind {
+    /// Learned from the network, valid until the given time in the
+    /// driver's time domain
+    Dynamic { expires_at_ms: u64 },
+    /// Configured, never expires
+    Static,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct NeighborEntry {
+    pub ip_addr: Ipv4Address,
+    pub mac_addr: EthernetAddress,
+    pub kind: NeighborKind,
+}
+
+impl fmt::Display for NeighborEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.ip_addr, self.mac_addr)?;
+        match self.kind {
+            NeighborKind::Dynamic { expires_at_ms } => {
+                write!(f, " dynamic expires_at_ms={}", expires_at_ms)
+            }
+            NeighborKind::Static => write!(f, " static"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum NeighborError {
+    /// No room left for another static entry
+    TableFull,
+    /// The address is outside the driver's IP subnet
+    NotInSubnet,
+    /// Broadcast, multicast or unspecified addresses can't be neighbors
+    NotUnicast,
+}
+
+impl fmt::Display for NeighborError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NeighborError::TableFull => f.write_str("Static neighbor table is full"),
+            NeighborError::NotInSubnet => f.write_str("Address is not in the local subnet"),
+            NeighborError::NotUnicast => f.write_str("Address is not a unicast address"),
+        }
+    }
+}
+
+/// A snapshot of the TCP/IP driver's neighbor table
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct NeighborList {
+    /// Time the snapshot was taken, in the driver's time domain
+    pub now_ms: u64,
+    pub entries: [Option<NeighborEntry>; MAX_NEIGHBOR_ENTRIES],
+}
+
+impl NeighborList {
+    pub fn iter(&self) -> impl Iterator<Item = &NeighborEntry> {
+        self.entries.iter().flatten()
+    }
+}
+
+impl fmt::Display for NeighborList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "NeighborList now_ms={}", self.now_ms)?;
+        for entry in self.iter() {
+            write!(f, "\n  {}", entry)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,257 @@
+//! A pool of MTU sized Ethernet frame buffers in a region of memory
+//! shared between the L2 and L3 drivers.
+//!
+//! Frames are passed over the queues as a `FrameHandle`, the index of a
+//! buffer in the pool and the length of the frame it holds, rather than
+//! being copied into each queue element. Whoever ends up with a handle
+//! gives the buffer back to the pool's free list.
+//!
+//! A handle can carry a `FrameTimestamp` taken as it was sent across, for
+//! the receiving driver to measure how long the hop took.
+
+use crate::MtuSize;
+use core::marker::PhantomData;
+use core::{fmt, mem, slice};
+use cross_queue::ArrayQueue;
+use typenum::*;
+
+/// Number of frame buffers in the pool
+pub type FramePoolFrameCount = U64;
+
+/// 2^17 bytes holds the free list and 64 MTU sized frame buffers
+pub type FramePoolMemSizeBits = U17;
+pub type FramePoolMemSize = op!(U1 << FramePoolMemSizeBits);
+
+/// The free list lives in the first page, the frame buffers follow it
+const FRAMES_OFFSET: usize = 4096;
+
+/// Ownership of a frame buffer in a `FramePool`
+#[derive(Debug, PartialEq, Eq)]
+pub struct FrameHandle {
+    index: u16,
+    len: u16,
+    timestamp: Option<FrameTimestamp>,
+}
+
+/// A cycle counter reading, taken by the sending driver as it hands a
+/// frame across
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameTimestamp(pub u32);
+
+impl FrameTimestamp {
+    /// Cycles from this timestamp until `now`, allowing for the counter
+    /// wrapping once
+    pub fn cycles_until(self, now: FrameTimestamp) -> u32 {
+        now.0.wrapping_sub(self.0)
+    }
+}
+
+impl FrameHandle {
+    pub fn index(&self) -> usize {
+        self.index.into()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len.into()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        if len < self.len() {
+            self.len = len as u16;
+        }
+    }
+
+    /// When the frame was sent across, if the sender stamped it
+    pub fn timestamp(&self) -> Option<FrameTimestamp> {
+        self.timestamp
+    }
+
+    pub fn stamp(&mut self, timestamp: FrameTimestamp) {
+        self.timestamp = Some(timestamp);
+    }
+}
+
+impl fmt::Display for FrameHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "FrameHandle index={} len={}", self.index, self.len)?;
+        if let Some(ts) = self.timestamp {
+            write!(f, " timestamp={}", ts.0)?;
+        }
+        Ok(())
+    }
+}
+
+/// Latency across one hop between drivers, from the timestamps on the
+/// frames crossing it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HopLatency {
+    pub name: &'static str,
+    pub frames: u32,
+    pub total_cycles: u64,
+    pub worst_cycles: u32,
+}
+
+impl HopLatency {
+    pub const fn new(name: &'static str) -> Self {
+        HopLatency {
+            name,
+            frames: 0,
+            total_cycles: 0,
+            worst_cycles: 0,
+        }
+    }
+
+    /// Record the hop of a frame that arrived `now`, returning the cycles
+    /// it took, or `None` if it wasn't stamped
+    pub fn record(&mut self, handle: &FrameHandle, now: FrameTimestamp) -> Option<u32> {
+        let cycles = handle.timestamp()?.cycles_until(now);
+        self.frames = self.frames.wrapping_add(1);
+        self.total_cycles = self.total_cycles.wrapping_add(cycles.into());
+        self.worst_cycles = self.worst_cycles.max(cycles);
+        Some(cycles)
+    }
+
+    pub fn mean_cycles(&self) -> u64 {
+        if self.frames == 0 {
+            0
+        } else {
+            self.total_cycles / u64::from(self.frames)
+        }
+    }
+}
+
+impl fmt::Display for HopLatency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "HopLatency {} frames={} mean={} worst={} cycles",
+            self.name,
+            self.frames,
+            self.mean_cycles(),
+            self.worst_cycles
+        )
+    }
+}
+
+pub struct FramePool<'a> {
+    free_list: &'a ArrayQueue<u16>,
+    frames: *mut u8,
+    _mem: PhantomData<&'a mut [u8]>,
+}
+
+impl<'a> FramePool<'a> {
+    /// Lay out the pool in `mem` with every buffer on the free list.
+    ///
+    /// This is done once, before the region is shared with the drivers.
+    pub fn init(mem: &'a mut [u8]) -> Self {
+        Self::check_layout(mem);
+
+        unsafe {
+            ArrayQueue::new_at_ptr(
+                mem.as_mut_ptr() as *mut ArrayQueue<u16>,
+                FramePoolFrameCount::USIZE,
+                ArrayQueue::<u16>::buffer_offset(),
+            );
+        }
+
+        let pool = unsafe { Self::attach(mem) };
+        for index in 0..FramePoolFrameCount::U16 {
+            pool.free_list
+                .push(index)
+                .expect("Frame pool free list is sized for every buffer");
+        }
+        pool
+    }
+
+    /// Use a pool already laid out in `mem`.
+    ///
+    /// # Safety
+    ///
+    /// `mem` must be a mapping of a region initialized by `FramePool::init`
+    pub unsafe fn attach(mem: &'a mut [u8]) -> Self {
+        Self::check_layout(mem);
+        let base = mem.as_mut_ptr();
+        let free_list = &*(base as *const ArrayQueue<u16>);
+        if let Err(e) = free_list.check_layout() {
+            panic!("Frame pool free list doesn't match this build: {}", e);
+        }
+        FramePool {
+            free_list,
+            frames: base.add(FRAMES_OFFSET),
+            _mem: PhantomData,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        FramePoolFrameCount::USIZE
+    }
+
+    /// Number of buffers currently on the free list
+    pub fn available(&self) -> usize {
+        self.free_list.len()
+    }
+
+    /// Take a buffer from the free list, its length starts out as the MTU
+    pub fn alloc(&self) -> Option<FrameHandle> {
+        self.free_list.pop().ok().map(|index| FrameHandle {
+            index,
+            len: MtuSize::U16,
+            timestamp: None,
+        })
+    }
+
+    /// Return a buffer to the free list
+    pub fn free(&self, handle: FrameHandle) {
+        self.check_handle(&handle);
+        if self.free_list.push(handle.index).is_err() {
+            panic!("Frame pool free list overflow, {} freed twice", handle);
+        }
+    }
+
+    pub fn frame<'h>(&'h self, handle: &'h FrameHandle) -> &'h [u8] {
+        self.check_handle(handle);
+        unsafe { slice::from_raw_parts(self.frame_ptr(handle), handle.len()) }
+    }
+
+    pub fn frame_mut<'h>(&'h self, handle: &'h mut FrameHandle) -> &'h mut [u8] {
+        self.check_handle(handle);
+        unsafe { slice::from_raw_parts_mut(self.frame_ptr(handle), handle.len()) }
+    }
+
+    fn frame_ptr(&self, handle: &FrameHandle) -> *mut u8 {
+        unsafe { self.frames.add(handle.index() * MtuSize::USIZE) }
+    }
+
+    fn check_handle(&self, handle: &FrameHandle) {
+        assert!(
+            handle.index() < self.capacity() && handle.len() <= MtuSize::USIZE,
+            "Invalid {}",
+            handle
+        );
+    }
+
+    fn check_layout(mem: &[u8]) {
+        let free_list_size = ArrayQueue::<u16>::region_size(FramePoolFrameCount::USIZE);
+        assert!(free_list_size <= FRAMES_OFFSET);
+        assert!(mem.len() >= FRAMES_OFFSET + (FramePoolFrameCount::USIZE * MtuSize::USIZE));
+        assert_eq!(
+            mem.as_ptr() as usize % mem::align_of::<ArrayQueue<u16>>(),
+            0
+        );
+    }
+}
+
+impl<'a> fmt::Display for FramePool<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "FramePool available={} capacity={}",
+            self.available(),
+            self.capacity()
+        )
+    }
+}
@@ -2,10 +2,18 @@
 
 use core::fmt;
 
+mod capture;
+mod control;
 mod frame;
+mod frame_pool;
+mod udp_receive_buffer;
 mod udp_transmit_buffer;
 
+pub use crate::capture::*;
+pub use crate::control::*;
 pub use crate::frame::*;
+pub use crate::frame_pool::*;
+pub use crate::udp_receive_buffer::*;
 pub use crate::udp_transmit_buffer::*;
 
 #[derive(Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Default)]
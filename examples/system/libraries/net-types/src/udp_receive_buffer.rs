@@ -0,0 +1,24 @@
+use crate::{EthernetFrameBuffer, Ipv4Address, MtuSize, Port};
+use core::fmt;
+use typenum::Unsigned;
+
+pub type IpcUdpReceiveBuffer = UdpReceiveBuffer<{ MtuSize::USIZE }>;
+
+/// A UDP receive buffer
+pub struct UdpReceiveBuffer<const N: usize> {
+    pub src_addr: Ipv4Address,
+    pub src_port: Port,
+    pub frame: EthernetFrameBuffer<N>,
+}
+
+impl<const N: usize> fmt::Display for UdpReceiveBuffer<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "UdpReceiveBuffer src_addr={} src_port={} len={}",
+            self.src_addr,
+            self.src_port,
+            self.frame.len()
+        )
+    }
+}
@@ -4,13 +4,36 @@ use typenum::Unsigned;
 
 pub type IpcUdpTransmitBuffer = UdpTransmitBuffer<{ MtuSize::USIZE }>;
 
+/// Ethernet, IPv4 (without options) and UDP header bytes that share a
+/// frame with the UDP payload
+pub const UDP_FRAME_OVERHEAD: usize = 14 + 20 + 8;
+
+const UDP_HEADER_LEN: usize = 8;
+const IP_PROTOCOL_UDP: u8 = 17;
+
 /// A UDP transmit buffer
 pub struct UdpTransmitBuffer<const N: usize> {
     pub dst_addr: Ipv4Address,
     pub dst_port: Port,
+    /// UDP checksum of the datagram, if the sender precomputed it
+    pub checksum: Option<u16>,
     pub frame: EthernetFrameBuffer<N>,
 }
 
+impl<const N: usize> UdpTransmitBuffer<N> {
+    /// Largest payload that fits in a single frame of the buffer's size
+    pub const MAX_PAYLOAD_LEN: usize = N - UDP_FRAME_OVERHEAD;
+
+    pub fn builder(dst_addr: Ipv4Address, dst_port: Port) -> UdpTransmitBufferBuilder<'static> {
+        UdpTransmitBufferBuilder {
+            dst_addr,
+            dst_port,
+            payload: &[],
+            checksum_src: None,
+        }
+    }
+}
+
 impl<const N: usize> fmt::Display for UdpTransmitBuffer<N> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
@@ -19,6 +42,142 @@ impl<const N: usize> fmt::Display for UdpTransmitBuffer<N> {
             self.dst_addr,
             self.dst_port,
             self.frame.len()
-        )
+        )?;
+        if let Some(checksum) = self.checksum {
+            write!(f, " checksum=0x{:04X}", checksum)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum UdpTransmitBufferError {
+    /// The payload doesn't fit in a single frame
+    PayloadTooLarge { len: usize, max_len: usize },
+    /// Port 0 is reserved
+    InvalidPort,
+    /// The unspecified, loopback and multicast addresses can't be sent to
+    InvalidAddress(Ipv4Address),
+}
+
+impl fmt::Display for UdpTransmitBufferError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UdpTransmitBufferError::PayloadTooLarge { len, max_len } => write!(
+                f,
+                "Payload of {} bytes is too large, must be at most {}",
+                len, max_len
+            ),
+            UdpTransmitBufferError::InvalidPort => write!(f, "Invalid port 0"),
+            UdpTransmitBufferError::InvalidAddress(addr) => {
+                write!(f, "Invalid destination address {}", addr)
+            }
+        }
+    }
+}
+
+/// Checked construction of a `UdpTransmitBuffer`
+pub struct UdpTransmitBufferBuilder<'a> {
+    dst_addr: Ipv4Address,
+    dst_port: Port,
+    payload: &'a [u8],
+    checksum_src: Option<(Ipv4Address, Port)>,
+}
+
+impl<'a> UdpTransmitBufferBuilder<'a> {
+    pub fn payload<'b>(self, payload: &'b [u8]) -> UdpTransmitBufferBuilder<'b> {
+        UdpTransmitBufferBuilder {
+            dst_addr: self.dst_addr,
+            dst_port: self.dst_port,
+            payload,
+            checksum_src: self.checksum_src,
+        }
+    }
+
+    /// Precompute the UDP checksum for a datagram sent from the given
+    /// source address and port
+    pub fn checksum(mut self, src_addr: Ipv4Address, src_port: Port) -> Self {
+        self.checksum_src = Some((src_addr, src_port));
+        self
+    }
+
+    pub fn build<const N: usize>(self) -> Result<UdpTransmitBuffer<N>, UdpTransmitBufferError> {
+        let max_len = UdpTransmitBuffer::<N>::MAX_PAYLOAD_LEN;
+        if self.payload.len() > max_len {
+            return Err(UdpTransmitBufferError::PayloadTooLarge {
+                len: self.payload.len(),
+                max_len,
+            });
+        }
+        if self.dst_port.0 == 0 {
+            return Err(UdpTransmitBufferError::InvalidPort);
+        }
+        let [first_octet, ..] = self.dst_addr.0;
+        if self.dst_addr.0 == [0; 4] || first_octet == 127 || (224..240).contains(&first_octet) {
+            return Err(UdpTransmitBufferError::InvalidAddress(self.dst_addr));
+        }
+
+        let checksum = self.checksum_src.map(|(src_addr, src_port)| {
+            udp_checksum(
+                src_addr,
+                src_port,
+                self.dst_addr,
+                self.dst_port,
+                self.payload,
+            )
+        });
+
+        let mut frame = EthernetFrameBuffer::new();
+        frame.truncate(self.payload.len());
+        frame.as_mut_slice().copy_from_slice(self.payload);
+
+        Ok(UdpTransmitBuffer {
+            dst_addr: self.dst_addr,
+            dst_port: self.dst_port,
+            checksum,
+            frame,
+        })
+    }
+}
+
+/// The UDP checksum (RFC 768) of a datagram, including the IPv4 pseudo header
+pub fn udp_checksum(
+    src_addr: Ipv4Address,
+    src_port: Port,
+    dst_addr: Ipv4Address,
+    dst_port: Port,
+    payload: &[u8],
+) -> u16 {
+    let udp_len = (UDP_HEADER_LEN + payload.len()) as u32;
+
+    let mut sum: u32 = 0;
+    for addr in [src_addr, dst_addr].iter() {
+        sum += u32::from(u16::from_be_bytes([addr.0[0], addr.0[1]]));
+        sum += u32::from(u16::from_be_bytes([addr.0[2], addr.0[3]]));
+    }
+    sum += u32::from(IP_PROTOCOL_UDP);
+    sum += udp_len;
+
+    // UDP header, with the checksum field zeroed
+    sum += u32::from(src_port.0);
+    sum += u32::from(dst_port.0);
+    sum += udp_len;
+
+    let mut chunks = payload.chunks_exact(2);
+    for chunk in &mut chunks {
+        sum += u32::from(u16::from_be_bytes([chunk[0], chunk[1]]));
+    }
+    if let [last] = chunks.remainder() {
+        sum += u32::from(u16::from_be_bytes([*last, 0]));
+    }
+
+    while (sum >> 16) != 0 {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+
+    // A computed checksum of zero is transmitted as all ones
+    match !(sum as u16) {
+        0 => 0xFFFF,
+        checksum => checksum,
     }
 }
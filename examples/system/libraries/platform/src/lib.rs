@@ -0,0 +1,184 @@
+//! The devices the example system is built around, per platform.
+//!
+//! The root task takes the device windows, interrupts and clock it
+//! hands out to the drivers from a `PlatformDevices` rather than from a
+//! particular board's peripheral access crate, so supporting another
+//! board starts with describing it here.
+//!
+//! Only the devices every platform has to provide are described: a
+//! console UART, an Ethernet controller, a timer and a bus for persistent
+//! storage. Board support beyond those, e.g. the i.MX6's pin muxing,
+//! watchdog and flash chip select GPIO, stays with the board.
+
+#![no_std]
+
+use core::fmt;
+
+use imx6_devices::{ecspi1::ECSPI1, enet::ENET, gpt::GPT, interrupts, uart1::UART1};
+
+/// A device's register window and interrupt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Device {
+    pub name: &'static str,
+    /// Physical address of the register window, page aligned
+    pub paddr: usize,
+    pub size: usize,
+    /// The interrupt number, if the device is used with one
+    pub irq: Option<usize>,
+}
+
+impl fmt::Display for Device {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} @ {:#010x}..{:#010x}",
+            self.name,
+            self.paddr,
+            self.paddr + self.size
+        )?;
+        match self.irq {
+            Some(irq) => write!(f, ", IRQ {}", irq),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A platform the example system runs on
+pub trait PlatformDevices {
+    const NAME: &'static str;
+
+    /// Cycle counter ticks per millisecond, for the root task's idle loop
+    /// to keep time by
+    const CPU_CYCLES_PER_MS: usize;
+
+    /// Cores the root task's idle loop samples the utilization of, no
+    /// more than the kernel's `KernelMaxNumNodes`
+    const CORE_COUNT: usize;
+
+    /// The UART the console application serves
+    const CONSOLE_UART: Device;
+
+    /// The Ethernet controller
+    const ETHERNET: Device;
+
+    /// The timer driving the TCP/IP stack
+    const TIMER: Device;
+
+    /// The bus persistent storage lives on
+    const STORAGE_BUS: Device;
+
+    /// Every device, for logging what's in use
+    fn devices() -> [Device; 4] {
+        [
+            Self::CONSOLE_UART,
+            Self::ETHERNET,
+            Self::TIMER,
+            Self::STORAGE_BUS,
+        ]
+    }
+}
+
+/// The Boundary Devices SABRE Lite i.MX6 board the example system's
+/// drivers are written for, and QEMU's `sabrelite` machine
+pub struct Imx6;
+
+impl PlatformDevices for Imx6 {
+    const NAME: &'static str = "imx6";
+
+    /// The 996MHz Cortex-A9
+    const CPU_CYCLES_PER_MS: usize = 996_000;
+
+    const CORE_COUNT: usize = 4;
+
+    const CONSOLE_UART: Device = Device {
+        name: "uart1",
+        paddr: UART1::PADDR as _,
+        size: UART1::SIZE,
+        irq: Some(interrupts::uart1::IRQ),
+    };
+
+    const ETHERNET: Device = Device {
+        name: "enet",
+        paddr: ENET::PADDR as _,
+        size: ENET::SIZE,
+        irq: Some(interrupts::enet::IRQ),
+    };
+
+    const TIMER: Device = Device {
+        name: "gpt",
+        paddr: GPT::PADDR as _,
+        size: GPT::SIZE,
+        irq: Some(interrupts::gpt::IRQ),
+    };
+
+    /// The SPI NOR flash is on ECSPI1, polled
+    const STORAGE_BUS: Device = Device {
+        name: "ecspi1",
+        paddr: ECSPI1::PADDR as _,
+        size: ECSPI1::SIZE,
+        irq: None,
+    };
+}
+
+/// QEMU's `virt` machine, see `hw/arm/virt.c` for its memory map and
+/// interrupts.
+///
+/// Its devices are virtio and PrimeCell ones rather than the i.MX6's, so
+/// the root task needs drivers for them before it can run here.
+pub struct Virt;
+
+impl Virt {
+    /// The virtio-mmio transports start here, 0x200 bytes apart with
+    /// consecutive interrupts
+    const VIRTIO_MMIO_BASE: usize = 0x0a00_0000;
+    const VIRTIO_MMIO_IRQ: usize = 48;
+
+    const PAGE_SIZE: usize = 0x1000;
+}
+
+impl PlatformDevices for Virt {
+    const NAME: &'static str = "virt";
+
+    /// QEMU's cycle counter ticks at a nominal 1GHz
+    const CPU_CYCLES_PER_MS: usize = 1_000_000;
+
+    /// Given `-smp 4`
+    const CORE_COUNT: usize = 4;
+
+    /// The PL011
+    const CONSOLE_UART: Device = Device {
+        name: "pl011",
+        paddr: 0x0900_0000,
+        size: Virt::PAGE_SIZE,
+        irq: Some(33),
+    };
+
+    /// A virtio-net device, given `-device virtio-net-device` ahead of
+    /// any other virtio device so that it's on the first transport.
+    ///
+    /// The transports share a page, so the first page is the Ethernet
+    /// controller's alone and the storage bus is elsewhere.
+    const ETHERNET: Device = Device {
+        name: "virtio-net",
+        paddr: Virt::VIRTIO_MMIO_BASE,
+        size: Virt::PAGE_SIZE,
+        irq: Some(Virt::VIRTIO_MMIO_IRQ),
+    };
+
+    /// The PL031 real time clock, whose alarm has only a resolution of a
+    /// second
+    const TIMER: Device = Device {
+        name: "pl031",
+        paddr: 0x0901_0000,
+        size: Virt::PAGE_SIZE,
+        irq: Some(34),
+    };
+
+    /// The second flash bank, which QEMU leaves to the guest
+    const STORAGE_BUS: Device = Device {
+        name: "pflash1",
+        paddr: 0x0400_0000,
+        size: 0x0400_0000,
+        irq: None,
+    };
+}
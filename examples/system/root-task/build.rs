@@ -1,7 +1,7 @@
 use ferros_build::*;
 use std::path::Path;
 
-fn main() {
+fn main() -> Result<(), CodegenError> {
     let out_dir = Path::new(&std::env::var_os("OUT_DIR").unwrap()).to_owned();
     let bin_dir = out_dir.join("..").join("..").join("..");
     let resources = out_dir.join("resources.rs");
@@ -11,6 +11,7 @@ fn main() {
         image_name: "iomux".to_owned(),
         type_name: "Iomux".to_owned(),
         stack_size_bits: Some(14),
+        reject_shared_segment_pages: false,
     };
     println!("cargo:rerun-if-changed={}", iomux.path.display());
 
@@ -19,6 +20,7 @@ fn main() {
         image_name: "enet".to_owned(),
         type_name: "Enet".to_owned(),
         stack_size_bits: Some(16),
+        reject_shared_segment_pages: false,
     };
     println!("cargo:rerun-if-changed={}", enet.path.display());
 
@@ -27,6 +29,7 @@ fn main() {
         image_name: "tcpip".to_owned(),
         type_name: "TcpIp".to_owned(),
         stack_size_bits: Some(16),
+        reject_shared_segment_pages: false,
     };
     println!("cargo:rerun-if-changed={}", tcpip.path.display());
 
@@ -35,29 +38,64 @@ fn main() {
         image_name: "persistent-storage".to_owned(),
         type_name: "PersistentStorage".to_owned(),
         stack_size_bits: Some(14),
+        reject_shared_segment_pages: false,
     };
     println!(
         "cargo:rerun-if-changed={}",
         persistent_storage.path.display()
     );
 
+    let config_service = ElfResource {
+        path: bin_dir.join("config-service"),
+        image_name: "config-service".to_owned(),
+        type_name: "ConfigService".to_owned(),
+        stack_size_bits: Some(14),
+        reject_shared_segment_pages: false,
+    };
+    println!("cargo:rerun-if-changed={}", config_service.path.display());
+
     let console = ElfResource {
         path: bin_dir.join("console"),
         image_name: "console".to_owned(),
         type_name: "Console".to_owned(),
         stack_size_bits: Some(15),
+        reject_shared_segment_pages: false,
     };
     println!("cargo:rerun-if-changed={}", console.path.display());
 
+    let udp_perf = ElfResource {
+        path: bin_dir.join("udp-perf"),
+        image_name: "udp-perf".to_owned(),
+        type_name: "UdpPerf".to_owned(),
+        stack_size_bits: Some(14),
+        reject_shared_segment_pages: false,
+    };
+    println!("cargo:rerun-if-changed={}", udp_perf.path.display());
+
+    let pcap = ElfResource {
+        path: bin_dir.join("pcap"),
+        image_name: "pcap".to_owned(),
+        type_name: "Pcap".to_owned(),
+        stack_size_bits: Some(14),
+        reject_shared_segment_pages: false,
+    };
+    println!("cargo:rerun-if-changed={}", pcap.path.display());
+
     let procs = vec![
         &iomux as &dyn Resource,
         &enet as &dyn Resource,
         &tcpip as &dyn Resource,
         &persistent_storage as &dyn Resource,
+        &config_service as &dyn Resource,
         &console as &dyn Resource,
+        &udp_perf as &dyn Resource,
+        &pcap as &dyn Resource,
     ];
 
-    embed_resources(&resources, procs);
+    write_build_metadata(out_dir.join("build_metadata.rs"), &procs);
+    embed_resources(&resources, procs)?;
+    report_typenum_costs(&[Path::new("src/main.rs")]);
 
-    built::write_built_file().expect("Failed to acquire build-time information")
+    built::write_built_file().expect("Failed to acquire build-time information");
+    Ok(())
 }
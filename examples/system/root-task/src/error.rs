@@ -1,12 +1,12 @@
-use ferros::alloc::micro_alloc::{
-    DeviceRangeAllocError, Error as AllocError, PageAlignedAddressRangeError,
-};
+use ferros::alloc::device_manager::DeviceClaimError;
+use ferros::alloc::micro_alloc::{Error as AllocError, PageAlignedAddressRangeError};
 use ferros::alloc::ut_buddy::UTBuddyError;
+use ferros::bootstrap::devicetree::DeviceTreeError;
 use ferros::cap::IRQError;
 use ferros::cap::RetypeError;
 use ferros::error::SeL4Error;
 use ferros::userland::{FaultManagementError, IPCError, MultiConsumerError, ProcessSetupError};
-use ferros::vspace::VSpaceError;
+use ferros::vspace::{RegionRegistryError, VSpaceError};
 use log::SetLoggerError;
 use selfe_arc::read::ReadError as ArchiveReadError;
 
@@ -14,7 +14,7 @@ use selfe_arc::read::ReadError as ArchiveReadError;
 #[derive(Debug)]
 pub enum TopLevelError {
     AllocError(AllocError),
-    DeviceRangeAllocError(DeviceRangeAllocError),
+    DeviceClaimError(DeviceClaimError),
     PageAlignedAddressRangeError(PageAlignedAddressRangeError),
     IPCError(IPCError),
     MultiConsumerError(MultiConsumerError),
@@ -27,6 +27,11 @@ pub enum TopLevelError {
     RetypeError(RetypeError),
     ArchiveReadError(ArchiveReadError),
     SetLoggerError(SetLoggerError),
+    RegionRegistryError(RegionRegistryError),
+    DeviceTreeError(DeviceTreeError),
+    /// The platform doesn't have the named device where its driver
+    /// expects it
+    PlatformMismatch(&'static str),
 }
 
 impl From<AllocError> for TopLevelError {
@@ -35,9 +40,9 @@ impl From<AllocError> for TopLevelError {
     }
 }
 
-impl From<DeviceRangeAllocError> for TopLevelError {
-    fn from(e: DeviceRangeAllocError) -> Self {
-        TopLevelError::DeviceRangeAllocError(e)
+impl From<DeviceClaimError> for TopLevelError {
+    fn from(e: DeviceClaimError) -> Self {
+        TopLevelError::DeviceClaimError(e)
     }
 }
 
@@ -112,3 +117,15 @@ impl From<SetLoggerError> for TopLevelError {
         TopLevelError::SetLoggerError(e)
     }
 }
+
+impl From<RegionRegistryError> for TopLevelError {
+    fn from(e: RegionRegistryError) -> Self {
+        TopLevelError::RegionRegistryError(e)
+    }
+}
+
+impl From<DeviceTreeError> for TopLevelError {
+    fn from(e: DeviceTreeError) -> Self {
+        TopLevelError::DeviceTreeError(e)
+    }
+}
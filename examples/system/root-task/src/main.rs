@@ -3,6 +3,7 @@
 
 mod error;
 
+use config_service::{ConfigChange, ConfigEntry, ConfigKeySet, FeatureFlagsSizeBits};
 use debug_logger::DebugLogger;
 use error::TopLevelError;
 use ferros::alloc::micro_alloc::*;
@@ -15,22 +16,56 @@ use ferros::vspace::*;
 use ferros::*;
 use imx6_hal::pac::{
     ecspi1::ECSPI1, enet::ENET, gpio::GPIO3, gpt::GPT, iomuxc::IOMUXC, uart1::UART1,
+    wdog::wdog1::WDOG1,
 };
-use net_types::{EthernetAddress, IpcEthernetFrame, IpcUdpTransmitBuffer, Ipv4Address, MtuSize};
+use liveness::{LivenessMonitor, LivenessPage, LivenessSizeBits};
+use net_types::{
+    ControlRequest, ControlResponse, EthernetAddress, FrameHandle, FramePool,
+    FramePoolFrameCount, FramePoolMemSizeBits, IpcUdpTransmitBuffer, Ipv4Address, MtuSize, Port,
+};
+use platform::{Device, PlatformDevices};
 use typenum::*;
 
-/// 2^16 bytes in the L2 queues can buffer ~43 Ethernet frames
-type L2IpcQueuePageBits = U16;
-type L2IpcQueueDepth = op!(((U1 << L2IpcQueuePageBits) / MtuSize) - U1);
+/// The L2 queues carry frame pool handles, deep enough to hold every frame
+/// in the pool so a send only fails when the pool is exhausted
+type L2IpcQueuePageBits = U12;
+type L2IpcQueueDepth = FramePoolFrameCount;
+assert_queue_depth!(L2IpcQueueDepth, Burst<FramePoolFrameCount>, U1);
+
+/// Cycles the idle loop spends sampling each core
+const IDLE_SAMPLE_WINDOW_CYCLES: usize = 50_000_000;
+
+/// The longest a yield back to the idle loop takes with nothing else to
+/// run on its core
+const IDLE_YIELD_CYCLES: usize = 5_000;
+
+/// Owners of the regions tagged in the root task's region registry
+const ENET_PROCESS_ID: ProcessId = ProcessId(1);
 
 /// 2^14 bytes in the UDP queue can buffer ~10 Ethernet frames
 type UdpIpcQueuePageBits = U14;
 type UdpIpcQueueDepth = op!(((U1 << UdpIpcQueuePageBits) / MtuSize) - U1);
 
+/// 2^16 bytes in the frame capture queue can buffer ~43 captured frames
+type CaptureIpcQueuePageBits = U16;
+type CaptureIpcQueueDepth = op!(((U1 << CaptureIpcQueuePageBits) / MtuSize) - U1);
+
+/// Management requests and responses are rare, a single page will do
+type NetControlIpcQueuePageBits = U12;
+type NetControlIpcQueueDepth = U4;
+
+/// Config changes are rare too, but at startup the config service sends
+/// every entry that differs from its default at once
+type ConfigChangeIpcQueuePageBits = U12;
+type ConfigChangeIpcQueueDepth = U8;
+
 // TODO - read hw OTP MAC address, use forged if not available
 // https://github.com/auxoncorp/ferros/issues/88
 const MAC_ADDRESS: EthernetAddress = EthernetAddress([0x00, 0xAD, 0xBE, 0xEF, 0xCA, 0xFE]);
-const IP_ADDRESS: Ipv4Address = Ipv4Address([192, 0, 2, 80]);
+const IP_ADDRESS: Ipv4Address = config_service::entry::NetIpAddr::DEFAULT;
+
+/// UDP port served by the udp-perf application
+const UDP_SERVICE_PORT: Port = Port(7);
 
 static LOGGER: DebugLogger = DebugLogger;
 
@@ -43,25 +78,72 @@ pub mod built_info {
     include!(concat!(env!("OUT_DIR"), "/built.rs"));
 }
 
+mod build_metadata {
+    include!(concat!(env!("OUT_DIR"), "/build_metadata.rs"));
+}
+
 #[allow(clippy::type_complexity)]
 mod resources {
     include! {concat!(env!("OUT_DIR"), "/resources.rs")}
 }
 
+/// The platform the system's built for. The drivers are i.MX6 ones, as
+/// are the pin muxing, watchdog and flash chip select set up below.
+type Platform = platform::Imx6;
+
 fn main() {
     let raw_bootinfo = unsafe { &*selfe_start::BOOTINFO };
-    run(raw_bootinfo).expect("Failed to run root task setup");
+    run::<Platform>(raw_bootinfo).expect("Failed to run root task setup");
 }
 
-fn run(raw_bootinfo: &'static selfe_sys::seL4_BootInfo) -> Result<(), TopLevelError> {
+/// Check that the interrupt a driver was built to consume is the one the
+/// platform has its device on
+fn check_device<Irq: Unsigned>(device: &Device) -> Result<(), TopLevelError> {
+    if device.irq == Some(Irq::USIZE) {
+        Ok(())
+    } else {
+        Err(TopLevelError::PlatformMismatch(device.name))
+    }
+}
+
+fn run<P: PlatformDevices>(
+    raw_bootinfo: &'static selfe_sys::seL4_BootInfo,
+) -> Result<(), TopLevelError> {
     log::set_logger(&LOGGER).map(|()| log::set_max_level(DebugLogger::max_log_level_from_env()))?;
     log::debug!(
-        "[root-task] Initializing version={} profile={}",
+        "[root-task] Initializing version={} profile={} git={} platform={}",
         built_info::PKG_VERSION,
         built_info::PROFILE,
+        build_metadata::BUILD_METADATA.git_hash().unwrap_or("unknown"),
+        P::NAME,
     );
+    for device in P::devices().iter() {
+        log::debug!("[root-task] Device {}", device);
+    }
+    // The devices are described by hand, so check them against the
+    // device tree the bootloader gave the kernel, when there is one
+    if let Some(tree) = devicetree::DeviceTree::from_bootinfo(raw_bootinfo) {
+        let tree = tree?;
+        for device in P::devices().iter() {
+            match tree.find_by_address(device.paddr) {
+                Some(node) => log::debug!(
+                    "[root-task] Device {} is {} in the device tree",
+                    device.name,
+                    node.name()
+                ),
+                None => log::warn!(
+                    "[root-task] Device {} at {:#X} isn't in the device tree",
+                    device.name,
+                    device.paddr
+                ),
+            }
+        }
+    }
+    check_device::<console::IrqBadgeBits>(&P::CONSOLE_UART)?;
+    check_device::<enet::IrqBadgeBits>(&P::ETHERNET)?;
+    check_device::<imx6_hal::pac::gpt::Irq>(&P::TIMER)?;
 
-    let (allocator, mut dev_allocator) = micro_alloc::bootstrap_allocators(raw_bootinfo)?;
+    let (allocator, dev_allocator) = micro_alloc::bootstrap_allocators(raw_bootinfo)?;
     let mut allocator = WUTBuddy::from(allocator);
 
     let (root_cnode, local_slots) = root_cnode(raw_bootinfo);
@@ -74,16 +156,19 @@ fn run(raw_bootinfo: &'static selfe_sys::seL4_BootInfo) -> Result<(), TopLevelEr
         asid_control,
         user_image,
         root_tcb,
-        mut irq_control,
+        irq_control,
         ..
     } = BootInfo::wrap(
         raw_bootinfo,
         allocator.alloc_strong::<U16>(&mut ut_slots)?,
         root_vspace_slots,
     );
+    let mut devices = DeviceManager::new(dev_allocator, irq_control);
 
     let tpa = root_tcb.downgrade_to_thread_priority_authority();
 
+    let mut regions = RegionRegistry::new();
+
     let archive_slice: &[u8] = unsafe {
         core::slice::from_raw_parts(
             &_selfe_arc_data_start,
@@ -112,11 +197,26 @@ fn run(raw_bootinfo: &'static selfe_sys::seL4_BootInfo) -> Result<(), TopLevelEr
         "[root-task] Found persistent-storage ELF data size={}",
         pstorage_elf_data.len()
     );
+    let config_elf_data = archive.file(resources::ConfigService::IMAGE_NAME)?;
+    log::debug!(
+        "[root-task] Found config-service ELF data size={}",
+        config_elf_data.len()
+    );
     let console_elf_data = archive.file(resources::Console::IMAGE_NAME)?;
     log::debug!(
         "[root-task] Found console ELF data size={}",
         console_elf_data.len()
     );
+    let udp_perf_elf_data = archive.file(resources::UdpPerf::IMAGE_NAME)?;
+    log::debug!(
+        "[root-task] Found udp-perf ELF data size={}",
+        udp_perf_elf_data.len()
+    );
+    let pcap_elf_data = archive.file(resources::Pcap::IMAGE_NAME)?;
+    log::debug!(
+        "[root-task] Found pcap ELF data size={}",
+        pcap_elf_data.len()
+    );
 
     let uts = alloc::ut_buddy(allocator.alloc_strong::<U27>(&mut ut_slots)?);
 
@@ -152,11 +252,9 @@ fn run(raw_bootinfo: &'static selfe_sys::seL4_BootInfo) -> Result<(), TopLevelEr
         let (iomux_cnode, iomux_slots) = retype_cnode::<U12>(ut, slots)?;
         let (ipc_slots, _iomux_slots) = iomux_slots.alloc();
         let (iomux_ipc_setup, responder) = call_channel(ut, &root_cnode, slots, ipc_slots)?;
-        let iomuxc_ut = dev_allocator
-            .get_untyped_by_address_range_slot_infallible(
-                PageAlignedAddressRange::new_by_size(IOMUXC::PADDR as _, IOMUXC::SIZE)?,
-                slots,
-            )?
+        let iomuxc_ut = devices
+            .claim(DeviceClaim::new(IOMUXC::PADDR as _, IOMUXC::SIZE)?, slots)?
+            .untyped
             .as_strong::<arch::PageBits>()
             .expect("Device untyped was not the right size!");
         let iomuxc_mem = iomux_vspace.map_region(
@@ -167,6 +265,7 @@ fn run(raw_bootinfo: &'static selfe_sys::seL4_BootInfo) -> Result<(), TopLevelEr
         let params = iomux::ProcParams {
             iomuxc: unsafe { IOMUXC::from_vaddr(iomuxc_mem.vaddr() as _) },
             responder,
+            root_build: build_metadata::BUILD_METADATA,
         };
         let stack_mem: UnmappedMemoryRegion<<resources::Iomux as ElfProc>::StackSizeBits, _> =
             UnmappedMemoryRegion::new(ut, slots).unwrap();
@@ -232,15 +331,122 @@ fn run(raw_bootinfo: &'static selfe_sys::seL4_BootInfo) -> Result<(), TopLevelEr
         )?;
         let (enet_cnode, enet_slots) = retype_cnode::<U12>(ut, slots)?;
         let (slots_c, enet_slots) = enet_slots.alloc();
-        let (enet_int_consumer, mut enet_int_consumer_token) =
-            InterruptConsumer::new(ut, &mut irq_control, &root_cnode, slots, slots_c)?;
+        let (enet_int_consumer, mut enet_int_consumer_token, enet_waker_setup) =
+            InterruptConsumer::new_with_waker(
+                ut,
+                devices.irq_control(),
+                &root_cnode,
+                slots,
+                slots_c,
+            )?;
         //
         // shared setup between tcpip and enet drivers
         //
 
+        // Ethernet frame pool, laid out before it's shared with the drivers
+        let mut frame_pool_mem_unmapped: UnmappedMemoryRegion<FramePoolMemSizeBits, _> =
+            UnmappedMemoryRegion::new(ut, slots)?;
+        scratch.temporarily_map_region(&mut frame_pool_mem_unmapped, |mem| {
+            log::debug!("[root-task] {}", FramePool::init(mem.as_mut_slice()));
+        })?;
+        regions.tag(&frame_pool_mem_unmapped, "frame pool", None)?;
+        let frame_pool_mem_unmapped = frame_pool_mem_unmapped.to_shared();
+        let tcpip_frame_pool_mem = tcpip_vspace.map_shared_region(
+            &frame_pool_mem_unmapped,
+            CapRights::RW,
+            arch::vm_attributes::DEFAULT,
+            slots,
+            &root_cnode,
+        )?;
+        let enet_frame_pool_mem = enet_vspace.map_shared_region_and_consume(
+            frame_pool_mem_unmapped,
+            CapRights::RW,
+            arch::vm_attributes::DEFAULT,
+        )?;
+
+        // System-wide event sequence counter, stamps the enet, tcpip and
+        // console log records
+        let sequence_counter_mem_unmapped: UnmappedMemoryRegion<SequenceCounterSizeBits, _> =
+            UnmappedMemoryRegion::new(ut, slots)?;
+        let sequence_counter_mem_unmapped = sequence_counter_mem_unmapped.to_shared();
+        let tcpip_sequence_counter_mem = tcpip_vspace.map_shared_region(
+            &sequence_counter_mem_unmapped,
+            CapRights::RW,
+            arch::vm_attributes::DEFAULT,
+            slots,
+            &root_cnode,
+        )?;
+        let enet_sequence_counter_mem = enet_vspace.map_shared_region(
+            &sequence_counter_mem_unmapped,
+            CapRights::RW,
+            arch::vm_attributes::DEFAULT,
+            slots,
+            &root_cnode,
+        )?;
+
+        // Feature flags, published by the config service and read, without
+        // IPC, by the enet, tcpip and console processes
+        let feature_flags_mem_unmapped: UnmappedMemoryRegion<FeatureFlagsSizeBits, _> =
+            UnmappedMemoryRegion::new(ut, slots)?;
+        let feature_flags_mem_unmapped = feature_flags_mem_unmapped.to_shared();
+        let tcpip_feature_flags_mem = tcpip_vspace.map_shared_region(
+            &feature_flags_mem_unmapped,
+            CapRights::R,
+            arch::vm_attributes::DEFAULT,
+            slots,
+            &root_cnode,
+        )?;
+        let enet_feature_flags_mem = enet_vspace.map_shared_region(
+            &feature_flags_mem_unmapped,
+            CapRights::R,
+            arch::vm_attributes::DEFAULT,
+            slots,
+            &root_cnode,
+        )?;
+
+        // Liveness signals, reported on by the enet and tcpip processes,
+        // watched by the root task's idle loop and reported by the console
+        let liveness_mem_unmapped: UnmappedMemoryRegion<LivenessSizeBits, _> =
+            UnmappedMemoryRegion::new(ut, slots)?;
+        let liveness_mem_unmapped = liveness_mem_unmapped.to_shared();
+        let tcpip_liveness_mem = tcpip_vspace.map_shared_region(
+            &liveness_mem_unmapped,
+            CapRights::RW,
+            arch::vm_attributes::DEFAULT,
+            slots,
+            &root_cnode,
+        )?;
+        let enet_liveness_mem = enet_vspace.map_shared_region(
+            &liveness_mem_unmapped,
+            CapRights::RW,
+            arch::vm_attributes::DEFAULT,
+            slots,
+            &root_cnode,
+        )?;
+        let liveness = LivenessPage::from_region(root_vspace.map_shared_region(
+            &liveness_mem_unmapped,
+            CapRights::RW,
+            arch::vm_attributes::DEFAULT,
+            slots,
+            &root_cnode,
+        )?);
+
+        // Per-core utilization, sampled by the root task's idle loop and
+        // reported by the console
+        let cpu_stats_mem_unmapped: UnmappedMemoryRegion<CpuStatsSizeBits, _> =
+            UnmappedMemoryRegion::new(ut, slots)?;
+        let cpu_stats_mem_unmapped = cpu_stats_mem_unmapped.to_shared();
+        let cpu_stats = CpuStats::from_region(root_vspace.map_shared_region(
+            &cpu_stats_mem_unmapped,
+            CapRights::RW,
+            arch::vm_attributes::DEFAULT,
+            slots,
+            &root_cnode,
+        )?);
+
         // enet <- tcpip L2 frame consumer & enet IRQ waker
         let (enet_consumer, enet_producer_setup) = enet_int_consumer
-            .add_queue::<IpcEthernetFrame, L2IpcQueueDepth, L2IpcQueuePageBits, _>(
+            .add_queue::<FrameHandle, L2IpcQueueDepth, L2IpcQueuePageBits, _>(
                 &mut enet_int_consumer_token,
                 ut,
                 &mut scratch,
@@ -292,9 +498,9 @@ fn run(raw_bootinfo: &'static selfe_sys::seL4_BootInfo) -> Result<(), TopLevelEr
         // tcpip <- console app UDP consumer & GPT IRQ waker
         let (slots_c, tcpip_slots) = tcpip_slots.alloc();
         let (tcpip_int_consumer, mut tcpip_int_consumer_token) =
-            InterruptConsumer::new(ut, &mut irq_control, &root_cnode, slots, slots_c)?;
+            InterruptConsumer::new(ut, devices.irq_control(), &root_cnode, slots, slots_c)?;
         let (tcpip_event_consumer, tcpip_event_producer_setup) = tcpip_int_consumer
-            .add_queue::<IpcUdpTransmitBuffer, UdpIpcQueueDepth, UdpIpcQueuePageBits, _>(
+            .add_queue::<Correlated<IpcUdpTransmitBuffer>, UdpIpcQueueDepth, UdpIpcQueuePageBits, _>(
             &mut tcpip_int_consumer_token,
             ut,
             &mut scratch,
@@ -304,11 +510,217 @@ fn run(raw_bootinfo: &'static selfe_sys::seL4_BootInfo) -> Result<(), TopLevelEr
             slots,
         )?;
 
+        // tcpip <- udp-perf app UDP service port consumer
+        let (tcpip_event_consumer, tcpip_service_producer_setup) = tcpip_event_consumer
+            .add_queue::<IpcUdpTransmitBuffer, UdpIpcQueueDepth, UdpIpcQueuePageBits, _>(
+            &tcpip_int_consumer_token,
+            ut,
+            &mut scratch,
+            &mut tcpip_vspace,
+            &root_cnode,
+            slots,
+            slots,
+        )?;
+
+        //
+        // applications/udp-perf setup
+        //
+
+        log::debug!("[root-task] Setting up udp-perf application");
+
+        let (asid, asid_pool) = asid_pool.alloc();
+        let vspace_slots: LocalCNodeSlots<ferros::arch::CodePageCount> = slots;
+        let vspace_ut: LocalCap<Untyped<U16>> = ut;
+        let mut udp_perf_vspace = VSpace::new_from_elf::<resources::UdpPerf>(
+            retype(ut, slots)?, // paging_root
+            asid,
+            vspace_slots.weaken(), // slots
+            vspace_ut.weaken(),    // paging_untyped
+            udp_perf_elf_data,
+            slots, // page_slots
+            ut,    // elf_writable_mem
+            &user_image,
+            &root_cnode,
+            &mut scratch,
+        )?;
+        let (udp_perf_cnode, udp_perf_slots) = retype_cnode::<U12>(ut, slots)?;
+
+        // udp-perf <- tcpip UDP service port consumer
+        let (slots_c, udp_perf_slots) = udp_perf_slots.alloc();
+        let (
+            udp_perf_consumer,
+            _udp_perf_consumer_token,
+            udp_perf_producer_setup,
+            _udp_perf_waker_setup,
+        ) = Consumer1::new::<UdpIpcQueueDepth, UdpIpcQueuePageBits, _>(
+            ut,
+            ut,
+            &mut scratch,
+            &mut udp_perf_vspace,
+            &root_cnode,
+            slots,
+            slots,
+            slots,
+            slots_c,
+        )?;
+
+        // tcpip -> udp-perf UDP service port producer
+        let (slots_p, tcpip_slots) = tcpip_slots.alloc();
+        let tcpip_udp_rx_producer = Producer::new(
+            &udp_perf_producer_setup,
+            slots_p,
+            &mut tcpip_vspace,
+            &root_cnode,
+            slots,
+        )?;
+
+        // tcpip <- console app management request consumer
+        let (tcpip_event_consumer, tcpip_control_producer_setup) = tcpip_event_consumer
+            .add_queue::<ControlRequest, NetControlIpcQueueDepth, NetControlIpcQueuePageBits, _>(
+                &tcpip_int_consumer_token,
+                ut,
+                &mut scratch,
+                &mut tcpip_vspace,
+                &root_cnode,
+                slots,
+                slots,
+            )?;
+
+        // tcpip <- config-service net entry change consumer
+        let (tcpip_event_consumer, tcpip_config_producer_setup) = tcpip_event_consumer
+            .add_queue::<ConfigChange, ConfigChangeIpcQueueDepth, ConfigChangeIpcQueuePageBits, _>(
+                &tcpip_int_consumer_token,
+                ut,
+                &mut scratch,
+                &mut tcpip_vspace,
+                &root_cnode,
+                slots,
+                slots,
+            )?;
+
+        //
+        // applications/console setup
+        //
+
+        log::debug!("[root-task] Setting up console application");
+
+        let (asid, asid_pool) = asid_pool.alloc();
+        let vspace_slots: LocalCNodeSlots<ferros::arch::CodePageCount> = slots;
+        let vspace_ut: LocalCap<Untyped<U16>> = ut;
+        let mut console_vspace = VSpace::new_from_elf::<resources::Console>(
+            retype(ut, slots)?, // paging_root
+            asid,
+            vspace_slots.weaken(), // slots
+            vspace_ut.weaken(),    // paging_untyped
+            console_elf_data,
+            slots, // page_slots
+            ut,    // elf_writable_mem
+            &user_image,
+            &root_cnode,
+            &mut scratch,
+        )?;
+        let (console_cnode, console_slots) = retype_cnode::<U12>(ut, slots)?;
+
+        // console <- UART IRQ & tcpip management response consumer
+        let (slots_c, console_slots) = console_slots.alloc();
+        let (console_int_consumer, mut console_int_consumer_token) =
+            InterruptConsumer::new(ut, devices.irq_control(), &root_cnode, slots, slots_c)?;
+        let (int_consumer, console_control_producer_setup) = console_int_consumer
+            .add_queue::<ControlResponse, NetControlIpcQueueDepth, NetControlIpcQueuePageBits, _>(
+                &mut console_int_consumer_token,
+                ut,
+                &mut scratch,
+                &mut console_vspace,
+                &root_cnode,
+                slots,
+                slots,
+            )?;
+
+        // tcpip -> console management response producer
+        let (slots_p, tcpip_slots) = tcpip_slots.alloc();
+        let tcpip_control_producer = Producer::new(
+            &console_control_producer_setup,
+            slots_p,
+            &mut tcpip_vspace,
+            &root_cnode,
+            slots,
+        )?;
+
+        //
+        // applications/pcap setup
+        //
+
+        log::debug!("[root-task] Setting up pcap application");
+
+        let (asid, asid_pool) = asid_pool.alloc();
+        let vspace_slots: LocalCNodeSlots<ferros::arch::CodePageCount> = slots;
+        let vspace_ut: LocalCap<Untyped<U16>> = ut;
+        let mut pcap_vspace = VSpace::new_from_elf::<resources::Pcap>(
+            retype(ut, slots)?, // paging_root
+            asid,
+            vspace_slots.weaken(), // slots
+            vspace_ut.weaken(),    // paging_untyped
+            pcap_elf_data,
+            slots, // page_slots
+            ut,    // elf_writable_mem
+            &user_image,
+            &root_cnode,
+            &mut scratch,
+        )?;
+        let (pcap_cnode, pcap_slots) = retype_cnode::<U12>(ut, slots)?;
+
+        // pcap <- tcpip captured frame consumer
+        let (slots_c, _pcap_slots) = pcap_slots.alloc();
+        let (pcap_consumer, _pcap_consumer_token, pcap_producer_setup, _pcap_waker_setup) =
+            Consumer1::new::<CaptureIpcQueueDepth, CaptureIpcQueuePageBits, _>(
+                ut,
+                ut,
+                &mut scratch,
+                &mut pcap_vspace,
+                &root_cnode,
+                slots,
+                slots,
+                slots,
+                slots_c,
+            )?;
+
+        // tcpip -> pcap captured frame producer
+        let (slots_p, tcpip_slots) = tcpip_slots.alloc();
+        let tcpip_capture_producer = Producer::new(
+            &pcap_producer_setup,
+            slots_p,
+            &mut tcpip_vspace,
+            &root_cnode,
+            slots,
+        )?;
+
+        let params = pcap::ProcParams {
+            frame_consumer: pcap_consumer,
+            root_build: build_metadata::BUILD_METADATA,
+        };
+        let stack_mem: UnmappedMemoryRegion<<resources::Pcap as ElfProc>::StackSizeBits, _> =
+            UnmappedMemoryRegion::new(ut, slots).unwrap();
+        let stack_mem =
+            root_vspace.map_region(stack_mem, CapRights::RW, arch::vm_attributes::DEFAULT)?;
+        let mut pcap_process = StandardProcess::new::<pcap::ProcParams<_>, _>(
+            &mut pcap_vspace,
+            pcap_cnode,
+            stack_mem,
+            &root_cnode,
+            pcap_elf_data,
+            params,
+            ut, // ipc_buffer_ut
+            ut, // tcb_ut
+            slots,
+            &tpa, // priority_authority
+            None, // fault
+        )?;
+
         //
         // drivers/tcpip setup continued
         //
 
-        let socket_buffer_mem_unmapped: UnmappedMemoryRegion<tcpip::RxTxSocketBufferSizeBits, _> =
+        let socket_buffer_mem_unmapped: UnmappedMemoryRegion<tcpip::SocketBufferMemSizeBits, _> =
             UnmappedMemoryRegion::new(ut, slots)?;
         let (mem_slots, _tcpip_slots) = tcpip_slots.alloc();
         let socket_buffer_mem = tcpip_vspace.map_region_and_move(
@@ -318,11 +730,9 @@ fn run(raw_bootinfo: &'static selfe_sys::seL4_BootInfo) -> Result<(), TopLevelEr
             &root_cnode,
             mem_slots,
         )?;
-        let gpt_ut = dev_allocator
-            .get_untyped_by_address_range_slot_infallible(
-                PageAlignedAddressRange::new_by_size(GPT::PADDR as _, GPT::SIZE)?,
-                slots,
-            )?
+        let gpt_ut = devices
+            .claim(DeviceClaim::new(P::TIMER.paddr, P::TIMER.size)?, slots)?
+            .untyped
             .as_strong::<arch::PageBits>()
             .expect("Device untyped was not the right size!");
         let gpt_mem = tcpip_vspace.map_region(
@@ -334,10 +744,19 @@ fn run(raw_bootinfo: &'static selfe_sys::seL4_BootInfo) -> Result<(), TopLevelEr
             gpt: unsafe { GPT::from_vaddr(gpt_mem.vaddr() as _) },
             frame_consumer: tcpip_eth_consumer,
             frame_producer: tcpip_eth_producer,
+            frame_pool_mem: tcpip_frame_pool_mem,
             event_consumer: tcpip_event_consumer,
+            udp_rx_producer: tcpip_udp_rx_producer,
+            capture_producer: tcpip_capture_producer,
+            control_producer: tcpip_control_producer,
+            udp_service_port: UDP_SERVICE_PORT,
             socket_buffer_mem,
             mac_addr: MAC_ADDRESS,
             ip_addr: IP_ADDRESS,
+            sequence_counter_mem: tcpip_sequence_counter_mem,
+            feature_flags_mem: tcpip_feature_flags_mem,
+            liveness_mem: tcpip_liveness_mem,
+            root_build: build_metadata::BUILD_METADATA,
         };
         let stack_mem: UnmappedMemoryRegion<<resources::TcpIp as ElfProc>::StackSizeBits, _> =
             UnmappedMemoryRegion::new(ut, slots).unwrap();
@@ -361,11 +780,9 @@ fn run(raw_bootinfo: &'static selfe_sys::seL4_BootInfo) -> Result<(), TopLevelEr
         // drivers/enet setup continued
         //
 
-        let enet_ut = dev_allocator
-            .get_untyped_by_address_range_slot_infallible(
-                PageAlignedAddressRange::new_by_size(ENET::PADDR as _, ENET::SIZE)?,
-                slots,
-            )?
+        let enet_ut = devices
+            .claim(DeviceClaim::new(P::ETHERNET.paddr, P::ETHERNET.size)?, slots)?
+            .untyped
             .as_strong::<arch::PageBits>()
             .expect("Device untyped was not the right size!");
         let enet_mem = enet_vspace.map_region(
@@ -375,21 +792,27 @@ fn run(raw_bootinfo: &'static selfe_sys::seL4_BootInfo) -> Result<(), TopLevelEr
         )?;
         let dma_mem_unmapped: UnmappedMemoryRegion<enet::EthDmaMemSizeInBits, _> =
             UnmappedMemoryRegion::new(ut, slots)?;
-        let (mem_slots, _enet_slots) = enet_slots.alloc();
-        let dma_mem = enet_vspace.map_region_and_move(
+        regions.tag(&dma_mem_unmapped, "enet dma pool", Some(ENET_PROCESS_ID))?;
+        let (mem_slots, enet_slots) = enet_slots.alloc();
+        let dma_mem = enet_vspace.map_region_and_move_with_cache_status(
             dma_mem_unmapped,
             CapRights::RW,
-            // NOTE: driver expects uncached DMA memory for the time being
-            arch::vm_attributes::DEFAULT & !arch::vm_attributes::PAGE_CACHEABLE,
             &root_cnode,
             mem_slots,
         )?;
+        let (waker_slot, _enet_slots) = enet_slots.alloc();
         let params = enet::ProcParams {
             enet: unsafe { ENET::from_vaddr(enet_mem.vaddr() as _) },
             consumer: enet_consumer,
+            waker: Waker::new(&enet_waker_setup, waker_slot, &root_cnode)?,
             producer: enet_producer,
+            frame_pool_mem: enet_frame_pool_mem,
             dma_mem,
             mac_addr: MAC_ADDRESS,
+            sequence_counter_mem: enet_sequence_counter_mem,
+            feature_flags_mem: enet_feature_flags_mem,
+            liveness_mem: enet_liveness_mem,
+            root_build: build_metadata::BUILD_METADATA,
         };
         let stack_mem: UnmappedMemoryRegion<<resources::Enet as ElfProc>::StackSizeBits, _> =
             UnmappedMemoryRegion::new(ut, slots).unwrap();
@@ -459,11 +882,9 @@ fn run(raw_bootinfo: &'static selfe_sys::seL4_BootInfo) -> Result<(), TopLevelEr
             &root_cnode,
             mem_slots,
         )?;
-        let spi1_ut = dev_allocator
-            .get_untyped_by_address_range_slot_infallible(
-                PageAlignedAddressRange::new_by_size(ECSPI1::PADDR as _, ECSPI1::SIZE)?,
-                slots,
-            )?
+        let spi1_ut = devices
+            .claim(DeviceClaim::new(P::STORAGE_BUS.paddr, P::STORAGE_BUS.size)?, slots)?
+            .untyped
             .as_strong::<arch::PageBits>()
             .expect("Device untyped was not the right size!");
         let spi1_mem = pstorage_vspace.map_region(
@@ -471,11 +892,9 @@ fn run(raw_bootinfo: &'static selfe_sys::seL4_BootInfo) -> Result<(), TopLevelEr
             CapRights::RW,
             arch::vm_attributes::DEFAULT & !arch::vm_attributes::PAGE_CACHEABLE,
         )?;
-        let gpio3_ut = dev_allocator
-            .get_untyped_by_address_range_slot_infallible(
-                PageAlignedAddressRange::new_by_size(GPIO3::PADDR as _, GPIO3::SIZE)?,
-                slots,
-            )?
+        let gpio3_ut = devices
+            .claim(DeviceClaim::new(GPIO3::PADDR as _, GPIO3::SIZE)?, slots)?
+            .untyped
             .as_strong::<arch::PageBits>()
             .expect("Device untyped was not the right size!");
         let gpio3_mem = pstorage_vspace.map_region(
@@ -490,6 +909,7 @@ fn run(raw_bootinfo: &'static selfe_sys::seL4_BootInfo) -> Result<(), TopLevelEr
             responder,
             storage_buffer,
             scratchpad_buffer,
+            root_build: build_metadata::BUILD_METADATA,
         };
         let stack_mem: UnmappedMemoryRegion<
             <resources::PersistentStorage as ElfProc>::StackSizeBits,
@@ -512,37 +932,91 @@ fn run(raw_bootinfo: &'static selfe_sys::seL4_BootInfo) -> Result<(), TopLevelEr
         )?;
 
         //
-        // applications/console setup
+        // drivers/config-service setup
         //
 
-        log::debug!("[root-task] Setting up console application");
+        log::debug!("[root-task] Setting up config-service");
 
         let (asid, _asid_pool) = asid_pool.alloc();
         let vspace_slots: LocalCNodeSlots<ferros::arch::CodePageCount> = slots;
         let vspace_ut: LocalCap<Untyped<U16>> = ut;
-        let mut console_vspace = VSpace::new_from_elf::<resources::Console>(
+        let mut config_vspace = VSpace::new_from_elf::<resources::ConfigService>(
             retype(ut, slots)?, // paging_root
             asid,
             vspace_slots.weaken(), // slots
             vspace_ut.weaken(),    // paging_untyped
-            console_elf_data,
+            config_elf_data,
             slots, // page_slots
             ut,    // elf_writable_mem
             &user_image,
             &root_cnode,
             &mut scratch,
         )?;
-        let (console_cnode, console_slots) = retype_cnode::<U12>(ut, slots)?;
-        let (ipc_slots, console_slots) = console_slots.alloc();
+        let (config_cnode, config_slots) = retype_cnode::<U12>(ut, slots)?;
+        let (ipc_slots, config_slots) = config_slots.alloc();
+        let (config_ipc_setup, responder) = call_channel(ut, &root_cnode, slots, ipc_slots)?;
+        let (ipc_slots, config_slots) = config_slots.alloc();
         let storage_caller = pstorage_ipc_setup.create_caller(ipc_slots)?;
-        let (slots_c, console_slots) = console_slots.alloc();
-        let (int_consumer, _int_consumer_token) =
-            InterruptConsumer::new(ut, &mut irq_control, &root_cnode, slots, slots_c)?;
-        let uart1_ut = dev_allocator
-            .get_untyped_by_address_range_slot_infallible(
-                PageAlignedAddressRange::new_by_size(UART1::PADDR as _, UART1::SIZE)?,
+
+        // config-service -> tcpip net entry change producer
+        let (slots_p, _config_slots) = config_slots.alloc();
+        let tcpip_config_producer = Producer::new(
+            &tcpip_config_producer_setup,
+            slots_p,
+            &mut config_vspace,
+            &root_cnode,
+            slots,
+        )?;
+
+        let params = config_service::ProcParams {
+            responder,
+            storage_caller,
+            tcpip_subscription: config_service::Subscription {
+                producer: tcpip_config_producer,
+                keys: ConfigKeySet::empty()
+                    .with(config_service::entry::NetIpAddr::KEY)
+                    .with(config_service::entry::NetPrefixLen::KEY),
+            },
+            feature_flags_mem: config_vspace.map_shared_region(
+                &feature_flags_mem_unmapped,
+                CapRights::RW,
+                arch::vm_attributes::DEFAULT,
                 slots,
-            )?
+                &root_cnode,
+            )?,
+            root_build: build_metadata::BUILD_METADATA,
+        };
+        let stack_mem: UnmappedMemoryRegion<
+            <resources::ConfigService as ElfProc>::StackSizeBits,
+            _,
+        > = UnmappedMemoryRegion::new(ut, slots).unwrap();
+        let stack_mem =
+            root_vspace.map_region(stack_mem, CapRights::RW, arch::vm_attributes::DEFAULT)?;
+        let mut config_process = StandardProcess::new::<config_service::ProcParams<_>, _>(
+            &mut config_vspace,
+            config_cnode,
+            stack_mem,
+            &root_cnode,
+            config_elf_data,
+            params,
+            ut, // ipc_buffer_ut
+            ut, // tcb_ut
+            slots,
+            &tpa, // priority_authority
+            None, // fault
+        )?;
+
+        //
+        // applications/console setup continued
+        //
+
+        let (ipc_slots, console_slots) = console_slots.alloc();
+        let storage_caller = pstorage_ipc_setup.create_caller(ipc_slots)?;
+        let (ipc_slots, console_slots) = console_slots.alloc();
+        let config_caller = config_ipc_setup.create_caller(ipc_slots)?;
+        let uart1_ut = devices
+            .claim(DeviceClaim::new(P::CONSOLE_UART.paddr, P::CONSOLE_UART.size)?, slots)?
+            .untyped
             .as_strong::<arch::PageBits>()
             .expect("Device untyped was not the right size!");
         let (slots_p, console_slots) = console_slots.alloc();
@@ -553,11 +1027,29 @@ fn run(raw_bootinfo: &'static selfe_sys::seL4_BootInfo) -> Result<(), TopLevelEr
             &root_cnode,
             slots,
         )?;
+        let (slots_p, console_slots) = console_slots.alloc();
+        let net_control_producer = Producer::new(
+            &tcpip_control_producer_setup,
+            slots_p,
+            &mut console_vspace,
+            &root_cnode,
+            slots,
+        )?;
         let uart1_mem = console_vspace.map_region(
             UnmappedMemoryRegion::new_device(uart1_ut, slots)?,
             CapRights::RW,
             arch::vm_attributes::DEFAULT & !arch::vm_attributes::PAGE_CACHEABLE,
         )?;
+        let wdog1_ut = devices
+            .claim(DeviceClaim::new(WDOG1::PADDR as _, WDOG1::SIZE)?, slots)?
+            .untyped
+            .as_strong::<arch::PageBits>()
+            .expect("Device untyped was not the right size!");
+        let wdog1_mem = console_vspace.map_region(
+            UnmappedMemoryRegion::new_device(wdog1_ut, slots)?,
+            CapRights::RW,
+            arch::vm_attributes::DEFAULT & !arch::vm_attributes::PAGE_CACHEABLE,
+        )?;
         let console_buffer_unmapped: UnmappedMemoryRegion<console::ConsoleBufferSizeBits, _> =
             UnmappedMemoryRegion::new(ut, slots)?;
         let (mem_slots, _console_slots) = console_slots.alloc();
@@ -570,10 +1062,34 @@ fn run(raw_bootinfo: &'static selfe_sys::seL4_BootInfo) -> Result<(), TopLevelEr
         )?;
         let params = console::ProcParams {
             uart: unsafe { UART1::from_vaddr(uart1_mem.vaddr() as _) },
+            wdog: unsafe { WDOG1::from_vaddr(wdog1_mem.vaddr() as _) },
             int_consumer,
             storage_caller,
+            config_caller,
             udp_producer,
+            net_control_producer,
             console_buffer,
+            sequence_counter_mem: console_vspace.map_shared_region_and_consume(
+                sequence_counter_mem_unmapped,
+                CapRights::RW,
+                arch::vm_attributes::DEFAULT,
+            )?,
+            feature_flags_mem: console_vspace.map_shared_region_and_consume(
+                feature_flags_mem_unmapped,
+                CapRights::R,
+                arch::vm_attributes::DEFAULT,
+            )?,
+            cpu_stats_mem: console_vspace.map_shared_region_and_consume(
+                cpu_stats_mem_unmapped,
+                CapRights::R,
+                arch::vm_attributes::DEFAULT,
+            )?,
+            liveness_mem: console_vspace.map_shared_region_and_consume(
+                liveness_mem_unmapped,
+                CapRights::R,
+                arch::vm_attributes::DEFAULT,
+            )?,
+            root_build: build_metadata::BUILD_METADATA,
         };
         let stack_mem: UnmappedMemoryRegion<<resources::Console as ElfProc>::StackSizeBits, _> =
             UnmappedMemoryRegion::new(ut, slots).unwrap();
@@ -592,6 +1108,41 @@ fn run(raw_bootinfo: &'static selfe_sys::seL4_BootInfo) -> Result<(), TopLevelEr
             &tpa, // priority_authority
             None, // fault
         )?;
+
+        //
+        // applications/udp-perf setup continued
+        //
+
+        let (slots_p, _udp_perf_slots) = udp_perf_slots.alloc();
+        let udp_producer = Producer::new(
+            &tcpip_service_producer_setup,
+            slots_p,
+            &mut udp_perf_vspace,
+            &root_cnode,
+            slots,
+        )?;
+        let params = udp_perf::ProcParams {
+            udp_consumer: udp_perf_consumer,
+            udp_producer,
+            root_build: build_metadata::BUILD_METADATA,
+        };
+        let stack_mem: UnmappedMemoryRegion<<resources::UdpPerf as ElfProc>::StackSizeBits, _> =
+            UnmappedMemoryRegion::new(ut, slots).unwrap();
+        let stack_mem =
+            root_vspace.map_region(stack_mem, CapRights::RW, arch::vm_attributes::DEFAULT)?;
+        let mut udp_perf_process = StandardProcess::new::<udp_perf::ProcParams<_>, _>(
+            &mut udp_perf_vspace,
+            udp_perf_cnode,
+            stack_mem,
+            &root_cnode,
+            udp_perf_elf_data,
+            params,
+            ut, // ipc_buffer_ut
+            ut, // tcb_ut
+            slots,
+            &tpa, // priority_authority
+            None, // fault
+        )?;
     });
 
     iomux_process.set_name("iomux");
@@ -612,14 +1163,52 @@ fn run(raw_bootinfo: &'static selfe_sys::seL4_BootInfo) -> Result<(), TopLevelEr
     pstorage_process.start()?;
     simple_yield_delay(1000);
 
+    config_process.set_name("config-service");
+    config_process.start()?;
+    simple_yield_delay(1000);
+
     console_process.set_name("console");
     unsafe { selfe_sys::seL4_TCB_SetAffinity(console_process.unsafe_get_tcb_cptr(), 3) };
     console_process.start()?;
+    simple_yield_delay(1000);
+
+    udp_perf_process.set_name("udp-perf");
+    udp_perf_process.start()?;
+    simple_yield_delay(1000);
+
+    pcap_process.set_name("pcap");
+    pcap_process.start()?;
+
+    for region in regions.iter() {
+        log::debug!("[root-task] Region {}", region);
+    }
+
+    // Spend the rest of the root task's life idling on each core in
+    // turn, to sample their utilization, checking on the liveness signals
+    // after each sample. Time is kept by the sample windows, the idle loop
+    // having nothing better to go by.
+    let mut idle_tracker = unsafe { IdleTracker::new(cpu_stats, IDLE_YIELD_CYCLES) };
+    let mut now_ms: u64 = 0;
+    let mut liveness_monitor = LivenessMonitor::new(liveness, liveness::system::SIGNALS, now_ms);
+    loop {
+        for core in 0..P::CORE_COUNT {
+            unsafe {
+                selfe_sys::seL4_TCB_SetAffinity(selfe_sys::seL4_CapInitThreadTCB as _, core as _);
+                // Get moved over to the core before sampling it
+                selfe_sys::seL4_Yield();
+            }
+            idle_tracker.sample(core, IDLE_SAMPLE_WINDOW_CYCLES);
 
-    // NOTE: we could stop the root-task here instead
-    unsafe {
-        loop {
-            selfe_sys::seL4_Yield();
+            now_ms += (IDLE_SAMPLE_WINDOW_CYCLES / P::CPU_CYCLES_PER_MS) as u64;
+            liveness_monitor.check(now_ms, |signal, quiet_ms| {
+                log::warn!(
+                    "[root-task] {}::{} has been quiet for {}ms, over its {}ms period",
+                    signal.process,
+                    signal.name,
+                    quiet_ms,
+                    signal.period_ms
+                );
+            });
         }
     }
 }
@@ -0,0 +1,51 @@
+#!/usr/bin/env python3
+"""Extract the pcap capture emitted by the pcap application from a serial log.
+
+Reads the log from a file (or stdin when omitted) and writes the `PCAP <hex>`
+lines as a .pcap file, e.g.:
+
+    ./scripts/simulate.sh | tee sim.log
+    ./scripts/pcap-from-log.py sim.log -o capture.pcap
+    wireshark capture.pcap
+"""
+
+import argparse
+import struct
+import sys
+
+LINE_PREFIX = "PCAP "
+GLOBAL_HEADER = struct.pack("<IHHiIII", 0xA1B2C3D4, 2, 4, 0, 0, 65535, 1)
+
+
+def main():
+    parser = argparse.ArgumentParser(description=__doc__.splitlines()[0])
+    parser.add_argument("log", nargs="?", help="Serial log file, defaults to stdin")
+    parser.add_argument("-o", "--output", required=True, help="Output .pcap file")
+    args = parser.parse_args()
+
+    src = open(args.log, errors="replace") if args.log else sys.stdin
+    records = 0
+    with open(args.output, "wb") as out:
+        # The capture process emits the global header once at startup,
+        # write our own in case the log starts later than that
+        out.write(GLOBAL_HEADER)
+        for line in src:
+            idx = line.find(LINE_PREFIX)
+            if idx < 0:
+                continue
+            try:
+                data = bytes.fromhex(line[idx + len(LINE_PREFIX):].strip())
+            except ValueError:
+                print(f"Skipping malformed line: {line.strip()}", file=sys.stderr)
+                continue
+            if data == GLOBAL_HEADER:
+                continue
+            out.write(data)
+            records += 1
+
+    print(f"Wrote {records} records to {args.output}")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(main())
@@ -0,0 +1,140 @@
+#!/usr/bin/env python3
+"""Host peer for the udp-perf application.
+
+echo:       measure round trip latency of datagrams echoed by the target
+throughput: stream sequence numbered datagrams at the target, then request
+            its receive side report
+
+Exits non-zero when a run exceeds the given loss or latency thresholds so it
+can be used as a regression check.
+"""
+
+import argparse
+import socket
+import struct
+import sys
+import time
+
+MAGIC = 0x46455250
+HEADER = struct.Struct(">IB3xI")
+STATS = struct.Struct(">IIII")
+
+KIND_DATA = 1
+KIND_REPORT_REQUEST = 2
+KIND_REPORT = 3
+
+
+def echo(sock, args):
+    rtts = []
+    timeouts = 0
+    payload = bytes(i % 256 for i in range(args.size))
+    sock.settimeout(args.timeout)
+    for _ in range(args.count):
+        start = time.monotonic()
+        sock.send(payload)
+        try:
+            data = sock.recv(65536)
+        except socket.timeout:
+            timeouts += 1
+            continue
+        if data != payload:
+            print("Echo reply payload mismatch", file=sys.stderr)
+            return 1
+        rtts.append((time.monotonic() - start) * 1000.0)
+        if args.interval:
+            time.sleep(args.interval)
+
+    print(f"sent={args.count} received={len(rtts)} timeouts={timeouts}")
+    if rtts:
+        print(
+            f"rtt_ms min={min(rtts):.3f} avg={sum(rtts) / len(rtts):.3f} max={max(rtts):.3f}"
+        )
+
+    if timeouts > args.count * args.max_loss / 100.0:
+        print(f"FAIL: loss exceeds {args.max_loss}%", file=sys.stderr)
+        return 1
+    if args.max_rtt is not None and rtts and max(rtts) > args.max_rtt:
+        print(f"FAIL: rtt exceeds {args.max_rtt} ms", file=sys.stderr)
+        return 1
+    return 0
+
+
+def throughput(sock, args):
+    padding = bytes(max(0, args.size - HEADER.size))
+    seq = 0
+    start = time.monotonic()
+    deadline = start + args.duration
+    gap = 1.0 / args.rate if args.rate else 0.0
+    next_send = start
+    while time.monotonic() < deadline:
+        sock.send(HEADER.pack(MAGIC, KIND_DATA, seq) + padding)
+        seq += 1
+        if gap:
+            next_send += gap
+            delay = next_send - time.monotonic()
+            if delay > 0:
+                time.sleep(delay)
+    elapsed = time.monotonic() - start
+
+    # Let the target drain its queues before asking for the report
+    time.sleep(args.timeout)
+    sock.settimeout(args.timeout)
+    sock.send(HEADER.pack(MAGIC, KIND_REPORT_REQUEST, seq))
+    try:
+        data = sock.recv(65536)
+    except socket.timeout:
+        print("FAIL: no report received", file=sys.stderr)
+        return 1
+
+    magic, kind, report_seq = HEADER.unpack_from(data)
+    if magic != MAGIC or kind != KIND_REPORT or report_seq != seq:
+        print("FAIL: malformed report", file=sys.stderr)
+        return 1
+    datagrams, nbytes, _lost, reordered = STATS.unpack_from(data, HEADER.size)
+
+    # Datagrams never seen after the last received sequence number are lost too
+    lost = seq - datagrams
+    loss = 100.0 * lost / seq if seq else 0.0
+    print(f"sent={seq} received={datagrams} lost={lost} ({loss:.2f}%) reordered={reordered}")
+    print(
+        f"elapsed={elapsed:.3f}s tx_rate={seq / elapsed:.1f} pkt/s "
+        f"rx_throughput={nbytes * 8 / elapsed / 1000.0:.1f} kbit/s"
+    )
+
+    if loss > args.max_loss:
+        print(f"FAIL: loss exceeds {args.max_loss}%", file=sys.stderr)
+        return 1
+    return 0
+
+
+def main():
+    parser = argparse.ArgumentParser(description=__doc__.splitlines()[0])
+    parser.add_argument("--addr", default="192.0.2.80", help="Target IP address")
+    parser.add_argument("--port", type=int, default=7, help="Target UDP port")
+    parser.add_argument("--size", type=int, default=64, help="Datagram size in bytes")
+    parser.add_argument("--timeout", type=float, default=1.0, help="Reply timeout in seconds")
+    parser.add_argument("--max-loss", type=float, default=0.0, help="Loss threshold percentage")
+    sub = parser.add_subparsers(dest="mode", required=True)
+
+    p = sub.add_parser("echo", help="Round trip latency measurement")
+    p.add_argument("--count", type=int, default=100, help="Number of datagrams")
+    p.add_argument("--interval", type=float, default=0.0, help="Delay between datagrams")
+    p.add_argument("--max-rtt", type=float, default=None, help="RTT threshold in ms")
+
+    p = sub.add_parser("throughput", help="Receive throughput measurement")
+    p.add_argument("--duration", type=float, default=5.0, help="Run time in seconds")
+    p.add_argument("--rate", type=float, default=0.0, help="Datagrams per second, 0 is unlimited")
+
+    args = parser.parse_args()
+    if args.size < HEADER.size and args.mode == "throughput":
+        parser.error(f"--size must be at least {HEADER.size} bytes")
+
+    sock = socket.socket(socket.AF_INET, socket.SOCK_DGRAM)
+    sock.connect((args.addr, args.port))
+    if args.mode == "echo":
+        return echo(sock, args)
+    return throughput(sock, args)
+
+
+if __name__ == "__main__":
+    sys.exit(main())
@@ -22,12 +22,24 @@ cargo build -p enet $@;
 echo "======================= building tcpip ======================"
 cargo build -p tcpip $@;
 
+echo "======================= building bridge ======================"
+cargo build -p bridge $@;
+
 echo "======================= building persistent-storage ======================"
 cargo build -p persistent-storage $@;
 
+echo "======================= building config-service ======================"
+cargo build -p config-service $@;
+
 echo "======================= building console ======================"
 cargo build -p console $@;
 
+echo "======================= building udp-perf ======================"
+cargo build -p udp-perf $@;
+
+echo "======================= building pcap ======================"
+cargo build -p pcap $@;
+
 echo "======================== building root-task ======================="
 cargo build -p root-task $@;
 
@@ -0,0 +1,32 @@
+//! List the sites in the given source files making the most work for the
+//! type checker, worst first.
+//!
+//! Usage: typenum-costs [-n COUNT] FILE...
+
+use std::process;
+
+use ferros_build::estimate_typenum_costs_in_files;
+
+fn main() {
+    let mut args = std::env::args().skip(1).peekable();
+    let mut count = 20;
+    if args.peek().map(String::as_str) == Some("-n") {
+        args.next();
+        count = match args.next().and_then(|n| n.parse().ok()) {
+            Some(n) => n,
+            None => {
+                eprintln!("-n needs a number of sites to list");
+                process::exit(2);
+            }
+        };
+    }
+    let paths: Vec<String> = args.collect();
+    if paths.is_empty() {
+        eprintln!("Usage: typenum-costs [-n COUNT] FILE...");
+        process::exit(2);
+    }
+
+    for cost in estimate_typenum_costs_in_files(&paths).iter().take(count) {
+        println!("{}", cost);
+    }
+}
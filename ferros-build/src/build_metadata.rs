@@ -0,0 +1,137 @@
+//! Generating the `BUILD_METADATA` constant, see
+//! `ferros::userland::BuildMetadata`.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::{elf_segment_hash, Resource};
+
+/// Write a file defining `BUILD_METADATA: ferros::userland::BuildMetadata`
+/// for the crate being built, for it to `include!`. `resources` are the
+/// ones embedded in the crate, if any, and are hashed into the metadata.
+///
+/// The build timestamp is taken from `SOURCE_DATE_EPOCH` if it's set,
+/// for reproducible builds.
+pub fn write_build_metadata<P: AsRef<Path>>(path: P, resources: &[&dyn Resource]) {
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+
+    let git_hash = git(&["rev-parse", "HEAD"]);
+    let git_dirty = git(&["status", "--porcelain"]).map_or(false, |s| !s.is_empty());
+    let profile = env::var("PROFILE").unwrap_or_default();
+    let build_timestamp = env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0)
+        });
+    let resources_hash = if resources.is_empty() {
+        0
+    } else {
+        let mut resource_hashes = Vec::new();
+        for res in resources {
+            let contents = fs::read(res.path()).unwrap_or_else(|_| {
+                panic!(
+                    "write_build_metadata: Couldn't read resource {}",
+                    res.path().display()
+                )
+            });
+            let name_hash = elf_segment_hash(res.image_name().as_bytes());
+            resource_hashes.extend_from_slice(&name_hash.to_le_bytes());
+            resource_hashes.extend_from_slice(&elf_segment_hash(&contents).to_le_bytes());
+        }
+        elf_segment_hash(&resource_hashes)
+    };
+
+    let code = format_build_metadata(
+        git_hash.as_deref(),
+        git_dirty,
+        &profile,
+        build_timestamp,
+        resources_hash,
+    );
+    fs::write(path, code).expect("Unable to write generated code for build metadata");
+}
+
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8(output.stdout)
+        .ok()
+        .map(|s| s.trim().to_owned())
+}
+
+fn format_build_metadata(
+    git_hash: Option<&str>,
+    git_dirty: bool,
+    profile: &str,
+    build_timestamp: u64,
+    resources_hash: u64,
+) -> String {
+    let git_hash = match git_hash {
+        Some(hash) if hash.len() == 40 && hash.bytes().all(|b| b.is_ascii_hexdigit()) => {
+            format!("*b\"{}\"", hash)
+        }
+        _ => "[0; 40]".to_owned(),
+    };
+    let profile = match profile {
+        "debug" => "Debug",
+        "release" => "Release",
+        _ => "Unknown",
+    };
+    format!(
+        r#"
+pub const BUILD_METADATA: ferros::userland::BuildMetadata = ferros::userland::BuildMetadata {{
+    git_hash: {},
+    git_dirty: {},
+    profile: ferros::userland::BuildProfile::{},
+    build_timestamp: {},
+    resources_hash: {:#x},
+}};
+"#,
+        git_hash, git_dirty, profile, build_timestamp, resources_hash
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn formats_known_git_hash_and_profile() {
+        let code = format_build_metadata(
+            Some("0123456789abcdef0123456789abcdef01234567"),
+            true,
+            "release",
+            1234,
+            0xabc,
+        );
+        assert!(code.contains("git_hash: *b\"0123456789abcdef0123456789abcdef01234567\","));
+        assert!(code.contains("git_dirty: true,"));
+        assert!(code.contains("profile: ferros::userland::BuildProfile::Release,"));
+        assert!(code.contains("build_timestamp: 1234,"));
+        assert!(code.contains("resources_hash: 0xabc,"));
+    }
+
+    #[test]
+    fn zeroes_unknown_or_malformed_git_hash() {
+        for hash in &[
+            None,
+            Some("abc"),
+            Some("not a hash, but exactly forty characters"),
+        ] {
+            let code = format_build_metadata(*hash, false, "debug", 0, 0);
+            assert!(code.contains("git_hash: [0; 40],"));
+            assert!(code.contains("profile: ferros::userland::BuildProfile::Debug,"));
+        }
+        let code = format_build_metadata(None, false, "bench", 0, 0);
+        assert!(code.contains("profile: ferros::userland::BuildProfile::Unknown,"));
+    }
+}
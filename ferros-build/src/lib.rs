@@ -2,18 +2,77 @@
 
 use memmap::Mmap;
 use selfe_arc;
+use std::fmt;
 use std::fs::{self, File};
+use std::io;
 use std::path::{Path, PathBuf};
 use xmas_elf;
 
+mod build_metadata;
+mod typenum_cost;
+
+pub use build_metadata::*;
+pub use typenum_cost::*;
+
 /// A resource that can be embedded in a ferros binary
 pub trait Resource {
     fn path(&self) -> &Path;
     /// The name this will get in the embedded selfe-arc
     fn image_name(&self) -> &str;
-    fn codegen(&self) -> String;
+    fn codegen(&self) -> Result<String, CodegenError>;
+}
+
+/// Why the code for a resource couldn't be generated
+#[derive(Debug)]
+pub enum CodegenError {
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    ElfParse {
+        path: PathBuf,
+        error: &'static str,
+    },
+    /// A loadable segment of the elf file is both writable and executable
+    ElfSegmentWritableAndExecutable {
+        path: PathBuf,
+        segment: usize,
+    },
+    /// A loadable segment of the elf file shares a page with the one before
+    /// it, and one of them is writable and the other executable, the
+    /// resource refuses shared pages, or both are read-only with different
+    /// contents for the page
+    ElfSegmentsSharePage {
+        path: PathBuf,
+        segment: usize,
+    },
 }
 
+impl fmt::Display for CodegenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CodegenError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            CodegenError::ElfParse { path, error } => {
+                write!(f, "{}: couldn't parse elf file: {}", path.display(), error)
+            }
+            CodegenError::ElfSegmentWritableAndExecutable { path, segment } => write!(
+                f,
+                "{}: segment {} is both writable and executable",
+                path.display(),
+                segment
+            ),
+            CodegenError::ElfSegmentsSharePage { path, segment } => write!(
+                f,
+                "{}: segment {} shares a page with the segment before it",
+                path.display(),
+                segment
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CodegenError {}
+
 /// A data file resource
 pub struct DataResource {
     pub path: PathBuf,
@@ -29,8 +88,8 @@ impl Resource for DataResource {
         &self.image_name
     }
 
-    fn codegen(&self) -> String {
-        "".to_owned()
+    fn codegen(&self) -> Result<String, CodegenError> {
+        Ok("".to_owned())
     }
 }
 
@@ -44,6 +103,10 @@ pub struct ElfResource {
     pub type_name: String,
     /// Explicitly specify the process stack size
     pub stack_size_bits: Option<u8>,
+    /// Refuse images with segments sharing a page, rather than have the
+    /// page mapped with the rights of all of them. A page shared by a
+    /// writable and an executable segment is always refused.
+    pub reject_shared_segment_pages: bool,
 }
 
 /// Format n as a fully expanded typenum (in binary form), so allowing arbitrary
@@ -58,6 +121,13 @@ fn format_as_typenum(n: u64) -> String {
     }
 }
 
+/// 64-bit FNV-1a, matching `ferros::vspace::elf_segment_hash`
+fn elf_segment_hash(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
 fn round_down_to_page_boundary(addr: u64) -> u64 {
     addr & !0xfff
 }
@@ -70,6 +140,15 @@ fn round_up_to_page_boundary(addr: u64) -> u64 {
     }
 }
 
+/// How many pages the address range `start..end` touches
+fn pages_spanned(start: u64, end: u64) -> u64 {
+    if start == end {
+        0
+    } else {
+        (round_up_to_page_boundary(end) - round_down_to_page_boundary(start)) >> 12
+    }
+}
+
 impl Resource for ElfResource {
     fn path(&self) -> &Path {
         &self.path
@@ -79,25 +158,92 @@ impl Resource for ElfResource {
         &self.image_name
     }
 
-    fn codegen(&self) -> String {
-        let file = File::open(&self.path).expect(&format!(
-            "ElfResource::codegen: Couldn't open file {}",
-            self.path.display()
-        ));
-        let data = unsafe { Mmap::map(&file).unwrap() };
-        let elf_file = xmas_elf::ElfFile::new(data.as_ref()).unwrap();
+    fn codegen(&self) -> Result<String, CodegenError> {
+        let io_error = |error| CodegenError::Io {
+            path: self.path.clone(),
+            error,
+        };
+        let file = File::open(&self.path).map_err(io_error)?;
+        let data = unsafe { Mmap::map(&file).map_err(io_error)? };
+        let elf_file =
+            xmas_elf::ElfFile::new(data.as_ref()).map_err(|error| CodegenError::ElfParse {
+                path: self.path.clone(),
+                error,
+            })?;
 
         let mut read_only_pages = 0;
         let mut writable_pages = 0;
+        let mut segment_hashes = Vec::new();
+        let mut segment_page_counts = Vec::new();
+        let mut previous: Option<(u64, bool, bool, u64)> = None;
 
-        for ph in elf_file
+        for (segment, ph) in elf_file
             .program_iter()
             .filter(|h| h.get_type() == Ok(xmas_elf::program::Type::Load))
+            .enumerate()
         {
+            // ferros refuses the same images `VSpace::new_from_elf` does
+            if ph.flags().is_write() && ph.flags().is_execute() {
+                return Err(CodegenError::ElfSegmentWritableAndExecutable {
+                    path: self.path.clone(),
+                    segment,
+                });
+            }
+            // Writable segments are loaded in full, the rest only as far as
+            // the file has contents for them
+            let loaded_size = if ph.flags().is_write() {
+                ph.mem_size()
+            } else {
+                ph.file_size()
+            };
+            let loaded_end = ph.virtual_addr() + loaded_size;
+            if loaded_size > 0 {
+                // Read-only pages are mapped straight from the image, so two
+                // read-only segments can only share a page that's the same
+                // page of the file for both
+                let file_displacement = ph.virtual_addr().wrapping_sub(ph.offset());
+                if let Some((
+                    previous_end_page,
+                    previous_is_write,
+                    previous_is_execute,
+                    previous_displacement,
+                )) = previous
+                {
+                    let writable_and_executable = (ph.flags().is_write() && previous_is_execute)
+                        || (ph.flags().is_execute() && previous_is_write);
+                    let different_file_pages = !ph.flags().is_write()
+                        && !previous_is_write
+                        && file_displacement != previous_displacement;
+                    if round_down_to_page_boundary(ph.virtual_addr()) < previous_end_page
+                        && (self.reject_shared_segment_pages
+                            || writable_and_executable
+                            || different_file_pages)
+                    {
+                        return Err(CodegenError::ElfSegmentsSharePage {
+                            path: self.path.clone(),
+                            segment,
+                        });
+                    }
+                }
+                previous = Some((
+                    round_up_to_page_boundary(loaded_end),
+                    ph.flags().is_write(),
+                    ph.flags().is_execute(),
+                    file_displacement,
+                ));
+            }
+            segment_page_counts.push(pages_spanned(ph.virtual_addr(), loaded_end).to_string());
+
             let page_aligned_segment_size =
                 round_up_to_page_boundary(ph.virtual_addr() + ph.mem_size())
                     - round_down_to_page_boundary(ph.virtual_addr());
             let segment_required_pages = page_aligned_segment_size >> 12;
+            let contents_start = ph.offset() as usize;
+            let contents_end = contents_start + ph.file_size() as usize;
+            segment_hashes.push(format!(
+                "{:#x}",
+                elf_segment_hash(&data[contents_start..contents_end])
+            ));
             if ph.flags().is_write() {
                 writable_pages += segment_required_pages;
             } else {
@@ -119,7 +265,7 @@ impl Resource for ElfResource {
         let required_memory_bits = (writable_pages as f64).log2().ceil() as u32 + 12;
         let required_pages = (1 << (required_memory_bits - 12)) + read_only_pages;
 
-        format!(
+        Ok(format!(
             r#"
 pub struct {} {{ }}
 impl ferros::vspace::ElfProc for {} {{
@@ -128,6 +274,9 @@ impl ferros::vspace::ElfProc for {} {{
     type WritablePages = {};
     type RequiredMemoryBits = {};
     type StackSizeBits = {};
+    const SEGMENT_HASHES: &'static [u64] = &[{}];
+    const SEGMENT_PAGE_COUNTS: &'static [usize] = &[{}];
+    const REJECT_SHARED_SEGMENT_PAGES: bool = {};
 }}
 "#,
             self.type_name,
@@ -136,8 +285,11 @@ impl ferros::vspace::ElfProc for {} {{
             format_as_typenum(required_pages),
             format_as_typenum(writable_pages),
             format_as_typenum(required_memory_bits.into()),
-            format_as_typenum(stack_size_bits)
-        )
+            format_as_typenum(stack_size_bits),
+            segment_hashes.join(", "),
+            segment_page_counts.join(", "),
+            self.reject_shared_segment_pages
+        ))
     }
 }
 
@@ -146,21 +298,26 @@ impl ferros::vspace::ElfProc for {} {{
 pub fn embed_resources<'a, P: AsRef<Path>, I: IntoIterator<Item = &'a dyn Resource>>(
     codegen_path: P,
     resources: I,
-) {
+) -> Result<(), CodegenError> {
     let mut code = "".to_owned();
     let mut arc_params: Vec<(String, PathBuf)> = Vec::new();
 
     for res in resources.into_iter() {
-        code += &res.codegen();
+        code += &res.codegen()?;
         code += "\n";
 
         arc_params.push((res.image_name().to_owned(), res.path().to_owned()));
     }
 
     let p = codegen_path.as_ref();
-    let _f = fs::write(p, code).expect("Unable to write generated code for resources");
+    fs::write(p, code).map_err(|error| CodegenError::Io {
+        path: p.to_owned(),
+        error,
+    })?;
+    report_typenum_costs(&[p]);
 
     selfe_arc::build::link_with_archive(arc_params.iter().map(|(a, b)| (a.as_str(), b.as_path())));
+    Ok(())
 }
 
 #[cfg(test)]
@@ -185,4 +342,19 @@ mod test {
         assert_eq!(format_as_typenum(4), "typenum::UInt<typenum::UInt<typenum::UInt<typenum::UTerm, typenum::B1>, typenum::B0>, typenum::B0>".to_string());
     }
 
+    #[test]
+    fn test_elf_segment_hash() {
+        assert_eq!(elf_segment_hash(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(elf_segment_hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(elf_segment_hash(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn test_pages_spanned() {
+        assert_eq!(pages_spanned(0x1000, 0x1000), 0);
+        assert_eq!(pages_spanned(0x1000, 0x1001), 1);
+        assert_eq!(pages_spanned(0x1000, 0x2000), 1);
+        assert_eq!(pages_spanned(0x0abc, 0x4abc), 5);
+        assert_eq!(pages_spanned(0x1fff, 0x2001), 2);
+    }
 }
@@ -0,0 +1,386 @@
+//! A rough guide to where a root task's type-level arithmetic is slowing
+//! down its compilation.
+//!
+//! Every `op!`, `Sum<..>` and so on in a type, and every `alloc()` or
+//! `split()` of a typed resource, is resolved by the compiler one trait
+//! impl per bit of its operands, and nested expressions and long chains of
+//! allocations from the same resource multiply that out. None of it shows
+//! up in the source as anything costly. This goes through source files line
+//! by line, e.g. a root task's `main.rs` and the code generated by
+//! `embed_resources`, and scores each call site by an estimate of how much
+//! work it makes for the type checker, so the worst can be moved to the
+//! weak, runtime-checked variants.
+//!
+//! The scores are only comparable with each other; they aren't a
+//! measurement of anything.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Set to how many sites to report, to have `embed_resources` and
+/// `report_typenum_costs` print the worst offenders as cargo warnings
+pub const TYPENUM_COSTS_ENV_VAR: &str = "FERROS_TYPENUM_COSTS";
+
+/// The least width assumed for operands, most of which are aliases whose
+/// values aren't known here
+const MIN_OPERAND_BITS: u32 = 8;
+
+/// Type-level operators, each followed by its `<`
+const ARITHMETIC_TYPES: &[&str] = &[
+    "Sum", "Diff", "Prod", "Quot", "Mod", "Shleft", "Shright", "Pow", "Exp", "Add1", "Sub1",
+    "Double", "Maximum", "Minimum", "Add", "Sub", "Mul", "Div", "Shl", "Shr",
+];
+
+/// Methods splitting a typed resource into smaller ones by arithmetic on
+/// its size
+const SPLITTING_METHODS: &[&str] = &["alloc", "split", "quarter", "split_into"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TypenumCostKind {
+    /// Type-level arithmetic, `ops` operators nested up to `depth` deep
+    Arithmetic { ops: u32, depth: u32, bits: u32 },
+    /// The `depth`th typed split in a row of the resource `binding`
+    SplitChain { binding: String, depth: u32 },
+    /// A number written out as nested `UInt`s, as generated for elf
+    /// resources
+    ExpandedNumber { bits: u32 },
+}
+
+impl fmt::Display for TypenumCostKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TypenumCostKind::Arithmetic { ops, depth, bits } => write!(
+                f,
+                "{} type-level operators, nested {} deep, on {}-bit operands",
+                ops, depth, bits
+            ),
+            TypenumCostKind::SplitChain { binding, depth } => write!(
+                f,
+                "split number {} in a row of `{}`, consider a weak variant",
+                depth, binding
+            ),
+            TypenumCostKind::ExpandedNumber { bits } => {
+                write!(f, "{}-bit number written out as nested UInts", bits)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypenumCost {
+    pub path: PathBuf,
+    /// Starting from 1
+    pub line: usize,
+    pub kind: TypenumCostKind,
+    pub score: u64,
+}
+
+impl fmt::Display for TypenumCost {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "typenum cost {}: {}:{}: {}",
+            self.score,
+            self.path.display(),
+            self.line,
+            self.kind
+        )
+    }
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// The identifier ending right before byte offset `end`
+fn ident_before(s: &str, end: usize) -> &str {
+    let start = s[..end]
+        .char_indices()
+        .rev()
+        .find(|(_, c)| !is_ident_char(*c))
+        .map(|(i, c)| i + c.len_utf8())
+        .unwrap_or(0);
+    &s[start..end]
+}
+
+/// The widest literal in `s`, like `U4096`
+fn literal_bits(s: &str) -> u32 {
+    let mut bits = 0;
+    for (i, _) in s.match_indices('U') {
+        if i > 0 && s[..i].ends_with(is_ident_char) {
+            continue;
+        }
+        let digits: String = s[i + 1..]
+            .chars()
+            .take_while(char::is_ascii_digit)
+            .collect();
+        if digits.is_empty() || s[i + 1 + digits.len()..].starts_with(is_ident_char) {
+            continue;
+        }
+        if let Ok(n) = digits.parse::<u64>() {
+            bits = bits.max(64 - n.leading_zeros());
+        }
+    }
+    bits
+}
+
+/// Operators and the deepest nesting of them, in the body of an `op!`
+fn op_macro_arithmetic(body: &str) -> (u32, u32) {
+    let (mut ops, mut max_depth, mut depth) = (0, 0, 0u32);
+    let mut chars = body.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            '<' | '>' if chars.peek() == Some(&c) => {
+                chars.next();
+                ops += 1;
+                max_depth = max_depth.max(depth + 1);
+            }
+            '+' | '-' | '*' | '/' | '%' => {
+                ops += 1;
+                max_depth = max_depth.max(depth + 1);
+            }
+            _ => (),
+        }
+    }
+    (ops, max_depth)
+}
+
+/// The bodies of any `op!` invocations in `line`
+fn op_macro_bodies(line: &str) -> Vec<&str> {
+    let mut bodies = Vec::new();
+    for (i, _) in line.match_indices("op!") {
+        let rest = line[i + 3..].trim_start();
+        let close = match rest.chars().next() {
+            Some('(') => ')',
+            Some('{') => '}',
+            Some('[') => ']',
+            _ => continue,
+        };
+        let open = rest.chars().next().unwrap();
+        let mut depth = 0;
+        for (j, c) in rest.char_indices() {
+            if c == open {
+                depth += 1;
+            } else if c == close {
+                depth -= 1;
+                if depth == 0 {
+                    bodies.push(&rest[1..j]);
+                    break;
+                }
+            }
+        }
+    }
+    bodies
+}
+
+/// Operators and the deepest nesting of them, written as generic types
+fn type_arithmetic(line: &str) -> (u32, u32) {
+    let (mut ops, mut max_depth) = (0, 0);
+    // Whether each open `<` belongs to an operator
+    let mut open: Vec<bool> = Vec::new();
+    for (i, c) in line.char_indices() {
+        match c {
+            '<' => {
+                let is_op = ARITHMETIC_TYPES.contains(&ident_before(line, i));
+                if is_op {
+                    ops += 1;
+                    let depth = open.iter().filter(|o| **o).count() as u32 + 1;
+                    max_depth = max_depth.max(depth);
+                }
+                open.push(is_op);
+            }
+            '>' => {
+                open.pop();
+            }
+            _ => (),
+        }
+    }
+    (ops, max_depth)
+}
+
+/// The resource split by `line`, with the name the remainder is bound to,
+/// for a line like `let (a, rest) = rest.alloc();`
+fn split_binding(line: &str) -> Option<(&str, &str)> {
+    let line = line.trim();
+    if !line.starts_with("let") {
+        return None;
+    }
+    let eq = line.find('=')?;
+    let (lhs, rhs) = (&line[..eq], line[eq + 1..].trim());
+    let dot = rhs.find('.')?;
+    let method: String = rhs[dot + 1..]
+        .chars()
+        .take_while(|c| is_ident_char(*c))
+        .collect();
+    if !SPLITTING_METHODS.contains(&method.as_str()) {
+        return None;
+    }
+    let receiver = &rhs[..dot];
+    if receiver.is_empty() || !receiver.chars().all(is_ident_char) {
+        return None;
+    }
+    // The remainder comes last in the tuple, before any type annotation
+    let lhs = lhs.split(':').next()?.trim_end().trim_end_matches(')');
+    let remainder = lhs.rsplit(|c: char| !is_ident_char(c)).next()?;
+    Some((receiver, remainder))
+}
+
+/// Score the sites in one file's source
+pub fn estimate_typenum_costs(path: &Path, source: &str) -> Vec<TypenumCost> {
+    let mut costs = Vec::new();
+    // How many splits in a row led to each binding
+    let mut chains: HashMap<String, u32> = HashMap::new();
+
+    for (i, line) in source.lines().enumerate() {
+        let code = line.split("//").next().unwrap_or("");
+        let mut site = |kind: TypenumCostKind, score: u64| {
+            costs.push(TypenumCost {
+                path: path.to_owned(),
+                line: i + 1,
+                kind,
+                score,
+            })
+        };
+        let bits = literal_bits(code).max(MIN_OPERAND_BITS);
+
+        let (mut ops, mut depth) = type_arithmetic(code);
+        for body in op_macro_bodies(code) {
+            let (body_ops, body_depth) = op_macro_arithmetic(body);
+            ops += body_ops;
+            depth = depth.max(body_depth);
+        }
+        if ops > 0 {
+            site(
+                TypenumCostKind::Arithmetic { ops, depth, bits },
+                u64::from(ops * depth * bits),
+            );
+        } else if code.contains("UInt<") {
+            let bits = code.matches("UInt<").count() as u32;
+            site(
+                TypenumCostKind::ExpandedNumber { bits },
+                u64::from(bits * bits),
+            );
+        }
+
+        if let Some((receiver, remainder)) = split_binding(code) {
+            let depth = chains.get(receiver).copied().unwrap_or(0) + 1;
+            chains.insert(remainder.to_owned(), depth);
+            site(
+                TypenumCostKind::SplitChain {
+                    binding: remainder.to_owned(),
+                    depth,
+                },
+                u64::from(depth * bits),
+            );
+        }
+    }
+    costs
+}
+
+/// Score the sites in each of `paths`, worst first
+pub fn estimate_typenum_costs_in_files<P: AsRef<Path>>(paths: &[P]) -> Vec<TypenumCost> {
+    let mut costs: Vec<TypenumCost> = paths
+        .iter()
+        .flat_map(|p| {
+            let p = p.as_ref();
+            let source = fs::read_to_string(p).expect(&format!(
+                "estimate_typenum_costs: Couldn't read file {}",
+                p.display()
+            ));
+            estimate_typenum_costs(p, &source)
+        })
+        .collect();
+    costs.sort_by(|a, b| b.score.cmp(&a.score));
+    costs
+}
+
+/// The number of sites to report, if `TYPENUM_COSTS_ENV_VAR` asks for a
+/// report
+fn requested_report_len() -> Option<usize> {
+    std::env::var(TYPENUM_COSTS_ENV_VAR)
+        .ok()
+        .map(|v| v.parse().unwrap_or(10))
+}
+
+/// From a build script, print the worst sites in `paths` as cargo warnings
+/// when `TYPENUM_COSTS_ENV_VAR` is set, and do nothing otherwise
+pub fn report_typenum_costs<P: AsRef<Path>>(paths: &[P]) {
+    println!("cargo:rerun-if-env-changed={}", TYPENUM_COSTS_ENV_VAR);
+    if let Some(worst) = requested_report_len() {
+        for cost in estimate_typenum_costs_in_files(paths).iter().take(worst) {
+            println!("cargo:warning={}", cost);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn costs(source: &str) -> Vec<TypenumCostKind> {
+        estimate_typenum_costs(Path::new("main.rs"), source)
+            .into_iter()
+            .map(|c| c.kind)
+            .collect()
+    }
+
+    #[test]
+    fn test_op_macro_arithmetic() {
+        assert_eq!(
+            costs("type Depth = op!(((U1 << PageBits) / MtuSize) - U1);"),
+            vec![TypenumCostKind::Arithmetic {
+                ops: 3,
+                depth: 3,
+                bits: 8
+            }]
+        );
+    }
+
+    #[test]
+    fn test_type_arithmetic() {
+        assert_eq!(
+            costs("type U42768 = Sum<U32768, Diff<U10000, U1>>;"),
+            vec![TypenumCostKind::Arithmetic {
+                ops: 2,
+                depth: 2,
+                bits: 16
+            }]
+        );
+    }
+
+    #[test]
+    fn test_split_chain() {
+        let source = "let (a, slots): (LocalCNodeSlots<U100>, _) = slots.alloc();\n\
+                      let (b, slots) = slots.alloc();\n\
+                      let (c, _slots) = slots.alloc();";
+        assert_eq!(
+            costs(source),
+            vec![
+                TypenumCostKind::SplitChain {
+                    binding: "slots".to_owned(),
+                    depth: 1
+                },
+                TypenumCostKind::SplitChain {
+                    binding: "slots".to_owned(),
+                    depth: 2
+                },
+                TypenumCostKind::SplitChain {
+                    binding: "_slots".to_owned(),
+                    depth: 3
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_expanded_number() {
+        assert_eq!(
+            costs("    type StackSizeBits = typenum::UInt<typenum::UInt<typenum::UTerm, typenum::B1>, typenum::B0>;"),
+            vec![TypenumCostKind::ExpandedNumber { bits: 2 }]
+        );
+    }
+}
@@ -0,0 +1,301 @@
+//! `#[derive(RetypeForSetup)]`, re-exported alongside the trait as
+//! `ferros::userland::RetypeForSetup`.
+extern crate proc_macro;
+use std::iter;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Group, TokenStream as TokenStream2, TokenTree};
+use quote::{quote, ToTokens};
+use syn::{
+    parse_macro_input, Data, DeriveInput, Error, GenericParam, Generics, Ident, Type,
+    TypeParamBound, WherePredicate,
+};
+
+/// Implement `RetypeForSetup` for a process parameter struct generic over
+/// a `CNodeRole`, retyping its `Local` version to its `Child` one, with a
+/// `PARAMS_LAYOUT_HASH` covering the name, type, size and alignment of
+/// each of its fields in order.
+#[proc_macro_derive(RetypeForSetup)]
+pub fn derive_retype_for_setup(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match retype_for_setup(input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn retype_for_setup(input: DeriveInput) -> Result<TokenStream2, Error> {
+    let fields = match &input.data {
+        Data::Struct(s) => &s.fields,
+        Data::Enum(e) => {
+            return Err(Error::new(
+                e.enum_token.span,
+                "RetypeForSetup can only be derived for structs",
+            ))
+        }
+        Data::Union(u) => {
+            return Err(Error::new(
+                u.union_token.span,
+                "RetypeForSetup can only be derived for structs",
+            ))
+        }
+    };
+    let role = role_param(&input.generics).ok_or_else(|| {
+        Error::new(
+            input.ident.span(),
+            "RetypeForSetup needs a type parameter bounded by CNodeRole",
+        )
+    })?;
+    let local = quote!(::ferros::cap::role::Local);
+    let child = quote!(::ferros::cap::role::Child);
+
+    let ident = &input.ident;
+    let local_args = type_args(&input.generics, &role, &local);
+    let child_args = type_args(&input.generics, &role, &child);
+
+    let mut impl_generics = input.generics.clone();
+    impl_generics.params = input
+        .generics
+        .params
+        .iter()
+        .filter(|param| !is_type_param(param, &role))
+        .cloned()
+        .collect();
+    impl_generics.where_clause = None;
+    let (impl_generics, _, _) = impl_generics.split_for_impl();
+    let predicates = input
+        .generics
+        .where_clause
+        .iter()
+        .flat_map(|w| w.predicates.iter())
+        .filter(|predicate| !bounds_type(predicate, &role))
+        .map(|predicate| replace_ident(predicate.into_token_stream(), &role, &local));
+
+    // The child's version is the one whose layout is handed over
+    let field_layouts = fields.iter().enumerate().map(|(index, field)| {
+        let name = field
+            .ident
+            .as_ref()
+            .map_or_else(|| index.to_string(), |ident| ident.to_string());
+        let ty = field.ty.clone().into_token_stream();
+        let ty_name = ty.to_string();
+        let child_ty = replace_ident(ty, &role, &child);
+        quote!(.field::<#child_ty>(#name, #ty_name))
+    });
+
+    Ok(quote! {
+        impl #impl_generics ::ferros::userland::RetypeForSetup for #ident<#(#local_args),*>
+        where
+            #(#predicates,)*
+        {
+            type Output = #ident<#(#child_args),*>;
+
+            const PARAMS_LAYOUT_HASH: u64 =
+                ::ferros::userland::ParamsLayout::of::<Self::Output>()
+                    #(#field_layouts)*
+                    .hash();
+        }
+    })
+}
+
+/// The type parameter bounded by `CNodeRole`, either in place or in the
+/// where clause
+fn role_param(generics: &Generics) -> Option<Ident> {
+    let where_bounds = || {
+        generics
+            .where_clause
+            .iter()
+            .flat_map(|w| w.predicates.iter())
+            .filter_map(|predicate| match predicate {
+                WherePredicate::Type(p) => Some(p),
+                _ => None,
+            })
+    };
+    generics
+        .type_params()
+        .find(|param| {
+            param.bounds.iter().any(is_cnode_role)
+                || where_bounds().any(|p| {
+                    is_ident(&p.bounded_ty, &param.ident) && p.bounds.iter().any(is_cnode_role)
+                })
+        })
+        .map(|param| param.ident.clone())
+}
+
+fn is_cnode_role(bound: &TypeParamBound) -> bool {
+    match bound {
+        TypeParamBound::Trait(t) => t
+            .path
+            .segments
+            .last()
+            .map_or(false, |segment| segment.value().ident == "CNodeRole"),
+        _ => false,
+    }
+}
+
+fn is_type_param(param: &GenericParam, ident: &Ident) -> bool {
+    match param {
+        GenericParam::Type(t) => t.ident == *ident,
+        _ => false,
+    }
+}
+
+fn bounds_type(predicate: &WherePredicate, ident: &Ident) -> bool {
+    match predicate {
+        WherePredicate::Type(p) => is_ident(&p.bounded_ty, ident),
+        _ => false,
+    }
+}
+
+fn is_ident(ty: &Type, ident: &Ident) -> bool {
+    match ty {
+        Type::Path(p) => p.qself.is_none() && p.path.is_ident(ident.clone()),
+        _ => false,
+    }
+}
+
+/// The struct's generic arguments, with `replacement` for the role
+fn type_args(generics: &Generics, role: &Ident, replacement: &TokenStream2) -> Vec<TokenStream2> {
+    generics
+        .params
+        .iter()
+        .map(|param| match param {
+            GenericParam::Type(t) if t.ident == *role => replacement.clone(),
+            GenericParam::Type(t) => {
+                let ident = &t.ident;
+                quote!(#ident)
+            }
+            GenericParam::Lifetime(l) => {
+                let lifetime = &l.lifetime;
+                quote!(#lifetime)
+            }
+            GenericParam::Const(c) => {
+                let ident = &c.ident;
+                quote!(#ident)
+            }
+        })
+        .collect()
+}
+
+fn replace_ident(tokens: TokenStream2, ident: &Ident, replacement: &TokenStream2) -> TokenStream2 {
+    tokens
+        .into_iter()
+        .flat_map(|tree| -> TokenStream2 {
+            match tree {
+                TokenTree::Ident(ref i) if i == ident => replacement.clone(),
+                TokenTree::Group(g) => {
+                    let mut group =
+                        Group::new(g.delimiter(), replace_ident(g.stream(), ident, replacement));
+                    group.set_span(g.span());
+                    iter::once(TokenTree::Group(group)).collect()
+                }
+                other => iter::once(other).collect(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syn::{parse_quote, ImplItem, ItemImpl};
+
+    fn derive(input: DeriveInput) -> ItemImpl {
+        syn::parse2(retype_for_setup(input).unwrap()).unwrap()
+    }
+
+    fn output_type(item: &ItemImpl) -> &Type {
+        item.items
+            .iter()
+            .find_map(|item| match item {
+                ImplItem::Type(t) => Some(&t.ty),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn derives_for_role_generic_struct() {
+        let item = derive(parse_quote! {
+            pub struct ProcParams<Role: CNodeRole> {
+                pub value: usize,
+                pub outcome_sender: Sender<bool, Role>,
+            }
+        });
+        let self_ty: Type = parse_quote!(ProcParams<::ferros::cap::role::Local>);
+        let output_ty: Type = parse_quote!(ProcParams<::ferros::cap::role::Child>);
+        assert_eq!(*item.self_ty, self_ty);
+        assert_eq!(*output_type(&item), output_ty);
+        assert!(item.generics.params.is_empty());
+
+        let code = item.into_token_stream().to_string();
+        assert!(code.contains("\"value\""));
+        assert!(code.contains("\"outcome_sender\""));
+    }
+
+    #[test]
+    fn keeps_other_generics_and_where_clause() {
+        let item = derive(parse_quote! {
+            pub struct UartParams<IRQ: Unsigned, Role>
+            where
+                Role: CNodeRole,
+                IRQ: Sync + Send,
+            {
+                pub irq: Cap<IRQHandler<IRQ, Role>, Role>,
+            }
+        });
+        let self_ty: Type = parse_quote!(UartParams<IRQ, ::ferros::cap::role::Local>);
+        let output_ty: Type = parse_quote!(UartParams<IRQ, ::ferros::cap::role::Child>);
+        assert_eq!(*item.self_ty, self_ty);
+        assert_eq!(*output_type(&item), output_ty);
+        assert_eq!(item.generics.params.len(), 1);
+        let where_clause = item.generics.where_clause.unwrap();
+        let irq_bound: WherePredicate = parse_quote!(IRQ: Sync + Send);
+        assert_eq!(where_clause.predicates.len(), 1);
+        assert_eq!(where_clause.predicates[0], irq_bound);
+    }
+
+    #[test]
+    fn replaces_role_in_nested_types() {
+        let role: Ident = parse_quote!(Role);
+        let replaced = replace_ident(
+            quote!(Cap<IRQHandler<IRQ, Role>, Role>),
+            &role,
+            &quote!(::ferros::cap::role::Child),
+        );
+        let expected: Type = parse_quote!(
+            Cap<IRQHandler<IRQ, ::ferros::cap::role::Child>, ::ferros::cap::role::Child>
+        );
+        assert_eq!(syn::parse2::<Type>(replaced).unwrap(), expected);
+    }
+
+    #[test]
+    fn tuple_fields_are_named_by_index() {
+        let item = derive(parse_quote! {
+            struct Pair<Role: ferros::cap::CNodeRole>(u32, Cap<Endpoint, Role>);
+        });
+        let code = item.into_token_stream().to_string();
+        assert!(code.contains("\"0\""));
+        assert!(code.contains("\"1\""));
+    }
+
+    #[test]
+    fn rejects_struct_without_role() {
+        let input: DeriveInput = parse_quote! {
+            struct ProcParams {
+                value: usize,
+            }
+        };
+        assert!(retype_for_setup(input).is_err());
+    }
+
+    #[test]
+    fn rejects_enum() {
+        let input: DeriveInput = parse_quote! {
+            enum ProcParams<Role: CNodeRole> {
+                A(Cap<Endpoint, Role>),
+            }
+        };
+        assert!(retype_for_setup(input).is_err());
+    }
+}
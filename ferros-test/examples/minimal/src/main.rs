@@ -6,7 +6,7 @@ use ferros::cap::{
     ASIDPool, LocalCNode, LocalCNodeSlots, LocalCap, ThreadPriorityAuthority, Untyped,
 };
 use ferros::test_support::TestOutcome;
-use ferros::vspace::ScratchRegion;
+use ferros::vspace::{ScratchRegion, VSpace};
 
 use ferros_test::{ferros_test, ferros_test_main};
 use typenum::*;
@@ -64,6 +64,9 @@ fn vspacescratch_parameter(scratch: &mut ScratchRegion) {}
 #[ferros_test]
 fn vspace_paging_root_parameter(vspace_paging_root: &LocalCap<ferros::arch::PagingRoot>) {}
 
+#[ferros_test]
+fn root_vspace_parameter(root_vspace: &mut VSpace) {}
+
 #[ferros_test]
 fn multiple_mixed_parameters(
     untyped: LocalCap<Untyped<U5>>,
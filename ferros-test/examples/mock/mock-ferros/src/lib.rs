@@ -37,6 +37,7 @@ pub mod userland {
 pub mod vspace {
     use core::marker::PhantomData;
     pub struct ScratchRegion<'a, 'b, T = ()>(pub PhantomData<&'a T>, pub PhantomData<&'b T>);
+    pub struct VSpace;
     pub struct MappedMemoryRegion<T, SS: SharedStatus>(PhantomData<T>, PhantomData<SS>);
     pub trait SharedStatus {}
     pub mod shared_status {
@@ -65,3 +65,6 @@ fn vspacescratch_parameter(scratch: &mut ScratchRegion) {}
 #[ferros_test]
 fn vspace_paging_root_parameter(vspace_paging_root: &LocalCap<ferros::arch::PagingRoot>) {}
 
+#[ferros_test]
+fn root_vspace_parameter(root_vspace: &mut VSpace) {}
+
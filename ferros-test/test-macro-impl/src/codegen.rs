@@ -144,6 +144,7 @@ fn local_allocations<G: IdGenerator>(
     let scratch = Ident::new("scratch", Span::call_site());
     let local_cnode = Ident::new("local_cnode", Span::call_site());
     let thread_authority = Ident::new("thread_authority", Span::call_site());
+    let root_vspace = Ident::new("root_vspace", Span::call_site());
     let vspace_paging_root = Ident::new("vspace_paging_root", Span::call_site());
     let user_image = Ident::new("user_image", Span::call_site());
     let ut_buddy_instance = Ident::new("ut_buddy_instance", Span::call_site());
@@ -165,6 +166,9 @@ fn local_allocations<G: IdGenerator>(
     };
     stmts.extend(buddy_block.stmts);
 
+    // A slot allocator gets whatever slots are left over once everything
+    // else has been allocated, whichever order the params are in
+    let mut slot_allocator_id = None;
     for p in params {
         let (p_block, output_ident): (Block, Ident) = match p.kind {
             ParamKind::CNodeSlots { .. } => {
@@ -176,6 +180,11 @@ fn local_allocations<G: IdGenerator>(
                     slot_id,
                 )
             }
+            ParamKind::WeakSlotAllocator => {
+                let allocator_id = gen_id(id_generator, "slotallocator");
+                slot_allocator_id = Some(allocator_id.clone());
+                (parse_quote!({}), allocator_id)
+            }
             ParamKind::Untyped { .. } => {
                 let slot_id = gen_id(id_generator, "cnodeslots");
                 let ut_id = gen_id(id_generator, "untyped");
@@ -197,6 +206,15 @@ fn local_allocations<G: IdGenerator>(
                     pool_id,
                 )
             }
+            ParamKind::WeakASIDPool => {
+                let pool_id = gen_id(id_generator, "weakasidpool");
+                (
+                    parse_quote! {{
+                        let #pool_id = #asid_pool.weaken();
+                    }},
+                    pool_id,
+                )
+            }
             ParamKind::IRQControl => (parse_quote!({}), irq_control.clone()),
             ParamKind::VSpaceScratch => (parse_quote!({}), scratch.clone()),
             ParamKind::MappedMemoryRegion => {
@@ -212,6 +230,7 @@ fn local_allocations<G: IdGenerator>(
             }
             ParamKind::CNode => (parse_quote!({}), local_cnode.clone()),
             ParamKind::ThreadPriorityAuthority => (parse_quote!({}), thread_authority.clone()),
+            ParamKind::RootVSpace => (parse_quote!({}), root_vspace.clone()),
             ParamKind::PagingRoot => (parse_quote!({}), vspace_paging_root.clone()),
             ParamKind::UserImage => (parse_quote!({}), user_image.clone()),
         };
@@ -221,6 +240,12 @@ fn local_allocations<G: IdGenerator>(
             output_ident,
         })
     }
+    if let Some(allocator_id) = slot_allocator_id {
+        let allocator_block: Block = parse_quote! {{
+            let #allocator_id = ferros::cap::WeakSlotAllocator::new(#slots.weaken());
+        }};
+        stmts.extend(allocator_block.stmts);
+    }
 
     (
         Block {
@@ -279,6 +304,7 @@ fn run_test_decl() -> FnDecl {
     run_test_inputs.push(parse_quote!(
         thread_authority: &ferros::cap::LocalCap<ferros::cap::ThreadPriorityAuthority>
     ));
+    run_test_inputs.push(parse_quote!(root_vspace: &mut ferros::vspace::VSpace));
     run_test_inputs.push(parse_quote!(
         vspace_paging_root: &ferros::cap::LocalCap<ferros::arch::PagingRoot>
     ));
@@ -353,6 +379,7 @@ mod tests {
                     ferros::test_support::MaxMappedMemoryRegionBitSize, ferros::vspace::shared_status::Exclusive,>,
                 local_cnode: &ferros::cap::LocalCap<ferros::cap::LocalCNode>,
                 thread_authority: &ferros::cap::LocalCap<ferros::cap::ThreadPriorityAuthority>,
+                root_vspace: &mut ferros::vspace::VSpace,
                 vspace_paging_root: &ferros::cap::LocalCap<ferros::arch::PagingRoot>,
                 user_image: &ferros::bootstrap::UserImage<ferros::cap::role::Local>,
                 irq_control: ferros::cap::LocalCap<ferros::cap::IRQControl>
@@ -416,6 +443,7 @@ mod tests {
                     ferros::test_support::MaxMappedMemoryRegionBitSize, ferros::vspace::shared_status::Exclusive,>,
                 local_cnode: &ferros::cap::LocalCap<ferros::cap::LocalCNode>,
                 thread_authority: &ferros::cap::LocalCap<ferros::cap::ThreadPriorityAuthority>,
+                root_vspace: &mut ferros::vspace::VSpace,
                 vspace_paging_root: &ferros::cap::LocalCap<ferros::arch::PagingRoot>,
                 user_image: &ferros::bootstrap::UserImage<ferros::cap::role::Local>,
                 irq_control: ferros::cap::LocalCap<ferros::cap::IRQControl>
@@ -483,6 +511,7 @@ mod tests {
                     ferros::test_support::MaxMappedMemoryRegionBitSize, ferros::vspace::shared_status::Exclusive,>,
                 local_cnode: &ferros::cap::LocalCap<ferros::cap::LocalCNode>,
                 thread_authority: &ferros::cap::LocalCap<ferros::cap::ThreadPriorityAuthority>,
+                root_vspace: &mut ferros::vspace::VSpace,
                 vspace_paging_root: &ferros::cap::LocalCap<ferros::arch::PagingRoot>,
                 user_image: &ferros::bootstrap::UserImage<ferros::cap::role::Local>,
                 irq_control: ferros::cap::LocalCap<ferros::cap::IRQControl>
@@ -37,8 +37,10 @@ pub(crate) struct Param {
 #[derive(Debug, Clone, PartialEq)]
 pub(crate) enum ParamKind {
     CNodeSlots { count: usize },
+    WeakSlotAllocator,
     Untyped { bits: usize },
     ASIDPool { count: usize },
+    WeakASIDPool,
     MappedMemoryRegion,
     VSpaceScratch,
     CNode,
@@ -46,6 +48,7 @@ pub(crate) enum ParamKind {
     UserImage,
     IRQControl,
     PagingRoot,
+    RootVSpace,
 }
 
 #[derive(Debug, Clone)]
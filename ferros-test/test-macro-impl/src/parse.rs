@@ -71,6 +71,9 @@ fn extract_expected_resources<'a>(
 fn validate_param_collection(params: &[Param]) -> Result<(), ParseError> {
     let mut scratch_count = 0;
     let mut irq_control_count = 0;
+    let mut slot_allocator_count = 0;
+    let mut asid_pool_count = 0;
+    let mut vspace_count = 0;
     for p in params {
         match p.kind {
             ParamKind::VSpaceScratch => {
@@ -91,6 +94,33 @@ fn validate_param_collection(params: &[Param]) -> Result<(), ParseError> {
                     });
                 }
             }
+            ParamKind::ASIDPool { .. } | ParamKind::WeakASIDPool => {
+                asid_pool_count += 1;
+                if asid_pool_count > 1 {
+                    return Err(ParseError::ArgumentConstraint {
+                        msg: "Only a single ASIDPool or WeakASIDPool argument may be specified.",
+                        span: p.original_ident.span(),
+                    });
+                }
+            }
+            ParamKind::RootVSpace => {
+                vspace_count += 1;
+                if vspace_count > 1 {
+                    return Err(ParseError::ArgumentConstraint {
+                        msg: "Only a single VSpace argument may be specified.",
+                        span: p.original_ident.span(),
+                    });
+                }
+            }
+            ParamKind::WeakSlotAllocator => {
+                slot_allocator_count += 1;
+                if slot_allocator_count > 1 {
+                    return Err(ParseError::ArgumentConstraint {
+                        msg: "Only a single WeakSlotAllocator argument may be specified.",
+                        span: p.original_ident.span(),
+                    });
+                }
+            }
             _ => (),
         }
     }
@@ -201,9 +231,39 @@ impl ParamKind {
                 // TODO - More detailed lifetime and ScratchRegion number of pages as type param matching
                 ParamKind::VSpaceScratch
             }
+            "VSpace" => {
+                if arg_kind == ArgKind::RefMut {
+                    ParamKind::RootVSpace
+                } else {
+                    return Err(ParseError::InvalidArgumentType {
+                        msg: "The root task's VSpace must be specified as a mutable reference, &mut VSpace.".to_string(),
+                        span: segment.span(),
+                    });
+                }
+            }
             "CNodeSlots" => ParamKind::CNodeSlots {
                 count: extract_first_argument_as_unsigned(&segment.arguments)?,
             },
+            "WeakSlotAllocator" => {
+                if arg_kind == ArgKind::Owned {
+                    ParamKind::WeakSlotAllocator
+                } else {
+                    return Err(ParseError::InvalidArgumentType {
+                        msg: "WeakSlotAllocator must be specified as an owned instance parameter, not a reference.".to_string(),
+                        span: segment.span(),
+                    });
+                }
+            }
+            "WeakASIDPool" => {
+                if arg_kind == ArgKind::Owned {
+                    ParamKind::WeakASIDPool
+                } else {
+                    return Err(ParseError::InvalidArgumentType {
+                        msg: "WeakASIDPool must be specified as an owned instance parameter, not a reference.".to_string(),
+                        span: segment.span(),
+                    });
+                }
+            }
             t => {
                 return Err(ParseError::InvalidArgumentType {
                     msg: format!("test function argument type was not recognized: {}", t),
@@ -235,6 +295,14 @@ fn parse_localcap_param_kind(
         "ASIDPool" => Ok(ParamKind::ASIDPool {
             count: extract_first_argument_as_unsigned(&segment.arguments)?,
         }),
+        "WASIDPool" => {
+            if arg_kind == ArgKind::Owned {
+                Ok(ParamKind::WeakASIDPool)
+            } else {
+                Err(ParseError::InvalidArgumentType {msg: format!("{} is only available as a type parameter of an owned LocalCap<>", &type_name),
+                span: segment.span() })
+            }
+        }
         "IRQControl" => Ok(ParamKind::IRQControl),
         "ThreadPriorityAuthority" => {
             if arg_kind == ArgKind::Ref {
@@ -495,4 +563,68 @@ mod tests {
             panic!("Should have produced an ArgumentConstraint error")
         }
     }
+
+    #[test]
+    fn parse_model_rejects_multiple_slot_allocator_params() {
+        let user_fn = quote! {
+            fn user_fn(_slots_a: WeakSlotAllocator, _slots_b: WeakSlotAllocator) {
+            }
+        };
+
+        let content = SynContent::parse(quote!(), user_fn).expect("SynContent not parsed");
+        if let ParseError::ArgumentConstraint { .. } =
+            TestModel::parse(content).expect_err("TestModel parse should have failed")
+        {
+            // Cool
+        } else {
+            panic!("Should have produced an ArgumentConstraint error")
+        }
+    }
+
+    #[test]
+    fn parse_model_rejects_multiple_asid_pool_params() {
+        let user_fn = quote! {
+            fn user_fn(_pool_a: LocalCap<ASIDPool<U4>>, _pool_b: WeakASIDPool) {
+            }
+        };
+
+        let content = SynContent::parse(quote!(), user_fn).expect("SynContent not parsed");
+        if let ParseError::ArgumentConstraint { .. } =
+            TestModel::parse(content).expect_err("TestModel parse should have failed")
+        {
+            // Cool
+        } else {
+            panic!("Should have produced an ArgumentConstraint error")
+        }
+    }
+
+    #[test]
+    fn parse_model_accepts_mutable_vspace_param() {
+        let user_fn = quote! {
+            fn user_fn(root_vspace: &mut VSpace) {
+            }
+        };
+
+        let content = SynContent::parse(quote!(), user_fn).expect("SynContent not parsed");
+        let model = TestModel::parse(content).expect("TestModel not parsed");
+        assert_eq!(1, model.resources.len());
+        assert_eq!(ParamKind::RootVSpace, model.resources[0].kind);
+    }
+
+    #[test]
+    fn parse_model_rejects_shared_vspace_param() {
+        let user_fn = quote! {
+            fn user_fn(root_vspace: &VSpace) {
+            }
+        };
+
+        let content = SynContent::parse(quote!(), user_fn).expect("SynContent not parsed");
+        if let ParseError::InvalidArgumentType { .. } =
+            TestModel::parse(content).expect_err("TestModel parse should have failed")
+        {
+            // Cool
+        } else {
+            panic!("Should have produced an InvalidArgumentType error")
+        }
+    }
 }
@@ -32,6 +32,8 @@ pub enum TestPlatform {
     VirtTx1Aarch64,
     /// The sabre aarch32
     SabreAarch32,
+    /// A pc99 x86_64 machine
+    Pc99X86_64,
 }
 
 impl TestPlatform {
@@ -39,12 +41,14 @@ impl TestPlatform {
         match self {
             TestPlatform::VirtTx1Aarch64 => "aarch64",
             TestPlatform::SabreAarch32 => "aarch32",
+            TestPlatform::Pc99X86_64 => "x86_64",
         }
     }
     fn platform(&self) -> &'static str {
         match self {
             TestPlatform::VirtTx1Aarch64 => "virt",
             TestPlatform::SabreAarch32 => "sabre",
+            TestPlatform::Pc99X86_64 => "pc99",
         }
     }
 }
@@ -161,7 +165,7 @@ mod tests {
         fn unified_tests_sabre() {
             run_qemu_test::<fn()>(
                 "unified_tests",
-                Regex::new(".*test result: ok\\. 23 passed;.*").unwrap(),
+                Regex::new(".*test result: ok\\. 52 passed;.*").unwrap(),
                 Regex::new(".*Root task should never return from main.*").unwrap(),
                 None,
                 None,
@@ -174,7 +178,7 @@ mod tests {
         fn unified_tests_virt() {
             run_qemu_test::<fn()>(
                 "unified_tests",
-                Regex::new(".*test result: ok\\. 23 passed;.*").unwrap(),
+                Regex::new(".*test result: ok\\. 52 passed;.*").unwrap(),
                 Regex::new(".*Root task should never return from main.*").unwrap(),
                 None,
                 None,
@@ -183,6 +187,19 @@ mod tests {
         }
     }
 
+    sequential_test! {
+        fn unified_tests_pc99() {
+            run_qemu_test::<fn()>(
+                "unified_tests",
+                Regex::new(".*test result: ok\\. 48 passed;.*").unwrap(),
+                Regex::new(".*Root task should never return from main.*").unwrap(),
+                None,
+                None,
+                TestPlatform::Pc99X86_64,
+            );
+        }
+    }
+
     sequential_test! {
         fn uart_sabre() {
             use std::net::TcpStream;
@@ -7,6 +7,8 @@ extern crate selfe_runtime;
 
 use elf_process::ProcParams;
 
+embed_params_layout!(ProcParams<role::Local>);
+
 static mut MUT_GLOBAL: u32 = 0;
 
 #[no_mangle]
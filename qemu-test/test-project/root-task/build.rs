@@ -2,7 +2,7 @@ use ferros_build::*;
 use std::env;
 use std::path::Path;
 
-fn main() {
+fn main() -> Result<(), CodegenError> {
     println!("cargo:rerun-if-env-changed=TEST_CASE");
 
     let test_case = match env::var("TEST_CASE") {
@@ -21,7 +21,9 @@ fn main() {
         image_name: "elf-process".to_owned(),
         type_name: "ElfProcess".to_owned(),
         stack_size_bits: None,
+        reject_shared_segment_pages: false,
     };
 
-    embed_resources(&resources, vec![&elf_proc as &dyn Resource]);
+    embed_resources(&resources, vec![&elf_proc as &dyn Resource])?;
+    Ok(())
 }
@@ -0,0 +1,250 @@
+use super::TopLevelError;
+
+use selfe_sys::seL4_Yield;
+
+use typenum::*;
+
+use ferros::alloc::{smart_alloc, ut_buddy};
+use ferros::bootstrap::UserImage;
+use ferros::cap::{
+    retype, retype_cnode, role, ASIDPool, CNodeRole, LocalCNode, LocalCNodeSlots, LocalCap,
+    ThreadPriorityAuthority, Untyped,
+};
+use ferros::userland::*;
+use ferros::vspace::*;
+
+type U33768 = op!(U32768 + U1000);
+
+/// How many times the caller tries its first call, the responder might
+/// not be waiting for requests yet
+const ATTEMPTS: usize = 8;
+
+/// Plenty for every call the caller makes to time out
+const TICKS: usize = 10_000;
+
+#[ferros_test::ferros_test]
+pub fn call_with_timeout(
+    local_slots: LocalCNodeSlots<U33768>,
+    local_ut: LocalCap<Untyped<U20>>,
+    asid_pool: LocalCap<ASIDPool<U3>>,
+    local_mapped_region: MappedMemoryRegion<U19, shared_status::Exclusive>,
+    root_cnode: &LocalCap<LocalCNode>,
+    user_image: &UserImage<role::Local>,
+    tpa: &LocalCap<ThreadPriorityAuthority>,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let (caller_asid, asid_pool) = asid_pool.alloc();
+        let (responder_asid, asid_pool) = asid_pool.alloc();
+        let (ticker_asid, _asid_pool) = asid_pool.alloc();
+
+        let caller_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let caller_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut caller_vspace = VSpace::new(
+            retype(ut, slots)?,
+            caller_asid,
+            caller_vspace_slots.weaken(),
+            caller_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+
+        let responder_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let responder_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut responder_vspace = VSpace::new(
+            retype(ut, slots)?,
+            responder_asid,
+            responder_vspace_slots.weaken(),
+            responder_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+
+        let ticker_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let ticker_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut ticker_vspace = VSpace::new(
+            retype(ut, slots)?,
+            ticker_asid,
+            ticker_vspace_slots.weaken(),
+            ticker_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+
+        let (caller_cnode, caller_slots) = retype_cnode::<U12>(ut, slots)?;
+        let (responder_cnode, responder_slots) = retype_cnode::<U12>(ut, slots)?;
+        let (ticker_cnode, ticker_slots) = retype_cnode::<U12>(ut, slots)?;
+
+        let (slots_r, _responder_slots) = responder_slots.alloc();
+        let (setup, responder) =
+            call_channel_with_timeout(ut, ut, ut, &root_cnode, slots, slots_r)?;
+
+        let (slots_t, _ticker_slots) = ticker_slots.alloc();
+        let timeout_source = setup.create_timeout_source(slots_t)?;
+
+        let (slots_c, caller_slots) = caller_slots.alloc();
+        let caller = setup.create_caller(slots_c)?;
+        let (child_fault_source_slot, _caller_slots) = caller_slots.alloc();
+        let (_fault_source, outcome_sender, handler) =
+            fault_or_message_channel(&root_cnode, ut, slots, child_fault_source_slot, slots)?;
+
+        let (caller_and_responder_region, ticker_region) = local_mapped_region.split()?;
+        let (caller_region, responder_region) = caller_and_responder_region.split()?;
+
+        let mut caller_process = StandardProcess::new(
+            &mut caller_vspace,
+            caller_cnode,
+            caller_region,
+            root_cnode,
+            caller_proc as extern "C" fn(_) -> (),
+            CallerParams::<role::Child> {
+                caller,
+                outcome_sender,
+            },
+            ut,
+            ut,
+            slots,
+            tpa,
+            None, // fault
+        )?;
+        caller_process.bind_notification(setup.notification())?;
+        caller_process.start()?;
+
+        let mut responder_process = StandardProcess::new(
+            &mut responder_vspace,
+            responder_cnode,
+            responder_region,
+            root_cnode,
+            responder_proc as extern "C" fn(_) -> (),
+            ResponderParams::<role::Child> { responder },
+            ut,
+            ut,
+            slots,
+            tpa,
+            None, // fault
+        )?;
+        responder_process.start()?;
+
+        let mut ticker_process = StandardProcess::new(
+            &mut ticker_vspace,
+            ticker_cnode,
+            ticker_region,
+            root_cnode,
+            ticker_proc as extern "C" fn(_) -> (),
+            TickerParams::<role::Child> { timeout_source },
+            ut,
+            ut,
+            slots,
+            tpa,
+            None, // fault
+        )?;
+        ticker_process.start()?;
+    });
+
+    match handler.await_message()? {
+        FaultOrMessage::Message(true) => Ok(()),
+        _ => Err(TopLevelError::TestAssertionFailure(
+            "Caller should have had an answer, then timed out",
+        )),
+    }
+}
+
+#[derive(Debug)]
+pub struct AdditionRequest {
+    a: u32,
+    b: u32,
+}
+
+#[derive(Debug)]
+pub struct AdditionResponse {
+    sum: u32,
+}
+
+#[derive(Debug)]
+pub struct CallerParams<Role: CNodeRole> {
+    pub caller: TimeoutCaller<AdditionRequest, AdditionResponse, Role>,
+    pub outcome_sender: Sender<bool, Role>,
+}
+
+impl RetypeForSetup for CallerParams<role::Local> {
+    type Output = CallerParams<role::Child>;
+}
+
+#[derive(Debug)]
+pub struct ResponderParams<Role: CNodeRole> {
+    pub responder: TimeoutResponder<AdditionRequest, AdditionResponse, Role>,
+}
+
+impl RetypeForSetup for ResponderParams<role::Local> {
+    type Output = ResponderParams<role::Child>;
+}
+
+#[derive(Debug)]
+pub struct TickerParams<Role: CNodeRole> {
+    pub timeout_source: TimeoutSource<Role>,
+}
+
+impl RetypeForSetup for TickerParams<role::Local> {
+    type Output = TickerParams<role::Child>;
+}
+
+pub extern "C" fn caller_proc(p: CallerParams<role::Local>) {
+    let answered = (0..ATTEMPTS).find_map(|_| {
+        p.caller
+            .call_with_timeout(&AdditionRequest { a: 1, b: 2 }, 2)
+            .ok()
+    });
+
+    // The caller's free to do something else while the responder works
+    let overlapped = (0..ATTEMPTS).find_map(|_| {
+        let mut pending = p
+            .caller
+            .send_nonblocking(&AdditionRequest { a: 2, b: 3 }, 2);
+        loop {
+            match p.caller.poll_response(&mut pending) {
+                Ok(Some(rsp)) => return Some(rsp),
+                Ok(None) => unsafe { seL4_Yield() },
+                Err(_) => return None,
+            }
+        }
+    });
+
+    // The responder never answers this one
+    let timed_out = match p
+        .caller
+        .call_with_timeout(&AdditionRequest { a: 0, b: 0 }, 2)
+    {
+        Err(CallError::TimedOut) => true,
+        _ => false,
+    };
+
+    p.outcome_sender
+        .blocking_send(
+            &(answered.map(|rsp| rsp.sum) == Some(3)
+                && overlapped.map(|rsp| rsp.sum) == Some(5)
+                && timed_out),
+        )
+        .expect("could not send outcome");
+}
+
+pub extern "C" fn responder_proc(p: ResponderParams<role::Local>) {
+    p.responder
+        .reply_recv(|req| {
+            if req.a == 0 {
+                yield_forever();
+            }
+            AdditionResponse { sum: req.a + req.b }
+        })
+        .expect("Could not set up a reply_recv");
+}
+
+pub extern "C" fn ticker_proc(p: TickerParams<role::Local>) {
+    for _ in 0..TICKS {
+        p.timeout_source.tick();
+        unsafe { seL4_Yield() };
+    }
+}
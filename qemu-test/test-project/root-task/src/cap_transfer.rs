@@ -0,0 +1,114 @@
+use super::TopLevelError;
+
+use typenum::*;
+
+use ferros::alloc::{smart_alloc, ut_buddy};
+use ferros::bootstrap::UserImage;
+use ferros::cap::*;
+use ferros::userland::*;
+use ferros::vspace::*;
+
+#[ferros_test::ferros_test]
+pub fn cap_transfer(
+    local_slots: LocalCNodeSlots<U32768>,
+    local_ut: LocalCap<Untyped<U20>>,
+    asid_pool: LocalCap<ASIDPool<U1>>,
+    local_mapped_region: MappedMemoryRegion<U17, shared_status::Exclusive>,
+    root_cnode: &LocalCap<LocalCNode>,
+    user_image: &UserImage<role::Local>,
+    tpa: &LocalCap<ThreadPriorityAuthority>,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let (child_cnode, child_slots) = retype_cnode::<U12>(ut, slots)?;
+
+        // Keep one copy of the notification to check the child signalled
+        // the other once it had it
+        let notification: LocalCap<Notification> = retype(ut, slots)?;
+        let notification_to_send = notification.copy(&root_cnode, slots, CapRights::RWG)?;
+
+        smart_alloc! {|slots_c: child_slots| {
+            let (_cnode_for_child, receive_slot): (_, ChildCap<CNodeSlotsData<U1, role::Child>>) =
+                child_cnode.generate_self_reference(&root_cnode, slots_c)?;
+            let (ipc_setup, responder) = call_channel::<CapMessage<u32, Notification>, bool, _>(
+                ut,
+                &root_cnode,
+                slots,
+                slots_c,
+            )?;
+        }}
+        let caller = ipc_setup.create_caller(slots)?;
+
+        let params = ProcParams {
+            responder,
+            receive_slot,
+        };
+
+        let (child_asid, _asid_pool) = asid_pool.alloc();
+
+        let child_root = retype(ut, slots)?;
+        let child_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let child_vspace_ut: LocalCap<Untyped<U15>> = ut;
+
+        let mut child_vspace = VSpace::new(
+            child_root,
+            child_asid,
+            child_vspace_slots.weaken(),
+            child_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+
+        let mut child_process = StandardProcess::new(
+            &mut child_vspace,
+            child_cnode,
+            local_mapped_region,
+            root_cnode,
+            proc_main as extern "C" fn(_) -> (),
+            params,
+            ut,
+            ut,
+            slots,
+            tpa,
+            None, // fault
+        )?;
+    });
+    child_process.start()?;
+
+    let answer = caller.blocking_call_with_cap(&42, notification_to_send, root_cnode)?;
+    if !answer {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Child should have received the value along with the notification",
+        ));
+    }
+    match notification.poll() {
+        Some(_) => Ok(()),
+        None => Err(TopLevelError::TestAssertionFailure(
+            "Child should have signalled the notification it was sent",
+        )),
+    }
+}
+
+pub struct ProcParams<Role: CNodeRole> {
+    pub responder: Responder<CapMessage<u32, Notification>, bool, Role>,
+    pub receive_slot: Cap<CNodeSlotsData<U1, Role>, Role>,
+}
+
+impl RetypeForSetup for ProcParams<role::Local> {
+    type Output = ProcParams<role::Child>;
+}
+
+pub extern "C" fn proc_main(params: ProcParams<role::Local>) {
+    let ProcParams {
+        responder,
+        receive_slot,
+    } = params;
+    responder
+        .recv_reply_once_with_cap(receive_slot, |value, notification| {
+            notification.signal();
+            value == 42
+        })
+        .expect("Could not receive the notification");
+}
@@ -0,0 +1,122 @@
+use super::TopLevelError;
+
+use typenum::*;
+
+use ferros::alloc::{smart_alloc, ut_buddy};
+use ferros::bootstrap::UserImage;
+use ferros::cap::*;
+use ferros::userland::{Consumer1, Producer};
+use ferros::vspace::*;
+
+#[ferros_test::ferros_test]
+pub fn channel_teardown(
+    local_slots: LocalCNodeSlots<U4096>,
+    local_ut: LocalCap<Untyped<U20>>,
+    asid_pool: LocalCap<ASIDPool<U2>>,
+    local_vspace_scratch: &mut ScratchRegion,
+    root_cnode: &LocalCap<LocalCNode>,
+    user_image: &UserImage<role::Local>,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let (consumer_asid, asid_pool) = asid_pool.alloc();
+        let (producer_asid, _asid_pool) = asid_pool.alloc();
+
+        let (_consumer_cnode, consumer_slots) = retype_cnode::<U8>(ut, slots)?;
+        let (_producer_cnode, producer_slots) = retype_cnode::<U8>(ut, slots)?;
+
+        let consumer_vspace_slots: LocalCNodeSlots<U256> = slots;
+        let consumer_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut consumer_vspace = VSpace::new(
+            retype(ut, slots)?,
+            consumer_asid,
+            consumer_vspace_slots.weaken(),
+            consumer_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+
+        let producer_vspace_slots: LocalCNodeSlots<U256> = slots;
+        let producer_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut producer_vspace = VSpace::new(
+            retype(ut, slots)?,
+            producer_asid,
+            producer_vspace_slots.weaken(),
+            producer_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+
+        let (slots_c, _consumer_slots) = consumer_slots.alloc();
+        let (_consumer, consumer_token, producer_setup, _waker_setup) =
+            Consumer1::<role::Child, Data>::new::<U20, U12, _>(
+                ut,
+                ut,
+                local_vspace_scratch,
+                &mut consumer_vspace,
+                &root_cnode,
+                slots,
+                slots,
+                slots,
+                slots_c,
+            )?;
+
+        let (slots_p, _producer_slots) = producer_slots.alloc();
+        let (_producer, producer_teardown) = Producer::new_with_teardown(
+            &producer_setup,
+            slots_p,
+            &mut producer_vspace,
+            &root_cnode,
+            slots,
+        )?;
+    });
+
+    // Take it all apart, producer first
+    let reclaimed_producer = producer_teardown.teardown(&mut producer_vspace, &root_cnode)?;
+    let reclaimed_queue = producer_setup.teardown(&mut consumer_vspace, &root_cnode)?;
+    let mut reclaimed_consumer = consumer_token.teardown(&root_cnode)?;
+
+    // and put it back together from what was handed back
+    let notification_slot = reclaimed_consumer
+        .local_slots
+        .alloc_strong::<U1>()
+        .map_err(|_| {
+            TopLevelError::TestAssertionFailure("The notification's slot should be handed back")
+        })?;
+    let slot_c = reclaimed_consumer
+        .consumer_slots
+        .alloc_strong::<U1>()
+        .map_err(|_| {
+            TopLevelError::TestAssertionFailure("The consumer's slot should be handed back")
+        })?;
+    let (_consumer, _consumer_token, producer_setup, _waker_setup) =
+        Consumer1::<role::Child, Data>::new::<U20, U12, _>(
+            reclaimed_consumer.notification_ut,
+            reclaimed_queue.shared_region_ut,
+            local_vspace_scratch,
+            &mut consumer_vspace,
+            &root_cnode,
+            reclaimed_queue.umr_slots,
+            reclaimed_queue.shared_slots,
+            notification_slot,
+            slot_c,
+        )?;
+
+    let _producer = Producer::new(
+        &producer_setup,
+        reclaimed_producer.dest_slot,
+        &mut producer_vspace,
+        &root_cnode,
+        reclaimed_producer.local_slots,
+    )?;
+
+    Ok(())
+}
+
+#[derive(Debug)]
+pub struct Data {
+    a: u64,
+}
@@ -0,0 +1,195 @@
+use super::TopLevelError;
+
+use ferros::alloc::{smart_alloc, ut_buddy};
+use typenum::*;
+
+use ferros::arch;
+use ferros::bootstrap::UserImage;
+use ferros::cap::*;
+use ferros::userland::{
+    fault_or_message_channel, CapRights, FaultOrMessage, RetypeForSetup, SelfHostedProcess, Sender,
+    ThreadAuthority, ThreadStacks,
+};
+use ferros::vspace::*;
+
+#[ferros_test::ferros_test]
+pub fn child_spawns_threads(
+    local_slots: LocalCNodeSlots<U32768>,
+    local_ut: LocalCap<Untyped<U20>>,
+    asid_pool: LocalCap<ASIDPool<U1>>,
+    local_mapped_region: MappedMemoryRegion<U17, shared_status::Exclusive>,
+    root_cnode: &LocalCap<LocalCNode>,
+    user_image: &UserImage<role::Local>,
+    tpa: &LocalCap<ThreadPriorityAuthority>,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let (child_cnode, child_slots) = retype_cnode::<U14>(ut, slots)?;
+
+        let stacks_ut: LocalCap<Untyped<U16>> = ut;
+        let ipc_buffer_ut: LocalCap<Untyped<U12>> = ut;
+        let tcb_ut: LocalCap<Untyped<arch::TCBBits>> = ut;
+        let notification_ut: LocalCap<Untyped<arch::NotificationBits>> = ut;
+        let thread_ut: LocalCap<Untyped<arch::NotificationBits>> = ut;
+
+        smart_alloc! {|slots_c: child_slots| {
+            let cap_transfer_slots: LocalCap<CNodeSlotsData<U1024, role::Child>> = slots_c;
+            let (cnode_for_child, slots_for_child):(_, ChildCap<CNodeSlotsData<U2048, role::Child>>) =
+                child_cnode.generate_self_reference(&root_cnode, slots_c)?;
+            let thread_authority =
+                ThreadAuthority::grant(cnode_for_child, tpa, &root_cnode, slots_c)?;
+            let child_stacks_ut = stacks_ut.move_to_slot(&root_cnode, slots_c)?;
+            let child_ipc_buffer_ut = ipc_buffer_ut.move_to_slot(&root_cnode, slots_c)?;
+            let child_tcb_ut = tcb_ut.move_to_slot(&root_cnode, slots_c)?;
+            let child_notification_ut = notification_ut.move_to_slot(&root_cnode, slots_c)?;
+            let child_thread_ut = thread_ut.move_to_slot(&root_cnode, slots_c)?;
+            let (fault_source, outcome_sender, handler) = fault_or_message_channel(
+                &root_cnode,
+                ut,
+                slots,
+                slots_c,
+                slots,
+            )?;
+        }}
+
+        let (child_paging_slots, slots_for_child): (Cap<CNodeSlotsData<U1024, _>, _>, _) =
+            slots_for_child.alloc();
+        let (exact_child_slots, _) = slots_for_child.alloc();
+
+        let params = ProcParams {
+            thread_authority,
+            stacks_ut: child_stacks_ut,
+            ipc_buffer_ut: child_ipc_buffer_ut,
+            tcb_ut: child_tcb_ut,
+            notification_ut: child_notification_ut,
+            thread_ut: child_thread_ut,
+            slots: exact_child_slots,
+            outcome_sender,
+        };
+
+        let (child_asid, _asid_pool) = asid_pool.alloc();
+
+        let child_root = retype(ut, slots)?;
+        let child_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let child_vspace_ut: LocalCap<Untyped<U15>> = ut;
+
+        let child_vspace = VSpace::new(
+            child_root,
+            child_asid,
+            child_vspace_slots.weaken(),
+            child_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+
+        let sh_process = SelfHostedProcess::new(
+            child_vspace,
+            child_cnode,
+            local_mapped_region,
+            root_cnode,
+            spawner_main,
+            params,
+            ut,
+            ut,
+            slots,
+            cap_transfer_slots.weaken(),
+            child_paging_slots.weaken(),
+            tpa,
+            Some(fault_source),
+        )?;
+    });
+
+    sh_process.start()?;
+
+    match handler.await_message()? {
+        FaultOrMessage::Message(true) => Ok(()),
+        _ => Err(TopLevelError::TestAssertionFailure(
+            "Child process should have reported success",
+        )),
+    }
+}
+
+pub struct ProcParams<Role: CNodeRole> {
+    pub thread_authority: ThreadAuthority<Role>,
+    pub stacks_ut: Cap<Untyped<U16>, Role>,
+    pub ipc_buffer_ut: Cap<Untyped<U12>, Role>,
+    pub tcb_ut: Cap<Untyped<arch::TCBBits>, Role>,
+    pub notification_ut: Cap<Untyped<arch::NotificationBits>, Role>,
+    /// For the spawned thread to retype
+    pub thread_ut: Cap<Untyped<arch::NotificationBits>, Role>,
+    pub slots: Cap<CNodeSlotsData<U32, Role>, Role>,
+    pub outcome_sender: Sender<bool, Role>,
+}
+
+impl RetypeForSetup for ProcParams<role::Local> {
+    type Output = ProcParams<role::Child>;
+}
+
+/// Retyping takes the CNode to retype into as an extra cap, which goes
+/// through the calling thread's IPC buffer
+fn retype_and_double(
+    (value, ut, slot): (
+        usize,
+        LocalCap<Untyped<arch::NotificationBits>>,
+        LocalCNodeSlot,
+    ),
+) -> Option<usize> {
+    let _notification: LocalCap<Notification> = ut.retype(slot).ok()?;
+    Some(value * 2)
+}
+
+pub extern "C" fn spawner_main(mut vspace: SelfHostedVSpace, params: ProcParams<role::Local>) {
+    let ProcParams {
+        thread_authority,
+        stacks_ut,
+        ipc_buffer_ut,
+        tcb_ut,
+        notification_ut,
+        thread_ut,
+        slots,
+        outcome_sender,
+    } = params;
+    let (stacks_slots, slots) = slots.alloc::<U16>();
+    let (ipc_buffer_slots, slots) = slots.alloc::<U1>();
+    let (spawn_slots, slots) = slots.alloc::<U2>();
+    let (retype_slot, _slots) = slots.alloc::<U1>();
+
+    let stacks_region = vspace
+        .map_region(
+            UnmappedMemoryRegion::new(stacks_ut, stacks_slots).expect("retyping stacks failed"),
+            CapRights::RW,
+            arch::vm_attributes::DEFAULT | arch::vm_attributes::EXECUTE_NEVER,
+        )
+        .expect("mapping stacks failed");
+    let mut stacks: ThreadStacks<U14> =
+        ThreadStacks::new(stacks_region).expect("unmapping stack guard pages failed");
+    let ipc_buffer = vspace
+        .map_region(
+            UnmappedMemoryRegion::new(ipc_buffer_ut, ipc_buffer_slots)
+                .expect("retyping ipc buffer failed"),
+            CapRights::RW,
+            arch::vm_attributes::DEFAULT | arch::vm_attributes::EXECUTE_NEVER,
+        )
+        .expect("mapping ipc buffer failed");
+
+    let handle = thread_authority
+        .spawn(
+            vspace.vspace(),
+            &mut stacks,
+            retype_and_double,
+            (21, thread_ut, retype_slot),
+            ipc_buffer,
+            tcb_ut,
+            notification_ut,
+            spawn_slots,
+        )
+        .expect("spawning thread failed");
+    let stacks_in_use = stacks.available() == 3;
+    let result = handle.join(&mut stacks);
+
+    outcome_sender
+        .blocking_send(&(result == Some(42) && stacks_in_use && stacks.available() == 4))
+        .expect("Found value does not match expectations")
+}
@@ -0,0 +1,185 @@
+use serde::{Deserialize, Serialize};
+use typenum::*;
+
+use ferros::alloc::{smart_alloc, ut_buddy};
+use ferros::bootstrap::UserImage;
+use ferros::cap::*;
+use ferros::userland::{
+    fault_or_compact_message_channel_with_overflow, fault_or_message_channel,
+    CompactFaultOrMessageHandler, CompactSender, FaultOrMessage, RetypeForSetup, Sender,
+    StandardProcess,
+};
+use ferros::vspace::*;
+
+use super::TopLevelError;
+
+/// Too big to encode into the IPC buffer, as every value takes up ten
+/// bytes once encoded
+const LOG_VALUE: u64 = u64::max_value() - 0xff;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub enum Report {
+    Progress { done: u32, total: u32 },
+    Log([[u64; 32]; 4]),
+}
+
+fn log() -> Report {
+    let mut log = [[LOG_VALUE; 32]; 4];
+    log[3][31] = 42;
+    Report::Log(log)
+}
+
+#[ferros_test::ferros_test]
+pub fn compact_messages(
+    local_slots: LocalCNodeSlots<U32768>,
+    local_ut: LocalCap<Untyped<U20>>,
+    asid_pool: LocalCap<ASIDPool<U2>>,
+    local_mapped_region: MappedMemoryRegion<U18, shared_status::Exclusive>,
+    root_cnode: &LocalCap<LocalCNode>,
+    user_image: &UserImage<role::Local>,
+    tpa: &LocalCap<ThreadPriorityAuthority>,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let (reporter_asid, asid_pool) = asid_pool.alloc();
+        let (collector_asid, _asid_pool) = asid_pool.alloc();
+
+        let reporter_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let reporter_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut reporter_vspace = VSpace::new(
+            retype(ut, slots)?,
+            reporter_asid,
+            reporter_vspace_slots.weaken(),
+            reporter_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+        let collector_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let collector_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut collector_vspace = VSpace::new(
+            retype(ut, slots)?,
+            collector_asid,
+            collector_vspace_slots.weaken(),
+            collector_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+
+        let (reporter_cnode, reporter_slots) = retype_cnode::<U12>(ut, slots)?;
+        let (collector_cnode, collector_slots) = retype_cnode::<U12>(ut, slots)?;
+
+        let (reporter_fault_source_slot, _reporter_slots) = reporter_slots.alloc();
+        let (handler_slot, collector_slots) = collector_slots.alloc();
+        let (reporter_fault_source, report_sender, report_handler) =
+            fault_or_compact_message_channel_with_overflow(
+                &root_cnode,
+                ut,
+                slots,
+                reporter_fault_source_slot,
+                handler_slot,
+                ut,
+                slots,
+                &mut collector_vspace,
+                &mut reporter_vspace,
+            )?;
+
+        let (collector_fault_source_slot, _collector_slots) = collector_slots.alloc();
+        let (collector_fault_source, outcome_sender, outcome_handler) =
+            fault_or_message_channel(&root_cnode, ut, slots, collector_fault_source_slot, slots)?;
+
+        let (reporter_stack, collector_stack) = local_mapped_region.split()?;
+
+        let mut collector_process = StandardProcess::new(
+            &mut collector_vspace,
+            collector_cnode,
+            collector_stack,
+            root_cnode,
+            collector_run as extern "C" fn(_) -> (),
+            CollectorParams {
+                report_handler,
+                outcome_sender,
+            },
+            ut,
+            ut,
+            slots,
+            tpa,
+            Some(collector_fault_source),
+        )?;
+        collector_process.start()?;
+
+        let mut reporter_process = StandardProcess::new(
+            &mut reporter_vspace,
+            reporter_cnode,
+            reporter_stack,
+            root_cnode,
+            reporter_run as extern "C" fn(_) -> (),
+            ReporterParams { report_sender },
+            ut,
+            ut,
+            slots,
+            tpa,
+            Some(reporter_fault_source),
+        )?;
+        reporter_process.start()?;
+    });
+
+    match outcome_handler.await_message()? {
+        FaultOrMessage::Message(true) => Ok(()),
+        _ => Err(TopLevelError::TestAssertionFailure(
+            "Collector should have received each report intact",
+        )),
+    }
+}
+
+pub struct ReporterParams<Role: CNodeRole> {
+    pub report_sender: CompactSender<Report, Role>,
+}
+
+impl RetypeForSetup for ReporterParams<role::Local> {
+    type Output = ReporterParams<role::Child>;
+}
+
+pub struct CollectorParams<Role: CNodeRole> {
+    pub report_handler: CompactFaultOrMessageHandler<Report, Role>,
+    pub outcome_sender: Sender<bool, Role>,
+}
+
+impl RetypeForSetup for CollectorParams<role::Local> {
+    type Output = CollectorParams<role::Child>;
+}
+
+/// Sends a report that fits in the IPC buffer either side of one that
+/// has to go by way of the shared page
+pub extern "C" fn reporter_run(p: ReporterParams<role::Local>) {
+    let reports = [
+        Report::Progress { done: 1, total: 2 },
+        log(),
+        Report::Progress { done: 2, total: 2 },
+    ];
+    for report in reports.iter() {
+        p.report_sender
+            .blocking_send(report)
+            .expect("Failed to send report");
+    }
+}
+
+pub extern "C" fn collector_run(p: CollectorParams<role::Local>) {
+    let expected = [
+        Report::Progress { done: 1, total: 2 },
+        log(),
+        Report::Progress { done: 2, total: 2 },
+    ];
+    let mut passed = true;
+    for expected_report in expected.iter() {
+        passed &= match p.report_handler.await_message() {
+            Ok(FaultOrMessage::Message(report)) => &report == expected_report,
+            _ => false,
+        };
+    }
+    p.outcome_sender
+        .blocking_send(&passed)
+        .expect("Failed to send test outcome");
+}
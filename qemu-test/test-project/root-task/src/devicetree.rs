@@ -0,0 +1,62 @@
+use super::TopLevelError;
+
+use ferros::bootstrap::devicetree::*;
+
+/// The blob of devicetree.dts
+static TEST_DTB: &[u8] = include_bytes!("devicetree.dtb");
+
+#[ferros_test::ferros_test]
+pub fn devicetree() -> Result<(), TopLevelError> {
+    let tree = DeviceTree::parse(TEST_DTB)?;
+
+    let uart = tree
+        .find_compatible("fsl,imx21-uart")
+        .ok_or(TopLevelError::TestAssertionFailure("No uart found"))?;
+    assert_eq!(uart.name(), "serial@2020000");
+    assert_eq!(uart.depth(), 2);
+    assert_eq!(
+        uart.reg(0)?,
+        MmioRange {
+            paddr: 0x0202_0000,
+            size: 0x4000
+        }
+    );
+    assert_eq!(uart.reg(1), Err(DeviceTreeError::NoSuchReg { index: 1 }));
+    // An SPI, numbered past the GIC's SGIs and PPIs
+    assert_eq!(uart.irq(0)?, 58);
+    let range = uart.reg(0)?.page_range()?;
+    assert_eq!((range.start(), range.size_bytes()), (0x0202_0000, 0x4000));
+
+    let timer = tree
+        .find_node("/soc/timer")
+        .ok_or(TopLevelError::TestAssertionFailure("No timer found"))?;
+    assert!(timer.is_compatible("fsl,imx6q-gpt"));
+    assert_eq!(timer.irq(0)?, 87);
+    assert_eq!(
+        timer.irq(1),
+        Err(DeviceTreeError::NoSuchInterrupt { index: 1 })
+    );
+    assert!(tree.find_node("/soc/serial@2098000").is_none());
+    assert!(tree.find_node("/timer@2098000").is_none());
+
+    // A register range smaller than a page, and not page aligned, is
+    // covered by the page it's in
+    let gic = tree
+        .find_by_address(0x00a0_1000)
+        .ok_or(TopLevelError::TestAssertionFailure("No GIC found"))?;
+    assert!(gic.property("interrupt-controller").is_some());
+    let range = gic.reg(1)?.page_range()?;
+    assert_eq!((range.start(), range.size_bytes()), (0x00a0_0000, 0x1000));
+
+    assert_eq!(tree.nodes().count(), 5);
+    assert_eq!(
+        DeviceTree::parse(&TEST_DTB[4..]).err(),
+        Some(DeviceTreeError::BadMagic)
+    );
+    assert_eq!(
+        DeviceTree::parse(&TEST_DTB[..TEST_DTB.len() - 1]).err(),
+        Some(DeviceTreeError::Truncated)
+    );
+
+    Ok(())
+}
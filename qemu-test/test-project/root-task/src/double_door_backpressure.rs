@@ -8,8 +8,8 @@ use ferros::alloc::{smart_alloc, ut_buddy};
 use ferros::bootstrap::UserImage;
 use ferros::cap::*;
 use ferros::userland::{
-    fault_or_message_channel, Consumer1, Consumer2, FaultOrMessage, Producer, QueueFullError,
-    RetypeForSetup, Sender, StandardProcess, Waker,
+    fault_or_message_channel, Consumer1, Consumer2, FaultOrMessage, Producer, RetypeForSetup,
+    SendError, Sender, StandardProcess, Waker,
 };
 use ferros::vspace::*;
 
@@ -351,12 +351,13 @@ pub extern "C" fn producer_a_proc(p: ProducerXParams<role::Local>) {
                 Ok(_) => {
                     break;
                 }
-                Err(QueueFullError(rejected_x)) => {
+                Err(SendError::QueueFull(rejected_x)) => {
                     x = rejected_x;
                     unsafe {
                         seL4_Yield();
                     }
                 }
+                Err(_) => panic!("The consumer should still be there"),
             }
         }
     }
@@ -376,13 +377,14 @@ pub extern "C" fn producer_b_proc(p: ProducerYParams<role::Local>) {
                 Ok(_) => {
                     break;
                 }
-                Err(QueueFullError(rejected_y)) => {
+                Err(SendError::QueueFull(rejected_y)) => {
                     y = rejected_y;
                     rejection_count += 1;
                     unsafe {
                         seL4_Yield();
                     }
                 }
+                Err(_) => panic!("The consumer should still be there"),
             }
         }
     }
@@ -0,0 +1,183 @@
+use super::TopLevelError;
+
+use typenum::*;
+
+use ferros::alloc::{smart_alloc, ut_buddy};
+use ferros::bootstrap::UserImage;
+use ferros::cap::{
+    retype, retype_cnode, role, ASIDPool, CNodeRole, LocalCNode, LocalCNodeSlots, LocalCap,
+    ThreadPriorityAuthority, Untyped,
+};
+use ferros::userland::*;
+use ferros::vspace::*;
+
+type U33768 = op!(U32768 + U1000);
+
+#[ferros_test::ferros_test]
+pub fn duplex_channel(
+    local_slots: LocalCNodeSlots<U33768>,
+    local_ut: LocalCap<Untyped<U20>>,
+    asid_pool: LocalCap<ASIDPool<U2>>,
+    local_mapped_region: MappedMemoryRegion<U18, shared_status::Exclusive>,
+    local_vspace_scratch: &mut ScratchRegion,
+    root_cnode: &LocalCap<LocalCNode>,
+    user_image: &UserImage<role::Local>,
+    tpa: &LocalCap<ThreadPriorityAuthority>,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let (client_asid, asid_pool) = asid_pool.alloc();
+        let (service_asid, _asid_pool) = asid_pool.alloc();
+
+        let client_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let client_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut client_vspace = VSpace::new(
+            retype(ut, slots)?,
+            client_asid,
+            client_vspace_slots.weaken(),
+            client_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+
+        let service_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let service_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut service_vspace = VSpace::new(
+            retype(ut, slots)?,
+            service_asid,
+            service_vspace_slots.weaken(),
+            service_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+
+        let (client_cnode, client_slots) = retype_cnode::<U12>(ut, slots)?;
+        let (service_cnode, service_slots) = retype_cnode::<U12>(ut, slots)?;
+
+        let (slots_c, client_slots) = client_slots.alloc();
+        let (slots_s, _service_slots) = service_slots.alloc();
+        let DuplexChannel {
+            caller,
+            responder,
+            producer,
+            consumer,
+            consumer_token: _,
+        } = duplex_channel::<u32, u32, u32, U4, U12, _, _>(
+            ut,
+            ut,
+            ut,
+            local_vspace_scratch,
+            &mut client_vspace,
+            &mut service_vspace,
+            &root_cnode,
+            slots,
+            slots,
+            slots,
+            slots,
+            slots_c,
+            slots_s,
+        )?;
+
+        let (child_fault_source_slot, _client_slots) = client_slots.alloc();
+        let (_fault_source, outcome_sender, handler) =
+            fault_or_message_channel(&root_cnode, ut, slots, child_fault_source_slot, slots)?;
+
+        let (client_region, service_region) = local_mapped_region.split()?;
+
+        let mut client_process = StandardProcess::new(
+            &mut client_vspace,
+            client_cnode,
+            client_region,
+            root_cnode,
+            client_proc as extern "C" fn(_) -> (),
+            ClientParams::<role::Child> {
+                caller,
+                consumer,
+                outcome_sender,
+            },
+            ut,
+            ut,
+            slots,
+            tpa,
+            None, // fault
+        )?;
+        client_process.start()?;
+
+        let mut service_process = StandardProcess::new(
+            &mut service_vspace,
+            service_cnode,
+            service_region,
+            root_cnode,
+            service_proc as extern "C" fn(_) -> (),
+            ServiceParams::<role::Child> {
+                responder,
+                producer,
+            },
+            ut,
+            ut,
+            slots,
+            tpa,
+            None, // fault
+        )?;
+        service_process.start()?;
+    });
+
+    match handler.await_message()? {
+        FaultOrMessage::Message(true) => Ok(()),
+        _ => Err(TopLevelError::TestAssertionFailure(
+            "Client should have had a response and an event",
+        )),
+    }
+}
+
+pub struct ClientParams<Role: CNodeRole> {
+    pub caller: Caller<u32, u32, Role>,
+    pub consumer: Consumer1<Role, u32>,
+    pub outcome_sender: Sender<bool, Role>,
+}
+
+impl RetypeForSetup for ClientParams<role::Local> {
+    type Output = ClientParams<role::Child>;
+}
+
+pub struct ServiceParams<Role: CNodeRole> {
+    pub responder: Responder<u32, u32, Role>,
+    pub producer: Producer<Role, u32>,
+}
+
+impl RetypeForSetup for ServiceParams<role::Local> {
+    type Output = ServiceParams<role::Child>;
+}
+
+pub extern "C" fn client_proc(p: ClientParams<role::Local>) {
+    let ClientParams {
+        caller,
+        mut consumer,
+        outcome_sender,
+    } = p;
+    let response = caller.blocking_call(&20).expect("blocking_call");
+    // The service queues its event before it responds
+    let event = consumer.poll();
+
+    outcome_sender
+        .blocking_send(&(response == 21 && event == Some(40)))
+        .expect("could not send outcome");
+}
+
+pub extern "C" fn service_proc(p: ServiceParams<role::Local>) {
+    let ServiceParams {
+        responder,
+        producer,
+    } = p;
+    responder
+        .reply_recv(move |req| {
+            if producer.send(req * 2).is_err() {
+                panic!("event queue full");
+            }
+            req + 1
+        })
+        .expect("Could not set up a reply_recv");
+}
@@ -0,0 +1,124 @@
+use super::TopLevelError;
+
+use ferros::alloc::{smart_alloc, ut_buddy};
+use typenum::*;
+
+use elf_process;
+use ferros::arch::PageBits;
+use ferros::bootstrap::UserImage;
+use ferros::cap::*;
+use ferros::userland::{
+    fault_or_message_channel, FaultOrMessage, StandardProcess, ThreadAuthority, ThreadStacks,
+};
+use ferros::vspace::*;
+use selfe_arc;
+
+use crate::resources::ElfProcess;
+
+#[ferros_test::ferros_test]
+pub fn elf_loaded_on_thread(
+    local_slots: LocalCNodeSlots<U32768>,
+    local_ut: LocalCap<Untyped<U20>>,
+    asid_pool: LocalCap<ASIDPool<U1>>,
+    local_mapped_region: MappedMemoryRegion<U18, shared_status::Exclusive>,
+    root_cnode: &LocalCap<LocalCNode>,
+    root_vspace: &mut VSpace,
+    user_image: &UserImage<role::Local>,
+    tpa: &LocalCap<ThreadPriorityAuthority>,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    let archive_slice: &[u8] = unsafe {
+        core::slice::from_raw_parts(
+            &crate::_selfe_arc_data_start,
+            &crate::_selfe_arc_data_end as *const _ as usize
+                - &crate::_selfe_arc_data_start as *const _ as usize,
+        )
+    };
+
+    let archive = selfe_arc::read::Archive::from_slice(archive_slice);
+    let elf_data = archive
+        .file(ElfProcess::IMAGE_NAME)
+        .expect("find elf-process in arc");
+    // The archive is linked into the image, so its files outlive any
+    // thread loading one of them
+    let elf_data: &'static [u8] =
+        unsafe { core::slice::from_raw_parts(elf_data.as_ptr(), elf_data.len()) };
+
+    let (stack_mem, loader_mem) = local_mapped_region.split()?;
+    let (loader_stacks_mem, loader_mem) = loader_mem.split()?;
+    let (loader_ipc_buffer, _) = loader_mem.split_into::<PageBits>()?;
+    let mut loader_stacks: ThreadStacks<U16> = ThreadStacks::new(loader_stacks_mem)?;
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let thread_authority = ThreadAuthority::for_root_task(&root_cnode, &tpa, slots)?;
+        let loader_scratch: ScratchRegion = root_vspace.reserve_scratch(retype(ut, slots)?)?;
+
+        let child_root = retype(ut, slots)?;
+        let child_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let child_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let (child_asid, _asid_pool) = asid_pool.alloc();
+
+        let child_vspace_loading = VSpace::new_from_elf_on_thread::<ElfProcess, _>(
+            child_root,
+            child_asid,
+            child_vspace_slots.weaken(),
+            child_vspace_ut.weaken(),
+            elf_data,
+            slots, // page_slots
+            ut,    // elf_writable_mem
+            &user_image,
+            &root_cnode,
+            loader_scratch,
+            &thread_authority,
+            &root_vspace,
+            &mut loader_stacks,
+            loader_ipc_buffer,
+            ut, // tcb_ut
+            ut, // notification_ut
+            slots,
+        )?;
+
+        let (child_cnode, child_slots) = retype_cnode::<U12>(ut, slots)?;
+        let (child_fault_source_slot, _child_slots) = child_slots.alloc();
+        let (fault_source, outcome_sender, handler) =
+            fault_or_message_channel(&root_cnode, ut, slots, child_fault_source_slot, slots)?;
+
+        let params: elf_process::ProcParams<role::Child> = elf_process::ProcParams {
+            value: 42,
+            outcome_sender,
+        };
+    });
+
+    let (mut child_vspace, _loader_scratch) = child_vspace_loading.join(&mut loader_stacks)?;
+    if loader_stacks.available() != 1 {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Joining the loading thread should have given its stack back",
+        ));
+    }
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let mut child_process = StandardProcess::new::<elf_process::ProcParams<_>, _>(
+            &mut child_vspace,
+            child_cnode,
+            stack_mem,
+            root_cnode,
+            elf_data,
+            params,
+            ut, // ipc_buffer_ut
+            ut, // tcb_ut
+            slots,
+            tpa,  // priority_authority
+            None, // fault
+        )?;
+    });
+
+    child_process.start()?;
+
+    match handler.await_message()? {
+        FaultOrMessage::Message(true) => Ok(()),
+        _ => Err(TopLevelError::TestAssertionFailure(
+            "Child process loaded on a thread should have reported success",
+        )),
+    }
+}
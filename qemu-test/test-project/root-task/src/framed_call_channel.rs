@@ -0,0 +1,190 @@
+use super::TopLevelError;
+
+use typenum::*;
+
+use ferros::alloc::{smart_alloc, ut_buddy};
+use ferros::bootstrap::UserImage;
+use ferros::cap::{
+    retype, retype_cnode, role, ASIDPool, CNodeRole, LocalCNode, LocalCNodeSlots, LocalCap,
+    ThreadPriorityAuthority, Untyped,
+};
+use ferros::userland::*;
+use ferros::vspace::*;
+
+type U33768 = op!(U32768 + U1000);
+
+const BLOB_WORDS: usize = 512;
+
+/// Too big for the IPC buffer on any platform, so always spilled
+pub struct Blob {
+    words: [u32; BLOB_WORDS],
+}
+
+#[ferros_test::ferros_test]
+pub fn framed_call_channel(
+    local_slots: LocalCNodeSlots<U33768>,
+    local_ut: LocalCap<Untyped<U20>>,
+    asid_pool: LocalCap<ASIDPool<U2>>,
+    local_mapped_region: MappedMemoryRegion<U18, shared_status::Exclusive>,
+    root_cnode: &LocalCap<LocalCNode>,
+    user_image: &UserImage<role::Local>,
+    tpa: &LocalCap<ThreadPriorityAuthority>,
+) -> Result<(), TopLevelError> {
+    if fits_ipc_buffer::<Blob>() || !fits_ipc_buffer::<u32>() {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Only a Blob should be spilled",
+        ));
+    }
+
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let (client_asid, asid_pool) = asid_pool.alloc();
+        let (service_asid, _asid_pool) = asid_pool.alloc();
+
+        let client_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let client_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut client_vspace = VSpace::new(
+            retype(ut, slots)?,
+            client_asid,
+            client_vspace_slots.weaken(),
+            client_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+
+        let service_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let service_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut service_vspace = VSpace::new(
+            retype(ut, slots)?,
+            service_asid,
+            service_vspace_slots.weaken(),
+            service_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+
+        let (client_cnode, client_slots) = retype_cnode::<U12>(ut, slots)?;
+        let (service_cnode, service_slots) = retype_cnode::<U12>(ut, slots)?;
+
+        let (slots_c, client_slots) = client_slots.alloc();
+        let (slots_s, _service_slots) = service_slots.alloc();
+        let (blob_caller, blob_responder) = framed_call_channel(
+            ut,
+            ut,
+            &root_cnode,
+            slots,
+            &mut client_vspace,
+            &mut service_vspace,
+            slots_c,
+            slots_s,
+        )?;
+
+        let (child_fault_source_slot, _client_slots) = client_slots.alloc();
+        let (_fault_source, outcome_sender, handler) =
+            fault_or_message_channel(&root_cnode, ut, slots, child_fault_source_slot, slots)?;
+
+        let (client_region, service_region) = local_mapped_region.split()?;
+
+        let mut client_process = StandardProcess::new(
+            &mut client_vspace,
+            client_cnode,
+            client_region,
+            root_cnode,
+            client_proc as extern "C" fn(_) -> (),
+            ClientParams::<role::Child> {
+                blob_caller,
+                outcome_sender,
+            },
+            ut,
+            ut,
+            slots,
+            tpa,
+            None, // fault
+        )?;
+        client_process.start()?;
+
+        let mut service_process = StandardProcess::new(
+            &mut service_vspace,
+            service_cnode,
+            service_region,
+            root_cnode,
+            service_proc as extern "C" fn(_) -> (),
+            ServiceParams::<role::Child> { blob_responder },
+            ut,
+            ut,
+            slots,
+            tpa,
+            None, // fault
+        )?;
+        service_process.start()?;
+    });
+
+    match handler.await_message()? {
+        FaultOrMessage::Message(true) => Ok(()),
+        _ => Err(TopLevelError::TestAssertionFailure(
+            "Client should have had every blob back doubled",
+        )),
+    }
+}
+
+pub struct ClientParams<Role: CNodeRole> {
+    pub blob_caller: FramedCaller<Blob, Blob, Role>,
+    pub outcome_sender: Sender<bool, Role>,
+}
+
+impl RetypeForSetup for ClientParams<role::Local> {
+    type Output = ClientParams<role::Child>;
+}
+
+pub struct ServiceParams<Role: CNodeRole> {
+    pub blob_responder: FramedResponder<Blob, Blob, Role>,
+}
+
+impl RetypeForSetup for ServiceParams<role::Local> {
+    type Output = ServiceParams<role::Child>;
+}
+
+pub extern "C" fn client_proc(p: ClientParams<role::Local>) {
+    let ClientParams {
+        blob_caller,
+        outcome_sender,
+    } = p;
+
+    let mut all_doubled = true;
+    for round in 0..3 {
+        let mut request = Blob {
+            words: [0; BLOB_WORDS],
+        };
+        for (i, word) in request.words.iter_mut().enumerate() {
+            *word = (round * BLOB_WORDS + i) as u32;
+        }
+        let response = blob_caller.blocking_call(&request).expect("blocking_call");
+        all_doubled &= request
+            .words
+            .iter()
+            .zip(response.words.iter())
+            .all(|(req, rsp)| *rsp == req * 2);
+    }
+
+    outcome_sender
+        .blocking_send(&all_doubled)
+        .expect("could not send outcome");
+}
+
+pub extern "C" fn service_proc(p: ServiceParams<role::Local>) {
+    let ServiceParams { blob_responder } = p;
+    blob_responder
+        .reply_recv(|req| {
+            let mut response = Blob {
+                words: [0; BLOB_WORDS],
+            };
+            for (rsp, req) in response.words.iter_mut().zip(req.words.iter()) {
+                *rsp = req * 2;
+            }
+            response
+        })
+        .expect("Could not set up a reply_recv");
+}
@@ -51,7 +51,8 @@ pub fn grandkid_process_runs(
             let (cnode_for_child, slots_for_child) =
                 child_cnode.generate_self_reference(&cnode, slots_c)?;
             let untyped_for_child = ut.move_to_slot(&cnode, slots_c)?;
-            let (asid_pool_for_child, _asid_pool): (_, LocalCap<ASIDPool<U0>>) = asid_pool.split(slots_c, slots, &cnode)?;
+            // The child supervises its own child with a share of the ASIDs
+            let (asid_pool_for_child, _asid_pool) = asid_pool.split_off(slots_c, &cnode)?;
             let user_image_for_child = user_image.copy(&cnode, slots_c)?;
             let thread_priority_authority_for_child =
                 tpa.copy(&cnode, slots_c, CapRights::RWG)?;
@@ -0,0 +1,147 @@
+use core::ptr::{read_volatile, write_volatile};
+
+use typenum::*;
+
+use ferros::alloc::{smart_alloc, ut_buddy};
+use ferros::arch::fault::Fault;
+use ferros::bootstrap::UserImage;
+use ferros::cap::{
+    retype, retype_cnode, role, ASIDPool, Badge, LocalCNode, LocalCNodeSlot, LocalCNodeSlots,
+    LocalCap, ThreadPriorityAuthority, Untyped,
+};
+use ferros::userland::{FaultSinkSetup, PagerEvent, RetypeForSetup, StackPager, StandardProcess};
+use ferros::vspace::*;
+
+use super::TopLevelError;
+
+/// Where the child faults on purpose once it's done with its stack
+const DONE_ADDRESS: usize = 0x8888_8888;
+
+#[ferros_test::ferros_test]
+pub fn lazy_stack_growth(
+    local_slots: LocalCNodeSlots<U32768>,
+    local_ut: LocalCap<Untyped<U20>>,
+    asid_pool: LocalCap<ASIDPool<U1>>,
+    local_mapped_region: MappedMemoryRegion<U17, shared_status::Exclusive>,
+    root_cnode: &LocalCap<LocalCNode>,
+    user_image: &UserImage<role::Local>,
+    tpa: &LocalCap<ThreadPriorityAuthority>,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let (child_asid, _asid_pool) = asid_pool.alloc();
+        let child_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let child_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut child_vspace = VSpace::new(
+            retype(ut, slots)?,
+            child_asid,
+            child_vspace_slots.weaken(),
+            child_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+
+        let (child_cnode, child_slots) = retype_cnode::<U12>(ut, slots)?;
+        let params = ProcParams {};
+
+        let setup = FaultSinkSetup::new(&root_cnode, ut, slots, slots)?;
+        let (child_slot_for_fault_source, _child_slots) = child_slots.alloc();
+        let fault_source =
+            setup.add_fault_source(&root_cnode, child_slot_for_fault_source, Badge::from(0))?;
+        let sink = setup.sink();
+
+        let (child_process, lazy_stack) = StandardProcess::new_with_lazy_stack::<_, _, U16>(
+            &mut child_vspace,
+            child_cnode,
+            local_mapped_region,
+            root_cnode,
+            proc_main as extern "C" fn(_) -> (),
+            params,
+            ut,
+            ut,
+            slots,
+            tpa,
+            Some(fault_source),
+        )?;
+
+        let reserve_ut: LocalCap<Untyped<U16>> = ut;
+        let pager_slots: LocalCNodeSlots<U64> = slots;
+        let reply_slot: LocalCNodeSlot = slots;
+    });
+
+    let mut pager = StackPager::new(
+        child_vspace,
+        sink,
+        lazy_stack,
+        reserve_ut.weaken(),
+        pager_slots.weaken(),
+        reply_slot,
+    )
+    .map_err(|_| TopLevelError::TestAssertionFailure("setting up the stack pager failed"))?;
+
+    child_process.start()?;
+
+    loop {
+        match pager.handle_fault() {
+            Ok(PagerEvent::Mapped { vaddr }) => {
+                if !lazy_stack.contains(vaddr) {
+                    return Err(TopLevelError::TestAssertionFailure(
+                        "page mapped outside of the lazy stack",
+                    ));
+                }
+            }
+            Ok(PagerEvent::Unhandled(Fault::VMFault(fault))) if fault.address == DONE_ADDRESS => {
+                break;
+            }
+            Ok(PagerEvent::Unhandled(_)) => {
+                return Err(TopLevelError::TestAssertionFailure(
+                    "unexpected fault in lazy_stack_growth",
+                ))
+            }
+            Err(_) => {
+                return Err(TopLevelError::TestAssertionFailure(
+                    "stack pager failed to map a page",
+                ))
+            }
+        }
+    }
+
+    if pager.mapped_pages() > 0 {
+        Ok(())
+    } else {
+        Err(TopLevelError::TestAssertionFailure(
+            "the child never grew its stack past the mapped part",
+        ))
+    }
+}
+
+pub struct ProcParams {}
+
+impl RetypeForSetup for ProcParams {
+    type Output = ProcParams;
+}
+
+/// Use a page of stack per level, `depth` levels deep
+fn grow(depth: usize) -> usize {
+    let mut buf = [0u8; 4096];
+    for b in buf.iter_mut() {
+        unsafe { write_volatile(b, depth as u8) };
+    }
+    let below = if depth == 0 { 0 } else { grow(depth - 1) };
+    below + unsafe { read_volatile(&buf[depth]) } as usize
+}
+
+pub extern "C" fn proc_main(_params: ProcParams) {
+    // Deeper than the 32 mapped pages of stack, into the 16 lazy pages
+    // below them but not out the bottom
+    let total = grow(40);
+    debug_println!("Grew the stack, total {}", total);
+
+    unsafe {
+        let x: *const usize = DONE_ADDRESS as _;
+        let y = read_volatile(x);
+        debug_println!("Value from arbitrary memory is: {}", y);
+    }
+}
@@ -13,29 +13,61 @@ extern crate selfe_sys;
 extern crate typenum;
 
 mod call_and_response_loop;
+mod call_with_timeout;
+mod cap_transfer;
+mod channel_teardown;
 mod child_process_cap_management;
 mod child_process_runs;
+mod child_spawns_threads;
 mod child_thread_runs;
+mod compact_messages;
+#[cfg(any(target_arch = "arm", target_arch = "aarch64"))]
+mod devicetree;
 mod dont_tread_on_me;
 mod double_door_backpressure;
+mod duplex_channel;
+mod elf_loaded_on_thread;
 mod elf_process_runs;
 mod fault_or_message_handler;
 mod fault_pair;
+mod framed_call_channel;
 mod grandkid_process_runs;
+#[cfg(any(target_arch = "arm", target_arch = "aarch64"))]
 mod irq_control_manipulation;
+mod lazy_stack_growth;
+#[cfg(any(target_arch = "arm", target_arch = "aarch64"))]
 mod memory_read_protection;
+#[cfg(any(target_arch = "arm", target_arch = "aarch64"))]
 mod memory_write_protection;
+mod notification_bus;
 mod over_register_size_params;
+mod params_layout_mismatch;
 mod polling_consumer;
+mod process_heap;
+mod read_only_sharing;
+mod retype_bulk;
 mod reuse_slots;
 mod reuse_untyped;
 mod root_task_runs;
+mod scratch_checkout;
+mod scratch_mapping;
 mod self_hosted_mem_mgmt;
+mod service_registry;
 mod shared_page_queue;
+mod simulated_device;
+mod slot_compactor;
 mod stack_setup;
+mod sub_supervisor_kit;
+mod thread_stack_guard;
+mod top_up;
+mod two_phase_commit;
 mod uart;
+mod unmap_and_reuse_region;
+mod weak_asid_pool;
 mod weak_elf;
+mod weak_slot_allocator;
 mod wutbuddy;
+mod wx_shared_page;
 
 mod resources {
     include! {concat!(env!("OUT_DIR"), "/resources.rs")}
@@ -48,11 +80,17 @@ extern "C" {
 
 use ferros::alloc::micro_alloc::Error as AllocError;
 use ferros::alloc::ut_buddy::UTBuddyError;
+use ferros::bootstrap::devicetree::DeviceTreeError;
+use ferros::cap::ASIDPoolError;
 use ferros::cap::IRQError;
 use ferros::cap::RetypeError;
+use ferros::cap::SlotAllocError;
+use ferros::cap::SlotCompactorError;
 use ferros::error::SeL4Error;
 use ferros::userland::{
-    FaultManagementError, IPCError, MultiConsumerError, ProcessSetupError, ThreadSetupError,
+    CompactMessageError, DuplexChannelError, FaultManagementError, HeapError, IPCError,
+    MultiConsumerError, ProcessSetupError, RegistryError, SubSupervisorKitError, ThreadSetupError,
+    TopUpError,
 };
 use ferros::vspace::VSpaceError;
 
@@ -62,28 +100,61 @@ use ferros_test::ferros_test_main;
 #[cfg(not(test_case = "uart"))]
 ferros_test_main!(&[
     &call_and_response_loop::call_and_response_loop,
+    &call_with_timeout::call_with_timeout,
+    &cap_transfer::cap_transfer,
+    &channel_teardown::channel_teardown,
     &child_process_cap_management::child_process_cap_management,
     &child_process_runs::child_process_runs,
+    &child_spawns_threads::child_spawns_threads,
     &child_thread_runs::child_thread_runs,
+    &compact_messages::compact_messages,
+    #[cfg(any(target_arch = "arm", target_arch = "aarch64"))]
+    &devicetree::devicetree,
     &dont_tread_on_me::dont_tread_on_me,
     &double_door_backpressure::double_door_backpressure,
+    &duplex_channel::duplex_channel,
+    &elf_loaded_on_thread::elf_loaded_on_thread,
     &elf_process_runs::elf_process_runs,
     &fault_or_message_handler::fault_or_message_handler,
     &fault_pair::fault_pair,
+    &framed_call_channel::framed_call_channel,
     &grandkid_process_runs::grandkid_process_runs,
+    #[cfg(any(target_arch = "arm", target_arch = "aarch64"))]
     &irq_control_manipulation::irq_control_manipulation,
+    &lazy_stack_growth::lazy_stack_growth,
+    #[cfg(any(target_arch = "arm", target_arch = "aarch64"))]
     &memory_read_protection::memory_read_protection,
+    #[cfg(any(target_arch = "arm", target_arch = "aarch64"))]
     &memory_write_protection::memory_write_protection,
+    &notification_bus::notification_bus,
     &over_register_size_params::over_register_size_params,
+    &params_layout_mismatch::params_layout_mismatch,
     &polling_consumer::polling_consumer,
+    &process_heap::process_heap,
+    &read_only_sharing::read_only_sharing,
+    &retype_bulk::retype_bulk,
     &reuse_slots::reuse_slots,
     &reuse_untyped::reuse_untyped,
     &root_task_runs::root_task_runs,
+    &scratch_checkout::scratch_checkout,
+    &scratch_mapping::scratch_mapping,
     &self_hosted_mem_mgmt::self_hosted_mem_mgmt,
+    &service_registry::service_registry,
     &shared_page_queue::shared_page_queue,
+    &simulated_device::simulated_device,
+    &slot_compactor::slot_compactor,
     &stack_setup::stack_setup,
+    &sub_supervisor_kit::sub_supervisor_kit,
+    &thread_stack_guard::thread_stack_guard,
+    &top_up::top_up,
+    &two_phase_commit::two_phase_commit,
+    &unmap_and_reuse_region::unmap_and_reuse_region,
     &wutbuddy::wutbuddy,
+    &wutbuddy::wutbuddy_untracked_splits,
+    &weak_asid_pool::weak_asid_pool,
     &weak_elf::weak_elf_process_runs,
+    &weak_slot_allocator::weak_slot_allocator,
+    &wx_shared_page::wx_shared_page,
 ]);
 
 #[cfg(test_case = "uart")]
@@ -106,16 +177,25 @@ pub fn run(raw_boot_info: &'static selfe_sys::seL4_BootInfo) {
 #[derive(Debug)]
 pub enum TopLevelError {
     AllocError(AllocError),
+    ASIDPoolError(ASIDPoolError),
     IPCError(IPCError),
     MultiConsumerError(MultiConsumerError),
     VSpaceError(VSpaceError),
     SeL4Error(SeL4Error),
     IRQError(IRQError),
     FaultManagementError(FaultManagementError),
+    HeapError(HeapError),
     ProcessSetupError(ProcessSetupError),
+    RegistryError(RegistryError),
+    SubSupervisorKitError(SubSupervisorKitError),
     ThreadSetupError(ThreadSetupError),
+    TopUpError(TopUpError),
     UTBuddyError(UTBuddyError),
     RetypeError(RetypeError),
+    SlotAllocError(SlotAllocError),
+    SlotCompactorError(SlotCompactorError),
+    DeviceTreeError(DeviceTreeError),
+    CompactMessageError(CompactMessageError),
     TestAssertionFailure(&'static str),
 }
 
@@ -125,6 +205,12 @@ impl From<AllocError> for TopLevelError {
     }
 }
 
+impl From<ASIDPoolError> for TopLevelError {
+    fn from(e: ASIDPoolError) -> Self {
+        TopLevelError::ASIDPoolError(e)
+    }
+}
+
 impl From<IPCError> for TopLevelError {
     fn from(e: IPCError) -> Self {
         TopLevelError::IPCError(e)
@@ -167,12 +253,36 @@ impl From<ProcessSetupError> for TopLevelError {
     }
 }
 
+impl From<HeapError> for TopLevelError {
+    fn from(e: HeapError) -> Self {
+        TopLevelError::HeapError(e)
+    }
+}
+
+impl From<RegistryError> for TopLevelError {
+    fn from(e: RegistryError) -> Self {
+        TopLevelError::RegistryError(e)
+    }
+}
+
+impl From<SubSupervisorKitError> for TopLevelError {
+    fn from(e: SubSupervisorKitError) -> Self {
+        TopLevelError::SubSupervisorKitError(e)
+    }
+}
+
 impl From<ThreadSetupError> for TopLevelError {
     fn from(e: ThreadSetupError) -> Self {
         TopLevelError::ThreadSetupError(e)
     }
 }
 
+impl From<TopUpError> for TopLevelError {
+    fn from(e: TopUpError) -> Self {
+        TopLevelError::TopUpError(e)
+    }
+}
+
 impl From<UTBuddyError> for TopLevelError {
     fn from(e: UTBuddyError) -> Self {
         TopLevelError::UTBuddyError(e)
@@ -184,3 +294,36 @@ impl From<RetypeError> for TopLevelError {
         TopLevelError::RetypeError(e)
     }
 }
+
+impl From<SlotAllocError> for TopLevelError {
+    fn from(e: SlotAllocError) -> Self {
+        TopLevelError::SlotAllocError(e)
+    }
+}
+
+impl From<SlotCompactorError> for TopLevelError {
+    fn from(e: SlotCompactorError) -> Self {
+        TopLevelError::SlotCompactorError(e)
+    }
+}
+
+impl From<DeviceTreeError> for TopLevelError {
+    fn from(e: DeviceTreeError) -> Self {
+        TopLevelError::DeviceTreeError(e)
+    }
+}
+
+impl From<CompactMessageError> for TopLevelError {
+    fn from(e: CompactMessageError) -> Self {
+        TopLevelError::CompactMessageError(e)
+    }
+}
+
+impl From<DuplexChannelError> for TopLevelError {
+    fn from(e: DuplexChannelError) -> Self {
+        match e {
+            DuplexChannelError::IPCError(e) => TopLevelError::IPCError(e),
+            DuplexChannelError::MultiConsumerError(e) => TopLevelError::MultiConsumerError(e),
+        }
+    }
+}
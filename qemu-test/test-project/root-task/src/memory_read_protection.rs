@@ -7,7 +7,9 @@ use ferros::cap::{
     retype, retype_cnode, role, ASIDPool, Badge, LocalCNode, LocalCNodeSlots, LocalCap,
     ThreadPriorityAuthority, Untyped,
 };
-use ferros::userland::{FaultSinkSetup, RetypeForSetup, StandardProcess};
+use ferros::userland::{
+    FaultAccess, FaultSinkSetup, RetypeForSetup, StandardProcess, VMFaultCause, VMFaultStatus,
+};
 use ferros::vspace::*;
 
 use super::TopLevelError;
@@ -65,7 +67,15 @@ pub fn memory_read_protection(
     child_process.start()?;
 
     match sink.wait_for_fault() {
-        Fault::VMFault(_) => Ok(()),
+        Fault::VMFault(fault) => match fault.status() {
+            VMFaultStatus {
+                cause: VMFaultCause::Translation { .. },
+                access: FaultAccess::Read,
+            } => Ok(()),
+            _ => Err(TopLevelError::TestAssertionFailure(
+                "VM fault decoded wrongly in memory_read_protection",
+            )),
+        },
         _ => Err(TopLevelError::TestAssertionFailure(
             "unexpected fault in memory_read_protection",
         )),
@@ -4,7 +4,9 @@ use typenum::*;
 use ferros::arch::fault::Fault;
 use ferros::bootstrap::UserImage;
 use ferros::cap::*;
-use ferros::userland::{FaultSinkSetup, RetypeForSetup, StandardProcess};
+use ferros::userland::{
+    FaultAccess, FaultSinkSetup, RetypeForSetup, StandardProcess, VMFaultCause, VMFaultStatus,
+};
 use ferros::vspace::*;
 
 use super::TopLevelError;
@@ -63,7 +65,15 @@ pub fn memory_write_protection<'a, 'b, 'c>(
     child_process.start()?;
 
     match sink.wait_for_fault() {
-        Fault::VMFault(_) => Ok(()),
+        Fault::VMFault(fault) => match fault.status() {
+            VMFaultStatus {
+                cause: VMFaultCause::Permission { .. },
+                access: FaultAccess::Write,
+            } => Ok(()),
+            _ => Err(TopLevelError::TestAssertionFailure(
+                "VM fault decoded wrongly in memory_write_protection",
+            )),
+        },
         o => Err(TopLevelError::TestAssertionFailure(
             "unexpected fault in memory_read_protection",
         )),
@@ -0,0 +1,77 @@
+use typenum::*;
+
+use ferros::alloc::{smart_alloc, ut_buddy};
+use ferros::cap::{LocalCNode, LocalCNodeSlots, LocalCap, Untyped};
+use ferros::userland::{BusEvent, EventSet, NotificationBusError, NotificationBusSetup};
+
+use super::TopLevelError;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Wakeup {
+    Rx,
+    Tx,
+    Timer,
+}
+
+impl BusEvent for Wakeup {
+    fn bit(self) -> u8 {
+        self as u8
+    }
+
+    fn from_bit(bit: u8) -> Option<Self> {
+        match bit {
+            0 => Some(Wakeup::Rx),
+            1 => Some(Wakeup::Tx),
+            2 => Some(Wakeup::Timer),
+            _ => None,
+        }
+    }
+}
+
+#[ferros_test::ferros_test]
+pub fn notification_bus(
+    local_slots: LocalCNodeSlots<U16>,
+    local_ut: LocalCap<Untyped<U10>>,
+    root_cnode: &LocalCap<LocalCNode>,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let setup = NotificationBusSetup::<Wakeup, _>::new(root_cnode, ut, slots, slots)?;
+        let rx = setup
+            .add_publisher(root_cnode, slots, Wakeup::Rx)
+            .map_err(bus_error)?;
+        let timer = setup
+            .add_publisher(root_cnode, slots, Wakeup::Timer)
+            .map_err(bus_error)?;
+    });
+    let listener = setup.listener();
+
+    let nothing_yet = listener.poll().is_none();
+
+    rx.publish();
+    timer.publish();
+    timer.publish();
+    let fired = listener.wait();
+
+    let mut expected = EventSet::empty();
+    expected.insert(Wakeup::Rx);
+    expected.insert(Wakeup::Timer);
+
+    let mut events = fired.iter();
+    let in_order = events.next() == Some(Wakeup::Rx)
+        && events.next() == Some(Wakeup::Timer)
+        && events.next().is_none();
+
+    if nothing_yet && fired == expected && !fired.contains(Wakeup::Tx) && in_order {
+        Ok(())
+    } else {
+        Err(TopLevelError::TestAssertionFailure(
+            "notification bus delivered the wrong events",
+        ))
+    }
+}
+
+fn bus_error(_: NotificationBusError) -> TopLevelError {
+    TopLevelError::TestAssertionFailure("adding a publisher to the notification bus failed")
+}
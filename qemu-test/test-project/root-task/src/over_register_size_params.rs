@@ -92,6 +92,8 @@ impl RetypeForSetup for OverRegisterSizeParams<role::Local> {
     type Output = OverRegisterSizeParams<role::Child>;
 }
 
+ferros::assert_params_passing!(OverRegisterSizeParams<role::Local>, Stack);
+
 pub extern "C" fn proc_main(params: OverRegisterSizeParams<role::Local>) {
     let OverRegisterSizeParams {
         nums,
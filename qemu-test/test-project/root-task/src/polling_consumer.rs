@@ -8,7 +8,7 @@ use ferros::alloc::{smart_alloc, ut_buddy};
 use ferros::bootstrap::UserImage;
 use ferros::cap::*;
 use ferros::userland::{
-    fault_or_message_channel, Consumer1, FaultOrMessage, Producer, QueueFullError, RetypeForSetup,
+    fault_or_message_channel, Consumer1, FaultOrMessage, Producer, RetypeForSetup, SendError,
     Sender, StandardProcess,
 };
 use ferros::vspace::*;
@@ -209,12 +209,13 @@ pub extern "C" fn producer_proc(p: ProducerParams<role::Local>) {
                 Ok(_) => {
                     break;
                 }
-                Err(QueueFullError(rejected_data)) => {
+                Err(SendError::QueueFull(rejected_data)) => {
                     data = rejected_data;
                     unsafe {
                         seL4_Yield();
                     }
                 }
+                Err(_) => panic!("The consumer should still be there"),
             }
         }
     }
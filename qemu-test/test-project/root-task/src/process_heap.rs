@@ -0,0 +1,99 @@
+use core::alloc::{GlobalAlloc, Layout};
+
+use typenum::*;
+
+use ferros::userland::{HeapError, HeapStats, ProcessHeap};
+use ferros::vspace::*;
+
+use super::TopLevelError;
+
+#[ferros_test::ferros_test]
+pub fn process_heap(
+    local_mapped_region: MappedMemoryRegion<U15, shared_status::Exclusive>,
+) -> Result<(), TopLevelError> {
+    let (heap_region, spare_region) = local_mapped_region.split()?;
+    let heap = ProcessHeap::empty();
+    let nothing = Layout::from_size_align(8, 8).unwrap();
+    if !unsafe { heap.alloc(nothing) }.is_null() {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Heap allocated before it had any memory",
+        ));
+    }
+
+    let size_bytes = heap_region.size_bytes();
+    heap.init(heap_region)?;
+    if heap.init(spare_region) != Err(HeapError::AlreadyInitialized) {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Heap was given memory twice",
+        ));
+    }
+
+    let small = Layout::from_size_align(24, 8).unwrap();
+    let aligned = Layout::from_size_align(100, 256).unwrap();
+    let (a, b, c) = unsafe { (heap.alloc(small), heap.alloc(aligned), heap.alloc(small)) };
+    if a.is_null() || b.is_null() || c.is_null() {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Heap failed small allocations",
+        ));
+    }
+    if b as usize % 256 != 0 {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Heap ignored an allocation's alignment",
+        ));
+    }
+    unsafe {
+        a.write_bytes(0xaa, small.size());
+        b.write_bytes(0xbb, aligned.size());
+        c.write_bytes(0xcc, small.size());
+    }
+    if unsafe { *a.add(small.size() - 1) != 0xaa || *c != 0xcc } {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Heap allocations overlapped",
+        ));
+    }
+    let stats = heap.stats();
+    if stats.live_allocations != 3 || stats.used_bytes < 2 * small.size() + aligned.size() {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Heap stats didn't count the allocations",
+        ));
+    }
+
+    // Freed out of order, the blocks have to merge back into one for
+    // the whole heap to be allocated again
+    unsafe {
+        heap.dealloc(b, aligned);
+        heap.dealloc(a, small);
+        heap.dealloc(c, small);
+    }
+    let everything = Layout::from_size_align(size_bytes, 8).unwrap();
+    let all = unsafe { heap.alloc(everything) };
+    if all.is_null() {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Freed blocks weren't merged",
+        ));
+    }
+    if !unsafe { heap.alloc(small) }.is_null() {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Heap allocated beyond its region",
+        ));
+    }
+    unsafe { heap.dealloc(all, everything) };
+
+    let stats = heap.stats();
+    let expected = HeapStats {
+        size_bytes,
+        used_bytes: 0,
+        peak_used_bytes: size_bytes,
+        largest_free_block_bytes: size_bytes,
+        live_allocations: 0,
+        allocations: 4,
+        frees: 4,
+        failed_allocations: 2,
+    };
+    if stats != expected {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Heap stats didn't add up",
+        ));
+    }
+    Ok(())
+}
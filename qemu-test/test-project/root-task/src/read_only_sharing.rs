@@ -0,0 +1,128 @@
+use ferros::alloc::{smart_alloc, ut_buddy};
+use typenum::*;
+
+use ferros::arch::{self, fault::Fault};
+use ferros::bootstrap::UserImage;
+use ferros::cap::*;
+use ferros::userland::{
+    CapRights, FaultAccess, FaultSinkSetup, RetypeForSetup, StandardProcess, VMFaultCause,
+    VMFaultStatus,
+};
+use ferros::vspace::*;
+
+use super::TopLevelError;
+
+const TABLE_MAGIC: usize = 0xc0ff_ee00;
+
+#[ferros_test::ferros_test]
+pub fn read_only_sharing(
+    local_slots: LocalCNodeSlots<U32768>,
+    local_ut: LocalCap<Untyped<U20>>,
+    asid_pool: LocalCap<ASIDPool<U1>>,
+    local_mapped_region: MappedMemoryRegion<U17, shared_status::Exclusive>,
+    root_cnode: &LocalCap<LocalCNode>,
+    user_image: &UserImage<role::Local>,
+    tpa: &LocalCap<ThreadPriorityAuthority>,
+    scratch: &mut ScratchRegion,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let (child_asid, _asid_pool) = asid_pool.alloc();
+        let child_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let child_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut child_vspace = VSpace::new(
+            retype(ut, slots)?,
+            child_asid,
+            child_vspace_slots.weaken(),
+            child_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+
+        let mut table: UnmappedMemoryRegion<U12, _> = UnmappedMemoryRegion::new(ut, slots)?;
+        {
+            let mut mapping = scratch.checkout()?.map(&mut table)?;
+            mapping.as_mut_slice()[..core::mem::size_of::<usize>()]
+                .copy_from_slice(&TABLE_MAGIC.to_ne_bytes());
+        }
+        let (read_only_table, _table) = table.share_read_only(slots, root_cnode)?;
+        let child_table = child_vspace.map_read_only_region(
+            &read_only_table,
+            arch::vm_attributes::DEFAULT | arch::vm_attributes::EXECUTE_NEVER,
+            slots,
+            root_cnode,
+        )?;
+        let table_is_read_only = child_table.rights() == CapRights::R;
+
+        let (child_cnode, child_slots) = retype_cnode::<U12>(ut, slots)?;
+        let params = ProcParams { table: child_table };
+
+        let setup = FaultSinkSetup::new(&root_cnode, ut, slots, slots)?;
+        let (child_slot_for_fault_source, _child_slots) = child_slots.alloc();
+        let fault_source =
+            setup.add_fault_source(&root_cnode, child_slot_for_fault_source, Badge::from(0))?;
+        let sink = setup.sink();
+
+        let mut child_process = StandardProcess::new(
+            &mut child_vspace,
+            child_cnode,
+            local_mapped_region,
+            root_cnode,
+            proc_main as extern "C" fn(_) -> (),
+            params,
+            ut,
+            ut,
+            slots,
+            tpa,
+            Some(fault_source),
+        )?;
+    });
+
+    if !table_is_read_only {
+        return Err(TopLevelError::TestAssertionFailure(
+            "read-only region was mapped writable",
+        ));
+    }
+
+    child_process.start()?;
+
+    // The child reads the table and then writes to it, only the write
+    // should fault
+    match sink.wait_for_fault() {
+        Fault::VMFault(fault) => match fault.status() {
+            VMFaultStatus {
+                cause: VMFaultCause::Permission { .. },
+                access: FaultAccess::Write,
+            } => Ok(()),
+            _ => Err(TopLevelError::TestAssertionFailure(
+                "expected a write permission fault on the read-only region",
+            )),
+        },
+        _ => Err(TopLevelError::TestAssertionFailure(
+            "unexpected fault in read_only_sharing",
+        )),
+    }
+}
+
+pub struct ProcParams {
+    pub table: MappedMemoryRegion<U12, shared_status::Shared>,
+}
+
+impl RetypeForSetup for ProcParams {
+    type Output = ProcParams;
+}
+
+pub extern "C" fn proc_main(mut params: ProcParams) {
+    let mut magic = [0u8; core::mem::size_of::<usize>()];
+    magic.copy_from_slice(&params.table.as_slice()[..magic.len()]);
+    if usize::from_ne_bytes(magic) != TABLE_MAGIC {
+        debug_println!("The table didn't hold what the root task put there");
+        return;
+    }
+
+    params.table.as_mut_slice()[0] = 0;
+
+    debug_println!("This is after writing to the read-only region, and should not be printed.");
+}
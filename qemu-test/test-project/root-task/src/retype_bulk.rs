@@ -0,0 +1,67 @@
+use ferros::alloc::{smart_alloc, ut_buddy};
+use typenum::*;
+
+use ferros::arch;
+use ferros::bootstrap::UserImage;
+use ferros::cap::*;
+use ferros::userland::CapRights;
+use ferros::vspace::*;
+
+use super::TopLevelError;
+
+#[ferros_test::ferros_test]
+pub fn retype_bulk(
+    local_slots: LocalCNodeSlots<U2048>,
+    local_ut: LocalCap<Untyped<U20>>,
+    asid_pool: LocalCap<ASIDPool<U1>>,
+    root_cnode: &LocalCap<LocalCNode>,
+    user_image: &UserImage<role::Local>,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let (child_asid, _asid_pool) = asid_pool.alloc();
+        let child_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let child_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut child_vspace = VSpace::new(
+            retype(ut, slots)?,
+            child_asid,
+            child_vspace_slots.weaken(),
+            child_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+
+        let pages_ut: LocalCap<Untyped<U18>> = ut;
+        let pages_slots: LocalCNodeSlots<U64> = slots;
+    });
+
+    let pages: CapRange<Page<page_state::Unmapped>, role::Local, U64> =
+        pages_ut.retype_bulk(pages_slots)?;
+
+    // Every page is one of its own, in its own slot
+    let mut count = 0;
+    let mut previous_vaddr = None;
+    for page in pages.into_iter() {
+        let mapped = child_vspace.map_region(
+            page.to_region(),
+            CapRights::RW,
+            arch::vm_attributes::DEFAULT,
+        )?;
+        if previous_vaddr.map_or(false, |v| v >= mapped.vaddr()) {
+            return Err(TopLevelError::TestAssertionFailure(
+                "Pages were mapped out of order",
+            ));
+        }
+        previous_vaddr = Some(mapped.vaddr());
+        count += 1;
+    }
+    if count != 64 {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Unexpected number of bulk retyped pages",
+        ));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,43 @@
+use typenum::*;
+
+use ferros::cap::{LocalCNodeSlots, LocalCap, Untyped};
+use ferros::vspace::*;
+
+use super::TopLevelError;
+
+#[ferros_test::ferros_test]
+pub fn scratch_checkout(
+    local_slots: LocalCNodeSlots<U1>,
+    ut: LocalCap<Untyped<U12>>,
+    scratch: &mut ScratchRegion,
+) -> Result<(), TopLevelError> {
+    let mut region = UnmappedMemoryRegion::new(ut, local_slots)?;
+    let scratch: &ScratchRegion = scratch;
+
+    let mut guard = scratch.checkout()?;
+    assert!(scratch.is_checked_out());
+    match scratch.checkout() {
+        Err(VSpaceError::ScratchRegionInUse) => (),
+        _ => {
+            return Err(TopLevelError::TestAssertionFailure(
+                "Checked out the scratch region while it was already out",
+            ))
+        }
+    }
+    guard.temporarily_map_region(&mut region, |mapped| {
+        mapped.as_mut_slice()[0] = 0xfe;
+    })?;
+    drop(guard);
+    assert!(!scratch.is_checked_out());
+
+    let first_byte = scratch
+        .checkout()?
+        .temporarily_map_region(&mut region, |mapped| mapped.as_slice()[0])?;
+    if first_byte == 0xfe {
+        Ok(())
+    } else {
+        Err(TopLevelError::TestAssertionFailure(
+            "Region contents didn't survive between checkouts",
+        ))
+    }
+}
@@ -0,0 +1,41 @@
+use typenum::*;
+
+use ferros::cap::{LocalCNodeSlots, LocalCap, Untyped};
+use ferros::vspace::*;
+
+use super::TopLevelError;
+
+#[ferros_test::ferros_test]
+pub fn scratch_mapping(
+    local_slots: LocalCNodeSlots<U2>,
+    ut: LocalCap<Untyped<U13>>,
+    scratch: &mut ScratchRegion,
+) -> Result<(), TopLevelError> {
+    let mut region: UnmappedMemoryRegion<U13, _> = UnmappedMemoryRegion::new(ut, local_slots)?;
+    let scratch: &ScratchRegion = scratch;
+
+    {
+        let mut mapping = scratch.checkout()?.map(&mut region)?;
+        assert!(scratch.is_checked_out());
+        assert_eq!(mapping.vaddr(), scratch.vaddr());
+        let last = mapping.size_bytes() - 1;
+        let bytes = mapping.as_mut_slice();
+        bytes[0] = 0x12;
+        bytes[last] = 0x34;
+    }
+    assert!(!scratch.is_checked_out());
+
+    let mapping = scratch.checkout()?.map(&mut region)?;
+    let bytes = mapping.as_slice();
+    let contents_survived = bytes[0] == 0x12 && bytes[bytes.len() - 1] == 0x34;
+    mapping.unmap()?;
+    assert!(!scratch.is_checked_out());
+
+    if contents_survived {
+        Ok(())
+    } else {
+        Err(TopLevelError::TestAssertionFailure(
+            "Region contents didn't survive between mappings",
+        ))
+    }
+}
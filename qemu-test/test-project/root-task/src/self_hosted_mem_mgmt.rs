@@ -57,7 +57,7 @@ pub fn self_hosted_mem_mgmt(
 
         let child_root = retype(ut, slots)?;
         let child_vspace_slots: LocalCNodeSlots<U1024> = slots;
-        let child_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let child_vspace_ut: LocalCap<Untyped<U16>> = ut;
 
         let child_vspace = VSpace::new(
             child_root,
@@ -107,7 +107,7 @@ impl RetypeForSetup for ProcParams<role::Local> {
     type Output = ProcParams<role::Child>;
 }
 
-pub extern "C" fn sh_main(mut vspace: VSpace, params: ProcParams<role::Local>) {
+pub extern "C" fn sh_main(mut vspace: SelfHostedVSpace, params: ProcParams<role::Local>) {
     let ProcParams {
         value,
         child_slots,
@@ -124,7 +124,27 @@ pub extern "C" fn sh_main(mut vspace: VSpace, params: ProcParams<role::Local>) {
         *vaddr = 8;
         *vaddr
     };
+
+    // Memory of the child's own making, out of what the vspace was
+    // handed off with
+    let free_bytes = vspace.free_bytes();
+    let own_region = vspace.new_region::<U12>().expect("making a region failed");
+    let own_mapped = vspace
+        .map_region(own_region, CapRights::RW, arch::vm_attributes::DEFAULT)
+        .expect("mapping own region failed");
+    unsafe { *(own_mapped.vaddr() as *mut u8) = 9 };
+    let used_own_untyped = vspace.free_bytes() < free_bytes;
+
+    // Unmapped and mapped again, it's the same memory
+    let own_region = vspace
+        .reclaim_region(own_mapped)
+        .expect("unmapping own region failed");
+    let own_mapped = vspace
+        .map_region(own_region, CapRights::RW, arch::vm_attributes::DEFAULT)
+        .expect("mapping own region again failed");
+    let own_val = unsafe { *(own_mapped.vaddr() as *const u8) };
+
     outcome_sender
-        .blocking_send(&(params.value == 42 && val_at_ptr == 8))
+        .blocking_send(&(value == 42 && val_at_ptr == 8 && used_own_untyped && own_val == 9))
         .expect("Found value does not match expectations")
 }
@@ -0,0 +1,204 @@
+use super::TopLevelError;
+
+use typenum::*;
+
+use ferros::alloc::{smart_alloc, ut_buddy};
+use ferros::bootstrap::UserImage;
+use ferros::cap::*;
+use ferros::userland::*;
+use ferros::vspace::*;
+
+#[ferros_test::ferros_test]
+pub fn service_registry(
+    local_slots: LocalCNodeSlots<U32768>,
+    local_ut: LocalCap<Untyped<U20>>,
+    asid_pool: LocalCap<ASIDPool<U2>>,
+    local_mapped_region: MappedMemoryRegion<U18, shared_status::Exclusive>,
+    root_cnode: &LocalCap<LocalCNode>,
+    user_image: &UserImage<role::Local>,
+    tpa: &LocalCap<ThreadPriorityAuthority>,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let (broker_asid, asid_pool) = asid_pool.alloc();
+        let (service_asid, _asid_pool) = asid_pool.alloc();
+
+        let broker_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let broker_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut broker_vspace = VSpace::new(
+            retype(ut, slots)?,
+            broker_asid,
+            broker_vspace_slots.weaken(),
+            broker_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+
+        let service_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let service_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut service_vspace = VSpace::new(
+            retype(ut, slots)?,
+            service_asid,
+            service_vspace_slots.weaken(),
+            service_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+
+        let (broker_cnode, broker_slots) = retype_cnode::<U12>(ut, slots)?;
+        let (service_cnode, service_slots) = retype_cnode::<U12>(ut, slots)?;
+
+        // The service signals once it has registered
+        let registered: LocalCap<Notification> = retype(ut, slots)?;
+
+        smart_alloc! {|slots_b: broker_slots| {
+            let (registry_setup, broker) = registry(ut, &root_cnode, slots, slots_b)?;
+            // The slots the broker keeps endpoints in and receives them
+            // into, addressed through its reference to its own CNode
+            let (cnode_for_broker, broker_endpoint_slots): (_, ChildCap<CNodeSlotsData<U33, role::Child>>) =
+                broker_cnode.generate_self_reference(&root_cnode, slots_b)?;
+        }}
+
+        smart_alloc! {|slots_s: service_slots| {
+            let service_registry = registry_setup.create_client(slots_s)?;
+            let endpoint_ut: LocalCap<Untyped<<Endpoint as DirectRetype>::SizeBits>> = ut;
+            let endpoint_ut = endpoint_ut.move_to_slot(&root_cnode, slots_s)?;
+            let service_registered = registered.copy(&root_cnode, slots_s, CapRights::RWG)?;
+            let (_cnode_for_service, endpoint_slot): (_, ChildCap<CNodeSlotsData<U1, role::Child>>) =
+                service_cnode.generate_self_reference(&root_cnode, slots_s)?;
+        }}
+
+        let local_registry = registry_setup.create_client(slots)?;
+        let (broker_region, service_region) = local_mapped_region.split()?;
+
+        let mut broker_process = StandardProcess::new(
+            &mut broker_vspace,
+            broker_cnode,
+            broker_region,
+            root_cnode,
+            broker_proc as extern "C" fn(_) -> (),
+            BrokerParams::<role::Child> {
+                broker,
+                cnode: cnode_for_broker,
+                slots: broker_endpoint_slots,
+            },
+            ut,
+            ut,
+            slots,
+            tpa,
+            None, // fault
+        )?;
+        broker_process.start()?;
+
+        let mut service_process = StandardProcess::new(
+            &mut service_vspace,
+            service_cnode,
+            service_region,
+            root_cnode,
+            service_proc as extern "C" fn(_) -> (),
+            ServiceParams::<role::Child> {
+                registry: service_registry,
+                endpoint_ut,
+                endpoint_slot,
+                registered: service_registered,
+            },
+            ut,
+            ut,
+            slots,
+            tpa,
+            None, // fault
+        )?;
+        service_process.start()?;
+
+        registered.wait();
+
+        match local_registry.lookup::<u32, u32>("missing", slots) {
+            Err(RegistryError::NotFound) => (),
+            _ => {
+                return Err(TopLevelError::TestAssertionFailure(
+                    "Looking up an unregistered name should fail",
+                ))
+            }
+        }
+        match local_registry.lookup::<u64, u32>("increment", slots) {
+            Err(RegistryError::MessageSizeMismatch) => (),
+            _ => {
+                return Err(TopLevelError::TestAssertionFailure(
+                    "Looking up a service for the wrong message types should fail",
+                ))
+            }
+        }
+        let impostor: LocalCap<Endpoint> = retype(ut, slots)?;
+        match local_registry.register::<u32, u32>("increment", &impostor) {
+            Err(RegistryError::NameTaken) => (),
+            _ => {
+                return Err(TopLevelError::TestAssertionFailure(
+                    "Registering a taken name should fail",
+                ))
+            }
+        }
+
+        let increment: Caller<u32, u32, _> = local_registry.lookup("increment", slots)?;
+        let answer = increment.blocking_call(&41)?;
+    });
+
+    if answer == 42 {
+        Ok(())
+    } else {
+        Err(TopLevelError::TestAssertionFailure(
+            "The looked up service should have incremented the value",
+        ))
+    }
+}
+
+pub struct BrokerParams<Role: CNodeRole> {
+    pub broker: RegistryBroker<Role>,
+    pub cnode: Cap<CNode<Role>, Role>,
+    pub slots: Cap<CNodeSlotsData<U33, Role>, Role>,
+}
+
+impl RetypeForSetup for BrokerParams<role::Local> {
+    type Output = BrokerParams<role::Child>;
+}
+
+pub struct ServiceParams<Role: CNodeRole> {
+    pub registry: RegistryClient<Role>,
+    pub endpoint_ut: Cap<Untyped<<Endpoint as DirectRetype>::SizeBits>, Role>,
+    pub endpoint_slot: Cap<CNodeSlotsData<U1, Role>, Role>,
+    pub registered: Cap<Notification, Role>,
+}
+
+impl RetypeForSetup for ServiceParams<role::Local> {
+    type Output = ServiceParams<role::Child>;
+}
+
+pub extern "C" fn broker_proc(p: BrokerParams<role::Local>) {
+    let BrokerParams {
+        broker,
+        cnode,
+        slots,
+    } = p;
+    let (endpoint_slots, receive_slot) = slots.alloc();
+    broker
+        .serve(endpoint_slots, receive_slot, &cnode)
+        .expect("Registry broker failed");
+}
+
+pub extern "C" fn service_proc(p: ServiceParams<role::Local>) {
+    let ServiceParams {
+        registry,
+        endpoint_ut,
+        endpoint_slot,
+        registered,
+    } = p;
+    let responder = registry
+        .register_service::<u32, u32>("increment", endpoint_ut, endpoint_slot)
+        .expect("Could not register the service");
+    registered.signal();
+    responder
+        .reply_recv(|value| value + 1)
+        .expect("Could not set up a reply_recv");
+}
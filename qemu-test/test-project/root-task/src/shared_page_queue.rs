@@ -4,7 +4,7 @@ use ferros::alloc::{smart_alloc, ut_buddy};
 use ferros::bootstrap::UserImage;
 use ferros::cap::*;
 use ferros::userland::{
-    fault_or_message_channel, Consumer1, FaultOrMessage, Producer, QueueFullError, RetypeForSetup,
+    fault_or_message_channel, Consumer1, FaultOrMessage, Producer, RetypeForSetup, SendError,
     Sender, StandardProcess,
 };
 use ferros::vspace::*;
@@ -180,13 +180,14 @@ pub extern "C" fn producer_run(p: ProducerParams<role::Local>) {
     for i in 0..256 {
         match p.producer.send(Xenon { a: i }) {
             Ok(_) => (),
-            Err(QueueFullError(_x)) => {
+            Err(SendError::QueueFull(_x)) => {
                 // Rejected sending this value, let's yield and let the consumer catch up
                 // Note that we do not attempt to resend the rejected value
                 unsafe {
                     selfe_sys::seL4_Yield();
                 }
             }
+            Err(_) => panic!("The consumer should still be there"),
         }
     }
 }
@@ -0,0 +1,200 @@
+use core::marker::PhantomData;
+
+use typenum::*;
+
+use ferros::alloc::{smart_alloc, ut_buddy};
+use ferros::arch;
+use ferros::bootstrap::UserImage;
+use ferros::cap::*;
+use ferros::test_support::{
+    simulated_device_run, RecordedWrite, ScriptStatus, ScriptStep, SimulatedDeviceMonitor,
+    SimulatedDeviceParams,
+};
+use ferros::userland::{
+    fault_or_message_channel, CapRights, FaultOrMessage, RetypeForSetup, Sender, StandardProcess,
+};
+use ferros::vspace::*;
+
+use super::TopLevelError;
+
+const CONTROL: usize = 0x0;
+const CONTROL_ENABLE: u32 = 0x1;
+const STATUS: usize = 0x4;
+const STATUS_READY: u32 = 0x1;
+const DATA: usize = 0x8;
+
+static SCRIPT: [ScriptStep; 3] = [
+    ScriptStep::AwaitWrite {
+        offset: CONTROL,
+        value: CONTROL_ENABLE,
+    },
+    ScriptStep::Set {
+        offset: STATUS,
+        value: STATUS_READY,
+    },
+    ScriptStep::AwaitWrite {
+        offset: DATA,
+        value: 0xAB,
+    },
+];
+
+#[ferros_test::ferros_test]
+pub fn simulated_device(
+    local_slots: LocalCNodeSlots<U32768>,
+    local_ut: LocalCap<Untyped<U20>>,
+    asid_pool: LocalCap<ASIDPool<U2>>,
+    local_mapped_region: MappedMemoryRegion<U18, shared_status::Exclusive>,
+    root_cnode: &LocalCap<LocalCNode>,
+    user_image: &UserImage<role::Local>,
+    tpa: &LocalCap<ThreadPriorityAuthority>,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let (device_asid, asid_pool) = asid_pool.alloc();
+        let (driver_asid, _asid_pool) = asid_pool.alloc();
+
+        let device_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let device_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut device_vspace = VSpace::new(
+            retype(ut, slots)?,
+            device_asid,
+            device_vspace_slots.weaken(),
+            device_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+        let driver_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let driver_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut driver_vspace = VSpace::new(
+            retype(ut, slots)?,
+            driver_asid,
+            driver_vspace_slots.weaken(),
+            driver_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+
+        let (device_cnode, _device_slots) = retype_cnode::<U12>(ut, slots)?;
+        let (driver_cnode, driver_slots) = retype_cnode::<U12>(ut, slots)?;
+
+        let (driver_sender_slot, _driver_slots) = driver_slots.alloc();
+        let (driver_fault_source, outcome_sender, handler) =
+            fault_or_message_channel(&root_cnode, ut, slots, driver_sender_slot, slots)?;
+
+        let device_page: UnmappedMemoryRegion<arch::PageBits, _> =
+            UnmappedMemoryRegion::new(ut, slots)?;
+        let device_page = device_page.to_shared();
+        let device_region = device_vspace.map_shared_region(
+            &device_page,
+            CapRights::RW,
+            arch::vm_attributes::DEFAULT,
+            slots,
+            &root_cnode,
+        )?;
+        let driver_region = driver_vspace.map_shared_region_and_consume(
+            device_page,
+            CapRights::RW,
+            arch::vm_attributes::DEFAULT,
+        )?;
+
+        let device_params = SimulatedDeviceParams::<role::Child> {
+            region: device_region,
+            script: &SCRIPT,
+            max_polls: 10_000,
+            _role: PhantomData,
+        };
+        let driver_params = DriverParams::<role::Child> {
+            region: driver_region,
+            outcome_sender,
+        };
+
+        let (device_stack, driver_stack) = local_mapped_region.split()?;
+
+        let mut device_process = StandardProcess::new(
+            &mut device_vspace,
+            device_cnode,
+            device_stack,
+            root_cnode,
+            simulated_device_run as extern "C" fn(_) -> (),
+            device_params,
+            ut,
+            ut,
+            slots,
+            tpa,
+            None, // fault handler
+        )?;
+        device_process.start()?;
+
+        let mut driver_process = StandardProcess::new(
+            &mut driver_vspace,
+            driver_cnode,
+            driver_stack,
+            root_cnode,
+            driver_run as extern "C" fn(_) -> (),
+            driver_params,
+            ut,
+            ut,
+            slots,
+            tpa,
+            Some(driver_fault_source),
+        )?;
+        driver_process.start()?;
+    });
+
+    match handler.await_message()? {
+        FaultOrMessage::Message(true) => Ok(()),
+        _ => Err(TopLevelError::TestAssertionFailure(
+            "Driver should have seen the simulated device through its script",
+        )),
+    }
+}
+
+pub struct DriverParams<Role: CNodeRole> {
+    pub region: MappedMemoryRegion<arch::PageBits, shared_status::Shared>,
+    pub outcome_sender: Sender<bool, Role>,
+}
+
+impl RetypeForSetup for DriverParams<role::Local> {
+    type Output = DriverParams<role::Child>;
+}
+
+/// A driver for the scripted device: enable it, wait until it's ready,
+/// then hand it some data
+pub extern "C" fn driver_run(p: DriverParams<role::Local>) {
+    let DriverParams {
+        region,
+        outcome_sender,
+    } = p;
+    let register = |offset: usize| (region.vaddr() + offset) as *mut u32;
+
+    unsafe {
+        core::ptr::write_volatile(register(CONTROL), CONTROL_ENABLE);
+        while core::ptr::read_volatile(register(STATUS)) & STATUS_READY == 0 {
+            selfe_sys::seL4_Yield();
+        }
+        core::ptr::write_volatile(register(DATA), 0xAB);
+    }
+
+    let monitor = SimulatedDeviceMonitor::new(&region);
+    while monitor.status() == ScriptStatus::Running {
+        unsafe { selfe_sys::seL4_Yield() };
+    }
+    let expected_writes = [
+        RecordedWrite {
+            offset: CONTROL as u32,
+            value: CONTROL_ENABLE,
+        },
+        RecordedWrite {
+            offset: DATA as u32,
+            value: 0xAB,
+        },
+    ];
+    let passed = monitor.status() == ScriptStatus::Done
+        && monitor.writes().eq(expected_writes.iter().cloned());
+    outcome_sender
+        .blocking_send(&passed)
+        .expect("Failed to send test outcome");
+}
@@ -0,0 +1,83 @@
+use super::TopLevelError;
+
+use typenum::*;
+
+use ferros::alloc::ut_buddy::{weak_ut_buddy, WUTBuddy};
+use ferros::cap::*;
+
+fn endpoint(
+    wut: &mut WUTBuddy,
+    slot_allocator: &mut WeakSlotAllocator,
+    compactor: &mut CNodeSlotCompactor,
+) -> Result<LocalCap<Endpoint>, TopLevelError> {
+    let ut = wut.alloc_strong::<U4>(slot_allocator.slots_mut())?;
+    Ok(retype(ut, compactor.alloc_slot()?)?)
+}
+
+#[ferros_test::ferros_test]
+pub fn slot_compactor(
+    local_ut: LocalCap<Untyped<U10>>,
+    mut slot_allocator: WeakSlotAllocator,
+    root_cnode: &LocalCap<LocalCNode>,
+) -> Result<(), TopLevelError> {
+    let mut wut = weak_ut_buddy(local_ut.weaken());
+    let mut compactor = CNodeSlotCompactor::new(slot_allocator.alloc_exact(8)?)?;
+
+    let a = endpoint(&mut wut, &mut slot_allocator, &mut compactor)?;
+    let b = endpoint(&mut wut, &mut slot_allocator, &mut compactor)?;
+    let c = endpoint(&mut wut, &mut slot_allocator, &mut compactor)?;
+    let mut d = endpoint(&mut wut, &mut slot_allocator, &mut compactor)?;
+    let a_cptr = a.cptr;
+
+    // Holes under the last cap, and a slot handed back out in one of them
+    let b_cptr = b.cptr;
+    b.delete(root_cnode)?;
+    compactor.release_slot(b_cptr)?;
+    let c_cptr = c.cptr;
+    c.delete(root_cnode)?;
+    compactor.release_slot(c_cptr)?;
+    assert_eq!(
+        compactor.release_slot(c_cptr),
+        Err(SlotCompactorError::SlotNotInUse(c_cptr))
+    );
+    let mut e = endpoint(&mut wut, &mut slot_allocator, &mut compactor)?;
+    assert_eq!(e.cptr, b_cptr);
+    assert_eq!(compactor.in_use(), 3);
+    assert_eq!(compactor.largest_free_run(), 4);
+
+    let mut moves = 0;
+    let freed = unsafe {
+        compactor.compact(root_cnode, |old_cptr, new_cptr| {
+            moves += 1;
+            if old_cptr == d.cptr {
+                d.cptr = new_cptr;
+            } else if old_cptr == e.cptr {
+                e.cptr = new_cptr;
+            }
+        })?
+    };
+    assert_eq!(moves, 1);
+    assert_eq!((a.cptr, e.cptr, d.cptr), (a_cptr, b_cptr, c_cptr));
+    assert_eq!(compactor.size(), 3);
+    assert_eq!(compactor.largest_free_run(), 0);
+
+    // The freed block goes back to the compactor, and the moved cap is
+    // where it was said to be, so it can be moved on into the block
+    let freed = WeakSlotAllocator::new(freed);
+    assert_eq!(freed.remaining(), 5);
+    assert_eq!(
+        compactor.extend(slot_allocator.alloc_exact(1)?),
+        Err(SlotCompactorError::NotContiguous)
+    );
+    compactor.extend(freed.into_slots())?;
+    assert_eq!(compactor.size(), 8);
+
+    let slot = compactor.alloc_slot()?;
+    let d_cptr = d.cptr;
+    let d = d.move_to_slot(root_cnode, slot)?;
+    compactor.release_slot(d_cptr)?;
+    assert_eq!(d.cptr, c_cptr + 1);
+    assert_eq!(compactor.in_use(), 3);
+
+    Ok(())
+}
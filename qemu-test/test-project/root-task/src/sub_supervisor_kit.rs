@@ -0,0 +1,166 @@
+use typenum::*;
+
+use ferros::alloc::ut_buddy::weak_ut_buddy;
+use ferros::alloc::{smart_alloc, ut_buddy};
+use ferros::bootstrap::UserImage;
+use ferros::cap::*;
+use ferros::userland::{
+    fault_or_message_channel, FaultOrMessage, RetypeForSetup, Sender, StandardProcess,
+    SubSupervisorKit,
+};
+use ferros::vspace::{
+    shared_status, MappedMemoryRegion, ProcessCodeImageConfig, UnmappedMemoryRegion, VSpace,
+};
+
+use super::TopLevelError;
+
+#[ferros_test::ferros_test]
+pub fn sub_supervisor_kit(
+    local_slots: LocalCNodeSlots<U32768>,
+    local_ut: LocalCap<Untyped<U23>>,
+    asid_pool: LocalCap<ASIDPool<U4>>,
+    local_mapped_region: MappedMemoryRegion<U17, shared_status::Exclusive>,
+    cnode: &LocalCap<LocalCNode>,
+    user_image: &UserImage<role::Local>,
+    tpa: &LocalCap<ThreadPriorityAuthority>,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let (child_cnode, child_slots) = retype_cnode::<U14>(ut, slots)?;
+
+        let (child_asid, asid_pool) = asid_pool.alloc();
+        let child_root = retype(ut, slots)?;
+        let child_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        // NOTE: this needs to be big enough to map in entire root task. That
+        // could grow if you add more tests elsewhere.
+        let child_vspace_ut: LocalCap<Untyped<U16>> = ut;
+
+        let mut child_vspace = VSpace::new(
+            child_root,
+            child_asid,
+            child_vspace_slots.weaken(),
+            child_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            cnode,
+        )?;
+
+        smart_alloc! {|slots_c: child_slots| {
+            let (fault_source, outcome_sender, handler) = fault_or_message_channel(
+                &cnode,
+                ut,
+                slots,
+                slots_c,
+                slots,
+            )?;
+            let kit_slots: ChildCNodeSlots<U4096> = slots_c;
+        }}
+
+        let kit_ut: LocalCap<Untyped<U20>> = ut;
+        let scratch: UnmappedMemoryRegion<U17, shared_status::Exclusive> =
+            UnmappedMemoryRegion::new(ut, slots)?;
+        let mut asid_pool = asid_pool.weaken();
+        let kit = SubSupervisorKit::new(
+            &child_cnode,
+            kit_slots.weaken(),
+            weak_ut_buddy(kit_ut.weaken()),
+            &mut asid_pool,
+            2,
+            scratch.weaken(),
+            &mut child_vspace,
+            user_image,
+            tpa,
+            cnode,
+        )?;
+
+        let params = ChildParams {
+            kit,
+            outcome_sender,
+        };
+
+        let mut child_process = StandardProcess::new(
+            &mut child_vspace,
+            child_cnode,
+            local_mapped_region,
+            &cnode,
+            child_main as extern "C" fn(_) -> (),
+            params,
+            ut,
+            ut,
+            slots,
+            tpa,
+            Some(fault_source),
+        )?;
+    });
+
+    child_process.start()?;
+
+    match handler.await_message()? {
+        FaultOrMessage::Message(true) => Ok(()),
+        _ => Err(TopLevelError::TestAssertionFailure(
+            "Grandkid process should have reported success",
+        )),
+    }
+}
+
+pub struct ChildParams<Role: CNodeRole> {
+    kit: SubSupervisorKit<Role>,
+    outcome_sender: Sender<bool, Role>,
+}
+
+impl RetypeForSetup for ChildParams<role::Local> {
+    type Output = ChildParams<role::Child>;
+}
+
+pub extern "C" fn child_main(params: ChildParams<role::Local>) {
+    child_run(params).expect("Error in child process");
+}
+
+/// Spawn a grandkid with nothing but what came in the kit
+fn child_run(params: ChildParams<role::Local>) -> Result<(), TopLevelError> {
+    let ChildParams {
+        mut kit,
+        outcome_sender,
+    } = params;
+
+    let mut grandkid_vspace = kit.vspace(1024, 15)?;
+    let (grandkid_cnode, grandkid_slots) =
+        retype_cnode::<U8>(kit.alloc_untyped()?, kit.alloc_slots()?)?;
+    let (outcome_sender_slot, _grandkid_slots) = grandkid_slots.alloc();
+    let params = GrandkidParams {
+        outcome_sender: outcome_sender.copy(&kit.cnode, outcome_sender_slot)?,
+    };
+
+    let mut grandkid_process = StandardProcess::new(
+        &mut grandkid_vspace,
+        grandkid_cnode,
+        kit.take_scratch::<U17>()?,
+        &kit.cnode,
+        grandkid_main as extern "C" fn(_) -> (),
+        params,
+        kit.alloc_untyped()?,
+        kit.alloc_untyped()?,
+        kit.alloc_slots()?,
+        &kit.thread_priority_authority,
+        None,
+    )?;
+    grandkid_process.start()?;
+
+    Ok(())
+}
+
+pub struct GrandkidParams<Role: CNodeRole> {
+    pub outcome_sender: Sender<bool, Role>,
+}
+
+impl RetypeForSetup for GrandkidParams<role::Local> {
+    type Output = GrandkidParams<role::Child>;
+}
+
+pub extern "C" fn grandkid_main(params: GrandkidParams<role::Local>) {
+    params
+        .outcome_sender
+        .blocking_send(&true)
+        .expect("failed to send test outcome");
+}
@@ -0,0 +1,92 @@
+use super::TopLevelError;
+
+use ferros::alloc::{smart_alloc, ut_buddy};
+use typenum::*;
+
+use ferros::arch::fault::Fault;
+use ferros::arch::PageBytes;
+use ferros::cap::*;
+use ferros::userland::{
+    fault_or_message_channel, FaultAccess, FaultOrMessage, RetypeForSetup, Sender, Thread,
+    VMFaultCause, VMFaultStatus,
+};
+use ferros::vspace::*;
+
+#[ferros_test::ferros_test]
+pub fn thread_stack_guard(
+    local_slots: LocalCNodeSlots<U32768>,
+    local_ut: LocalCap<Untyped<U20>>,
+    stack_mapped_region: MappedMemoryRegion<U14, shared_status::Exclusive>,
+    ipc_buffer_region: MappedMemoryRegion<U12, shared_status::Exclusive>,
+    root_cnode: &LocalCap<LocalCNode>,
+    tpa: &LocalCap<ThreadPriorityAuthority>,
+    vspace_paging_root: &LocalCap<ferros::arch::PagingRoot>,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+    let guard_page = stack_mapped_region.vaddr();
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let (child_cnode, child_slots) = retype_cnode::<U12>(ut, slots)?;
+        let (child_fault_source_slot, _child_slots) = child_slots.alloc();
+        let (fault_source, outcome_sender, handler) =
+            fault_or_message_channel(&root_cnode, ut, slots, child_fault_source_slot, slots)?;
+        let params = ProcParams {
+            guard_page,
+            outcome_sender,
+        };
+
+        let child_thread = Thread::new(
+            vspace_paging_root,
+            child_cnode,
+            stack_mapped_region,
+            proc_main,
+            params,
+            ipc_buffer_region,
+            ut,
+            slots,
+            tpa,
+            Some(fault_source),
+        )?;
+    });
+
+    child_thread.start()?;
+
+    match handler.await_message()? {
+        FaultOrMessage::Fault(Fault::VMFault(fault)) => match fault.status() {
+            VMFaultStatus {
+                cause: VMFaultCause::Translation { .. },
+                access: FaultAccess::Write,
+            } if fault.address >= guard_page && fault.address < guard_page + PageBytes::USIZE => {
+                Ok(())
+            }
+            _ => Err(TopLevelError::TestAssertionFailure(
+                "Thread faulted somewhere other than its guard page",
+            )),
+        },
+        _ => Err(TopLevelError::TestAssertionFailure(
+            "Thread should have faulted on its guard page",
+        )),
+    }
+}
+
+pub struct ProcParams<Role: CNodeRole> {
+    pub guard_page: usize,
+    pub outcome_sender: Sender<bool, Role>,
+}
+
+impl RetypeForSetup for ProcParams<role::Local> {
+    type Output = ProcParams<role::Child>;
+}
+
+pub extern "C" fn proc_main(params: ProcParams<role::Local>) {
+    // Where an overflowing stack would go next
+    unsafe {
+        let below_stack = (params.guard_page + PageBytes::USIZE) as *mut usize;
+        core::ptr::write_volatile(below_stack.offset(-1), 42);
+    }
+
+    params
+        .outcome_sender
+        .blocking_send(&true)
+        .expect("Failed to report that the guard page was missing")
+}
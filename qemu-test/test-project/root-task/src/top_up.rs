@@ -0,0 +1,145 @@
+use typenum::*;
+
+use ferros::alloc::ut_buddy::{weak_ut_buddy, UTBuddyError};
+use ferros::alloc::{smart_alloc, ut_buddy};
+use ferros::bootstrap::UserImage;
+use ferros::cap::*;
+use ferros::userland::{
+    fault_or_message_channel, top_up_channel, FaultOrMessage, RetypeForSetup, Sender,
+    StandardProcess, TopUp, TopUpReceiver,
+};
+use ferros::vspace::*;
+
+use super::TopLevelError;
+
+#[ferros_test::ferros_test]
+pub fn top_up(
+    local_slots: LocalCNodeSlots<U32768>,
+    local_ut: LocalCap<Untyped<U20>>,
+    asid_pool: LocalCap<ASIDPool<U1>>,
+    local_mapped_region: MappedMemoryRegion<U17, shared_status::Exclusive>,
+    root_cnode: &LocalCap<LocalCNode>,
+    user_image: &UserImage<role::Local>,
+    tpa: &LocalCap<ThreadPriorityAuthority>,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let (child_asid, _asid_pool) = asid_pool.alloc();
+        let (child_cnode, child_slots) = retype_cnode::<U12>(ut, slots)?;
+
+        let ut5: LocalCap<Untyped<U5>> = ut;
+        let ut12: LocalCap<Untyped<U12>> = ut;
+
+        // The child's own two slots, then the reserve straight after them
+        let (self_reference_slots, child_slots) = child_slots.alloc::<U3>();
+        let (_cnode_for_child, slots_for_child) =
+            child_cnode.generate_self_reference::<U2>(&root_cnode, self_reference_slots)?;
+        let (reserve, child_slots) = child_slots.alloc::<U16>();
+
+        let (ut_slot, child_slots) = child_slots.alloc();
+        let child_ut5 = ut5.move_to_slot(&root_cnode, ut_slot)?;
+        let (receiver_slot, child_slots) = child_slots.alloc();
+        let (mut donor, receiver) =
+            top_up_channel(ut, &root_cnode, slots, receiver_slot, reserve.weaken())?;
+        let (sender_slot, _child_slots) = child_slots.alloc();
+        let (fault_source, outcome_sender, handler) =
+            fault_or_message_channel(&root_cnode, ut, slots, sender_slot, slots)?;
+
+        let params = TopUpParams {
+            my_slots: slots_for_child.weaken(),
+            my_ut: child_ut5,
+            receiver,
+            outcome_sender,
+        };
+
+        let child_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let child_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut child_vspace = VSpace::new(
+            retype(ut, slots)?,
+            child_asid,
+            child_vspace_slots.weaken(),
+            child_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+
+        let mut child_process = StandardProcess::new(
+            &mut child_vspace,
+            child_cnode,
+            local_mapped_region,
+            root_cnode,
+            proc_main as extern "C" fn(_) -> (),
+            params,
+            ut,
+            ut,
+            slots,
+            tpa,
+            Some(fault_source),
+        )?;
+    });
+
+    child_process.start()?;
+
+    donor.donate_slots(8)?;
+    donor.donate_untyped(ut12.weaken(), &root_cnode)?;
+    if donor.reserved_slots() != 7 {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Donations should have come out of the reserve",
+        ));
+    }
+
+    match handler.await_message()? {
+        FaultOrMessage::Message(true) => Ok(()),
+        _ => Err(TopLevelError::TestAssertionFailure(
+            "Child should have allocated from its top-up",
+        )),
+    }
+}
+
+pub struct TopUpParams<Role: CNodeRole> {
+    pub my_slots: Cap<WCNodeSlotsData<Role>, Role>,
+    pub my_ut: Cap<Untyped<U5>, Role>,
+    pub receiver: TopUpReceiver<Role>,
+    pub outcome_sender: Sender<bool, Role>,
+}
+
+impl RetypeForSetup for TopUpParams<role::Local> {
+    type Output = TopUpParams<role::Child>;
+}
+
+pub extern "C" fn proc_main(params: TopUpParams<role::Local>) {
+    let TopUpParams {
+        my_slots,
+        my_ut,
+        receiver,
+        outcome_sender,
+    } = params;
+    let mut slots = my_slots;
+    let mut ut_buddy = weak_ut_buddy(my_ut.weaken());
+
+    // Nothing big enough to start with
+    let too_small = match ut_buddy.alloc(&mut slots, 10) {
+        Err(UTBuddyError::CannotAllocateRequestedSize(_)) => true,
+        _ => false,
+    };
+
+    let slots_top_up = receiver.receive(&mut slots, &mut ut_buddy);
+    let untyped_top_up = receiver.receive(&mut slots, &mut ut_buddy);
+    let topped_up = match (slots_top_up, untyped_top_up) {
+        (Ok(TopUp::Slots { size: 8, .. }), Ok(TopUp::Untyped { size_bits: 12, .. })) => true,
+        _ => false,
+    };
+
+    // Splitting down to 10 bits takes four slots, more than the child
+    // had of its own
+    let allocated = match ut_buddy.alloc(&mut slots, 10) {
+        Ok(ut) => ut.retype::<Endpoint>(&mut slots).is_ok(),
+        Err(_) => false,
+    };
+
+    outcome_sender
+        .blocking_send(&(too_small && topped_up && allocated))
+        .expect("Failed to send test outcome");
+}
@@ -0,0 +1,195 @@
+use super::TopLevelError;
+
+use typenum::*;
+
+use ferros::alloc::{smart_alloc, ut_buddy};
+use ferros::bootstrap::UserImage;
+use ferros::cap::{
+    retype, retype_cnode, role, ASIDPool, Badge, CNodeRole, Cap, LocalCNode, LocalCNodeSlots,
+    LocalCap, Notification, ThreadPriorityAuthority, Untyped,
+};
+use ferros::userland::*;
+use ferros::vspace::*;
+
+type U33768 = op!(U32768 + U1000);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Refusal {
+    Odd,
+}
+
+#[ferros_test::ferros_test]
+pub fn two_phase_commit(
+    local_slots: LocalCNodeSlots<U33768>,
+    local_ut: LocalCap<Untyped<U20>>,
+    asid_pool: LocalCap<ASIDPool<U2>>,
+    local_mapped_region: MappedMemoryRegion<U18, shared_status::Exclusive>,
+    root_cnode: &LocalCap<LocalCNode>,
+    user_image: &UserImage<role::Local>,
+    tpa: &LocalCap<ThreadPriorityAuthority>,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let (lenient_asid, asid_pool) = asid_pool.alloc();
+        let (strict_asid, _asid_pool) = asid_pool.alloc();
+
+        let lenient_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let lenient_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut lenient_vspace = VSpace::new(
+            retype(ut, slots)?,
+            lenient_asid,
+            lenient_vspace_slots.weaken(),
+            lenient_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+
+        let strict_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let strict_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut strict_vspace = VSpace::new(
+            retype(ut, slots)?,
+            strict_asid,
+            strict_vspace_slots.weaken(),
+            strict_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+
+        let (lenient_cnode, lenient_slots) = retype_cnode::<U12>(ut, slots)?;
+        let (strict_cnode, strict_slots) = retype_cnode::<U12>(ut, slots)?;
+
+        let (slots_l, lenient_slots) = lenient_slots.alloc();
+        let (lenient_setup, lenient_responder) = call_channel(ut, &root_cnode, slots, slots_l)?;
+        let lenient = lenient_setup.create_caller(slots)?;
+
+        let (slots_s, strict_slots) = strict_slots.alloc();
+        let (strict_setup, strict_responder) = call_channel(ut, &root_cnode, slots, slots_s)?;
+        let strict = strict_setup.create_caller(slots)?;
+
+        // Each participant signals, with a badge of its own, when it finds
+        // itself still holding the first change after the second fails
+        let notification: LocalCap<Notification> = retype(ut, slots)?;
+        let (slots_l, _lenient_slots) = lenient_slots.alloc();
+        let lenient_notification =
+            notification.mint(root_cnode, slots_l, CapRights::RWG, Badge::from(0b01))?;
+        let (slots_s, _strict_slots) = strict_slots.alloc();
+        let strict_notification =
+            notification.mint(root_cnode, slots_s, CapRights::RWG, Badge::from(0b10))?;
+
+        let (lenient_region, strict_region) = local_mapped_region.split()?;
+
+        let mut lenient_process = StandardProcess::new(
+            &mut lenient_vspace,
+            lenient_cnode,
+            lenient_region,
+            root_cnode,
+            participant_proc as extern "C" fn(_) -> (),
+            ParticipantParams::<role::Child> {
+                responder: lenient_responder,
+                notification: lenient_notification,
+                refuse_odd: false,
+            },
+            ut,
+            ut,
+            slots,
+            tpa,
+            None, // fault
+        )?;
+        lenient_process.start()?;
+
+        let mut strict_process = StandardProcess::new(
+            &mut strict_vspace,
+            strict_cnode,
+            strict_region,
+            root_cnode,
+            participant_proc as extern "C" fn(_) -> (),
+            ParticipantParams::<role::Child> {
+                responder: strict_responder,
+                notification: strict_notification,
+                refuse_odd: true,
+            },
+            ut,
+            ut,
+            slots,
+            tpa,
+            None, // fault
+        )?;
+        strict_process.start()?;
+    });
+
+    let mut coordinator = Coordinator::new();
+    let participants = [&lenient, &strict];
+
+    let committed = coordinator.run(&participants, 2).is_ok();
+    let refused = match coordinator.run(&participants, 3) {
+        Err(TwoPhaseError::Refused {
+            participant: 1,
+            reason: Refusal::Odd,
+        }) => true,
+        _ => false,
+    };
+    let both_kept_first_change = notification.poll().map(usize::from) == Some(0b11);
+
+    if committed && refused && both_kept_first_change {
+        Ok(())
+    } else {
+        Err(TopLevelError::TestAssertionFailure(
+            "the first change should have been committed, the second aborted",
+        ))
+    }
+}
+
+#[derive(Debug)]
+pub struct ParticipantParams<Role: CNodeRole> {
+    pub responder: Responder<TwoPhaseRequest<u32>, TwoPhaseResponse<Refusal>, Role>,
+    pub notification: Cap<Notification, Role>,
+    pub refuse_odd: bool,
+}
+
+impl RetypeForSetup for ParticipantParams<role::Local> {
+    type Output = ParticipantParams<role::Child>;
+}
+
+struct Setting {
+    applied: u32,
+    refuse_odd: bool,
+    notification: Cap<Notification, role::Local>,
+}
+
+impl TwoPhaseParticipant<u32, Refusal> for Setting {
+    fn prepare(&mut self, change: &u32) -> Result<(), Refusal> {
+        if self.refuse_odd && change % 2 == 1 {
+            if self.applied == 2 {
+                self.notification.signal();
+            }
+            return Err(Refusal::Odd);
+        }
+        Ok(())
+    }
+
+    fn commit(&mut self, change: u32) {
+        self.applied = change;
+    }
+
+    fn abort(&mut self, _change: u32) {
+        if self.applied == 2 {
+            self.notification.signal();
+        }
+    }
+}
+
+pub extern "C" fn participant_proc(p: ParticipantParams<role::Local>) {
+    let mut setting = Setting {
+        applied: 0,
+        refuse_odd: p.refuse_odd,
+        notification: p.notification,
+    };
+    p.responder
+        .reply_recv_with_state(ParticipantLog::new(), move |req, mut log| {
+            (log.handle(&mut setting, req), log)
+        })
+        .expect("Could not set up a reply_recv");
+}
@@ -0,0 +1,67 @@
+use ferros::alloc::{smart_alloc, ut_buddy};
+use typenum::*;
+
+use ferros::arch;
+use ferros::bootstrap::UserImage;
+use ferros::cap::*;
+use ferros::userland::CapRights;
+use ferros::vspace::*;
+
+use super::TopLevelError;
+
+#[ferros_test::ferros_test]
+pub fn unmap_and_reuse_region(
+    local_slots: LocalCNodeSlots<U2048>,
+    local_ut: LocalCap<Untyped<U20>>,
+    asid_pool: LocalCap<ASIDPool<U1>>,
+    root_cnode: &LocalCap<LocalCNode>,
+    user_image: &UserImage<role::Local>,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let (child_asid, _asid_pool) = asid_pool.alloc();
+        let child_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let child_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut child_vspace = VSpace::new(
+            retype(ut, slots)?,
+            child_asid,
+            child_vspace_slots.weaken(),
+            child_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+
+        let region_ut: LocalCap<Untyped<U14>> = ut;
+        let region_slots: LocalCNodeSlots<U4> = slots;
+        let next_region_ut: LocalCap<Untyped<U14>> = ut;
+    });
+
+    let region = UnmappedMemoryRegion::new(region_ut, region_slots)?;
+    let mapped = child_vspace.map_region(region, CapRights::RW, arch::vm_attributes::DEFAULT)?;
+    let first_vaddr = mapped.vaddr();
+
+    // Unmapping gives the addresses back, so the region lands where it
+    // was before when it's mapped again
+    let region = mapped.unmap(&mut child_vspace)?;
+    let mapped = child_vspace.map_region(region, CapRights::RW, arch::vm_attributes::DEFAULT)?;
+    if mapped.vaddr() != first_vaddr {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Remapped region was not given its reclaimed addresses",
+        ));
+    }
+
+    // Once deleted, the region's slots can hold another region's pages
+    let freed_slots = mapped.unmap(&mut child_vspace)?.delete(root_cnode)?;
+    let next_region = UnmappedMemoryRegion::new(next_region_ut, freed_slots)?;
+    let next_mapped =
+        child_vspace.map_region(next_region, CapRights::RW, arch::vm_attributes::DEFAULT)?;
+    if next_mapped.vaddr() != first_vaddr {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Region in reused slots was not given the reclaimed addresses",
+        ));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,58 @@
+use super::TopLevelError;
+
+use typenum::*;
+
+use ferros::cap::*;
+
+#[ferros_test::ferros_test]
+pub fn weak_asid_pool(
+    mut asid_pool: WeakASIDPool,
+    local_slots: LocalCNodeSlots<U2>,
+    root_cnode: &LocalCap<LocalCNode>,
+) -> Result<(), TopLevelError> {
+    let total = asid_pool.available();
+
+    let _asid = asid_pool.alloc()?;
+    assert_eq!(asid_pool.available(), total - 1);
+
+    // Hand a subsystem a few of its own
+    let mut sub_pool = asid_pool.split(4)?;
+    assert_eq!(asid_pool.available(), total - 5);
+    assert_eq!(sub_pool.drain().count(), 4);
+    assert_eq!(sub_pool.available(), 0);
+    assert_eq!(
+        sub_pool.alloc().map(|_| ()),
+        Err(ASIDPoolError::NotEnoughASIDs {
+            requested: 1,
+            available: 0,
+        })
+    );
+
+    let strong_pool: LocalCap<ASIDPool<U2>> = asid_pool.split_strong()?;
+    let (_asid, _strong_pool) = strong_pool.alloc();
+    assert_eq!(asid_pool.available(), total - 7);
+
+    // Sub-pools copied into a slot of their own, as for handing to a
+    // child that supervises processes of its own
+    let (weak_slot, local_slots) = local_slots.alloc();
+    let (strong_slot, _local_slots) = local_slots.alloc();
+    let mut copied_pool: LocalCap<WASIDPool> = asid_pool.split_into(3, weak_slot, root_cnode)?;
+    assert_eq!(asid_pool.available(), total - 10);
+    assert_eq!(copied_pool.drain().count(), 3);
+    let copied_strong_pool: LocalCap<ASIDPool<U1>> =
+        asid_pool.split_strong_into(strong_slot, root_cnode)?;
+    let (_asid, _copied_strong_pool) = copied_strong_pool.alloc();
+    assert_eq!(asid_pool.available(), total - 11);
+
+    let remaining = asid_pool.available();
+    assert_eq!(
+        asid_pool.split(remaining + 1).map(|_| ()),
+        Err(ASIDPoolError::NotEnoughASIDs {
+            requested: remaining + 1,
+            available: remaining,
+        })
+    );
+    assert_eq!(asid_pool.drain().count(), remaining);
+
+    Ok(())
+}
@@ -0,0 +1,46 @@
+use super::TopLevelError;
+
+use typenum::*;
+
+use ferros::alloc::ut_buddy::weak_ut_buddy;
+use ferros::cap::*;
+
+#[ferros_test::ferros_test]
+pub fn weak_slot_allocator(
+    local_ut: LocalCap<Untyped<U13>>,
+    mut slot_allocator: WeakSlotAllocator,
+) -> Result<(), TopLevelError> {
+    let total = slot_allocator.remaining();
+    let mut wut = weak_ut_buddy(local_ut.weaken());
+
+    // Hand a helper a pool of its own
+    let mut child_slots = slot_allocator.split(8)?;
+    assert_eq!(slot_allocator.remaining(), total - 8);
+    assert_eq!(slot_allocator.allocated(), 8);
+
+    let weak_12 = wut.alloc(child_slots.slots_mut(), 12)?;
+    let _ = weak_12.retype::<Page<page_state::Unmapped>>(child_slots.slots_mut())?;
+    assert!(child_slots.allocated() > 0);
+
+    // Asking for too much leaves the pool as it was
+    let remaining = child_slots.remaining();
+    assert_eq!(
+        child_slots.alloc_exact(remaining + 1).map(|_| ()),
+        Err(SlotAllocError {
+            requested: remaining + 1,
+            available: remaining,
+        })
+    );
+    assert_eq!(child_slots.remaining(), remaining);
+
+    let exact = WeakSlotAllocator::new(child_slots.alloc_exact(remaining)?);
+    assert_eq!(exact.remaining(), remaining);
+    assert_eq!(child_slots.remaining(), 0);
+
+    // Strong slots still come out typed
+    let strong_slot = slot_allocator.alloc_slot()?;
+    let ut12 = wut.alloc_strong::<U12>(slot_allocator.slots_mut())?;
+    let _ = ut12.retype::<Page<page_state::Unmapped>, role::Local>(strong_slot)?;
+
+    Ok(())
+}
@@ -2,7 +2,7 @@ use super::TopLevelError;
 
 use typenum::*;
 
-use ferros::alloc::ut_buddy::weak_ut_buddy;
+use ferros::alloc::ut_buddy::{weak_ut_buddy, MAX_TRACKED_SPLITS};
 use ferros::cap::*;
 
 #[ferros_test::ferros_test]
@@ -27,3 +27,26 @@ pub fn wutbuddy(
     let _ = ut12.retype::<Page<page_state::Unmapped>, role::Local>(strong_slot)?;
     Ok(())
 }
+
+#[ferros_test::ferros_test]
+pub fn wutbuddy_untracked_splits(
+    local_slots: LocalCNodeSlots<U512>,
+    local_ut: LocalCap<Untyped<U20>>,
+) -> Result<(), TopLevelError> {
+    let mut wut = weak_ut_buddy(local_ut.weaken());
+    let mut weak_slots = local_slots.weaken();
+
+    // A pool that never frees anything may split more times than it can
+    // remember for coalescing
+    let mut last = None;
+    for _ in 0..2 * MAX_TRACKED_SPLITS {
+        last = Some(wut.alloc(&mut weak_slots, 12)?);
+    }
+
+    // The last untyped allocated is still usable
+    let last = last.ok_or(TopLevelError::TestAssertionFailure(
+        "Should have allocated untypeds",
+    ))?;
+    let _ = last.retype::<Page<page_state::Unmapped>>(&mut weak_slots)?;
+    Ok(())
+}
@@ -0,0 +1,136 @@
+use super::TopLevelError;
+
+use ferros::alloc::{smart_alloc, ut_buddy};
+use typenum::*;
+
+use ferros::bootstrap::UserImage;
+use ferros::cap::*;
+use ferros::vspace::*;
+
+#[cfg(target_arch = "arm")]
+const MACHINE: u64 = 40;
+#[cfg(target_arch = "aarch64")]
+const MACHINE: u64 = 183;
+#[cfg(target_arch = "x86_64")]
+const MACHINE: u64 = 62;
+
+const IS_64_BIT: bool = cfg!(target_pointer_width = "64");
+const WORD_BYTES: usize = core::mem::size_of::<usize>();
+const HEADER_BYTES: u64 = if IS_64_BIT { 64 } else { 52 };
+const PROGRAM_HEADER_BYTES: u64 = if IS_64_BIT { 56 } else { 32 };
+
+const PT_LOAD: u64 = 1;
+const PF_X: u64 = 1;
+const PF_W: u64 = 2;
+const PF_R: u64 = 4;
+
+/// Aligned for the header to be read in place
+#[repr(C, align(8))]
+struct ElfImage([u8; 512]);
+
+struct ElfWriter {
+    image: ElfImage,
+    at: usize,
+}
+
+impl ElfWriter {
+    fn put(&mut self, value: u64, size: usize) {
+        self.image.0[self.at..self.at + size].copy_from_slice(&value.to_le_bytes()[..size]);
+        self.at += size;
+    }
+
+    fn word(&mut self, value: u64) {
+        self.put(value, WORD_BYTES)
+    }
+
+    fn program_header(&mut self, flags: u64, offset: u64, vaddr: u64, size: u64) {
+        self.put(PT_LOAD, 4);
+        if IS_64_BIT {
+            self.put(flags, 4);
+        }
+        self.word(offset);
+        self.word(vaddr); // virtual address
+        self.word(vaddr); // physical address
+        self.word(size); // file size
+        self.word(size); // memory size
+        if !IS_64_BIT {
+            self.put(flags, 4);
+        }
+        self.word(0x1000); // alignment
+    }
+}
+
+/// An image for the machine under test whose data segment starts in the
+/// last page of its code segment, as a linker packing segments together
+/// would lay them out
+fn shared_page_image() -> ElfImage {
+    let mut elf = ElfWriter {
+        image: ElfImage([0; 512]),
+        at: 0,
+    };
+    elf.put(0x464c_457f, 4); // "\x7fELF"
+    elf.put(if IS_64_BIT { 2 } else { 1 }, 1); // class
+    elf.put(1, 1); // little-endian
+    elf.put(1, 1); // version
+    elf.at = 16;
+    elf.put(2, 2); // executable
+    elf.put(MACHINE, 2);
+    elf.put(1, 4); // version
+    elf.word(0x10000); // entry point
+    elf.word(HEADER_BYTES); // program header offset
+    elf.word(0); // section header offset
+    elf.put(0, 4); // flags
+    elf.put(HEADER_BYTES, 2);
+    elf.put(PROGRAM_HEADER_BYTES, 2);
+    elf.put(2, 2); // program header count
+    elf.put(0, 2); // section header size
+    elf.put(0, 2); // section header count
+    elf.put(0, 2); // section name table index
+
+    elf.program_header(PF_R | PF_X, 0, 0x10000, 0x100);
+    elf.program_header(PF_R | PF_W, 0x100, 0x10100, 0x100);
+    elf.image
+}
+
+#[ferros_test::ferros_test]
+pub fn wx_shared_page(
+    local_slots: LocalCNodeSlots<U32768>,
+    local_ut: LocalCap<Untyped<U20>>,
+    asid_pool: LocalCap<ASIDPool<U1>>,
+    root_cnode: &LocalCap<LocalCNode>,
+    user_image: &UserImage<role::Local>,
+    mut local_vspace_scratch: &mut ScratchRegion,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+    let image = shared_page_image();
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let child_root = retype(ut, slots)?;
+        let child_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let child_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let (child_asid, _asid_pool) = asid_pool.alloc();
+
+        let page_slots: LocalCNodeSlots<U1024> = slots;
+        let writable_mem: LocalCap<Untyped<U18>> = ut;
+
+        let outcome = VSpace::new_from_elf_weak(
+            child_root,
+            child_asid,
+            child_vspace_slots.weaken(),
+            child_vspace_ut.weaken(),
+            &image.0,
+            page_slots.weaken(),
+            writable_mem.weaken(),
+            &user_image,
+            &root_cnode,
+            &mut local_vspace_scratch,
+        );
+    });
+
+    match outcome {
+        Err(VSpaceError::ElfSegmentsSharePage { segment: 1 }) => Ok(()),
+        _ => Err(TopLevelError::TestAssertionFailure(
+            "A page shared by code and data should have been refused",
+        )),
+    }
+}
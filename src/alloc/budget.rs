@@ -0,0 +1,313 @@
+//! Pre-flight checking of the kernel objects a system needs.
+//!
+//! Tally up what the root task is about to build in a `ResourceBudget`,
+//! then `check` it against what's actually left before building any of
+//! it. Running out halfway through setting up the processes leaves a
+//! half-built system and an error from whichever step happened to come
+//! last; the budget check fails up front instead, with a report of every
+//! kind of resource that's short and by how much.
+//!
+//! ```ignore
+//! let mut budget = ResourceBudget::new();
+//! budget
+//!     .process(ProcessPlan::for_elf::<proc::Console>(12, 15, 1024))
+//!     .fault_or_message_channel()
+//!     .queue(12);
+//! budget.check(&allocator, &ut_slots, &asid_pool)?;
+//! ```
+use core::fmt;
+
+use typenum::*;
+
+use crate::arch::{self, CNodeSlotBits, MaxUntypedSize, MinUntypedSize, PageBits};
+use crate::cap::{
+    role, CNodeRole, DirectRetype, Endpoint, LocalCap, Notification, ThreadControlBlock, WASIDPool,
+    WCNodeSlotsData,
+};
+use crate::vspace::ElfProc;
+
+use super::WUTBuddy;
+
+/// How many untyped size classes a budget tracks, from `MinUntypedSize`
+/// upwards, as many as `WUTBuddy` pools
+const SIZE_CLASSES: usize = MaxUntypedSize::USIZE;
+
+/// The kernel resources a set of processes, channels and queues needs
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResourceBudget {
+    slots: usize,
+    asids: usize,
+    /// Untypeds needed of each size, indexed from `MinUntypedSize`
+    untypeds: [usize; SIZE_CLASSES],
+}
+
+/// What a single process costs to set up, for `ResourceBudget::process`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProcessPlan {
+    /// The radix of the process' CNode
+    pub cnode_radix_bits: u8,
+    pub stack_bits: u8,
+    /// The untyped given to its `VSpace` for paging structures
+    pub paging_untyped_bits: u8,
+    /// The slots given to its `VSpace`
+    pub paging_slots: usize,
+    /// The writable memory and page slots an ELF image needs loading,
+    /// `None` for a process running out of the root task's image
+    pub elf: Option<ElfPlan>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ElfPlan {
+    pub required_pages: usize,
+    pub required_memory_bits: u8,
+}
+
+impl ProcessPlan {
+    /// A process loaded from the ELF image `E`, with the stack size the
+    /// image was built for.
+    pub fn for_elf<E: ElfProc>(
+        cnode_radix_bits: u8,
+        paging_untyped_bits: u8,
+        paging_slots: usize,
+    ) -> ProcessPlan {
+        ProcessPlan {
+            cnode_radix_bits,
+            stack_bits: E::StackSizeBits::U8,
+            paging_untyped_bits,
+            paging_slots,
+            elf: Some(ElfPlan {
+                required_pages: E::RequiredPages::USIZE,
+                required_memory_bits: E::RequiredMemoryBits::U8,
+            }),
+        }
+    }
+}
+
+impl Default for ResourceBudget {
+    fn default() -> Self {
+        ResourceBudget::new()
+    }
+}
+
+impl ResourceBudget {
+    pub fn new() -> ResourceBudget {
+        ResourceBudget {
+            slots: 0,
+            asids: 0,
+            untypeds: [0; SIZE_CLASSES],
+        }
+    }
+
+    /// The total number of CNode slots in the root task's CSpace needed
+    pub fn required_slots(&self) -> usize {
+        self.slots
+    }
+
+    pub fn required_asids(&self) -> usize {
+        self.asids
+    }
+
+    /// The untypeds needed, as `(size_bits, count)` pairs from smallest to
+    /// largest, leaving out the sizes none are needed of
+    pub fn required_untypeds(&self) -> impl Iterator<Item = (u8, usize)> + '_ {
+        (0..)
+            .zip(self.untypeds.iter())
+            .filter(|(_, count)| **count > 0)
+            .map(|(idx, count)| (idx + MinUntypedSize::U8, *count))
+    }
+
+    pub fn required_untyped_bytes(&self) -> usize {
+        self.required_untypeds()
+            .map(|(size_bits, count)| count << size_bits)
+            .sum()
+    }
+
+    pub fn slots(&mut self, count: usize) -> &mut Self {
+        self.slots += count;
+        self
+    }
+
+    pub fn asids(&mut self, count: usize) -> &mut Self {
+        self.asids += count;
+        self
+    }
+
+    /// An untyped of `size_bits`, rounded up to the smallest untyped
+    /// there is.
+    ///
+    /// # Panics
+    ///
+    /// If `size_bits` is beyond the biggest untyped there is.
+    pub fn untyped(&mut self, size_bits: u8) -> &mut Self {
+        let size_bits = core::cmp::max(size_bits, MinUntypedSize::U8);
+        self.untypeds[usize::from(size_bits - MinUntypedSize::U8)] += 1;
+        self
+    }
+
+    /// A kernel object retyped from an untyped of its own, into a slot of
+    /// its own
+    pub fn object<T: DirectRetype>(&mut self) -> &mut Self {
+        self.untyped(T::SizeBits::U8).slots(1)
+    }
+
+    /// A memory region of `size_bits`, retyped into pages, see
+    /// `UnmappedMemoryRegion::new`
+    pub fn region(&mut self, size_bits: u8) -> &mut Self {
+        self.untyped(size_bits).slots(num_pages(size_bits))
+    }
+
+    /// A process set up with `StandardProcess::new`, or `new_from_elf` and
+    /// then `StandardProcess::new` for an ELF image: its CNode, `VSpace`,
+    /// ASID, stack, IPC buffer and TCB.
+    pub fn process(&mut self, plan: ProcessPlan) -> &mut Self {
+        self.untyped(plan.cnode_radix_bits + CNodeSlotBits::U8)
+            .slots(1)
+            .object::<arch::PagingRoot>()
+            .asids(1)
+            .untyped(plan.paging_untyped_bits)
+            .slots(plan.paging_slots)
+            .region(plan.stack_bits)
+            .untyped(PageBits::U8)
+            .untyped(<ThreadControlBlock as DirectRetype>::SizeBits::U8)
+            .slots(num_pages(plan.stack_bits) + 2);
+        if let Some(elf) = plan.elf {
+            self.untyped(elf.required_memory_bits)
+                .slots(elf.required_pages);
+        }
+        self
+    }
+
+    /// A `call_channel`, with the responder's slot in its own CNode
+    pub fn call_channel(&mut self) -> &mut Self {
+        self.object::<Endpoint>()
+    }
+
+    /// A `call_channel_with_timeout`, with the responder's and caller's
+    /// slots in their own CNodes
+    pub fn call_channel_with_timeout(&mut self) -> &mut Self {
+        self.object::<Endpoint>()
+            .object::<Endpoint>()
+            .notification()
+    }
+
+    /// A `framed_call_channel`, with the caller's and responder's slots in
+    /// their own CNodes
+    pub fn framed_call_channel(&mut self) -> &mut Self {
+        self.object::<Endpoint>().region(PageBits::U8).slots(1)
+    }
+
+    /// A `fault_or_message_channel`, with the other slots in the child's
+    /// CNode
+    pub fn fault_or_message_channel(&mut self) -> &mut Self {
+        self.object::<Endpoint>()
+    }
+
+    pub fn notification(&mut self) -> &mut Self {
+        self.object::<Notification>()
+    }
+
+    /// A shared memory queue of `queue_size_bits` set up with
+    /// `Consumer1::new`
+    pub fn queue(&mut self, queue_size_bits: u8) -> &mut Self {
+        self.untyped(queue_size_bits)
+            .slots(2 * num_pages(queue_size_bits))
+            .notification()
+    }
+
+    /// Check the budget fits in what's left of `untypeds`, `slots` and
+    /// `asids`, without taking anything from them.
+    ///
+    /// The untypeds fit if the buddy allocator could hand out every one
+    /// needed, splitting bigger ones as it goes.
+    pub fn check<Role: CNodeRole>(
+        &self,
+        untypeds: &WUTBuddy<Role>,
+        slots: &LocalCap<WCNodeSlotsData<role::Local>>,
+        asids: &LocalCap<WASIDPool>,
+    ) -> Result<(), BudgetShortfall> {
+        let mut free = [0; SIZE_CLASSES];
+        for (size_bits, count) in untypeds.free_counts() {
+            if let Some(f) = free.get_mut(usize::from(size_bits - MinUntypedSize::U8)) {
+                *f = count;
+            }
+        }
+
+        let mut shortfall = BudgetShortfall {
+            slots_required: self.slots,
+            slots_available: slots.size(),
+            asids_required: self.asids,
+            asids_available: asids.available(),
+            untyped_bytes_required: self.required_untyped_bytes(),
+            untyped_bytes_available: untypeds.free_bytes(),
+            untyped_short_of: None,
+        };
+
+        // From the biggest size down, whatever's left over of one size can
+        // be split into twice as many of the next size down
+        let mut carried: usize = 0;
+        for idx in (0..SIZE_CLASSES).rev() {
+            let available = free[idx].saturating_add(carried.saturating_mul(2));
+            if available < self.untypeds[idx] {
+                shortfall.untyped_short_of = Some(idx as u8 + MinUntypedSize::U8);
+                break;
+            }
+            carried = available - self.untypeds[idx];
+        }
+
+        if shortfall.slots_required > shortfall.slots_available
+            || shortfall.asids_required > shortfall.asids_available
+            || shortfall.untyped_short_of.is_some()
+        {
+            Err(shortfall)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+fn num_pages(size_bits: u8) -> usize {
+    1 << size_bits.saturating_sub(PageBits::U8)
+}
+
+/// What a `ResourceBudget` needs, against what was available, when it
+/// didn't fit
+#[derive(Debug, Clone, PartialEq)]
+pub struct BudgetShortfall {
+    pub slots_required: usize,
+    pub slots_available: usize,
+    pub asids_required: usize,
+    pub asids_available: usize,
+    pub untyped_bytes_required: usize,
+    pub untyped_bytes_available: usize,
+    /// The biggest untyped size that couldn't be met, even splitting
+    /// bigger ones, if any
+    pub untyped_short_of: Option<u8>,
+}
+
+impl fmt::Display for BudgetShortfall {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "resource budget exceeded:")?;
+        if self.slots_required > self.slots_available {
+            write!(
+                f,
+                " slots {} required, {} available;",
+                self.slots_required, self.slots_available
+            )?;
+        }
+        if self.asids_required > self.asids_available {
+            write!(
+                f,
+                " ASIDs {} required, {} available;",
+                self.asids_required, self.asids_available
+            )?;
+        }
+        if let Some(size_bits) = self.untyped_short_of {
+            write!(
+                f,
+                " untyped short of {}-bit untypeds, {} bytes required, {} available;",
+                size_bits, self.untyped_bytes_required, self.untyped_bytes_available
+            )?;
+        }
+        Ok(())
+    }
+}
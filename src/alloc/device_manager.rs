@@ -0,0 +1,164 @@
+//! Handing each device's memory and interrupt out to one driver.
+//!
+//! A `DeviceManager` owns the `DeviceAllocator` and the `IRQControl`, and
+//! drivers' setup declares what each driver needs as a `DeviceClaim`.
+//! The manager keeps the claims it's granted, so a claim overlapping one
+//! of them, or for an IRQ that's already been handed out, is turned down
+//! as a conflict before anything is allocated for it.
+//!
+//! ```ignore
+//! let mut devices = DeviceManager::new(dev_allocator, irq_control);
+//! let uart = devices.claim(DeviceClaim::new(UART_PADDR, UART_SIZE)?.with_irq(UART_IRQ), slots)?;
+//! let uart_mem = UnmappedMemoryRegion::new_device(
+//!     uart.untyped.as_strong::<arch::PageBits>().ok_or(TopLevelError::WrongDeviceSize)?,
+//!     slots,
+//! )?;
+//! ```
+use arrayvec::ArrayVec;
+use typenum::*;
+
+use crate::alloc::micro_alloc::{
+    DeviceAllocator, DeviceRangeAllocError, PageAlignedAddressRange, PageAlignedAddressRangeError,
+};
+use crate::arch::MaxNaiveSplitCount;
+use crate::cap::irq_handler::weak::WIRQHandler;
+use crate::cap::{
+    irq_state, memory_kind, IRQControl, IRQError, LocalCNodeSlots, LocalCap, MaxIRQCount, WUntyped,
+};
+
+/// The most claims a `DeviceManager` grants
+pub const MAX_DEVICE_CLAIMS: usize = 64;
+
+/// The slots a claim takes, enough to split the device's untyped out of
+/// the biggest there is, and one for its IRQ handler
+pub type DeviceClaimSlots = op!(MaxNaiveSplitCount + MaxNaiveSplitCount + U1);
+
+/// A driver's device: its registers' physical address range, and the
+/// interrupt it raises if the driver handles it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DeviceClaim {
+    pub range: PageAlignedAddressRange,
+    pub irq: Option<u16>,
+}
+
+impl DeviceClaim {
+    /// Claim the `size_bytes` of registers at `paddr`, which have to be a
+    /// power of two number of pages and aligned to their size
+    pub fn new(paddr: usize, size_bytes: usize) -> Result<Self, PageAlignedAddressRangeError> {
+        Ok(DeviceClaim {
+            range: PageAlignedAddressRange::new_by_size(paddr, size_bytes)?,
+            irq: None,
+        })
+    }
+
+    /// Claim `irq` along with the registers
+    pub fn with_irq(self, irq: u16) -> Self {
+        DeviceClaim {
+            irq: Some(irq),
+            ..self
+        }
+    }
+
+    fn overlaps(&self, other: &DeviceClaim) -> bool {
+        self.range
+            .overlaps(other.range.start(), other.range.size_bytes())
+    }
+}
+
+#[derive(Debug)]
+pub enum DeviceClaimError {
+    /// The registers overlap those of a claim already granted
+    ConflictingClaim(DeviceClaim),
+    /// The IRQ has already been handed out
+    ConflictingIRQ(u16),
+    /// The manager already has `MAX_DEVICE_CLAIMS` claims
+    TooManyClaims,
+    DeviceRangeAllocError(DeviceRangeAllocError),
+    IRQError(IRQError),
+}
+
+impl From<DeviceRangeAllocError> for DeviceClaimError {
+    fn from(e: DeviceRangeAllocError) -> Self {
+        DeviceClaimError::DeviceRangeAllocError(e)
+    }
+}
+
+impl From<IRQError> for DeviceClaimError {
+    fn from(e: IRQError) -> Self {
+        DeviceClaimError::IRQError(e)
+    }
+}
+
+/// What a granted `DeviceClaim` gets
+pub struct DeviceGrant {
+    /// The untyped covering exactly the claim's registers
+    pub untyped: LocalCap<WUntyped<memory_kind::Device>>,
+    /// The handler for the claim's IRQ, if it had one
+    pub irq_handler: Option<LocalCap<WIRQHandler<irq_state::Unset>>>,
+}
+
+/// Grants each device's memory and IRQ to one claim only
+pub struct DeviceManager {
+    devices: DeviceAllocator,
+    irq_control: LocalCap<IRQControl>,
+    claims: ArrayVec<[DeviceClaim; MAX_DEVICE_CLAIMS]>,
+}
+
+impl DeviceManager {
+    pub fn new(devices: DeviceAllocator, irq_control: LocalCap<IRQControl>) -> Self {
+        DeviceManager {
+            devices,
+            irq_control,
+            claims: ArrayVec::new(),
+        }
+    }
+
+    /// Grant `claim`, unless it conflicts with one granted already.
+    pub fn claim(
+        &mut self,
+        claim: DeviceClaim,
+        slots: LocalCNodeSlots<DeviceClaimSlots>,
+    ) -> Result<DeviceGrant, DeviceClaimError> {
+        if let Some(granted) = self.claims.iter().find(|granted| granted.overlaps(&claim)) {
+            return Err(DeviceClaimError::ConflictingClaim(*granted));
+        }
+        if let Some(irq) = claim.irq {
+            if irq >= MaxIRQCount::U16 {
+                return Err(IRQError::OutOfRangeIRQ(irq).into());
+            }
+            if !self.irq_control.cap_data.available[usize::from(irq)] {
+                return Err(DeviceClaimError::ConflictingIRQ(irq));
+            }
+        }
+        if self.claims.is_full() {
+            return Err(DeviceClaimError::TooManyClaims);
+        }
+
+        let (irq_slot, split_slots) = slots.alloc();
+        let untyped = self
+            .devices
+            .get_untyped_by_address_range_slot_infallible(claim.range, split_slots)?;
+        let irq_handler = match claim.irq {
+            Some(irq) => Some(self.irq_control.create_weak_handler(irq_slot, irq)?),
+            None => None,
+        };
+
+        self.claims.push(claim);
+        Ok(DeviceGrant {
+            untyped,
+            irq_handler,
+        })
+    }
+
+    /// The claims granted so far
+    pub fn claims(&self) -> &[DeviceClaim] {
+        &self.claims
+    }
+
+    /// The IRQ control, for handlers made for interrupts that aren't
+    /// claimed with a device's registers, e.g. by an `InterruptConsumer`.
+    /// An IRQ handed out this way is a conflict for a later claim of it.
+    pub fn irq_control(&mut self) -> &mut LocalCap<IRQControl> {
+        &mut self.irq_control
+    }
+}
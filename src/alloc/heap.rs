@@ -0,0 +1,234 @@
+//! An opt-in global allocator for the root task, behind the
+//! `root_task_heap` feature.
+//!
+//! This is a bring-up convenience: constructing a complex system is
+//! sometimes easier with a `Vec` or a `BTreeMap` at hand, before the
+//! static shape of things has settled. Nothing in ferros itself
+//! allocates, and production builds should leave the feature off so the
+//! root task's memory use stays fully accounted for in its types.
+//!
+//! The heap is backed by a single untyped, retyped to pages and mapped
+//! into the root task's address space by `init_root_task_heap`. Until
+//! that's called every allocation fails. The allocator is a bump
+//! allocator that reclaims the most recent allocation when it's freed
+//! and starts over once everything has been freed, which suits the
+//! build-up-then-drop pattern of system construction; long-lived churn
+//! will exhaust it.
+//!
+//! Enabling the feature installs the `#[global_allocator]` and an
+//! `#[alloc_error_handler]`, so the root task must not define its own.
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::UnsafeCell;
+use core::ops::Sub;
+use core::ptr;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use typenum::*;
+
+use crate::arch::{self, PageBits};
+use crate::cap::{LocalCNodeSlots, LocalCap, Untyped};
+use crate::error::SeL4Error;
+use crate::pow::{Pow, _Pow};
+use crate::userland::CapRights;
+use crate::vspace::{
+    KernelRetypeFanOutLimit, NumPages, UnmappedMemoryRegion, VSpace, VSpaceError,
+};
+
+/// A conservative default heap size, 256 KiB, for
+/// `init_root_task_heap`.
+pub type DefaultRootTaskHeapSizeBits = U18;
+
+#[global_allocator]
+static HEAP: RootTaskHeap = RootTaskHeap::empty();
+
+#[alloc_error_handler]
+fn root_task_heap_exhausted(layout: Layout) -> ! {
+    panic!(
+        "root task heap exhausted allocating {} bytes ({} in use of {})",
+        layout.size(),
+        HEAP.used_bytes(),
+        HEAP.size_bytes()
+    )
+}
+
+/// Retype `ut` to pages, map them read-write into `vspace` and hand them
+/// to the root task heap. `DefaultRootTaskHeapSizeBits` is a reasonable
+/// `SizeBits` to start with.
+///
+/// The heap can only be set up once; the pages stay mapped for the rest
+/// of the root task's life.
+pub fn init_root_task_heap<SizeBits: Unsigned>(
+    ut: LocalCap<Untyped<SizeBits>>,
+    slots: LocalCNodeSlots<NumPages<SizeBits>>,
+    vspace: &mut VSpace,
+) -> Result<(), RootTaskHeapError>
+where
+    SizeBits: IsGreaterOrEqual<PageBits>,
+    SizeBits: Sub<PageBits>,
+    <SizeBits as Sub<PageBits>>::Output: Unsigned,
+    <SizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+    Pow<<SizeBits as Sub<PageBits>>::Output>:
+        IsLessOrEqual<KernelRetypeFanOutLimit, Output = True>,
+{
+    if HEAP.is_initialized() {
+        return Err(RootTaskHeapError::AlreadyInitialized);
+    }
+    let region = UnmappedMemoryRegion::new(ut, slots)?;
+    let mapped = vspace.map_region(region, CapRights::RW, arch::vm_attributes::DEFAULT)?;
+    HEAP.init(mapped.vaddr(), mapped.size_bytes())
+}
+
+/// How many bytes of the root task heap are currently handed out,
+/// including alignment padding.
+pub fn root_task_heap_used_bytes() -> usize {
+    HEAP.used_bytes()
+}
+
+/// The size of the root task heap in bytes, zero until
+/// `init_root_task_heap` has been called.
+pub fn root_task_heap_size_bytes() -> usize {
+    HEAP.size_bytes()
+}
+
+#[derive(Debug)]
+pub enum RootTaskHeapError {
+    /// `init_root_task_heap` was already called.
+    AlreadyInitialized,
+    SeL4Error(SeL4Error),
+    VSpaceError(VSpaceError),
+}
+
+impl From<SeL4Error> for RootTaskHeapError {
+    fn from(e: SeL4Error) -> Self {
+        RootTaskHeapError::SeL4Error(e)
+    }
+}
+
+impl From<VSpaceError> for RootTaskHeapError {
+    fn from(e: VSpaceError) -> Self {
+        RootTaskHeapError::VSpaceError(e)
+    }
+}
+
+struct HeapState {
+    start: usize,
+    next: usize,
+    end: usize,
+    live_allocations: usize,
+}
+
+struct RootTaskHeap {
+    locked: AtomicBool,
+    state: UnsafeCell<HeapState>,
+}
+
+// The state is only touched with `locked` held.
+unsafe impl Sync for RootTaskHeap {}
+
+impl RootTaskHeap {
+    const fn empty() -> Self {
+        RootTaskHeap {
+            locked: AtomicBool::new(false),
+            state: UnsafeCell::new(HeapState {
+                start: 0,
+                next: 0,
+                end: 0,
+                live_allocations: 0,
+            }),
+        }
+    }
+
+    fn with_state<R, F: FnOnce(&mut HeapState) -> R>(&self, f: F) -> R {
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            core::hint::spin_loop();
+        }
+        let r = f(unsafe { &mut *self.state.get() });
+        self.locked.store(false, Ordering::Release);
+        r
+    }
+
+    fn is_initialized(&self) -> bool {
+        self.with_state(|s| s.end != 0)
+    }
+
+    fn init(&self, vaddr: usize, size_bytes: usize) -> Result<(), RootTaskHeapError> {
+        self.with_state(|s| {
+            if s.end != 0 {
+                return Err(RootTaskHeapError::AlreadyInitialized);
+            }
+            s.start = vaddr;
+            s.next = vaddr;
+            s.end = vaddr + size_bytes;
+            Ok(())
+        })
+    }
+
+    fn used_bytes(&self) -> usize {
+        self.with_state(|s| s.next - s.start)
+    }
+
+    fn size_bytes(&self) -> usize {
+        self.with_state(|s| s.end - s.start)
+    }
+}
+
+unsafe impl GlobalAlloc for RootTaskHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.with_state(|s| {
+            let addr = (s.next + layout.align() - 1) & !(layout.align() - 1);
+            match addr.checked_add(layout.size()) {
+                Some(new_next) if s.end != 0 && new_next <= s.end => {
+                    s.next = new_next;
+                    s.live_allocations += 1;
+                    addr as *mut u8
+                }
+                _ => ptr::null_mut(),
+            }
+        })
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.with_state(|s| {
+            s.live_allocations -= 1;
+            if s.live_allocations == 0 {
+                s.next = s.start;
+            } else if ptr as usize + layout.size() == s.next {
+                s.next = ptr as usize;
+            }
+        })
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        // Growing or shrinking the most recent allocation can happen in
+        // place, which keeps a single growing `Vec` from eating the heap.
+        let in_place = self.with_state(|s| {
+            let addr = ptr as usize;
+            if addr + layout.size() != s.next {
+                return false;
+            }
+            match addr.checked_add(new_size) {
+                Some(new_next) if new_next <= s.end => {
+                    s.next = new_next;
+                    true
+                }
+                _ => false,
+            }
+        });
+        if in_place {
+            return ptr;
+        }
+        let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+        let new_ptr = self.alloc(new_layout);
+        if !new_ptr.is_null() {
+            ptr::copy_nonoverlapping(ptr, new_ptr, core::cmp::min(layout.size(), new_size));
+            self.dealloc(ptr, layout);
+        }
+        new_ptr
+    }
+}
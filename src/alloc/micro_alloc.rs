@@ -1,6 +1,8 @@
 //! A tiny first-chance allocator for the untyped capabilities sel4's BOOTINFO.
-//! This one doesn't split anything; it just hands out the smallest untyped item
-//! that's big enough for the request.
+//! `get_untyped` doesn't split anything; it just hands out an untyped item
+//! of exactly the requested size. `get_untyped_by_splitting` splits a bigger
+//! one down when needed. Which item either of them picks is up to the
+//! allocator's `AllocationPolicy`.
 use core::fmt::{Debug, Error as FmtError, Formatter};
 use core::marker::PhantomData;
 
@@ -32,11 +34,37 @@ pub enum Error {
     TooManyGeneralUntypeds,
 }
 
+#[derive(Debug, PartialEq)]
+pub enum GeneralAllocError {
+    /// No untyped is big enough for the request
+    NotEnoughMemory,
+    NotEnoughCNodeSlots,
+    /// Not enough internal storage space for the untypeds left over from
+    /// splitting
+    TooManyGeneralUntypeds,
+    SplitError(WUntypedSplitError),
+}
+
 /// Use `BootInfo` to bootstrap both the device and general allocators.
 pub fn bootstrap_allocators(
     bootinfo: &'static seL4_BootInfo,
+) -> Result<(Allocator, DeviceAllocator), Error> {
+    bootstrap_allocators_with_quarantine(bootinfo, &[])
+}
+
+/// Use `BootInfo` to bootstrap both the device and general allocators,
+/// quarantining the general memory known to be bad.
+///
+/// Any general untyped overlapping with one of the `bad_memory` ranges,
+/// e.g. from a platform-provided list or the faults recorded in a
+/// `MemoryFaultLog` before the last restart, is withheld from the
+/// allocator altogether rather than split around the bad range.
+pub fn bootstrap_allocators_with_quarantine(
+    bootinfo: &'static seL4_BootInfo,
+    bad_memory: &[PageAlignedAddressRange],
 ) -> Result<(Allocator, DeviceAllocator), Error> {
     let mut general_uts = ArrayVec::new();
+    let mut quarantined = ArrayVec::new();
     let mut device_uts: ArrayVec<[LocalCap<WUntyped<memory_kind::Device>>; MAX_DEVICE_UTS]> =
         ArrayVec::new();
 
@@ -55,14 +83,28 @@ pub fn bootstrap_allocators(
                 Ok(()) => (),
                 Err(_) => return Err(Error::TooManyDeviceUntypeds),
             }
+        } else if bad_memory
+            .iter()
+            .any(|range| range.overlaps(ut.paddr, 1 << ut.sizeBits))
+        {
+            match quarantined.try_push(QuarantinedUntyped {
+                paddr: ut.paddr,
+                size_bits: ut.sizeBits,
+            }) {
+                Ok(()) => (),
+                Err(_) => return Err(Error::TooManyGeneralUntypeds),
+            }
         } else {
-            match general_uts.try_push(Cap {
-                cptr,
-                cap_data: WUntyped {
-                    size_bits: ut.sizeBits,
-                    kind: memory_kind::General {},
+            match general_uts.try_push(GeneralUntyped {
+                ut: Cap {
+                    cptr,
+                    cap_data: WUntyped {
+                        size_bits: ut.sizeBits,
+                        kind: memory_kind::General {},
+                    },
+                    _role: PhantomData,
                 },
-                _role: PhantomData,
+                paddr: ut.paddr,
             }) {
                 Ok(()) => (),
                 Err(_) => return Err(Error::TooManyGeneralUntypeds),
@@ -73,7 +115,11 @@ pub fn bootstrap_allocators(
     // initial insertion
     pdqsort::sort_by_key(&mut device_uts, |wut| wut.cap_data.kind.paddr);
     Ok((
-        Allocator { items: general_uts },
+        Allocator {
+            items: general_uts,
+            quarantined,
+            policy: &FirstFit,
+        },
         DeviceAllocator {
             untypeds: device_uts,
         },
@@ -82,14 +128,106 @@ pub fn bootstrap_allocators(
 
 /// An allocator for general purpose memory.
 pub struct Allocator {
-    pub(super) items: ArrayVec<[LocalCap<WUntyped<memory_kind::General>>; MAX_INIT_UNTYPED_ITEMS]>,
+    pub(super) items: ArrayVec<[GeneralUntyped; MAX_INIT_UNTYPED_ITEMS]>,
+    quarantined: ArrayVec<[QuarantinedUntyped; MAX_INIT_UNTYPED_ITEMS]>,
+    policy: &'static dyn AllocationPolicy,
+}
+
+pub(super) struct GeneralUntyped {
+    pub(super) ut: LocalCap<WUntyped<memory_kind::General>>,
+    paddr: usize,
+}
+
+/// An untyped an `AllocationPolicy` may choose
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UntypedCandidate {
+    pub paddr: usize,
+    pub size_bits: u8,
+}
+
+/// How an `Allocator` picks which of its untypeds to hand out.
+///
+/// `choose` is given the candidates in the order the allocator holds
+/// them, which starts out as the order the kernel listed them in the
+/// `BootInfo`, and must return the index of one at least `size_bits` in
+/// size, or `None` if there's nothing suitable.
+pub trait AllocationPolicy {
+    fn choose(&self, candidates: &[UntypedCandidate], size_bits: u8) -> Option<usize>;
+}
+
+/// The first untyped that's big enough, in the order the allocator holds
+/// them. The default.
+///
+/// Depends on the order the kernel lists the boot untypeds in, which
+/// differs across kernel versions and configurations.
+#[derive(Debug, Clone, Copy)]
+pub struct FirstFit;
+
+impl AllocationPolicy for FirstFit {
+    fn choose(&self, candidates: &[UntypedCandidate], size_bits: u8) -> Option<usize> {
+        candidates.iter().position(|c| c.size_bits >= size_bits)
+    }
+}
+
+/// The smallest untyped that's big enough, the lowest addressed one among
+/// those of that size.
+///
+/// Depends only on the addresses and sizes of the untypeds, so the same
+/// memory layout and sequence of requests gives the same physical
+/// addresses whatever order the kernel lists them in.
+#[derive(Debug, Clone, Copy)]
+pub struct BestFit;
+
+impl AllocationPolicy for BestFit {
+    fn choose(&self, candidates: &[UntypedCandidate], size_bits: u8) -> Option<usize> {
+        candidates
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.size_bits >= size_bits)
+            .min_by_key(|(_, c)| (c.size_bits, c.paddr))
+            .map(|(i, _)| i)
+    }
+}
+
+/// The lowest addressed untyped that's big enough.
+///
+/// Like `BestFit`, independent of the order the kernel lists the
+/// untypeds in, and keeps allocations packed towards the bottom of
+/// memory at the cost of splitting more.
+#[derive(Debug, Clone, Copy)]
+pub struct AddressOrdered;
+
+impl AllocationPolicy for AddressOrdered {
+    fn choose(&self, candidates: &[UntypedCandidate], size_bits: u8) -> Option<usize> {
+        candidates
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.size_bits >= size_bits)
+            .min_by_key(|(_, c)| c.paddr)
+            .map(|(i, _)| i)
+    }
+}
+
+/// A general untyped withheld from allocation because it overlaps with
+/// bad memory
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QuarantinedUntyped {
+    pub paddr: usize,
+    pub size_bits: u8,
 }
 
 impl Debug for Allocator {
     fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
         f.write_str("Allocator { items:")?;
         for i in &self.items {
-            write!(f, "\ncptr: {}, size_bits: {}", i.cptr, i.size_bits()).unwrap();
+            write!(
+                f,
+                "\ncptr: {}, size_bits: {}, paddr: {:#018X?}",
+                i.ut.cptr,
+                i.ut.size_bits(),
+                i.paddr
+            )
+            .unwrap();
         }
         f.write_str("\n }")
     }
@@ -101,24 +239,104 @@ impl Allocator {
         Ok(alloc)
     }
 
-    /// Find an untyped of the given size. If one is found, remove
+    /// The untypeds quarantined at bootstrap, see
+    /// `bootstrap_allocators_with_quarantine`
+    pub fn quarantined(&self) -> &[QuarantinedUntyped] {
+        &self.quarantined
+    }
+
+    /// The total size of the untypeds still available from this allocator
+    pub fn free_bytes(&self) -> usize {
+        self.items.iter().map(|i| i.ut.size_bytes()).sum()
+    }
+
+    /// Choose which untyped to hand out with `policy` from now on, instead
+    /// of `FirstFit`.
+    pub fn set_policy(&mut self, policy: &'static dyn AllocationPolicy) {
+        self.policy = policy;
+    }
+
+    /// Find an untyped of the given size, as chosen by the allocator's
+    /// policy among those of exactly that size. If one is found, remove
     /// from the list and return it.
     pub fn get_untyped<BitSize: Unsigned>(
         &mut self,
     ) -> Option<LocalCap<Untyped<BitSize, memory_kind::General>>> {
-        let position = self
-            .items
-            .iter()
-            .position(|ut| ut.size_bits() == BitSize::U8)?;
-        let ut_ref = &self.items[position];
+        let mut positions: ArrayVec<[usize; MAX_INIT_UNTYPED_ITEMS]> = ArrayVec::new();
+        let mut candidates: ArrayVec<[UntypedCandidate; MAX_INIT_UNTYPED_ITEMS]> = ArrayVec::new();
+        for (position, item) in self.items.iter().enumerate() {
+            if item.ut.size_bits() == BitSize::U8 {
+                positions.push(position);
+                candidates.push(item.candidate());
+            }
+        }
+        let chosen = self.policy.choose(&candidates, BitSize::U8)?;
+        let position = *positions.get(chosen)?;
         let ut = Cap {
-            cptr: ut_ref.cptr,
+            cptr: self.items[position].ut.cptr,
             cap_data: PhantomCap::phantom_instance(),
             _role: PhantomData,
         };
         self.items.remove(position);
         Some(ut)
     }
+
+    /// Find an untyped of the given size, as chosen by the allocator's
+    /// policy among those at least that size, and split it down if it's
+    /// bigger, keeping the lowest addressed part. The rest of it stays
+    /// with the allocator. Splitting takes two slots per halving.
+    pub fn get_untyped_by_splitting<BitSize: Unsigned>(
+        &mut self,
+        slots: &mut LocalCap<WCNodeSlotsData<role::Local>>,
+    ) -> Result<LocalCap<Untyped<BitSize, memory_kind::General>>, GeneralAllocError> {
+        let candidates: ArrayVec<[UntypedCandidate; MAX_INIT_UNTYPED_ITEMS]> =
+            self.items.iter().map(GeneralUntyped::candidate).collect();
+        let position = self
+            .policy
+            .choose(&candidates, BitSize::U8)
+            .filter(|p| {
+                candidates
+                    .get(*p)
+                    .map_or(false, |c| c.size_bits >= BitSize::U8)
+            })
+            .ok_or(GeneralAllocError::NotEnoughMemory)?;
+
+        let num_splits = usize::from(self.items[position].ut.size_bits() - BitSize::U8);
+        if 2 * num_splits > slots.size() {
+            return Err(GeneralAllocError::NotEnoughCNodeSlots);
+        }
+        if self.items.len() + num_splits - 1 > MAX_INIT_UNTYPED_ITEMS {
+            return Err(GeneralAllocError::TooManyGeneralUntypeds);
+        }
+
+        let GeneralUntyped { mut ut, paddr } = self.items.remove(position);
+        while ut.size_bits() > BitSize::U8 {
+            let slot_pair = slots
+                .alloc_strong::<U2>()
+                .map_err(|_| GeneralAllocError::NotEnoughCNodeSlots)?;
+            let (lower, upper) = ut.split(slot_pair).map_err(GeneralAllocError::SplitError)?;
+            // Checked there's room above
+            self.items.push(GeneralUntyped {
+                paddr: paddr + lower.size_bytes(),
+                ut: upper,
+            });
+            ut = lower;
+        }
+        Ok(Cap {
+            cptr: ut.cptr,
+            cap_data: PhantomCap::phantom_instance(),
+            _role: PhantomData,
+        })
+    }
+}
+
+impl GeneralUntyped {
+    fn candidate(&self) -> UntypedCandidate {
+        UntypedCandidate {
+            paddr: self.paddr,
+            size_bits: self.ut.size_bits(),
+        }
+    }
 }
 
 // TODO(dan@auxon.io): I have no idea what to put here.
@@ -186,6 +404,20 @@ impl TryFrom<usize> for PageAligned {
 pub struct NotPageAligned;
 
 impl PageAlignedAddressRange {
+    pub fn start(&self) -> usize {
+        self.start.0
+    }
+
+    pub fn size_bytes(&self) -> usize {
+        self.size_bytes.0
+    }
+
+    /// Whether any of this range falls within `size_bytes` from `paddr`
+    pub fn overlaps(&self, paddr: usize, size_bytes: usize) -> bool {
+        self.start.0 < paddr.saturating_add(size_bytes)
+            && paddr < self.start.0.saturating_add(self.size_bytes.0)
+    }
+
     pub fn new_by_size(
         start: usize,
         size_bytes: usize,
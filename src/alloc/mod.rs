@@ -1,5 +1,10 @@
+pub mod budget;
+pub mod device_manager;
+#[cfg(feature = "root_task_heap")]
+pub mod heap;
 pub mod micro_alloc;
 pub mod ut_buddy;
 
+pub use self::device_manager::{DeviceClaim, DeviceClaimError, DeviceGrant, DeviceManager};
 pub use self::ut_buddy::{ut_buddy, UTBuddy, WUTBuddy};
 pub use crate::smart_alloc::smart_alloc;
@@ -12,12 +12,27 @@ use typenum::*;
 use crate::arch::{MaxUntypedSize, MinUntypedSize};
 use crate::cap::{
     memory_kind, role, CNodeRole, Cap, LocalCNode, LocalCNodeSlot, LocalCNodeSlots, LocalCap,
-    PhantomCap, Untyped, WCNodeSlots, WCNodeSlotsData, WUntyped,
+    PhantomCap, RevokedUntyped, Untyped, WCNodeSlots, WCNodeSlotsData, WUntyped,
 };
 use crate::error::{ErrorExt, SeL4Error};
 
 type UTPoolSlotsPerSize = U4;
 
+/// How many splits a `WUTBuddy` remembers for coalescing freed untypeds.
+/// Splits beyond this still go ahead, but aren't remembered, so the two
+/// halves of each are never merged back together.
+pub const MAX_TRACKED_SPLITS: usize = 64;
+
+/// An untyped split in two by the allocator, the halves of which are in
+/// consecutive slots
+#[derive(Debug, Clone, Copy)]
+struct Split {
+    parent: usize,
+    first_half: usize,
+    /// The size of each half
+    size_bits: u8,
+}
+
 /// A type-level linked list of typenum::Unsigned.
 pub trait UList {
     type Length: Unsigned;
@@ -162,7 +177,13 @@ impl<PoolSizes: UList> UTBuddy<PoolSizes> {
         PoolSizes: _TakeUntyped<Diff<BitSize, MinUntypedSize>, NumSplits = NumSplits>,
         TakeUntyped_ResultPoolSizes<PoolSizes, Diff<BitSize, MinUntypedSize>>: UList,
     {
-        let weak_ut = alloc(&mut self.pool, slots.iter(), BitSize::U8, NumSplits::U8)?;
+        let weak_ut = alloc(
+            &mut self.pool,
+            slots.iter(),
+            BitSize::U8,
+            NumSplits::U8,
+            &mut |_| (),
+        )?;
         Ok((
             Cap::wrap_cptr(weak_ut.cptr),
             UTBuddy {
@@ -181,6 +202,7 @@ pub fn weak_ut_buddy<Role: CNodeRole>(
     pool[usize::from(ut.cap_data.size_bits) - MinUntypedSize::USIZE].push(ut.cptr);
     WUTBuddy {
         pool,
+        splits: ArrayVec::new(),
         _role: PhantomData,
     }
 }
@@ -196,8 +218,11 @@ pub enum UTBuddyError {
     /// splitting.
     NotEnoughSlots,
     /// The wrapped untyped lacks the sufficient size to do this
-    /// allocation request.
+    /// allocation request. `WUTBuddy::largest_available` tells what
+    /// could have been allocated instead.
     CannotAllocateRequestedSize(u8),
+    /// There's no room in the pool for a freed untyped of this size.
+    PoolFull(u8),
     /// We got an error from an seL4 syscall, namely the
     /// `seL4_Untyped_Retype` call.
     SeL4Error(SeL4Error),
@@ -215,6 +240,7 @@ impl From<SeL4Error> for UTBuddyError {
 /// Presently restricted to provide memory_kind::General untyped
 pub struct WUTBuddy<Role: CNodeRole = role::Local> {
     pool: [ArrayVec<[usize; UTPoolSlotsPerSize::USIZE]>; MaxUntypedSize::USIZE],
+    splits: ArrayVec<[Split; MAX_TRACKED_SPLITS]>,
     _role: PhantomData<Role>,
 }
 
@@ -285,15 +311,118 @@ impl WUTBuddy<role::Local> {
         slots.cap_data.offset += slot_count;
         slots.cap_data.size -= slot_count;
 
+        let splits = &mut self.splits;
         let ut = alloc(
             &mut self.pool,
             slots_for_alloc_to_consume.into_strong_iter(),
             size,
             split_count,
+            &mut |split| {
+                // With no room to track it, the split's halves just stay
+                // apart, see `MAX_TRACKED_SPLITS`
+                let _ = splits.try_push(split);
+            },
         )?;
         Ok(ut)
     }
 
+    /// Return an untyped to the pool, merging it back together with its
+    /// buddy, and so on up, for as long as the buddy is free too.
+    ///
+    /// Only untypeds allocated from this pool should be freed to it. The
+    /// CNode slots used up by splitting aren't reclaimed, and an untyped
+    /// from a split there wasn't room to track (see `MAX_TRACKED_SPLITS`)
+    /// goes back to the pool without being merged with its buddy.
+    pub fn free(
+        &mut self,
+        ut: RevokedUntyped,
+        parent_cnode: &LocalCap<LocalCNode>,
+    ) -> Result<(), UTBuddyError> {
+        let mut cptr = ut.ut.cptr;
+        let mut size_bits = ut.size_bits();
+        if size_bits < MinUntypedSize::U8 || size_bits > MaxUntypedSize::U8 {
+            return Err(UTBuddyError::RequestedSizeExceedsMax(size_bits));
+        }
+        loop {
+            let idx = usize::from(size_bits - MinUntypedSize::U8);
+            let split = self.splits.iter().position(|s| {
+                s.size_bits == size_bits && (s.first_half == cptr || s.first_half + 1 == cptr)
+            });
+            let split = match split {
+                Some(split) => split,
+                None => break,
+            };
+            let first_half = self.splits[split].first_half;
+            let buddy = if cptr == first_half {
+                first_half + 1
+            } else {
+                first_half
+            };
+            let buddy_pos = match self.pool[idx].iter().position(|c| *c == buddy) {
+                Some(buddy_pos) => buddy_pos,
+                None => break,
+            };
+
+            // Both halves are free, so revoking the parent destroys
+            // nothing but the halves themselves
+            let parent = self.splits[split].parent;
+            unsafe {
+                seL4_CNode_Revoke(
+                    parent_cnode.cptr,   // _service
+                    parent,              // index
+                    seL4_WordBits as u8, // depth
+                )
+            }
+            .as_result()
+            .map_err(SeL4Error::CNodeRevoke)?;
+            self.pool[idx].remove(buddy_pos);
+            self.splits.remove(split);
+
+            cptr = parent;
+            size_bits += 1;
+        }
+
+        let idx = usize::from(size_bits - MinUntypedSize::U8);
+        self.pool[idx]
+            .try_push(cptr)
+            .map_err(|_| UTBuddyError::PoolFull(size_bits))
+    }
+
+    /// Give the pool another untyped, one that didn't come from it, such
+    /// as one donated by a supervisor.
+    pub fn add(
+        &mut self,
+        ut: LocalCap<WUntyped<memory_kind::General>>,
+    ) -> Result<(), UTBuddyError> {
+        let size_bits = ut.size_bits();
+        if size_bits < MinUntypedSize::U8 || size_bits > MaxUntypedSize::U8 {
+            return Err(UTBuddyError::RequestedSizeExceedsMax(size_bits));
+        }
+        self.pool[usize::from(size_bits - MinUntypedSize::U8)]
+            .try_push(ut.cptr)
+            .map_err(|_| UTBuddyError::PoolFull(size_bits))
+    }
+
+    /// Allocate a weak untyped of `size` bits if possible, and otherwise
+    /// the largest available, provided it's at least `min_size` bits.
+    ///
+    /// On a fragmented pool this lets the caller adapt to what's left,
+    /// e.g. by using a smaller buffer, rather than failing outright.
+    pub fn try_alloc_best_effort(
+        &mut self,
+        slots: &mut WCNodeSlots,
+        size: u8,
+        min_size: u8,
+    ) -> Result<LocalCap<WUntyped<memory_kind::General>>, UTBuddyError> {
+        match self.alloc(slots, size) {
+            Err(UTBuddyError::CannotAllocateRequestedSize(_)) => match self.largest_available() {
+                Some(largest) if largest >= min_size => self.alloc(slots, largest),
+                _ => Err(UTBuddyError::CannotAllocateRequestedSize(size)),
+            },
+            r => r,
+        }
+    }
+
     fn total_occupied_slots(&self) -> usize {
         self.pool.iter().map(|sub_pool| sub_pool.len()).sum()
     }
@@ -331,19 +460,56 @@ impl WUTBuddy<role::Local> {
                 child_bucket.push(child_wut.cptr);
             }
         }
+        // Splits are tracked by local cptr, so the child can't coalesce
+        // anything split before the move
         Ok(WUTBuddy {
             pool: child_pool,
+            splits: ArrayVec::new(),
             _role: PhantomData,
         })
     }
 }
 
 impl<Role: CNodeRole> WUTBuddy<Role> {
+    /// The number of free untypeds of exactly `size_bits` bits, which
+    /// doesn't count those that could be split off larger ones
+    pub fn free_count(&self, size_bits: u8) -> usize {
+        size_bits
+            .checked_sub(MinUntypedSize::U8)
+            .and_then(|idx| self.pool.get(usize::from(idx)))
+            .map_or(0, |sub_pool| sub_pool.len())
+    }
+
+    /// The free untypeds of each size, as `(size_bits, count)` pairs from
+    /// smallest to largest
+    pub fn free_counts(&self) -> impl Iterator<Item = (u8, usize)> + '_ {
+        (0..)
+            .zip(self.pool.iter())
+            .map(|(idx, sub_pool)| (idx + MinUntypedSize::U8, sub_pool.len()))
+    }
+
+    /// The size in bits of the largest untyped that can be allocated,
+    /// `None` when the pool is empty
+    pub fn largest_available(&self) -> Option<u8> {
+        self.free_counts()
+            .filter(|(_, count)| *count != 0)
+            .map(|(size_bits, _)| size_bits)
+            .last()
+    }
+
+    /// The total number of free bytes in the pool
+    pub fn free_bytes(&self) -> usize {
+        self.free_counts()
+            .map(|(size_bits, count)| count << size_bits)
+            .sum()
+    }
+
     // This might be brought back to life later on
     #[allow(dead_code)]
     pub(crate) fn empty() -> WUTBuddy<Role> {
         WUTBuddy {
             pool: make_pool(),
+            splits: ArrayVec::new(),
             _role: PhantomData,
         }
     }
@@ -354,6 +520,7 @@ fn alloc(
     slots_iter: impl Iterator<Item = LocalCNodeSlot>,
     size_bits: u8,
     split_count: u8,
+    on_split: &mut dyn FnMut(Split),
 ) -> Result<LocalCap<WUntyped<memory_kind::General>>, SeL4Error> {
     // The index in the pool array where Untypeds of the requested
     // size are stored.
@@ -386,6 +553,11 @@ fn alloc(
 
             pool[usize::from(i) - 1].push(slot_offset);
             pool[usize::from(i) - 1].push(slot_offset + 1);
+            on_split(Split {
+                parent: cptr,
+                first_half: slot_offset,
+                size_bits: cptr_bitsize - 1,
+            });
         }
     }
 
@@ -405,13 +577,14 @@ impl From<super::micro_alloc::Allocator> for WUTBuddy<role::Local> {
     fn from(alloc: super::micro_alloc::Allocator) -> Self {
         let mut pool = make_pool();
 
-        for ut in alloc.items {
+        for ut in alloc.items.into_iter().map(|i| i.ut) {
             let ut_idx = ut.cap_data.size_bits as usize - MinUntypedSize::USIZE;
             pool[ut_idx].push(ut.cptr);
         }
 
         WUTBuddy {
             pool,
+            splits: ArrayVec::new(),
             _role: PhantomData,
         }
     }
@@ -5,6 +5,8 @@ mod page_directory;
 mod page_global_directory;
 mod page_table;
 mod page_upper_directory;
+#[cfg(KernelAllowSMCCalls)]
+mod smc;
 #[cfg(KernelArmHypervisorSupport)]
 mod vcpu;
 
@@ -15,5 +17,7 @@ pub use page_directory::*;
 pub use page_global_directory::*;
 pub use page_table::*;
 pub use page_upper_directory::*;
+#[cfg(KernelAllowSMCCalls)]
+pub use smc::*;
 #[cfg(KernelArmHypervisorSupport)]
 pub use vcpu::*;
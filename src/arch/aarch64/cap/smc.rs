@@ -0,0 +1,98 @@
+//! Secure monitor calls, for kernels built to allow them.
+//!
+//! User level can't issue an `smc` itself; the kernel makes the call on
+//! behalf of whoever holds the SMC capability, which the root task is
+//! handed in its bootinfo. The calls that matter most are PSCI's, the
+//! firmware interface for restarting and powering off the system.
+use selfe_sys::*;
+
+use crate::cap::{CapType, LocalCap, PhantomCap};
+use crate::error::{ErrorExt, SeL4Error};
+
+/// The authority to make secure monitor calls
+#[derive(Debug)]
+pub struct SMC {}
+
+impl CapType for SMC {}
+
+impl PhantomCap for SMC {
+    fn phantom_instance() -> Self {
+        Self {}
+    }
+}
+
+/// The PSCI function IDs, see Arm DEN 0022
+pub mod psci {
+    pub const VERSION: usize = 0x8400_0000;
+    pub const SYSTEM_OFF: usize = 0x8400_0008;
+    pub const SYSTEM_RESET: usize = 0x8400_0009;
+}
+
+#[derive(Debug)]
+pub enum PSCIError {
+    /// The kernel refused to make the call
+    SeL4Error(SeL4Error),
+    /// The firmware returned from a call that shouldn't return, with
+    /// this status
+    Returned(isize),
+}
+
+impl From<SeL4Error> for PSCIError {
+    fn from(e: SeL4Error) -> Self {
+        PSCIError::SeL4Error(e)
+    }
+}
+
+impl LocalCap<SMC> {
+    /// Make a secure monitor call with `args` in x0-x7, returning what the
+    /// secure monitor left in x0-x7.
+    pub fn call(&self, args: [usize; 8]) -> Result<[usize; 8], SeL4Error> {
+        let mut smc_args = seL4_ARM_SMCContext {
+            x0: args[0],
+            x1: args[1],
+            x2: args[2],
+            x3: args[3],
+            x4: args[4],
+            x5: args[5],
+            x6: args[6],
+            x7: args[7],
+        };
+        let mut response: seL4_ARM_SMCContext = unsafe { core::mem::zeroed() };
+        unsafe { seL4_ARM_SMC_Call(self.cptr, &mut smc_args, &mut response) }
+            .as_result()
+            .map_err(SeL4Error::SMCCall)?;
+        Ok([
+            response.x0,
+            response.x1,
+            response.x2,
+            response.x3,
+            response.x4,
+            response.x5,
+            response.x6,
+            response.x7,
+        ])
+    }
+
+    /// The PSCI version the firmware implements, as (major, minor)
+    pub fn psci_version(&self) -> Result<(u16, u16), SeL4Error> {
+        let version = self.call([psci::VERSION, 0, 0, 0, 0, 0, 0, 0])?[0];
+        Ok(((version >> 16) as u16, version as u16))
+    }
+
+    /// Restart the system. Only returns if the restart didn't happen.
+    pub fn system_reset(&self) -> PSCIError {
+        self.psci_no_return(psci::SYSTEM_RESET)
+    }
+
+    /// Power the system off. Only returns if it's still on.
+    pub fn system_off(&self) -> PSCIError {
+        self.psci_no_return(psci::SYSTEM_OFF)
+    }
+
+    fn psci_no_return(&self, function_id: usize) -> PSCIError {
+        match self.call([function_id, 0, 0, 0, 0, 0, 0, 0]) {
+            Ok(response) => PSCIError::Returned(response[0] as isize),
+            Err(e) => PSCIError::SeL4Error(e),
+        }
+    }
+}
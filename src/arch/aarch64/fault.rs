@@ -1,5 +1,7 @@
+use core::fmt;
+
 use crate::cap::Badge;
-use crate::userland::MessageInfo;
+use crate::userland::{FaultAccess, LookupFailure, MessageInfo, VMFaultCause, VMFaultStatus};
 use selfe_sys::*;
 
 #[derive(Debug)]
@@ -43,8 +45,10 @@ pub struct NullFault {
 #[derive(Debug)]
 pub struct CapFault {
     pub sender: Badge,
+    pub program_counter: usize,
     pub in_receive_phase: bool,
     pub cap_address: usize,
+    pub lookup_failure: LookupFailure,
 }
 /// Grab bag for faults that don't fit the regular classification
 #[derive(Debug)]
@@ -145,8 +149,10 @@ impl From<(MessageInfo, Badge)> for Fault {
             }),
             CAP_FAULT => Fault::CapFault(CapFault {
                 sender,
+                program_counter: buffer.msg[seL4_CapFault_IP as usize],
                 cap_address: buffer.msg[seL4_CapFault_Addr as usize],
                 in_receive_phase: 1 == buffer.msg[seL4_CapFault_InRecvPhase as usize],
+                lookup_failure: LookupFailure::from_cap_fault(buffer),
             }),
             #[cfg(KernelArmHypervisorSupport)]
             VGIC_MAINTENANCE_FAULT => Fault::VGICMaintenanceFault(VGICMaintenanceFault {
@@ -162,3 +168,112 @@ impl From<(MessageInfo, Badge)> for Fault {
         }
     }
 }
+
+impl VMFault {
+    /// Decode the fault status register, the exception syndrome register
+    /// of the abort
+    pub fn status(&self) -> VMFaultStatus {
+        let esr = self.fault_status_register;
+        let access = if self.is_instruction_fault {
+            FaultAccess::InstructionFetch
+        } else if esr & (1 << 6) != 0 {
+            // WnR
+            FaultAccess::Write
+        } else {
+            FaultAccess::Read
+        };
+        let fault_status_code = esr & 0x3f;
+        let level = Some((fault_status_code & 0b11) as u8);
+        let cause = match fault_status_code {
+            0b00_0000..=0b00_0011 => VMFaultCause::AddressSize { level },
+            0b00_0100..=0b00_0111 => VMFaultCause::Translation { level },
+            0b00_1000..=0b00_1011 => VMFaultCause::AccessFlag { level },
+            0b00_1100..=0b00_1111 => VMFaultCause::Permission { level },
+            0b01_0000 => VMFaultCause::ExternalAbort,
+            0b10_0001 => VMFaultCause::Alignment,
+            0b10_0010 => VMFaultCause::Debug,
+            code => VMFaultCause::Unknown(code),
+        };
+        VMFaultStatus { cause, access }
+    }
+}
+
+/// The class of a `UserException`, from its exception syndrome register
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExceptionClass {
+    /// Most often an undefined instruction
+    Unknown,
+    IllegalExecutionState,
+    ProgramCounterAlignment,
+    StackPointerAlignment,
+    FloatingPoint,
+    Breakpoint,
+    Other(u8),
+}
+
+impl UserException {
+    /// The kernel reports the exception syndrome register as the
+    /// exception number
+    pub fn exception_class(&self) -> ExceptionClass {
+        match ((self.number >> 26) & 0x3f) as u8 {
+            0x00 => ExceptionClass::Unknown,
+            0x0e => ExceptionClass::IllegalExecutionState,
+            0x22 => ExceptionClass::ProgramCounterAlignment,
+            0x26 => ExceptionClass::StackPointerAlignment,
+            0x28 | 0x2c => ExceptionClass::FloatingPoint,
+            0x3c => ExceptionClass::Breakpoint,
+            class => ExceptionClass::Other(class),
+        }
+    }
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "fault from badge {:#x}: ", usize::from(self.sender()))?;
+        match self {
+            Fault::VMFault(fault) => write!(
+                f,
+                "VM fault at pc {:#x}, {} at {:#x} (ESR {:#x})",
+                fault.program_counter,
+                fault.status(),
+                fault.address,
+                fault.fault_status_register
+            ),
+            Fault::UnknownSyscall(fault) => write!(
+                f,
+                "unknown syscall {} at pc {:#x}, sp {:#x}",
+                fault.syscall, fault.program_counter, fault.stack_pointer
+            ),
+            Fault::UserException(fault) => write!(
+                f,
+                "user exception at pc {:#x}, sp {:#x}, {:?} (ESR {:#x})",
+                fault.program_counter,
+                fault.stack_pointer,
+                fault.exception_class(),
+                fault.number
+            ),
+            Fault::NullFault(_) => write!(f, "null fault"),
+            Fault::CapFault(fault) => write!(
+                f,
+                "cap fault at pc {:#x} looking up {:#x}{}, {}",
+                fault.program_counter,
+                fault.cap_address,
+                if fault.in_receive_phase {
+                    " while receiving"
+                } else {
+                    ""
+                },
+                fault.lookup_failure
+            ),
+            Fault::UnidentifiedFault(_) => write!(f, "unidentified fault"),
+            #[cfg(KernelArmHypervisorSupport)]
+            Fault::VGICMaintenanceFault(fault) => {
+                write!(f, "VGIC maintenance fault, index {}", fault.index)
+            }
+            #[cfg(KernelArmHypervisorSupport)]
+            Fault::VCPUFault(fault) => {
+                write!(f, "VCPU fault, HSR {:#x}", fault.hyp_syndrome_register)
+            }
+        }
+    }
+}
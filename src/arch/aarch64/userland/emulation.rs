@@ -0,0 +1,346 @@
+//! Emulating a guest's devices in processes of their own.
+//!
+//! A guest's accesses to the addresses of a device nothing's mapped for
+//! exit to its fault handler as `VMFault`s. An `MmioDispatcher` in the
+//! handler decodes each access and forwards it as an `MmioRequest` over a
+//! call channel to the emulator of the device it falls in, waits for the
+//! `MmioResponse`, puts a read's value in the guest's register and lets
+//! the guest carry on past the access. Each emulator is a process
+//! running `serve_device_model` with a `DeviceModel`, so a fault in one
+//! device's emulation can't take the handler or the other emulators down.
+//!
+//! ```ignore
+//! // In the VMM
+//! let (uart_setup, uart_responder) = call_channel(ut, &root_cnode, slot, uart_slot)?;
+//! let mut dispatcher = MmioDispatcher::new(guest_fault_sink, reply_slot);
+//! dispatcher.add_device(UART_BASE, UART_SIZE, uart_setup.create_caller(slot)?)?;
+//! guest.start()?;
+//! loop {
+//!     match dispatcher.handle_exit(&mut guest)? {
+//!         EmulationEvent::Emulated { .. } => (),
+//!         EmulationEvent::Unhandled(fault) => debug_println!("Guest stopped: {:?}", fault),
+//!     }
+//! }
+//!
+//! // In the uart's emulator process
+//! serve_device_model(params.responder, Pl011::new())?;
+//! ```
+use arrayvec::ArrayVec;
+use selfe_sys::*;
+
+use crate::arch::fault::{Fault, VMFault};
+use crate::cap::{role, FaultReplyEndpoint, LocalCNodeSlot, LocalCap};
+use crate::error::SeL4Error;
+use crate::userland::{Caller, FaultSink, IPCError, Responder};
+
+use super::guest::Guest;
+
+/// The most devices an `MmioDispatcher` forwards accesses for
+pub const MAX_EMULATED_DEVICES: usize = 16;
+
+/// Instruction Syndrome Valid, whether the rest of the ISS describes the
+/// access
+const ISS_ISV: usize = 1 << 24;
+const ISS_SAS_SHIFT: usize = 22;
+/// Syndrome Sign Extend, whether a read is sign extended to the register
+const ISS_SSE: usize = 1 << 21;
+const ISS_SRT_SHIFT: usize = 16;
+/// Write not Read
+const ISS_WNR: usize = 1 << 6;
+/// Instruction Length, set for a 32 bit instruction
+const ESR_IL: usize = 1 << 25;
+
+/// How wide a guest's access to a device is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MmioWidth {
+    Byte,
+    HalfWord,
+    Word,
+    DoubleWord,
+}
+
+impl MmioWidth {
+    pub fn bytes(self) -> usize {
+        match self {
+            MmioWidth::Byte => 1,
+            MmioWidth::HalfWord => 2,
+            MmioWidth::Word => 4,
+            MmioWidth::DoubleWord => 8,
+        }
+    }
+
+    /// The bits of a register an access of this width uses
+    pub fn mask(self) -> usize {
+        match self {
+            MmioWidth::DoubleWord => !0,
+            width => (1 << (width.bytes() * 8)) - 1,
+        }
+    }
+}
+
+/// An access to an emulated device, at `offset` bytes into it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MmioRequest {
+    pub offset: usize,
+    pub width: MmioWidth,
+    /// The value written, or `None` for a read
+    pub write: Option<usize>,
+}
+
+/// An emulator's answer to an `MmioRequest`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MmioResponse {
+    /// The value read, ignored for a write
+    pub value: usize,
+}
+
+#[derive(Debug)]
+pub enum EmulationError {
+    /// The dispatcher already has `MAX_EMULATED_DEVICES` devices
+    TooManyDevices,
+    /// The device's addresses overlap another's
+    OverlappingDevices,
+    /// The exit was an access the syndrome doesn't describe, e.g. a load
+    /// or store pair, which the guest can't be carried on past
+    UndecodableAccess {
+        address: usize,
+    },
+    IPCError(IPCError),
+    SeL4Error(SeL4Error),
+}
+
+impl From<IPCError> for EmulationError {
+    fn from(e: IPCError) -> Self {
+        EmulationError::IPCError(e)
+    }
+}
+
+impl From<SeL4Error> for EmulationError {
+    fn from(e: SeL4Error) -> Self {
+        EmulationError::SeL4Error(e)
+    }
+}
+
+/// What `MmioDispatcher::handle_exit` did with a guest's exit
+#[derive(Debug)]
+pub enum EmulationEvent {
+    /// An access at `address` was emulated and the guest resumed
+    Emulated { address: usize },
+    /// Not an access to an emulated device, the guest is left stopped
+    Unhandled(Fault),
+}
+
+/// A device model's registers, as seen from the guest.
+pub trait DeviceModel {
+    /// The value of the register at `offset`
+    fn read(&mut self, offset: usize, width: MmioWidth) -> usize;
+
+    /// Write `value` to the register at `offset`
+    fn write(&mut self, offset: usize, width: MmioWidth, value: usize);
+}
+
+/// Answer the requests `responder` receives with `model`, for as long as
+/// the emulator process runs.
+pub fn serve_device_model<M: DeviceModel>(
+    responder: Responder<MmioRequest, MmioResponse, role::Local>,
+    model: M,
+) -> Result<(), IPCError> {
+    responder.reply_recv_with_state(model, |request, mut model| {
+        let value = match request.write {
+            Some(value) => {
+                model.write(request.offset, request.width, value & request.width.mask());
+                0
+            }
+            None => model.read(request.offset, request.width) & request.width.mask(),
+        };
+        (MmioResponse { value }, model)
+    })?;
+    Ok(())
+}
+
+struct EmulatedDevice {
+    base: usize,
+    size: usize,
+    emulator: Caller<MmioRequest, MmioResponse, role::Local>,
+}
+
+impl EmulatedDevice {
+    fn contains(&self, address: usize) -> bool {
+        self.base <= address && address - self.base < self.size
+    }
+}
+
+/// A decoded data abort
+struct MmioAccess {
+    width: MmioWidth,
+    register: usize,
+    sign_extend: bool,
+    write: bool,
+    instruction_bytes: usize,
+}
+
+impl MmioAccess {
+    fn decode(fault: &VMFault) -> Option<MmioAccess> {
+        let esr = fault.fault_status_register;
+        if fault.is_instruction_fault || esr & ISS_ISV == 0 {
+            return None;
+        }
+        let width = match (esr >> ISS_SAS_SHIFT) & 0b11 {
+            0 => MmioWidth::Byte,
+            1 => MmioWidth::HalfWord,
+            2 => MmioWidth::Word,
+            _ => MmioWidth::DoubleWord,
+        };
+        Some(MmioAccess {
+            width,
+            register: (esr >> ISS_SRT_SHIFT) & 0b1_1111,
+            sign_extend: esr & ISS_SSE != 0,
+            write: esr & ISS_WNR != 0,
+            instruction_bytes: if esr & ESR_IL != 0 { 4 } else { 2 },
+        })
+    }
+}
+
+/// Forwards a guest's accesses to its emulated devices to their
+/// emulators
+pub struct MmioDispatcher {
+    sink: FaultSink<role::Local>,
+    reply_slot: Option<LocalCNodeSlot>,
+    devices: ArrayVec<[EmulatedDevice; MAX_EMULATED_DEVICES]>,
+}
+
+impl MmioDispatcher {
+    /// Dispatch the exits of the guest whose fault source goes to `sink`,
+    /// saving the reply that resumes the guest in `reply_slot`
+    pub fn new(sink: FaultSink<role::Local>, reply_slot: LocalCNodeSlot) -> MmioDispatcher {
+        MmioDispatcher {
+            sink,
+            reply_slot: Some(reply_slot),
+            devices: ArrayVec::new(),
+        }
+    }
+
+    /// Forward accesses to the `size` bytes at guest physical address
+    /// `base` to `emulator`, which mustn't be mapped in the guest's
+    /// vspace
+    pub fn add_device(
+        &mut self,
+        base: usize,
+        size: usize,
+        emulator: Caller<MmioRequest, MmioResponse, role::Local>,
+    ) -> Result<(), EmulationError> {
+        if self
+            .devices
+            .iter()
+            .any(|d| base < d.base + d.size && d.base < base + size)
+        {
+            return Err(EmulationError::OverlappingDevices);
+        }
+        self.devices
+            .try_push(EmulatedDevice {
+                base,
+                size,
+                emulator,
+            })
+            .map_err(|_| EmulationError::TooManyDevices)
+    }
+
+    /// Wait for `guest` to exit, and emulate the access if it was to one
+    /// of the devices.
+    pub fn handle_exit(&mut self, guest: &mut Guest) -> Result<EmulationEvent, EmulationError> {
+        let fault = self.sink.wait_for_fault();
+        let (address, device, access) = match &fault {
+            Fault::VMFault(f) => match self.devices.iter().find(|d| d.contains(f.address)) {
+                Some(device) => (f.address, device, MmioAccess::decode(f)),
+                None => return Ok(EmulationEvent::Unhandled(fault)),
+            },
+            _ => return Ok(EmulationEvent::Unhandled(fault)),
+        };
+        let access = access.ok_or(EmulationError::UndecodableAccess { address })?;
+
+        // Calling the emulator leaves the guest's reply where it is, but
+        // the guest is left stopped if the emulation fails, since it
+        // can't be carried on past an access that wasn't emulated
+        Self::emulate(device, guest, address, &access)?;
+
+        let reply_slot = self
+            .reply_slot
+            .take()
+            .expect("The reply slot is always given back");
+        let reply = LocalCap::<FaultReplyEndpoint>::save_caller_and_create(reply_slot)?;
+        self.reply_slot = Some(reply.resume_faulted_thread());
+        Ok(EmulationEvent::Emulated { address })
+    }
+
+    fn emulate(
+        device: &EmulatedDevice,
+        guest: &mut Guest,
+        address: usize,
+        access: &MmioAccess,
+    ) -> Result<(), EmulationError> {
+        let mut registers = guest.registers()?;
+        let write = if access.write {
+            let value = general_purpose_register(&mut registers, access.register).map_or(0, |r| *r);
+            Some(value & access.width.mask())
+        } else {
+            None
+        };
+        let response = device.emulator.blocking_call(&MmioRequest {
+            offset: address - device.base,
+            width: access.width,
+            write,
+        })?;
+        if !access.write {
+            let mut value = response.value & access.width.mask();
+            let sign_bit = 1 << (access.width.bytes() * 8 - 1);
+            if access.sign_extend && value & sign_bit != 0 {
+                value |= !access.width.mask();
+            }
+            if let Some(r) = general_purpose_register(&mut registers, access.register) {
+                *r = value;
+            }
+        }
+        registers.pc += access.instruction_bytes;
+        guest.set_registers(&mut registers)?;
+        Ok(())
+    }
+}
+
+/// General purpose register `n`, or `None` for 31, which is the zero
+/// register for a load or store's transfer register
+fn general_purpose_register(r: &mut seL4_UserContext, n: usize) -> Option<&mut usize> {
+    Some(match n {
+        0 => &mut r.x0,
+        1 => &mut r.x1,
+        2 => &mut r.x2,
+        3 => &mut r.x3,
+        4 => &mut r.x4,
+        5 => &mut r.x5,
+        6 => &mut r.x6,
+        7 => &mut r.x7,
+        8 => &mut r.x8,
+        9 => &mut r.x9,
+        10 => &mut r.x10,
+        11 => &mut r.x11,
+        12 => &mut r.x12,
+        13 => &mut r.x13,
+        14 => &mut r.x14,
+        15 => &mut r.x15,
+        16 => &mut r.x16,
+        17 => &mut r.x17,
+        18 => &mut r.x18,
+        19 => &mut r.x19,
+        20 => &mut r.x20,
+        21 => &mut r.x21,
+        22 => &mut r.x22,
+        23 => &mut r.x23,
+        24 => &mut r.x24,
+        25 => &mut r.x25,
+        26 => &mut r.x26,
+        27 => &mut r.x27,
+        28 => &mut r.x28,
+        29 => &mut r.x29,
+        30 => &mut r.x30,
+        _ => return None,
+    })
+}
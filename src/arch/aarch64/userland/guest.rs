@@ -0,0 +1,170 @@
+//! Running a guest VM on a virtual CPU, for kernels built with
+//! `KernelArmHypervisorSupport`.
+//!
+//! A thread bound to a `VCpu` runs at EL1, with its vspace as the stage 2
+//! translation, so the addresses regions are mapped at in the vspace are
+//! the guest's physical addresses. A `Guest` is such a thread, started at
+//! a kernel image's entry point the way the arm64 Linux boot protocol
+//! expects it: the MMU off, interrupts masked and the device tree's
+//! address in x0.
+//!
+//! The guest's exits come to the handler of its fault source, a
+//! `VMFault` for an access to an address nothing's mapped at, e.g. an
+//! emulated device's, and a `VCPUFault` for a trapped instruction. The
+//! handler emulates what it has to and replies to let the guest carry on,
+//! and forwards the guest's interrupts with `Guest::inject_irq`.
+//!
+//! ```ignore
+//! let ram = guest_vspace.map_region_at_addr(ram_region, GUEST_RAM_BASE, CapRights::RWX, ...)?;
+//! // ...copy the kernel image and device tree into the RAM through a
+//! // shared copy of the region...
+//! let mut guest = Guest::new(
+//!     &guest_vspace,
+//!     guest_cnode,
+//!     GUEST_RAM_BASE + KERNEL_OFFSET,
+//!     GUEST_RAM_BASE + DTB_OFFSET,
+//!     ut, // tcb_ut
+//!     ut, // vcpu_ut
+//!     slots,
+//!     &tpa,
+//!     guest_fault_source,
+//! )?;
+//! guest.start()?;
+//! ```
+use selfe_sys::*;
+use typenum::*;
+
+use crate::arch::cap::{vcpu_state, VCpu};
+use crate::arch::ARMVCPUBits;
+use crate::cap::{
+    role, ChildCNode, DirectRetype, LocalCNodeSlots, LocalCap, ThreadControlBlock,
+    ThreadPriorityAuthority, Untyped,
+};
+use crate::error::{ErrorExt, SeL4Error};
+use crate::userland::FaultSource;
+use crate::vspace::VSpace;
+
+/// EL1h with the D, A, I and F exceptions masked
+const GUEST_ENTRY_SPSR: usize = 0x3c5;
+
+const USER_CONTEXT_WORDS: usize =
+    core::mem::size_of::<seL4_UserContext>() / core::mem::size_of::<usize>();
+
+/// The priority a guest's thread runs at, below the root task's so the
+/// guest can't keep its host from running
+pub const DEFAULT_GUEST_PRIORITY: usize = 254;
+
+/// A guest VM's virtual CPU and the thread running on it
+pub struct Guest {
+    tcb: LocalCap<ThreadControlBlock>,
+    vcpu: LocalCap<VCpu<vcpu_state::Bound>>,
+}
+
+impl Guest {
+    /// Set up a guest in `vspace` to start at `entry_point` with
+    /// `device_tree_addr` in x0, both guest physical addresses.
+    ///
+    /// The guest has `cspace` for a CSpace, which it has no use for
+    /// unless it makes seL4 system calls of its own, and its exits go to
+    /// `fault_source`.
+    pub fn new(
+        vspace: &VSpace,
+        cspace: LocalCap<ChildCNode>,
+        entry_point: usize,
+        device_tree_addr: usize,
+        tcb_ut: LocalCap<Untyped<<ThreadControlBlock as DirectRetype>::SizeBits>>,
+        vcpu_ut: LocalCap<Untyped<ARMVCPUBits>>,
+        slots: LocalCNodeSlots<U2>,
+        priority_authority: &LocalCap<ThreadPriorityAuthority>,
+        fault_source: FaultSource<role::Child>,
+    ) -> Result<Guest, SeL4Error> {
+        let (tcb_slot, vcpu_slot) = slots.alloc();
+        let mut tcb: LocalCap<ThreadControlBlock> = tcb_ut.retype(tcb_slot)?;
+        let vcpu: LocalCap<VCpu<vcpu_state::Unbound>> = vcpu_ut.retype(vcpu_slot)?;
+
+        tcb.configure(cspace, Some(fault_source), vspace.root(), None)?;
+        tcb.set_priority(priority_authority, DEFAULT_GUEST_PRIORITY)?;
+        let vcpu = vcpu.bind_tcb(&mut tcb)?;
+        let mut guest = Guest { tcb, vcpu };
+
+        let mut registers: seL4_UserContext = unsafe { core::mem::zeroed() };
+        registers.pc = entry_point;
+        registers.x0 = device_tree_addr;
+        registers.spsr = GUEST_ENTRY_SPSR;
+        guest.set_registers(&mut registers)?;
+
+        Ok(guest)
+    }
+
+    /// The guest's general purpose registers, e.g. while it's stopped on
+    /// an exit
+    pub fn registers(&self) -> Result<seL4_UserContext, SeL4Error> {
+        let mut registers: seL4_UserContext = unsafe { core::mem::zeroed() };
+        unsafe {
+            seL4_TCB_ReadRegisters(
+                self.tcb.cptr,
+                0,
+                0,
+                // all the regs
+                USER_CONTEXT_WORDS,
+                &mut registers,
+            )
+        }
+        .as_result()
+        .map_err(SeL4Error::TCBReadRegisters)?;
+        Ok(registers)
+    }
+
+    /// Set the guest's general purpose registers, which it runs with
+    /// from the next time it's started or resumed after an exit
+    pub fn set_registers(&mut self, registers: &mut seL4_UserContext) -> Result<(), SeL4Error> {
+        unsafe {
+            seL4_TCB_WriteRegisters(
+                self.tcb.cptr,
+                0,
+                0,
+                // all the regs
+                USER_CONTEXT_WORDS,
+                registers,
+            )
+        }
+        .as_result()
+        .map_err(SeL4Error::TCBWriteRegisters)
+    }
+
+    /// Set the priority the guest's thread runs at, by default
+    /// `DEFAULT_GUEST_PRIORITY`.
+    pub fn set_priority(
+        &mut self,
+        priority_authority: &LocalCap<ThreadPriorityAuthority>,
+        priority: usize,
+    ) -> Result<(), SeL4Error> {
+        self.tcb.set_priority(priority_authority, priority)
+    }
+
+    pub fn start(&self) -> Result<(), SeL4Error> {
+        unsafe { seL4_TCB_Resume(self.tcb.cptr) }
+            .as_result()
+            .map_err(SeL4Error::TCBResume)
+    }
+
+    /// Stop the guest, until it's started again
+    pub fn suspend(&self) -> Result<(), SeL4Error> {
+        unsafe { seL4_TCB_Suspend(self.tcb.cptr) }
+            .as_result()
+            .map_err(SeL4Error::TCBSuspend)
+    }
+
+    /// Raise virtual interrupt `virq` in the guest, through the VGIC list
+    /// register `list_register`, which mustn't be holding another pending
+    /// interrupt
+    pub fn inject_irq(&mut self, virq: u16, list_register: u8) -> Result<(), SeL4Error> {
+        self.vcpu.inject_irq(virq, 0, 0, list_register)
+    }
+
+    /// The guest's virtual CPU, e.g. to read and write its EL1 system
+    /// registers while handling an exit
+    pub fn vcpu(&mut self) -> &mut LocalCap<VCpu<vcpu_state::Bound>> {
+        &mut self.vcpu
+    }
+}
@@ -1 +1,5 @@
+#[cfg(KernelArmHypervisorSupport)]
+pub mod emulation;
+#[cfg(KernelArmHypervisorSupport)]
+pub mod guest;
 pub mod process;
@@ -1,5 +1,7 @@
+use core::fmt;
+
 use crate::cap::Badge;
-use crate::userland::MessageInfo;
+use crate::userland::{FaultAccess, LookupFailure, MessageInfo, VMFaultCause, VMFaultStatus};
 use selfe_sys::*;
 
 #[derive(Debug)]
@@ -43,8 +45,10 @@ pub struct NullFault {
 #[derive(Debug)]
 pub struct CapFault {
     pub sender: Badge,
+    pub program_counter: usize,
     pub in_receive_phase: bool,
     pub cap_address: usize,
+    pub lookup_failure: LookupFailure,
 }
 /// Grab bag for faults that don't fit the regular classification
 #[derive(Debug)]
@@ -145,8 +149,10 @@ impl From<(MessageInfo, Badge)> for Fault {
             }),
             CAP_FAULT => Fault::CapFault(CapFault {
                 sender,
+                program_counter: buffer.msg[seL4_CapFault_IP as usize],
                 cap_address: buffer.msg[seL4_CapFault_Addr as usize],
                 in_receive_phase: 1 == buffer.msg[seL4_CapFault_InRecvPhase as usize],
+                lookup_failure: LookupFailure::from_cap_fault(buffer),
             }),
             #[cfg(KernelArmHypervisorSupport)]
             VGIC_MAINTENANCE_FAULT => Fault::VGICMaintenanceFault(VGICMaintenanceFault {
@@ -162,3 +168,86 @@ impl From<(MessageInfo, Badge)> for Fault {
         }
     }
 }
+
+impl VMFault {
+    /// Decode the fault status register, the short-descriptor format
+    /// DFSR or IFSR
+    pub fn status(&self) -> VMFaultStatus {
+        let fsr = self.fault_status_register;
+        let access = if self.is_instruction_fault {
+            FaultAccess::InstructionFetch
+        } else if fsr & (1 << 11) != 0 {
+            // WnR
+            FaultAccess::Write
+        } else {
+            FaultAccess::Read
+        };
+        // FS[4] is bit 10, FS[3:0] bits 3 to 0
+        let fault_status = ((fsr >> 6) & 0x10) | (fsr & 0xf);
+        let cause = match fault_status {
+            0b0_0001 => VMFaultCause::Alignment,
+            0b0_0010 => VMFaultCause::Debug,
+            0b0_0011 => VMFaultCause::AccessFlag { level: Some(1) },
+            0b0_0110 => VMFaultCause::AccessFlag { level: Some(2) },
+            0b0_0101 => VMFaultCause::Translation { level: Some(1) },
+            0b0_0111 => VMFaultCause::Translation { level: Some(2) },
+            0b0_1001 => VMFaultCause::Domain { level: Some(1) },
+            0b0_1011 => VMFaultCause::Domain { level: Some(2) },
+            0b0_1101 => VMFaultCause::Permission { level: Some(1) },
+            0b0_1111 => VMFaultCause::Permission { level: Some(2) },
+            0b0_1000 => VMFaultCause::ExternalAbort,
+            code => VMFaultCause::Unknown(code),
+        };
+        VMFaultStatus { cause, access }
+    }
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "fault from badge {:#x}: ", usize::from(self.sender()))?;
+        match self {
+            Fault::VMFault(fault) => write!(
+                f,
+                "VM fault at pc {:#x}, {} at {:#x} (FSR {:#x})",
+                fault.program_counter,
+                fault.status(),
+                fault.address,
+                fault.fault_status_register
+            ),
+            Fault::UnknownSyscall(fault) => write!(
+                f,
+                "unknown syscall {} at pc {:#x}, sp {:#x}",
+                fault.syscall, fault.program_counter, fault.stack_pointer
+            ),
+            // The kernel only raises these for undefined instructions, and
+            // doesn't fill in the number or code
+            Fault::UserException(fault) => write!(
+                f,
+                "user exception at pc {:#x}, sp {:#x}, CPSR {:#x}",
+                fault.program_counter, fault.stack_pointer, fault.current_program_status_register
+            ),
+            Fault::NullFault(_) => write!(f, "null fault"),
+            Fault::CapFault(fault) => write!(
+                f,
+                "cap fault at pc {:#x} looking up {:#x}{}, {}",
+                fault.program_counter,
+                fault.cap_address,
+                if fault.in_receive_phase {
+                    " while receiving"
+                } else {
+                    ""
+                },
+                fault.lookup_failure
+            ),
+            Fault::UnidentifiedFault(_) => write!(f, "unidentified fault"),
+            #[cfg(KernelArmHypervisorSupport)]
+            Fault::VGICMaintenanceFault(fault) => {
+                write!(f, "VGIC maintenance fault, index {}", fault.index)
+            }
+            #[cfg(KernelArmHypervisorSupport)]
+            Fault::VCPUFault(fault) => {
+                write!(f, "VCPU fault, HSR {:#x}", fault.hyp_syndrome_register)
+            }
+        }
+    }
+}
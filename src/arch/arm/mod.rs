@@ -25,6 +25,10 @@ pub type ASIDPoolCount = op!((U1 << ASIDHighBits) - U1);
 pub type ASIDPoolSize = op!(U1 << ASIDLowBits);
 pub type TCBBits = U10;
 pub type NotificationBits = U4;
+#[cfg(KernelIsMCS)]
+pub type SchedContextBits = U7;
+#[cfg(KernelIsMCS)]
+pub type ReplyBits = U4;
 
 #[cfg(KernelHypervisorSupport)]
 mod hyp_dependent_constants {
@@ -82,6 +86,10 @@ pub type PageDirectoryBits = U14;
 pub type PageBits = U12;
 pub type PageIndexBits = U12;
 pub type PageBytes = op!(U1 << U12);
+
+/// The ELF relocation type adjusting a word by the load bias, R_ARM_RELATIVE
+pub const ELF_RELATIVE_RELOCATION: u32 = 23;
+
 pub type LargePageBits = U16;
 
 pub type BasePageDirFreeSlots = op!((U1 << PageDirIndexBits) - (U1 << U9));
@@ -115,6 +123,10 @@ pub type KernelReservedStart = op!((U1 << U32) - U1);
 
 pub const WORDS_PER_PAGE: usize = PageBytes::USIZE / core::mem::size_of::<usize>();
 
+/// The largest process parameter passed to a process entirely in
+/// registers, see `userland::ParamsPassing`
+pub const PARAM_REGISTER_BYTES: usize = 4 * core::mem::size_of::<usize>();
+
 /// Type type alias allows us to treat vm_attributes in a cross-architecture
 /// way, abstractly
 pub type VMAttributes = selfe_sys::seL4_ARM_VMAttributes;
@@ -134,6 +146,11 @@ pub mod vm_attributes {
 
     pub const EXECUTE_NEVER: VMAttributes = selfe_sys::seL4_ARM_VMAttributes_seL4_ARM_ExecuteNever;
 
+    pub const UNCACHED: VMAttributes = DEFAULT & !PAGE_CACHEABLE;
+
+    /// seL4 has no write-combining attribute on ARM, so this is `UNCACHED`
+    pub const WRITE_COMBINING: VMAttributes = UNCACHED;
+
     pub const PROGRAM_CODE: VMAttributes = DEFAULT;
 
     pub const PROGRAM_DATA: VMAttributes = PAGE_CACHEABLE | PARITY_ENABLED | EXECUTE_NEVER;
@@ -146,3 +163,48 @@ pub(crate) unsafe fn flush_page(cptr: usize) -> Result<(), SeL4Error> {
 
     Ok(())
 }
+
+pub(crate) unsafe fn clean_page(cptr: usize) -> Result<(), SeL4Error> {
+    selfe_sys::seL4_ARM_Page_Clean_Data(cptr, 0x0000, PageBytes::USIZE)
+        .as_result()
+        .map_err(SeL4Error::PageCleanData)?;
+
+    Ok(())
+}
+
+pub(crate) unsafe fn invalidate_page(cptr: usize) -> Result<(), SeL4Error> {
+    selfe_sys::seL4_ARM_Page_Invalidate_Data(cptr, 0x0000, PageBytes::USIZE)
+        .as_result()
+        .map_err(SeL4Error::PageInvalidateData)?;
+
+    Ok(())
+}
+
+/// Wait for writes through write-combining (Normal non-cacheable)
+/// mappings to complete, so a device reading the memory sees them.
+pub(crate) fn drain_write_combining() {
+    unsafe { asm!("dsb st", options(nostack)) }
+}
+
+/// Start the PMU cycle counter, read by `cycle_count`.
+///
+/// # Safety
+///
+/// The kernel must export the PMU to user level
+/// (`KernelArmExportPMUUser`), otherwise this faults.
+pub unsafe fn enable_cycle_counter() {
+    // PMCR.E, then PMCNTENSET.C
+    asm!("mcr p15, 0, {}, c9, c12, 0", in(reg) 1usize, options(nomem, nostack));
+    asm!("mcr p15, 0, {}, c9, c12, 1", in(reg) 1usize << 31, options(nomem, nostack));
+}
+
+/// Read the PMU cycle counter (PMCCNTR), wrapping at the word size.
+///
+/// # Safety
+///
+/// As with `enable_cycle_counter`.
+pub unsafe fn cycle_count() -> usize {
+    let count: usize;
+    asm!("mrc p15, 0, {}, c9, c13, 0", out(reg) count, options(nomem, nostack));
+    count
+}
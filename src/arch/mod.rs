@@ -8,6 +8,11 @@ mod aarch64;
 #[cfg(target_arch = "aarch64")]
 pub use aarch64::*;
 
+#[cfg(target_arch = "x86_64")]
+mod x86_64;
+#[cfg(target_arch = "x86_64")]
+pub use x86_64::*;
+
 /// For use in places where code is generated from bitfield DSL files and
 /// hard-codes the output integer size rather than referring to seL4Word
 /// or equivalent.
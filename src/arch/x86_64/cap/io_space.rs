@@ -0,0 +1,134 @@
+use selfe_sys::*;
+
+use crate::cap::{
+    page_state, Badge, CNodeRole, CNodeSlot, Cap, CapType, CopyAliasable, DirectRetype, LocalCNode,
+    LocalCap, Mintable, Page, PhantomCap,
+};
+use crate::error::{ErrorExt, KernelError, SeL4Error};
+use crate::userland::CapRights;
+
+/// An IOMMU address space: the addresses a device's DMA is translated
+/// through. The root task's bootinfo has the one covering every device,
+/// from which one for each device is minted, see `for_device`.
+#[derive(Debug)]
+pub struct IOSpace {}
+
+impl CapType for IOSpace {}
+
+impl PhantomCap for IOSpace {
+    fn phantom_instance() -> Self {
+        IOSpace {}
+    }
+}
+
+impl CopyAliasable for IOSpace {
+    type CopyOutput = Self;
+}
+
+impl Mintable for IOSpace {}
+
+impl<'a> From<&'a IOSpace> for IOSpace {
+    fn from(_val: &'a IOSpace) -> Self {
+        PhantomCap::phantom_instance()
+    }
+}
+
+/// Which PCI function an IOSpace is for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PCIRequestId {
+    pub bus: u8,
+    /// Only the low 5 bits are used
+    pub device: u8,
+    /// Only the low 3 bits are used
+    pub function: u8,
+}
+
+impl PCIRequestId {
+    fn to_word(self) -> usize {
+        (usize::from(self.bus) << 8)
+            | (usize::from(self.device & 0x1f) << 3)
+            | usize::from(self.function & 0x7)
+    }
+}
+
+impl LocalCap<IOSpace> {
+    /// Mint the IOSpace for one PCI function from the bootinfo's. Devices
+    /// given the same `domain_id` share their translations.
+    pub fn for_device<DestRole: CNodeRole>(
+        &self,
+        src_cnode: &LocalCap<LocalCNode>,
+        dest_slot: CNodeSlot<DestRole>,
+        domain_id: u16,
+        request_id: PCIRequestId,
+    ) -> Result<Cap<IOSpace, DestRole>, SeL4Error> {
+        self.mint(
+            src_cnode,
+            dest_slot,
+            CapRights::RW,
+            Badge::from((usize::from(domain_id) << 16) | request_id.to_word()),
+        )
+    }
+}
+
+/// One level of an IOSpace's translation tables. How many levels there
+/// are depends on the IOMMU, so they're only found to be missing when a
+/// mapping fails.
+#[derive(Debug)]
+pub struct IOPageTable {}
+
+impl CapType for IOPageTable {}
+
+impl PhantomCap for IOPageTable {
+    fn phantom_instance() -> Self {
+        IOPageTable {}
+    }
+}
+
+impl DirectRetype for IOPageTable {
+    type SizeBits = super::super::IOPageTableBits;
+    fn sel4_type_id() -> usize {
+        _object_seL4_X86_IOPageTableObject as usize
+    }
+}
+
+impl LocalCap<IOPageTable> {
+    /// Map the table in at the first level missing on the way to
+    /// `ioaddr`.
+    pub(crate) fn map_io(
+        &self,
+        io_space: &LocalCap<IOSpace>,
+        ioaddr: usize,
+    ) -> Result<(), SeL4Error> {
+        unsafe { seL4_X86_IOPageTable_Map(self.cptr, io_space.cptr, ioaddr) }
+            .as_result()
+            .map_err(SeL4Error::IOPageTableMap)
+    }
+}
+
+impl LocalCap<Page<page_state::Unmapped>> {
+    /// Map the page at `ioaddr`, failing with `FailedLookup` when there's
+    /// a level of page tables to map first.
+    pub(crate) fn map_io(
+        &self,
+        io_space: &LocalCap<IOSpace>,
+        rights: CapRights,
+        ioaddr: usize,
+    ) -> Result<(), KernelError> {
+        unsafe {
+            seL4_X86_Page_MapIO(
+                self.cptr,
+                io_space.cptr,
+                seL4_CapRights_t::from(rights),
+                ioaddr,
+            )
+        }
+        .as_result()
+    }
+
+    /// Take the page back out of whichever IOSpace it's mapped in.
+    pub(crate) fn unmap_io(&self) -> Result<(), SeL4Error> {
+        unsafe { seL4_X86_Page_Unmap(self.cptr) }
+            .as_result()
+            .map_err(SeL4Error::PageUnmap)
+    }
+}
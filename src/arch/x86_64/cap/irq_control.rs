@@ -0,0 +1,137 @@
+use core::marker::PhantomData;
+
+use selfe_sys::*;
+use typenum::*;
+
+use crate::cap::{
+    irq_state, CNodeRole, CNodeSlot, Cap, IRQControl, IRQError, IRQHandler, LocalCap, MaxIRQCount,
+};
+
+/// How an interrupt is wired to an IOAPIC pin
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IOAPICRoute {
+    pub ioapic: usize,
+    pub pin: usize,
+    pub level_triggered: bool,
+    pub active_low: bool,
+}
+
+impl IOAPICRoute {
+    /// The usual route of a legacy ISA interrupt: edge triggered and
+    /// active high, to the pin of the same number on the first IOAPIC
+    pub fn isa(irq: u16) -> Self {
+        IOAPICRoute {
+            ioapic: 0,
+            pin: usize::from(irq),
+            level_triggered: false,
+            active_low: false,
+        }
+    }
+}
+
+/// Which PCI function a message signaled interrupt comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MSIRoute {
+    pub pci_bus: usize,
+    pub pci_device: usize,
+    pub pci_function: usize,
+    /// Passed through to the kernel, which uses it to identify the
+    /// interrupt when an IOMMU remaps interrupts
+    pub handle: usize,
+}
+
+impl LocalCap<IRQControl> {
+    /// Make a handler for an interrupt wired other than as an ISA
+    /// interrupt, e.g. a level triggered PCI interrupt. `IRQ` is the
+    /// vector the interrupt is delivered on; `create_handler` assumes
+    /// `IOAPICRoute::isa`.
+    pub fn create_ioapic_handler<IRQ: Unsigned, DestRole: CNodeRole>(
+        &mut self,
+        dest_slot: CNodeSlot<DestRole>,
+        route: IOAPICRoute,
+    ) -> Result<Cap<IRQHandler<IRQ, irq_state::Unset>, DestRole>, IRQError>
+    where
+        IRQ: IsLess<MaxIRQCount, Output = True>,
+    {
+        let destination_relative_cptr =
+            self.internal_create_handler(dest_slot, IRQ::U16, |control, irq, root, index| {
+                ioapic_get(control, route, irq, root, index)
+            })?;
+        Ok(Cap {
+            cptr: destination_relative_cptr,
+            cap_data: IRQHandler {
+                _irq: PhantomData,
+                _set_state: PhantomData,
+            },
+            _role: PhantomData,
+        })
+    }
+
+    /// Make a handler for a message signaled interrupt, delivered on
+    /// vector `IRQ`. The device is then programmed to send its MSIs to
+    /// that vector.
+    pub fn create_msi_handler<IRQ: Unsigned, DestRole: CNodeRole>(
+        &mut self,
+        dest_slot: CNodeSlot<DestRole>,
+        route: MSIRoute,
+    ) -> Result<Cap<IRQHandler<IRQ, irq_state::Unset>, DestRole>, IRQError>
+    where
+        IRQ: IsLess<MaxIRQCount, Output = True>,
+    {
+        let destination_relative_cptr =
+            self.internal_create_handler(dest_slot, IRQ::U16, |control, irq, root, index| {
+                msi_get(control, route, irq, root, index)
+            })?;
+        Ok(Cap {
+            cptr: destination_relative_cptr,
+            cap_data: IRQHandler {
+                _irq: PhantomData,
+                _set_state: PhantomData,
+            },
+            _role: PhantomData,
+        })
+    }
+}
+
+/// Make the handler for an ISA interrupt, see `IOAPICRoute::isa`
+pub(crate) fn irq_control_get(control: usize, irq: u16, root: usize, index: usize) -> seL4_Error {
+    ioapic_get(control, IOAPICRoute::isa(irq), irq, root, index)
+}
+
+fn ioapic_get(
+    control: usize,
+    route: IOAPICRoute,
+    vector: u16,
+    root: usize,
+    index: usize,
+) -> seL4_Error {
+    unsafe {
+        seL4_IRQControl_GetIOAPIC(
+            control,                        // _service
+            root,                           // root
+            index,                          // index
+            seL4_WordBits as u8,            // depth
+            route.ioapic,                   // ioapic
+            route.pin,                      // pin
+            route.level_triggered as usize, // level
+            route.active_low as usize,      // polarity
+            usize::from(vector),            // vector
+        )
+    }
+}
+
+fn msi_get(control: usize, route: MSIRoute, vector: u16, root: usize, index: usize) -> seL4_Error {
+    unsafe {
+        seL4_IRQControl_GetMSI(
+            control,             // _service
+            root,                // root
+            index,               // index
+            seL4_WordBits as u8, // depth
+            route.pci_bus,       // pci_bus
+            route.pci_device,    // pci_dev
+            route.pci_function,  // pci_func
+            route.handle,        // handle
+            usize::from(vector), // vector
+        )
+    }
+}
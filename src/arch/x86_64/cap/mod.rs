@@ -0,0 +1,21 @@
+mod asid;
+mod asid_control;
+#[cfg(KernelIOMMU)]
+mod io_space;
+mod irq_control;
+mod page;
+mod page_directory;
+mod page_directory_pointer_table;
+mod page_table;
+mod pml4;
+
+pub use asid::*;
+pub use asid_control::*;
+#[cfg(KernelIOMMU)]
+pub use io_space::*;
+pub use irq_control::*;
+pub use page::*;
+pub use page_directory::*;
+pub use page_directory_pointer_table::*;
+pub use page_table::*;
+pub use pml4::*;
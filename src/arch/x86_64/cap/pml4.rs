@@ -0,0 +1,42 @@
+use selfe_sys::*;
+
+use crate::cap::{CapType, DirectRetype, LocalCap, Movable, PhantomCap};
+use crate::error::{ErrorExt, SeL4Error};
+use crate::userland::CapRights;
+use crate::vspace::{MappingError, Maps};
+
+use super::super::PagingRoot;
+use super::PageDirectoryPointerTable;
+
+#[derive(Debug)]
+pub struct PML4 {}
+
+impl Maps<PageDirectoryPointerTable> for PML4 {
+    fn map_granule(
+        &mut self,
+        pdpt: &LocalCap<PageDirectoryPointerTable>,
+        addr: usize,
+        root: &mut LocalCap<PagingRoot>,
+        _rights: CapRights,
+        vm_attributes: seL4_X86_VMAttributes,
+    ) -> Result<(), MappingError> {
+        unsafe { seL4_X86_PDPT_Map(pdpt.cptr, root.cptr, addr, vm_attributes) }
+            .as_result()
+            .map_err(|e| MappingError::IntermediateLayerFailure(SeL4Error::PDPTMap(e)))
+    }
+}
+
+impl CapType for PML4 {}
+impl Movable for PML4 {}
+impl PhantomCap for PML4 {
+    fn phantom_instance() -> Self {
+        PML4 {}
+    }
+}
+
+impl DirectRetype for PML4 {
+    type SizeBits = super::super::PML4Bits;
+    fn sel4_type_id() -> usize {
+        _mode_object_seL4_X64_PML4Object as usize
+    }
+}
@@ -0,0 +1,249 @@
+use core::fmt;
+
+use crate::cap::Badge;
+use crate::userland::{FaultAccess, LookupFailure, MessageInfo, VMFaultCause, VMFaultStatus};
+use selfe_sys::*;
+
+#[derive(Debug)]
+pub struct VMFault {
+    pub sender: Badge,
+    pub program_counter: usize,
+    pub address: usize,
+    pub is_instruction_fault: bool,
+    pub fault_status_register: usize,
+}
+#[derive(Debug)]
+pub struct UnknownSyscall {
+    pub sender: Badge,
+    pub rax: usize,
+    pub rbx: usize,
+    pub rcx: usize,
+    pub rdx: usize,
+    pub rsi: usize,
+    pub rdi: usize,
+    pub rbp: usize,
+    pub r8: usize,
+    pub r9: usize,
+    pub r10: usize,
+    pub r11: usize,
+    pub r12: usize,
+    pub r13: usize,
+    pub r14: usize,
+    pub r15: usize,
+    pub program_counter: usize,
+    pub stack_pointer: usize,
+    pub flags: usize,
+    pub syscall: usize,
+}
+#[derive(Debug)]
+pub struct UserException {
+    pub sender: Badge,
+    pub program_counter: usize,
+    pub stack_pointer: usize,
+    pub flags: usize,
+    pub number: usize,
+    pub code: usize,
+}
+#[derive(Debug)]
+pub struct NullFault {
+    pub sender: Badge,
+}
+#[derive(Debug)]
+pub struct CapFault {
+    pub sender: Badge,
+    pub program_counter: usize,
+    pub in_receive_phase: bool,
+    pub cap_address: usize,
+    pub lookup_failure: LookupFailure,
+}
+/// Grab bag for faults that don't fit the regular classification
+#[derive(Debug)]
+pub struct UnidentifiedFault {
+    pub sender: Badge,
+}
+
+#[derive(Debug)]
+pub enum Fault {
+    VMFault(VMFault),
+    UnknownSyscall(UnknownSyscall),
+    UserException(UserException),
+    NullFault(NullFault),
+    CapFault(CapFault),
+    UnidentifiedFault(UnidentifiedFault),
+}
+
+impl Fault {
+    pub fn sender(&self) -> Badge {
+        match self {
+            Fault::VMFault(f) => f.sender,
+            Fault::UnknownSyscall(f) => f.sender,
+            Fault::UserException(f) => f.sender,
+            Fault::NullFault(f) => f.sender,
+            Fault::CapFault(f) => f.sender,
+            Fault::UnidentifiedFault(f) => f.sender,
+        }
+    }
+}
+
+impl From<(MessageInfo, Badge)> for Fault {
+    fn from(info_and_sender: (MessageInfo, Badge)) -> Self {
+        let (info, sender) = info_and_sender;
+        let buffer: &mut seL4_IPCBuffer = unsafe { &mut *seL4_GetIPCBuffer() };
+        const VM_FAULT: usize = seL4_Fault_tag_seL4_Fault_VMFault as usize;
+        const UNKNOWN_SYSCALL: usize = seL4_Fault_tag_seL4_Fault_UnknownSyscall as usize;
+        const USER_EXCEPTION: usize = seL4_Fault_tag_seL4_Fault_UserException as usize;
+        const NULL_FAULT: usize = seL4_Fault_tag_seL4_Fault_NullFault as usize;
+        const CAP_FAULT: usize = seL4_Fault_tag_seL4_Fault_CapFault as usize;
+        match info.label() {
+            NULL_FAULT => Fault::NullFault(NullFault { sender }),
+            VM_FAULT => Fault::VMFault(VMFault {
+                sender,
+                program_counter: buffer.msg[seL4_VMFault_IP as usize],
+                address: buffer.msg[seL4_VMFault_Addr as usize],
+                is_instruction_fault: 1 == buffer.msg[seL4_VMFault_PrefetchFault as usize],
+                fault_status_register: buffer.msg[seL4_VMFault_FSR as usize],
+            }),
+            UNKNOWN_SYSCALL => Fault::UnknownSyscall(UnknownSyscall {
+                sender,
+                rax: buffer.msg[seL4_UnknownSyscall_RAX as usize],
+                rbx: buffer.msg[seL4_UnknownSyscall_RBX as usize],
+                rcx: buffer.msg[seL4_UnknownSyscall_RCX as usize],
+                rdx: buffer.msg[seL4_UnknownSyscall_RDX as usize],
+                rsi: buffer.msg[seL4_UnknownSyscall_RSI as usize],
+                rdi: buffer.msg[seL4_UnknownSyscall_RDI as usize],
+                rbp: buffer.msg[seL4_UnknownSyscall_RBP as usize],
+                r8: buffer.msg[seL4_UnknownSyscall_R8 as usize],
+                r9: buffer.msg[seL4_UnknownSyscall_R9 as usize],
+                r10: buffer.msg[seL4_UnknownSyscall_R10 as usize],
+                r11: buffer.msg[seL4_UnknownSyscall_R11 as usize],
+                r12: buffer.msg[seL4_UnknownSyscall_R12 as usize],
+                r13: buffer.msg[seL4_UnknownSyscall_R13 as usize],
+                r14: buffer.msg[seL4_UnknownSyscall_R14 as usize],
+                r15: buffer.msg[seL4_UnknownSyscall_R15 as usize],
+                program_counter: buffer.msg[seL4_UnknownSyscall_FaultIP as usize],
+                stack_pointer: buffer.msg[seL4_UnknownSyscall_SP as usize],
+                flags: buffer.msg[seL4_UnknownSyscall_FLAGS as usize],
+                syscall: buffer.msg[seL4_UnknownSyscall_Syscall as usize],
+            }),
+            USER_EXCEPTION => Fault::UserException(UserException {
+                sender,
+                program_counter: buffer.msg[seL4_UserException_FaultIP as usize],
+                stack_pointer: buffer.msg[seL4_UserException_SP as usize],
+                flags: buffer.msg[seL4_UserException_FLAGS as usize],
+                number: buffer.msg[seL4_UserException_Number as usize],
+                code: buffer.msg[seL4_UserException_Code as usize],
+            }),
+            CAP_FAULT => Fault::CapFault(CapFault {
+                sender,
+                program_counter: buffer.msg[seL4_CapFault_IP as usize],
+                cap_address: buffer.msg[seL4_CapFault_Addr as usize],
+                in_receive_phase: 1 == buffer.msg[seL4_CapFault_InRecvPhase as usize],
+                lookup_failure: LookupFailure::from_cap_fault(buffer),
+            }),
+            _ => Fault::UnidentifiedFault(UnidentifiedFault { sender }),
+        }
+    }
+}
+
+impl VMFault {
+    /// Decode the fault status register, the page fault error code
+    pub fn status(&self) -> VMFaultStatus {
+        let error_code = self.fault_status_register;
+        let access = if self.is_instruction_fault || error_code & (1 << 4) != 0 {
+            FaultAccess::InstructionFetch
+        } else if error_code & (1 << 1) != 0 {
+            FaultAccess::Write
+        } else {
+            FaultAccess::Read
+        };
+        let cause = if error_code & (1 << 3) != 0 {
+            VMFaultCause::ReservedBit
+        } else if error_code & 1 != 0 {
+            VMFaultCause::Permission { level: None }
+        } else {
+            VMFaultCause::Translation { level: None }
+        };
+        VMFaultStatus { cause, access }
+    }
+}
+
+/// The vector of a `UserException`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExceptionVector {
+    DivideError,
+    Debug,
+    Breakpoint,
+    Overflow,
+    BoundRangeExceeded,
+    InvalidOpcode,
+    DeviceNotAvailable,
+    StackSegmentFault,
+    GeneralProtection,
+    FloatingPoint,
+    AlignmentCheck,
+    SIMDFloatingPoint,
+    Other(usize),
+}
+
+impl UserException {
+    pub fn vector(&self) -> ExceptionVector {
+        match self.number {
+            0 => ExceptionVector::DivideError,
+            1 => ExceptionVector::Debug,
+            3 => ExceptionVector::Breakpoint,
+            4 => ExceptionVector::Overflow,
+            5 => ExceptionVector::BoundRangeExceeded,
+            6 => ExceptionVector::InvalidOpcode,
+            7 => ExceptionVector::DeviceNotAvailable,
+            12 => ExceptionVector::StackSegmentFault,
+            13 => ExceptionVector::GeneralProtection,
+            16 => ExceptionVector::FloatingPoint,
+            17 => ExceptionVector::AlignmentCheck,
+            19 => ExceptionVector::SIMDFloatingPoint,
+            vector => ExceptionVector::Other(vector),
+        }
+    }
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "fault from badge {:#x}: ", usize::from(self.sender()))?;
+        match self {
+            Fault::VMFault(fault) => write!(
+                f,
+                "VM fault at pc {:#x}, {} at {:#x} (error code {:#x})",
+                fault.program_counter,
+                fault.status(),
+                fault.address,
+                fault.fault_status_register
+            ),
+            Fault::UnknownSyscall(fault) => write!(
+                f,
+                "unknown syscall {} at pc {:#x}, sp {:#x}",
+                fault.syscall, fault.program_counter, fault.stack_pointer
+            ),
+            Fault::UserException(fault) => write!(
+                f,
+                "user exception at pc {:#x}, sp {:#x}, {:?} (error code {:#x})",
+                fault.program_counter,
+                fault.stack_pointer,
+                fault.vector(),
+                fault.code
+            ),
+            Fault::NullFault(_) => write!(f, "null fault"),
+            Fault::CapFault(fault) => write!(
+                f,
+                "cap fault at pc {:#x} looking up {:#x}{}, {}",
+                fault.program_counter,
+                fault.cap_address,
+                if fault.in_receive_phase {
+                    " while receiving"
+                } else {
+                    ""
+                },
+                fault.lookup_failure
+            ),
+            Fault::UnidentifiedFault(_) => write!(f, "unidentified fault"),
+        }
+    }
+}
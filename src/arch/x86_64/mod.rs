@@ -0,0 +1,209 @@
+use core::marker::PhantomData;
+use core::sync::atomic::{fence, Ordering};
+
+use typenum::*;
+
+use crate::cap::{page_state, Page, PageTable, PhantomCap};
+use crate::error::SeL4Error;
+use crate::vspace::{PagingRec, PagingTop};
+
+pub mod cap;
+pub mod fault;
+pub mod userland;
+
+pub type WordSize = U64;
+pub type MinUntypedSize = U4;
+// MaxUntypedSize is half the address space and/or word size.
+pub type MaxUntypedSize = U47;
+/// The number of splits it would take to extract an untyped of the minimum
+/// size starting from an untyped of the maximum size
+pub type MaxNaiveSplitCount = op!(MaxUntypedSize - MinUntypedSize);
+
+/// The ASID address space is a total of 12 bits. It is bifurcated
+/// into high bits and low bits where the high bits determine the
+/// number of pools while the low bits identify the ASID /in/ its
+/// pool.
+pub type ASIDHighBits = U3;
+pub type ASIDLowBits = U9;
+/// The total number of available pools is 2 ^ ASIDHighBits, however,
+/// there is an initial pool given to the root thread.
+pub type ASIDPoolCount = op!(U1 << ASIDHighBits);
+pub type ASIDPoolSize = op!(U1 << ASIDLowBits);
+pub type TCBBits = U11;
+pub type NotificationBits = U5;
+#[cfg(KernelIsMCS)]
+pub type SchedContextBits = U8;
+#[cfg(KernelIsMCS)]
+pub type ReplyBits = U5;
+
+// The paging structures are layed out as follows:
+// L0: PML4
+// L1: |_PageDirectoryPointerTable *L2 | HugePage
+// L2:   |_PageDirectory           *L3 | LargePage
+// L3:    |_PageTable
+//          |_Page
+pub type PML4Bits = U12;
+pub type PML4IndexBits = U9;
+pub type PDPTBits = U12;
+pub type PDPTIndexBits = U9;
+pub type PageDirectoryBits = U12;
+pub type PageDirIndexBits = U9;
+pub type PageTableBits = U12; // How big is the kernel object for a PageTable
+pub type PageTableIndexBits = U9; // How many slots are there, in addressable bit space?
+pub type PageBits = U12;
+pub type PageIndexBits = U12;
+/// How big is the kernel object for one level of IOMMU translation tables
+pub type IOPageTableBits = U12;
+
+pub type PageBytes = op!(U1 << U12);
+
+/// The ELF relocation type adjusting a word by the load bias, R_X86_64_RELATIVE
+pub const ELF_RELATIVE_RELOCATION: u32 = 8;
+
+pub type LargePageBits = U21;
+pub type HugePageBits = U30;
+
+pub type AddressSpace = PagingRec<
+    Page<page_state::Unmapped>,
+    PageTable,
+    PagingRec<
+        PageTable,
+        cap::PageDirectory,
+        PagingRec<cap::PageDirectory, cap::PageDirectoryPointerTable, PagingTop>,
+    >,
+>;
+
+pub type PagingRoot = cap::PML4;
+/// The level directly underneath the PagingRoot
+pub type PagingRootLowerLevel = cap::PageDirectoryPointerTable;
+
+impl AddressSpace {
+    pub fn new() -> Self {
+        PagingRec {
+            layer: PageTable::phantom_instance(),
+            next: PagingRec {
+                layer: cap::PageDirectory::phantom_instance(),
+                next: PagingRec {
+                    layer: cap::PageDirectoryPointerTable::phantom_instance(),
+                    next: PagingTop {
+                        layer: cap::PML4::phantom_instance(),
+                        _item: PhantomData,
+                    },
+                    _item: PhantomData,
+                },
+                _item: PhantomData,
+            },
+            _item: PhantomData,
+        }
+    }
+}
+
+pub type BasePageDirFreeSlots = op!(U1 << PageDirIndexBits);
+pub type BasePageTableFreeSlots = op!(U1 << PageTableIndexBits);
+
+// TODO remove these when elf stuff lands.
+// this is the default start address for x86_64 executables.
+/// 0x00400000
+pub type ProgramStart = op!(U4 << U20);
+pub type CodePageTableBits = U5;
+pub type CodePageTableCount = op!(U1 << CodePageTableBits); // 32 page tables, but larger == 64 mb
+pub type CodePageCount = op!(CodePageTableCount * BasePageTableFreeSlots); // 2^14
+pub type TotalCodeSizeBits = op!(CodePageTableBits + PageBits + PageTableIndexBits);
+pub type TotalCodeSizeBytes = crate::pow::Pow<TotalCodeSizeBits>;
+// The root task has a stack size configurable by the sel4.toml
+// in the `root-task-stack-bytes` metadata property.
+// This configuration is turned into a generated Rust type named
+// `RootTaskStackPageTableCount` that implements `typenum::Unsigned` in the
+// `build.rs` file.
+include!(concat!(
+    env!("OUT_DIR"),
+    "/ROOT_TASK_STACK_PAGE_TABLE_COUNT"
+));
+// The first N page tables are already mapped for the user image in the root
+// task. Add in the stack-reserved page tables (minimum of 1 more)
+pub type RootTaskReservedPageDirSlots = op!(CodePageTableCount + RootTaskStackPageTableCount);
+pub type RootTaskPageDirFreeSlots = op!(BasePageDirFreeSlots - RootTaskReservedPageDirSlots);
+
+/* User level gets the lower half of the 48 bit canonical address space,
+ * the kernel is mapped in the upper half.
+ * 0x0000800000000000 */
+pub type KernelReservedStart = op!(U1 << U47);
+
+pub const WORDS_PER_PAGE: usize = PageBytes::USIZE / core::mem::size_of::<usize>();
+
+/// The largest process parameter passed to a process entirely in
+/// registers, see `userland::ParamsPassing`
+pub const PARAM_REGISTER_BYTES: usize = 2 * core::mem::size_of::<usize>();
+
+/// Type type alias allows us to treat vm_attributes in a cross-architecture
+/// way, abstractly
+pub type VMAttributes = selfe_sys::seL4_X86_VMAttributes;
+
+/// A convenience module
+///
+/// The x86 attributes pick a caching type, rather than being flags; there's
+/// no execute-never attribute, so `EXECUTE_NEVER` changes nothing.
+pub mod vm_attributes {
+    use super::*;
+
+    pub const DEFAULT: VMAttributes =
+        selfe_sys::seL4_X86_VMAttributes_seL4_X86_Default_VMAttributes;
+
+    pub const WRITE_THROUGH: VMAttributes = selfe_sys::seL4_X86_VMAttributes_seL4_X86_WriteThrough;
+
+    pub const CACHE_DISABLED: VMAttributes =
+        selfe_sys::seL4_X86_VMAttributes_seL4_X86_CacheDisabled;
+
+    pub const EXECUTE_NEVER: VMAttributes = DEFAULT;
+
+    pub const UNCACHED: VMAttributes = CACHE_DISABLED;
+
+    pub const WRITE_COMBINING: VMAttributes =
+        selfe_sys::seL4_X86_VMAttributes_seL4_X86_WriteCombining;
+
+    pub const PROGRAM_CODE: VMAttributes = DEFAULT;
+
+    /// As there's no execute-never attribute, an ELF image's data is as
+    /// executable as its code, and images aren't loaded W^X on x86_64
+    pub const PROGRAM_DATA: VMAttributes = DEFAULT;
+}
+
+/// x86 caches are coherent with DMA, so there's nothing to flush, clean
+/// or invalidate.
+pub(crate) unsafe fn flush_page(_cptr: usize) -> Result<(), SeL4Error> {
+    Ok(())
+}
+
+pub(crate) unsafe fn clean_page(_cptr: usize) -> Result<(), SeL4Error> {
+    Ok(())
+}
+
+pub(crate) unsafe fn invalidate_page(_cptr: usize) -> Result<(), SeL4Error> {
+    Ok(())
+}
+
+/// Drain the write-combining buffers, which a full fence orders along
+/// with everything else.
+pub(crate) fn drain_write_combining() {
+    fence(Ordering::SeqCst);
+}
+
+/// The time stamp counter always runs, so there's nothing to start.
+///
+/// # Safety
+///
+/// Safe to call, and unsafe only to match the other architectures.
+pub unsafe fn enable_cycle_counter() {}
+
+/// Read the time stamp counter.
+///
+/// # Safety
+///
+/// The kernel must leave `rdtsc` enabled at user level, which it does
+/// unless built otherwise.
+pub unsafe fn cycle_count() -> usize {
+    let low: u32;
+    let high: u32;
+    asm!("rdtsc", out("eax") low, out("edx") high, options(nomem, nostack));
+    ((high as usize) << 32) | low as usize
+}
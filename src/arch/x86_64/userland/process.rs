@@ -0,0 +1,214 @@
+use core::mem;
+use core::ptr;
+
+use selfe_sys::*;
+
+/// Set up the target registers and stack to pass the parameter.
+/// https://en.wikipedia.org/wiki/X86_calling_conventions#System_V_AMD64_ABI
+///
+/// Returns a tuple of (regs, stack_extent), where regs only has rdi and rsi
+/// set. x86 calls keep the return address on the stack rather than in a
+/// register, so there's always a word for it: the stack is left as though
+/// the entry point had just been called, and `set_thread_link_register` has
+/// nothing to do.
+pub(crate) unsafe fn setup_initial_stack_and_regs(
+    param: *const usize,
+    param_size: usize,
+    stack_top: *mut usize,
+    child_stack_top: usize,
+) -> (seL4_UserContext, usize) {
+    let word_size = mem::size_of::<usize>();
+    let mut regs: seL4_UserContext = mem::zeroed();
+
+    let params_extent = if param_size <= 2 * word_size {
+        let mut words = [0_usize; 2];
+        ptr::copy_nonoverlapping(
+            param as *const u8,
+            words.as_mut_ptr() as *mut u8,
+            param_size,
+        );
+        regs.rdi = words[0];
+        regs.rsi = words[1];
+        0
+    } else {
+        // The stack is 16-byte aligned where the return address is pushed
+        let padded_param_size = (param_size + 15) & !15;
+        let params = (stack_top as *mut u8).sub(padded_param_size);
+        ptr::copy_nonoverlapping(param as *const u8, params, param_size);
+        regs.rdi = child_stack_top - padded_param_size;
+        padded_param_size
+    };
+
+    let return_address = (stack_top as *mut u8).sub(params_extent + word_size) as *mut usize;
+    *return_address = crate::userland::process::yield_forever as usize;
+
+    (regs, params_extent + word_size)
+}
+
+/// The return address is set up by `setup_initial_stack_and_regs`, and is
+/// always `yield_forever`.
+pub(crate) fn set_thread_link_register(
+    _registers: &mut selfe_sys::seL4_UserContext,
+    _post_return_fn: fn() -> !,
+) {
+}
+
+pub(crate) fn set_thread_stack_pointer(registers: &mut selfe_sys::seL4_UserContext, sp: usize) {
+    registers.rsp = sp;
+}
+
+pub(crate) fn set_thread_program_counter(registers: &mut selfe_sys::seL4_UserContext, pc: usize) {
+    registers.rip = pc;
+}
+
+pub(crate) fn set_thread_tls_base(registers: &mut selfe_sys::seL4_UserContext, tls_base: usize) {
+    registers.fs_base = tls_base;
+}
+
+#[doc(hidden)]
+#[allow(dead_code)]
+#[cfg(feature = "test_support")]
+pub mod test {
+    use super::*;
+
+    #[doc(hidden)]
+    #[derive(Debug, Clone)]
+    pub struct ComparisonError {
+        name: &'static str,
+        expected: usize,
+        actual: usize,
+    }
+
+    fn check_return_address(
+        name: &'static str,
+        stack: &[usize; 256],
+        index: usize,
+    ) -> Result<(), ComparisonError> {
+        let expected = crate::userland::process::yield_forever as usize;
+        if stack[index] != expected {
+            return Err(ComparisonError {
+                name,
+                expected,
+                actual: stack[index],
+            });
+        }
+        Ok(())
+    }
+
+    fn smaller_than_16() -> Result<(), ComparisonError> {
+        let smaller_than_16: [usize; 1] = [42; 1];
+        let mut stack: [usize; 256] = [0; 256];
+        let stack_top = unsafe { (&mut stack as *mut [usize; 256] as *mut usize).add(256) };
+        let child_stack_top = 2048;
+        let (regs, stack_extent) = unsafe {
+            setup_initial_stack_and_regs(
+                &smaller_than_16 as *const usize,
+                mem::size_of::<[usize; 1]>(),
+                stack_top,
+                child_stack_top,
+            )
+        };
+        if stack_extent != 8 {
+            return Err(ComparisonError {
+                name: "smaller_than_16: stack extent was incorrect",
+                expected: 8,
+                actual: stack_extent,
+            });
+        }
+
+        if regs.rdi != 42 {
+            return Err(ComparisonError {
+                name: "smaller_than_16: rdi was incorrect",
+                expected: 42,
+                actual: regs.rdi,
+            });
+        }
+        check_return_address("smaller_than_16: return address was incorrect", &stack, 255)
+    }
+
+    fn is_16() -> Result<(), ComparisonError> {
+        let is_16: [usize; 2] = [42; 2];
+        let mut stack: [usize; 256] = [0; 256];
+        let stack_top = unsafe { (&mut stack as *mut [usize; 256] as *mut usize).add(256) };
+        let child_stack_top = 2048;
+        let (regs, stack_extent) = unsafe {
+            setup_initial_stack_and_regs(
+                &is_16 as *const usize,
+                mem::size_of::<[usize; 2]>(),
+                stack_top,
+                child_stack_top,
+            )
+        };
+        if stack_extent != 8 {
+            return Err(ComparisonError {
+                name: "is_16: stack extent was incorrect",
+                expected: 8,
+                actual: stack_extent,
+            });
+        }
+
+        if regs.rdi != 42 {
+            return Err(ComparisonError {
+                name: "is_16: rdi was incorrect",
+                expected: 42,
+                actual: regs.rdi,
+            });
+        }
+        if regs.rsi != 42 {
+            return Err(ComparisonError {
+                name: "is_16: rsi was incorrect",
+                expected: 42,
+                actual: regs.rsi,
+            });
+        }
+        check_return_address("is_16: return address was incorrect", &stack, 255)
+    }
+
+    fn larger_than_16() -> Result<(), ComparisonError> {
+        let larger_than_16: [usize; 10] = [42; 10];
+        let mut stack: [usize; 256] = [0; 256];
+        let stack_top = unsafe { (&mut stack as *mut [usize; 256] as *mut usize).add(256) };
+        let child_stack_top = 2048;
+        let (regs, stack_extent) = unsafe {
+            setup_initial_stack_and_regs(
+                &larger_than_16 as *const usize,
+                mem::size_of::<[usize; 10]>(),
+                stack_top,
+                child_stack_top,
+            )
+        };
+        if stack_extent != 88 {
+            return Err(ComparisonError {
+                name: "larger_than_16: stack extent was incorrect",
+                expected: 88,
+                actual: stack_extent,
+            });
+        }
+
+        if regs.rdi != child_stack_top - mem::size_of::<[usize; 10]>() {
+            return Err(ComparisonError {
+                name: "larger_than_16: rdi was incorrect",
+                expected: child_stack_top - mem::size_of::<[usize; 10]>(),
+                actual: regs.rdi,
+            });
+        }
+        for idx in 0..10 {
+            // we should copy into the last ten slots of the stack.
+            if stack[idx + 246] != 42 {
+                return Err(ComparisonError {
+                    name: "larger_than_16: stack was incorrect",
+                    expected: 42,
+                    actual: stack[idx + 246],
+                });
+            }
+        }
+        check_return_address("larger_than_16: return address was incorrect", &stack, 245)
+    }
+
+    pub fn test_stack_setup() -> Result<(), ComparisonError> {
+        smaller_than_16()?;
+        is_16()?;
+        larger_than_16()?;
+        Ok(())
+    }
+}
@@ -6,10 +6,12 @@ use typenum::operator_aliases::Diff;
 use typenum::*;
 
 use crate::arch::*;
+#[cfg(KernelIsMCS)]
+use crate::cap::SchedControl;
 use crate::cap::{
-    page_state, role, ASIDControl, AssignedASID, CNode, CNodeRole, CNodeSlots, Cap, IRQControl,
-    InternalASID, LocalCNode, LocalCNodeSlots, LocalCap, MaxIRQCount, Page, ThreadControlBlock,
-    Untyped,
+    page_state, role, ASIDControl, AssignedASID, CNode, CNodeRole, CNodeSlots, Cap, DomainControl,
+    IRQControl, InternalASID, LocalCNode, LocalCNodeSlots, LocalCap, MaxIRQCount, Page,
+    ThreadControlBlock, Untyped,
 };
 use crate::error::SeL4Error;
 use crate::pow::Pow;
@@ -17,6 +19,8 @@ use crate::userland::process::NeitherSendNorSync;
 use crate::userland::CapRights;
 use crate::vspace::VSpace;
 
+pub mod devicetree;
+
 // The root CNode radix is 19. Conservatively set aside 2^12 (the default root
 // cnode size) for system use. TODO: verify at build time that this is enough /
 // compute a better number
@@ -69,6 +73,18 @@ pub struct BootInfo<ASIDControlFreePools: Unsigned> {
 
     pub asid_control: LocalCap<ASIDControl<ASIDControlFreePools>>,
     pub irq_control: LocalCap<IRQControl>,
+    /// For placing threads in the kernel's scheduling domains
+    pub domain_control: LocalCap<DomainControl>,
+    /// The first core's scheduling control
+    #[cfg(KernelIsMCS)]
+    pub sched_control: LocalCap<SchedControl>,
+    /// For secure monitor calls, e.g. to restart the system
+    #[cfg(all(target_arch = "aarch64", KernelAllowSMCCalls))]
+    pub smc: LocalCap<crate::arch::cap::SMC>,
+    /// The IOMMU address space covering every device, for minting ones
+    /// for each device from
+    #[cfg(all(target_arch = "x86_64", KernelIOMMU))]
+    pub io_space: LocalCap<crate::arch::cap::IOSpace>,
     pub user_image: UserImage<role::Local>,
 
     #[allow(dead_code)]
@@ -131,6 +147,13 @@ impl BootInfo<op!(ASIDPoolCount - U1)> {
                 },
                 _role: PhantomData,
             },
+            domain_control: Cap::wrap_cptr(seL4_CapDomain as usize),
+            #[cfg(KernelIsMCS)]
+            sched_control: Cap::wrap_cptr(bootinfo.schedcontrol.start),
+            #[cfg(all(target_arch = "aarch64", KernelAllowSMCCalls))]
+            smc: Cap::wrap_cptr(seL4_CapSMC as usize),
+            #[cfg(all(target_arch = "x86_64", KernelIOMMU))]
+            io_space: Cap::wrap_cptr(seL4_CapIOSpace as usize),
             user_image,
             neither_send_nor_sync: Default::default(),
         }
@@ -142,6 +165,17 @@ impl UserImage<role::Local> {
         self.page_table_count
     }
 
+    /// Another reference to the same image, for a thread of the root task
+    /// that needs one of its own
+    pub(crate) fn alias(&self) -> Self {
+        UserImage {
+            frames_start_cptr: self.frames_start_cptr,
+            frames_count: self.frames_count,
+            page_table_count: self.page_table_count,
+            _role: PhantomData,
+        }
+    }
+
     // TODO this doesn't enforce the aliasing constraints we want at the type
     // level. This can be modeled as an array (or other sized thing) once we
     // know how big the user image is.
@@ -0,0 +1,552 @@
+//! Finding a platform's devices in its flattened device tree.
+//!
+//! The kernel passes the device tree the bootloader gave it on to the root
+//! task in the bootinfo's extra region, `DeviceTree::from_bootinfo`, and
+//! one embedded in the root task, e.g. a `.dtb` added to its selfe-arc,
+//! can be read with `DeviceTree::parse`. A device's node gives its MMIO
+//! ranges and IRQ numbers, and its untyped memory comes straight from the
+//! `DeviceAllocator` with `get_untyped_by_device`:
+//!
+//! ```ignore
+//! let tree = DeviceTree::from_bootinfo(raw_bootinfo).ok_or(TopLevelError::NoDeviceTree)??;
+//! let uart = tree.find_compatible("fsl,imx6q-uart").ok_or(TopLevelError::NoUart)?;
+//! let uart_ut = dev_allocator.get_untyped_by_device(&uart, 0, &mut slots)?;
+//! let uart_irq = uart.irq(0)?;
+//! ```
+//!
+//! Bus `ranges` are taken to be identity mappings, as they are for the
+//! SoCs ferros runs on, so a `reg`'s address is the physical address.
+use core::str;
+
+use selfe_sys::seL4_BootInfo;
+use typenum::Unsigned;
+
+use crate::alloc::micro_alloc::{
+    DeviceAllocator, DeviceRangeAllocError, PageAlignedAddressRange, PageAlignedAddressRangeError,
+};
+use crate::arch::PageBytes;
+use crate::cap::{memory_kind, LocalCap, WCNodeSlots, WUntyped};
+
+pub const FDT_MAGIC: u32 = 0xd00d_feed;
+
+/// The version of the format this reads, which a tree has to be
+/// compatible back to
+const FDT_LAST_COMPATIBLE_VERSION: u32 = 16;
+const FDT_HEADER_SIZE: usize = 40;
+
+const FDT_BEGIN_NODE: u32 = 0x1;
+const FDT_END_NODE: u32 = 0x2;
+const FDT_PROP: u32 = 0x3;
+const FDT_NOP: u32 = 0x4;
+const FDT_END: u32 = 0x9;
+
+/// libsel4's `SEL4_BOOTINFO_HEADER_FDT`, the id of the bootinfo extra
+/// region chunk holding the device tree
+const SEL4_BOOTINFO_HEADER_FDT: usize = 6;
+
+/// The deepest nodes can be nested in a tree
+pub const MAX_DEVICE_TREE_DEPTH: usize = 16;
+
+/// The `#address-cells` and `#size-cells` a node without them has
+const DEFAULT_ADDRESS_CELLS: u32 = 2;
+const DEFAULT_SIZE_CELLS: u32 = 1;
+const DEFAULT_INTERRUPT_CELLS: u32 = 1;
+
+/// Where the GIC's shared and private peripheral interrupts start
+const GIC_SPI_BASE: usize = 32;
+const GIC_PPI_BASE: usize = 16;
+
+#[derive(Debug, PartialEq)]
+pub enum DeviceTreeError {
+    /// The blob doesn't start with `FDT_MAGIC`
+    BadMagic,
+    /// The blob's too new a version of the format to read
+    UnsupportedVersion(u32),
+    /// The blob ends before one of its blocks does
+    Truncated,
+    /// Something other than a node or property where one was expected
+    BadToken {
+        offset: usize,
+    },
+    /// Nodes are nested deeper than `MAX_DEVICE_TREE_DEPTH`
+    TooDeep,
+    /// The node has no `reg` entry with that index
+    NoSuchReg {
+        index: usize,
+    },
+    /// The node has no interrupt with that index
+    NoSuchInterrupt {
+        index: usize,
+    },
+    /// Addresses or sizes wider than a word
+    UnsupportedCells,
+    PageAlignedAddressRangeError(PageAlignedAddressRangeError),
+    DeviceRangeAllocError(DeviceRangeAllocError),
+}
+
+impl From<PageAlignedAddressRangeError> for DeviceTreeError {
+    fn from(e: PageAlignedAddressRangeError) -> Self {
+        DeviceTreeError::PageAlignedAddressRangeError(e)
+    }
+}
+
+impl From<DeviceRangeAllocError> for DeviceTreeError {
+    fn from(e: DeviceRangeAllocError) -> Self {
+        DeviceTreeError::DeviceRangeAllocError(e)
+    }
+}
+
+/// A device's registers, `size` bytes at physical address `paddr`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MmioRange {
+    pub paddr: usize,
+    pub size: usize,
+}
+
+impl MmioRange {
+    /// The smallest range of pages an untyped can cover that holds the
+    /// registers, for `get_untyped_by_address_range`
+    pub fn page_range(&self) -> Result<PageAlignedAddressRange, DeviceTreeError> {
+        let start = self.paddr & !(PageBytes::USIZE - 1);
+        let end = self.paddr.saturating_add(self.size);
+        let size = core::cmp::max(end - start, PageBytes::USIZE).next_power_of_two();
+        Ok(PageAlignedAddressRange::new_by_size(start, size)?)
+    }
+}
+
+/// A flattened device tree, read in place
+#[derive(Debug, Clone, Copy)]
+pub struct DeviceTree<'a> {
+    structure: &'a [u8],
+    strings: &'a [u8],
+}
+
+impl<'a> DeviceTree<'a> {
+    pub fn parse(blob: &'a [u8]) -> Result<DeviceTree<'a>, DeviceTreeError> {
+        if blob.len() < FDT_HEADER_SIZE {
+            return Err(DeviceTreeError::Truncated);
+        }
+        let header = |field: usize| be32(blob, field * 4).unwrap_or(0);
+        if header(0) != FDT_MAGIC {
+            return Err(DeviceTreeError::BadMagic);
+        }
+        let total_size = header(1) as usize;
+        let (off_structure, off_strings) = (header(2) as usize, header(3) as usize);
+        let last_compatible_version = header(6);
+        let (size_strings, size_structure) = (header(8) as usize, header(9) as usize);
+        if last_compatible_version > FDT_LAST_COMPATIBLE_VERSION {
+            return Err(DeviceTreeError::UnsupportedVersion(last_compatible_version));
+        }
+        let blob = blob.get(..total_size).ok_or(DeviceTreeError::Truncated)?;
+        let block = |offset: usize, size: usize| {
+            offset
+                .checked_add(size)
+                .and_then(|end| blob.get(offset..end))
+                .ok_or(DeviceTreeError::Truncated)
+        };
+        Ok(DeviceTree {
+            structure: block(off_structure, size_structure)?,
+            strings: block(off_strings, size_strings)?,
+        })
+    }
+
+    /// Read the tree at `addr`, whose header says how big it is.
+    ///
+    /// # Safety
+    ///
+    /// `addr` has to be mapped for as many bytes as the header says, for
+    /// as long as the tree's used.
+    pub unsafe fn from_addr(addr: usize) -> Result<DeviceTree<'static>, DeviceTreeError> {
+        let header = core::slice::from_raw_parts(addr as *const u8, FDT_HEADER_SIZE);
+        if be32(header, 0) != Some(FDT_MAGIC) {
+            return Err(DeviceTreeError::BadMagic);
+        }
+        let total_size = be32(header, 4).unwrap_or(0) as usize;
+        DeviceTree::parse(core::slice::from_raw_parts(addr as *const u8, total_size))
+    }
+
+    /// The tree the kernel passed on to the root task, if it was given
+    /// one.
+    pub fn from_bootinfo(
+        bootinfo: &'static seL4_BootInfo,
+    ) -> Option<Result<DeviceTree<'static>, DeviceTreeError>> {
+        // The extra region follows the bootinfo's own page, as a run of
+        // chunks each starting with an id and a length that includes the
+        // two words of header
+        const WORD: usize = core::mem::size_of::<usize>();
+        let mut chunk = bootinfo as *const seL4_BootInfo as usize + PageBytes::USIZE;
+        let end = chunk + bootinfo.extraLen;
+        while chunk + 2 * WORD <= end {
+            let (id, len) =
+                unsafe { (*(chunk as *const usize), *((chunk + WORD) as *const usize)) };
+            if id == SEL4_BOOTINFO_HEADER_FDT {
+                return Some(unsafe { DeviceTree::from_addr(chunk + 2 * WORD) });
+            }
+            if len == 0 {
+                break;
+            }
+            chunk += len;
+        }
+        None
+    }
+
+    /// Every node in the tree, in the order they're laid out
+    pub fn nodes(&self) -> Nodes<'a> {
+        Nodes {
+            tree: *self,
+            offset: 0,
+            depth: 0,
+            scopes: [Scope::ROOT; MAX_DEVICE_TREE_DEPTH],
+            done: false,
+        }
+    }
+
+    /// The first node with `compatible` among its compatible strings
+    pub fn find_compatible(&self, compatible: &str) -> Option<Node<'a>> {
+        self.nodes()
+            .filter_map(Result::ok)
+            .find(|node| node.is_compatible(compatible))
+    }
+
+    /// The node at `path`, e.g. `/soc/aips-bus@2000000/serial@2020000`,
+    /// where a name without a unit address matches one with any
+    pub fn find_node(&self, path: &str) -> Option<Node<'a>> {
+        let mut components = path.split('/').filter(|c| !c.is_empty());
+        let mut wanted = components.next();
+        let mut matched_depth = 0;
+        for node in self.nodes() {
+            let node = node.ok()?;
+            if node.depth <= matched_depth && node.depth > 0 {
+                // Left the subtree the rest of the path would be in
+                return None;
+            }
+            let name = match wanted {
+                None if node.depth == 0 => return Some(node),
+                None => return None,
+                Some(name) => name,
+            };
+            if node.depth == matched_depth + 1 && node_name_matches(node.name, name) {
+                matched_depth = node.depth;
+                wanted = components.next();
+                if wanted.is_none() {
+                    return Some(node);
+                }
+            }
+        }
+        None
+    }
+
+    /// The node whose `phandle` is `phandle`, as other nodes refer to it
+    pub fn find_phandle(&self, phandle: u32) -> Option<Node<'a>> {
+        self.nodes().filter_map(Result::ok).find(|node| {
+            node.property("phandle")
+                .or_else(|| node.property("linux,phandle"))
+                .and_then(|p| be32(p, 0))
+                == Some(phandle)
+        })
+    }
+
+    /// The node with registers starting at `paddr`
+    pub fn find_by_address(&self, paddr: usize) -> Option<Node<'a>> {
+        self.nodes()
+            .filter_map(Result::ok)
+            .find(|node| node.regs().any(|r| r.paddr == paddr))
+    }
+
+    fn string(&self, offset: usize) -> Option<&'a str> {
+        c_str(self.strings.get(offset..)?)
+    }
+}
+
+/// What a node's properties say about its children
+#[derive(Debug, Clone, Copy)]
+struct Scope {
+    address_cells: u32,
+    size_cells: u32,
+    interrupt_parent: Option<u32>,
+}
+
+impl Scope {
+    const ROOT: Scope = Scope {
+        address_cells: DEFAULT_ADDRESS_CELLS,
+        size_cells: DEFAULT_SIZE_CELLS,
+        interrupt_parent: None,
+    };
+}
+
+/// An iterator over a tree's nodes, see `DeviceTree::nodes`
+pub struct Nodes<'a> {
+    tree: DeviceTree<'a>,
+    offset: usize,
+    depth: usize,
+    /// The scope each node on the way down to `depth` sets up for its
+    /// children
+    scopes: [Scope; MAX_DEVICE_TREE_DEPTH],
+    done: bool,
+}
+
+impl<'a> Iterator for Nodes<'a> {
+    type Item = Result<Node<'a>, DeviceTreeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let structure = self.tree.structure;
+        loop {
+            let token = match be32(structure, self.offset) {
+                Some(token) => token,
+                None => return self.fail(DeviceTreeError::Truncated),
+            };
+            self.offset += 4;
+            match token {
+                FDT_BEGIN_NODE => {
+                    let name = match structure.get(self.offset..).and_then(c_str) {
+                        Some(name) => name,
+                        None => return self.fail(DeviceTreeError::Truncated),
+                    };
+                    self.offset = align4(self.offset + name.len() + 1);
+                    if self.depth >= MAX_DEVICE_TREE_DEPTH {
+                        return self.fail(DeviceTreeError::TooDeep);
+                    }
+                    let parent = if self.depth == 0 {
+                        Scope::ROOT
+                    } else {
+                        self.scopes[self.depth - 1]
+                    };
+                    let mut node = Node {
+                        tree: self.tree,
+                        name,
+                        depth: self.depth,
+                        properties_offset: self.offset,
+                        address_cells: parent.address_cells,
+                        size_cells: parent.size_cells,
+                        interrupt_parent: parent.interrupt_parent,
+                    };
+                    if let Some(phandle) =
+                        node.property("interrupt-parent").and_then(|p| be32(p, 0))
+                    {
+                        node.interrupt_parent = Some(phandle);
+                    }
+                    self.scopes[self.depth] = Scope {
+                        address_cells: node
+                            .cells("#address-cells")
+                            .unwrap_or(DEFAULT_ADDRESS_CELLS),
+                        size_cells: node.cells("#size-cells").unwrap_or(DEFAULT_SIZE_CELLS),
+                        interrupt_parent: node.interrupt_parent,
+                    };
+                    self.depth += 1;
+                    return Some(Ok(node));
+                }
+                FDT_END_NODE => {
+                    if self.depth == 0 {
+                        return self.fail(DeviceTreeError::BadToken {
+                            offset: self.offset - 4,
+                        });
+                    }
+                    self.depth -= 1;
+                }
+                FDT_PROP => match property_at(structure, self.offset - 4) {
+                    Some((_, _, next)) => self.offset = next,
+                    None => return self.fail(DeviceTreeError::Truncated),
+                },
+                FDT_NOP => (),
+                FDT_END => {
+                    self.done = true;
+                    return None;
+                }
+                _ => {
+                    return self.fail(DeviceTreeError::BadToken {
+                        offset: self.offset - 4,
+                    })
+                }
+            }
+        }
+    }
+}
+
+impl<'a> Nodes<'a> {
+    /// Give back `error`, and stop there
+    fn fail(&mut self, error: DeviceTreeError) -> Option<Result<Node<'a>, DeviceTreeError>> {
+        self.done = true;
+        Some(Err(error))
+    }
+}
+
+/// A node of a `DeviceTree`
+#[derive(Debug, Clone, Copy)]
+pub struct Node<'a> {
+    tree: DeviceTree<'a>,
+    name: &'a str,
+    depth: usize,
+    properties_offset: usize,
+    /// The parent's `#address-cells` and `#size-cells`, which this node's
+    /// `reg` is laid out by
+    address_cells: u32,
+    size_cells: u32,
+    interrupt_parent: Option<u32>,
+}
+
+impl<'a> Node<'a> {
+    /// The node's name, with its unit address, e.g. `serial@2020000`, or
+    /// empty for the root
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// How many nodes this one's nested in
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// The value of property `name`
+    pub fn property(&self, name: &str) -> Option<&'a [u8]> {
+        self.properties()
+            .find(|(n, _)| *n == name)
+            .map(|(_, value)| value)
+    }
+
+    /// The node's properties' names and values
+    pub fn properties(&self) -> impl Iterator<Item = (&'a str, &'a [u8])> + 'a {
+        let tree = self.tree;
+        let mut offset = self.properties_offset;
+        core::iter::from_fn(move || loop {
+            match be32(tree.structure, offset)? {
+                FDT_NOP => offset += 4,
+                FDT_PROP => {
+                    let (name_offset, value, next) = property_at(tree.structure, offset)?;
+                    offset = next;
+                    return Some((tree.string(name_offset)?, value));
+                }
+                _ => return None,
+            }
+        })
+    }
+
+    /// Whether `compatible` is one of the node's compatible strings
+    pub fn is_compatible(&self, compatible: &str) -> bool {
+        self.compatible().any(|c| c == compatible)
+    }
+
+    /// The node's compatible strings, most specific first
+    pub fn compatible(&self) -> impl Iterator<Item = &'a str> + 'a {
+        self.property("compatible")
+            .unwrap_or(&[])
+            .split(|b| *b == 0)
+            .filter(|s| !s.is_empty())
+            .filter_map(|s| str::from_utf8(s).ok())
+    }
+
+    /// The node's register ranges, in `reg` order
+    pub fn regs(&self) -> impl Iterator<Item = MmioRange> + 'a {
+        let (address_cells, size_cells) = (self.address_cells as usize, self.size_cells as usize);
+        let entry_size = (address_cells + size_cells) * 4;
+        let reg: &'a [u8] = if entry_size == 0 || address_cells > 2 || size_cells > 2 {
+            &[]
+        } else {
+            self.property("reg").unwrap_or(&[])
+        };
+        reg.chunks_exact(core::cmp::max(entry_size, 1))
+            .filter_map(move |entry| {
+                Some(MmioRange {
+                    paddr: read_cells(entry, 0, address_cells)?,
+                    size: read_cells(entry, address_cells, size_cells)?,
+                })
+            })
+    }
+
+    /// Register range `index`
+    pub fn reg(&self, index: usize) -> Result<MmioRange, DeviceTreeError> {
+        if self.address_cells > 2 || self.size_cells > 2 {
+            return Err(DeviceTreeError::UnsupportedCells);
+        }
+        self.regs()
+            .nth(index)
+            .ok_or(DeviceTreeError::NoSuchReg { index })
+    }
+
+    /// The IRQ number of interrupt `index`, as the kernel numbers it.
+    ///
+    /// An interrupt described to a GIC, as its type, number and flags, is
+    /// numbered past the GIC's SGIs and PPIs, and any other interrupt
+    /// controller's are taken as they are.
+    pub fn irq(&self, index: usize) -> Result<usize, DeviceTreeError> {
+        let parent = self
+            .interrupt_parent
+            .and_then(|phandle| self.tree.find_phandle(phandle));
+        let cells = parent
+            .and_then(|p| p.cells("#interrupt-cells"))
+            .unwrap_or(DEFAULT_INTERRUPT_CELLS) as usize;
+        let is_gic = parent.map_or(false, |p| p.compatible().any(|c| c.contains("gic")));
+        let interrupts = self.property("interrupts").unwrap_or(&[]);
+        let offset = index * cells;
+        if cells == 0 || (offset + cells) * 4 > interrupts.len() {
+            return Err(DeviceTreeError::NoSuchInterrupt { index });
+        }
+        let cell = |n| be32(interrupts, (offset + n) * 4).map(|c| c as usize);
+        let irq = if is_gic && cells >= 3 {
+            match cell(0) {
+                Some(0) => cell(1).map(|n| n + GIC_SPI_BASE),
+                Some(1) => cell(1).map(|n| n + GIC_PPI_BASE),
+                _ => None,
+            }
+        } else {
+            cell(0)
+        };
+        irq.ok_or(DeviceTreeError::NoSuchInterrupt { index })
+    }
+
+    fn cells(&self, name: &str) -> Option<u32> {
+        self.property(name).and_then(|p| be32(p, 0))
+    }
+}
+
+impl DeviceAllocator {
+    /// The device untyped covering register range `reg_index` of `node`,
+    /// see `get_untyped_by_address_range`
+    pub fn get_untyped_by_device(
+        &mut self,
+        node: &Node,
+        reg_index: usize,
+        slots: &mut WCNodeSlots,
+    ) -> Result<LocalCap<WUntyped<memory_kind::Device>>, DeviceTreeError> {
+        let range = node.reg(reg_index)?.page_range()?;
+        Ok(self.get_untyped_by_address_range(range, slots)?)
+    }
+}
+
+fn be32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// A value of one or two cells at cell `first` of `data`
+fn read_cells(data: &[u8], first: usize, count: usize) -> Option<usize> {
+    (first..first + count).try_fold(0usize, |value, cell| {
+        let cell = be32(data, cell * 4)? as usize;
+        Some(value.checked_shl(32).unwrap_or(0) | cell)
+    })
+}
+
+/// The name offset and value of the property token at `offset`, and the
+/// offset of the token after it
+fn property_at(structure: &[u8], offset: usize) -> Option<(usize, &[u8], usize)> {
+    let len = be32(structure, offset + 4)? as usize;
+    let name_offset = be32(structure, offset + 8)? as usize;
+    let value_start = offset + 12;
+    let value = structure.get(value_start..value_start.checked_add(len)?)?;
+    Some((name_offset, value, align4(value_start + len)))
+}
+
+fn c_str(data: &[u8]) -> Option<&str> {
+    let end = data.iter().position(|b| *b == 0)?;
+    str::from_utf8(&data[..end]).ok()
+}
+
+fn align4(offset: usize) -> usize {
+    (offset + 3) & !3
+}
+
+fn node_name_matches(name: &str, wanted: &str) -> bool {
+    name == wanted || (!wanted.contains('@') && name.split('@').next() == Some(wanted))
+}
@@ -4,7 +4,7 @@ use core::ops::Sub;
 use typenum::*;
 
 use crate::arch;
-use crate::cap::{Cap, CapType, LocalCap, UnassignedASID};
+use crate::cap::{CNodeRole, CNodeSlot, Cap, CapType, LocalCNode, LocalCap, UnassignedASID};
 use crate::error::SeL4Error;
 use crate::userland::CapRights;
 
@@ -17,7 +17,44 @@ pub struct ASIDPool<FreeSlots: Unsigned> {
 
 impl<FreeSlots: Unsigned> CapType for ASIDPool<FreeSlots> {}
 
+/// Weakly-typed (runtime-counted) ASIDPool
+#[derive(Debug)]
+pub struct WASIDPool {
+    pub(crate) id: usize,
+    pub(crate) next_free_slot: usize,
+    pub(crate) free_slots: usize,
+}
+
+impl CapType for WASIDPool {}
+pub type WeakASIDPool = LocalCap<WASIDPool>;
+
+#[derive(Debug, PartialEq)]
+pub enum ASIDPoolError {
+    NotEnoughASIDs { requested: usize, available: usize },
+    SeL4Error(SeL4Error),
+}
+
+impl From<SeL4Error> for ASIDPoolError {
+    fn from(e: SeL4Error) -> Self {
+        ASIDPoolError::SeL4Error(e)
+    }
+}
+
 impl<FreeSlots: Unsigned> LocalCap<ASIDPool<FreeSlots>> {
+    /// Erase the type-level count of free slots, tracking it at
+    /// runtime instead.
+    pub fn weaken(self) -> LocalCap<WASIDPool> {
+        Cap {
+            cptr: self.cptr,
+            _role: PhantomData,
+            cap_data: WASIDPool {
+                id: self.cap_data.id,
+                next_free_slot: self.cap_data.next_free_slot,
+                free_slots: FreeSlots::USIZE,
+            },
+        }
+    }
+
     pub fn alloc(
         self,
     ) -> (
@@ -100,6 +137,48 @@ impl<FreeSlots: Unsigned> LocalCap<ASIDPool<FreeSlots>> {
         ))
     }
 
+    /// Split off the first `Count` ASIDs as a pool of their own, copied
+    /// into `slot`, keeping the rest. With `slot` in a child's CNode, the
+    /// child can act as a supervisor of its own, creating processes with
+    /// its share of the ASIDs and splitting it further for its children.
+    pub fn split_off<Count: Unsigned, Role: CNodeRole>(
+        self,
+        slot: CNodeSlot<Role>,
+        src_cnode: &LocalCap<LocalCNode>,
+    ) -> Result<
+        (
+            Cap<ASIDPool<Count>, Role>,
+            LocalCap<ASIDPool<Diff<FreeSlots, Count>>>,
+        ),
+        SeL4Error,
+    >
+    where
+        FreeSlots: Sub<Count>,
+        Diff<FreeSlots, Count>: Unsigned,
+    {
+        let offset = self.unchecked_copy(src_cnode, slot, CapRights::RWG)?;
+        Ok((
+            Cap {
+                cptr: offset,
+                _role: PhantomData,
+                cap_data: ASIDPool {
+                    id: self.cap_data.id,
+                    next_free_slot: self.cap_data.next_free_slot,
+                    _free_slots: PhantomData,
+                },
+            },
+            Cap {
+                cptr: self.cptr,
+                _role: PhantomData,
+                cap_data: ASIDPool {
+                    id: self.cap_data.id,
+                    next_free_slot: self.cap_data.next_free_slot + Count::USIZE,
+                    _free_slots: PhantomData,
+                },
+            },
+        ))
+    }
+
     pub fn truncate<OutFreeSlots: Unsigned>(self) -> LocalCap<ASIDPool<OutFreeSlots>>
     where
         FreeSlots: IsGreaterOrEqual<OutFreeSlots, Output = True>,
@@ -116,6 +195,123 @@ impl<FreeSlots: Unsigned> LocalCap<ASIDPool<FreeSlots>> {
         }
     }
 }
+impl LocalCap<WASIDPool> {
+    /// How many ASIDs are left to hand out
+    pub fn available(&self) -> usize {
+        self.cap_data.free_slots
+    }
+
+    pub fn alloc(&mut self) -> Result<LocalCap<UnassignedASID>, ASIDPoolError> {
+        let slot = self.take(1)?;
+        Ok(Cap {
+            cptr: self.cptr,
+            _role: PhantomData,
+            cap_data: UnassignedASID {
+                asid: InternalASID {
+                    asid: (self.cap_data.id << arch::ASIDLowBits::USIZE) | slot,
+                },
+            },
+        })
+    }
+
+    /// Split off a pool of its own with `count` of the ASIDs, e.g. for
+    /// handing to another subsystem.
+    pub fn split(&mut self, count: usize) -> Result<LocalCap<WASIDPool>, ASIDPoolError> {
+        let next_free_slot = self.take(count)?;
+        Ok(Cap {
+            cptr: self.cptr,
+            _role: PhantomData,
+            cap_data: WASIDPool {
+                id: self.cap_data.id,
+                next_free_slot,
+                free_slots: count,
+            },
+        })
+    }
+
+    /// Split off `Count` of the ASIDs as a strongly-typed pool
+    pub fn split_strong<Count: Unsigned>(
+        &mut self,
+    ) -> Result<LocalCap<ASIDPool<Count>>, ASIDPoolError> {
+        let next_free_slot = self.take(Count::USIZE)?;
+        Ok(Cap {
+            cptr: self.cptr,
+            _role: PhantomData,
+            cap_data: ASIDPool {
+                id: self.cap_data.id,
+                next_free_slot,
+                _free_slots: PhantomData,
+            },
+        })
+    }
+
+    /// Like `split`, with the new pool copied into `slot`, e.g. in a
+    /// child's CNode for a child supervising processes of its own.
+    pub fn split_into<Role: CNodeRole>(
+        &mut self,
+        count: usize,
+        slot: CNodeSlot<Role>,
+        src_cnode: &LocalCap<LocalCNode>,
+    ) -> Result<Cap<WASIDPool, Role>, ASIDPoolError> {
+        self.check_available(count)?;
+        let offset = self.unchecked_copy(src_cnode, slot, CapRights::RWG)?;
+        let next_free_slot = self.take(count)?;
+        Ok(Cap {
+            cptr: offset,
+            _role: PhantomData,
+            cap_data: WASIDPool {
+                id: self.cap_data.id,
+                next_free_slot,
+                free_slots: count,
+            },
+        })
+    }
+
+    /// Like `split_strong`, with the new pool copied into `slot`
+    pub fn split_strong_into<Count: Unsigned, Role: CNodeRole>(
+        &mut self,
+        slot: CNodeSlot<Role>,
+        src_cnode: &LocalCap<LocalCNode>,
+    ) -> Result<Cap<ASIDPool<Count>, Role>, ASIDPoolError> {
+        self.check_available(Count::USIZE)?;
+        let offset = self.unchecked_copy(src_cnode, slot, CapRights::RWG)?;
+        let next_free_slot = self.take(Count::USIZE)?;
+        Ok(Cap {
+            cptr: offset,
+            _role: PhantomData,
+            cap_data: ASIDPool {
+                id: self.cap_data.id,
+                next_free_slot,
+                _free_slots: PhantomData,
+            },
+        })
+    }
+
+    /// Allocate ASIDs until the pool runs out
+    pub fn drain(&mut self) -> impl Iterator<Item = LocalCap<UnassignedASID>> + '_ {
+        core::iter::from_fn(move || self.alloc().ok())
+    }
+
+    /// Reserve `count` slots, returning the first of them
+    fn take(&mut self, count: usize) -> Result<usize, ASIDPoolError> {
+        self.check_available(count)?;
+        let first = self.cap_data.next_free_slot;
+        self.cap_data.next_free_slot += count;
+        self.cap_data.free_slots -= count;
+        Ok(first)
+    }
+
+    fn check_available(&self, count: usize) -> Result<(), ASIDPoolError> {
+        if count > self.cap_data.free_slots {
+            return Err(ASIDPoolError::NotEnoughASIDs {
+                requested: count,
+                available: self.cap_data.free_slots,
+            });
+        }
+        Ok(())
+    }
+}
+
 /// Internal-only newtype wrapper around a single unique ASID
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub(crate) struct InternalASID {
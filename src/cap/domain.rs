@@ -0,0 +1,42 @@
+//! The kernel's domain scheduler.
+//!
+//! The kernel's configuration fixes a number of domains, and a schedule
+//! of how long each gets in turn. Only threads in the current domain are
+//! scheduled, whatever their priority, so groups of processes placed in
+//! different domains are separated in time as well as in space. Every
+//! thread starts out in domain 0.
+use selfe_sys::*;
+
+use crate::cap::{CapType, LocalCap, PhantomCap, ThreadControlBlock};
+use crate::error::{ErrorExt, SeL4Error};
+
+/// The authority to move threads between domains, handed to the root task
+/// in its bootinfo
+#[derive(Debug)]
+pub struct DomainControl {}
+
+impl CapType for DomainControl {}
+
+impl PhantomCap for DomainControl {
+    fn phantom_instance() -> Self {
+        Self {}
+    }
+}
+
+/// One of the kernel's scheduling domains, which must be less than the
+/// number the kernel is configured with (`KernelNumDomains`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Domain(pub u8);
+
+impl LocalCap<DomainControl> {
+    /// Move the thread `tcb` to `domain`.
+    pub fn set_domain(
+        &self,
+        tcb: &LocalCap<ThreadControlBlock>,
+        domain: Domain,
+    ) -> Result<(), SeL4Error> {
+        unsafe { seL4_DomainSet_Set(self.cptr, domain.0, tcb.cptr) }
+            .as_result()
+            .map_err(SeL4Error::DomainSetSet)
+    }
+}
@@ -2,7 +2,11 @@ use typenum::*;
 
 use selfe_sys::*;
 
-use crate::cap::{CapType, CopyAliasable, DirectRetype, Mintable, PhantomCap};
+use crate::arch;
+use crate::cap::{
+    Badge, CapType, CopyAliasable, Delible, DirectRetype, LocalCap, Mintable, Movable, PhantomCap,
+};
+use crate::userland::{IPCError, MessageInfo};
 
 #[derive(Debug)]
 pub struct Endpoint {}
@@ -26,9 +30,105 @@ impl<'a> From<&'a Endpoint> for Endpoint {
 
 impl Mintable for Endpoint {}
 
+impl Movable for Endpoint {}
+
+impl Delible for Endpoint {}
+
 impl DirectRetype for Endpoint {
     type SizeBits = U4;
     fn sel4_type_id() -> usize {
         api_object_seL4_EndpointObject as usize
     }
 }
+
+/// Raw message passing on an endpoint, a word at a time through the
+/// thread's IPC buffer.
+///
+/// These are the primitives `Sender`, `Caller` and the like are built on,
+/// for IPC patterns they don't cover. Nothing checks that both sides
+/// agree on what the words mean; a badge to tell senders apart comes from
+/// `mint`ing the endpoint.
+impl LocalCap<Endpoint> {
+    /// Blocking send of `words`, labeled with `label`
+    pub fn send(&self, label: usize, words: &[usize]) -> Result<(), IPCError> {
+        let info = unsafe { write_message(label, words) }?;
+        unsafe { seL4_Send(self.cptr, info) };
+        Ok(())
+    }
+
+    /// Send `words` if a receiver is already waiting, dropping them
+    /// otherwise
+    pub fn nb_send(&self, label: usize, words: &[usize]) -> Result<(), IPCError> {
+        let info = unsafe { write_message(label, words) }?;
+        unsafe { seL4_NBSend(self.cptr, info) };
+        Ok(())
+    }
+
+    /// Blocking receive into `words`, returning the message's info and the
+    /// badge of the capability it was sent on
+    pub fn recv(&self, words: &mut [usize]) -> Result<(MessageInfo, Badge), IPCError> {
+        let mut sender_badge: usize = 0;
+        let info: MessageInfo =
+            unsafe { seL4_Recv(self.cptr, &mut sender_badge as *mut usize) }.into();
+        unsafe { read_message(&info, words) }?;
+        Ok((info, Badge::from(sender_badge)))
+    }
+
+    /// Receive into `words` if a message is already waiting
+    pub fn nb_recv(&self, words: &mut [usize]) -> Result<Option<(MessageInfo, Badge)>, IPCError> {
+        let mut sender_badge: usize = 0;
+        let info: MessageInfo =
+            unsafe { seL4_NBRecv(self.cptr, &mut sender_badge as *mut usize) }.into();
+        // Nothing to receive comes back as an empty message from badge 0,
+        // which is ambiguous with an actual empty unbadged message
+        if sender_badge == 0 && info.label() == 0 && info.length_words() == 0 {
+            return Ok(None);
+        }
+        unsafe { read_message(&info, words) }?;
+        Ok(Some((info, Badge::from(sender_badge))))
+    }
+
+    /// Send `words` and wait for the reply, received into `reply`
+    pub fn call(
+        &self,
+        label: usize,
+        words: &[usize],
+        reply: &mut [usize],
+    ) -> Result<MessageInfo, IPCError> {
+        let info = unsafe { write_message(label, words) }?;
+        let reply_info: MessageInfo = unsafe { seL4_Call(self.cptr, info) }.into();
+        if reply_info.length_words() > reply.len() {
+            return Err(IPCError::ResponseSizeTooBig);
+        }
+        unsafe { read_message(&reply_info, reply) }?;
+        Ok(reply_info)
+    }
+}
+
+unsafe fn write_message(label: usize, words: &[usize]) -> Result<seL4_MessageInfo_t, IPCError> {
+    let buffer = &mut *seL4_GetIPCBuffer();
+    if words.len() > buffer.msg.len() {
+        return Err(IPCError::RequestSizeTooBig);
+    }
+    for (mr, word) in buffer.msg.iter_mut().zip(words) {
+        *mr = arch::to_sel4_word(*word);
+    }
+    Ok(seL4_MessageInfo_new(
+        arch::to_sel4_word(label),       // label,
+        0,                               // capsUnwrapped,
+        0,                               // extraCaps,
+        arch::to_sel4_word(words.len()), // length in words!
+    ))
+}
+
+unsafe fn read_message(info: &MessageInfo, words: &mut [usize]) -> Result<(), IPCError> {
+    let buffer = &*seL4_GetIPCBuffer();
+    let length = info.length_words();
+    if length > words.len() {
+        return Err(IPCError::RequestSizeTooBig);
+    }
+    for (word, mr) in words.iter_mut().zip(&buffer.msg[..length]) {
+        *word = *mr as usize;
+    }
+    Ok(())
+}
@@ -9,6 +9,9 @@ use crate::cap::{
 };
 use crate::error::{ErrorExt, SeL4Error};
 
+#[cfg(target_arch = "x86_64")]
+use crate::arch::cap::irq_control_get;
+
 pub type MaxIRQCount = U1024;
 
 // The goal of tracking is to prevent accidental double-binding to a single IRQ
@@ -47,7 +50,8 @@ impl LocalCap<IRQControl> {
     where
         IRQ: IsLess<U1024, Output = True>,
     {
-        let destination_relative_cptr = self.internal_create_handler(dest_slot, IRQ::U16)?;
+        let destination_relative_cptr =
+            self.internal_create_handler(dest_slot, IRQ::U16, irq_control_get)?;
         Ok(Cap {
             cptr: destination_relative_cptr,
             cap_data: IRQHandler {
@@ -66,7 +70,8 @@ impl LocalCap<IRQControl> {
         if irq >= MaxIRQCount::U16 {
             return Err(IRQError::OutOfRangeIRQ(irq));
         }
-        let destination_relative_cptr = self.internal_create_handler(dest_slot, irq)?;
+        let destination_relative_cptr =
+            self.internal_create_handler(dest_slot, irq, irq_control_get)?;
         Ok(Cap {
             cptr: destination_relative_cptr,
             cap_data: irq_handler::weak::WIRQHandler {
@@ -77,29 +82,40 @@ impl LocalCap<IRQControl> {
         })
     }
 
-    fn internal_create_handler<DestRole: CNodeRole>(
+    /// Claim `irq`, with `get` making the handler for it from this IRQ
+    /// control's cptr, the IRQ, and the destination cnode and slot
+    pub(crate) fn internal_create_handler<DestRole: CNodeRole, F>(
         &mut self,
         dest_slot: CNodeSlot<DestRole>,
         irq: u16,
-    ) -> Result<usize, IRQError> {
+        get: F,
+    ) -> Result<usize, IRQError>
+    where
+        F: FnOnce(usize, u16, usize, usize) -> seL4_Error,
+    {
         let (dest_cptr, dest_offset, _) = dest_slot.elim();
 
         if !self.cap_data.available[usize::from(irq)] {
             return Err(IRQError::UnavailableIRQ(irq));
         }
-        unsafe {
-            seL4_IRQControl_Get(
-                self.cptr,           // service/authority
-                usize::from(irq),    // irq
-                dest_cptr,           // root
-                dest_offset,         // index
-                seL4_WordBits as u8, // depth
-            )
-        }
-        .as_result()
-        .map_err(|e| IRQError::SeL4Error(SeL4Error::IRQControlGet(e)))?;
+        get(self.cptr, irq, dest_cptr, dest_offset)
+            .as_result()
+            .map_err(|e| IRQError::SeL4Error(SeL4Error::IRQControlGet(e)))?;
 
         self.cap_data.available[usize::from(irq)] = false;
         Ok(dest_offset)
     }
 }
+
+#[cfg(not(target_arch = "x86_64"))]
+fn irq_control_get(control: usize, irq: u16, root: usize, index: usize) -> seL4_Error {
+    unsafe {
+        seL4_IRQControl_Get(
+            control,             // service/authority
+            usize::from(irq),    // irq
+            root,                // root
+            index,               // index
+            seL4_WordBits as u8, // depth
+        )
+    }
+}
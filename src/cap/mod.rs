@@ -11,6 +11,7 @@ mod asid_control;
 mod asid_pool;
 mod badge;
 mod cnode;
+mod domain;
 mod endpoint;
 mod fault_reply_endpoint;
 mod irq_control;
@@ -18,6 +19,10 @@ pub mod irq_handler;
 mod notification;
 mod page;
 mod page_table;
+#[cfg(KernelIsMCS)]
+mod sched_context;
+mod slot_allocator;
+mod slot_compactor;
 mod tcb;
 mod untyped;
 
@@ -26,6 +31,7 @@ pub use asid_control::*;
 pub use asid_pool::*;
 pub use badge::*;
 pub use cnode::*;
+pub use domain::*;
 pub use endpoint::*;
 pub use fault_reply_endpoint::*;
 pub use irq_control::*;
@@ -33,6 +39,10 @@ pub use irq_handler::*;
 pub use notification::*;
 pub use page::*;
 pub use page_table::*;
+#[cfg(KernelIsMCS)]
+pub use sched_context::*;
+pub use slot_allocator::*;
+pub use slot_compactor::*;
 pub use tcb::*;
 pub use untyped::*;
 
@@ -218,6 +228,59 @@ impl<CT: CapType + CopyAliasable, Role: CNodeRole, Slots: Unsigned> CapRange<CT,
     }
 }
 
+/// A batch copy or mint of a `CapRange` that failed part way through
+#[derive(Debug, PartialEq)]
+pub struct BatchCopyError {
+    /// The index within the range of the cap that couldn't be copied.
+    /// Those before it were, and are left in place in the destination.
+    pub index: usize,
+    pub error: SeL4Error,
+}
+
+impl<CT: CapType + CopyAliasable + PhantomCap, Role: CNodeRole, Slots: Unsigned>
+    CapRange<CT, Role, Slots>
+where
+    <CT as CopyAliasable>::CopyOutput: PhantomCap,
+{
+    /// Copy every cap in the range to the same position within a range of
+    /// slots in another CNode
+    pub fn batch_copy<DestRole: CNodeRole>(
+        &self,
+        src_cnode: &LocalCap<CNode<Role>>,
+        slots: CNodeSlots<Slots, DestRole>,
+        rights: CapRights,
+    ) -> Result<CapRange<CT::CopyOutput, DestRole, Slots>, BatchCopyError> {
+        let copied_to_start_cptr = slots.cap_data.offset;
+        for (index, slot) in slots.iter().enumerate() {
+            let cap: Cap<CT, Role> = Cap::wrap_cptr(self.start_cptr + index);
+            cap.copy(src_cnode, slot, rights)
+                .map_err(|error| BatchCopyError { index, error })?;
+        }
+        Ok(CapRange::new_phantom(copied_to_start_cptr))
+    }
+
+    /// Mint every cap in the range to the same position within a range of
+    /// slots in another CNode, badging each with `badge(index)`
+    pub fn batch_mint<DestRole: CNodeRole, F: FnMut(usize) -> Badge>(
+        &self,
+        src_cnode: &LocalCap<LocalCNode>,
+        slots: CNodeSlots<Slots, DestRole>,
+        rights: CapRights,
+        mut badge: F,
+    ) -> Result<CapRange<CT::CopyOutput, DestRole, Slots>, BatchCopyError>
+    where
+        CT: Mintable,
+    {
+        let minted_to_start_cptr = slots.cap_data.offset;
+        for (index, slot) in slots.iter().enumerate() {
+            let cap: Cap<CT, Role> = Cap::wrap_cptr(self.start_cptr + index);
+            cap.mint(src_cnode, slot, rights, badge(index))
+                .map_err(|error| BatchCopyError { index, error })?;
+        }
+        Ok(CapRange::new_phantom(minted_to_start_cptr))
+    }
+}
+
 pub struct WeakCapRange<CT: CapType, Role: CNodeRole> {
     pub(crate) start_cptr: usize,
     pub(crate) start_cap_data: CT,
@@ -531,11 +594,18 @@ mod private {
     impl SealedCapType for Notification {}
     impl<FreeSlots: Unsigned> SealedCapType for ASIDPool<FreeSlots> {}
     impl SealedCapType for IRQControl {}
+    impl SealedCapType for DomainControl {}
     impl<IRQ: Unsigned, SetState: IRQSetState> SealedCapType for IRQHandler<IRQ, SetState> where
         IRQ: IsLess<MaxIRQCount, Output = True>
     {
     }
     impl<State: PageState> SealedCapType for Page<State> {}
+    #[cfg(KernelIsMCS)]
+    impl<State: SchedContextState> SealedCapType for SchedContext<State> {}
+    #[cfg(KernelIsMCS)]
+    impl SealedCapType for SchedControl {}
+    #[cfg(KernelIsMCS)]
+    impl SealedCapType for Reply {}
 
     /*
     Cross Arch things:
@@ -580,5 +650,12 @@ mod private {
         impl<FreePools: Unsigned> super::SealedCapType for ASIDControl<FreePools> {}
         impl super::SealedCapType for UnassignedASID {}
         impl super::SealedCapType for AssignedASID {}
+
+        #[cfg(all(target_arch = "aarch64", KernelAllowSMCCalls))]
+        impl super::SealedCapType for SMC {}
+        #[cfg(all(target_arch = "x86_64", KernelIOMMU))]
+        impl super::SealedCapType for IOSpace {}
+        #[cfg(all(target_arch = "x86_64", KernelIOMMU))]
+        impl super::SealedCapType for IOPageTable {}
     }
 }
@@ -44,4 +44,18 @@ impl LocalCap<Notification> {
         };
         Badge::from(sender_badge)
     }
+
+    /// Non-blocking check of a notification, clearing and returning its
+    /// word if any bits were signalled
+    pub fn poll(&self) -> Option<Badge> {
+        let mut sender_badge: usize = 0;
+        unsafe {
+            seL4_Poll(self.cptr, &mut sender_badge as *mut usize);
+        };
+        if sender_badge == 0 {
+            None
+        } else {
+            Some(Badge::from(sender_badge))
+        }
+    }
 }
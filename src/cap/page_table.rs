@@ -1,6 +1,4 @@
-use selfe_sys::*;
-
-use crate::arch::PagingRoot;
+use crate::arch::{PagingRoot, VMAttributes};
 use crate::cap::{page_state, CapType, LocalCap, Page, PhantomCap};
 use crate::error::{KernelError, SeL4Error};
 use crate::userland::CapRights;
@@ -22,7 +20,7 @@ impl Maps<Page<page_state::Unmapped>> for PageTable {
         addr: usize,
         root: &mut LocalCap<PagingRoot>,
         rights: CapRights,
-        vm_attributes: seL4_ARM_VMAttributes,
+        vm_attributes: VMAttributes,
     ) -> Result<(), MappingError> {
         if is_aligned(addr) {
             match unsafe { page.unchecked_page_map(addr, root, rights, vm_attributes) } {
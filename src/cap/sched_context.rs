@@ -0,0 +1,165 @@
+//! Scheduling contexts, for kernels built with the MCS configuration.
+//!
+//! On an MCS kernel a thread only runs while it has a scheduling context
+//! bound to it, and then for at most `BudgetMicros` microseconds out of every
+//! `PeriodMicros`. A `SchedContext` is retyped from an untyped like any other
+//! object, given its budget and period by the core's `SchedControl`, and
+//! then bound to a thread's TCB.
+use core::marker::PhantomData;
+
+use selfe_sys::*;
+use typenum::*;
+
+use crate::cap::{Badge, Cap, CapType, DirectRetype, LocalCap, PhantomCap, ThreadControlBlock};
+use crate::error::{ErrorExt, SeL4Error};
+
+pub trait SchedContextState: private::SealedSchedContextState {}
+
+pub mod sched_context_state {
+    use core::marker::PhantomData;
+
+    use typenum::Unsigned;
+
+    /// Freshly retyped, with no budget to run a thread on
+    pub struct Unconfigured;
+    impl super::SchedContextState for Unconfigured {}
+
+    /// Gives `BudgetMicros` of every `PeriodMicros` microseconds to the
+    /// thread bound to it
+    pub struct Configured<BudgetMicros: Unsigned, PeriodMicros: Unsigned> {
+        _budget: PhantomData<BudgetMicros>,
+        _period: PhantomData<PeriodMicros>,
+    }
+    impl<BudgetMicros: Unsigned, PeriodMicros: Unsigned> super::SchedContextState
+        for Configured<BudgetMicros, PeriodMicros>
+    {
+    }
+}
+
+#[derive(Debug)]
+pub struct SchedContext<State: SchedContextState> {
+    _state: PhantomData<State>,
+}
+
+impl<State: SchedContextState> CapType for SchedContext<State> {}
+
+impl PhantomCap for SchedContext<sched_context_state::Unconfigured> {
+    fn phantom_instance() -> Self {
+        Self {
+            _state: PhantomData,
+        }
+    }
+}
+
+impl DirectRetype for SchedContext<sched_context_state::Unconfigured> {
+    type SizeBits = crate::arch::SchedContextBits;
+    fn sel4_type_id() -> usize {
+        api_object_seL4_SchedContextObject as usize
+    }
+}
+
+/// The authority to set the budgets and periods of scheduling contexts
+/// on one core, handed to the root task in its bootinfo
+#[derive(Debug)]
+pub struct SchedControl {}
+
+impl CapType for SchedControl {}
+
+impl PhantomCap for SchedControl {
+    fn phantom_instance() -> Self {
+        Self {}
+    }
+}
+
+/// A reply object, which an MCS kernel has a server receive into so that
+/// it can reply to the caller later
+#[derive(Debug)]
+pub struct Reply {}
+
+impl CapType for Reply {}
+
+impl PhantomCap for Reply {
+    fn phantom_instance() -> Self {
+        Self {}
+    }
+}
+
+impl DirectRetype for Reply {
+    type SizeBits = crate::arch::ReplyBits;
+    fn sel4_type_id() -> usize {
+        api_object_seL4_ReplyObject as usize
+    }
+}
+
+impl LocalCap<SchedControl> {
+    /// Give a scheduling context `BudgetMicros` of every `PeriodMicros`
+    /// microseconds, split into at most `extra_refills` + 1 chunks. Timeout
+    /// faults from threads running on it are sent with `badge`.
+    ///
+    /// A scheduling context that's already configured, or even bound to a
+    /// running thread, can be configured again.
+    pub fn configure<BudgetMicros: Unsigned, PeriodMicros: Unsigned, State: SchedContextState>(
+        &self,
+        sched_context: LocalCap<SchedContext<State>>,
+        extra_refills: usize,
+        badge: Badge,
+    ) -> Result<
+        LocalCap<SchedContext<sched_context_state::Configured<BudgetMicros, PeriodMicros>>>,
+        SeL4Error,
+    >
+    where
+        BudgetMicros: IsGreater<U0, Output = True>,
+        BudgetMicros: IsLessOrEqual<PeriodMicros, Output = True>,
+    {
+        unsafe {
+            seL4_SchedControl_Configure(
+                self.cptr,          // _service
+                sched_context.cptr, // schedcontext
+                BudgetMicros::U64,  // budget
+                PeriodMicros::U64,  // period
+                extra_refills,      // extra_refills
+                usize::from(badge), // badge
+            )
+        }
+        .as_result()
+        .map_err(SeL4Error::SchedControlConfigure)?;
+        Ok(Cap {
+            cptr: sched_context.cptr,
+            cap_data: SchedContext {
+                _state: PhantomData,
+            },
+            _role: PhantomData,
+        })
+    }
+}
+
+impl<BudgetMicros: Unsigned, PeriodMicros: Unsigned>
+    LocalCap<SchedContext<sched_context_state::Configured<BudgetMicros, PeriodMicros>>>
+{
+    /// Run a thread on this scheduling context. Neither the thread nor
+    /// the scheduling context can already be bound to another.
+    pub fn bind_tcb(&self, tcb: &LocalCap<ThreadControlBlock>) -> Result<(), SeL4Error> {
+        unsafe { seL4_SchedContext_Bind(self.cptr, tcb.cptr) }
+            .as_result()
+            .map_err(SeL4Error::SchedContextBind)
+    }
+
+    /// Take this scheduling context back from whatever it's bound to,
+    /// leaving a thread bound to it unable to run until it's given another
+    pub fn unbind(&self) -> Result<(), SeL4Error> {
+        unsafe { seL4_SchedContext_Unbind(self.cptr) }
+            .as_result()
+            .map_err(SeL4Error::SchedContextUnbind)
+    }
+}
+
+mod private {
+    use typenum::Unsigned;
+
+    pub trait SealedSchedContextState {}
+    impl SealedSchedContextState for super::sched_context_state::Unconfigured {}
+    impl<BudgetMicros: Unsigned, PeriodMicros: Unsigned> SealedSchedContextState
+        for super::sched_context_state::Configured<BudgetMicros, PeriodMicros>
+    {
+    }
+}
@@ -0,0 +1,84 @@
+use typenum::Unsigned;
+
+use crate::cap::{role, CNodeRole, CNodeSlot, CNodeSlots, Cap, LocalCap, WCNodeSlotsData};
+
+/// A pool of CNode slots sized at runtime, for when how many slots are
+/// needed isn't known until then, or when tracking it in the types is
+/// more trouble than it's worth, as for a setup helper taking whatever
+/// it needs from a pool the caller hands down.
+///
+/// Keeps count of how many of its slots have been handed out, and how
+/// many are left.
+#[derive(Debug)]
+pub struct WeakSlotAllocator<Role: CNodeRole = role::Local> {
+    slots: LocalCap<WCNodeSlotsData<Role>>,
+    total: usize,
+}
+
+/// Asked for more slots than were left
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlotAllocError {
+    pub requested: usize,
+    pub available: usize,
+}
+
+impl<Role: CNodeRole> WeakSlotAllocator<Role> {
+    pub fn new(slots: LocalCap<WCNodeSlotsData<Role>>) -> Self {
+        WeakSlotAllocator {
+            total: slots.cap_data.size,
+            slots,
+        }
+    }
+
+    /// How many slots are left to hand out
+    pub fn remaining(&self) -> usize {
+        self.slots.cap_data.size
+    }
+
+    /// How many slots have been handed out, including to allocators
+    /// split off from this one
+    pub fn allocated(&self) -> usize {
+        self.total - self.remaining()
+    }
+
+    /// Exactly `count` contiguous slots
+    pub fn alloc_exact(
+        &mut self,
+        count: usize,
+    ) -> Result<LocalCap<WCNodeSlotsData<Role>>, SlotAllocError> {
+        let available = self.remaining();
+        self.slots.alloc(count).map_err(|_| SlotAllocError {
+            requested: count,
+            available,
+        })
+    }
+
+    /// `Count` contiguous slots, typed, for code that wants them that way
+    pub fn alloc_strong<Count: Unsigned>(
+        &mut self,
+    ) -> Result<CNodeSlots<Count, Role>, SlotAllocError> {
+        let slots = self.alloc_exact(Count::USIZE)?;
+        Ok(Cap::internal_new(slots.cptr, slots.cap_data.offset))
+    }
+
+    pub fn alloc_slot(&mut self) -> Result<CNodeSlot<Role>, SlotAllocError> {
+        self.alloc_strong()
+    }
+
+    /// Split off an allocator of its own with `count` of the slots, for
+    /// handing down to a helper.
+    pub fn split(&mut self, count: usize) -> Result<WeakSlotAllocator<Role>, SlotAllocError> {
+        self.alloc_exact(count).map(WeakSlotAllocator::new)
+    }
+
+    /// The slots that are left, for code that takes them directly, e.g.
+    /// `WUTBuddy::alloc`. Slots used from them are accounted for.
+    pub fn slots_mut(&mut self) -> &mut LocalCap<WCNodeSlotsData<Role>> {
+        &mut self.slots
+    }
+
+    /// Give up the slots that are left
+    pub fn into_slots(self) -> LocalCap<WCNodeSlotsData<Role>> {
+        self.slots
+    }
+}
@@ -0,0 +1,218 @@
+use core::marker::PhantomData;
+
+use selfe_sys::*;
+
+use crate::cap::{
+    CNodeSlotsData, Cap, LocalCNode, LocalCNodeSlot, LocalCap, WCNodeSlots, WCNodeSlotsData,
+};
+use crate::error::{ErrorExt, SeL4Error};
+
+/// The most slots a `CNodeSlotCompactor` keeps track of
+pub const MAX_COMPACTOR_SLOTS: usize = 4096;
+
+const WORD_BITS: usize = core::mem::size_of::<usize>() * 8;
+
+#[derive(Debug, PartialEq)]
+pub enum SlotCompactorError {
+    /// The span has more than `MAX_COMPACTOR_SLOTS` slots
+    TooManySlots(usize),
+    /// Every slot in the span is in use
+    NoFreeSlots,
+    /// The slot isn't in the span, or isn't in use
+    SlotNotInUse(usize),
+    /// The slots to extend the span with don't follow on from it
+    NotContiguous,
+    SeL4Error(SeL4Error),
+}
+
+impl From<SeL4Error> for SlotCompactorError {
+    fn from(e: SeL4Error) -> Self {
+        SlotCompactorError::SeL4Error(e)
+    }
+}
+
+/// A span of slots handed out and released one at a time, for a system
+/// whose caps come and go for as long as it runs, that can move the caps
+/// left in it together to free up a contiguous block of slots.
+///
+/// Slots handed out and released over time leave the caps still in use
+/// scattered across the span, so that a request for a run of slots, e.g.
+/// for a region's page caps or a process' CNode, can fail with plenty of
+/// slots free. `compact` moves every cap down into the lowest free slots
+/// and gives up the slots above them as one block.
+pub struct CNodeSlotCompactor {
+    cptr: usize,
+    offset: usize,
+    size: usize,
+    /// A bit per slot in the span, set for those in use
+    in_use: [usize; MAX_COMPACTOR_SLOTS / WORD_BITS],
+    in_use_count: usize,
+}
+
+impl CNodeSlotCompactor {
+    pub fn new(slots: WCNodeSlots) -> Result<Self, SlotCompactorError> {
+        if slots.cap_data.size > MAX_COMPACTOR_SLOTS {
+            return Err(SlotCompactorError::TooManySlots(slots.cap_data.size));
+        }
+        Ok(CNodeSlotCompactor {
+            cptr: slots.cptr,
+            offset: slots.cap_data.offset,
+            size: slots.cap_data.size,
+            in_use: [0; MAX_COMPACTOR_SLOTS / WORD_BITS],
+            in_use_count: 0,
+        })
+    }
+
+    /// How many slots are in the span
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// How many of the span's slots are in use
+    pub fn in_use(&self) -> usize {
+        self.in_use_count
+    }
+
+    /// The longest run of free slots, which is as many slots as the span
+    /// has free short of compacting it
+    pub fn largest_free_run(&self) -> usize {
+        let mut largest = 0;
+        let mut run = 0;
+        for index in 0..self.size {
+            if self.is_set(index) {
+                run = 0;
+            } else {
+                run += 1;
+                largest = core::cmp::max(largest, run);
+            }
+        }
+        largest
+    }
+
+    /// Hand out the lowest free slot
+    pub fn alloc_slot(&mut self) -> Result<LocalCNodeSlot, SlotCompactorError> {
+        let index = (0..self.size)
+            .find(|&index| !self.is_set(index))
+            .ok_or(SlotCompactorError::NoFreeSlots)?;
+        self.set(index, true);
+        Ok(Cap {
+            cptr: self.cptr,
+            cap_data: CNodeSlotsData {
+                offset: self.offset + index,
+                _size: PhantomData,
+                _role: PhantomData,
+            },
+            _role: PhantomData,
+        })
+    }
+
+    /// Take back the slot whose cap is `cptr`, once the cap has been
+    /// deleted or moved out of it
+    pub fn release_slot(&mut self, cptr: usize) -> Result<(), SlotCompactorError> {
+        match self.index_of(cptr) {
+            Some(index) if self.is_set(index) => {
+                self.set(index, false);
+                Ok(())
+            }
+            _ => Err(SlotCompactorError::SlotNotInUse(cptr)),
+        }
+    }
+
+    /// Move the caps in use down into the lowest free slots, calling
+    /// `relocated` with each moved cap's old and new cptr, and give up
+    /// the free slots left above them.
+    ///
+    /// The span shrinks to the slots still in use, which are all of them,
+    /// so it has to be handed slots to carry on handing them out. A cap
+    /// failing to move stops the compaction where it is, with the caps
+    /// moved so far in their new slots.
+    ///
+    /// # Safety
+    ///
+    /// Every `Cap` in the span names its slot by cptr, so the holder of a
+    /// moved cap has to update its cptr with the new one from `relocated`
+    /// before it's used again, otherwise it'll name whatever cap ends up
+    /// in its old slot.
+    pub unsafe fn compact<F>(
+        &mut self,
+        cnode: &LocalCap<LocalCNode>,
+        mut relocated: F,
+    ) -> Result<WCNodeSlots, SlotCompactorError>
+    where
+        F: FnMut(usize, usize),
+    {
+        let mut next_free = 0;
+        for index in 0..self.size {
+            if !self.is_set(index) {
+                continue;
+            }
+            if index != next_free {
+                let old_cptr = self.offset + index;
+                let new_cptr = self.offset + next_free;
+                seL4_CNode_Move(
+                    self.cptr,           // _service
+                    new_cptr,            // index
+                    seL4_WordBits as u8, // depth
+                    // Since cnode is restricted to Root, the cptr must
+                    // actually be the slot index
+                    cnode.cptr,          // src_root
+                    old_cptr,            // src_index
+                    seL4_WordBits as u8, // src_depth
+                )
+                .as_result()
+                .map_err(SeL4Error::CNodeMove)?;
+                self.set(index, false);
+                self.set(next_free, true);
+                relocated(old_cptr, new_cptr);
+            }
+            next_free += 1;
+        }
+
+        let freed: WCNodeSlots = Cap {
+            cptr: self.cptr,
+            cap_data: WCNodeSlotsData {
+                offset: self.offset + next_free,
+                size: self.size - next_free,
+                _role: PhantomData,
+            },
+            _role: PhantomData,
+        };
+        self.size = next_free;
+        Ok(freed)
+    }
+
+    /// Give the span more slots to hand out, which have to follow on
+    /// from it, e.g. the block given up by the last compaction
+    pub fn extend(&mut self, slots: WCNodeSlots) -> Result<(), SlotCompactorError> {
+        let size = self.size + slots.cap_data.size;
+        if size > MAX_COMPACTOR_SLOTS {
+            return Err(SlotCompactorError::TooManySlots(size));
+        }
+        if slots.cptr != self.cptr || slots.cap_data.offset != self.offset + self.size {
+            return Err(SlotCompactorError::NotContiguous);
+        }
+        self.size = size;
+        Ok(())
+    }
+
+    fn index_of(&self, cptr: usize) -> Option<usize> {
+        cptr.checked_sub(self.offset)
+            .filter(|&index| index < self.size)
+    }
+
+    fn is_set(&self, index: usize) -> bool {
+        self.in_use[index / WORD_BITS] & (1 << (index % WORD_BITS)) != 0
+    }
+
+    fn set(&mut self, index: usize, in_use: bool) {
+        let bit = 1 << (index % WORD_BITS);
+        let word = &mut self.in_use[index / WORD_BITS];
+        if in_use {
+            *word |= bit;
+            self.in_use_count += 1;
+        } else {
+            *word &= !bit;
+            self.in_use_count -= 1;
+        }
+    }
+}
@@ -72,7 +72,7 @@ impl LocalCap<ThreadControlBlock> {
         cspace_root: LocalCap<ChildCNode>,
         fault_source: Option<FaultSource<role::Child>>,
         virtual_address_space_root: &LocalCap<crate::arch::PagingRoot>, // vspace_root,
-        ipc_buffer: Option<LocalCap<Page<page_state::Mapped>>>,
+        ipc_buffer: Option<&LocalCap<Page<page_state::Mapped>>>,
     ) -> Result<(), SeL4Error> {
         // Set up the cspace's guard to take the part of the cptr that's not
         // used by the radix.
@@ -116,4 +116,17 @@ impl LocalCap<ThreadControlBlock> {
             .as_result()
             .map_err(SeL4Error::TCBSetPriority)
     }
+
+    /// Point this TCB at a different IPC buffer page, which the kernel
+    /// uses from the thread's next system call on.
+    ///
+    /// The page must be mapped into the thread's own address space.
+    pub fn set_ipc_buffer(
+        &mut self,
+        ipc_buffer: &LocalCap<Page<page_state::Mapped>>,
+    ) -> Result<(), SeL4Error> {
+        unsafe { seL4_TCB_SetIPCBuffer(self.cptr, ipc_buffer.vaddr(), ipc_buffer.cptr) }
+            .as_result()
+            .map_err(SeL4Error::TCBSetIPCBuffer)
+    }
 }
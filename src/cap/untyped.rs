@@ -122,26 +122,18 @@ impl<Kind: MemoryKind> LocalCap<WUntyped<Kind>> {
             return Err(RetypeError::NotBigEnough);
         }
         let num_pages = 1 << usize::from(self.cap_data.size_bits - PageBits::U8);
-        if num_pages > KernelRetypeFanOutLimit::USIZE {
-            return Err(RetypeError::KernelRetypeFanOutLimit);
-        }
         // TODO - REVIEW - Do we need more constraints on num_pages?
         let dest_slots = slots
             .alloc(num_pages)
             .map_err(RetypeError::CNodeSlotsError)?;
         unsafe {
-            seL4_Untyped_Retype(
-                self.cptr,                  // _service
-                Page::sel4_type_id(),       // type
-                0,                          // size_bits
-                dest_slots.cptr,            // root
-                0,                          // index
-                0,                          // depth
-                dest_slots.cap_data.offset, // offset
-                num_pages,                  // num_objects
-            )
-            .as_result()
-            .map_err(SeL4Error::UntypedRetype)?;
+            retype_bulk_internal(
+                self.cptr,
+                num_pages,
+                Page::sel4_type_id(),
+                dest_slots.cptr,
+                dest_slots.cap_data.offset,
+            )?;
         }
 
         Ok(WeakCapRange::new(
@@ -320,6 +312,37 @@ impl From<CNodeSlotsError> for RetypeError {
     }
 }
 
+/// A general purpose untyped with every capability derived from it
+/// revoked, so that none of its memory is in use and it can be handed
+/// back to an allocator
+#[derive(Debug)]
+pub struct RevokedUntyped {
+    pub(crate) ut: LocalCap<WUntyped<memory_kind::General>>,
+}
+
+impl RevokedUntyped {
+    pub fn size_bits(&self) -> u8 {
+        self.ut.cap_data.size_bits
+    }
+}
+
+impl LocalCap<WUntyped<memory_kind::General>> {
+    /// Revoke all capabilities derived from this untyped, destroying
+    /// everything retyped from it.
+    pub fn revoke(self, parent_cnode: &LocalCap<LocalCNode>) -> Result<RevokedUntyped, SeL4Error> {
+        unsafe {
+            seL4_CNode_Revoke(
+                parent_cnode.cptr,   // _service
+                self.cptr,           // index
+                seL4_WordBits as u8, // depth
+            )
+        }
+        .as_result()
+        .map_err(SeL4Error::CNodeRevoke)?;
+        Ok(RevokedUntyped { ut: self })
+    }
+}
+
 impl<BitSize: Unsigned, Kind: MemoryKind> LocalCap<Untyped<BitSize, Kind>> {
     /// Gain temporary access to an untyped capability for use in a function
     /// context. When the passed function call is complete, all capabilities
@@ -533,6 +556,37 @@ impl<BitSize: Unsigned, Kind: MemoryKind> LocalCap<Untyped<BitSize, Kind>> {
     }
 }
 
+/// Retype `count` objects into consecutive slots starting at
+/// `dest_offset`, in chunks of at most `KernelRetypeFanOutLimit`. Each
+/// retype carries on from where the last one left the untyped, so the
+/// objects are laid out as they would be by a single retype.
+unsafe fn retype_bulk_internal(
+    untyped_cptr: usize,
+    count: usize,
+    type_id: usize,
+    dest_cptr: usize,
+    dest_offset: usize,
+) -> Result<(), SeL4Error> {
+    let mut retyped = 0;
+    while retyped < count {
+        let chunk = core::cmp::min(count - retyped, KernelRetypeFanOutLimit::USIZE);
+        seL4_Untyped_Retype(
+            untyped_cptr,          // _service
+            type_id,               // type
+            0,                     // size_bits
+            dest_cptr,             // root
+            0,                     // index
+            0,                     // depth
+            dest_offset + retyped, // offset
+            chunk,                 // num_objects
+        )
+        .as_result()
+        .map_err(SeL4Error::UntypedRetype)?;
+        retyped += chunk;
+    }
+    Ok(())
+}
+
 /// A version of retype that concretely specifies the required untyped size,
 /// to work well with type inference.
 pub fn retype<TargetCapType: CapType, TargetRole: CNodeRole>(
@@ -642,6 +696,43 @@ impl<BitSize: Unsigned> LocalCap<Untyped<BitSize, memory_kind::General>> {
         Ok(CapRange::new_phantom(dest_offset))
     }
 
+    /// Like `retype_multi`, for any number of objects, retyping them as
+    /// many at a time as the kernel's `KernelRetypeFanOutLimit` allows,
+    /// e.g. a process' copy of the code image, whose page count is only
+    /// within the limit for some kernel configurations.
+    pub fn retype_bulk<TargetCapType: CapType, Count: Unsigned>(
+        self,
+        dest_slots: LocalCNodeSlots<Count>,
+    ) -> Result<CapRange<TargetCapType, role::Local, Count>, SeL4Error>
+    where
+        TargetCapType: DirectRetype,
+        TargetCapType: PhantomCap,
+
+        BitSize: _Pow,
+        Pow<BitSize>: Unsigned,
+
+        <TargetCapType as DirectRetype>::SizeBits: _Pow,
+        Pow<<TargetCapType as DirectRetype>::SizeBits>: Mul<Count>,
+        Prod<Pow<<TargetCapType as DirectRetype>::SizeBits>, Count>: Unsigned,
+
+        Pow<BitSize>: IsGreaterOrEqual<
+            Prod<Pow<<TargetCapType as DirectRetype>::SizeBits>, Count>,
+            Output = True,
+        >,
+    {
+        let (dest_cptr, dest_offset, _) = dest_slots.elim();
+        unsafe {
+            retype_bulk_internal(
+                self.cptr,
+                Count::USIZE,
+                TargetCapType::sel4_type_id(),
+                dest_cptr,
+                dest_offset,
+            )?;
+        }
+        Ok(CapRange::new_phantom(dest_offset))
+    }
+
     unsafe fn retype_multi_internal(
         self_cptr: usize,
         count: usize,
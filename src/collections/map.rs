@@ -0,0 +1,210 @@
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::mem::MaybeUninit;
+use core::ptr;
+
+use generic_array::sequence::GenericSequence;
+use generic_array::{ArrayLength, GenericArray};
+use typenum::Unsigned;
+
+use super::CapacityError;
+
+const EMPTY: u8 = 0;
+const OCCUPIED: u8 = 1;
+/// Once occupied, kept so lookups probe past it
+const REMOVED: u8 = 2;
+
+/// A slot of a `FixedMap`
+#[repr(C)]
+pub struct Bucket<K: Copy, V: Copy> {
+    state: u8,
+    key: MaybeUninit<K>,
+    value: MaybeUninit<V>,
+}
+
+impl<K: Copy, V: Copy> Bucket<K, V> {
+    fn empty() -> Self {
+        Bucket {
+            state: EMPTY,
+            key: MaybeUninit::uninit(),
+            value: MaybeUninit::uninit(),
+        }
+    }
+
+    fn entry(&self) -> Option<(&K, &V)> {
+        if self.state == OCCUPIED {
+            Some(unsafe { (&*self.key.as_ptr(), &*self.value.as_ptr()) })
+        } else {
+            None
+        }
+    }
+}
+
+/// A hash map of up to `N` entries, with open addressing and linear
+/// probing
+#[repr(C)]
+pub struct FixedMap<K: Copy + Eq + Hash, V: Copy, N: ArrayLength<Bucket<K, V>>> {
+    len: usize,
+    buckets: GenericArray<Bucket<K, V>, N>,
+}
+
+impl<K: Copy + Eq + Hash, V: Copy, N: ArrayLength<Bucket<K, V>>> FixedMap<K, V, N> {
+    pub fn new() -> Self {
+        FixedMap {
+            len: 0,
+            buckets: GenericArray::generate(|_| Bucket::empty()),
+        }
+    }
+
+    /// Set up an empty map at `ptr`, e.g. in a shared region, without
+    /// building it on the stack first.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be valid for writes and suitably aligned.
+    pub unsafe fn init_at_ptr(ptr: *mut Self) {
+        ptr::write_bytes(ptr, 0, 1);
+    }
+
+    pub fn capacity(&self) -> usize {
+        N::USIZE
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The buckets to look in for `key`, in order
+    fn probe(&self, key: &K) -> impl Iterator<Item = usize> {
+        let mut hasher = FnvHasher::default();
+        key.hash(&mut hasher);
+        let start = if N::USIZE == 0 {
+            0
+        } else {
+            hasher.finish() as usize % N::USIZE
+        };
+        (0..N::USIZE).map(move |i| (start + i) % N::USIZE)
+    }
+
+    fn find(&self, key: &K) -> Option<usize> {
+        for i in self.probe(key) {
+            let bucket = &self.buckets[i];
+            match bucket.state {
+                OCCUPIED if unsafe { &*bucket.key.as_ptr() } == key => return Some(i),
+                OCCUPIED | REMOVED => continue,
+                _ => return None,
+            }
+        }
+        None
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.find(key)
+            .map(|i| unsafe { &*self.buckets[i].value.as_ptr() })
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        match self.find(key) {
+            Some(i) => Some(unsafe { &mut *self.buckets[i].value.as_mut_ptr() }),
+            None => None,
+        }
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.find(key).is_some()
+    }
+
+    /// Insert `value` for `key`, returning the value it replaced if there
+    /// was one
+    pub fn insert(&mut self, key: K, value: V) -> Result<Option<V>, CapacityError<(K, V)>> {
+        if let Some(i) = self.find(&key) {
+            let old = unsafe { self.buckets[i].value.assume_init() };
+            self.buckets[i].value = MaybeUninit::new(value);
+            return Ok(Some(old));
+        }
+        let free = self
+            .probe(&key)
+            .find(|i| self.buckets[*i].state != OCCUPIED);
+        match free {
+            Some(i) => {
+                self.buckets[i] = Bucket {
+                    state: OCCUPIED,
+                    key: MaybeUninit::new(key),
+                    value: MaybeUninit::new(value),
+                };
+                self.len += 1;
+                Ok(None)
+            }
+            None => Err(CapacityError((key, value))),
+        }
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let i = self.find(key)?;
+        self.buckets[i].state = REMOVED;
+        self.len -= 1;
+        Some(unsafe { self.buckets[i].value.assume_init() })
+    }
+
+    pub fn clear(&mut self) {
+        for bucket in self.buckets.iter_mut() {
+            bucket.state = EMPTY;
+        }
+        self.len = 0;
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.buckets.iter().filter_map(Bucket::entry)
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(k, _)| k)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, v)| v)
+    }
+}
+
+impl<K: Copy + Eq + Hash, V: Copy, N: ArrayLength<Bucket<K, V>>> Default for FixedMap<K, V, N> {
+    fn default() -> Self {
+        FixedMap::new()
+    }
+}
+
+impl<K, V, N> fmt::Debug for FixedMap<K, V, N>
+where
+    K: Copy + Eq + Hash + fmt::Debug,
+    V: Copy + fmt::Debug,
+    N: ArrayLength<Bucket<K, V>>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+/// 64-bit FNV-1a, which needs no keys or state beyond the hash itself
+struct FnvHasher(u64);
+
+impl Default for FnvHasher {
+    fn default() -> Self {
+        FnvHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for FnvHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= u64::from(*b);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
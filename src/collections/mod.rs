@@ -0,0 +1,24 @@
+//! Fixed-capacity collections for state kept in shared memory.
+//!
+//! `FixedVec`, `FixedMap` and `FixedString` keep their elements inline,
+//! with capacities given as typenum numbers, and nothing in them refers to
+//! its own address. Like the queues in `cross_queue`, which address their
+//! buffers by offset, they can live in a region shared between processes
+//! and be used from wherever each process has the region mapped.
+//!
+//! All-zero memory is a valid, empty collection of any of them, so a
+//! collection laid out in a freshly retyped region is ready to use as it
+//! is. They don't synchronize access on their own; processes sharing one
+//! need some other way to take turns with it. Elements are `Copy`, since
+//! nothing is ever dropped.
+mod map;
+mod string;
+mod vec;
+
+pub use crate::collections::map::*;
+pub use crate::collections::string::*;
+pub use crate::collections::vec::*;
+
+/// A collection was full, with the value that didn't fit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CapacityError<T>(pub T);
@@ -0,0 +1,120 @@
+use core::fmt;
+use core::mem::MaybeUninit;
+use core::ops::Deref;
+use core::str;
+
+use generic_array::ArrayLength;
+
+use super::{CapacityError, FixedVec};
+
+/// A UTF-8 string of up to `N` bytes
+#[repr(C)]
+pub struct FixedString<N: ArrayLength<MaybeUninit<u8>>> {
+    bytes: FixedVec<u8, N>,
+}
+
+impl<N: ArrayLength<MaybeUninit<u8>>> FixedString<N> {
+    pub fn new() -> Self {
+        FixedString {
+            bytes: FixedVec::new(),
+        }
+    }
+
+    /// Set up an empty string at `ptr`, e.g. in a shared region, without
+    /// building it on the stack first.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be valid for writes and suitably aligned.
+    pub unsafe fn init_at_ptr(ptr: *mut Self) {
+        FixedVec::init_at_ptr(&mut (*ptr).bytes);
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.bytes.capacity()
+    }
+
+    pub fn len(&self) -> usize {
+        self.as_str().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The string, up to the first invalid UTF-8 in case another process
+    /// wrote any
+    pub fn as_str(&self) -> &str {
+        match str::from_utf8(&self.bytes) {
+            Ok(s) => s,
+            Err(e) => unsafe { str::from_utf8_unchecked(&self.bytes[..e.valid_up_to()]) },
+        }
+    }
+
+    /// Append `s` if it fits, leaving the string as it was if it doesn't
+    pub fn push_str<'a>(&mut self, s: &'a str) -> Result<(), CapacityError<&'a str>> {
+        if self.bytes.len() + s.len() > self.capacity() {
+            return Err(CapacityError(s));
+        }
+        for b in s.bytes() {
+            let _ = self.bytes.push(b);
+        }
+        Ok(())
+    }
+
+    pub fn push(&mut self, c: char) -> Result<(), CapacityError<char>> {
+        let mut buf = [0; 4];
+        self.push_str(c.encode_utf8(&mut buf))
+            .map_err(|_| CapacityError(c))
+    }
+
+    pub fn clear(&mut self) {
+        self.bytes.clear();
+    }
+}
+
+impl<N: ArrayLength<MaybeUninit<u8>>> Default for FixedString<N> {
+    fn default() -> Self {
+        FixedString::new()
+    }
+}
+
+impl<N: ArrayLength<MaybeUninit<u8>>> Clone for FixedString<N> {
+    fn clone(&self) -> Self {
+        FixedString {
+            bytes: self.bytes.clone(),
+        }
+    }
+}
+
+impl<N: ArrayLength<MaybeUninit<u8>>> Deref for FixedString<N> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl<N: ArrayLength<MaybeUninit<u8>>> PartialEq for FixedString<N> {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl<N: ArrayLength<MaybeUninit<u8>>> fmt::Write for FixedString<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push_str(s).map_err(|_| fmt::Error)
+    }
+}
+
+impl<N: ArrayLength<MaybeUninit<u8>>> fmt::Display for FixedString<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl<N: ArrayLength<MaybeUninit<u8>>> fmt::Debug for FixedString<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
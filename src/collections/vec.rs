@@ -0,0 +1,178 @@
+use core::fmt;
+use core::mem::MaybeUninit;
+use core::ops::{Deref, DerefMut};
+use core::ptr;
+use core::slice;
+
+use generic_array::sequence::GenericSequence;
+use generic_array::{ArrayLength, GenericArray};
+use typenum::Unsigned;
+
+use super::CapacityError;
+
+/// A vector of up to `N` elements
+#[repr(C)]
+pub struct FixedVec<T: Copy, N: ArrayLength<MaybeUninit<T>>> {
+    len: usize,
+    items: GenericArray<MaybeUninit<T>, N>,
+}
+
+impl<T: Copy, N: ArrayLength<MaybeUninit<T>>> FixedVec<T, N> {
+    pub fn new() -> Self {
+        FixedVec {
+            len: 0,
+            items: GenericArray::generate(|_| MaybeUninit::uninit()),
+        }
+    }
+
+    /// Set up an empty vector at `ptr`, e.g. in a shared region, without
+    /// building it on the stack first.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be valid for writes and suitably aligned.
+    pub unsafe fn init_at_ptr(ptr: *mut Self) {
+        ptr::write(&mut (*ptr).len, 0);
+    }
+
+    pub fn capacity(&self) -> usize {
+        N::USIZE
+    }
+
+    pub fn len(&self) -> usize {
+        // Another process may have scribbled on the length
+        self.len.min(N::USIZE)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() == N::USIZE
+    }
+
+    pub fn push(&mut self, item: T) -> Result<(), CapacityError<T>> {
+        let len = self.len();
+        if len == N::USIZE {
+            return Err(CapacityError(item));
+        }
+        self.items[len] = MaybeUninit::new(item);
+        self.len = len + 1;
+        Ok(())
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        let len = self.len();
+        if len == 0 {
+            return None;
+        }
+        self.len = len - 1;
+        Some(unsafe { self.items[len - 1].assume_init() })
+    }
+
+    /// Insert `item` at `index`, shifting everything after it along
+    pub fn insert(&mut self, index: usize, item: T) -> Result<(), CapacityError<T>> {
+        let len = self.len();
+        assert!(index <= len, "insertion index out of bounds");
+        if len == N::USIZE {
+            return Err(CapacityError(item));
+        }
+        self.items.copy_within(index..len, index + 1);
+        self.items[index] = MaybeUninit::new(item);
+        self.len = len + 1;
+        Ok(())
+    }
+
+    /// Remove the element at `index`, shifting everything after it back
+    pub fn remove(&mut self, index: usize) -> Option<T> {
+        let len = self.len();
+        if index >= len {
+            return None;
+        }
+        let item = unsafe { self.items[index].assume_init() };
+        self.items.copy_within(index + 1..len, index);
+        self.len = len - 1;
+        Some(item)
+    }
+
+    /// Remove the element at `index`, replacing it with the last one
+    pub fn swap_remove(&mut self, index: usize) -> Option<T> {
+        let len = self.len();
+        if index >= len {
+            return None;
+        }
+        let item = unsafe { self.items[index].assume_init() };
+        self.items[index] = self.items[len - 1];
+        self.len = len - 1;
+        Some(item)
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        if len < self.len() {
+            self.len = len;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    pub fn as_slice(&self) -> &[T] {
+        unsafe { slice::from_raw_parts(self.items.as_ptr() as *const T, self.len()) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        unsafe { slice::from_raw_parts_mut(self.items.as_mut_ptr() as *mut T, self.len()) }
+    }
+}
+
+impl<T: Copy, N: ArrayLength<MaybeUninit<T>>> Default for FixedVec<T, N> {
+    fn default() -> Self {
+        FixedVec::new()
+    }
+}
+
+impl<T: Copy, N: ArrayLength<MaybeUninit<T>>> Clone for FixedVec<T, N> {
+    fn clone(&self) -> Self {
+        let mut v = FixedVec::new();
+        v.items[..self.len()].copy_from_slice(&self.items[..self.len()]);
+        v.len = self.len();
+        v
+    }
+}
+
+impl<T: Copy, N: ArrayLength<MaybeUninit<T>>> Deref for FixedVec<T, N> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        self.as_slice()
+    }
+}
+
+impl<T: Copy, N: ArrayLength<MaybeUninit<T>>> DerefMut for FixedVec<T, N> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        self.as_mut_slice()
+    }
+}
+
+impl<T: Copy + fmt::Debug, N: ArrayLength<MaybeUninit<T>>> fmt::Debug for FixedVec<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: Copy + PartialEq, N: ArrayLength<MaybeUninit<T>>> PartialEq for FixedVec<T, N> {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl<'a, T: Copy, N: ArrayLength<MaybeUninit<T>>> IntoIterator for &'a FixedVec<T, N> {
+    type Item = &'a T;
+    type IntoIter = slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
@@ -30,3 +30,9 @@ macro_rules! debug_println {
     ($fmt:expr) => ($crate::debug_print!(concat!($fmt, "\n")));
     ($fmt:expr, $($arg:tt)*) => ($crate::debug_print!(concat!($fmt, "\n"), $($arg)*));
 }
+
+#[cfg(feature = "capdump")]
+pub mod capdump;
+
+#[cfg(feature = "redzone")]
+pub mod redzone;
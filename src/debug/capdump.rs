@@ -0,0 +1,189 @@
+//! Walking a CNode to see what's in it, for tracking down slot count
+//! mix-ups and leaked capabilities.
+//!
+//! The kernel only identifies capabilities with `seL4_DebugCapIdentify`
+//! in debug builds (`KernelDebugBuild`); otherwise every slot reads as
+//! `CapKind::Unavailable`. It tells the type of a capability and no
+//! more, so badges and rights don't show up in a dump.
+//!
+//! Only the caller's own root CNode can be walked, where a slot's index
+//! is its cptr: the root task's, or a process' own through the
+//! reference to itself from `generate_self_reference`.
+use core::fmt;
+use core::ops::Range;
+
+use crate::cap::{CNode, CNodeRole, LocalCap};
+
+/// What the kernel says is in a slot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CapKind {
+    Null,
+    Untyped,
+    Endpoint,
+    Notification,
+    Reply,
+    CNode,
+    Thread,
+    IRQControl,
+    IRQHandler,
+    Zombie,
+    Domain,
+    SchedContext,
+    SchedControl,
+    /// An architecture-specific capability, e.g. a frame or a paging
+    /// structure, by its kernel cap tag
+    Arch(u32),
+    /// The kernel can't say, it's not a debug build
+    Unavailable,
+}
+
+/// The number of distinct `CapKind`s counted in a `CapCensus`, with all
+/// `Arch` capabilities counted together
+const COUNTED_KINDS: usize = 15;
+
+impl CapKind {
+    fn from_tag(tag: u32) -> CapKind {
+        // Generic cap tags are even, arch-specific ones odd, see the
+        // kernel's structures.bf
+        match tag {
+            0 => CapKind::Null,
+            2 => CapKind::Untyped,
+            4 => CapKind::Endpoint,
+            6 => CapKind::Notification,
+            8 => CapKind::Reply,
+            10 => CapKind::CNode,
+            12 => CapKind::Thread,
+            14 => CapKind::IRQControl,
+            16 => CapKind::IRQHandler,
+            18 => CapKind::Zombie,
+            20 => CapKind::Domain,
+            22 => CapKind::SchedContext,
+            24 => CapKind::SchedControl,
+            tag => CapKind::Arch(tag),
+        }
+    }
+
+    fn index(self) -> usize {
+        match self {
+            CapKind::Null => 0,
+            CapKind::Untyped => 1,
+            CapKind::Endpoint => 2,
+            CapKind::Notification => 3,
+            CapKind::Reply => 4,
+            CapKind::CNode => 5,
+            CapKind::Thread => 6,
+            CapKind::IRQControl => 7,
+            CapKind::IRQHandler => 8,
+            CapKind::Zombie => 9,
+            CapKind::Domain => 10,
+            CapKind::SchedContext => 11,
+            CapKind::SchedControl => 12,
+            CapKind::Arch(_) => 13,
+            CapKind::Unavailable => 14,
+        }
+    }
+}
+
+/// Identify the capability at `cptr` in the caller's CSpace.
+#[cfg(KernelDebugBuild)]
+pub fn identify(cptr: usize) -> CapKind {
+    CapKind::from_tag(unsafe { selfe_sys::seL4_DebugCapIdentify(cptr) })
+}
+
+/// Identify the capability at `cptr` in the caller's CSpace.
+#[cfg(not(KernelDebugBuild))]
+pub fn identify(_cptr: usize) -> CapKind {
+    CapKind::Unavailable
+}
+
+/// The occupied slots of `cnode`, the caller's own root CNode, in
+/// `slots`, with what's in each.
+pub fn occupied_slots<Role: CNodeRole>(
+    cnode: &LocalCap<CNode<Role>>,
+    slots: Range<usize>,
+) -> impl Iterator<Item = (usize, CapKind)> {
+    let end = core::cmp::min(slots.end, 1 << cnode.cap_data.radix);
+    (slots.start..end)
+        .map(|slot| (slot, identify(slot)))
+        .filter(|(_, kind)| *kind != CapKind::Null)
+}
+
+/// Print each occupied slot of `cnode` in `slots`, then a `CapCensus` of
+/// them.
+pub fn print<Role: CNodeRole>(cnode: &LocalCap<CNode<Role>>, slots: Range<usize>) {
+    debug_println!("capdump: slots {}..{}", slots.start, slots.end);
+    let mut census = CapCensus::default();
+    for (slot, kind) in occupied_slots(cnode, slots) {
+        debug_println!("capdump: {:#07x} {:?}", slot, kind);
+        census.add(kind);
+    }
+    debug_println!("capdump: {}", census);
+}
+
+/// How many capabilities of each kind there are, e.g. for comparing
+/// before and after something that shouldn't leak any.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CapCensus {
+    counts: [usize; COUNTED_KINDS],
+}
+
+impl CapCensus {
+    /// Count the occupied slots of `cnode`, the caller's own root CNode,
+    /// in `slots`.
+    pub fn take<Role: CNodeRole>(cnode: &LocalCap<CNode<Role>>, slots: Range<usize>) -> Self {
+        let mut census = CapCensus::default();
+        for (_, kind) in occupied_slots(cnode, slots) {
+            census.add(kind);
+        }
+        census
+    }
+
+    fn add(&mut self, kind: CapKind) {
+        self.counts[kind.index()] += 1;
+    }
+
+    /// The number of capabilities of `kind`, with all the `Arch` ones
+    /// counted together
+    pub fn count(&self, kind: CapKind) -> usize {
+        self.counts[kind.index()]
+    }
+
+    /// The number of occupied slots
+    pub fn total(&self) -> usize {
+        self.counts.iter().sum()
+    }
+}
+
+impl fmt::Display for CapCensus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        const KINDS: [CapKind; COUNTED_KINDS] = [
+            CapKind::Null,
+            CapKind::Untyped,
+            CapKind::Endpoint,
+            CapKind::Notification,
+            CapKind::Reply,
+            CapKind::CNode,
+            CapKind::Thread,
+            CapKind::IRQControl,
+            CapKind::IRQHandler,
+            CapKind::Zombie,
+            CapKind::Domain,
+            CapKind::SchedContext,
+            CapKind::SchedControl,
+            CapKind::Arch(0),
+            CapKind::Unavailable,
+        ];
+        write!(f, "{} occupied", self.total())?;
+        for kind in KINDS.iter() {
+            let count = self.count(*kind);
+            if count == 0 {
+                continue;
+            }
+            match kind {
+                CapKind::Arch(_) => write!(f, ", Arch: {}", count)?,
+                kind => write!(f, ", {:?}: {}", kind, count)?,
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,240 @@
+//! Red zones around sub-allocations carved out of a mapped region, for
+//! catching overruns the MMU can't: a frame pool or arena shares its
+//! pages between every buffer in it, so writing past the end of one
+//! buffer quietly corrupts the next.
+//!
+//! `RedZoneArena` hands out allocations from a region with a band of
+//! `RED_ZONE_BYTES` filled with `RED_ZONE_PATTERN` on either side of each.
+//! The bands are checked when an allocation is freed, and all of them
+//! whenever `check` is called, e.g. once per pass of a process' event
+//! loop. A band that no longer holds the pattern means something wrote
+//! past the allocation next to it.
+//!
+//! Allocation is a bump allocator; freed memory isn't handed out again,
+//! it's filled with `FREED_PATTERN` instead, to make use after free stand
+//! out in a debugger.
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::Sub;
+
+use arrayvec::ArrayVec;
+use typenum::*;
+
+use crate::arch::PageBits;
+use crate::pow::{Pow, _Pow};
+use crate::vspace::{MappedMemoryRegion, SharedStatus};
+
+/// The size of the red zone on either side of an allocation
+pub const RED_ZONE_BYTES: usize = 16;
+
+pub const RED_ZONE_PATTERN: u8 = 0xfd;
+
+pub const FREED_PATTERN: u8 = 0xdd;
+
+/// The most allocations an arena keeps track of at once
+pub const MAX_RED_ZONED_ALLOCATIONS: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedZoneError {
+    /// Not enough of the region is left for the allocation and its red
+    /// zones
+    OutOfMemory,
+    /// `MAX_RED_ZONED_ALLOCATIONS` are already live
+    TooManyAllocations,
+    AlignmentNotPowerOfTwo,
+    /// The allocation wasn't made by this arena, or was already freed
+    UnknownAllocation,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedZoneSide {
+    Before,
+    After,
+}
+
+/// A red zone found overwritten
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RedZoneViolation {
+    /// Where the allocation the red zone guards starts, from the start of
+    /// the region
+    pub offset: usize,
+    pub len: usize,
+    pub side: RedZoneSide,
+    /// The first overwritten byte, from the start of the region
+    pub corrupt_at: usize,
+}
+
+impl fmt::Display for RedZoneViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let side = match self.side {
+            RedZoneSide::Before => "before",
+            RedZoneSide::After => "after",
+        };
+        write!(
+            f,
+            "red zone {} allocation at offset {:#x} ({} bytes) overwritten at offset {:#x}",
+            side, self.offset, self.len, self.corrupt_at
+        )
+    }
+}
+
+/// An allocation from a `RedZoneArena`, to be given back with `free`
+#[derive(Debug, PartialEq, Eq)]
+pub struct RedZoned {
+    offset: usize,
+    len: usize,
+}
+
+impl RedZoned {
+    /// Where the allocation starts, from the start of the region
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Live {
+    offset: usize,
+    len: usize,
+}
+
+pub struct RedZoneArena<'r> {
+    base: *mut u8,
+    size: usize,
+    next: usize,
+    live: ArrayVec<[Live; MAX_RED_ZONED_ALLOCATIONS]>,
+    _mem: PhantomData<&'r mut [u8]>,
+}
+
+impl<'r> RedZoneArena<'r> {
+    /// Sub-allocate out of the whole of `region`, for as long as it's
+    /// borrowed
+    pub fn new<SizeBits: Unsigned, SS: SharedStatus>(
+        region: &'r mut MappedMemoryRegion<SizeBits, SS>,
+    ) -> Self
+    where
+        SizeBits: IsGreaterOrEqual<PageBits>,
+        SizeBits: Sub<PageBits>,
+        <SizeBits as Sub<PageBits>>::Output: Unsigned,
+        <SizeBits as Sub<PageBits>>::Output: _Pow,
+        Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+    {
+        Self::from_slice(region.as_mut_slice())
+    }
+
+    /// Sub-allocate out of `mem`, e.g. the part of a region left over
+    /// after a frame pool's free list
+    pub fn from_slice(mem: &'r mut [u8]) -> Self {
+        RedZoneArena {
+            base: mem.as_mut_ptr(),
+            size: mem.len(),
+            next: 0,
+            live: ArrayVec::new(),
+            _mem: PhantomData,
+        }
+    }
+
+    /// How many bytes are left, red zones included
+    pub fn remaining(&self) -> usize {
+        self.size - self.next
+    }
+
+    pub fn live_allocations(&self) -> usize {
+        self.live.len()
+    }
+
+    /// Allocate `len` bytes aligned to `align`, between two red zones
+    pub fn alloc(&mut self, len: usize, align: usize) -> Result<RedZoned, RedZoneError> {
+        if !align.is_power_of_two() {
+            return Err(RedZoneError::AlignmentNotPowerOfTwo);
+        }
+        if self.live.is_full() {
+            return Err(RedZoneError::TooManyAllocations);
+        }
+        let base = self.base as usize;
+        let start = (base + self.next + RED_ZONE_BYTES)
+            .checked_add(align - 1)
+            .ok_or(RedZoneError::OutOfMemory)?
+            & !(align - 1);
+        let offset = start - base;
+        let end = offset
+            .checked_add(len)
+            .and_then(|end| end.checked_add(RED_ZONE_BYTES))
+            .ok_or(RedZoneError::OutOfMemory)?;
+        if end > self.size {
+            return Err(RedZoneError::OutOfMemory);
+        }
+
+        self.fill(offset - RED_ZONE_BYTES, RED_ZONE_BYTES, RED_ZONE_PATTERN);
+        self.fill(offset + len, RED_ZONE_BYTES, RED_ZONE_PATTERN);
+        self.next = end;
+        self.live.push(Live { offset, len });
+        Ok(RedZoned { offset, len })
+    }
+
+    pub fn get(&self, allocation: &RedZoned) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.base.add(allocation.offset), allocation.len) }
+    }
+
+    pub fn get_mut(&mut self, allocation: &mut RedZoned) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self.base.add(allocation.offset), allocation.len) }
+    }
+
+    /// Give an allocation back, checking its red zones
+    pub fn free(&mut self, allocation: RedZoned) -> Result<(), FreeError> {
+        let index = self
+            .live
+            .iter()
+            .position(|l| l.offset == allocation.offset && l.len == allocation.len)
+            .ok_or(FreeError::RedZoneError(RedZoneError::UnknownAllocation))?;
+        let live = self.live.swap_remove(index);
+        self.fill(live.offset, live.len, FREED_PATTERN);
+        self.check_one(&live).map_err(FreeError::Violation)
+    }
+
+    /// Check the red zones of every live allocation, reporting the first
+    /// overwritten one
+    pub fn check(&self) -> Result<(), RedZoneViolation> {
+        self.live.iter().try_for_each(|live| self.check_one(live))
+    }
+
+    fn check_one(&self, live: &Live) -> Result<(), RedZoneViolation> {
+        let zones = [
+            (RedZoneSide::Before, live.offset - RED_ZONE_BYTES),
+            (RedZoneSide::After, live.offset + live.len),
+        ];
+        for &(side, zone_offset) in zones.iter() {
+            let zone =
+                unsafe { core::slice::from_raw_parts(self.base.add(zone_offset), RED_ZONE_BYTES) };
+            if let Some(i) = zone.iter().position(|b| *b != RED_ZONE_PATTERN) {
+                return Err(RedZoneViolation {
+                    offset: live.offset,
+                    len: live.len,
+                    side,
+                    corrupt_at: zone_offset + i,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn fill(&mut self, offset: usize, len: usize, pattern: u8) {
+        unsafe { core::ptr::write_bytes(self.base.add(offset), pattern, len) }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FreeError {
+    RedZoneError(RedZoneError),
+    /// The allocation was freed, but one of its red zones had been
+    /// overwritten
+    Violation(RedZoneViolation),
+}
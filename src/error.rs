@@ -5,6 +5,7 @@ pub enum SeL4Error {
     PageTableMap(KernelError),
     PageUpperDirectoryMap(KernelError),
     PageDirectoryMap(KernelError),
+    PDPTMap(KernelError),
     ASIDControlMakePool(KernelError),
     ASIDPoolAssign(KernelError),
     PageGetAddress(KernelError),
@@ -17,6 +18,7 @@ pub enum SeL4Error {
     TCBReadRegisters(KernelError),
     TCBSetPriority(KernelError),
     TCBResume(KernelError),
+    TCBSuspend(KernelError),
     CNodeMutate(KernelError),
     CNodeMove(KernelError),
     CNodeDelete(KernelError),
@@ -25,12 +27,22 @@ pub enum SeL4Error {
     IRQHandlerAck(KernelError),
     GetPageAddr(KernelError),
     PageCleanInvalidateData(KernelError),
+    PageCleanData(KernelError),
+    PageInvalidateData(KernelError),
     CNodeRevoke(KernelError),
     VCPUInjectIRQ(KernelError),
     VCPUReadRegisters(KernelError),
     VCPUWriteRegisters(KernelError),
     VCPUBindTcb(KernelError),
     TCBBindNotification(KernelError),
+    TCBSetIPCBuffer(KernelError),
+    SchedControlConfigure(KernelError),
+    SchedContextBind(KernelError),
+    SchedContextUnbind(KernelError),
+    SMCCall(KernelError),
+    IOPageTableMap(KernelError),
+    PageMapIO(KernelError),
+    DomainSetSet(KernelError),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
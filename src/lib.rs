@@ -1,6 +1,8 @@
 #![no_std]
 #![recursion_limit = "256"]
 #![feature(proc_macro_hygiene)]
+#![feature(asm)]
+#![cfg_attr(feature = "root_task_heap", feature(alloc_error_handler))]
 #![allow(
     clippy::too_many_arguments,
     clippy::type_complexity,
@@ -24,8 +26,10 @@ pub mod alloc;
 pub mod arch;
 pub mod bootstrap;
 pub mod cap;
+pub mod collections;
 pub mod error;
 pub mod pow;
+pub mod startup_report;
 #[cfg(feature = "test_support")]
 pub mod test_support;
 pub mod userland;
@@ -1,6 +1,9 @@
-//! 2^n for typenum
+//! 2^n for typenum, and other type-level byte math.
+//!
+//! The type-level helpers are mirrored by `const fn`s for the same
+//! calculation at runtime, which return `None` on overflow.
 use core::ops::Sub;
-use typenum::operator_aliases::Diff;
+use typenum::operator_aliases::{Diff, Prod, Quot, Shleft, Sub1, Sum};
 use typenum::{Bit, UInt, UTerm, Unsigned, B0, B1, U1, U2};
 
 pub trait _Pow {
@@ -32,3 +35,40 @@ where
 
 // shortcut
 pub type Pow<A> = <A as _Pow>::Output;
+
+/// The number of bytes in a region of `Bits` bits, `1 << Bits`
+pub type Pow2Bytes<Bits> = Shleft<U1, Bits>;
+
+/// `N / D`, rounded up
+pub type DivRoundUp<N, D> = Quot<Sub1<Sum<N, D>>, D>;
+
+/// `N` rounded up to the next multiple of `Align`
+pub type AlignUp<N, Align> = Prod<DivRoundUp<N, Align>, Align>;
+
+/// The number of bytes in a region of `bits` bits, see `Pow2Bytes`
+pub const fn pow2_bytes(bits: u32) -> Option<usize> {
+    if bits < usize::BITS {
+        Some(1 << bits)
+    } else {
+        None
+    }
+}
+
+/// `n / d`, rounded up, see `DivRoundUp`
+pub const fn div_round_up(n: usize, d: usize) -> Option<usize> {
+    if d == 0 {
+        return None;
+    }
+    match n.checked_add(d - 1) {
+        Some(sum) => Some(sum / d),
+        None => None,
+    }
+}
+
+/// `n` rounded up to the next multiple of `align`, see `AlignUp`
+pub const fn align_up(n: usize, align: usize) -> Option<usize> {
+    match div_round_up(n, align) {
+        Some(q) => q.checked_mul(align),
+        None => None,
+    }
+}
@@ -0,0 +1,211 @@
+//! A structured summary of a system printed once the root task has set
+//! everything up, so every ferros system boots with the same greppable
+//! inventory:
+//!
+//! ```text
+//! ferros-startup: platform=sabre arch=arm cores=1
+//! ferros-startup: untyped total_bytes=... free_bytes=... device_bytes=... quarantined_bytes=...
+//! ferros-startup: resource name=proc-a size_bytes=... fnv1a=0x...
+//! ferros-startup: process name=proc-a priority=255 affinity=0
+//! ferros-startup: end
+//! ```
+//!
+//! Every line starts with `ferros-startup:` and is made of `key=value`
+//! pairs. The memory figures come from the boot info and the allocators
+//! given to the report; resources and processes are recorded by the root
+//! task as it embeds and spawns them.
+
+use core::fmt;
+
+use arrayvec::ArrayVec;
+use selfe_sys::seL4_BootInfo;
+
+use crate::alloc::micro_alloc::Allocator;
+use crate::alloc::WUTBuddy;
+use crate::cap::CNodeRole;
+use crate::vspace::elf_segment_hash;
+
+pub const MAX_REPORTED_RESOURCES: usize = 32;
+pub const MAX_REPORTED_PROCESSES: usize = 32;
+
+/// The prefix of every line of the report
+pub const STARTUP_REPORT_PREFIX: &str = "ferros-startup:";
+
+#[cfg(any(target_arch = "arm", target_arch = "aarch32"))]
+const ARCH: &str = "arm";
+#[cfg(target_arch = "aarch64")]
+const ARCH: &str = "aarch64";
+#[cfg(target_arch = "x86_64")]
+const ARCH: &str = "x86_64";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ResourceSummary<'a> {
+    pub name: &'a str,
+    pub size_bytes: usize,
+    /// The FNV-1a hash of the contents, the same as `elf_segment_hash`
+    pub hash: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProcessSummary<'a> {
+    pub name: &'a str,
+    pub priority: usize,
+    /// The core the process' thread is bound to
+    pub affinity: usize,
+}
+
+pub struct StartupReport<'a> {
+    platform: Option<&'a str>,
+    cores: usize,
+    untyped_total_bytes: usize,
+    device_untyped_bytes: usize,
+    free_untyped_bytes: Option<usize>,
+    quarantined_bytes: usize,
+    resources: ArrayVec<[ResourceSummary<'a>; MAX_REPORTED_RESOURCES]>,
+    processes: ArrayVec<[ProcessSummary<'a>; MAX_REPORTED_PROCESSES]>,
+    omitted_resources: usize,
+    omitted_processes: usize,
+}
+
+impl<'a> StartupReport<'a> {
+    /// Start a report with the core count and the untyped memory the
+    /// kernel handed to the root task.
+    pub fn new(bootinfo: &seL4_BootInfo) -> Self {
+        let untyped_count = (bootinfo.untyped.end - bootinfo.untyped.start) as usize;
+        let (general, device) = bootinfo.untypedList[..untyped_count].iter().fold(
+            (0, 0),
+            |(general, device), ut| {
+                let size_bytes = 1usize << ut.sizeBits;
+                if ut.isDevice == 1 {
+                    (general, device + size_bytes)
+                } else {
+                    (general + size_bytes, device)
+                }
+            },
+        );
+        StartupReport {
+            platform: None,
+            cores: bootinfo.numNodes as usize,
+            untyped_total_bytes: general,
+            device_untyped_bytes: device,
+            free_untyped_bytes: None,
+            quarantined_bytes: 0,
+            resources: ArrayVec::new(),
+            processes: ArrayVec::new(),
+            omitted_resources: 0,
+            omitted_processes: 0,
+        }
+    }
+
+    /// Name the platform, e.g. the selfe config's platform, which isn't
+    /// known to ferros at runtime.
+    pub fn platform(&mut self, name: &'a str) -> &mut Self {
+        self.platform = Some(name);
+        self
+    }
+
+    /// Count the untyped memory left in `allocator` as free, and what it
+    /// quarantined at bootstrap.
+    pub fn allocator(&mut self, allocator: &Allocator) -> &mut Self {
+        *self.free_untyped_bytes.get_or_insert(0) += allocator.free_bytes();
+        self.quarantined_bytes += allocator
+            .quarantined()
+            .iter()
+            .map(|q| 1usize << q.size_bits)
+            .sum::<usize>();
+        self
+    }
+
+    /// Count the untyped memory left in `buddy` as free too.
+    pub fn ut_buddy<Role: CNodeRole>(&mut self, buddy: &WUTBuddy<Role>) -> &mut Self {
+        *self.free_untyped_bytes.get_or_insert(0) += buddy.free_bytes();
+        self
+    }
+
+    /// Record an embedded resource, hashing its contents.
+    pub fn resource(&mut self, name: &'a str, data: &[u8]) -> &mut Self {
+        let summary = ResourceSummary {
+            name,
+            size_bytes: data.len(),
+            hash: elf_segment_hash(data),
+        };
+        if self.resources.try_push(summary).is_err() {
+            self.omitted_resources += 1;
+        }
+        self
+    }
+
+    /// Record a spawned process, e.g. with `StandardProcess::PRIORITY`.
+    pub fn process(&mut self, name: &'a str, priority: usize, affinity: usize) -> &mut Self {
+        let summary = ProcessSummary {
+            name,
+            priority,
+            affinity,
+        };
+        if self.processes.try_push(summary).is_err() {
+            self.omitted_processes += 1;
+        }
+        self
+    }
+
+    pub fn resources(&self) -> &[ResourceSummary<'a>] {
+        &self.resources
+    }
+
+    pub fn processes(&self) -> &[ProcessSummary<'a>] {
+        &self.processes
+    }
+
+    /// Print the report on the kernel debug console.
+    pub fn print(&self) {
+        debug_print!("{}", self);
+    }
+}
+
+impl<'a> fmt::Display for StartupReport<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{} platform={} arch={} cores={}",
+            STARTUP_REPORT_PREFIX,
+            self.platform.unwrap_or("unknown"),
+            ARCH,
+            self.cores
+        )?;
+        write!(
+            f,
+            "{} untyped total_bytes={}",
+            STARTUP_REPORT_PREFIX, self.untyped_total_bytes
+        )?;
+        if let Some(free) = self.free_untyped_bytes {
+            write!(f, " free_bytes={}", free)?;
+        }
+        writeln!(
+            f,
+            " device_bytes={} quarantined_bytes={}",
+            self.device_untyped_bytes, self.quarantined_bytes
+        )?;
+        for r in &self.resources {
+            writeln!(
+                f,
+                "{} resource name={} size_bytes={} fnv1a={:#018x}",
+                STARTUP_REPORT_PREFIX, r.name, r.size_bytes, r.hash
+            )?;
+        }
+        for p in &self.processes {
+            writeln!(
+                f,
+                "{} process name={} priority={} affinity={}",
+                STARTUP_REPORT_PREFIX, p.name, p.priority, p.affinity
+            )?;
+        }
+        if self.omitted_resources > 0 || self.omitted_processes > 0 {
+            writeln!(
+                f,
+                "{} omitted resources={} processes={}",
+                STARTUP_REPORT_PREFIX, self.omitted_resources, self.omitted_processes
+            )?;
+        }
+        writeln!(f, "{} end", STARTUP_REPORT_PREFIX)
+    }
+}
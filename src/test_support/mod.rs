@@ -10,10 +10,12 @@ use crate::error::{ErrorExt, SeL4Error};
 use crate::pow::{Pow, _Pow};
 
 mod resources;
+mod simulated_device;
 mod types;
 
 use crate::vspace::MappedMemoryRegion;
 pub use resources::*;
+pub use simulated_device::*;
 pub use types::*;
 
 impl TestReporter for crate::debug::DebugOutHandle {
@@ -64,6 +66,7 @@ pub fn execute_tests<'t, R: types::TestReporter>(
         mapped_memory_region,
         cnode,
         thread_authority,
+        vspace,
         vspace_paging_root,
         user_image,
         irq_control,
@@ -91,6 +94,7 @@ pub fn execute_tests<'t, R: types::TestReporter>(
                     inner_mapped_memory_region,
                     cnode,
                     thread_authority,
+                    vspace,
                     vspace_paging_root,
                     user_image,
                     inner_irq_control,
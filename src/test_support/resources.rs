@@ -11,7 +11,6 @@ pub struct Resources {
     pub(super) slots: LocalCNodeSlots<super::types::MaxTestCNodeSlots>,
     pub(super) untyped: LocalCap<Untyped<super::types::MaxTestUntypedSize>>,
     pub(super) asid_pool: LocalCap<ASIDPool<super::types::MaxTestASIDPoolSize>>,
-    #[allow(dead_code)]
     pub(super) vspace: VSpace<vspace_state::Imaged, role::Local>,
     pub(super) scratch: ScratchRegion,
     pub(super) mapped_memory_region: MappedMemoryRegion<
@@ -36,6 +35,7 @@ pub struct TestResourceRefs<'t> {
     >,
     pub(super) cnode: &'t LocalCap<LocalCNode>,
     pub(super) thread_authority: &'t LocalCap<ThreadPriorityAuthority>,
+    pub(super) vspace: &'t mut VSpace,
     pub(super) vspace_paging_root: &'t LocalCap<crate::arch::PagingRoot>,
     pub(super) user_image: &'t UserImage<role::Local>,
     pub(super) irq_control: &'t mut LocalCap<IRQControl>,
@@ -166,6 +166,7 @@ impl Resources {
             mapped_memory_region: &mut self.mapped_memory_region,
             cnode: &self.cnode,
             thread_authority: &self.thread_authority,
+            vspace: &mut self.vspace,
             vspace_paging_root: &self.vspace_paging_root,
             user_image: &self.user_image,
             irq_control: &mut self.irq_control,
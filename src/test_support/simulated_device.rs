@@ -0,0 +1,288 @@
+//! Simulated devices, for testing drivers on platforms without the
+//! peripheral.
+//!
+//! A simulated device is a process sharing a page with the driver under
+//! test. The start of the page stands in for the device's registers: the
+//! driver is handed the page's address in place of the device's, and reads
+//! and writes its registers there as usual. The device process works
+//! through a script, setting registers for the driver to read and waiting
+//! on the driver's writes, and records every write it sees in the rest of
+//! the page, where the test can check them with a `SimulatedDeviceMonitor`.
+//!
+//! The device only sees the driver's writes by polling, so a register
+//! written twice between polls, or written with the value it already had,
+//! shows up as one write or none.
+use core::marker::PhantomData;
+use core::ptr;
+
+use selfe_sys::seL4_Yield;
+
+use crate::arch::PageBits;
+use crate::cap::{role, CNodeRole};
+use crate::userland::RetypeForSetup;
+use crate::vspace::{shared_status, MappedMemoryRegion};
+
+/// How many 32 bit registers a simulated device has
+pub const SIMULATED_REGISTER_COUNT: usize = 256;
+/// How many of the driver's writes are recorded; later ones are dropped
+pub const SIMULATED_WRITE_LOG_CAPACITY: usize = 256;
+
+const STATUS_RUNNING: usize = 0;
+const STATUS_DONE: usize = 1;
+const STATUS_FAILED_AT_STEP: usize = 2;
+
+#[repr(C)]
+struct DeviceBlock {
+    registers: [u32; SIMULATED_REGISTER_COUNT],
+    status: usize,
+    write_count: usize,
+    writes: [RecordedWrite; SIMULATED_WRITE_LOG_CAPACITY],
+}
+
+/// A write the driver made to one of the simulated registers
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(C)]
+pub struct RecordedWrite {
+    /// The register's byte offset from the start of the page
+    pub offset: u32,
+    pub value: u32,
+}
+
+/// One thing for the simulated device to do. Registers are given by byte
+/// offset, as in the device's datasheet.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScriptStep {
+    /// Set a register, as the device would, for the driver to read
+    Set { offset: usize, value: u32 },
+    /// Wait for the driver to write `value` to a register
+    AwaitWrite { offset: usize, value: u32 },
+    /// Wait for the bits of a register under `mask` to read `value`
+    AwaitBits {
+        offset: usize,
+        mask: u32,
+        value: u32,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScriptError {
+    /// The step's offset isn't that of a simulated register
+    BadOffset { step: usize },
+    /// The step was still waiting after `max_polls` polls
+    Timeout { step: usize },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScriptStatus {
+    Running,
+    Done,
+    /// The device gave up at this step of its script
+    Failed {
+        step: usize,
+    },
+}
+
+fn register_index(offset: usize) -> Option<usize> {
+    if offset % 4 == 0 && offset / 4 < SIMULATED_REGISTER_COUNT {
+        Some(offset / 4)
+    } else {
+        None
+    }
+}
+
+/// The device's side of the shared page
+pub struct SimulatedDevice {
+    region: MappedMemoryRegion<PageBits, shared_status::Shared>,
+    /// The registers as of the last poll
+    shadow: [u32; SIMULATED_REGISTER_COUNT],
+    /// The first write not yet looked at by an `AwaitWrite`
+    next_unawaited_write: usize,
+}
+
+impl SimulatedDevice {
+    /// The page should be freshly retyped, and so zeroed, with the device
+    /// started before the driver.
+    pub fn new(region: MappedMemoryRegion<PageBits, shared_status::Shared>) -> Self {
+        SimulatedDevice {
+            region,
+            shadow: [0; SIMULATED_REGISTER_COUNT],
+            next_unawaited_write: 0,
+        }
+    }
+
+    fn block(&self) -> *mut DeviceBlock {
+        self.region.vaddr() as *mut DeviceBlock
+    }
+
+    /// Work through `script`, giving up on a step that's still waiting
+    /// after `max_polls` polls. Whether the script finished is also left
+    /// in the page for the monitor.
+    pub fn run(&mut self, script: &[ScriptStep], max_polls: usize) -> Result<(), ScriptError> {
+        for (step, action) in script.iter().enumerate() {
+            if let Err(e) = self.run_step(step, action, max_polls) {
+                self.set_status(STATUS_FAILED_AT_STEP + step);
+                return Err(e);
+            }
+        }
+        self.observe();
+        self.set_status(STATUS_DONE);
+        Ok(())
+    }
+
+    fn run_step(
+        &mut self,
+        step: usize,
+        action: &ScriptStep,
+        max_polls: usize,
+    ) -> Result<(), ScriptError> {
+        match *action {
+            ScriptStep::Set { offset, value } => {
+                let index = register_index(offset).ok_or(ScriptError::BadOffset { step })?;
+                // Catch any writes first, so the device's own doesn't hide them
+                self.observe();
+                unsafe { ptr::write_volatile(&mut (*self.block()).registers[index], value) };
+                self.shadow[index] = value;
+                Ok(())
+            }
+            ScriptStep::AwaitWrite { offset, value } => {
+                register_index(offset).ok_or(ScriptError::BadOffset { step })?;
+                let wanted = RecordedWrite {
+                    offset: offset as u32,
+                    value,
+                };
+                self.poll(step, max_polls, |device| {
+                    while device.next_unawaited_write < device.recorded_writes() {
+                        let write = device.recorded_write(device.next_unawaited_write);
+                        device.next_unawaited_write += 1;
+                        if write == wanted {
+                            return true;
+                        }
+                    }
+                    false
+                })
+            }
+            ScriptStep::AwaitBits {
+                offset,
+                mask,
+                value,
+            } => {
+                let index = register_index(offset).ok_or(ScriptError::BadOffset { step })?;
+                self.poll(step, max_polls, |device| {
+                    device.shadow[index] & mask == value
+                })
+            }
+        }
+    }
+
+    fn poll<F: FnMut(&mut Self) -> bool>(
+        &mut self,
+        step: usize,
+        max_polls: usize,
+        mut done: F,
+    ) -> Result<(), ScriptError> {
+        for _ in 0..max_polls {
+            self.observe();
+            if done(self) {
+                return Ok(());
+            }
+            unsafe { seL4_Yield() };
+        }
+        Err(ScriptError::Timeout { step })
+    }
+
+    /// Record whatever the driver's written since the last poll
+    fn observe(&mut self) {
+        let block = self.block();
+        for index in 0..SIMULATED_REGISTER_COUNT {
+            let value = unsafe { ptr::read_volatile(&(*block).registers[index]) };
+            if value != self.shadow[index] {
+                self.shadow[index] = value;
+                self.record(RecordedWrite {
+                    offset: (index * 4) as u32,
+                    value,
+                });
+            }
+        }
+    }
+
+    fn record(&mut self, write: RecordedWrite) {
+        let block = self.block();
+        let count = self.recorded_writes();
+        if count < SIMULATED_WRITE_LOG_CAPACITY {
+            unsafe {
+                ptr::write_volatile(&mut (*block).writes[count], write);
+                ptr::write_volatile(&mut (*block).write_count, count + 1);
+            }
+        }
+    }
+
+    fn recorded_writes(&self) -> usize {
+        unsafe { ptr::read_volatile(&(*self.block()).write_count) }
+    }
+
+    fn recorded_write(&self, index: usize) -> RecordedWrite {
+        unsafe { ptr::read_volatile(&(*self.block()).writes[index]) }
+    }
+
+    fn set_status(&mut self, status: usize) {
+        unsafe { ptr::write_volatile(&mut (*self.block()).status, status) };
+    }
+}
+
+/// A look at how a simulated device is getting on, from any process with
+/// the shared page mapped
+pub struct SimulatedDeviceMonitor<'a> {
+    block: *const DeviceBlock,
+    _region: PhantomData<&'a ()>,
+}
+
+impl<'a> SimulatedDeviceMonitor<'a> {
+    pub fn new(region: &'a MappedMemoryRegion<PageBits, shared_status::Shared>) -> Self {
+        SimulatedDeviceMonitor {
+            block: region.vaddr() as *const DeviceBlock,
+            _region: PhantomData,
+        }
+    }
+
+    pub fn status(&self) -> ScriptStatus {
+        match unsafe { ptr::read_volatile(&(*self.block).status) } {
+            STATUS_RUNNING => ScriptStatus::Running,
+            STATUS_DONE => ScriptStatus::Done,
+            failed => ScriptStatus::Failed {
+                step: failed - STATUS_FAILED_AT_STEP,
+            },
+        }
+    }
+
+    /// The driver's writes, oldest first
+    pub fn writes(&self) -> impl Iterator<Item = RecordedWrite> + '_ {
+        let count = unsafe { ptr::read_volatile(&(*self.block).write_count) };
+        (0..count).map(move |index| unsafe { ptr::read_volatile(&(*self.block).writes[index]) })
+    }
+}
+
+/// The parameters of a simulated device process, see `simulated_device_run`
+pub struct SimulatedDeviceParams<Role: CNodeRole> {
+    pub region: MappedMemoryRegion<PageBits, shared_status::Shared>,
+    /// The script has to be somewhere the device process can see it, such
+    /// as a `static` in the code image it shares with its parent
+    pub script: &'static [ScriptStep],
+    pub max_polls: usize,
+    pub _role: PhantomData<Role>,
+}
+
+impl RetypeForSetup for SimulatedDeviceParams<role::Local> {
+    type Output = SimulatedDeviceParams<role::Child>;
+}
+
+/// The entry point of a simulated device process; its outcome is left in
+/// the shared page for a `SimulatedDeviceMonitor`.
+pub extern "C" fn simulated_device_run(params: SimulatedDeviceParams<role::Local>) {
+    let SimulatedDeviceParams {
+        region,
+        script,
+        max_polls,
+        ..
+    } = params;
+    let _ = SimulatedDevice::new(region).run(script, max_polls);
+}
@@ -28,6 +28,7 @@ pub type RunTest = dyn Fn(
     >,
     &LocalCap<LocalCNode>,
     &LocalCap<ThreadPriorityAuthority>,
+    &mut VSpace,
     &LocalCap<crate::arch::PagingRoot>,
     &UserImage<role::Local>,
     LocalCap<IRQControl>,
@@ -0,0 +1,105 @@
+//! Bounding how much a consumer's handler does per wakeup.
+//!
+//! A handler draining a ring or a queue for as long as there's something
+//! on it can hold its core for as long as the producer keeps up, leaving
+//! any other thread on the core waiting. A `BatchBudget` runs a handler's
+//! work a step at a time, at most so many steps per wakeup. When the
+//! budget runs out with work possibly left, it signals the consumer's own
+//! `Waker` and yields, so that the other threads get their turn before
+//! the consumer's woken again to carry on where it left off.
+//!
+//! ```ignore
+//! let rx_batch = BatchBudget::new(RX_BATCH_FRAMES, params.waker);
+//! consumer.consume(state, |mut state| {
+//!     if state.device.ack_irqs() || state.rx_backlog {
+//!         let batch = rx_batch.run(|| match state.device.receive() {
+//!             Some(frame) => { ...; BatchStep::Processed }
+//!             None => BatchStep::Empty,
+//!         });
+//!         state.rx_backlog = batch.deferred;
+//!     }
+//!     state
+//! }, ...);
+//! ```
+use core::cell::Cell;
+
+use selfe_sys::seL4_Yield;
+
+use crate::cap::role;
+use crate::userland::Waker;
+
+/// What a step of a batch found to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatchStep {
+    /// It handled an element, and there may be more
+    Processed,
+    /// There was nothing left to handle
+    Empty,
+}
+
+/// How a run of a batch went
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Batch {
+    /// Elements handled
+    pub processed: usize,
+    /// Whether the budget ran out first, leaving the rest to the next
+    /// wakeup
+    pub deferred: bool,
+}
+
+pub struct BatchBudget {
+    max_per_wakeup: usize,
+    waker: Waker<role::Local>,
+    deferrals: Cell<usize>,
+}
+
+impl BatchBudget {
+    /// A budget of `max_per_wakeup` steps, deferring the rest with
+    /// `waker`, a waker for the consumer running the batches
+    pub fn new(max_per_wakeup: usize, waker: Waker<role::Local>) -> Self {
+        BatchBudget {
+            max_per_wakeup,
+            waker,
+            deferrals: Cell::new(0),
+        }
+    }
+
+    pub fn max_per_wakeup(&self) -> usize {
+        self.max_per_wakeup
+    }
+
+    /// How many runs have been cut short by the budget
+    pub fn deferrals(&self) -> usize {
+        self.deferrals.get()
+    }
+
+    /// Run `step` until it comes up empty or the budget's spent.
+    ///
+    /// Once it's spent the consumer is woken again through its waker's
+    /// path, so the handler carrying on with the work has to be the one
+    /// run for the waker, e.g. the one for the interrupt, and has to
+    /// remember that there's work left rather than count on the device
+    /// saying so again. A budget running out just as the work does costs
+    /// one wakeup that finds nothing to do.
+    pub fn run<F>(&self, mut step: F) -> Batch
+    where
+        F: FnMut() -> BatchStep,
+    {
+        for processed in 0..self.max_per_wakeup {
+            if step() == BatchStep::Empty {
+                return Batch {
+                    processed,
+                    deferred: false,
+                };
+            }
+        }
+
+        self.deferrals.set(self.deferrals.get() + 1);
+        self.waker.send_wakeup_signal();
+        unsafe { seL4_Yield() };
+        Batch {
+            processed: self.max_per_wakeup,
+            deferred: true,
+        }
+    }
+}
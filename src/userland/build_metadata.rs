@@ -0,0 +1,70 @@
+//! Build metadata compiled into the root task and its children.
+//!
+//! `ferros_build::write_build_metadata` generates a `BUILD_METADATA`
+//! constant for each crate that includes it. The root task hands its own
+//! to each child in the child's process parameters, and the child checks
+//! it against the one it was built with, so a driver left over from a
+//! different build shows up at boot rather than as a mysterious protocol
+//! mismatch later on.
+
+/// The build profile a crate was compiled with
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildProfile {
+    Debug,
+    Release,
+    Unknown,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BuildMetadata {
+    /// The git commit the crate was built from, as hex, or all zeros if
+    /// it wasn't built from a git checkout
+    pub git_hash: [u8; 40],
+    /// Whether the checkout had uncommitted changes
+    pub git_dirty: bool,
+    pub profile: BuildProfile,
+    /// Seconds since the Unix epoch
+    pub build_timestamp: u64,
+    /// A hash of the resources embedded in the crate, zero if none are
+    pub resources_hash: u64,
+}
+
+/// How two builds differ, see `BuildMetadata::check_skew`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildSkew {
+    /// They were built from different commits
+    GitHash,
+    /// They were built from the same commit, but at least one of them
+    /// had uncommitted changes
+    DirtyCheckout,
+    Profile,
+}
+
+impl BuildMetadata {
+    /// The git commit hash, or `None` if it's unknown.
+    pub fn git_hash(&self) -> Option<&str> {
+        if self.git_hash.iter().all(|b| *b == 0) {
+            return None;
+        }
+        core::str::from_utf8(&self.git_hash).ok()
+    }
+
+    /// Check this build came from the same source and profile as
+    /// `other`, e.g. a child's own metadata against the root task's.
+    /// The timestamps and resource hashes are expected to differ
+    /// between crates and aren't compared.
+    pub fn check_skew(&self, other: &BuildMetadata) -> Result<(), BuildSkew> {
+        if self.git_hash != other.git_hash {
+            return Err(BuildSkew::GitHash);
+        }
+        if self.git_dirty || other.git_dirty {
+            return Err(BuildSkew::DirtyCheckout);
+        }
+        if self.profile != other.profile {
+            return Err(BuildSkew::Profile);
+        }
+        Ok(())
+    }
+}
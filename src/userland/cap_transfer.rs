@@ -0,0 +1,216 @@
+//! Sending a capability along with a message.
+//!
+//! A channel whose message type is a `CapMessage<T, C>` carries a
+//! capability to a `C` with each `T`, e.g. a device manager's
+//! `Sender<CapMessage<DeviceId, IRQHandler<..>>, _>` handing a driver that
+//! started later the interrupt for its device. The kernel copies the
+//! capability into a slot the receiver sets aside for it as the message
+//! is delivered, then the sender's own copy is deleted, so the capability
+//! moves from one to the other.
+//!
+//! The receiver gets nothing but the capability itself to go on, so only
+//! capabilities that carry no other state, those that are `PhantomCap`,
+//! can be received.
+//!
+//! ```ignore
+//! // device manager
+//! irq_sender.blocking_send_with_cap(&DeviceId::Uart1, irq_handler, &cnode)?;
+//! // driver
+//! match irq_handler.await_message_with_cap(slot)? {
+//!     FaultOrMessage::Message((device, irq)) => ...,
+//!     FaultOrMessage::Fault(_) => ...,
+//! }
+//! ```
+use core::marker::PhantomData;
+
+use selfe_sys::*;
+
+use crate::arch;
+use crate::cap::{role, Badge, Cap, CapType, LocalCNode, LocalCNodeSlot, LocalCap, PhantomCap};
+use crate::error::{ErrorExt, SeL4Error};
+use crate::userland::correlation::{current_correlation_id, with_correlation_id, CorrelationId};
+use crate::userland::ipc::{
+    labeled_type_length_message_info, type_length_in_words, unchecked_raw_ipc_buffer, IPCBuffer,
+    MessageInfo,
+};
+use crate::userland::{Caller, FaultOrMessage, FaultOrMessageHandler, IPCError, Responder, Sender};
+
+/// The message type of a channel carrying a capability to a `C` with
+/// each `T`. Never sent itself, only there to give the channel's message
+/// type the size of a `T` and the type of the capability.
+pub struct CapMessage<T, C: CapType>(T, PhantomData<C>);
+
+impl<T, C: CapType, Rsp> Caller<CapMessage<T, C>, Rsp, role::Local> {
+    /// Call with `message`, moving `cap` to the responder
+    pub fn blocking_call_with_cap(
+        &self,
+        message: &T,
+        cap: LocalCap<C>,
+        local_cnode: &LocalCap<LocalCNode>,
+    ) -> Result<Rsp, IPCError> {
+        // Sizes were checked when the channel was set up
+        let mut ipc_buffer: IPCBuffer<(), ()> = unsafe { IPCBuffer::unchecked_new() };
+        let msg_info: MessageInfo = unsafe {
+            ipc_buffer.unchecked_copy_into_buffer(message);
+            offer_cap(&cap);
+            seL4_Call(
+                self.endpoint.cptr,
+                cap_message_info::<T>(CorrelationId::into_label(current_correlation_id())),
+            )
+        }
+        .into();
+        delete_sent_cap(cap, local_cnode)?;
+        if msg_info.length_words() != type_length_in_words::<Rsp>() {
+            return Err(IPCError::ResponseSizeMismatch);
+        }
+        Ok(unsafe { ipc_buffer.unchecked_copy_from_buffer() })
+    }
+}
+
+impl<T, C: CapType + PhantomCap, Rsp> Responder<CapMessage<T, C>, Rsp, role::Local> {
+    /// Handle a single call, receiving its capability into `slot`
+    pub fn recv_reply_once_with_cap<F>(&self, slot: LocalCNodeSlot, f: F) -> Result<(), IPCError>
+    where
+        F: FnOnce(T, LocalCap<C>) -> Rsp,
+    {
+        let mut ipc_buffer: IPCBuffer<(), ()> = unsafe { IPCBuffer::unchecked_new() };
+        let mut sender_badge: usize = 0;
+        let msg_info: MessageInfo = unsafe {
+            let receive_cptr = set_receive_slot(slot);
+            let msg_info = seL4_Recv(self.endpoint.cptr, &mut sender_badge as *mut usize).into();
+            clear_receive_slot();
+            received_cap::<C>(&msg_info, receive_cptr).map(|cap| (msg_info, cap))
+        }
+        .and_then(|(msg_info, cap)| {
+            if msg_info.length_words() != type_length_in_words::<T>() {
+                return Err(IPCError::RequestSizeMismatch);
+            }
+            let correlation_id = CorrelationId::from_label(msg_info.label());
+            let request = unsafe { ipc_buffer.unchecked_copy_from_buffer() };
+            let response = with_correlation_id(correlation_id, || f(request, cap));
+            unsafe { ipc_buffer.unchecked_copy_into_buffer(&response) };
+            Ok(labeled_type_length_message_info::<Rsp>(
+                CorrelationId::into_label(correlation_id),
+            ))
+        })?;
+
+        unsafe { seL4_Reply(msg_info) };
+        Ok(())
+    }
+}
+
+impl<T, C: CapType> Sender<CapMessage<T, C>, role::Local> {
+    /// Send `message`, moving `cap` to the receiver
+    pub fn blocking_send_with_cap(
+        &self,
+        message: &T,
+        cap: LocalCap<C>,
+        local_cnode: &LocalCap<LocalCNode>,
+    ) -> Result<(), IPCError> {
+        // Sizes were checked when the channel was set up
+        let mut ipc_buffer: IPCBuffer<(), ()> = unsafe { IPCBuffer::unchecked_new() };
+        unsafe {
+            ipc_buffer.unchecked_copy_into_buffer(message);
+            offer_cap(&cap);
+            seL4_Send(self.endpoint.cptr, cap_message_info::<T>(0));
+        }
+        delete_sent_cap(cap, local_cnode)
+    }
+}
+
+impl<T, C: CapType + PhantomCap> FaultOrMessageHandler<CapMessage<T, C>, role::Local> {
+    /// Wait for a fault or a message, receiving a message's capability
+    /// into `slot`
+    pub fn await_message_with_cap(
+        &self,
+        slot: LocalCNodeSlot,
+    ) -> Result<FaultOrMessage<(T, LocalCap<C>)>, IPCError> {
+        let ipc_buffer: IPCBuffer<(), ()> = unsafe { IPCBuffer::unchecked_new() };
+        let mut sender: usize = 0;
+        let msg_info: MessageInfo;
+        let receive_cptr;
+        unsafe {
+            receive_cptr = set_receive_slot(slot);
+            msg_info = seL4_Recv(self.endpoint.cptr, &mut sender as *mut usize).into();
+            clear_receive_slot();
+        }
+
+        if !msg_info.has_null_fault_label() {
+            return Ok(FaultOrMessage::Fault(
+                (msg_info, Badge::from(sender)).into(),
+            ));
+        }
+        let cap = received_cap::<C>(&msg_info, receive_cptr)?;
+        if msg_info.length_words() != type_length_in_words::<T>() {
+            return Err(IPCError::RequestSizeMismatch);
+        }
+        Ok(FaultOrMessage::Message((
+            unsafe { ipc_buffer.unchecked_copy_from_buffer() },
+            cap,
+        )))
+    }
+}
+
+pub(crate) fn cap_message_info<T>(label: usize) -> seL4_MessageInfo_t {
+    unsafe {
+        seL4_MessageInfo_new(
+            arch::to_sel4_word(label),                       // label,
+            0,                                               // capsUnwrapped,
+            1,                                               // extraCaps,
+            arch::to_sel4_word(type_length_in_words::<T>()), // length in words!
+        )
+    }
+}
+
+/// Put `cap` in the IPC buffer to go with the next message sent
+pub(crate) unsafe fn offer_cap<C: CapType>(cap: &LocalCap<C>) {
+    unchecked_raw_ipc_buffer().caps_or_badges[0] = cap.cptr as _;
+}
+
+/// Have the next message received put the capability it comes with in
+/// `slot`, returning where that is
+pub(crate) unsafe fn set_receive_slot(slot: LocalCNodeSlot) -> usize {
+    let (cnode_cptr, offset, _) = slot.elim();
+    set_receive_cptr(cnode_cptr, offset);
+    offset
+}
+
+/// As `set_receive_slot`, for a slot that's received into over and over,
+/// emptied each time
+pub(crate) unsafe fn set_receive_cptr(cnode_cptr: usize, offset: usize) {
+    let buffer = unchecked_raw_ipc_buffer();
+    buffer.receiveCNode = cnode_cptr as _;
+    buffer.receiveIndex = offset as _;
+    buffer.receiveDepth = seL4_WordBits as _;
+}
+
+/// Stop accepting capabilities, so that a later plain receive doesn't
+/// try to put one in a slot that's since been filled
+pub(crate) unsafe fn clear_receive_slot() {
+    let buffer = unchecked_raw_ipc_buffer();
+    buffer.receiveCNode = 0;
+    buffer.receiveIndex = 0;
+    buffer.receiveDepth = 0;
+}
+
+pub(crate) fn received_cap<C: CapType + PhantomCap>(
+    msg_info: &MessageInfo,
+    receive_cptr: usize,
+) -> Result<LocalCap<C>, IPCError> {
+    // An unwrapped capability was one to the receiving endpoint itself,
+    // and only its badge came through
+    if msg_info.extra_caps() != 1 || msg_info.caps_unwrapped() != 0 {
+        return Err(IPCError::CapNotTransferred);
+    }
+    Ok(Cap::wrap_cptr(receive_cptr))
+}
+
+/// The kernel has made the receiver a copy, so the sender's can go
+fn delete_sent_cap<C: CapType>(
+    cap: LocalCap<C>,
+    local_cnode: &LocalCap<LocalCNode>,
+) -> Result<(), IPCError> {
+    unsafe { seL4_CNode_Delete(local_cnode.cptr, cap.cptr, seL4_WordBits as u8) }
+        .as_result()
+        .map_err(|e| IPCError::SeL4Error(SeL4Error::CNodeDelete(e)))
+}
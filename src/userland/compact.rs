@@ -0,0 +1,316 @@
+//! Fault-or-message channels carrying postcard-encoded messages.
+//!
+//! A plain `fault_or_message_channel` copies its message type into the IPC
+//! buffer as it is in memory, so the type has to fit in the buffer and be
+//! plain data. The channels here encode a message with postcard instead,
+//! which is usually far smaller than the type and lets the message have
+//! variable length parts. A message that encodes too big for the IPC
+//! buffer can go by way of a page shared between the sender and the
+//! handler, where the channel has one.
+//!
+//! On the wire, the first word of the message is the encoded length in
+//! bytes, with `OVERFLOW_FLAG` set if the bytes are in the shared page
+//! rather than following it in the IPC buffer. A message in the shared
+//! page is sent with a call, which the handler answers once it's decoded
+//! the message, so the sender can't overwrite the page before then.
+//!
+//! ```ignore
+//! #[derive(Serialize, Deserialize)]
+//! enum Report {
+//!     Progress { done: u32, total: u32 },
+//!     Finished { log: heapless::Vec<u8, U2048> },
+//! }
+//!
+//! let (fault_source, report_sender, handler) = fault_or_compact_message_channel_with_overflow(
+//!     &root_cnode, ut, slots, child_fault_source_slot, slots, page_ut, slots,
+//!     &mut root_vspace, &mut child_vspace,
+//! )?;
+//! // ...start the child with `report_sender` in its params...
+//! match handler.await_message()? {
+//!     FaultOrMessage::Message(Report::Finished { log }) => (),
+//!     ...
+//! }
+//! ```
+use core::marker::PhantomData;
+
+use selfe_sys::*;
+use serde::{de::DeserializeOwned, Serialize};
+use typenum::{Unsigned, U2};
+
+use crate::arch::{self, PageBits, PageBytes};
+use crate::cap::{
+    role, Badge, CNodeRole, CNodeSlot, Cap, ChildCNodeSlot, DirectRetype, Endpoint, LocalCNode,
+    LocalCNodeSlot, LocalCNodeSlots, LocalCap, Untyped,
+};
+use crate::error::SeL4Error;
+use crate::userland::ipc::unchecked_raw_ipc_buffer;
+use crate::userland::{
+    fault_or_message_channel, CapRights, FaultManagementError, FaultOrMessage, FaultSource,
+    MessageInfo,
+};
+use crate::vspace::{UnmappedMemoryRegion, VSpace, VSpaceError};
+
+/// Set in a message's length word when the encoded message is in the
+/// shared page
+pub const OVERFLOW_FLAG: usize = 1 << (core::mem::size_of::<usize>() * 8 - 1);
+
+#[derive(Debug)]
+pub enum CompactMessageError {
+    /// The message encodes too big for the IPC buffer, and for the
+    /// shared page if the channel has one
+    TooBig,
+    /// What arrived isn't a message as a compact sender sends them
+    Malformed,
+    Postcard(postcard::Error),
+    FaultManagementError(FaultManagementError),
+    VSpaceError(VSpaceError),
+    SeL4Error(SeL4Error),
+}
+
+impl From<postcard::Error> for CompactMessageError {
+    fn from(e: postcard::Error) -> Self {
+        CompactMessageError::Postcard(e)
+    }
+}
+
+impl From<FaultManagementError> for CompactMessageError {
+    fn from(e: FaultManagementError) -> Self {
+        CompactMessageError::FaultManagementError(e)
+    }
+}
+
+impl From<VSpaceError> for CompactMessageError {
+    fn from(e: VSpaceError) -> Self {
+        CompactMessageError::VSpaceError(e)
+    }
+}
+
+impl From<SeL4Error> for CompactMessageError {
+    fn from(e: SeL4Error) -> Self {
+        CompactMessageError::SeL4Error(e)
+    }
+}
+
+/// Set up a channel for a child's faults and its postcard-encoded
+/// messages, which have to fit in the IPC buffer once encoded.
+pub fn fault_or_compact_message_channel<Msg, HandlerRole: CNodeRole>(
+    local_cnode: &LocalCap<LocalCNode>,
+    untyped: LocalCap<Untyped<<Endpoint as DirectRetype>::SizeBits>>,
+    endpoint_slot: LocalCNodeSlot,
+    fault_source_slot: ChildCNodeSlot,
+    handler_slot: CNodeSlot<HandlerRole>,
+) -> Result<
+    (
+        FaultSource<role::Child>,
+        CompactSender<Msg, role::Child>,
+        CompactFaultOrMessageHandler<Msg, HandlerRole>,
+    ),
+    CompactMessageError,
+>
+where
+    Msg: Serialize + DeserializeOwned,
+{
+    let (fault_source, sender, handler) = fault_or_message_channel::<(), HandlerRole>(
+        local_cnode,
+        untyped,
+        endpoint_slot,
+        fault_source_slot,
+        handler_slot,
+    )?;
+    Ok((
+        fault_source,
+        CompactSender {
+            endpoint: sender.endpoint,
+            overflow_page_address: None,
+            _msg: PhantomData,
+        },
+        CompactFaultOrMessageHandler {
+            endpoint: handler.endpoint,
+            overflow_page_address: None,
+            _msg: PhantomData,
+        },
+    ))
+}
+
+/// Set up a channel for a child's faults and its postcard-encoded
+/// messages, with a page shared between the child's `sender_vspace` and
+/// the handler's `handler_vspace` for the messages that encode too big
+/// for the IPC buffer.
+pub fn fault_or_compact_message_channel_with_overflow<Msg, HandlerRole: CNodeRole>(
+    local_cnode: &LocalCap<LocalCNode>,
+    untyped: LocalCap<Untyped<<Endpoint as DirectRetype>::SizeBits>>,
+    endpoint_slot: LocalCNodeSlot,
+    fault_source_slot: ChildCNodeSlot,
+    handler_slot: CNodeSlot<HandlerRole>,
+    overflow_page_ut: LocalCap<Untyped<PageBits>>,
+    local_slots: LocalCNodeSlots<U2>,
+    handler_vspace: &mut VSpace,
+    sender_vspace: &mut VSpace,
+) -> Result<
+    (
+        FaultSource<role::Child>,
+        CompactSender<Msg, role::Child>,
+        CompactFaultOrMessageHandler<Msg, HandlerRole>,
+    ),
+    CompactMessageError,
+>
+where
+    Msg: Serialize + DeserializeOwned,
+{
+    let (fault_source, mut sender, mut handler) = fault_or_compact_message_channel(
+        local_cnode,
+        untyped,
+        endpoint_slot,
+        fault_source_slot,
+        handler_slot,
+    )?;
+
+    let (slot, local_slots) = local_slots.alloc();
+    let region = UnmappedMemoryRegion::new(overflow_page_ut, slot)?;
+    let shared_region = region.to_shared();
+
+    let (slot, _local_slots) = local_slots.alloc();
+    let handler_region = handler_vspace.map_shared_region(
+        &shared_region,
+        CapRights::RW,
+        arch::vm_attributes::DEFAULT,
+        slot,
+        local_cnode,
+    )?;
+    let sender_region = sender_vspace.map_shared_region_and_consume(
+        shared_region,
+        CapRights::RW,
+        arch::vm_attributes::DEFAULT,
+    )?;
+
+    handler.overflow_page_address = Some(handler_region.vaddr());
+    sender.overflow_page_address = Some(sender_region.vaddr());
+    Ok((fault_source, sender, handler))
+}
+
+/// The sending side of a compact fault-or-message channel
+#[derive(Debug)]
+pub struct CompactSender<Msg, Role: CNodeRole> {
+    endpoint: Cap<Endpoint, Role>,
+    overflow_page_address: Option<usize>,
+    _msg: PhantomData<Msg>,
+}
+
+impl<Msg: Serialize> CompactSender<Msg, role::Local> {
+    /// Send `message`, waiting for the handler to decode it if it went by
+    /// way of the shared page.
+    pub fn blocking_send(&self, message: &Msg) -> Result<(), CompactMessageError> {
+        let buffer = unchecked_raw_ipc_buffer();
+        let (length, inline) = buffer.msg.split_at_mut(1);
+        match postcard::to_slice(message, as_bytes_mut(inline)) {
+            Ok(encoded) => {
+                length[0] = encoded.len();
+                unsafe {
+                    seL4_Send(
+                        self.endpoint.cptr,
+                        message_info(1 + words_for_bytes(encoded.len())),
+                    )
+                };
+                Ok(())
+            }
+            Err(postcard::Error::SerializeBufferFull) => {
+                let page = match self.overflow_page_address {
+                    Some(address) => unsafe { overflow_page(address) },
+                    None => return Err(CompactMessageError::TooBig),
+                };
+                let encoded = postcard::to_slice(message, page).map_err(|e| match e {
+                    postcard::Error::SerializeBufferFull => CompactMessageError::TooBig,
+                    e => CompactMessageError::Postcard(e),
+                })?;
+                length[0] = encoded.len() | OVERFLOW_FLAG;
+                // Calling leaves us blocked until the handler's done with
+                // the page
+                unsafe { seL4_Call(self.endpoint.cptr, message_info(1)) };
+                Ok(())
+            }
+            Err(e) => Err(CompactMessageError::Postcard(e)),
+        }
+    }
+}
+
+/// The handling side of a compact fault-or-message channel
+pub struct CompactFaultOrMessageHandler<Msg, Role: CNodeRole> {
+    endpoint: Cap<Endpoint, Role>,
+    overflow_page_address: Option<usize>,
+    _msg: PhantomData<Msg>,
+}
+
+impl<Msg: DeserializeOwned> CompactFaultOrMessageHandler<Msg, role::Local> {
+    pub fn await_message(&self) -> Result<FaultOrMessage<Msg>, CompactMessageError> {
+        let mut sender: usize = 0;
+        let msg_info: MessageInfo =
+            unsafe { seL4_Recv(self.endpoint.cptr, &mut sender as *mut usize) }.into();
+
+        if !msg_info.has_null_fault_label() {
+            return Ok(FaultOrMessage::Fault(
+                (msg_info, Badge::from(sender)).into(),
+            ));
+        }
+        if msg_info.length_words() == 0 {
+            return Err(CompactMessageError::Malformed);
+        }
+
+        let buffer = unchecked_raw_ipc_buffer();
+        let length = buffer.msg[0];
+        if length & OVERFLOW_FLAG == 0 {
+            if 1 + words_for_bytes(length) != msg_info.length_words() {
+                return Err(CompactMessageError::Malformed);
+            }
+            let encoded = &as_bytes_mut(&mut buffer.msg[1..])[..length];
+            return Ok(FaultOrMessage::Message(postcard::from_bytes(encoded)?));
+        }
+
+        let length = length & !OVERFLOW_FLAG;
+        let decoded = match self.overflow_page_address {
+            Some(address) if length <= PageBytes::USIZE => {
+                let page = unsafe { overflow_page(address) };
+                postcard::from_bytes(&page[..length]).map_err(CompactMessageError::from)
+            }
+            _ => Err(CompactMessageError::Malformed),
+        };
+        // The sender's waiting on the reply whether or not the message
+        // made sense, so it always gets one
+        unsafe { seL4_Reply(message_info(0)) };
+        Ok(FaultOrMessage::Message(decoded?))
+    }
+}
+
+fn message_info(length_words: usize) -> seL4_MessageInfo_t {
+    unsafe {
+        seL4_MessageInfo_new(
+            0,                                // label,
+            0,                                // capsUnwrapped,
+            0,                                // extraCaps,
+            arch::to_sel4_word(length_words), // length in words!
+        )
+    }
+}
+
+fn words_for_bytes(bytes: usize) -> usize {
+    let word_bytes = core::mem::size_of::<usize>();
+    (bytes + word_bytes - 1) / word_bytes
+}
+
+fn as_bytes_mut(words: &mut [usize]) -> &mut [u8] {
+    unsafe {
+        core::slice::from_raw_parts_mut(
+            words.as_mut_ptr() as *mut u8,
+            core::mem::size_of_val(words),
+        )
+    }
+}
+
+/// The shared page mapped at `address`
+///
+/// # Safety
+///
+/// `address` has to be where the channel's shared page is mapped in the
+/// calling thread's vspace.
+unsafe fn overflow_page<'a>(address: usize) -> &'a mut [u8] {
+    core::slice::from_raw_parts_mut(address as *mut u8, PageBytes::USIZE)
+}
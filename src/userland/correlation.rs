@@ -0,0 +1,129 @@
+//! Correlation ids tie together the work different processes do on
+//! behalf of a single action.
+//!
+//! Each process has a current correlation id. `Caller::blocking_call`
+//! attaches it to what it sends, and `Responder` makes the attached id
+//! current while the handler for that message runs. Multi-consumer queues
+//! carry it when their element type is `Correlated`. Loggers can include the current id in
+//! their records to follow an action across processes.
+use core::fmt;
+use core::num::NonZeroU32;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// This process' current correlation id, zero when there is none.
+///
+/// ferros processes run their message handling on a single thread, so a
+/// per-process value serves as the thread-local context.
+static CURRENT: AtomicU32 = AtomicU32::new(0);
+
+/// Source of fresh ids for actions started in this process
+static NEXT: AtomicU32 = AtomicU32::new(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[repr(transparent)]
+pub struct CorrelationId(NonZeroU32);
+
+impl CorrelationId {
+    /// Ids are carried in the IPC message label, which is 20 bits wide
+    /// on 32 bit platforms
+    pub const MAX: u32 = (1 << 20) - 1;
+
+    pub fn new(id: u32) -> Option<Self> {
+        if id > Self::MAX {
+            return None;
+        }
+        NonZeroU32::new(id).map(CorrelationId)
+    }
+
+    /// A fresh id for an action starting in this process
+    pub fn next() -> Self {
+        loop {
+            let id = NEXT.fetch_add(1, Ordering::Relaxed) & Self::MAX;
+            if let Some(id) = Self::new(id) {
+                return id;
+            }
+        }
+    }
+
+    pub fn get(self) -> u32 {
+        self.0.get()
+    }
+
+    pub(crate) fn from_label(label: usize) -> Option<Self> {
+        Self::new(label as u32)
+    }
+
+    pub(crate) fn into_label(id: Option<Self>) -> usize {
+        id.map_or(0, |id| id.get() as usize)
+    }
+}
+
+impl fmt::Display for CorrelationId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:05x}", self.get())
+    }
+}
+
+/// The correlation id of the action this process is currently working on
+pub fn current_correlation_id() -> Option<CorrelationId> {
+    CorrelationId::new(CURRENT.load(Ordering::Relaxed))
+}
+
+/// Replace the current correlation id, returning the previous one
+pub fn set_current_correlation_id(id: Option<CorrelationId>) -> Option<CorrelationId> {
+    let previous = CURRENT.swap(id.map_or(0, CorrelationId::get), Ordering::Relaxed);
+    CorrelationId::new(previous)
+}
+
+/// Run `f` with `id` as the current correlation id, restoring the previous
+/// one afterwards
+pub fn with_correlation_id<F, R>(id: Option<CorrelationId>, f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    let previous = set_current_correlation_id(id);
+    let result = f();
+    set_current_correlation_id(previous);
+    result
+}
+
+/// A queue element along with the correlation id current when it was sent.
+///
+/// Multi-consumer queues carry their elements as they are, so a queue only
+/// carries ids if its element type is `Correlated`:
+///
+/// ```ignore
+/// // Sending side, with a `Producer<role::Local, Correlated<Request>>`
+/// producer.send(Correlated::current(request))?;
+///
+/// // Consuming side, with a `Consumer1<role::Local, Correlated<Request>>`
+/// consumer.consume(state, |request, state| request.handle(|r| handle_request(r, state)));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(C)]
+pub struct Correlated<T> {
+    pub correlation_id: Option<CorrelationId>,
+    pub value: T,
+}
+
+impl<T> Correlated<T> {
+    /// Tag `value` with this process' current correlation id
+    pub fn current(value: T) -> Self {
+        Correlated {
+            correlation_id: current_correlation_id(),
+            value,
+        }
+    }
+
+    /// Run `f` on the value with its id as the current correlation id
+    pub fn handle<F, R>(self, f: F) -> R
+    where
+        F: FnOnce(T) -> R,
+    {
+        let Correlated {
+            correlation_id,
+            value,
+        } = self;
+        with_correlation_id(correlation_id, || f(value))
+    }
+}
@@ -0,0 +1,150 @@
+//! Worst-case execution budgets for event-loop handlers, checked as they
+//! run.
+//!
+//! A `DeadlineMonitor` wraps the handlers given to a consumer's `consume`
+//! and times each invocation with the cycle counter. An invocation running
+//! past its handler's declared budget is reported through the monitor's
+//! reporting function, e.g. to the process' logger, so that a driver whose
+//! callbacks occasionally blow their latency budget under load shows up
+//! while it's running rather than as a missed deadline further along.
+//!
+//! ```ignore
+//! let monitor = unsafe { DeadlineMonitor::new(report_budget_violation) };
+//! consumer.consume(
+//!     state,
+//!     monitor.waker_handler("irq", IRQ_BUDGET_CYCLES, |state| ...),
+//!     monitor.queue_handler("tx", TX_BUDGET_CYCLES, |frame, state| ...),
+//! );
+//! ```
+use core::cell::Cell;
+use core::fmt;
+
+use crate::arch;
+
+/// A handler invocation that ran past its budget
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BudgetViolation {
+    /// The handler's name, as given to the monitor
+    pub handler: &'static str,
+    pub budget_cycles: usize,
+    pub elapsed_cycles: usize,
+    /// How many of the handler's invocations have run over, this one
+    /// included
+    pub violations: usize,
+    /// The longest any of the handler's invocations has run
+    pub worst_cycles: usize,
+}
+
+impl fmt::Display for BudgetViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Handler '{}' took {} cycles, over its budget of {} ({} violations, worst {})",
+            self.handler,
+            self.elapsed_cycles,
+            self.budget_cycles,
+            self.violations,
+            self.worst_cycles
+        )
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct DeadlineMonitor {
+    report: fn(&BudgetViolation),
+}
+
+impl DeadlineMonitor {
+    /// Start the cycle counter and make a monitor reporting violations
+    /// with `report`.
+    ///
+    /// # Safety
+    ///
+    /// The kernel must export the cycle counter to user level, see
+    /// `arch::enable_cycle_counter`.
+    pub unsafe fn new(report: fn(&BudgetViolation)) -> Self {
+        arch::enable_cycle_counter();
+        DeadlineMonitor { report }
+    }
+
+    /// A budget for a single handler, for timing code that isn't handed
+    /// to `consume` as a closure
+    pub fn handler(&self, name: &'static str, budget_cycles: usize) -> HandlerBudget {
+        HandlerBudget {
+            name,
+            budget_cycles,
+            report: self.report,
+            violations: Cell::new(0),
+            worst_cycles: Cell::new(0),
+        }
+    }
+
+    /// Wrap a consumer's non-queue wakeup handler
+    pub fn waker_handler<State, F>(
+        &self,
+        name: &'static str,
+        budget_cycles: usize,
+        f: F,
+    ) -> impl Fn(State) -> State
+    where
+        F: Fn(State) -> State,
+    {
+        let budget = self.handler(name, budget_cycles);
+        move |state| budget.measure(|| f(state))
+    }
+
+    /// Wrap a consumer's queue handler
+    pub fn queue_handler<E, State, F>(
+        &self,
+        name: &'static str,
+        budget_cycles: usize,
+        f: F,
+    ) -> impl Fn(E, State) -> State
+    where
+        F: Fn(E, State) -> State,
+    {
+        let budget = self.handler(name, budget_cycles);
+        move |e, state| budget.measure(|| f(e, state))
+    }
+}
+
+pub struct HandlerBudget {
+    name: &'static str,
+    budget_cycles: usize,
+    report: fn(&BudgetViolation),
+    violations: Cell<usize>,
+    worst_cycles: Cell<usize>,
+}
+
+impl HandlerBudget {
+    /// Run `f`, reporting it if it runs over the budget
+    pub fn measure<R, F: FnOnce() -> R>(&self, f: F) -> R {
+        let start = unsafe { arch::cycle_count() };
+        let r = f();
+        let elapsed_cycles = unsafe { arch::cycle_count() }.wrapping_sub(start);
+
+        if elapsed_cycles > self.worst_cycles.get() {
+            self.worst_cycles.set(elapsed_cycles);
+        }
+        if elapsed_cycles > self.budget_cycles {
+            self.violations.set(self.violations.get() + 1);
+            (self.report)(&BudgetViolation {
+                handler: self.name,
+                budget_cycles: self.budget_cycles,
+                elapsed_cycles,
+                violations: self.violations.get(),
+                worst_cycles: self.worst_cycles.get(),
+            });
+        }
+        r
+    }
+
+    /// The longest an invocation has run so far
+    pub fn worst_cycles(&self) -> usize {
+        self.worst_cycles.get()
+    }
+
+    pub fn violations(&self) -> usize {
+        self.violations.get()
+    }
+}
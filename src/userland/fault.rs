@@ -1,3 +1,4 @@
+use core::fmt;
 use core::marker::PhantomData;
 
 use selfe_sys::*;
@@ -120,6 +121,153 @@ impl FaultSink<role::Local> {
     }
 }
 
+/// What the faulting access in a `VMFault` was
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultAccess {
+    Read,
+    Write,
+    InstructionFetch,
+}
+
+/// Why a `VMFault` happened, decoded from the architecture's fault status
+/// bits. `level` is the level of the translation tables the fault was
+/// found at, where the architecture reports it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VMFaultCause {
+    /// Nothing is mapped at the address
+    Translation {
+        level: Option<u8>,
+    },
+    /// Something is mapped, but not with the rights for the access
+    Permission {
+        level: Option<u8>,
+    },
+    AccessFlag {
+        level: Option<u8>,
+    },
+    AddressSize {
+        level: Option<u8>,
+    },
+    Domain {
+        level: Option<u8>,
+    },
+    Alignment,
+    /// A reserved bit was set in a paging structure
+    ReservedBit,
+    ExternalAbort,
+    Debug,
+    /// A fault status code this decoding doesn't know
+    Unknown(usize),
+}
+
+/// A `VMFault`'s fault status register, decoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VMFaultStatus {
+    pub cause: VMFaultCause,
+    pub access: FaultAccess,
+}
+
+impl fmt::Display for VMFaultStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let access = match self.access {
+            FaultAccess::Read => "read",
+            FaultAccess::Write => "write",
+            FaultAccess::InstructionFetch => "instruction fetch",
+        };
+        let (cause, level) = match self.cause {
+            VMFaultCause::Translation { level } => ("nothing mapped", level),
+            VMFaultCause::Permission { level } => ("permission", level),
+            VMFaultCause::AccessFlag { level } => ("access flag", level),
+            VMFaultCause::AddressSize { level } => ("address size", level),
+            VMFaultCause::Domain { level } => ("domain", level),
+            VMFaultCause::Alignment => ("alignment", None),
+            VMFaultCause::ReservedBit => ("reserved bit set", None),
+            VMFaultCause::ExternalAbort => ("external abort", None),
+            VMFaultCause::Debug => ("debug event", None),
+            VMFaultCause::Unknown(code) => return write!(f, "{}, status {:#x}", access, code),
+        };
+        match level {
+            Some(level) => write!(f, "{}, {} at level {}", access, cause, level),
+            None => write!(f, "{}, {}", access, cause),
+        }
+    }
+}
+
+/// Why looking up the capability in a `CapFault` failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LookupFailure {
+    None,
+    InvalidRoot,
+    /// The slot the lookup ended up at is empty
+    MissingCapability {
+        bits_left: usize,
+    },
+    DepthMismatch {
+        bits_left: usize,
+        bits_found: usize,
+    },
+    GuardMismatch {
+        bits_left: usize,
+        guard_found: usize,
+        guard_bits: usize,
+    },
+    Unknown(usize),
+}
+
+impl LookupFailure {
+    /// Read the lookup failure out of a `CapFault` message in `buffer`
+    pub(crate) fn from_cap_fault(buffer: &seL4_IPCBuffer) -> LookupFailure {
+        let bits_left = buffer.msg[seL4_CapFault_BitsLeft as usize];
+        match buffer.msg[seL4_CapFault_LookupFailureType as usize] as u32 {
+            seL4_LookupFailureType_seL4_NoFailure => LookupFailure::None,
+            seL4_LookupFailureType_seL4_InvalidRoot => LookupFailure::InvalidRoot,
+            seL4_LookupFailureType_seL4_MissingCapability => {
+                LookupFailure::MissingCapability { bits_left }
+            }
+            seL4_LookupFailureType_seL4_DepthMismatch => LookupFailure::DepthMismatch {
+                bits_left,
+                bits_found: buffer.msg[seL4_CapFault_DepthMismatch_BitsFound as usize],
+            },
+            seL4_LookupFailureType_seL4_GuardMismatch => LookupFailure::GuardMismatch {
+                bits_left,
+                guard_found: buffer.msg[seL4_CapFault_GuardMismatch_GuardFound as usize],
+                guard_bits: buffer.msg[seL4_CapFault_GuardMismatch_BitsFound as usize],
+            },
+            other => LookupFailure::Unknown(other as usize),
+        }
+    }
+}
+
+impl fmt::Display for LookupFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LookupFailure::None => write!(f, "no lookup failure"),
+            LookupFailure::InvalidRoot => write!(f, "invalid CSpace root"),
+            LookupFailure::MissingCapability { bits_left } => {
+                write!(f, "empty slot, {} bits left to resolve", bits_left)
+            }
+            LookupFailure::DepthMismatch {
+                bits_left,
+                bits_found,
+            } => write!(
+                f,
+                "depth mismatch, {} bits left to resolve, {} found",
+                bits_left, bits_found
+            ),
+            LookupFailure::GuardMismatch {
+                bits_left,
+                guard_found,
+                guard_bits,
+            } => write!(
+                f,
+                "guard mismatch, {} bits left to resolve, guard {:#x} of {} bits found",
+                bits_left, guard_found, guard_bits
+            ),
+            LookupFailure::Unknown(kind) => write!(f, "lookup failure type {}", kind),
+        }
+    }
+}
+
 pub fn fault_or_message_channel<Msg: Sized, HandlerRole: CNodeRole>(
     local_cnode: &LocalCap<LocalCNode>,
     untyped: LocalCap<Untyped<<Endpoint as DirectRetype>::SizeBits>>,
@@ -175,7 +323,7 @@ pub fn fault_or_message_channel<Msg: Sized, HandlerRole: CNodeRole>(
 }
 
 pub struct FaultOrMessageHandler<Msg: Sized, Role: CNodeRole> {
-    endpoint: Cap<Endpoint, Role>,
+    pub(crate) endpoint: Cap<Endpoint, Role>,
     _msg: PhantomData<Msg>,
 }
 
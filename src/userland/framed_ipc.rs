@@ -0,0 +1,256 @@
+//! Call channels for messages too big for the IPC buffer.
+//!
+//! A `FramedCaller` and its `FramedResponder` share a page of memory as
+//! well as an endpoint. A request or response that fits in the IPC buffer
+//! goes through it as with `Caller` and `Responder`; one that doesn't is
+//! copied into the shared page instead, with the IPC message carrying
+//! only its length. Which way a message goes depends on nothing but the
+//! size of its type, so both ends agree on it without having to say, and
+//! message types like a storage driver's values don't have to be trimmed
+//! to fit the IPC buffer.
+//!
+//! Messages are copied byte for byte either way, so they mustn't hold
+//! pointers into either process' memory.
+//!
+//! ```ignore
+//! let (caller, responder) = framed_call_channel::<Request, Response, _, _>(
+//!     ut, ut, &root_cnode, slots, &mut client_vspace, &mut service_vspace, caller_slot,
+//!     responder_slot,
+//! )?;
+//! ```
+use core::marker::PhantomData;
+
+use selfe_sys::*;
+use typenum::*;
+
+use crate::arch::{self, PageBits, PageBytes};
+use crate::cap::{
+    role, CNodeRole, CNodeSlot, Cap, DirectRetype, Endpoint, LocalCNode, LocalCNodeSlots, LocalCap,
+    Untyped,
+};
+use crate::userland::correlation::{current_correlation_id, with_correlation_id, CorrelationId};
+use crate::userland::ipc::{type_length_in_words, IPCBuffer, MessageInfo};
+use crate::userland::{CapRights, IPCError};
+use crate::vspace::{UnmappedMemoryRegion, VSpace};
+
+/// A call channel whose messages are spilled into a shared page when they
+/// don't fit the IPC buffer. The page is the caller's alone, so a channel
+/// only ever has the one caller.
+pub fn framed_call_channel<
+    Req: Send + Sync,
+    Rsp: Send + Sync,
+    CallerRole: CNodeRole,
+    ResponderRole: CNodeRole,
+>(
+    endpoint_ut: LocalCap<Untyped<<Endpoint as DirectRetype>::SizeBits>>,
+    spill_page_ut: LocalCap<Untyped<PageBits>>,
+    local_cnode: &LocalCap<LocalCNode>,
+    local_slots: LocalCNodeSlots<U3>,
+    caller_vspace: &mut VSpace,
+    responder_vspace: &mut VSpace,
+    caller_slot: CNodeSlot<CallerRole>,
+    responder_slot: CNodeSlot<ResponderRole>,
+) -> Result<
+    (
+        FramedCaller<Req, Rsp, CallerRole>,
+        FramedResponder<Req, Rsp, ResponderRole>,
+    ),
+    IPCError,
+> {
+    // TODO - Move this to compile-time somehow
+    if core::mem::size_of::<Req>() > PageBytes::USIZE {
+        return Err(IPCError::RequestSizeTooBig);
+    }
+    if core::mem::size_of::<Rsp>() > PageBytes::USIZE {
+        return Err(IPCError::ResponseSizeTooBig);
+    }
+
+    let (slot, local_slots) = local_slots.alloc();
+    let local_endpoint: LocalCap<Endpoint> = endpoint_ut.retype(slot)?;
+    let caller_endpoint = local_endpoint.copy(local_cnode, caller_slot, CapRights::RWG)?;
+    let responder_endpoint = local_endpoint.copy(local_cnode, responder_slot, CapRights::RW)?;
+
+    let (slot, local_slots) = local_slots.alloc();
+    let shared_region = UnmappedMemoryRegion::new(spill_page_ut, slot)?.to_shared();
+    let (slot, _local_slots) = local_slots.alloc();
+    let caller_region = caller_vspace.map_shared_region(
+        &shared_region,
+        CapRights::RW,
+        arch::vm_attributes::DEFAULT | arch::vm_attributes::EXECUTE_NEVER,
+        slot,
+        local_cnode,
+    )?;
+    let responder_region = responder_vspace.map_shared_region_and_consume(
+        shared_region,
+        CapRights::RW,
+        arch::vm_attributes::DEFAULT | arch::vm_attributes::EXECUTE_NEVER,
+    )?;
+
+    Ok((
+        FramedCaller {
+            endpoint: caller_endpoint,
+            spill_page: SpillPage {
+                vaddr: caller_region.vaddr(),
+            },
+            _req: PhantomData,
+            _rsp: PhantomData,
+        },
+        FramedResponder {
+            endpoint: responder_endpoint,
+            spill_page: SpillPage {
+                vaddr: responder_region.vaddr(),
+            },
+            _req: PhantomData,
+            _rsp: PhantomData,
+        },
+    ))
+}
+
+/// Whether a `T` goes through the IPC buffer rather than the spill page
+pub fn fits_ipc_buffer<T>() -> bool {
+    core::mem::size_of::<T>() <= IPCBuffer::<(), ()>::max_size()
+}
+
+/// The page a channel's messages are spilled into, at its address in
+/// the process holding this end of the channel
+#[derive(Debug)]
+struct SpillPage {
+    vaddr: usize,
+}
+
+impl SpillPage {
+    /// Put `message` wherever its type goes, returning the length of the
+    /// IPC message to send for it, in words
+    unsafe fn write<T>(&self, message: &T) -> usize {
+        let mut ipc_buffer = IPCBuffer::<(), ()>::unchecked_new();
+        if fits_ipc_buffer::<T>() {
+            ipc_buffer.unchecked_copy_into_buffer(message);
+            type_length_in_words::<T>()
+        } else {
+            core::ptr::copy_nonoverlapping(message as *const T, self.vaddr as *mut T, 1);
+            ipc_buffer.unchecked_copy_into_buffer(&core::mem::size_of::<T>());
+            1
+        }
+    }
+
+    /// Take a message of type `T` from wherever its type goes, `None` if
+    /// the IPC message that announced it wasn't the right length for it
+    unsafe fn read<T>(&self, msg_info: &MessageInfo) -> Option<T> {
+        let ipc_buffer = IPCBuffer::<(), ()>::unchecked_new();
+        if fits_ipc_buffer::<T>() {
+            if msg_info.length_words() != type_length_in_words::<T>() {
+                return None;
+            }
+            Some(ipc_buffer.unchecked_copy_from_buffer())
+        } else {
+            if msg_info.length_words() != 1
+                || ipc_buffer.unchecked_copy_from_buffer::<usize>() != core::mem::size_of::<T>()
+            {
+                return None;
+            }
+            let mut message = core::mem::MaybeUninit::<T>::uninit();
+            core::ptr::copy_nonoverlapping(self.vaddr as *const T, message.as_mut_ptr(), 1);
+            Some(message.assume_init())
+        }
+    }
+}
+
+fn frame_message_info(label: usize, length_words: usize) -> seL4_MessageInfo_t {
+    unsafe {
+        seL4_MessageInfo_new(
+            arch::to_sel4_word(label),        // label,
+            0,                                // capsUnwrapped,
+            0,                                // extraCaps,
+            arch::to_sel4_word(length_words), // length in words!
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct FramedCaller<Req: Sized, Rsp: Sized, Role: CNodeRole> {
+    endpoint: Cap<Endpoint, Role>,
+    spill_page: SpillPage,
+    _req: PhantomData<Req>,
+    _rsp: PhantomData<Rsp>,
+}
+
+impl<Req, Rsp> FramedCaller<Req, Rsp, role::Local> {
+    pub fn blocking_call(&self, request: &Req) -> Result<Rsp, IPCError> {
+        let msg_info: MessageInfo = unsafe {
+            let length_words = self.spill_page.write(request);
+            seL4_Call(
+                self.endpoint.cptr,
+                frame_message_info(
+                    CorrelationId::into_label(current_correlation_id()),
+                    length_words,
+                ),
+            )
+        }
+        .into();
+        unsafe { self.spill_page.read(&msg_info) }.ok_or(IPCError::ResponseSizeMismatch)
+    }
+}
+
+#[derive(Debug)]
+pub struct FramedResponder<Req: Sized, Rsp: Sized, Role: CNodeRole> {
+    endpoint: Cap<Endpoint, Role>,
+    spill_page: SpillPage,
+    _req: PhantomData<Req>,
+    _rsp: PhantomData<Rsp>,
+}
+
+impl<Req, Rsp> FramedResponder<Req, Rsp, role::Local> {
+    pub fn reply_recv<F>(self, mut f: F) -> Result<Rsp, IPCError>
+    where
+        F: FnMut(Req) -> Rsp,
+    {
+        self.reply_recv_with_state((), move |req, state| (f(req), state))
+    }
+
+    pub fn reply_recv_with_state<F, State>(
+        self,
+        initial_state: State,
+        mut f: F,
+    ) -> Result<Rsp, IPCError>
+    where
+        F: FnMut(Req, State) -> (Rsp, State),
+    {
+        let mut sender_badge: usize = 0;
+        let mut msg_info: MessageInfo =
+            unsafe { seL4_Recv(self.endpoint.cptr, &mut sender_badge as *mut usize) }.into();
+        let mut state = initial_state;
+        loop {
+            let request = match unsafe { self.spill_page.read::<Req>(&msg_info) } {
+                Some(request) => request,
+                None => {
+                    // Not knowing what the message is, drop it, leaving
+                    // its caller blocked, as `Responder` does
+                    debug_println!(
+                        "Framed request of {} words does not match its expected size.",
+                        msg_info.length_words()
+                    );
+                    msg_info =
+                        unsafe { seL4_Recv(self.endpoint.cptr, &mut sender_badge as *mut usize) }
+                            .into();
+                    continue;
+                }
+            };
+
+            // The request was copied out of the spill page before the
+            // response goes into it
+            let correlation_id = CorrelationId::from_label(msg_info.label());
+            let (response, next_state) = with_correlation_id(correlation_id, || f(request, state));
+            state = next_state;
+
+            msg_info = unsafe {
+                let length_words = self.spill_page.write(&response);
+                seL4_ReplyRecv(
+                    self.endpoint.cptr,
+                    frame_message_info(CorrelationId::into_label(correlation_id), length_words),
+                    &mut sender_badge as *mut usize,
+                )
+            }
+            .into();
+        }
+    }
+}
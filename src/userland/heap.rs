@@ -0,0 +1,330 @@
+//! A heap for child processes, backed by a region of memory granted to
+//! them.
+//!
+//! A `ProcessHeap` implements `GlobalAlloc`, so a process that wants
+//! `alloc` collections declares one as its global allocator and hands it
+//! a mapped region once it starts running. Until then every allocation
+//! fails.
+//!
+//! ```ignore
+//! #![feature(alloc_error_handler)]
+//!
+//! #[global_allocator]
+//! static HEAP: ProcessHeap = ProcessHeap::empty();
+//!
+//! #[alloc_error_handler]
+//! fn heap_exhausted(layout: Layout) -> ! {
+//!     panic!("heap exhausted allocating {} bytes, {}", layout.size(), HEAP.stats())
+//! }
+//!
+//! impl RetypeForSetup for ProcParams<role::Local> { ... }
+//!
+//! pub extern "C" fn run(params: ProcParams<role::Local>) {
+//!     HEAP.init(params.heap_mem).expect("heap already initialized");
+//!     let mut connections = Vec::new();
+//!     ...
+//! }
+//! ```
+//!
+//! The allocator keeps an address-ordered list of free blocks in the free
+//! memory itself, allocates from the first block that fits and merges
+//! freed blocks with their free neighbours, so a process can allocate and
+//! free for as long as it runs without losing memory to anything but
+//! fragmentation. Block sizes and addresses are rounded to the size of a
+//! list entry, two words.
+//!
+//! What the heap's been up to is available from `ProcessHeap::stats`, and
+//! over IPC to whoever holds a `Caller<HeapStatsRequest, HeapStats, _>`
+//! with `ProcessHeap::serve_stats`.
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::UnsafeCell;
+use core::cmp;
+use core::fmt;
+use core::mem;
+use core::ops::Sub;
+use core::ptr;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use typenum::*;
+
+use crate::arch::PageBits;
+use crate::cap::role;
+use crate::pow::{Pow, _Pow};
+use crate::userland::{IPCError, Responder};
+use crate::vspace::{shared_status, MappedMemoryRegion};
+
+#[derive(Debug, PartialEq)]
+pub enum HeapError {
+    /// `ProcessHeap::init` was already called.
+    AlreadyInitialized,
+}
+
+/// A request for a heap's stats, see `ProcessHeap::serve_stats`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeapStatsRequest;
+
+/// What a `ProcessHeap` has been up to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct HeapStats {
+    pub size_bytes: usize,
+    /// Bytes handed out, including rounding
+    pub used_bytes: usize,
+    /// The most bytes handed out at once
+    pub peak_used_bytes: usize,
+    /// The largest single allocation the heap could make right now
+    pub largest_free_block_bytes: usize,
+    pub live_allocations: usize,
+    pub allocations: usize,
+    pub frees: usize,
+    /// Allocations that didn't fit anywhere in the heap
+    pub failed_allocations: usize,
+}
+
+impl fmt::Display for HeapStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} bytes used (peak {}, largest free block {}), {} live allocations ({} made, {} freed, {} failed)",
+            self.used_bytes,
+            self.size_bytes,
+            self.peak_used_bytes,
+            self.largest_free_block_bytes,
+            self.live_allocations,
+            self.allocations,
+            self.frees,
+            self.failed_allocations
+        )
+    }
+}
+
+/// An entry in the free list, at the start of the free block it describes
+struct FreeBlock {
+    size: usize,
+    next: *mut FreeBlock,
+}
+
+/// Every block's size and address is a multiple of this, so whatever's
+/// left of a block around an allocation has room for a list entry
+const BLOCK_GRANULE: usize = mem::size_of::<FreeBlock>();
+
+fn align_up(addr: usize, align: usize) -> usize {
+    (addr + align - 1) & !(align - 1)
+}
+
+/// The size of the block backing an allocation of `layout`
+fn block_size(layout: &Layout) -> usize {
+    cmp::max(align_up(layout.size(), BLOCK_GRANULE), BLOCK_GRANULE)
+}
+
+struct HeapState {
+    /// Free blocks in order of address
+    free: *mut FreeBlock,
+    size_bytes: usize,
+    used_bytes: usize,
+    peak_used_bytes: usize,
+    live_allocations: usize,
+    allocations: usize,
+    frees: usize,
+    failed_allocations: usize,
+}
+
+impl HeapState {
+    unsafe fn allocate(&mut self, layout: Layout) -> *mut u8 {
+        let size = block_size(&layout);
+        let align = cmp::max(layout.align(), BLOCK_GRANULE);
+        let mut link: *mut *mut FreeBlock = &mut self.free;
+        while !(*link).is_null() {
+            let block = *link;
+            let block_start = block as usize;
+            let block_end = block_start + (*block).size;
+            let start = align_up(block_start, align);
+            let end = match start.checked_add(size) {
+                Some(end) if end <= block_end => end,
+                _ => {
+                    link = &mut (*block).next;
+                    continue;
+                }
+            };
+
+            // Whatever's left either side of the allocation stays on the
+            // list, in the block's place
+            *link = (*block).next;
+            if end < block_end {
+                let tail = end as *mut FreeBlock;
+                tail.write(FreeBlock {
+                    size: block_end - end,
+                    next: *link,
+                });
+                *link = tail;
+            }
+            if start > block_start {
+                block.write(FreeBlock {
+                    size: start - block_start,
+                    next: *link,
+                });
+                *link = block;
+            }
+
+            self.used_bytes += size;
+            self.peak_used_bytes = cmp::max(self.peak_used_bytes, self.used_bytes);
+            self.live_allocations += 1;
+            self.allocations += 1;
+            return start as *mut u8;
+        }
+        self.failed_allocations += 1;
+        ptr::null_mut()
+    }
+
+    unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout) {
+        let size = block_size(&layout);
+        let start = ptr as usize;
+        let mut prev: *mut FreeBlock = ptr::null_mut();
+        let mut next = self.free;
+        while !next.is_null() && (next as usize) < start {
+            prev = next;
+            next = (*next).next;
+        }
+
+        let block = start as *mut FreeBlock;
+        block.write(FreeBlock { size, next });
+        if !next.is_null() && start + size == next as usize {
+            (*block).size += (*next).size;
+            (*block).next = (*next).next;
+        }
+        if prev.is_null() {
+            self.free = block;
+        } else if prev as usize + (*prev).size == start {
+            (*prev).size += (*block).size;
+            (*prev).next = (*block).next;
+        } else {
+            (*prev).next = block;
+        }
+
+        self.used_bytes -= size;
+        self.live_allocations -= 1;
+        self.frees += 1;
+    }
+
+    fn largest_free_block_bytes(&self) -> usize {
+        let mut largest = 0;
+        let mut block = self.free;
+        while !block.is_null() {
+            unsafe {
+                largest = cmp::max(largest, (*block).size);
+                block = (*block).next;
+            }
+        }
+        largest
+    }
+}
+
+/// A linked-list allocator over a mapped region, fit to be a process'
+/// `#[global_allocator]`
+pub struct ProcessHeap {
+    locked: AtomicBool,
+    state: UnsafeCell<HeapState>,
+}
+
+// The state is only touched with `locked` held.
+unsafe impl Sync for ProcessHeap {}
+
+impl ProcessHeap {
+    /// A heap with no memory, until it's given some with `init`
+    pub const fn empty() -> Self {
+        ProcessHeap {
+            locked: AtomicBool::new(false),
+            state: UnsafeCell::new(HeapState {
+                free: ptr::null_mut(),
+                size_bytes: 0,
+                used_bytes: 0,
+                peak_used_bytes: 0,
+                live_allocations: 0,
+                allocations: 0,
+                frees: 0,
+                failed_allocations: 0,
+            }),
+        }
+    }
+
+    fn with_state<R, F: FnOnce(&mut HeapState) -> R>(&self, f: F) -> R {
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            core::hint::spin_loop();
+        }
+        let r = f(unsafe { &mut *self.state.get() });
+        self.locked.store(false, Ordering::Release);
+        r
+    }
+
+    /// Allocate from `region`, which stays mapped for the rest of the
+    /// process' life.
+    ///
+    /// The heap can only be given memory once.
+    pub fn init<SizeBits: Unsigned>(
+        &self,
+        region: MappedMemoryRegion<SizeBits, shared_status::Exclusive>,
+    ) -> Result<(), HeapError>
+    where
+        SizeBits: IsGreaterOrEqual<PageBits>,
+        SizeBits: Sub<PageBits>,
+        <SizeBits as Sub<PageBits>>::Output: Unsigned,
+        <SizeBits as Sub<PageBits>>::Output: _Pow,
+        Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+    {
+        let start = region.vaddr();
+        let size_bytes = region.size_bytes();
+        self.with_state(|s| {
+            if s.size_bytes != 0 {
+                return Err(HeapError::AlreadyInitialized);
+            }
+            // A region is page aligned, and so aligned for the free list
+            let block = start as *mut FreeBlock;
+            unsafe {
+                block.write(FreeBlock {
+                    size: size_bytes,
+                    next: ptr::null_mut(),
+                })
+            };
+            s.free = block;
+            s.size_bytes = size_bytes;
+            Ok(())
+        })
+    }
+
+    pub fn stats(&self) -> HeapStats {
+        self.with_state(|s| HeapStats {
+            size_bytes: s.size_bytes,
+            used_bytes: s.used_bytes,
+            peak_used_bytes: s.peak_used_bytes,
+            largest_free_block_bytes: s.largest_free_block_bytes(),
+            live_allocations: s.live_allocations,
+            allocations: s.allocations,
+            frees: s.frees,
+            failed_allocations: s.failed_allocations,
+        })
+    }
+
+    /// Answer requests for the heap's stats on `responder`, forever.
+    ///
+    /// This blocks, so it's for a thread of its own; a process that's
+    /// already serving requests can answer with `stats` instead.
+    pub fn serve_stats(
+        &self,
+        responder: Responder<HeapStatsRequest, HeapStats, role::Local>,
+    ) -> Result<HeapStats, IPCError> {
+        responder.reply_recv(|_| self.stats())
+    }
+}
+
+unsafe impl GlobalAlloc for ProcessHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.with_state(|s| s.allocate(layout))
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.with_state(|s| s.deallocate(ptr, layout))
+    }
+}
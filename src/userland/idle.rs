@@ -0,0 +1,135 @@
+//! Approximate per-core CPU utilization, from time spent idling.
+//!
+//! An `IdleTracker` is run from a thread with nothing better to do,
+//! e.g. the root task once it has started everything. It yields for a
+//! window of time, and counts a yield that comes straight back as the core
+//! idling for that long; a yield that ran other threads takes longer, and
+//! counts as busy. Each window's utilization is published to a page of
+//! memory shared with whoever reports on it.
+//!
+//! The tracker only sees the core it runs on, so a single thread covers
+//! several cores by moving between them, sampling each in turn.
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use selfe_sys::seL4_Yield;
+
+use crate::arch::{self, PageBits};
+use crate::vspace::{shared_status, MappedMemoryRegion};
+
+/// Size of the shared region backing `CpuStats`
+pub type CpuStatsSizeBits = PageBits;
+
+/// The most cores `CpuStats` keeps track of
+pub const CPU_STATS_MAX_CORES: usize = 16;
+
+#[repr(C)]
+pub struct CoreStats {
+    /// Busy time within the last sampled window, in tenths of a percent
+    busy_permille: AtomicU32,
+    /// How many windows have been sampled, zero for a core that never was
+    samples: AtomicU32,
+}
+
+impl CoreStats {
+    /// Utilization of the last sampled window, in tenths of a percent
+    pub fn busy_permille(&self) -> u32 {
+        self.busy_permille.load(Ordering::Relaxed)
+    }
+
+    pub fn samples(&self) -> u32 {
+        self.samples.load(Ordering::Acquire)
+    }
+}
+
+/// The stats of each core by index, laid out at the start of the shared
+/// page
+#[repr(C)]
+pub struct CpuStats {
+    cores: [CoreStats; CPU_STATS_MAX_CORES],
+}
+
+impl CpuStats {
+    /// Use the stats in a mapping of the shared page.
+    ///
+    /// Retyped memory starts out zeroed, so every core reads as never
+    /// sampled until the tracker gets to it.
+    pub fn from_region(
+        region: MappedMemoryRegion<CpuStatsSizeBits, shared_status::Shared>,
+    ) -> &'static CpuStats {
+        unsafe { &*(region.vaddr() as *const CpuStats) }
+    }
+
+    pub fn core(&self, core: usize) -> Option<&CoreStats> {
+        self.cores.get(core)
+    }
+
+    /// The cores sampled at least once, with their index
+    pub fn sampled_cores(&self) -> impl Iterator<Item = (usize, &CoreStats)> {
+        self.cores
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.samples() != 0)
+    }
+}
+
+pub struct IdleTracker {
+    stats: &'static CpuStats,
+    /// The longest a yield can take and still count as idle
+    idle_yield_cycles: usize,
+}
+
+impl IdleTracker {
+    /// A tracker publishing to `stats`, the page mapped writable.
+    ///
+    /// `idle_yield_cycles` is the longest a yield with no other thread to
+    /// run takes, a little over the cost of the system call.
+    ///
+    /// # Safety
+    ///
+    /// The kernel must export the cycle counter to user level, see
+    /// `arch::enable_cycle_counter`.
+    pub unsafe fn new(stats: &'static CpuStats, idle_yield_cycles: usize) -> Self {
+        IdleTracker {
+            stats,
+            idle_yield_cycles,
+        }
+    }
+
+    /// Yield for at least `window_cycles`, then publish the utilization
+    /// seen as that of `core`, the one the calling thread is running on
+    pub fn sample(&mut self, core: usize, window_cycles: usize) {
+        let core_stats = match self.stats.cores.get(core) {
+            Some(core_stats) => core_stats,
+            None => return,
+        };
+
+        // The cycle counter is per-core, and this one may not have been
+        // started yet
+        let start = unsafe {
+            arch::enable_cycle_counter();
+            arch::cycle_count()
+        };
+        let mut last = start;
+        let mut idle_cycles: usize = 0;
+        let elapsed = loop {
+            unsafe { seL4_Yield() };
+            let now = unsafe { arch::cycle_count() };
+            let yield_cycles = now.wrapping_sub(last);
+            if yield_cycles <= self.idle_yield_cycles {
+                idle_cycles = idle_cycles.saturating_add(yield_cycles);
+            }
+            last = now;
+
+            let elapsed = now.wrapping_sub(start);
+            if elapsed >= window_cycles && elapsed != 0 {
+                break elapsed;
+            }
+        };
+
+        let idle_permille = (idle_cycles as u64 * 1000 / elapsed as u64) as u32;
+        core_stats
+            .busy_permille
+            .store(1000 - idle_permille, Ordering::Relaxed);
+        core_stats.samples.fetch_add(1, Ordering::Release);
+    }
+}
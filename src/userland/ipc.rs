@@ -1,18 +1,27 @@
 use core::marker::PhantomData;
+use core::ops::Sub;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use cross_queue::Slot;
+use generic_array::ArrayLength;
 
 use selfe_sys::*;
 
-use crate::arch;
+use crate::arch::{self, PageBits};
 use crate::cap::{
-    role, Badge, CNode, CNodeRole, CNodeSlot, Cap, DirectRetype, Endpoint, LocalCNode,
-    LocalCNodeSlot, LocalCNodeSlots, LocalCap, Notification, Untyped,
+    role, Badge, CNode, CNodeRole, CNodeSlot, CNodeSlots, Cap, ChildCNodeSlots, DirectRetype,
+    Endpoint, LocalCNode, LocalCNodeSlot, LocalCNodeSlots, LocalCap, Notification, Untyped,
 };
 use crate::error::SeL4Error;
-use crate::userland::multi_consumer::WakerSetup;
+use crate::pow::{Pow, _Pow};
+use crate::userland::correlation::{current_correlation_id, with_correlation_id, CorrelationId};
+use crate::userland::multi_consumer::{
+    Consumer1, ConsumerToken, MultiConsumerError, Producer, WakerSetup,
+};
 use crate::userland::shared_memory_ipc::WAKER_BADGE;
 use crate::userland::CapRights;
-use crate::vspace::VSpaceError;
-use typenum::U2;
+use crate::vspace::{KernelRetypeFanOutLimit, NumPages, ScratchRegion, VSpace, VSpaceError};
+use typenum::*;
 
 #[derive(Debug)]
 pub enum IPCError {
@@ -20,6 +29,8 @@ pub enum IPCError {
     ResponseSizeTooBig,
     ResponseSizeMismatch,
     RequestSizeMismatch,
+    /// A message that should have come with a capability didn't
+    CapNotTransferred,
     SeL4Error(SeL4Error),
     VSpaceError(VSpaceError),
 }
@@ -116,6 +127,117 @@ pub fn call_channel_with_waker<Req: Send + Sync, Rsp: Send + Sync, ResponderRole
     ))
 }
 
+#[derive(Debug)]
+pub enum DuplexChannelError {
+    IPCError(IPCError),
+    MultiConsumerError(MultiConsumerError),
+}
+
+impl From<IPCError> for DuplexChannelError {
+    fn from(e: IPCError) -> Self {
+        DuplexChannelError::IPCError(e)
+    }
+}
+
+impl From<MultiConsumerError> for DuplexChannelError {
+    fn from(e: MultiConsumerError) -> Self {
+        DuplexChannelError::MultiConsumerError(e)
+    }
+}
+
+/// Both directions between a client process and a service: the client
+/// calls the service, and the service sends the client events of its own
+/// accord on a queue.
+pub struct DuplexChannel<Req, Rsp, Evt: Sized + Send + Sync, ServiceRole: CNodeRole> {
+    pub caller: Caller<Req, Rsp, role::Child>,
+    pub responder: Responder<Req, Rsp, ServiceRole>,
+    pub producer: Producer<ServiceRole, Evt>,
+    pub consumer: Consumer1<role::Child, Evt>,
+    /// For adding more queues to the client's consumer
+    pub consumer_token: ConsumerToken,
+}
+
+/// Set up a `call_channel` from the client to the service, and an event
+/// queue of `ELen` `Evt`s back from the service to the client, the
+/// service producing and the client consuming.
+pub fn duplex_channel<
+    Req: Send + Sync,
+    Rsp: Send + Sync,
+    Evt: Sized + Send + Sync,
+    ELen: Unsigned,
+    EQueueSizeBits: Unsigned,
+    ScratchPages: Unsigned,
+    ServiceRole: CNodeRole,
+>(
+    endpoint_ut: LocalCap<Untyped<<Endpoint as DirectRetype>::SizeBits>>,
+    notification_ut: LocalCap<Untyped<<Notification as DirectRetype>::SizeBits>>,
+    queue_ut: LocalCap<Untyped<EQueueSizeBits>>,
+    local_vspace_scratch: &mut ScratchRegion<ScratchPages>,
+    client_vspace: &mut VSpace,
+    service_vspace: &mut VSpace,
+    local_cnode: &LocalCap<LocalCNode>,
+    local_slots: LocalCNodeSlots<U2>,
+    umr_slots: LocalCNodeSlots<NumPages<EQueueSizeBits>>,
+    shared_slots: LocalCNodeSlots<NumPages<EQueueSizeBits>>,
+    producer_slots: LocalCNodeSlots<NumPages<EQueueSizeBits>>,
+    client_slots: ChildCNodeSlots<U2>,
+    service_slots: CNodeSlots<U2, ServiceRole>,
+) -> Result<DuplexChannel<Req, Rsp, Evt, ServiceRole>, DuplexChannelError>
+where
+    ELen: ArrayLength<Slot<Evt>>,
+    ELen: IsGreater<U0, Output = True>,
+    ScratchPages: IsGreaterOrEqual<NumPages<EQueueSizeBits>, Output = True>,
+
+    // needed for memoryregion
+    EQueueSizeBits: IsGreaterOrEqual<PageBits>,
+    EQueueSizeBits: Sub<PageBits>,
+    <EQueueSizeBits as Sub<PageBits>>::Output: Unsigned,
+    <EQueueSizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<EQueueSizeBits as Sub<PageBits>>::Output>: Unsigned + IsGreaterOrEqual<U1, Output = True>,
+
+    // needed for unmappedMemoryRegion constructor
+    Pow<<EQueueSizeBits as Sub<PageBits>>::Output>:
+        IsLessOrEqual<KernelRetypeFanOutLimit, Output = True>,
+{
+    let (local_slot, local_slots) = local_slots.alloc();
+    let (client_slot, client_slots) = client_slots.alloc();
+    let (service_slot, service_slots) = service_slots.alloc();
+    let (ipc_setup, responder) = call_channel(endpoint_ut, local_cnode, local_slot, service_slot)?;
+    let caller = ipc_setup.create_caller(client_slot)?;
+
+    let (local_slot, _local_slots) = local_slots.alloc();
+    let (client_slot, _client_slots) = client_slots.alloc();
+    let (consumer, consumer_token, producer_setup, _waker_setup) =
+        Consumer1::new::<ELen, EQueueSizeBits, ScratchPages>(
+            notification_ut,
+            queue_ut,
+            local_vspace_scratch,
+            client_vspace,
+            local_cnode,
+            umr_slots,
+            shared_slots,
+            local_slot,
+            client_slot,
+        )?;
+
+    let (service_slot, _service_slots) = service_slots.alloc();
+    let producer = Producer::new(
+        &producer_setup,
+        service_slot,
+        service_vspace,
+        local_cnode,
+        producer_slots,
+    )?;
+
+    Ok(DuplexChannel {
+        caller,
+        responder,
+        producer,
+        consumer,
+        consumer_token,
+    })
+}
+
 impl<'a, Req, Rsp> IpcSetup<'a, Req, Rsp> {
     pub fn create_caller<Role: CNodeRole>(
         &self,
@@ -135,7 +257,7 @@ impl<'a, Req, Rsp> IpcSetup<'a, Req, Rsp> {
 
 #[derive(Debug)]
 pub struct Caller<Req: Sized, Rsp: Sized, Role: CNodeRole> {
-    endpoint: Cap<Endpoint, Role>,
+    pub(crate) endpoint: Cap<Endpoint, Role>,
     _req: PhantomData<Req>,
     _rsp: PhantomData<Rsp>,
 }
@@ -190,14 +312,14 @@ impl<'a, Req: Sized, Rsp: Sized> IPCBuffer<'a, Req, Rsp> {
         }
     }
 
-    unsafe fn unchecked_copy_into_buffer<T: Sized>(&mut self, data: &T) {
+    pub(crate) unsafe fn unchecked_copy_into_buffer<T: Sized>(&mut self, data: &T) {
         core::ptr::copy(
             data as *const T,
             &self.buffer.msg as *const [usize] as *const T as *mut T,
             1,
         );
     }
-    unsafe fn unchecked_copy_from_buffer<T: Sized>(&self) -> T {
+    pub(crate) unsafe fn unchecked_copy_from_buffer<T: Sized>(&self) -> T {
         let mut data = core::mem::zeroed();
         core::ptr::copy_nonoverlapping(
             &self.buffer.msg as *const [usize] as *const T,
@@ -224,7 +346,7 @@ impl<'a, Req: Sized, Rsp: Sized> IPCBuffer<'a, Req, Rsp> {
 }
 
 #[inline]
-fn unchecked_raw_ipc_buffer<'a>() -> &'a mut seL4_IPCBuffer {
+pub(crate) fn unchecked_raw_ipc_buffer<'a>() -> &'a mut seL4_IPCBuffer {
     unsafe { &mut *seL4_GetIPCBuffer() }
 }
 
@@ -247,9 +369,19 @@ pub(crate) fn type_length_in_words<T>() -> usize {
 }
 
 fn type_length_message_info<T>() -> seL4_MessageInfo_t {
+    labeled_type_length_message_info::<T>(0)
+}
+
+/// Message info labeled with the current correlation id, for the
+/// Caller/Responder pair to carry it across the call
+fn correlated_type_length_message_info<T>() -> seL4_MessageInfo_t {
+    labeled_type_length_message_info::<T>(CorrelationId::into_label(current_correlation_id()))
+}
+
+pub(crate) fn labeled_type_length_message_info<T>(label: usize) -> seL4_MessageInfo_t {
     unsafe {
         seL4_MessageInfo_new(
-            0,                                               // label,
+            arch::to_sel4_word(label),                       // label,
             0,                                               // capsUnwrapped,
             0,                                               // extraCaps,
             arch::to_sel4_word(type_length_in_words::<T>()), // length in words!
@@ -273,7 +405,7 @@ impl MessageInfo {
     /// Length of the message in words, ought to be
     /// less than the length of the IPC Buffer's msg array,
     /// an array of `usize` words.
-    pub(crate) fn length_words(&self) -> usize {
+    pub fn length_words(&self) -> usize {
         unsafe {
             seL4_MessageInfo_ptr_get_length(
                 &self.inner as *const seL4_MessageInfo_t as *mut seL4_MessageInfo_t,
@@ -281,6 +413,25 @@ impl MessageInfo {
         }
     }
 
+    /// How many capabilities came with the message
+    pub fn extra_caps(&self) -> usize {
+        unsafe {
+            seL4_MessageInfo_ptr_get_extraCaps(
+                &self.inner as *const seL4_MessageInfo_t as *mut seL4_MessageInfo_t,
+            ) as usize
+        }
+    }
+
+    /// Which of the capabilities that came with the message were
+    /// unwrapped to their badge instead of transferred, as a bitmask
+    pub fn caps_unwrapped(&self) -> usize {
+        unsafe {
+            seL4_MessageInfo_ptr_get_capsUnwrapped(
+                &self.inner as *const seL4_MessageInfo_t as *mut seL4_MessageInfo_t,
+            ) as usize
+        }
+    }
+
     /// Does this message info have the label tag
     /// that indicates that no fault has occurred?
     pub(crate) fn has_null_fault_label(&self) -> bool {
@@ -318,7 +469,10 @@ impl<Req, Rsp> Caller<Req, Rsp, role::Local> {
         let mut ipc_buffer = unsafe { IPCBuffer::unchecked_new() };
         let msg_info: MessageInfo = unsafe {
             ipc_buffer.copy_req_into_buffer(request);
-            seL4_Call(self.endpoint.cptr, type_length_message_info::<Req>())
+            seL4_Call(
+                self.endpoint.cptr,
+                correlated_type_length_message_info::<Req>(),
+            )
         }
         .into();
         if msg_info.length_words() != type_length_in_words::<Rsp>() {
@@ -330,7 +484,7 @@ impl<Req, Rsp> Caller<Req, Rsp, role::Local> {
 
 #[derive(Debug)]
 pub struct Responder<Req: Sized, Rsp: Sized, Role: CNodeRole> {
-    endpoint: Cap<Endpoint, Role>,
+    pub(crate) endpoint: Cap<Endpoint, Role>,
     _req: PhantomData<Req>,
     _rsp: PhantomData<Rsp>,
     _role: PhantomData<Role>,
@@ -408,7 +562,11 @@ impl<Req, Rsp> Responder<Req, Rsp, role::Local> {
                 msg_info.length_words(), request_length_in_words);
                     continue;
                 }
-                let out = f(ipc_buffer.copy_req_from_buffer(), state);
+                // Handle the request, and reply, as part of the caller's action
+                let correlation_id = CorrelationId::from_label(msg_info.label());
+                let out = with_correlation_id(correlation_id, || {
+                    f(ipc_buffer.copy_req_from_buffer(), state)
+                });
                 response = out.0;
                 state = out.1;
 
@@ -416,7 +574,9 @@ impl<Req, Rsp> Responder<Req, Rsp, role::Local> {
                 msg_info = unsafe {
                     seL4_ReplyRecv(
                         self.endpoint.cptr,
-                        type_length_message_info::<Rsp>(),
+                        labeled_type_length_message_info::<Rsp>(CorrelationId::into_label(
+                            correlation_id,
+                        )),
                         &mut sender_badge as *mut usize,
                     )
                 }
@@ -460,11 +620,14 @@ impl<Req, Rsp> Responder<Req, Rsp, role::Local> {
             return Err(IPCError::RequestSizeMismatch);
         }
 
-        let response = f(ipc_buffer.copy_req_from_buffer());
+        let correlation_id = CorrelationId::from_label(msg_info.label());
+        let response = with_correlation_id(correlation_id, || f(ipc_buffer.copy_req_from_buffer()));
         ipc_buffer.copy_rsp_into_buffer(&response);
 
         unsafe {
-            seL4_Reply(type_length_message_info::<Rsp>());
+            seL4_Reply(labeled_type_length_message_info::<Rsp>(
+                CorrelationId::into_label(correlation_id),
+            ));
         }
 
         Ok(())
@@ -502,3 +665,402 @@ impl<Msg: Sized, Role: CNodeRole> Sender<Msg, Role> {
         })
     }
 }
+
+/// The badge a `TimeoutSource` signals a timeout caller's bound
+/// notification with
+pub const TIMEOUT_BADGE: usize = 1;
+
+#[derive(Debug)]
+pub enum CallError {
+    /// The timeout source ticked as many times as the call allowed
+    /// without a response arriving
+    TimedOut,
+    IPCError(IPCError),
+}
+
+impl From<IPCError> for CallError {
+    fn from(e: IPCError) -> Self {
+        CallError::IPCError(e)
+    }
+}
+
+pub struct TimeoutCallSetup<'a, Req, Rsp> {
+    request_endpoint: LocalCap<Endpoint>,
+    response_endpoint: LocalCap<Endpoint>,
+    notification: LocalCap<Notification>,
+    local_cnode: &'a LocalCap<LocalCNode>,
+    _req: PhantomData<Req>,
+    _rsp: PhantomData<Rsp>,
+}
+
+/// A call channel whose caller can give up on a response, for calling a
+/// responder that might die or wedge.
+///
+/// `seL4_Call` can't be interrupted while it waits for the reply, so the
+/// request and the response go over an endpoint each instead. The caller
+/// waits for its response on a notification bound to its thread as well,
+/// which a timer driver ticks through a `TimeoutSource`; a call gives up
+/// once it's seen as many ticks as it was allowed. Requests and responses
+/// carry a sequence number so that a response arriving after its call
+/// timed out isn't taken for the next call's.
+///
+/// The caller can also send a request and get on with other work, picking
+/// up the response once it's arrived, see `TimeoutCaller::send_nonblocking`.
+///
+/// Bind `TimeoutCallSetup::notification` to the caller's thread, e.g.
+/// with `StandardProcess::bind_notification`, before it makes any calls.
+pub fn call_channel_with_timeout<
+    'a,
+    Req: Send + Sync,
+    Rsp: Send + Sync,
+    ResponderRole: CNodeRole,
+>(
+    request_ut: LocalCap<Untyped<<Endpoint as DirectRetype>::SizeBits>>,
+    response_ut: LocalCap<Untyped<<Endpoint as DirectRetype>::SizeBits>>,
+    notification_ut: LocalCap<Untyped<<Notification as DirectRetype>::SizeBits>>,
+    local_cnode: &'a LocalCap<LocalCNode>,
+    local_slots: LocalCNodeSlots<U3>,
+    responder_slots: CNodeSlots<U2, ResponderRole>,
+) -> Result<
+    (
+        TimeoutCallSetup<'a, Req, Rsp>,
+        TimeoutResponder<Req, Rsp, ResponderRole>,
+    ),
+    IPCError,
+> {
+    // Check buffer fits Req and Rsp, each followed by a sequence number
+    if !fits_with_sequence::<Req>() {
+        return Err(IPCError::RequestSizeTooBig);
+    }
+    if !fits_with_sequence::<Rsp>() {
+        return Err(IPCError::ResponseSizeTooBig);
+    }
+
+    let (local_slot, local_slots) = local_slots.alloc::<U1>();
+    let request_endpoint: LocalCap<Endpoint> = request_ut.retype(local_slot)?;
+    let (local_slot, local_slots) = local_slots.alloc::<U1>();
+    let response_endpoint: LocalCap<Endpoint> = response_ut.retype(local_slot)?;
+    let (local_slot, _local_slots) = local_slots.alloc::<U1>();
+    let notification: LocalCap<Notification> = notification_ut.retype(local_slot)?;
+
+    let (responder_slot, responder_slots) = responder_slots.alloc::<U1>();
+    let responder_request_endpoint =
+        request_endpoint.copy(local_cnode, responder_slot, CapRights::R)?;
+    let (responder_slot, _responder_slots) = responder_slots.alloc::<U1>();
+    let responder_response_endpoint =
+        response_endpoint.copy(local_cnode, responder_slot, CapRights::W)?;
+
+    Ok((
+        TimeoutCallSetup {
+            request_endpoint,
+            response_endpoint,
+            notification,
+            local_cnode,
+            _req: PhantomData,
+            _rsp: PhantomData,
+        },
+        TimeoutResponder {
+            request_endpoint: responder_request_endpoint,
+            response_endpoint: responder_response_endpoint,
+            _req: PhantomData,
+            _rsp: PhantomData,
+            _role: PhantomData,
+        },
+    ))
+}
+
+impl<'a, Req, Rsp> TimeoutCallSetup<'a, Req, Rsp> {
+    /// The notification to bind to the caller's thread
+    pub fn notification(&self) -> &LocalCap<Notification> {
+        &self.notification
+    }
+
+    /// Mint a capability into `slot` for the timer driver to tick the
+    /// caller's timeouts with
+    pub fn create_timeout_source<Role: CNodeRole>(
+        &self,
+        slot: CNodeSlot<Role>,
+    ) -> Result<TimeoutSource<Role>, IPCError> {
+        let notification = self.notification.mint(
+            self.local_cnode,
+            slot,
+            CapRights::W,
+            Badge::from(TIMEOUT_BADGE),
+        )?;
+        Ok(TimeoutSource { notification })
+    }
+
+    /// Make the channel's caller. Only make the one, the responses to
+    /// every caller would come back on the same endpoint.
+    pub fn create_caller<Role: CNodeRole>(
+        &self,
+        caller_slots: CNodeSlots<U2, Role>,
+    ) -> Result<TimeoutCaller<Req, Rsp, Role>, IPCError> {
+        let (caller_slot, caller_slots) = caller_slots.alloc::<U1>();
+        let request_endpoint =
+            self.request_endpoint
+                .copy(self.local_cnode, caller_slot, CapRights::W)?;
+        let (caller_slot, _caller_slots) = caller_slots.alloc::<U1>();
+        let response_endpoint =
+            self.response_endpoint
+                .copy(self.local_cnode, caller_slot, CapRights::R)?;
+
+        Ok(TimeoutCaller {
+            request_endpoint,
+            response_endpoint,
+            sequence: AtomicUsize::new(0),
+            _req: PhantomData,
+            _rsp: PhantomData,
+        })
+    }
+}
+
+/// Ticks a `TimeoutCaller`'s timeouts, held by whatever drives the timer
+#[derive(Debug)]
+pub struct TimeoutSource<Role: CNodeRole> {
+    notification: Cap<Notification, Role>,
+}
+
+impl TimeoutSource<role::Local> {
+    pub fn tick(&self) {
+        self.notification.signal()
+    }
+}
+
+#[derive(Debug)]
+pub struct TimeoutCaller<Req: Sized, Rsp: Sized, Role: CNodeRole> {
+    request_endpoint: Cap<Endpoint, Role>,
+    response_endpoint: Cap<Endpoint, Role>,
+    sequence: AtomicUsize,
+    _req: PhantomData<Req>,
+    _rsp: PhantomData<Rsp>,
+}
+
+/// A request sent with `TimeoutCaller::send_nonblocking`, whose response
+/// is yet to be collected with `poll_response`
+#[derive(Debug)]
+#[must_use]
+pub struct PendingCall {
+    sequence: usize,
+    ticks: usize,
+    ticked: usize,
+}
+
+impl PendingCall {
+    /// How many times the timeout source has ticked while waiting
+    pub fn ticked(&self) -> usize {
+        self.ticked
+    }
+}
+
+impl<Req, Rsp> TimeoutCaller<Req, Rsp, role::Local> {
+    /// Make a call, giving up with `CallError::TimedOut` once the timeout
+    /// source has ticked `ticks` times, at least once, without a response.
+    ///
+    /// The request is only delivered if the responder is already waiting
+    /// for one. A responder that's died, or is still busy, e.g. with a
+    /// call that already timed out, leaves the call to time out.
+    pub fn call_with_timeout(&self, request: &Req, ticks: usize) -> Result<Rsp, CallError> {
+        let mut pending = self.send_nonblocking(request, ticks);
+        loop {
+            let mut sender_badge: usize = 0;
+            let msg_info: MessageInfo =
+                unsafe { seL4_Recv(self.response_endpoint.cptr, &mut sender_badge as *mut usize) }
+                    .into();
+            if let Some(response) = self.take_response(&mut pending, msg_info, sender_badge)? {
+                return Ok(response);
+            }
+        }
+    }
+
+    /// Send a request without waiting for its response, for a process'
+    /// main loop to carry on with other work and collect the response
+    /// with `poll_response` later on.
+    ///
+    /// There's one call outstanding at a time; sending another request
+    /// gives up on the last one. Delivery and timeouts are as for
+    /// `call_with_timeout`.
+    pub fn send_nonblocking(&self, request: &Req, ticks: usize) -> PendingCall {
+        let request_length_in_words = type_length_in_words::<Req>();
+        self.discard_stale();
+
+        let sequence = self
+            .sequence
+            .fetch_add(1, Ordering::Relaxed)
+            .wrapping_add(1);
+        // Can safely use unchecked_new because we check sizing during the creation of
+        // TimeoutCaller
+        let mut ipc_buffer: IPCBuffer<Req, Rsp> = unsafe { IPCBuffer::unchecked_new() };
+        ipc_buffer.copy_req_into_buffer(request);
+        ipc_buffer.buffer.msg[request_length_in_words] = arch::to_sel4_word(sequence);
+        unsafe {
+            seL4_NBSend(
+                self.request_endpoint.cptr,
+                sequenced_message_info::<Req>(CorrelationId::into_label(current_correlation_id())),
+            )
+        };
+
+        PendingCall {
+            sequence,
+            ticks,
+            ticked: 0,
+        }
+    }
+
+    /// The response to `pending`, if it's arrived, without blocking.
+    pub fn poll_response(&self, pending: &mut PendingCall) -> Result<Option<Rsp>, CallError> {
+        loop {
+            let mut sender_badge: usize = 0;
+            let msg_info: MessageInfo = unsafe {
+                seL4_NBRecv(self.response_endpoint.cptr, &mut sender_badge as *mut usize)
+            }
+            .into();
+            if is_nothing_received(&msg_info, sender_badge) {
+                return Ok(None);
+            }
+            if let Some(response) = self.take_response(pending, msg_info, sender_badge)? {
+                return Ok(Some(response));
+            }
+        }
+    }
+
+    /// Make sense of a message received while waiting on `pending`: a
+    /// tick, a response to it, or a late response to an earlier call
+    fn take_response(
+        &self,
+        pending: &mut PendingCall,
+        msg_info: MessageInfo,
+        sender_badge: usize,
+    ) -> Result<Option<Rsp>, CallError> {
+        let response_length_in_words = type_length_in_words::<Rsp>();
+        // nonzero badges are from the bound notification
+        if sender_badge != 0 {
+            if sender_badge & TIMEOUT_BADGE != 0 {
+                pending.ticked += 1;
+                if pending.ticked >= pending.ticks {
+                    return Err(CallError::TimedOut);
+                }
+            }
+            return Ok(None);
+        }
+        if msg_info.length_words() != response_length_in_words + 1 {
+            return Err(IPCError::ResponseSizeMismatch.into());
+        }
+        // Can safely use unchecked_new because we check sizing during the creation of
+        // TimeoutCaller
+        let mut ipc_buffer: IPCBuffer<Req, Rsp> = unsafe { IPCBuffer::unchecked_new() };
+        // A late response to a call that already timed out
+        if ipc_buffer.buffer.msg[response_length_in_words] as usize != pending.sequence {
+            return Ok(None);
+        }
+        Ok(Some(ipc_buffer.copy_rsp_from_buffer()))
+    }
+
+    /// Take whatever's left over from calls that timed out, late
+    /// responses and ticks, without blocking. A responder blocked sending
+    /// a late response is freed up for the next request.
+    fn discard_stale(&self) {
+        loop {
+            let mut sender_badge: usize = 0;
+            let msg_info: MessageInfo = unsafe {
+                seL4_NBRecv(self.response_endpoint.cptr, &mut sender_badge as *mut usize)
+            }
+            .into();
+            if is_nothing_received(&msg_info, sender_badge) {
+                return;
+            }
+        }
+    }
+}
+
+/// Nothing to receive comes back as an empty message from badge 0; every
+/// response on a timeout call channel has at least its sequence number
+fn is_nothing_received(msg_info: &MessageInfo, sender_badge: usize) -> bool {
+    sender_badge == 0 && msg_info.length_words() == 0
+}
+
+#[derive(Debug)]
+pub struct TimeoutResponder<Req: Sized, Rsp: Sized, Role: CNodeRole> {
+    request_endpoint: Cap<Endpoint, Role>,
+    response_endpoint: Cap<Endpoint, Role>,
+    _req: PhantomData<Req>,
+    _rsp: PhantomData<Rsp>,
+    _role: PhantomData<Role>,
+}
+
+impl<Req, Rsp> TimeoutResponder<Req, Rsp, role::Local> {
+    pub fn reply_recv<F>(self, mut f: F) -> Result<Rsp, IPCError>
+    where
+        F: FnMut(Req) -> Rsp,
+    {
+        self.reply_recv_with_state((), move |req, state| (f(req), state))
+    }
+
+    pub fn reply_recv_with_state<F, State>(
+        self,
+        initial_state: State,
+        mut f: F,
+    ) -> Result<Rsp, IPCError>
+    where
+        F: FnMut(Req, State) -> (Rsp, State),
+    {
+        // Can safely use unchecked_new because we check sizing during the creation of
+        // TimeoutResponder
+        let mut ipc_buffer = unsafe { IPCBuffer::unchecked_new() };
+        let request_length_in_words = type_length_in_words::<Req>();
+        let response_length_in_words = type_length_in_words::<Rsp>();
+        let mut state = initial_state;
+        loop {
+            let mut sender_badge: usize = 0;
+            let msg_info: MessageInfo =
+                unsafe { seL4_Recv(self.request_endpoint.cptr, &mut sender_badge as *mut usize) }
+                    .into();
+            // nonzero badges are from a notification bound to the responder,
+            // not requests
+            if sender_badge != 0 {
+                continue;
+            }
+            if msg_info.length_words() != request_length_in_words + 1 {
+                debug_println!("Request size incoming ({} words) does not match static size expectation ({} words).",
+                msg_info.length_words(), request_length_in_words + 1);
+                continue;
+            }
+            let sequence = ipc_buffer.buffer.msg[request_length_in_words];
+            let request = ipc_buffer.copy_req_from_buffer();
+
+            // Handle the request, and respond, as part of the caller's action
+            let correlation_id = CorrelationId::from_label(msg_info.label());
+            let out = with_correlation_id(correlation_id, || f(request, state));
+            state = out.1;
+
+            ipc_buffer.copy_rsp_into_buffer(&out.0);
+            ipc_buffer.buffer.msg[response_length_in_words] = sequence;
+            // Blocks until the caller takes the response, either waiting on
+            // the call or clearing it out at the start of its next one
+            unsafe {
+                seL4_Send(
+                    self.response_endpoint.cptr,
+                    sequenced_message_info::<Rsp>(CorrelationId::into_label(correlation_id)),
+                )
+            };
+        }
+    }
+}
+
+/// Whether `T` fits the IPC buffer with a sequence number after it
+fn fits_with_sequence<T>() -> bool {
+    (type_length_in_words::<T>() + 1) * core::mem::size_of::<usize>()
+        <= IPCBuffer::<(), ()>::max_size()
+}
+
+/// Message info for a `T` followed by a sequence number
+fn sequenced_message_info<T>(label: usize) -> seL4_MessageInfo_t {
+    unsafe {
+        seL4_MessageInfo_new(
+            arch::to_sel4_word(label),                           // label,
+            0,                                                   // capsUnwrapped,
+            0,                                                   // extraCaps,
+            arch::to_sel4_word(type_length_in_words::<T>() + 1), // length in words!
+        )
+    }
+}
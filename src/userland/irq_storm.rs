@@ -0,0 +1,151 @@
+//! Throttling an interrupt that fires faster than its consumer expects.
+//!
+//! A device stuck asserting its line wakes its consumer for as long as it
+//! stays stuck, starving everything below the consumer's priority. Given a
+//! `StormGuard`, an `InterruptConsumer` counts its wakeups with the cycle
+//! counter, and once they exceed the guard's limit within its window,
+//! leaves the interrupt unacked, and so masked, for the backoff before
+//! acking it again. Each storm is reported through the guard's reporting
+//! function, e.g. to the process' logger.
+//!
+//! The cycle counter wraps, every few seconds on a 32-bit counter, so the
+//! window and backoff have to be shorter than that.
+//!
+//! ```ignore
+//! let guard = unsafe { StormGuard::new(UART_STORM_LIMIT, report_irq_storm) };
+//! irq_consumer.with_storm_guard(guard).consume(state, |state| ...);
+//! ```
+use core::cell::Cell;
+use core::fmt;
+
+use crate::arch;
+
+/// How many wakeups an interrupt is allowed, and how long it's masked
+/// for when it goes over
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StormLimit {
+    /// The most wakeups allowed within a window
+    pub max_wakeups: usize,
+    pub window_cycles: usize,
+    /// How long the interrupt stays masked once it's over the limit
+    pub backoff_cycles: usize,
+}
+
+/// An interrupt found going over its `StormLimit`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IrqStorm {
+    pub irq: u16,
+    /// Wakeups in the window so far, this one included
+    pub wakeups: usize,
+    pub limit: StormLimit,
+    /// How many storms the interrupt has had, this one included
+    pub storms: usize,
+}
+
+impl fmt::Display for IrqStorm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "IRQ {} woke its consumer {} times within {} cycles, over its limit of {}, masked for {} cycles ({} storms)",
+            self.irq,
+            self.wakeups,
+            self.limit.window_cycles,
+            self.limit.max_wakeups,
+            self.limit.backoff_cycles,
+            self.storms
+        )
+    }
+}
+
+pub struct StormGuard {
+    limit: StormLimit,
+    report: fn(&IrqStorm),
+    window_start: Cell<usize>,
+    window_wakeups: Cell<usize>,
+    storms: Cell<usize>,
+    /// When the interrupt was left masked, for as long as it is
+    masked_at: Cell<Option<usize>>,
+}
+
+impl StormGuard {
+    /// Start the cycle counter and make a guard holding an interrupt to
+    /// `limit`, reporting storms with `report`.
+    ///
+    /// # Safety
+    ///
+    /// The kernel must export the cycle counter to user level, see
+    /// `arch::enable_cycle_counter`.
+    pub unsafe fn new(limit: StormLimit, report: fn(&IrqStorm)) -> Self {
+        arch::enable_cycle_counter();
+        StormGuard {
+            limit,
+            report,
+            window_start: Cell::new(arch::cycle_count()),
+            window_wakeups: Cell::new(0),
+            storms: Cell::new(0),
+            masked_at: Cell::new(None),
+        }
+    }
+
+    pub fn limit(&self) -> StormLimit {
+        self.limit
+    }
+
+    pub fn storms(&self) -> usize {
+        self.storms.get()
+    }
+
+    /// Whether the interrupt is being left masked
+    pub fn is_masked(&self) -> bool {
+        self.masked_at.get().is_some()
+    }
+
+    /// Count a wakeup of interrupt `irq`, returning whether it's put the
+    /// interrupt over the limit, in which case it's to be left masked
+    pub(crate) fn record_wakeup(&self, irq: u16) -> bool {
+        let now = unsafe { arch::cycle_count() };
+        if now.wrapping_sub(self.window_start.get()) > self.limit.window_cycles {
+            self.window_start.set(now);
+            self.window_wakeups.set(0);
+        }
+        let wakeups = self.window_wakeups.get() + 1;
+        self.window_wakeups.set(wakeups);
+        if wakeups <= self.limit.max_wakeups {
+            return false;
+        }
+
+        self.storms.set(self.storms.get() + 1);
+        self.masked_at.set(Some(now));
+        (self.report)(&IrqStorm {
+            irq,
+            wakeups,
+            limit: self.limit,
+            storms: self.storms.get(),
+        });
+        true
+    }
+
+    /// Whether the interrupt is masked and has been for the backoff
+    pub(crate) fn backoff_elapsed(&self) -> bool {
+        match self.masked_at.get() {
+            Some(masked_at) => {
+                unsafe { arch::cycle_count() }.wrapping_sub(masked_at) >= self.limit.backoff_cycles
+            }
+            None => false,
+        }
+    }
+
+    /// Yield until the backoff is over
+    pub(crate) fn wait_out_backoff(&self) {
+        while self.is_masked() && !self.backoff_elapsed() {
+            unsafe { selfe_sys::seL4_Yield() };
+        }
+    }
+
+    /// The interrupt's been acked again, count its wakeups afresh
+    pub(crate) fn unmasked(&self) {
+        self.masked_at.set(None);
+        self.window_start.set(unsafe { arch::cycle_count() });
+        self.window_wakeups.set(0);
+    }
+}
@@ -0,0 +1,195 @@
+//! Temporary grants of a capability to a child, revoked by the supervisor
+//! once they expire.
+//!
+//! The supervisor keeps a copy of the capability it leases out, and the
+//! child's copy is derived from that one. Revoking the supervisor's copy
+//! deletes the child's along with it, wherever the child has put it, so a
+//! grant like one-shot access to a device can be taken back without the
+//! child's cooperation.
+//!
+//! Expiry is in whatever unit of time ("ticks") the supervisor keeps. The
+//! supervisor checks its leases with `Lease::tick`, which revokes any that
+//! are due and publishes the time remaining to a page of memory shared
+//! with the child, so the child can check how long it has left.
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use selfe_sys::{seL4_CNode_Revoke, seL4_WordBits};
+use typenum::U3;
+
+use crate::arch::{self, PageBits};
+use crate::cap::{
+    role, CNodeRole, CNodeSlot, Cap, CapType, CopyAliasable, LocalCNode, LocalCNodeSlots, LocalCap,
+    Untyped,
+};
+use crate::error::{ErrorExt, SeL4Error};
+use crate::userland::{CapRights, IPCError};
+use crate::vspace::{UnmappedMemoryRegion, VSpace};
+
+/// A lease's state, laid out at the start of the shared page
+#[repr(C)]
+struct LeaseStatus {
+    /// Ticks left as of the supervisor's last check
+    remaining: AtomicUsize,
+    revoked: AtomicBool,
+}
+
+/// Lease a copy of `cap` to a child for `duration` ticks from `now`.
+///
+/// The child's copy is placed in `child_slot`, and the lease's status page
+/// is mapped read-only into the child's vspace.
+pub fn lease<CT, ChildRole: CNodeRole>(
+    cap: &LocalCap<CT>,
+    local_cnode: &LocalCap<LocalCNode>,
+    local_slots: LocalCNodeSlots<U3>,
+    status_ut: LocalCap<Untyped<PageBits>>,
+    local_vspace: &mut VSpace,
+    child_vspace: &mut VSpace,
+    child_slot: CNodeSlot<ChildRole>,
+    rights: CapRights,
+    now: usize,
+    duration: usize,
+) -> Result<(Lease<CT>, LeasedCap<CT, ChildRole>), IPCError>
+where
+    CT: CapType + CopyAliasable<CopyOutput = CT>,
+{
+    let (slot, local_slots) = local_slots.alloc();
+    let region = UnmappedMemoryRegion::new(status_ut, slot)?;
+    let shared_region = region.to_shared();
+
+    let (slot, local_slots) = local_slots.alloc();
+    let local_region = local_vspace.map_shared_region(
+        &shared_region,
+        CapRights::RW,
+        arch::vm_attributes::DEFAULT,
+        slot,
+        local_cnode,
+    )?;
+    let child_region = child_vspace.map_shared_region_and_consume(
+        shared_region,
+        CapRights::R,
+        arch::vm_attributes::DEFAULT,
+    )?;
+
+    let status = unsafe { &*(local_region.vaddr() as *const LeaseStatus) };
+    status.remaining.store(duration, Ordering::Relaxed);
+    status.revoked.store(false, Ordering::Release);
+
+    let (slot, _local_slots) = local_slots.alloc();
+    let origin = cap.copy(local_cnode, slot, rights)?;
+    let child_cap = origin.copy(local_cnode, child_slot, rights)?;
+
+    Ok((
+        Lease {
+            origin,
+            status,
+            expires_at: now.saturating_add(duration),
+        },
+        LeasedCap {
+            cap: child_cap,
+            status_address: child_region.vaddr(),
+        },
+    ))
+}
+
+/// The supervisor's side of a lease
+pub struct Lease<CT: CapType> {
+    /// The copy the child's is derived from
+    origin: LocalCap<CT>,
+    status: &'static LeaseStatus,
+    expires_at: usize,
+}
+
+impl<CT: CapType> Lease<CT> {
+    pub fn is_revoked(&self) -> bool {
+        self.status.revoked.load(Ordering::Relaxed)
+    }
+
+    /// Ticks left until the lease expires, zero once it's revoked
+    pub fn remaining(&self, now: usize) -> usize {
+        if self.is_revoked() {
+            0
+        } else {
+            self.expires_at.saturating_sub(now)
+        }
+    }
+
+    /// Push the expiry `duration` ticks past `now`, unless the lease has
+    /// already been revoked
+    pub fn renew(&mut self, now: usize, duration: usize) {
+        if !self.is_revoked() {
+            self.expires_at = now.saturating_add(duration);
+            self.status.remaining.store(duration, Ordering::Relaxed);
+        }
+    }
+
+    /// Revoke the lease if it has expired, and otherwise publish the time
+    /// remaining to the child. Returns whether the lease is still valid.
+    pub fn tick(
+        &mut self,
+        local_cnode: &LocalCap<LocalCNode>,
+        now: usize,
+    ) -> Result<bool, SeL4Error> {
+        if self.is_revoked() {
+            return Ok(false);
+        }
+        let remaining = self.remaining(now);
+        if remaining == 0 {
+            self.revoke(local_cnode)?;
+            return Ok(false);
+        }
+        self.status.remaining.store(remaining, Ordering::Relaxed);
+        Ok(true)
+    }
+
+    /// Take the child's copy away now, whether or not the lease expired
+    pub fn revoke(&mut self, local_cnode: &LocalCap<LocalCNode>) -> Result<(), SeL4Error> {
+        if self.is_revoked() {
+            return Ok(());
+        }
+        unsafe {
+            seL4_CNode_Revoke(
+                local_cnode.cptr,    // _service
+                self.origin.cptr,    // index
+                seL4_WordBits as u8, // depth
+            )
+        }
+        .as_result()
+        .map_err(SeL4Error::CNodeRevoke)?;
+        self.status.remaining.store(0, Ordering::Relaxed);
+        self.status.revoked.store(true, Ordering::Release);
+        Ok(())
+    }
+}
+
+/// The child's side of a lease, the leased cap along with the status
+/// published by the supervisor
+pub struct LeasedCap<CT: CapType, Role: CNodeRole> {
+    cap: Cap<CT, Role>,
+    status_address: usize,
+}
+
+impl<CT: CapType> LeasedCap<CT, role::Local> {
+    fn status(&self) -> &LeaseStatus {
+        unsafe { &*(self.status_address as *const LeaseStatus) }
+    }
+
+    /// The leased cap, only usable until the lease is revoked
+    pub fn cap(&self) -> &LocalCap<CT> {
+        &self.cap
+    }
+
+    /// Ticks left as of the supervisor's last check, `None` once the lease
+    /// has been revoked
+    pub fn remaining(&self) -> Option<usize> {
+        let status = self.status();
+        if status.revoked.load(Ordering::Acquire) {
+            None
+        } else {
+            Some(status.remaining.load(Ordering::Relaxed))
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.remaining().is_some()
+    }
+}
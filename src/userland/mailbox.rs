@@ -0,0 +1,241 @@
+//! A single-word mailbox holding only the latest value sent.
+//!
+//! Where a queue would deliver every value in turn, a mailbox keeps just
+//! the newest one: the sender overwrites it and signals, and the receiver
+//! picks up whatever is current when it gets around to reading. This
+//! suits "latest sensor reading" or "current link state" data, for which a
+//! backlog of stale values is meaningless.
+//!
+//! Sending never blocks or fails, so it's safe from an interrupt handler.
+//! Each value is stored with a sequence number, the receiver uses it to
+//! tell a new value from one it's read already and to count the values
+//! overwritten before it got to them. The receiver acknowledges what it
+//! has read, and the sender can check whether its latest value has been
+//! seen.
+use core::marker::PhantomData;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use selfe_sys::{seL4_Signal, seL4_Wait};
+use typenum::{U1, U3};
+
+use crate::arch::{self, PageBits};
+use crate::cap::{
+    role, Badge, CNodeRole, CNodeSlots, Cap, DirectRetype, LocalCNode, LocalCNodeSlots, LocalCap,
+    Notification, Untyped,
+};
+use crate::userland::{CapRights, IPCError};
+use crate::vspace::{UnmappedMemoryRegion, VSpace};
+
+/// A value that fits in a machine word, and so can be sent through a
+/// mailbox in a single atomic store
+pub trait MailboxValue: Copy {
+    fn into_word(self) -> usize;
+    fn from_word(word: usize) -> Self;
+}
+
+macro_rules! impl_mailbox_value {
+    ($($t:ty),*) => {
+        $(
+            impl MailboxValue for $t {
+                fn into_word(self) -> usize {
+                    self as usize
+                }
+
+                fn from_word(word: usize) -> Self {
+                    word as $t
+                }
+            }
+        )*
+    };
+}
+
+impl_mailbox_value!(u8, u16, u32, usize, i8, i16, i32, isize);
+
+impl MailboxValue for bool {
+    fn into_word(self) -> usize {
+        self as usize
+    }
+
+    fn from_word(word: usize) -> Self {
+        word != 0
+    }
+}
+
+/// The mailbox itself, laid out at the start of the shared page.
+///
+/// `seq` is the number of values sent so far. The sender stores `value`
+/// before bumping `seq`, so a receiver that sees a new `seq` sees the value
+/// sent with it, or a newer one.
+#[repr(C)]
+struct MailboxState {
+    seq: AtomicUsize,
+    value: AtomicUsize,
+    acked: AtomicUsize,
+}
+
+/// A mailbox's value as read by the receiver
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reading<T> {
+    pub value: T,
+    /// How many values had been sent when this one was, this one included
+    pub sequence: usize,
+    /// How many values were overwritten since the previous reading
+    pub missed: usize,
+}
+
+/// Set up a mailbox between a sender and a receiver, with a page of
+/// memory shared between their address spaces and a notification for the
+/// receiver to wait on.
+pub fn mailbox<T: MailboxValue, SenderRole: CNodeRole, ReceiverRole: CNodeRole>(
+    local_cnode: &LocalCap<LocalCNode>,
+    local_slots: LocalCNodeSlots<U3>,
+    shared_region_ut: LocalCap<Untyped<PageBits>>,
+    notification_ut: LocalCap<Untyped<<Notification as DirectRetype>::SizeBits>>,
+    sender_vspace: &mut VSpace,
+    receiver_vspace: &mut VSpace,
+    sender_slots: CNodeSlots<U1, SenderRole>,
+    receiver_slots: CNodeSlots<U1, ReceiverRole>,
+) -> Result<
+    (
+        MailboxSender<T, SenderRole>,
+        MailboxReceiver<T, ReceiverRole>,
+    ),
+    IPCError,
+> {
+    let (slot, local_slots) = local_slots.alloc();
+    let region = UnmappedMemoryRegion::new(shared_region_ut, slot)?;
+    let shared_region = region.to_shared();
+
+    let (slot, local_slots) = local_slots.alloc();
+    let sender_region = sender_vspace.map_shared_region(
+        &shared_region,
+        CapRights::RW,
+        arch::vm_attributes::DEFAULT,
+        slot,
+        local_cnode,
+    )?;
+
+    // The receiver writes its acknowledgments to the page too
+    let receiver_region = receiver_vspace.map_shared_region_and_consume(
+        shared_region,
+        CapRights::RW,
+        arch::vm_attributes::DEFAULT,
+    )?;
+
+    let (slot, _local_slots) = local_slots.alloc();
+    let local_notification: LocalCap<Notification> = notification_ut.retype(slot)?;
+
+    let (sender_slot, _sender_slots) = sender_slots.alloc();
+    let sender_notification = local_notification.mint(
+        local_cnode,
+        sender_slot,
+        CapRights::RWG,
+        Badge::from(1 << 0),
+    )?;
+
+    let (receiver_slot, _receiver_slots) = receiver_slots.alloc();
+    let receiver_notification = local_notification.mint(
+        local_cnode,
+        receiver_slot,
+        CapRights::RWG,
+        Badge::from(1 << 1),
+    )?;
+
+    Ok((
+        MailboxSender {
+            notification: sender_notification,
+            shared_page_address: sender_region.vaddr(),
+            _value: PhantomData,
+        },
+        MailboxReceiver {
+            notification: receiver_notification,
+            shared_page_address: receiver_region.vaddr(),
+            last_seq: 0,
+            _value: PhantomData,
+        },
+    ))
+}
+
+pub struct MailboxSender<T: MailboxValue, Role: CNodeRole> {
+    notification: Cap<Notification, Role>,
+    shared_page_address: usize,
+    _value: PhantomData<T>,
+}
+
+impl<T: MailboxValue> MailboxSender<T, role::Local> {
+    fn shared(&self) -> &MailboxState {
+        unsafe { &*(self.shared_page_address as *const MailboxState) }
+    }
+
+    /// Overwrite the mailbox's value and signal the receiver, returning
+    /// the value's sequence number
+    pub fn send(&mut self, value: T) -> usize {
+        let shared = self.shared();
+        let seq = shared.seq.load(Ordering::Relaxed).wrapping_add(1);
+        shared.value.store(value.into_word(), Ordering::Relaxed);
+        shared.seq.store(seq, Ordering::Release);
+        unsafe { seL4_Signal(self.notification.cptr) };
+        seq
+    }
+
+    /// Whether the receiver has acknowledged the latest value sent
+    pub fn is_acknowledged(&self) -> bool {
+        let shared = self.shared();
+        shared.acked.load(Ordering::Acquire) == shared.seq.load(Ordering::Relaxed)
+    }
+}
+
+pub struct MailboxReceiver<T: MailboxValue, Role: CNodeRole> {
+    notification: Cap<Notification, Role>,
+    shared_page_address: usize,
+    /// The sequence number of the last value read
+    last_seq: usize,
+    _value: PhantomData<T>,
+}
+
+impl<T: MailboxValue> MailboxReceiver<T, role::Local> {
+    fn shared(&self) -> &MailboxState {
+        unsafe { &*(self.shared_page_address as *const MailboxState) }
+    }
+
+    /// Read the mailbox's value, if one was sent since the last reading.
+    ///
+    /// A send racing the read can leave the reading with the newer value
+    /// under the older sequence number, in which case the next reading
+    /// repeats the value under its own.
+    pub fn try_read(&mut self) -> Option<Reading<T>> {
+        let shared = self.shared();
+        let seq = shared.seq.load(Ordering::Acquire);
+        if seq == self.last_seq {
+            return None;
+        }
+        let word = shared.value.load(Ordering::Relaxed);
+        let reading = Reading {
+            value: T::from_word(word),
+            sequence: seq,
+            missed: seq.wrapping_sub(self.last_seq) - 1,
+        };
+        self.last_seq = seq;
+        Some(reading)
+    }
+
+    /// Wait for a value to be sent, unless there's one not read yet
+    pub fn wait(&mut self) -> Reading<T> {
+        let mut sender_badge: usize = 0;
+        loop {
+            if let Some(reading) = self.try_read() {
+                return reading;
+            }
+            unsafe {
+                seL4_Wait(self.notification.cptr, &mut sender_badge as *mut usize);
+            }
+        }
+    }
+
+    /// Let the sender know a reading has been handled
+    pub fn ack(&self, reading: &Reading<T>) {
+        self.shared()
+            .acked
+            .store(reading.sequence, Ordering::Release);
+    }
+}
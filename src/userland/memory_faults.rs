@@ -0,0 +1,83 @@
+//! A record of memory faults reported by drivers, for retiring bad memory
+//! on the next restart.
+//!
+//! Drivers that detect ECC or parity errors, e.g. from a memory
+//! controller's error interrupt, report the faulting physical address to
+//! a `MemoryFaultLog` in a page of memory shared with the supervisor. The
+//! supervisor persists the log however the platform allows, and on the
+//! next boot hands its `bad_memory` ranges to
+//! `bootstrap_allocators_with_quarantine` so the untypeds behind them are
+//! never allocated from again.
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use typenum::Unsigned;
+
+use crate::alloc::micro_alloc::PageAlignedAddressRange;
+use crate::arch::{PageBits, PageBytes};
+use crate::vspace::{shared_status, MappedMemoryRegion};
+
+/// Size of the shared region backing `MemoryFaultLog`
+pub type MemoryFaultLogSizeBits = PageBits;
+
+/// The most distinct pages a `MemoryFaultLog` keeps track of
+pub const MEMORY_FAULT_LOG_CAPACITY: usize = 128;
+
+/// Faulting pages by physical address, laid out at the start of the
+/// shared page
+#[repr(C)]
+pub struct MemoryFaultLog {
+    /// How many entries have been claimed, some may still be mid-write
+    claimed: AtomicUsize,
+    /// Page addresses with the low bit set, zero for an entry not written
+    /// yet
+    pages: [AtomicUsize; MEMORY_FAULT_LOG_CAPACITY],
+}
+
+impl MemoryFaultLog {
+    /// Use the log in a mapping of the shared page.
+    ///
+    /// Reporting drivers map the page writable. Retyped memory starts out
+    /// zeroed, so the log starts out empty.
+    pub fn from_region(
+        region: MappedMemoryRegion<MemoryFaultLogSizeBits, shared_status::Shared>,
+    ) -> &'static MemoryFaultLog {
+        unsafe { &*(region.vaddr() as *const MemoryFaultLog) }
+    }
+
+    /// Report a fault at `paddr`, which retires the whole page containing
+    /// it. Returns false if the log is full and the fault went unrecorded.
+    pub fn report(&self, paddr: usize) -> bool {
+        let page = paddr & !(PageBytes::USIZE - 1);
+        if self.contains(page) {
+            return true;
+        }
+        let index = self.claimed.fetch_add(1, Ordering::Relaxed);
+        if index >= MEMORY_FAULT_LOG_CAPACITY {
+            self.claimed
+                .store(MEMORY_FAULT_LOG_CAPACITY, Ordering::Relaxed);
+            return false;
+        }
+        self.pages[index].store(page | 1, Ordering::Release);
+        true
+    }
+
+    fn contains(&self, page: usize) -> bool {
+        self.faulted_pages().any(|p| p == page)
+    }
+
+    /// The address of every page reported so far
+    pub fn faulted_pages(&self) -> impl Iterator<Item = usize> + '_ {
+        self.pages
+            .iter()
+            .map(|p| p.load(Ordering::Acquire))
+            .filter(|p| *p != 0)
+            .map(|p| p & !1)
+    }
+
+    /// The pages reported so far, as ranges to quarantine at the next
+    /// bootstrap
+    pub fn bad_memory(&self) -> impl Iterator<Item = PageAlignedAddressRange> + '_ {
+        self.faulted_pages()
+            .filter_map(|page| PageAlignedAddressRange::new_by_size(page, PageBytes::USIZE).ok())
+    }
+}
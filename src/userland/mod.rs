@@ -1,15 +1,63 @@
+mod batch;
+mod build_metadata;
+mod cap_transfer;
+#[cfg(feature = "compact_messages")]
+mod compact;
+mod correlation;
+mod deadline;
 mod fault;
+mod framed_ipc;
+pub mod heap;
+mod idle;
 mod ipc;
 mod irq;
+mod irq_storm;
+mod lease;
+mod mailbox;
+mod memory_faults;
 mod multi_consumer;
+mod notification_bus;
+mod pager;
 pub(crate) mod process;
+mod queue_sizing;
+mod registry;
 mod rights;
+mod sequence;
 mod shared_memory_ipc;
+mod snapshot;
+mod sub_supervisor;
+mod supervisor;
+mod top_up;
+mod two_phase;
 
+pub use crate::userland::batch::*;
+pub use crate::userland::build_metadata::*;
+pub use crate::userland::cap_transfer::*;
+#[cfg(feature = "compact_messages")]
+pub use crate::userland::compact::*;
+pub use crate::userland::correlation::*;
+pub use crate::userland::deadline::*;
 pub use crate::userland::fault::*;
+pub use crate::userland::framed_ipc::*;
+pub use crate::userland::heap::*;
+pub use crate::userland::idle::*;
 pub use crate::userland::ipc::*;
 pub use crate::userland::irq::*;
+pub use crate::userland::irq_storm::*;
+pub use crate::userland::lease::*;
+pub use crate::userland::mailbox::*;
+pub use crate::userland::memory_faults::*;
 pub use crate::userland::multi_consumer::*;
+pub use crate::userland::notification_bus::*;
+pub use crate::userland::pager::*;
 pub use crate::userland::process::*;
+pub use crate::userland::queue_sizing::*;
+pub use crate::userland::registry::*;
 pub use crate::userland::rights::*;
+pub use crate::userland::sequence::*;
 pub use crate::userland::shared_memory_ipc::*;
+pub use crate::userland::snapshot::*;
+pub use crate::userland::sub_supervisor::*;
+pub use crate::userland::supervisor::*;
+pub use crate::userland::top_up::*;
+pub use crate::userland::two_phase::*;
@@ -19,6 +19,16 @@
 //! * The memory region where the queue lives mapped into its VSpace.
 //! * A pointer to the shared memory queue valid in its VSpace.
 //!
+//! For a queue taken from by several consumer threads instead, see
+//! `SharedQueueSetup`.
+//!
+//! Once set up, the consumer and its queues can be torn down again,
+//! e.g. to rewire the processes after one has died, handing back the
+//! untypeds and slots they were made from: producers with the
+//! `ProducerTeardown` from `Producer::new_with_teardown`, then queues with
+//! `ProducerSetup::teardown`, then the consumer with
+//! `ConsumerToken::teardown`.
+//!
 //! There are two doors into the consumer thread. Do you pick door A
 //! or B?
 //!
@@ -36,23 +46,26 @@
 //!     local_cnode,
 //!     dest_slots)?;
 use core::marker::PhantomData;
-use core::mem::size_of;
 use core::ops::Sub;
+use core::sync::atomic::{AtomicUsize, Ordering};
 
 use cross_queue::{ArrayQueue, PushError, Slot};
 use generic_array::ArrayLength;
-use selfe_sys::{seL4_Signal, seL4_Wait};
+use selfe_sys::{seL4_CNode_Delete, seL4_CNode_Revoke, seL4_Signal, seL4_Wait, seL4_WordBits};
 use typenum::*;
 
+#[cfg(target_arch = "x86_64")]
+use crate::arch::cap::MSIRoute;
 use crate::arch::{self, PageBits};
 use crate::cap::{
-    irq_state, role, Badge, CNodeRole, CNodeSlot, Cap, ChildCNodeSlot, ChildCNodeSlots,
-    DirectRetype, IRQControl, IRQError, IRQHandler, InternalASID, LocalCNode, LocalCNodeSlot,
-    LocalCNodeSlots, LocalCap, MaxIRQCount, Notification, PhantomCap, Untyped,
+    irq_state, role, Badge, CNodeRole, CNodeSlot, CNodeSlotsData, Cap, ChildCNodeSlot,
+    ChildCNodeSlots, DirectRetype, IRQControl, IRQError, IRQHandler, InternalASID, LocalCNode,
+    LocalCNodeSlot, LocalCNodeSlots, LocalCap, MaxIRQCount, Notification, PhantomCap, Untyped,
+    WCNodeSlots, WCNodeSlotsData,
 };
-use crate::error::SeL4Error;
+use crate::error::{ErrorExt, SeL4Error};
 use crate::pow::{Pow, _Pow};
-use crate::userland::CapRights;
+use crate::userland::{CapRights, StormGuard};
 use crate::vspace::{
     shared_status, KernelRetypeFanOutLimit, MappedMemoryRegion, NumPages, ScratchRegion,
     UnmappedMemoryRegion, VSpace, VSpaceError,
@@ -69,6 +82,7 @@ where
     irq_handler: Cap<IRQHandler<IRQ, irq_state::Set>, Role>,
     interrupt_badge: Badge,
     notification: Cap<Notification, Role>,
+    storm_guard: Option<StormGuard>,
 }
 
 /// A multi-consumer that consumes interrupt-style notifications and from 1
@@ -87,60 +101,6 @@ where
     queue: QueueHandle<T, Role>,
 }
 
-/// A multi-consumer that consumes interrupt-style notifications and from 2
-/// queues
-///
-/// Designed to be handed to a new process as a member of the
-/// initial thread parameters struct (see `VSpace::prepare_thread`).
-pub struct Consumer2<Role: CNodeRole, E, F, IRQ: Unsigned = U0>
-where
-    IRQ: IsLess<MaxIRQCount, Output = True>,
-{
-    irq_handler: Option<Cap<IRQHandler<IRQ, irq_state::Set>, Role>>,
-    interrupt_badge: Badge,
-    notification: Cap<Notification, Role>,
-    queues: ((Badge, QueueHandle<E, Role>), (Badge, QueueHandle<F, Role>)),
-}
-
-/// A multi-consumer that consumes interrupt-style notifications and from 3
-/// queues
-///
-/// Designed to be handed to a new process as a member of the
-/// initial thread parameters struct (see `VSpace::prepare_thread`).
-pub struct Consumer3<Role: CNodeRole, E, F, G, IRQ: Unsigned = U0>
-where
-    IRQ: IsLess<MaxIRQCount, Output = True>,
-{
-    irq_handler: Option<Cap<IRQHandler<IRQ, irq_state::Set>, Role>>,
-    interrupt_badge: Badge,
-    notification: Cap<Notification, Role>,
-    queues: (
-        (Badge, QueueHandle<E, Role>),
-        (Badge, QueueHandle<F, Role>),
-        (Badge, QueueHandle<G, Role>),
-    ),
-}
-
-/// A multi-consumer that consumes interrupt-style notifications and from 4
-/// queues
-///
-/// Designed to be handed to a new process as a member of the
-/// initial thread parameters struct (see `VSpace::prepare_thread`).
-pub struct Consumer4<Role: CNodeRole, E, F, G, H, IRQ: Unsigned = U0>
-where
-    IRQ: IsLess<MaxIRQCount, Output = True>,
-{
-    irq_handler: Option<Cap<IRQHandler<IRQ, irq_state::Set>, Role>>,
-    interrupt_badge: Badge,
-    notification: Cap<Notification, Role>,
-    queues: (
-        (Badge, QueueHandle<E, Role>),
-        (Badge, QueueHandle<F, Role>),
-        (Badge, QueueHandle<G, Role>),
-        (Badge, QueueHandle<H, Role>),
-    ),
-}
-
 /// Wrapper around the necessary support and capabilities for a given
 /// thread to push elements to an ingest queue for a multi-consumer
 /// (e.g. `Consumer1`, `Consumer2`,etc).
@@ -150,6 +110,7 @@ where
 pub struct Producer<Role: CNodeRole, T: Sized + Sync + Send> {
     notification: Cap<Notification, Role>,
     queue: QueueHandle<T, Role>,
+    dropped: AtomicUsize,
 }
 
 struct QueueHandle<T: Sized, Role: CNodeRole> {
@@ -196,6 +157,10 @@ where
     Pow<<QSizeBits as Sub<PageBits>>::Output>: Unsigned,
 {
     shared_region: UnmappedMemoryRegion<QSizeBits, shared_status::Shared>,
+    /// The consumer's mapping of the queue, and the untyped it was made
+    /// from, for tearing it down
+    consumer_mapping: MappedMemoryRegion<QSizeBits, shared_status::Shared>,
+    shared_region_ut: usize,
     queue_badge: Badge,
     // User-concealed alias'ing happening here.
     // Don't mutate this Cap. Copying/minting is okay.
@@ -224,6 +189,176 @@ pub struct ConsumerToken {
     // Don't mutate/delete this Cap. Copying/minting is okay.
     notification: Cap<Notification, role::Local>,
     consumer_vspace_asid: Option<InternalASID>,
+    /// Where the consumer was made from, for tearing it down
+    notification_ut: usize,
+    local_slots: WCNodeSlots,
+    /// Where an interrupt consumer's IRQ handler was made, if it was
+    /// made along with the consumer
+    handler_slot: Option<LocalCNodeSlot>,
+    consumer_slots: LocalCap<WCNodeSlotsData<role::Child>>,
+}
+
+impl ConsumerToken {
+    /// Tear down the consumer, deleting its caps from the consumer's
+    /// CNode and destroying its notification along with every copy of it
+    /// handed to producers and wakers. Hands back what it was made from.
+    ///
+    /// The consumer's process should be stopped first, or otherwise be
+    /// done with the consumer. Its queues are torn down separately, see
+    /// `ProducerSetup::teardown`.
+    pub fn teardown(
+        self,
+        local_cnode: &LocalCap<LocalCNode>,
+    ) -> Result<ReclaimedConsumer, SeL4Error> {
+        let consumer_slots = &self.consumer_slots.cap_data;
+        for offset in consumer_slots.offset..consumer_slots.offset + consumer_slots.size {
+            unsafe {
+                seL4_CNode_Delete(
+                    self.consumer_slots.cptr, // _service
+                    offset,                   // index
+                    seL4_WordBits as u8,      // depth
+                )
+            }
+            .as_result()
+            .map_err(SeL4Error::CNodeDelete)?;
+        }
+        unsafe {
+            seL4_CNode_Revoke(
+                local_cnode.cptr,     // _service
+                self.notification_ut, // index
+                seL4_WordBits as u8,  // depth
+            )
+        }
+        .as_result()
+        .map_err(SeL4Error::CNodeRevoke)?;
+        Ok(ReclaimedConsumer {
+            notification_ut: Cap::wrap_cptr(self.notification_ut),
+            local_slots: self.local_slots,
+            handler_slot: self.handler_slot,
+            consumer_slots: self.consumer_slots,
+        })
+    }
+
+    /// Hand back `handler_slot` too, which the handler was made in
+    /// before it was moved out
+    fn with_handler_slot(mut self, handler_slot: LocalCNodeSlot) -> Self {
+        self.handler_slot = Some(handler_slot);
+        self
+    }
+}
+
+/// What a torn down consumer was made from, see `ConsumerToken::teardown`
+pub struct ReclaimedConsumer {
+    pub notification_ut: LocalCap<Untyped<<Notification as DirectRetype>::SizeBits>>,
+    /// Emptied, in the local CNode
+    pub local_slots: WCNodeSlots,
+    /// The slot the IRQ handler was made in, emptied, for an interrupt
+    /// consumer made along with its handler
+    pub handler_slot: Option<LocalCNodeSlot>,
+    /// Emptied, in the consumer's CNode
+    pub consumer_slots: LocalCap<WCNodeSlotsData<role::Child>>,
+}
+
+impl<T, QLen: Unsigned, QSizeBits: Unsigned> ProducerSetup<T, QLen, QSizeBits>
+where
+    QSizeBits: IsGreaterOrEqual<PageBits>,
+    QSizeBits: Sub<PageBits>,
+    <QSizeBits as Sub<PageBits>>::Output: Unsigned,
+    <QSizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<QSizeBits as Sub<PageBits>>::Output>: Unsigned,
+{
+    /// Mark the queue as closed, e.g. once its consumer's process has
+    /// died, so the producers' sends fail with `SendError::ConsumerGone`
+    /// rather than `QueueFull` once they notice. The queue is mapped into
+    /// `local_vspace` with `local_slots` just long enough to mark it, and
+    /// the slots are handed back.
+    ///
+    /// Closing the queue before tearing it down lets producers that are
+    /// still running find out, rather than fault on the unmapped pages.
+    pub fn close(
+        &self,
+        local_vspace: &mut VSpace,
+        local_cnode: &LocalCap<LocalCNode>,
+        local_slots: LocalCNodeSlots<NumPages<QSizeBits>>,
+    ) -> Result<LocalCNodeSlots<NumPages<QSizeBits>>, MultiConsumerError> {
+        let mapped = local_vspace.map_shared_region(
+            &self.shared_region,
+            CapRights::RW,
+            arch::vm_attributes::DEFAULT,
+            local_slots,
+            local_cnode,
+        )?;
+        let queue: &ArrayQueue<T> = unsafe { core::mem::transmute(mapped.vaddr()) };
+        queue.close();
+        let local_copies = local_vspace.reclaim_region(mapped)?;
+        Ok(local_copies.delete_caps(local_cnode)?)
+    }
+
+    /// Tear down the queue, unmapping it from the consumer's vspace and
+    /// destroying its pages. Hands back what it was made from, ready for
+    /// another `add_queue`.
+    ///
+    /// Producers should be torn down first, see `ProducerTeardown`, to get
+    /// back the slots their copies of the pages are in. Those that aren't,
+    /// e.g. of a process that's died, just have their copies destroyed
+    /// along with the pages. Either way, the consumer's and producers'
+    /// processes should be done with the queue.
+    pub fn teardown(
+        self,
+        consumer_vspace: &mut VSpace,
+        local_cnode: &LocalCap<LocalCNode>,
+    ) -> Result<ReclaimedQueue<QSizeBits>, MultiConsumerError> {
+        if consumer_vspace.asid() != self.consumer_vspace_asid {
+            return Err(MultiConsumerError::ConsumerIdentityMismatch);
+        }
+        let consumer_copies = consumer_vspace.reclaim_region(self.consumer_mapping)?;
+        let shared_slots = consumer_copies.delete_caps(local_cnode)?;
+        let umr_slots = self.shared_region.delete_caps(local_cnode)?;
+        unsafe {
+            seL4_CNode_Revoke(
+                local_cnode.cptr,      // _service
+                self.shared_region_ut, // index
+                seL4_WordBits as u8,   // depth
+            )
+        }
+        .as_result()
+        .map_err(SeL4Error::CNodeRevoke)?;
+        Ok(ReclaimedQueue {
+            shared_region_ut: Cap::wrap_cptr(self.shared_region_ut),
+            umr_slots,
+            shared_slots,
+        })
+    }
+}
+
+/// What a torn down queue was made from, see `ProducerSetup::teardown`
+pub struct ReclaimedQueue<QSizeBits: Unsigned>
+where
+    QSizeBits: IsGreaterOrEqual<PageBits>,
+    QSizeBits: Sub<PageBits>,
+    <QSizeBits as Sub<PageBits>>::Output: Unsigned,
+    <QSizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<QSizeBits as Sub<PageBits>>::Output>: Unsigned,
+{
+    pub shared_region_ut: LocalCap<Untyped<QSizeBits>>,
+    pub umr_slots: LocalCNodeSlots<NumPages<QSizeBits>>,
+    pub shared_slots: LocalCNodeSlots<NumPages<QSizeBits>>,
+}
+
+/// Slots as they were before being used up, for handing them back once
+/// they're emptied again
+fn record_slots<Size: Unsigned, Role: CNodeRole>(
+    slots: &LocalCap<CNodeSlotsData<Size, Role>>,
+) -> LocalCap<WCNodeSlotsData<Role>> {
+    Cap {
+        cptr: slots.cptr,
+        cap_data: WCNodeSlotsData {
+            offset: slots.cap_data.offset,
+            size: Size::USIZE,
+            _role: PhantomData,
+        },
+        _role: PhantomData,
+    }
 }
 
 impl<IRQ: Unsigned> InterruptConsumer<IRQ, role::Child>
@@ -237,45 +372,70 @@ where
         local_slots: LocalCNodeSlots<U3>,
         consumer_slots: ChildCNodeSlots<U2>,
     ) -> Result<(InterruptConsumer<IRQ, role::Child>, ConsumerToken), IRQError> {
-        // Make a notification, mint-copy it to establish a badge
-        let (local_slot, local_slots) = local_slots.alloc();
-        let unbadged_notification: LocalCap<Notification> = notification_ut.retype(local_slot)?;
-
-        let interrupt_badge = Badge::from(1);
+        let (consumer, token, _waker_setup) = Self::new_with_waker(
+            notification_ut,
+            irq_control,
+            local_cnode,
+            local_slots,
+            consumer_slots,
+        )?;
+        Ok((consumer, token))
+    }
 
+    pub fn new_with_waker(
+        notification_ut: LocalCap<Untyped<<Notification as DirectRetype>::SizeBits>>,
+        irq_control: &mut LocalCap<IRQControl>,
+        local_cnode: &LocalCap<LocalCNode>,
+        local_slots: LocalCNodeSlots<U3>,
+        consumer_slots: ChildCNodeSlots<U2>,
+    ) -> Result<
+        (
+            InterruptConsumer<IRQ, role::Child>,
+            ConsumerToken,
+            WakerSetup,
+        ),
+        IRQError,
+    > {
         let (local_slot, local_slots) = local_slots.alloc();
-        let notification =
-            unbadged_notification.mint_inside_cnode(local_slot, CapRights::RWG, interrupt_badge)?;
-
-        // Make a new IRQHandler, link it to the notification and move both to the child
-        // CNode
-        let (local_slot, _local_slots) = local_slots.alloc();
+        let handler_slot =
+            LocalCNodeSlot::internal_new(local_slot.cptr, local_slot.cap_data.offset);
         let irq_handler = irq_control.create_handler(local_slot)?;
-        let irq_handler = irq_handler.set_notification(&notification)?;
-
-        let (consumer_slot, consumer_slots) = consumer_slots.alloc();
-        let irq_handler_in_child = irq_handler.move_to_slot(local_cnode, consumer_slot)?;
+        let (consumer, token, waker_setup) = Self::from_handler_with_waker(
+            notification_ut,
+            irq_handler,
+            local_cnode,
+            local_slots,
+            consumer_slots,
+        )?;
+        Ok((consumer, token.with_handler_slot(handler_slot), waker_setup))
+    }
 
-        let (consumer_slot, _consumer_slots) = consumer_slots.alloc();
-        let notification_in_child = notification.copy(local_cnode, consumer_slot, CapRights::RW)?;
-        Ok((
-            InterruptConsumer {
-                irq_handler: irq_handler_in_child,
-                interrupt_badge,
-                notification: notification_in_child,
-            },
-            ConsumerToken {
-                notification: unbadged_notification,
-                consumer_vspace_asid: None,
-            },
-        ))
+    /// Consume the interrupts of a handler made some other way than
+    /// `IRQControl::create_handler`, e.g. for an interrupt routed through
+    /// an IOAPIC or signaled by message.
+    pub fn from_handler(
+        notification_ut: LocalCap<Untyped<<Notification as DirectRetype>::SizeBits>>,
+        irq_handler: LocalCap<IRQHandler<IRQ, irq_state::Unset>>,
+        local_cnode: &LocalCap<LocalCNode>,
+        local_slots: LocalCNodeSlots<U2>,
+        consumer_slots: ChildCNodeSlots<U2>,
+    ) -> Result<(InterruptConsumer<IRQ, role::Child>, ConsumerToken), IRQError> {
+        let (consumer, token, _waker_setup) = Self::from_handler_with_waker(
+            notification_ut,
+            irq_handler,
+            local_cnode,
+            local_slots,
+            consumer_slots,
+        )?;
+        Ok((consumer, token))
     }
 
-    pub fn new_with_waker(
+    /// See `from_handler` and `new_with_waker`.
+    pub fn from_handler_with_waker(
         notification_ut: LocalCap<Untyped<<Notification as DirectRetype>::SizeBits>>,
-        irq_control: &mut LocalCap<IRQControl>,
+        irq_handler: LocalCap<IRQHandler<IRQ, irq_state::Unset>>,
         local_cnode: &LocalCap<LocalCNode>,
-        local_slots: LocalCNodeSlots<U3>,
+        local_slots: LocalCNodeSlots<U2>,
         consumer_slots: ChildCNodeSlots<U2>,
     ) -> Result<
         (
@@ -285,20 +445,22 @@ where
         ),
         IRQError,
     > {
+        let notification_ut_cptr = notification_ut.cptr;
+        let local_slots_record = record_slots(&local_slots);
+        let consumer_slots_record = record_slots(&consumer_slots);
+
         // Make a notification, mint-copy it to establish a badge
         let (local_slot, local_slots) = local_slots.alloc();
         let unbadged_notification: LocalCap<Notification> = notification_ut.retype(local_slot)?;
 
         let interrupt_badge = Badge::from(1);
 
-        let (local_slot, local_slots) = local_slots.alloc();
+        let (local_slot, _local_slots) = local_slots.alloc();
         let notification =
             unbadged_notification.mint_inside_cnode(local_slot, CapRights::RWG, interrupt_badge)?;
 
-        // Make a new IRQHandler, link it to the notification and move both to the child
+        // Link the IRQHandler to the notification and move both to the child
         // CNode
-        let (local_slot, _local_slots) = local_slots.alloc();
-        let irq_handler = irq_control.create_handler(local_slot)?;
         let irq_handler = irq_handler.set_notification(&notification)?;
 
         let (consumer_slot, consumer_slots) = consumer_slots.alloc();
@@ -321,10 +483,15 @@ where
                 irq_handler: irq_handler_in_child,
                 interrupt_badge,
                 notification: notification_in_child,
+                storm_guard: None,
             },
             ConsumerToken {
                 notification: unbadged_notification,
                 consumer_vspace_asid: None,
+                notification_ut: notification_ut_cptr,
+                local_slots: local_slots_record,
+                handler_slot: None,
+                consumer_slots: consumer_slots_record,
             },
             waker_setup,
         ))
@@ -373,7 +540,7 @@ where
         if consumer_token.consumer_vspace_asid.is_some() {
             return Err(MultiConsumerError::ConsumerIdentityMismatch);
         }
-        let (shared_region, consumer_shared_region) =
+        let (shared_region, consumer_shared_region, shared_region_ut) =
             create_region_filled_with_array_queue::<ScratchPages, E, ELen, EQueueSizeBits>(
                 shared_region_ut,
                 local_vspace_scratch,
@@ -390,6 +557,8 @@ where
         let producer_setup: ProducerSetup<E, ELen, EQueueSizeBits> = ProducerSetup {
             consumer_vspace_asid: consumer_vspace.asid(),
             shared_region,
+            consumer_mapping: consumer_shared_region,
+            shared_region_ut,
             queue_badge: fresh_queue_badge,
             // Construct a user-inaccessible copy of the local notification
             // purely for use in producing child-cnode-residing copies.
@@ -409,7 +578,7 @@ where
                 notification: self.notification,
                 queue_badge: fresh_queue_badge,
                 queue: QueueHandle {
-                    shared_queue: consumer_shared_region.vaddr(),
+                    shared_queue: producer_setup.consumer_mapping.vaddr(),
                     _role: PhantomData,
                     _t: PhantomData,
                     queue_len: ELen::USIZE,
@@ -461,7 +630,7 @@ where
         Pow<<EQueueSizeBits as Sub<PageBits>>::Output>:
             IsLessOrEqual<KernelRetypeFanOutLimit, Output = True>,
     {
-        let (shared_region, consumer_shared_region) =
+        let (shared_region, consumer_shared_region, shared_region_ut) =
             create_region_filled_with_array_queue::<ScratchPages, E, ELen, EQueueSizeBits>(
                 shared_region_ut,
                 local_vspace_scratch,
@@ -471,6 +640,9 @@ where
                 shared_slots,
             )?;
 
+        let notification_ut_cptr = notification_ut.cptr;
+        let local_slots_record = record_slots(&notification_slot);
+        let consumer_slots_record = record_slots(&consumer_slot);
         let local_notification: LocalCap<Notification> =
             notification_ut.retype(notification_slot)?;
 
@@ -486,6 +658,8 @@ where
         let producer_setup: ProducerSetup<E, ELen, EQueueSizeBits> = ProducerSetup {
             consumer_vspace_asid: consumer_vspace.asid(),
             shared_region,
+            consumer_mapping: consumer_shared_region,
+            shared_region_ut,
             queue_badge,
             // Construct a user-inaccessible copy of the local notification
             // purely for use in producing child-cnode-residing copies.
@@ -506,6 +680,10 @@ where
                 _role: PhantomData,
             },
             consumer_vspace_asid: Some(consumer_vspace.asid()),
+            notification_ut: notification_ut_cptr,
+            local_slots: local_slots_record,
+            handler_slot: None,
+            consumer_slots: consumer_slots_record,
         };
         let waker_setup = WakerSetup {
             interrupt_badge,
@@ -518,7 +696,7 @@ where
                 queue_badge,
                 notification: consumer_notification,
                 queue: QueueHandle {
-                    shared_queue: consumer_shared_region.vaddr(),
+                    shared_queue: producer_setup.consumer_mapping.vaddr(),
                     _role: PhantomData,
                     _t: PhantomData,
                     queue_len: ELen::USIZE,
@@ -578,7 +756,7 @@ where
         } else {
             return Err(MultiConsumerError::ConsumerIdentityMismatch);
         }
-        let (shared_region, consumer_shared_region) =
+        let (shared_region, consumer_shared_region, shared_region_ut) =
             create_region_filled_with_array_queue::<ScratchPages, F, FLen, FQueueSizeBits>(
                 shared_region_ut,
                 local_vspace_scratch,
@@ -592,6 +770,8 @@ where
         let producer_setup: ProducerSetup<F, FLen, FQueueSizeBits> = ProducerSetup {
             consumer_vspace_asid: consumer_vspace.asid(),
             shared_region,
+            consumer_mapping: consumer_shared_region,
+            shared_region_ut,
             queue_badge: fresh_queue_badge,
             // Construct a user-inaccessible copy of the local notification
             // purely for use in producing child-cnode-residing copies.
@@ -613,7 +793,7 @@ where
                     (
                         fresh_queue_badge,
                         QueueHandle {
-                            shared_queue: consumer_shared_region.vaddr(),
+                            shared_queue: producer_setup.consumer_mapping.vaddr(),
                             _role: PhantomData,
                             _t: PhantomData,
                             queue_len: FLen::USIZE,
@@ -626,212 +806,59 @@ where
     }
 }
 
-impl<E: Sized + Sync + Send, F: Sized + Sync + Send, IRQ: Unsigned>
-    Consumer2<role::Child, E, F, IRQ>
+fn create_region_filled_with_array_queue<
+    ScratchPages: Unsigned,
+    T: Sized + Send + Sync,
+    QLen: Unsigned,
+    QSizeBits: Unsigned,
+>(
+    shared_region_ut: LocalCap<Untyped<QSizeBits>>,
+    local_vspace_scratch: &mut ScratchRegion<ScratchPages>,
+    consumer_vspace: &mut VSpace,
+    local_cnode: &LocalCap<LocalCNode>,
+    umr_slots: LocalCNodeSlots<NumPages<QSizeBits>>,
+    shared_slots: LocalCNodeSlots<NumPages<QSizeBits>>,
+) -> Result<
+    (
+        UnmappedMemoryRegion<QSizeBits, shared_status::Shared>,
+        MappedMemoryRegion<QSizeBits, shared_status::Shared>,
+        usize,
+    ),
+    MultiConsumerError,
+>
 where
-    IRQ: IsLess<MaxIRQCount, Output = True>,
-{
-    pub fn add_queue<
-        G: Sized + Send + Sync,
-        GLen: Unsigned,
-        GQueueSizeBits: Unsigned,
-        ScratchPages: Unsigned,
-    >(
-        self,
-        consumer_token: &ConsumerToken,
-        shared_region_ut: LocalCap<Untyped<GQueueSizeBits>>,
-        local_vspace_scratch: &mut ScratchRegion<ScratchPages>,
-        consumer_vspace: &mut VSpace,
-        local_cnode: &LocalCap<LocalCNode>,
-        umr_slots: LocalCNodeSlots<NumPages<GQueueSizeBits>>,
-        shared_slots: LocalCNodeSlots<NumPages<GQueueSizeBits>>,
-    ) -> Result<
-        (
-            Consumer3<role::Child, E, F, G, IRQ>,
-            ProducerSetup<G, GLen, GQueueSizeBits>,
-        ),
-        MultiConsumerError,
-    >
-    where
-        GLen: ArrayLength<Slot<G>>,
-        GLen: IsGreater<U0, Output = True>,
-        ScratchPages: IsGreaterOrEqual<NumPages<GQueueSizeBits>, Output = True>,
-
-        // needed by temporarily_map_region
-        GQueueSizeBits: IsGreaterOrEqual<PageBits>,
-        GQueueSizeBits: Sub<PageBits>,
-        <GQueueSizeBits as Sub<PageBits>>::Output: Unsigned,
-        <GQueueSizeBits as Sub<PageBits>>::Output: _Pow,
-        Pow<<GQueueSizeBits as Sub<PageBits>>::Output>:
-            Unsigned + IsGreaterOrEqual<U1, Output = True>,
-
-        // Needed by unmappedMemoryRegion::new
-        Pow<<GQueueSizeBits as Sub<PageBits>>::Output>:
-            IsLessOrEqual<KernelRetypeFanOutLimit, Output = True>,
-    {
-        // Ensure that the consumer process that the `waker_setup` is wrapping
-        // a notification to is the same process as the one referred to by
-        // the `consumer_vspace` parameter.
-        if let Some(ref consumer_token_vspace_asid) = consumer_token.consumer_vspace_asid {
-            if consumer_token_vspace_asid != &consumer_vspace.asid() {
-                return Err(MultiConsumerError::ConsumerIdentityMismatch);
-            }
-        } else {
-            return Err(MultiConsumerError::ConsumerIdentityMismatch);
-        }
-        let (shared_region, consumer_shared_region) =
-            create_region_filled_with_array_queue::<ScratchPages, G, GLen, GQueueSizeBits>(
-                shared_region_ut,
-                local_vspace_scratch,
-                consumer_vspace,
-                local_cnode,
-                umr_slots,
-                shared_slots,
-            )?;
+    QLen: ArrayLength<Slot<T>>,
+    QLen: IsGreater<U0, Output = True>,
+    ScratchPages: IsGreaterOrEqual<NumPages<QSizeBits>, Output = True>,
 
-        let fresh_queue_badge = Badge::from((self.queues.1).0.inner << 1);
-        let producer_setup: ProducerSetup<G, GLen, GQueueSizeBits> = ProducerSetup {
-            consumer_vspace_asid: consumer_vspace.asid(),
-            shared_region,
-            queue_badge: fresh_queue_badge,
-            // Construct a user-inaccessible copy of the local notification
-            // purely for use in producing child-cnode-residing copies.
-            notification: Cap {
-                cptr: consumer_token.notification.cptr,
-                cap_data: PhantomCap::phantom_instance(),
-                _role: PhantomData,
-            },
-            _queue_element_type: PhantomData,
-            _queue_length: PhantomData,
-        };
-        Ok((
-            Consumer3 {
-                irq_handler: self.irq_handler,
-                interrupt_badge: self.interrupt_badge,
-                notification: self.notification,
-                queues: (
-                    self.queues.0,
-                    self.queues.1,
-                    (
-                        fresh_queue_badge,
-                        QueueHandle {
-                            shared_queue: consumer_shared_region.vaddr(),
-                            _role: PhantomData,
-                            _t: PhantomData,
-                            queue_len: GLen::USIZE,
-                        },
-                    ),
-                ),
-            },
-            producer_setup,
-        ))
-    }
-}
+    // needed by temporarily_map_region
+    QSizeBits: IsGreaterOrEqual<PageBits>,
+    QSizeBits: Sub<PageBits>,
+    <QSizeBits as Sub<PageBits>>::Output: Unsigned,
+    <QSizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<QSizeBits as Sub<PageBits>>::Output>: Unsigned + IsGreaterOrEqual<U1, Output = True>,
 
-impl<E: Sized + Sync + Send, F: Sized + Sync + Send, G: Sized + Sync + Send, IRQ: Unsigned>
-    Consumer3<role::Child, E, F, G, IRQ>
-where
-    IRQ: IsLess<MaxIRQCount, Output = True>,
+    // Needed by unmappedMemoryRegion::new
+    Pow<<QSizeBits as Sub<PageBits>>::Output>:
+        IsLessOrEqual<KernelRetypeFanOutLimit, Output = True>,
 {
-    pub fn add_queue<
-        H: Sized + Send + Sync,
-        HLen: Unsigned,
-        HQueueSizeBits: Unsigned,
-        ScratchPages: Unsigned,
-    >(
-        self,
-        consumer_token: &ConsumerToken,
-        shared_region_ut: LocalCap<Untyped<HQueueSizeBits>>,
-        local_vspace_scratch: &mut ScratchRegion<ScratchPages>,
-        consumer_vspace: &mut VSpace,
-        local_cnode: &LocalCap<LocalCNode>,
-        umr_slots: LocalCNodeSlots<NumPages<HQueueSizeBits>>,
-        shared_slots: LocalCNodeSlots<NumPages<HQueueSizeBits>>,
-    ) -> Result<
-        (
-            Consumer4<role::Child, E, F, G, H, IRQ>,
-            ProducerSetup<H, HLen, HQueueSizeBits>,
-        ),
-        MultiConsumerError,
-    >
-    where
-        HLen: ArrayLength<Slot<H>>,
-        HLen: IsGreater<U0, Output = True>,
-        ScratchPages: IsGreaterOrEqual<NumPages<HQueueSizeBits>, Output = True>,
-
-        // needed by temporarily_map_region
-        HQueueSizeBits: IsGreaterOrEqual<PageBits>,
-        HQueueSizeBits: Sub<PageBits>,
-        <HQueueSizeBits as Sub<PageBits>>::Output: Unsigned,
-        <HQueueSizeBits as Sub<PageBits>>::Output: _Pow,
-        Pow<<HQueueSizeBits as Sub<PageBits>>::Output>:
-            Unsigned + IsGreaterOrEqual<U1, Output = True>,
-
-        // Needed by unmappedMemoryRegion::new
-        Pow<<HQueueSizeBits as Sub<PageBits>>::Output>:
-            IsLessOrEqual<KernelRetypeFanOutLimit, Output = True>,
-    {
-        // Ensure that the consumer process that the `waker_setup` is wrapping
-        // a notification to is the same process as the one referred to by
-        // the `consumer_vspace` parameter.
-        if let Some(ref consumer_token_vspace_asid) = consumer_token.consumer_vspace_asid {
-            if consumer_token_vspace_asid != &consumer_vspace.asid() {
-                return Err(MultiConsumerError::ConsumerIdentityMismatch);
-            }
-        } else {
-            return Err(MultiConsumerError::ConsumerIdentityMismatch);
-        }
-        let (shared_region, consumer_shared_region) =
-            create_region_filled_with_array_queue::<ScratchPages, H, HLen, HQueueSizeBits>(
-                shared_region_ut,
-                local_vspace_scratch,
-                consumer_vspace,
-                local_cnode,
-                umr_slots,
-                shared_slots,
-            )?;
-
-        let fresh_queue_badge = Badge::from((self.queues.1).0.inner << 1);
-        let producer_setup: ProducerSetup<H, HLen, HQueueSizeBits> = ProducerSetup {
-            consumer_vspace_asid: consumer_vspace.asid(),
-            shared_region,
-            queue_badge: fresh_queue_badge,
-            // Construct a user-inaccessible copy of the local notification
-            // purely for use in producing child-cnode-residing copies.
-            notification: Cap {
-                cptr: consumer_token.notification.cptr,
-                cap_data: PhantomCap::phantom_instance(),
-                _role: PhantomData,
-            },
-            _queue_element_type: PhantomData,
-            _queue_length: PhantomData,
-        };
-        Ok((
-            Consumer4 {
-                irq_handler: self.irq_handler,
-                interrupt_badge: self.interrupt_badge,
-                notification: self.notification,
-                queues: (
-                    self.queues.0,
-                    self.queues.1,
-                    self.queues.2,
-                    (
-                        fresh_queue_badge,
-                        QueueHandle {
-                            shared_queue: consumer_shared_region.vaddr(),
-                            _role: PhantomData,
-                            _t: PhantomData,
-                            queue_len: HLen::USIZE,
-                        },
-                    ),
-                ),
-            },
-            producer_setup,
-        ))
-    }
+    // Kept for handing back once the queue's torn down
+    let shared_region_ut_cptr = shared_region_ut.cptr;
+    let shared_region = create_array_queue_region::<ScratchPages, T, QLen, QSizeBits>(
+        shared_region_ut,
+        local_vspace_scratch,
+        umr_slots,
+    )?;
+    let consumer_shared_region = map_queue_region_with_guard_pages(
+        &shared_region,
+        consumer_vspace,
+        local_cnode,
+        shared_slots,
+    )?;
+    Ok((shared_region, consumer_shared_region, shared_region_ut_cptr))
 }
 
-fn create_region_filled_with_array_queue<
+fn create_array_queue_region<
     ScratchPages: Unsigned,
     T: Sized + Send + Sync,
     QLen: Unsigned,
@@ -839,17 +866,8 @@ fn create_region_filled_with_array_queue<
 >(
     shared_region_ut: LocalCap<Untyped<QSizeBits>>,
     local_vspace_scratch: &mut ScratchRegion<ScratchPages>,
-    consumer_vspace: &mut VSpace,
-    local_cnode: &LocalCap<LocalCNode>,
     umr_slots: LocalCNodeSlots<NumPages<QSizeBits>>,
-    shared_slots: LocalCNodeSlots<NumPages<QSizeBits>>,
-) -> Result<
-    (
-        UnmappedMemoryRegion<QSizeBits, shared_status::Shared>,
-        MappedMemoryRegion<QSizeBits, shared_status::Shared>,
-    ),
-    MultiConsumerError,
->
+) -> Result<UnmappedMemoryRegion<QSizeBits, shared_status::Shared>, MultiConsumerError>
 where
     QLen: ArrayLength<Slot<T>>,
     QLen: IsGreater<U0, Output = True>,
@@ -867,9 +885,7 @@ where
         IsLessOrEqual<KernelRetypeFanOutLimit, Output = True>,
 {
     // Assert that there is enough space for the queue
-    assert!(
-        1 << QSizeBits::USIZE >= size_of::<ArrayQueue<T>>() + (QLen::USIZE * size_of::<Slot<T>>())
-    );
+    assert!(1 << QSizeBits::USIZE >= ArrayQueue::<T>::region_size(QLen::USIZE));
 
     let mut region = UnmappedMemoryRegion::new(shared_region_ut, umr_slots)?;
 
@@ -880,24 +896,37 @@ where
         // Operate directly on a pointer to an uninitialized/zeroed pointer
         // in order to reduces odds of the full ArrayQueue instance
         // materializing all at once on the local stack (potentially blowing it)
-        ArrayQueue::<T>::new_at_ptr(aq_ptr, QLen::USIZE, size_of::<ArrayQueue<T>>());
+        ArrayQueue::<T>::new_at_ptr(aq_ptr, QLen::USIZE, ArrayQueue::<T>::buffer_offset());
     })?;
 
-    let shared_region = region.to_shared();
+    Ok(region.to_shared())
+}
 
+fn map_queue_region_with_guard_pages<QSizeBits: Unsigned>(
+    shared_region: &UnmappedMemoryRegion<QSizeBits, shared_status::Shared>,
+    consumer_vspace: &mut VSpace,
+    local_cnode: &LocalCap<LocalCNode>,
+    shared_slots: LocalCNodeSlots<NumPages<QSizeBits>>,
+) -> Result<MappedMemoryRegion<QSizeBits, shared_status::Shared>, MultiConsumerError>
+where
+    QSizeBits: IsGreaterOrEqual<PageBits>,
+    QSizeBits: Sub<PageBits>,
+    <QSizeBits as Sub<PageBits>>::Output: Unsigned,
+    <QSizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<QSizeBits as Sub<PageBits>>::Output>: Unsigned,
+{
     // put guard pages on either side of the shared region, so any overruns
     // become page faults instead of data corruption.
     consumer_vspace.skip_pages(1)?;
     let consumer_shared_region = consumer_vspace.map_shared_region(
-        &shared_region,
+        shared_region,
         CapRights::RW,
         arch::vm_attributes::DEFAULT,
         shared_slots,
         local_cnode,
     )?;
     consumer_vspace.skip_pages(1)?;
-
-    Ok((shared_region, consumer_shared_region))
+    Ok(consumer_shared_region)
 }
 
 /// Wrapper around the necessary capabilities for a given
@@ -939,6 +968,15 @@ impl<IRQ: Unsigned> InterruptConsumer<IRQ, role::Local>
 where
     IRQ: IsLess<MaxIRQCount, Output = True>,
 {
+    /// Hold the interrupt to the guard's limit, leaving it masked for a
+    /// while whenever it wakes the consumer more often than that
+    pub fn with_storm_guard(self, storm_guard: StormGuard) -> Self {
+        InterruptConsumer {
+            storm_guard: Some(storm_guard),
+            ..self
+        }
+    }
+
     pub fn consume<State, WFn>(self, initial_state: State, mut waker_fn: WFn) -> !
     where
         WFn: FnMut(State) -> State,
@@ -954,32 +992,182 @@ where
             }
         };
         loop {
+            if let Some(ref storm_guard) = self.storm_guard {
+                // Nothing will wake the consumer while the interrupt's
+                // masked, so sit out the backoff before waiting on it
+                if storm_guard.is_masked() {
+                    storm_guard.wait_out_backoff();
+                    self.unmask(storm_guard);
+                }
+            }
             unsafe {
                 seL4_Wait(self.notification.cptr, &mut sender_badge as *mut usize);
-                let current_badge = Badge::from(sender_badge);
-                if self
-                    .interrupt_badge
-                    .are_all_overlapping_bits_set(current_badge)
-                {
-                    state = waker_fn(state);
-                    match self.irq_handler.ack() {
-                        Ok(_) => (),
-                        Err(e) => {
-                            debug_println!("Ack error in InterruptConsumer::consume loop. {:?}", e);
-                            panic!()
-                        }
-                    };
-                } else {
-                    debug_println!(
-                        "Unexpected badge in InterruptConsumer::consume loop. {:?}",
-                        current_badge
-                    );
+            }
+            state = self.handle_badge(Badge::from(sender_badge), state, &mut waker_fn);
+        }
+    }
+
+    /// Handle an interrupt if one is already pending, without blocking,
+    /// handing the state straight back if none is. For running the
+    /// consumer from an outer loop of its own instead of handing that over
+    /// to `consume`.
+    ///
+    /// Unlike `consume`, this doesn't ack the interrupt up front, see
+    /// `ack_interrupt`.
+    pub fn try_consume<State, WFn>(&self, state: State, mut waker_fn: WFn) -> State
+    where
+        WFn: FnMut(State) -> State,
+    {
+        if let Some(ref storm_guard) = self.storm_guard {
+            if storm_guard.backoff_elapsed() {
+                self.unmask(storm_guard);
+            }
+        }
+        match self.notification.poll() {
+            Some(badge) => self.handle_badge(badge, state, &mut waker_fn),
+            None => state,
+        }
+    }
+
+    /// Clear out interrupt state, as `consume` does before it first waits
+    pub fn ack_interrupt(&self) -> Result<(), SeL4Error> {
+        self.irq_handler.ack()
+    }
+
+    fn handle_badge<State, WFn>(
+        &self,
+        current_badge: Badge,
+        state: State,
+        waker_fn: &mut WFn,
+    ) -> State
+    where
+        WFn: FnMut(State) -> State,
+    {
+        if self
+            .interrupt_badge
+            .are_all_overlapping_bits_set(current_badge)
+        {
+            let state = waker_fn(state);
+            if let Some(ref storm_guard) = self.storm_guard {
+                if storm_guard.record_wakeup(IRQ::U16) {
+                    // Left unacked, the interrupt stays masked
+                    return state;
+                }
+            }
+            match self.irq_handler.ack() {
+                Ok(_) => (),
+                Err(e) => {
+                    debug_println!("Ack error in InterruptConsumer::consume loop. {:?}", e);
                     panic!()
                 }
+            };
+            state
+        } else {
+            debug_println!(
+                "Unexpected badge in InterruptConsumer::consume loop. {:?}",
+                current_badge
+            );
+            panic!()
+        }
+    }
+
+    fn unmask(&self, storm_guard: &StormGuard) {
+        match self.irq_handler.ack() {
+            Ok(_) => (),
+            Err(e) => {
+                debug_println!("Ack error in InterruptConsumer unmasking. {:?}", e);
+                panic!()
             }
+        };
+        storm_guard.unmasked();
+    }
+}
+
+/// An `InterruptConsumer` for a message signaled interrupt, delivered on
+/// vector `IRQ` from the PCI function named by its `MSIRoute`. Once made,
+/// it's consumed exactly like any other interrupt.
+///
+/// Only x86 is covered: seL4 has no way of acquiring a GICv3 LPI.
+#[cfg(target_arch = "x86_64")]
+pub struct MsiInterruptConsumer<IRQ: Unsigned, Role: CNodeRole>
+where
+    IRQ: IsLess<MaxIRQCount, Output = True>,
+{
+    consumer: InterruptConsumer<IRQ, Role>,
+    route: MSIRoute,
+}
+
+#[cfg(target_arch = "x86_64")]
+impl<IRQ: Unsigned> MsiInterruptConsumer<IRQ, role::Child>
+where
+    IRQ: IsLess<MaxIRQCount, Output = True>,
+{
+    pub fn new(
+        notification_ut: LocalCap<Untyped<<Notification as DirectRetype>::SizeBits>>,
+        irq_control: &mut LocalCap<IRQControl>,
+        route: MSIRoute,
+        local_cnode: &LocalCap<LocalCNode>,
+        local_slots: LocalCNodeSlots<U3>,
+        consumer_slots: ChildCNodeSlots<U2>,
+    ) -> Result<(MsiInterruptConsumer<IRQ, role::Child>, ConsumerToken), IRQError> {
+        let (local_slot, local_slots) = local_slots.alloc();
+        let handler_slot =
+            LocalCNodeSlot::internal_new(local_slot.cptr, local_slot.cap_data.offset);
+        let irq_handler = irq_control.create_msi_handler(local_slot, route)?;
+        let (consumer, token) = InterruptConsumer::from_handler(
+            notification_ut,
+            irq_handler,
+            local_cnode,
+            local_slots,
+            consumer_slots,
+        )?;
+        Ok((
+            MsiInterruptConsumer { consumer, route },
+            token.with_handler_slot(handler_slot),
+        ))
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+impl<IRQ: Unsigned> MsiInterruptConsumer<IRQ, role::Local>
+where
+    IRQ: IsLess<MaxIRQCount, Output = True>,
+{
+    /// Where the device's MSIs come from, for programming its MSI
+    /// capability
+    pub fn route(&self) -> MSIRoute {
+        self.route
+    }
+
+    /// See `InterruptConsumer::with_storm_guard`
+    pub fn with_storm_guard(self, storm_guard: StormGuard) -> Self {
+        MsiInterruptConsumer {
+            consumer: self.consumer.with_storm_guard(storm_guard),
+            route: self.route,
         }
     }
+
+    /// See `InterruptConsumer::consume`
+    pub fn consume<State, WFn>(self, initial_state: State, waker_fn: WFn) -> !
+    where
+        WFn: FnMut(State) -> State,
+    {
+        self.consumer.consume(initial_state, waker_fn)
+    }
+
+    /// See `InterruptConsumer::try_consume`
+    pub fn try_consume<State, WFn>(&self, state: State, waker_fn: WFn) -> State
+    where
+        WFn: FnMut(State) -> State,
+    {
+        self.consumer.try_consume(state, waker_fn)
+    }
+
+    pub fn ack_interrupt(&self) -> Result<(), SeL4Error> {
+        self.consumer.ack_interrupt()
+    }
 }
+
 impl<E: Sized + Sync + Send, IRQ: Unsigned> Consumer1<role::Local, E, IRQ>
 where
     IRQ: IsLess<MaxIRQCount, Output = True>,
@@ -1005,7 +1193,6 @@ where
     {
         let mut sender_badge: usize = 0;
         let mut state = initial_state;
-        let queue: &mut ArrayQueue<E> = unsafe { core::mem::transmute(self.queue.shared_queue) };
         if let Some(ref irq_handler) = self.irq_handler {
             // Run an initial ack to clear out interrupt state ahead of waiting
             match irq_handler.ack() {
@@ -1019,7 +1206,190 @@ where
         loop {
             unsafe {
                 seL4_Wait(self.notification.cptr, &mut sender_badge as *mut usize);
-                let current_badge = Badge::from(sender_badge);
+            }
+            state = self.handle_badge(Badge::from(sender_badge), state, &waker_fn, &queue_fn);
+        }
+    }
+
+    /// Handle whatever is already pending, without blocking, handing the
+    /// state straight back if nothing is. For running the consumer from an
+    /// outer loop of its own instead of handing that over to `consume`.
+    ///
+    /// Unlike `consume`, this doesn't ack the interrupt up front, see
+    /// `ack_interrupt`.
+    pub fn try_consume<State, WFn, EFn>(&self, state: State, waker_fn: WFn, queue_fn: EFn) -> State
+    where
+        WFn: Fn(State) -> State,
+        EFn: Fn(E, State) -> State,
+    {
+        match self.notification.poll() {
+            Some(badge) => self.handle_badge(badge, state, &waker_fn, &queue_fn),
+            None => state,
+        }
+    }
+
+    /// Clear out interrupt state, as `consume` does before it first waits
+    pub fn ack_interrupt(&self) -> Result<(), SeL4Error> {
+        match self.irq_handler {
+            Some(ref irq_handler) => irq_handler.ack(),
+            None => Ok(()),
+        }
+    }
+
+    fn handle_badge<State, WFn, EFn>(
+        &self,
+        current_badge: Badge,
+        mut state: State,
+        waker_fn: &WFn,
+        queue_fn: &EFn,
+    ) -> State
+    where
+        WFn: Fn(State) -> State,
+        EFn: Fn(E, State) -> State,
+    {
+        let queue: &mut ArrayQueue<E> = unsafe { core::mem::transmute(self.queue.shared_queue) };
+        if self
+            .interrupt_badge
+            .are_all_overlapping_bits_set(current_badge)
+        {
+            state = waker_fn(state);
+            if let Some(ref irq_handler) = self.irq_handler {
+                match irq_handler.ack() {
+                    Ok(_) => (),
+                    Err(e) => {
+                        debug_println!("Ack error in InterruptConsumer::consume loop. {:?}", e);
+                        panic!()
+                    }
+                };
+            }
+        }
+        if self.queue_badge.are_all_overlapping_bits_set(current_badge) {
+            for _ in 0..queue.len().saturating_add(1) {
+                if let Ok(e) = queue.pop() {
+                    state = queue_fn(e, state);
+                } else {
+                    break;
+                }
+            }
+        }
+        state
+    }
+}
+
+/// Defines a multi-consumer of interrupt-style notifications and of one
+/// queue per element type, along with its ways of consuming them
+macro_rules! multi_consumer {
+    ($name:ident, $count:literal, $(($T:ident, $queue_fn:ident, $TFn:ident, $badge:ident, $handle:ident, $queue:ident)),+) => {
+        #[doc = concat!(
+            "A multi-consumer that consumes interrupt-style notifications and from ",
+            $count,
+            "\nqueues\n\n",
+            "Designed to be handed to a new process as a member of the\n",
+            "initial thread parameters struct (see `VSpace::prepare_thread`)."
+        )]
+        pub struct $name<Role: CNodeRole, $($T,)+ IRQ: Unsigned = U0>
+        where
+            IRQ: IsLess<MaxIRQCount, Output = True>,
+        {
+            irq_handler: Option<Cap<IRQHandler<IRQ, irq_state::Set>, Role>>,
+            interrupt_badge: Badge,
+            notification: Cap<Notification, Role>,
+            queues: ($((Badge, QueueHandle<$T, Role>),)+),
+        }
+
+        impl<$($T: Sized + Sync + Send,)+ IRQ: Unsigned> $name<role::Local, $($T,)+ IRQ>
+        where
+            IRQ: IsLess<MaxIRQCount, Output = True>,
+        {
+            pub fn capacity(&self) -> ($(multi_consumer!(@usize $T),)+) {
+                let ($((_, ref $handle),)+) = self.queues;
+                ($($handle.queue_len,)+)
+            }
+
+            pub fn consume<State, WFn, $($TFn,)+>(
+                self,
+                initial_state: State,
+                waker_fn: WFn,
+                $($queue_fn: $TFn,)+
+            ) -> !
+            where
+                WFn: Fn(State) -> State,
+                $($TFn: Fn($T, State) -> State,)+
+            {
+                let mut sender_badge: usize = 0;
+                let mut state = initial_state;
+
+                if let Some(ref irq_handler) = self.irq_handler {
+                    match irq_handler.ack() {
+                        Ok(_) => (),
+                        Err(e) => {
+                            debug_println!("Ack error in InterruptConsumer::consume setup. {:?}", e);
+                            panic!()
+                        }
+                    };
+                }
+                loop {
+                    unsafe {
+                        seL4_Wait(self.notification.cptr, &mut sender_badge as *mut usize);
+                    }
+                    state = self.handle_badge(
+                        Badge::from(sender_badge),
+                        state,
+                        &waker_fn,
+                        $(&$queue_fn,)+
+                    );
+                }
+            }
+
+            /// Handle whatever is already pending, without blocking, handing
+            /// the state straight back if nothing is. For running the consumer
+            /// from an outer loop of its own instead of handing that over to
+            /// `consume`.
+            ///
+            /// Unlike `consume`, this doesn't ack the interrupt up front, see
+            /// `ack_interrupt`.
+            pub fn try_consume<State, WFn, $($TFn,)+>(
+                &self,
+                state: State,
+                waker_fn: WFn,
+                $($queue_fn: $TFn,)+
+            ) -> State
+            where
+                WFn: Fn(State) -> State,
+                $($TFn: Fn($T, State) -> State,)+
+            {
+                match self.notification.poll() {
+                    Some(badge) => self.handle_badge(badge, state, &waker_fn, $(&$queue_fn,)+),
+                    None => state,
+                }
+            }
+
+            /// Clear out interrupt state, as `consume` does before it first
+            /// waits
+            pub fn ack_interrupt(&self) -> Result<(), SeL4Error> {
+                match self.irq_handler {
+                    Some(ref irq_handler) => irq_handler.ack(),
+                    None => Ok(()),
+                }
+            }
+
+            fn handle_badge<State, WFn, $($TFn,)+>(
+                &self,
+                current_badge: Badge,
+                mut state: State,
+                waker_fn: &WFn,
+                $($queue_fn: &$TFn,)+
+            ) -> State
+            where
+                WFn: Fn(State) -> State,
+                $($TFn: Fn($T, State) -> State,)+
+            {
+                let ($(($badge, ref $handle),)+) = self.queues;
+                $(
+                    let $queue: &mut ArrayQueue<$T> =
+                        unsafe { core::mem::transmute($handle.shared_queue) };
+                )+
+
                 if self
                     .interrupt_badge
                     .are_all_overlapping_bits_set(current_badge)
@@ -1038,331 +1408,559 @@ where
                         };
                     }
                 }
-                if self.queue_badge.are_all_overlapping_bits_set(current_badge) {
-                    for _ in 0..queue.len().saturating_add(1) {
-                        if let Ok(e) = queue.pop() {
-                            state = queue_fn(e, state);
-                        } else {
-                            break;
+                $(
+                    if $badge.are_all_overlapping_bits_set(current_badge) {
+                        for _ in 0..$queue.len().saturating_add(1) {
+                            if let Ok(e) = $queue.pop() {
+                                state = $queue_fn(e, state);
+                            } else {
+                                break;
+                            }
                         }
                     }
+                )+
+                state
+            }
+        }
+    };
+    (@usize $T:ident) => {
+        usize
+    };
+}
+
+/// Defines `add_queue` on a multi-consumer, making the next larger one
+macro_rules! multi_consumer_add_queue {
+    ($name:ident => $next:ident, $New:ident, $NewLen:ident, $NewQueueSizeBits:ident, $($T:ident: $q:ident),+; $last:ident) => {
+        impl<$($T: Sized + Sync + Send,)+ IRQ: Unsigned> $name<role::Child, $($T,)+ IRQ>
+        where
+            IRQ: IsLess<MaxIRQCount, Output = True>,
+        {
+            pub fn add_queue<
+                $New: Sized + Send + Sync,
+                $NewLen: Unsigned,
+                $NewQueueSizeBits: Unsigned,
+                ScratchPages: Unsigned,
+            >(
+                self,
+                consumer_token: &ConsumerToken,
+                shared_region_ut: LocalCap<Untyped<$NewQueueSizeBits>>,
+                local_vspace_scratch: &mut ScratchRegion<ScratchPages>,
+                consumer_vspace: &mut VSpace,
+                local_cnode: &LocalCap<LocalCNode>,
+                umr_slots: LocalCNodeSlots<NumPages<$NewQueueSizeBits>>,
+                shared_slots: LocalCNodeSlots<NumPages<$NewQueueSizeBits>>,
+            ) -> Result<
+                (
+                    $next<role::Child, $($T,)+ $New, IRQ>,
+                    ProducerSetup<$New, $NewLen, $NewQueueSizeBits>,
+                ),
+                MultiConsumerError,
+            >
+            where
+                $NewLen: ArrayLength<Slot<$New>>,
+                $NewLen: IsGreater<U0, Output = True>,
+                ScratchPages: IsGreaterOrEqual<NumPages<$NewQueueSizeBits>, Output = True>,
+
+                // needed by temporarily_map_region
+                $NewQueueSizeBits: IsGreaterOrEqual<PageBits>,
+                $NewQueueSizeBits: Sub<PageBits>,
+                <$NewQueueSizeBits as Sub<PageBits>>::Output: Unsigned,
+                <$NewQueueSizeBits as Sub<PageBits>>::Output: _Pow,
+                Pow<<$NewQueueSizeBits as Sub<PageBits>>::Output>:
+                    Unsigned + IsGreaterOrEqual<U1, Output = True>,
+
+                // Needed by unmappedMemoryRegion::new
+                Pow<<$NewQueueSizeBits as Sub<PageBits>>::Output>:
+                    IsLessOrEqual<KernelRetypeFanOutLimit, Output = True>,
+            {
+                // Ensure that the consumer process that the `waker_setup` is wrapping
+                // a notification to is the same process as the one referred to by
+                // the `consumer_vspace` parameter.
+                if let Some(ref consumer_token_vspace_asid) = consumer_token.consumer_vspace_asid {
+                    if consumer_token_vspace_asid != &consumer_vspace.asid() {
+                        return Err(MultiConsumerError::ConsumerIdentityMismatch);
+                    }
+                } else {
+                    return Err(MultiConsumerError::ConsumerIdentityMismatch);
                 }
+                let (shared_region, consumer_shared_region, shared_region_ut) = create_region_filled_with_array_queue::<
+                    ScratchPages,
+                    $New,
+                    $NewLen,
+                    $NewQueueSizeBits,
+                >(
+                    shared_region_ut,
+                    local_vspace_scratch,
+                    consumer_vspace,
+                    local_cnode,
+                    umr_slots,
+                    shared_slots,
+                )?;
+
+                let ($($q,)+) = self.queues;
+                let fresh_queue_badge = Badge::from($last.0.inner << 1);
+                let producer_setup: ProducerSetup<$New, $NewLen, $NewQueueSizeBits> = ProducerSetup {
+                    consumer_vspace_asid: consumer_vspace.asid(),
+                    shared_region,
+                    consumer_mapping: consumer_shared_region,
+                    shared_region_ut,
+                    queue_badge: fresh_queue_badge,
+                    // Construct a user-inaccessible copy of the local notification
+                    // purely for use in producing child-cnode-residing copies.
+                    notification: Cap {
+                        cptr: consumer_token.notification.cptr,
+                        cap_data: PhantomCap::phantom_instance(),
+                        _role: PhantomData,
+                    },
+                    _queue_element_type: PhantomData,
+                    _queue_length: PhantomData,
+                };
+                Ok((
+                    $next {
+                        irq_handler: self.irq_handler,
+                        interrupt_badge: self.interrupt_badge,
+                        notification: self.notification,
+                        queues: (
+                            $($q,)+
+                            (
+                                fresh_queue_badge,
+                                QueueHandle {
+                                    shared_queue: producer_setup.consumer_mapping.vaddr(),
+                                    _role: PhantomData,
+                                    _t: PhantomData,
+                                    queue_len: $NewLen::USIZE,
+                                },
+                            ),
+                        ),
+                    },
+                    producer_setup,
+                ))
             }
         }
-    }
+    };
 }
 
-impl<E: Sized + Sync + Send, F: Sized + Sync + Send, IRQ: Unsigned>
-    Consumer2<role::Local, E, F, IRQ>
-where
-    IRQ: IsLess<MaxIRQCount, Output = True>,
-{
-    pub fn capacity(&self) -> (usize, usize) {
-        ((self.queues.0).1.queue_len, (self.queues.1).1.queue_len)
-    }
+multi_consumer!(
+    Consumer2,
+    "2",
+    (E, queue_e_fn, EFn, badge_e, handle_e, queue_e),
+    (F, queue_f_fn, FFn, badge_f, handle_f, queue_f)
+);
+multi_consumer!(
+    Consumer3,
+    "3",
+    (E, queue_e_fn, EFn, badge_e, handle_e, queue_e),
+    (F, queue_f_fn, FFn, badge_f, handle_f, queue_f),
+    (G, queue_g_fn, GFn, badge_g, handle_g, queue_g)
+);
+multi_consumer!(
+    Consumer4,
+    "4",
+    (E, queue_e_fn, EFn, badge_e, handle_e, queue_e),
+    (F, queue_f_fn, FFn, badge_f, handle_f, queue_f),
+    (G, queue_g_fn, GFn, badge_g, handle_g, queue_g),
+    (H, queue_h_fn, HFn, badge_h, handle_h, queue_h)
+);
+multi_consumer!(
+    Consumer5,
+    "5",
+    (E, queue_e_fn, EFn, badge_e, handle_e, queue_e),
+    (F, queue_f_fn, FFn, badge_f, handle_f, queue_f),
+    (G, queue_g_fn, GFn, badge_g, handle_g, queue_g),
+    (H, queue_h_fn, HFn, badge_h, handle_h, queue_h),
+    (I, queue_i_fn, IFn, badge_i, handle_i, queue_i)
+);
+
+multi_consumer_add_queue!(Consumer2 => Consumer3, G, GLen, GQueueSizeBits, E: queue_e, F: queue_f; queue_f);
+multi_consumer_add_queue!(Consumer3 => Consumer4, H, HLen, HQueueSizeBits, E: queue_e, F: queue_f, G: queue_g; queue_g);
+multi_consumer_add_queue!(Consumer4 => Consumer5, I, ILen, IQueueSizeBits, E: queue_e, F: queue_f, G: queue_g, H: queue_h; queue_h);
 
-    pub fn consume<State, WFn, EFn, FFn>(
-        self,
-        initial_state: State,
-        waker_fn: WFn,
-        queue_e_fn: EFn,
-        queue_f_fn: FFn,
-    ) -> !
-    where
-        WFn: Fn(State) -> State,
-        EFn: Fn(E, State) -> State,
-        FFn: Fn(F, State) -> State,
-    {
-        let mut sender_badge: usize = 0;
-        let mut state = initial_state;
+impl<T: Sized + Sync + Send, Role: CNodeRole> Producer<Role, T> {
+    pub fn new<QSizeBits: Unsigned, QLen: Unsigned>(
+        setup: &ProducerSetup<T, QLen, QSizeBits>,
+        dest_slot: CNodeSlot<Role>,
+        dest_vspace: &mut VSpace,
+        local_cnode: &LocalCap<LocalCNode>,
+        local_slots: LocalCNodeSlots<NumPages<QSizeBits>>,
+    ) -> Result<Self, MultiConsumerError>
+    where
+        QLen: IsGreater<U0, Output = True>,
+        QLen: ArrayLength<Slot<T>>,
 
-        let (badge_e, handle_e) = self.queues.0;
-        let queue_e: &mut ArrayQueue<E> = unsafe { core::mem::transmute(handle_e.shared_queue) };
+        // needed for memoryregion
+        QSizeBits: IsGreaterOrEqual<PageBits>,
+        QSizeBits: Sub<PageBits>,
+        <QSizeBits as Sub<PageBits>>::Output: Unsigned,
+        <QSizeBits as Sub<PageBits>>::Output: _Pow,
+        Pow<<QSizeBits as Sub<PageBits>>::Output>: Unsigned,
+    {
+        let (producer, _teardown) =
+            Self::new_with_teardown(setup, dest_slot, dest_vspace, local_cnode, local_slots)?;
+        Ok(producer)
+    }
 
-        let (badge_f, handle_f) = self.queues.1;
-        let queue_f: &mut ArrayQueue<F> = unsafe { core::mem::transmute(handle_f.shared_queue) };
+    /// As `new`, also keeping what's needed to take the producer's
+    /// mapping of the queue and its notification back out again.
+    pub fn new_with_teardown<QSizeBits: Unsigned, QLen: Unsigned>(
+        setup: &ProducerSetup<T, QLen, QSizeBits>,
+        dest_slot: CNodeSlot<Role>,
+        dest_vspace: &mut VSpace,
+        local_cnode: &LocalCap<LocalCNode>,
+        local_slots: LocalCNodeSlots<NumPages<QSizeBits>>,
+    ) -> Result<(Self, ProducerTeardown<Role, QSizeBits>), MultiConsumerError>
+    where
+        QLen: IsGreater<U0, Output = True>,
+        QLen: ArrayLength<Slot<T>>,
 
-        if let Some(ref irq_handler) = self.irq_handler {
-            match irq_handler.ack() {
-                Ok(_) => (),
-                Err(e) => {
-                    debug_println!("Ack error in InterruptConsumer::consume setup. {:?}", e);
-                    panic!()
-                }
-            };
-        }
-        loop {
-            unsafe {
-                seL4_Wait(self.notification.cptr, &mut sender_badge as *mut usize);
-                let current_badge = Badge::from(sender_badge);
-                if self
-                    .interrupt_badge
-                    .are_all_overlapping_bits_set(current_badge)
-                {
-                    state = waker_fn(state);
-                    if let Some(ref irq_handler) = self.irq_handler {
-                        match irq_handler.ack() {
-                            Ok(_) => (),
-                            Err(e) => {
-                                debug_println!(
-                                    "Ack error in InterruptConsumer::consume loop. {:?}",
-                                    e
-                                );
-                                panic!()
-                            }
-                        };
-                    }
-                }
-                if badge_e.are_all_overlapping_bits_set(current_badge) {
-                    for _ in 0..queue_e.len().saturating_add(1) {
-                        if let Ok(e) = queue_e.pop() {
-                            state = queue_e_fn(e, state);
-                        } else {
-                            break;
-                        }
-                    }
-                }
-                if badge_f.are_all_overlapping_bits_set(current_badge) {
-                    for _ in 0..queue_f.len().saturating_add(1) {
-                        if let Ok(e) = queue_f.pop() {
-                            state = queue_f_fn(e, state);
-                        } else {
-                            break;
-                        }
-                    }
-                }
-            }
+        // needed for memoryregion
+        QSizeBits: IsGreaterOrEqual<PageBits>,
+        QSizeBits: Sub<PageBits>,
+        <QSizeBits as Sub<PageBits>>::Output: Unsigned,
+        <QSizeBits as Sub<PageBits>>::Output: _Pow,
+        Pow<<QSizeBits as Sub<PageBits>>::Output>: Unsigned,
+    {
+        if setup.consumer_vspace_asid == dest_vspace.asid() {
+            // To simplify reasoning about likely control flow patterns,
+            // we presently disallow a consumer thread from producing to one
+            // of its own ingest queues.
+            return Err(MultiConsumerError::ProduceToOwnQueueForbidden);
         }
+        let producer_shared_region = dest_vspace.map_shared_region(
+            &setup.shared_region,
+            CapRights::RW,
+            arch::vm_attributes::DEFAULT,
+            local_slots,
+            local_cnode,
+        )?;
+        let notification_slot = Cap::internal_new(dest_slot.cptr, dest_slot.cap_data.offset);
+        let notification =
+            setup
+                .notification
+                .mint(local_cnode, dest_slot, CapRights::RWG, setup.queue_badge)?;
+        Ok((
+            Producer {
+                notification,
+                queue: QueueHandle {
+                    shared_queue: producer_shared_region.vaddr(),
+                    _role: PhantomData,
+                    _t: PhantomData,
+                    queue_len: QLen::USIZE,
+                },
+                dropped: AtomicUsize::new(0),
+            },
+            ProducerTeardown {
+                producer_mapping: producer_shared_region,
+                notification_slot,
+            },
+        ))
     }
 }
 
-impl<E: Sized + Sync + Send, F: Sized + Sync + Send, G: Sized + Sync + Send, IRQ: Unsigned>
-    Consumer3<role::Local, E, F, G, IRQ>
+/// What's needed to tear down a producer, see
+/// `Producer::new_with_teardown`
+pub struct ProducerTeardown<Role: CNodeRole, QSizeBits: Unsigned>
 where
-    IRQ: IsLess<MaxIRQCount, Output = True>,
+    QSizeBits: IsGreaterOrEqual<PageBits>,
+    QSizeBits: Sub<PageBits>,
+    <QSizeBits as Sub<PageBits>>::Output: Unsigned,
+    <QSizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<QSizeBits as Sub<PageBits>>::Output>: Unsigned,
 {
-    pub fn capacity(&self) -> (usize, usize, usize) {
-        (
-            (self.queues.0).1.queue_len,
-            (self.queues.1).1.queue_len,
-            (self.queues.2).1.queue_len,
-        )
-    }
+    producer_mapping: MappedMemoryRegion<QSizeBits, shared_status::Shared>,
+    notification_slot: CNodeSlot<Role>,
+}
+
+/// What a torn down producer was made from, see
+/// `ProducerTeardown::teardown`
+pub struct ReclaimedProducer<Role: CNodeRole, QSizeBits: Unsigned>
+where
+    QSizeBits: IsGreaterOrEqual<PageBits>,
+    QSizeBits: Sub<PageBits>,
+    <QSizeBits as Sub<PageBits>>::Output: Unsigned,
+    <QSizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<QSizeBits as Sub<PageBits>>::Output>: Unsigned,
+{
+    pub local_slots: LocalCNodeSlots<NumPages<QSizeBits>>,
+    pub dest_slot: CNodeSlot<Role>,
+}
 
-    pub fn consume<State, WFn, EFn, FFn, GFn>(
+impl<Role: CNodeRole, QSizeBits: Unsigned> ProducerTeardown<Role, QSizeBits>
+where
+    QSizeBits: IsGreaterOrEqual<PageBits>,
+    QSizeBits: Sub<PageBits>,
+    <QSizeBits as Sub<PageBits>>::Output: Unsigned,
+    <QSizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<QSizeBits as Sub<PageBits>>::Output>: Unsigned,
+{
+    /// Unmap the queue from the producer's vspace and delete its copies
+    /// of the pages and the notification. The producer's process should
+    /// be done with the producer.
+    pub fn teardown(
         self,
-        initial_state: State,
-        waker_fn: WFn,
-        queue_e_fn: EFn,
-        queue_f_fn: FFn,
-        queue_g_fn: GFn,
-    ) -> !
-    where
-        WFn: Fn(State) -> State,
-        EFn: Fn(E, State) -> State,
-        FFn: Fn(F, State) -> State,
-        GFn: Fn(G, State) -> State,
-    {
-        let mut sender_badge: usize = 0;
-        let mut state = initial_state;
+        producer_vspace: &mut VSpace,
+        local_cnode: &LocalCap<LocalCNode>,
+    ) -> Result<ReclaimedProducer<Role, QSizeBits>, MultiConsumerError> {
+        let producer_copies = producer_vspace.reclaim_region(self.producer_mapping)?;
+        let local_slots = producer_copies.delete_caps(local_cnode)?;
+        unsafe {
+            seL4_CNode_Delete(
+                self.notification_slot.cptr,            // _service
+                self.notification_slot.cap_data.offset, // index
+                seL4_WordBits as u8,                    // depth
+            )
+        }
+        .as_result()
+        .map_err(SeL4Error::CNodeDelete)?;
+        Ok(ReclaimedProducer {
+            local_slots,
+            dest_slot: self.notification_slot,
+        })
+    }
+}
 
-        let (badge_e, handle_e) = self.queues.0;
-        let queue_e: &mut ArrayQueue<E> = unsafe { core::mem::transmute(handle_e.shared_queue) };
+/// Why `Producer::send` turned an element away. Either way, the element
+/// is handed back.
+#[derive(Clone, Copy, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum SendError<T> {
+    /// The consumer hasn't kept up and the queue is full
+    QueueFull(T),
+    /// The queue's been closed, its consumer is gone and won't take
+    /// anything from it again, see `ProducerSetup::close`
+    ConsumerGone(T),
+}
 
-        let (badge_f, handle_f) = self.queues.1;
-        let queue_f: &mut ArrayQueue<F> = unsafe { core::mem::transmute(handle_f.shared_queue) };
+impl<T> SendError<T> {
+    /// The element that wasn't sent
+    pub fn into_inner(self) -> T {
+        match self {
+            SendError::QueueFull(t) => t,
+            SendError::ConsumerGone(t) => t,
+        }
+    }
+}
 
-        let (badge_g, handle_g) = self.queues.2;
-        let queue_g: &mut ArrayQueue<G> = unsafe { core::mem::transmute(handle_g.shared_queue) };
+impl<T> From<PushError<T>> for SendError<T> {
+    fn from(p: PushError<T>) -> Self {
+        SendError::QueueFull(p.0)
+    }
+}
 
-        if let Some(ref irq_handler) = self.irq_handler {
-            match irq_handler.ack() {
-                Ok(_) => (),
-                Err(e) => {
-                    debug_println!("Ack error in InterruptConsumer::consume setup. {:?}", e);
-                    panic!()
-                }
-            };
-        }
-        loop {
-            unsafe {
-                seL4_Wait(self.notification.cptr, &mut sender_badge as *mut usize);
-                let current_badge = Badge::from(sender_badge);
-                if self
-                    .interrupt_badge
-                    .are_all_overlapping_bits_set(current_badge)
-                {
-                    state = waker_fn(state);
-                    if let Some(ref irq_handler) = self.irq_handler {
-                        match irq_handler.ack() {
-                            Ok(_) => (),
-                            Err(e) => {
-                                debug_println!(
-                                    "Ack error in InterruptConsumer::consume loop. {:?}",
-                                    e
-                                );
-                                panic!()
-                            }
-                        };
-                    }
-                }
-                if badge_e.are_all_overlapping_bits_set(current_badge) {
-                    for _ in 0..queue_e.len().saturating_add(1) {
-                        if let Ok(e) = queue_e.pop() {
-                            state = queue_e_fn(e, state);
-                        } else {
-                            break;
-                        }
-                    }
-                }
-                if badge_f.are_all_overlapping_bits_set(current_badge) {
-                    for _ in 0..queue_f.len().saturating_add(1) {
-                        if let Ok(e) = queue_f.pop() {
-                            state = queue_f_fn(e, state);
-                        } else {
-                            break;
-                        }
-                    }
-                }
-                if badge_g.are_all_overlapping_bits_set(current_badge) {
-                    for _ in 0..queue_g.len().saturating_add(1) {
-                        if let Ok(e) = queue_g.pop() {
-                            state = queue_g_fn(e, state);
-                        } else {
-                            break;
-                        }
-                    }
-                }
-            }
+impl<T: Sized + Sync + Send> Producer<role::Local, T> {
+    pub fn capacity(&self) -> usize {
+        self.queue.queue_len
+    }
+
+    pub fn is_full(&self) -> bool {
+        let queue: &ArrayQueue<T> = unsafe { core::mem::transmute(self.queue.shared_queue) };
+        queue.is_full()
+    }
+
+    pub fn send(&self, t: T) -> Result<(), SendError<T>> {
+        let queue: &mut ArrayQueue<T> = unsafe { core::mem::transmute(self.queue.shared_queue) };
+        if queue.is_closed() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return Err(SendError::ConsumerGone(t));
         }
+        queue.push(t).map_err(|e| {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            SendError::QueueFull(e.0)
+        })?;
+        unsafe { seL4_Signal(self.notification.cptr) }
+        Ok(())
+    }
+
+    /// How many elements `send` has turned away so far
+    pub fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
     }
 }
 
-impl<
-        E: Sized + Sync + Send,
-        F: Sized + Sync + Send,
-        G: Sized + Sync + Send,
-        H: Sized + Sync + Send,
-        IRQ: Unsigned,
-    > Consumer4<role::Local, E, F, G, H, IRQ>
+/// Wrapper around the necessary resources to add consumers and producers
+/// to a single queue shared among several consumers, for spreading its
+/// elements across worker processes.
+///
+/// All the `SharedConsumer`s wait on the same notification, and each
+/// signal from a producer wakes one of them. A consumer that finds more
+/// elements waiting after taking one signals the notification again, so
+/// another idle consumer wakes to take the next one instead of leaving it
+/// for this one to get around to.
+pub struct SharedQueueSetup<T, QLen: Unsigned, QSizeBits: Unsigned>
 where
-    IRQ: IsLess<MaxIRQCount, Output = True>,
+    // needed for memoryregion
+    QSizeBits: IsGreaterOrEqual<PageBits>,
+    QSizeBits: Sub<PageBits>,
+    <QSizeBits as Sub<PageBits>>::Output: Unsigned,
+    <QSizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<QSizeBits as Sub<PageBits>>::Output>: Unsigned,
 {
-    pub fn capacity(&self) -> (usize, usize, usize, usize) {
-        (
-            (self.queues.0).1.queue_len,
-            (self.queues.1).1.queue_len,
-            (self.queues.2).1.queue_len,
-            (self.queues.3).1.queue_len,
-        )
-    }
+    shared_region: UnmappedMemoryRegion<QSizeBits, shared_status::Shared>,
+    queue_badge: Badge,
+    notification: LocalCap<Notification>,
+    _queue_element_type: PhantomData<T>,
+    _queue_length: PhantomData<QLen>,
+}
 
-    pub fn consume<State, WFn, EFn, FFn, GFn, HFn>(
-        self,
-        initial_state: State,
-        waker_fn: WFn,
-        queue_e_fn: EFn,
-        queue_f_fn: FFn,
-        queue_g_fn: GFn,
-        queue_h_fn: HFn,
-    ) -> !
+/// One of several consumers of a queue shared among them, see
+/// `SharedQueueSetup`.
+///
+/// Designed to be handed to a new process as a member of the
+/// initial thread parameters struct (see `VSpace::prepare_thread`).
+pub struct SharedConsumer<Role: CNodeRole, T: Sized + Sync + Send> {
+    notification: Cap<Notification, Role>,
+    queue: QueueHandle<T, Role>,
+}
+
+impl<T: Sized + Sync + Send, QLen: Unsigned, QSizeBits: Unsigned>
+    SharedQueueSetup<T, QLen, QSizeBits>
+where
+    QLen: ArrayLength<Slot<T>>,
+    QLen: IsGreater<U0, Output = True>,
+
+    // needed for memoryregion
+    QSizeBits: IsGreaterOrEqual<PageBits>,
+    QSizeBits: Sub<PageBits>,
+    <QSizeBits as Sub<PageBits>>::Output: Unsigned,
+    <QSizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<QSizeBits as Sub<PageBits>>::Output>: Unsigned + IsGreaterOrEqual<U1, Output = True>,
+
+    // needed for unmappedMemoryRegion constructor
+    Pow<<QSizeBits as Sub<PageBits>>::Output>:
+        IsLessOrEqual<KernelRetypeFanOutLimit, Output = True>,
+{
+    pub fn new<ScratchPages: Unsigned>(
+        notification_ut: LocalCap<Untyped<<Notification as DirectRetype>::SizeBits>>,
+        shared_region_ut: LocalCap<Untyped<QSizeBits>>,
+        local_vspace_scratch: &mut ScratchRegion<ScratchPages>,
+        umr_slots: LocalCNodeSlots<NumPages<QSizeBits>>,
+        notification_slot: LocalCNodeSlot,
+    ) -> Result<Self, MultiConsumerError>
     where
-        WFn: Fn(State) -> State,
-        EFn: Fn(E, State) -> State,
-        FFn: Fn(F, State) -> State,
-        GFn: Fn(G, State) -> State,
-        HFn: Fn(H, State) -> State,
+        ScratchPages: IsGreaterOrEqual<NumPages<QSizeBits>, Output = True>,
     {
-        let mut sender_badge: usize = 0;
-        let mut state = initial_state;
+        let shared_region = create_array_queue_region::<ScratchPages, T, QLen, QSizeBits>(
+            shared_region_ut,
+            local_vspace_scratch,
+            umr_slots,
+        )?;
+        let notification: LocalCap<Notification> = notification_ut.retype(notification_slot)?;
+        Ok(SharedQueueSetup {
+            shared_region,
+            queue_badge: Badge::from(1 << 0),
+            notification,
+            _queue_element_type: PhantomData,
+            _queue_length: PhantomData,
+        })
+    }
+}
 
-        let (badge_e, handle_e) = self.queues.0;
-        let queue_e: &mut ArrayQueue<E> = unsafe { core::mem::transmute(handle_e.shared_queue) };
+impl<T: Sized + Sync + Send> SharedConsumer<role::Child, T> {
+    pub fn new<QSizeBits: Unsigned, QLen: Unsigned>(
+        setup: &SharedQueueSetup<T, QLen, QSizeBits>,
+        dest_slot: ChildCNodeSlot,
+        consumer_vspace: &mut VSpace,
+        local_cnode: &LocalCap<LocalCNode>,
+        shared_slots: LocalCNodeSlots<NumPages<QSizeBits>>,
+    ) -> Result<Self, MultiConsumerError>
+    where
+        QLen: IsGreater<U0, Output = True>,
+        QLen: ArrayLength<Slot<T>>,
 
-        let (badge_f, handle_f) = self.queues.1;
-        let queue_f: &mut ArrayQueue<F> = unsafe { core::mem::transmute(handle_f.shared_queue) };
+        // needed for memoryregion
+        QSizeBits: IsGreaterOrEqual<PageBits>,
+        QSizeBits: Sub<PageBits>,
+        <QSizeBits as Sub<PageBits>>::Output: Unsigned,
+        <QSizeBits as Sub<PageBits>>::Output: _Pow,
+        Pow<<QSizeBits as Sub<PageBits>>::Output>: Unsigned,
+    {
+        let consumer_shared_region = map_queue_region_with_guard_pages(
+            &setup.shared_region,
+            consumer_vspace,
+            local_cnode,
+            shared_slots,
+        )?;
+        // Badged like a producer's, so the consumer can pass a wakeup on
+        // to the others
+        let notification =
+            setup
+                .notification
+                .mint(local_cnode, dest_slot, CapRights::RWG, setup.queue_badge)?;
+        Ok(SharedConsumer {
+            notification,
+            queue: QueueHandle {
+                shared_queue: consumer_shared_region.vaddr(),
+                _role: PhantomData,
+                _t: PhantomData,
+                queue_len: QLen::USIZE,
+            },
+        })
+    }
+}
 
-        let (badge_g, handle_g) = self.queues.2;
-        let queue_g: &mut ArrayQueue<G> = unsafe { core::mem::transmute(handle_g.shared_queue) };
+impl<T: Sized + Sync + Send> SharedConsumer<role::Local, T> {
+    pub fn capacity(&self) -> usize {
+        self.queue.queue_len
+    }
 
-        let (badge_h, handle_h) = self.queues.3;
-        let queue_h: &mut ArrayQueue<H> = unsafe { core::mem::transmute(handle_h.shared_queue) };
+    pub fn poll(&mut self) -> Option<T> {
+        let queue: &mut ArrayQueue<T> = unsafe { core::mem::transmute(self.queue.shared_queue) };
 
-        if let Some(ref irq_handler) = self.irq_handler {
-            match irq_handler.ack() {
-                Ok(_) => (),
-                Err(e) => {
-                    debug_println!("Ack error in InterruptConsumer::consume setup. {:?}", e);
-                    panic!()
-                }
-            };
+        if let Ok(e) = queue.pop() {
+            Some(e)
+        } else {
+            None
         }
+    }
+
+    pub fn consume<State, QFn>(self, initial_state: State, mut queue_fn: QFn) -> !
+    where
+        QFn: FnMut(T, State) -> State,
+    {
+        let mut sender_badge: usize = 0;
+        let mut state = initial_state;
         loop {
             unsafe {
                 seL4_Wait(self.notification.cptr, &mut sender_badge as *mut usize);
-                let current_badge = Badge::from(sender_badge);
-                if self
-                    .interrupt_badge
-                    .are_all_overlapping_bits_set(current_badge)
-                {
-                    state = waker_fn(state);
-                    if let Some(ref irq_handler) = self.irq_handler {
-                        match irq_handler.ack() {
-                            Ok(_) => (),
-                            Err(e) => {
-                                debug_println!(
-                                    "Ack error in InterruptConsumer::consume loop. {:?}",
-                                    e
-                                );
-                                panic!()
-                            }
-                        };
-                    }
-                }
-                if badge_e.are_all_overlapping_bits_set(current_badge) {
-                    for _ in 0..queue_e.len().saturating_add(1) {
-                        if let Ok(e) = queue_e.pop() {
-                            state = queue_e_fn(e, state);
-                        } else {
-                            break;
-                        }
-                    }
-                }
-                if badge_f.are_all_overlapping_bits_set(current_badge) {
-                    for _ in 0..queue_f.len().saturating_add(1) {
-                        if let Ok(e) = queue_f.pop() {
-                            state = queue_f_fn(e, state);
-                        } else {
-                            break;
-                        }
-                    }
-                }
-                if badge_g.are_all_overlapping_bits_set(current_badge) {
-                    for _ in 0..queue_g.len().saturating_add(1) {
-                        if let Ok(e) = queue_g.pop() {
-                            state = queue_g_fn(e, state);
-                        } else {
-                            break;
-                        }
-                    }
-                }
-                if badge_h.are_all_overlapping_bits_set(current_badge) {
-                    for _ in 0..queue_h.len().saturating_add(1) {
-                        if let Ok(e) = queue_h.pop() {
-                            state = queue_h_fn(e, state);
-                        } else {
-                            break;
-                        }
-                    }
-                }
             }
+            state = self.drain(state, &mut queue_fn);
+        }
+    }
+
+    /// Take from the queue if a producer has signalled, without blocking,
+    /// handing the state straight back otherwise. For running the consumer
+    /// from an outer loop of its own instead of handing that over to
+    /// `consume`.
+    pub fn try_consume<State, QFn>(&self, state: State, mut queue_fn: QFn) -> State
+    where
+        QFn: FnMut(T, State) -> State,
+    {
+        match self.notification.poll() {
+            Some(_) => self.drain(state, &mut queue_fn),
+            None => state,
+        }
+    }
+
+    fn drain<State, QFn>(&self, mut state: State, queue_fn: &mut QFn) -> State
+    where
+        QFn: FnMut(T, State) -> State,
+    {
+        let queue: &ArrayQueue<T> = unsafe { core::mem::transmute(self.queue.shared_queue) };
+        while let Ok(e) = queue.pop() {
+            if !queue.is_empty() {
+                unsafe { seL4_Signal(self.notification.cptr) }
+            }
+            state = queue_fn(e, state);
         }
+        state
     }
 }
 
 impl<T: Sized + Sync + Send, Role: CNodeRole> Producer<Role, T> {
-    pub fn new<QSizeBits: Unsigned, QLen: Unsigned>(
-        setup: &ProducerSetup<T, QLen, QSizeBits>,
+    /// Produce to a queue shared among several consumers. Unlike
+    /// `Producer::new`, nothing stops one of the consumers from producing
+    /// to it as well.
+    pub fn new_shared<QSizeBits: Unsigned, QLen: Unsigned>(
+        setup: &SharedQueueSetup<T, QLen, QSizeBits>,
         dest_slot: CNodeSlot<Role>,
         dest_vspace: &mut VSpace,
         local_cnode: &LocalCap<LocalCNode>,
@@ -1379,12 +1977,6 @@ impl<T: Sized + Sync + Send, Role: CNodeRole> Producer<Role, T> {
         <QSizeBits as Sub<PageBits>>::Output: _Pow,
         Pow<<QSizeBits as Sub<PageBits>>::Output>: Unsigned,
     {
-        if setup.consumer_vspace_asid == dest_vspace.asid() {
-            // To simplify reasoning about likely control flow patterns,
-            // we presently disallow a consumer thread from producing to one
-            // of its own ingest queues.
-            return Err(MultiConsumerError::ProduceToOwnQueueForbidden);
-        }
         let producer_shared_region = dest_vspace.map_shared_region(
             &setup.shared_region,
             CapRights::RW,
@@ -1404,34 +1996,7 @@ impl<T: Sized + Sync + Send, Role: CNodeRole> Producer<Role, T> {
                 _t: PhantomData,
                 queue_len: QLen::USIZE,
             },
+            dropped: AtomicUsize::new(0),
         })
     }
 }
-
-/// Error which occurs when pushing into a full queue.
-#[derive(Clone, Copy, Eq, PartialEq)]
-pub struct QueueFullError<T>(pub T);
-
-impl<T> From<PushError<T>> for QueueFullError<T> {
-    fn from(p: PushError<T>) -> Self {
-        QueueFullError(p.0)
-    }
-}
-
-impl<T: Sized + Sync + Send> Producer<role::Local, T> {
-    pub fn capacity(&self) -> usize {
-        self.queue.queue_len
-    }
-
-    pub fn is_full(&self) -> bool {
-        let queue: &ArrayQueue<T> = unsafe { core::mem::transmute(self.queue.shared_queue) };
-        queue.is_full()
-    }
-
-    pub fn send(&self, t: T) -> Result<(), QueueFullError<T>> {
-        let queue: &mut ArrayQueue<T> = unsafe { core::mem::transmute(self.queue.shared_queue) };
-        queue.push(t)?;
-        unsafe { seL4_Signal(self.notification.cptr) }
-        Ok(())
-    }
-}
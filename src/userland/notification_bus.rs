@@ -0,0 +1,211 @@
+//! Many wakeup sources sharing a single notification.
+//!
+//! Each kind of event on a `NotificationBus` gets a bit of the
+//! notification word. Publishers are handed a capability minted with
+//! their event's bit as its badge, so signalling it sets that bit, and
+//! the listener gets back the set of events that fired since it last
+//! waited, all in one word. That replaces a notification and a consumer
+//! per source with one of each, at the cost of an event only ever being
+//! counted once however many times it fired in between.
+//!
+//! ```ignore
+//! #[derive(Clone, Copy)]
+//! enum Wakeup { Rx, Tx, Timer }
+//!
+//! impl BusEvent for Wakeup {
+//!     fn bit(self) -> u8 { self as u8 }
+//!     fn from_bit(bit: u8) -> Option<Self> { ... }
+//! }
+//!
+//! let setup = NotificationBusSetup::<Wakeup, _>::new(&root_cnode, ut, slot, listener_slot)?;
+//! let rx = setup.add_publisher(&root_cnode, child_slot, Wakeup::Rx)?;
+//! let listener = setup.listener();
+//! for event in listener.wait().iter() { ... }
+//! ```
+use core::fmt;
+use core::marker::PhantomData;
+
+use crate::arch::NotificationBits;
+use crate::cap::{
+    role, Badge, CNodeRole, CNodeSlot, Cap, LocalCNode, LocalCNodeSlot, LocalCap, Notification,
+    Untyped,
+};
+use crate::error::SeL4Error;
+use crate::userland::CapRights;
+
+/// How many distinct events a bus carries, the bits of a badge the kernel
+/// keeps
+pub const BUS_EVENT_BITS: u8 = (core::mem::size_of::<usize>() * 8 - 4) as u8;
+
+/// An event carried on a `NotificationBus`, by the bit of the
+/// notification word that stands for it
+pub trait BusEvent: Copy + Send + Sync {
+    /// The event's bit, less than `BUS_EVENT_BITS`
+    fn bit(self) -> u8;
+
+    /// The event a bit stands for, if any
+    fn from_bit(bit: u8) -> Option<Self>;
+}
+
+#[derive(Debug)]
+pub enum NotificationBusError {
+    /// The event's bit doesn't fit in a badge
+    EventBitOutOfRange(u8),
+    SeL4Error(SeL4Error),
+}
+
+impl From<SeL4Error> for NotificationBusError {
+    fn from(e: SeL4Error) -> Self {
+        NotificationBusError::SeL4Error(e)
+    }
+}
+
+pub struct NotificationBusSetup<E: BusEvent, ListenerRole: CNodeRole> {
+    local_notification: LocalCap<Notification>,
+    listener_notification: Cap<Notification, ListenerRole>,
+    _event: PhantomData<E>,
+}
+
+impl<E: BusEvent, ListenerRole: CNodeRole> NotificationBusSetup<E, ListenerRole> {
+    /// Make the bus' notification, with the listener's copy of it in
+    /// `listener_slot`.
+    pub fn new(
+        local_cnode: &LocalCap<LocalCNode>,
+        untyped: LocalCap<Untyped<NotificationBits>>,
+        notification_slot: LocalCNodeSlot,
+        listener_slot: CNodeSlot<ListenerRole>,
+    ) -> Result<Self, SeL4Error> {
+        let local_notification: LocalCap<Notification> = untyped.retype(notification_slot)?;
+        let listener_notification =
+            local_notification.copy(local_cnode, listener_slot, CapRights::R)?;
+        Ok(NotificationBusSetup {
+            local_notification,
+            listener_notification,
+            _event: PhantomData,
+        })
+    }
+
+    /// Mint a capability into `publisher_slot` for signalling `event`.
+    /// An event can have any number of publishers.
+    pub fn add_publisher<PublisherRole: CNodeRole>(
+        &self,
+        local_cnode: &LocalCap<LocalCNode>,
+        publisher_slot: CNodeSlot<PublisherRole>,
+        event: E,
+    ) -> Result<BusPublisher<E, PublisherRole>, NotificationBusError> {
+        let bit = event.bit();
+        if bit >= BUS_EVENT_BITS {
+            return Err(NotificationBusError::EventBitOutOfRange(bit));
+        }
+        let notification = self.local_notification.mint(
+            local_cnode,
+            publisher_slot,
+            CapRights::W,
+            Badge::from(1 << bit),
+        )?;
+        Ok(BusPublisher {
+            notification,
+            event,
+        })
+    }
+
+    pub fn listener(self) -> BusListener<E, ListenerRole> {
+        BusListener {
+            notification: self.listener_notification,
+            _event: PhantomData,
+        }
+    }
+}
+
+/// Signals one event on a bus
+pub struct BusPublisher<E: BusEvent, Role: CNodeRole> {
+    notification: Cap<Notification, Role>,
+    event: E,
+}
+
+impl<E: BusEvent> BusPublisher<E, role::Local> {
+    pub fn event(&self) -> E {
+        self.event
+    }
+
+    pub fn publish(&self) {
+        self.notification.signal()
+    }
+}
+
+/// Waits for the events on a bus
+pub struct BusListener<E: BusEvent, Role: CNodeRole> {
+    notification: Cap<Notification, Role>,
+    _event: PhantomData<E>,
+}
+
+impl<E: BusEvent> BusListener<E, role::Local> {
+    /// Block until at least one event fires, returning every one that
+    /// has since the last `wait` or `poll`
+    pub fn wait(&self) -> EventSet<E> {
+        EventSet::from_badge(self.notification.wait())
+    }
+
+    /// The events that have fired since the last `wait` or `poll`, if
+    /// any, without blocking
+    pub fn poll(&self) -> Option<EventSet<E>> {
+        self.notification.poll().map(EventSet::from_badge)
+    }
+}
+
+/// A set of events that fired on a bus
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct EventSet<E: BusEvent> {
+    bits: usize,
+    _event: PhantomData<E>,
+}
+
+impl<E: BusEvent> EventSet<E> {
+    fn from_badge(badge: Badge) -> Self {
+        EventSet {
+            bits: badge.into(),
+            _event: PhantomData,
+        }
+    }
+
+    pub fn empty() -> Self {
+        EventSet {
+            bits: 0,
+            _event: PhantomData,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bits == 0
+    }
+
+    pub fn contains(&self, event: E) -> bool {
+        event.bit() < BUS_EVENT_BITS && self.bits & (1 << event.bit()) != 0
+    }
+
+    pub fn insert(&mut self, event: E) {
+        if event.bit() < BUS_EVENT_BITS {
+            self.bits |= 1 << event.bit();
+        }
+    }
+
+    /// The raw notification word
+    pub fn bits(&self) -> usize {
+        self.bits
+    }
+
+    /// The events in the set, lowest bit first. Bits no event stands for
+    /// are skipped.
+    pub fn iter(&self) -> impl Iterator<Item = E> {
+        let bits = self.bits;
+        (0..BUS_EVENT_BITS)
+            .filter(move |bit| bits & (1 << bit) != 0)
+            .filter_map(E::from_bit)
+    }
+}
+
+impl<E: BusEvent> fmt::Debug for EventSet<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "EventSet({:#b})", self.bits)
+    }
+}
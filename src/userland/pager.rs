@@ -0,0 +1,161 @@
+//! Growing a process' stack on demand.
+//!
+//! `StandardProcess::new_with_lazy_stack` maps only the top of a process'
+//! stack, and leaves the address space below it unmapped. The process'
+//! faults go to a `StackPager`, in the root task or a process set aside
+//! for it, which maps a page from its reserve of memory wherever the
+//! stack faults and lets the process carry on. Any other fault, or one
+//! in the guard page below the lazy part of the stack, is handed back to
+//! the pager's caller to deal with.
+use typenum::Unsigned;
+
+use crate::alloc::ut_buddy::{weak_ut_buddy, UTBuddyError, WUTBuddy};
+use crate::arch::fault::Fault;
+use crate::arch::{self, PageBits, PageBytes};
+use crate::cap::{
+    memory_kind, page_state, role, FaultReplyEndpoint, InternalASID, LocalCNodeSlot, LocalCap,
+    Page, WCNodeSlots, WUntyped,
+};
+use crate::error::SeL4Error;
+use crate::userland::{CapRights, FaultSink};
+use crate::vspace::{VSpace, VSpaceError};
+
+/// The unmapped part of a process' stack, from `bottom` up to where the
+/// mapped part starts at `top`
+#[derive(Debug, Clone, Copy)]
+pub struct LazyStack {
+    pub(crate) bottom: usize,
+    pub(crate) top: usize,
+    pub(crate) asid: InternalASID,
+}
+
+impl LazyStack {
+    pub fn contains(&self, vaddr: usize) -> bool {
+        self.bottom <= vaddr && vaddr < self.top
+    }
+
+    /// How many pages the stack can grow by
+    pub fn page_count(&self) -> usize {
+        (self.top - self.bottom) / PageBytes::USIZE
+    }
+}
+
+#[derive(Debug)]
+pub enum PagerError {
+    LazyStackASIDMustMatchVSpaceASID,
+    /// The reserve has no more pages to give
+    ReserveExhausted(UTBuddyError),
+    VSpaceError(VSpaceError),
+    SeL4Error(SeL4Error),
+}
+
+impl From<VSpaceError> for PagerError {
+    fn from(e: VSpaceError) -> Self {
+        PagerError::VSpaceError(e)
+    }
+}
+
+impl From<SeL4Error> for PagerError {
+    fn from(e: SeL4Error) -> Self {
+        PagerError::SeL4Error(e)
+    }
+}
+
+/// What `StackPager::handle_fault` did with a fault
+#[derive(Debug)]
+pub enum PagerEvent {
+    /// A page was mapped into the stack at `vaddr` and the process
+    /// resumed
+    Mapped { vaddr: usize },
+    /// Not a fault in the lazy part of the stack, the process is left
+    /// stopped
+    Unhandled(Fault),
+}
+
+/// Maps pages into a process' lazy stack as it faults on them
+pub struct StackPager {
+    vspace: VSpace,
+    sink: FaultSink<role::Local>,
+    stack: LazyStack,
+    reserve: WUTBuddy,
+    slots: WCNodeSlots,
+    reply_slot: Option<LocalCNodeSlot>,
+    mapped_pages: usize,
+}
+
+impl StackPager {
+    /// Page the lazy `stack` of the process with `vspace`, which sends its
+    /// faults to `sink`, with pages from `reserve`. Each page takes a slot
+    /// from `slots`, and splitting `reserve` down to pages takes more.
+    pub fn new(
+        vspace: VSpace,
+        sink: FaultSink<role::Local>,
+        stack: LazyStack,
+        reserve: LocalCap<WUntyped<memory_kind::General>>,
+        slots: WCNodeSlots,
+        reply_slot: LocalCNodeSlot,
+    ) -> Result<StackPager, PagerError> {
+        if stack.asid != vspace.asid() {
+            return Err(PagerError::LazyStackASIDMustMatchVSpaceASID);
+        }
+        Ok(StackPager {
+            vspace,
+            sink,
+            stack,
+            reserve: weak_ut_buddy(reserve),
+            slots,
+            reply_slot: Some(reply_slot),
+            mapped_pages: 0,
+        })
+    }
+
+    /// How many pages have been mapped into the stack so far
+    pub fn mapped_pages(&self) -> usize {
+        self.mapped_pages
+    }
+
+    /// Wait for the process to fault, and map a page into its stack if
+    /// that's where it faulted.
+    pub fn handle_fault(&mut self) -> Result<PagerEvent, PagerError> {
+        let fault = self.sink.wait_for_fault();
+        let vaddr = match &fault {
+            Fault::VMFault(f) if self.stack.contains(f.address) => {
+                f.address & !(PageBytes::USIZE - 1)
+            }
+            _ => return Ok(PagerEvent::Unhandled(fault)),
+        };
+        // Save the reply before anything else is received
+        let reply_slot = self
+            .reply_slot
+            .take()
+            .expect("The reply slot is always given back");
+        let reply = LocalCap::<FaultReplyEndpoint>::save_caller_and_create(reply_slot)?;
+
+        let page = self.map_page(vaddr);
+        self.reply_slot = Some(reply.resume_faulted_thread());
+        page?;
+        self.mapped_pages += 1;
+        Ok(PagerEvent::Mapped { vaddr })
+    }
+
+    fn map_page(&mut self, vaddr: usize) -> Result<(), PagerError> {
+        let ut = self
+            .reserve
+            .alloc_strong::<PageBits>(&mut self.slots)
+            .map_err(PagerError::ReserveExhausted)?;
+        let slot = self
+            .slots
+            .alloc_strong()
+            .map_err(|_| PagerError::ReserveExhausted(UTBuddyError::NotEnoughSlots))?;
+        let page: LocalCap<Page<page_state::Unmapped>> = ut.retype(slot)?;
+        self.vspace
+            .map_region_at_addr(
+                page.to_region(),
+                vaddr,
+                CapRights::RW,
+                arch::vm_attributes::DEFAULT | arch::vm_attributes::EXECUTE_NEVER,
+            )
+            .map_err(|(e, _)| e)?;
+        Ok(())
+    }
+}
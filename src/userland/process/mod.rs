@@ -7,17 +7,24 @@ use typenum::*;
 use crate::error::*;
 use crate::vspace::VSpaceError;
 
+pub use ferros_derive::RetypeForSetup;
+
 pub(crate) use crate::arch::userland::process::*;
 
 mod thread;
 pub use thread::{Thread, ThreadSetupError};
 
 mod standard;
-pub use standard::StandardProcess;
+pub use standard::{ReclaimedProcess, StandardProcess};
 
 mod self_hosted;
 pub use self_hosted::SelfHostedProcess;
 
+mod spawn;
+pub use spawn::{JoinHandle, ThreadAuthority, ThreadStack, ThreadStacks, MAX_THREAD_STACKS};
+
+mod tls;
+
 pub type DefaultStackBitSize = U20;
 pub type DefaultStackPageCount = op!((U1 << U20) / U4096);
 pub type DefaultPrepareThreadCNodeSlots = op!(DefaultStackPageCount + U64);
@@ -25,6 +32,180 @@ pub type DefaultPrepareThreadCNodeSlots = op!(DefaultStackPageCount + U64);
 // TODO - consider renaming for clarity
 pub trait RetypeForSetup: Sized + Send + Sync {
     type Output: Sized + Send + Sync;
+
+    /// How the parameter reaches the child's entry point
+    const PARAMS_PASSING: ParamsPassing = ParamsPassing::of::<Self::Output>();
+
+    /// A hash of the layout of the parameter as handed to the child,
+    /// checked against the one embedded in an ELF image with
+    /// `embed_params_layout!` when the process is set up. Only the size
+    /// and alignment are covered unless the impl is derived, in which
+    /// case each field is too.
+    const PARAMS_LAYOUT_HASH: u64 = ParamsLayout::of::<Self::Output>().hash();
+}
+
+/// The layout of a process parameter type, built up a field at a time
+/// into a `RetypeForSetup::PARAMS_LAYOUT_HASH`, 64-bit FNV-1a like
+/// `vspace::elf_segment_hash`.
+///
+/// This can't see a change to a field's type that keeps its name, size
+/// and alignment, e.g. a reordering of the fields of a nested struct, but
+/// does catch the usual ways a root task and a child built against
+/// different versions of a shared parameter struct disagree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParamsLayout(u64);
+
+impl ParamsLayout {
+    pub const fn of<T>() -> ParamsLayout {
+        ParamsLayout(0xcbf2_9ce4_8422_2325)
+            .write_usize(core::mem::size_of::<T>())
+            .write_usize(core::mem::align_of::<T>())
+    }
+
+    /// Add a field named `name`, of the type spelled `type_name`
+    pub const fn field<F>(self, name: &str, type_name: &str) -> ParamsLayout {
+        self.write_str(name)
+            .write_str(type_name)
+            .write_usize(core::mem::size_of::<F>())
+            .write_usize(core::mem::align_of::<F>())
+    }
+
+    pub const fn hash(self) -> u64 {
+        self.0
+    }
+
+    const fn write_str(self, s: &str) -> ParamsLayout {
+        self.write_usize(s.len()).write_bytes(s.as_bytes())
+    }
+
+    const fn write_usize(self, n: usize) -> ParamsLayout {
+        self.write_bytes(&(n as u64).to_le_bytes())
+    }
+
+    const fn write_bytes(self, bytes: &[u8]) -> ParamsLayout {
+        let mut hash = self.0;
+        let mut i = 0;
+        while i < bytes.len() {
+            hash = (hash ^ bytes[i] as u64).wrapping_mul(0x0000_0100_0000_01b3);
+            i += 1;
+        }
+        ParamsLayout(hash)
+    }
+}
+
+/// The symbol `embed_params_layout!` defines in a child's image
+pub const PARAMS_LAYOUT_SYMBOL: &str = "FERROS_PARAMS_LAYOUT_HASH";
+
+/// Embed the `PARAMS_LAYOUT_HASH` of the process parameter type `$params`
+/// in a child's ELF image, for the root task to check against its own
+/// when it sets the process up. Use it once, in the child's binary crate.
+#[macro_export]
+macro_rules! embed_params_layout {
+    ($params:ty) => {
+        #[no_mangle]
+        #[used]
+        pub static FERROS_PARAMS_LAYOUT_HASH: u64 =
+            <$params as $crate::userland::RetypeForSetup>::PARAMS_LAYOUT_HASH;
+    };
+}
+
+/// The params layout hash embedded in an ELF image by
+/// `embed_params_layout!`, if there is one. An image without a symbol
+/// table, e.g. a stripped one, has none.
+pub(crate) fn elf_params_layout_hash(
+    elf: &xmas_elf::ElfFile,
+) -> Result<Option<u64>, ProcessSetupError> {
+    use xmas_elf::sections::{SectionData, ShType};
+    use xmas_elf::symbol_table::Entry;
+
+    let symtab = match elf.find_section_by_name(".symtab") {
+        Some(s) => s,
+        None => return Ok(None),
+    };
+    let symbols = symtab
+        .get_data(elf)
+        .map_err(ProcessSetupError::ElfParseError)?;
+    let symbol = match symbols {
+        SectionData::SymbolTable64(entries) => entries
+            .iter()
+            .find(|e| e.get_name(elf) == Ok(PARAMS_LAYOUT_SYMBOL))
+            .map(|e| (e.value(), e.size())),
+        SectionData::SymbolTable32(entries) => entries
+            .iter()
+            .find(|e| e.get_name(elf) == Ok(PARAMS_LAYOUT_SYMBOL))
+            .map(|e| (e.value(), e.size())),
+        _ => return Err(ProcessSetupError::ElfParseError("Malformed symbol table")),
+    };
+    let address = match symbol {
+        Some((address, 8)) => address,
+        Some(_) => return Err(ProcessSetupError::ElfParseError("Malformed symbol table")),
+        None => return Ok(None),
+    };
+
+    // The hash is a plain word in a loaded section, needing no relocation
+    let section = elf
+        .section_iter()
+        .find(|s| {
+            s.get_type() != Ok(ShType::NoBits)
+                && s.address() <= address
+                && address + 8 <= s.address() + s.size()
+        })
+        .ok_or(ProcessSetupError::ElfParseError(
+            "Params layout hash outside any section",
+        ))?;
+    let start = (section.offset() + address - section.address()) as usize;
+    let bytes = elf
+        .input
+        .get(start..start + 8)
+        .ok_or(ProcessSetupError::ElfParseError(
+            "Params layout hash extends past end of file",
+        ))?;
+    let mut word = [0; 8];
+    word.copy_from_slice(bytes);
+    Ok(Some(u64::from_ne_bytes(word)))
+}
+
+/// How a process or thread's parameter is handed to its entry point,
+/// following the platform's C calling convention for `extern "C" fn(T)`.
+///
+/// Small parameters are passed in registers alone. Larger ones are copied
+/// onto the top of the child's stack as well, using up some of it before
+/// the entry point even runs, and so must fit in the stack. Either way the
+/// entry point receives its parameter by value as usual.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParamsPassing {
+    Registers,
+    Stack,
+}
+
+impl ParamsPassing {
+    pub const fn for_size(size_bytes: usize) -> ParamsPassing {
+        if size_bytes <= crate::arch::PARAM_REGISTER_BYTES {
+            ParamsPassing::Registers
+        } else {
+            ParamsPassing::Stack
+        }
+    }
+
+    pub const fn of<T>() -> ParamsPassing {
+        ParamsPassing::for_size(core::mem::size_of::<T>())
+    }
+
+    /// `const` equality, for `assert_params_passing!`
+    pub const fn is(self, other: ParamsPassing) -> bool {
+        self as u8 == other as u8
+    }
+}
+
+/// Fails to compile unless the process parameter type `$params` is passed
+/// as `$passing`, a `ParamsPassing` variant, e.g. to make sure a parameter
+/// meant to fit in registers doesn't quietly outgrow them.
+#[macro_export]
+macro_rules! assert_params_passing {
+    ($params:ty, $passing:ident) => {
+        const _: [(); 1] = [(); <$params as $crate::userland::RetypeForSetup>::PARAMS_PASSING
+            .is($crate::userland::ParamsPassing::$passing) as usize];
+    };
 }
 
 pub type SetupVer<X> = <X as RetypeForSetup>::Output;
@@ -58,9 +239,22 @@ pub enum ProcessSetupError {
     ProcessParameterHandoffSizeMismatch,
     NotEnoughCNodeSlots,
     ParentMappedMemoryRegionASIDShouldNotMatchChildVSpaceASID,
+    IPCBufferASIDMustMatchProcessVSpaceASID,
+    /// The process' IPC buffer can only be moved before it's first started
+    IPCBufferMovedAfterStart,
+    /// The address space left for a lazy stack didn't end up right below
+    /// the mapped part of the stack
+    LazyStackNotBelowMappedStack,
     VSpaceError(VSpaceError),
     SeL4Error(SeL4Error),
     ElfParseError(&'static str),
+    /// The process parameter's `PARAMS_LAYOUT_HASH` isn't the one the ELF
+    /// image was built with: the root task and the child disagree on
+    /// its layout
+    ParamsLayoutMismatch {
+        expected: u64,
+        found: u64,
+    },
 }
 
 impl From<VSpaceError> for ProcessSetupError {
@@ -6,8 +6,9 @@ use selfe_sys::*;
 
 use crate::arch::{self, PageBits};
 use crate::cap::{
-    role, CNodeRole, CNodeSlotsError, Cap, ChildCNode, DirectRetype, LocalCNode, LocalCNodeSlots,
-    LocalCap, ThreadControlBlock, ThreadPriorityAuthority, Untyped, WCNodeSlotsData,
+    role, CNodeRole, CNodeSlotsError, Cap, ChildCNode, DirectRetype, Domain, DomainControl,
+    LocalCNode, LocalCNodeSlots, LocalCap, ThreadControlBlock, ThreadPriorityAuthority, Untyped,
+    WCNodeSlotsData,
 };
 use crate::userland::CapRights;
 use crate::vspace::*;
@@ -21,8 +22,8 @@ pub struct SelfHostedProcess<StackBitSize: Unsigned = DefaultStackBitSize> {
 
 struct SelfHostedParams<T, Role: CNodeRole> {
     params: T,
-    vspace: VSpace<vspace_state::Imaged, Role>,
-    child_main: extern "C" fn(VSpace<vspace_state::Imaged, role::Local>, T) -> (),
+    vspace: SelfHostedVSpace<Role>,
+    child_main: extern "C" fn(SelfHostedVSpace, T) -> (),
 }
 
 #[allow(improper_ctypes_definitions)] // Not FFI-safe, see #5
@@ -41,7 +42,7 @@ impl<StackBitSize: Unsigned> SelfHostedProcess<StackBitSize> {
         cspace: LocalCap<ChildCNode>,
         parent_mapped_region: MappedMemoryRegion<StackBitSize, shared_status::Exclusive>,
         parent_cnode: &LocalCap<LocalCNode>,
-        function_descriptor: extern "C" fn(VSpace<vspace_state::Imaged, role::Local>, T) -> (),
+        function_descriptor: extern "C" fn(SelfHostedVSpace, T) -> (),
         process_parameter: SetupVer<T>,
         ipc_buffer_ut: LocalCap<Untyped<PageBits>>,
         tcb_ut: LocalCap<Untyped<<ThreadControlBlock as DirectRetype>::SizeBits>>,
@@ -102,7 +103,7 @@ impl<StackBitSize: Unsigned> SelfHostedProcess<StackBitSize> {
             cspace,
             fault_source,
             vspace.root(),
-            Some(ipc_buffer.to_page()),
+            Some(&ipc_buffer.to_page()),
         )?;
 
         // Reserve a guard page before the stack
@@ -124,7 +125,7 @@ impl<StackBitSize: Unsigned> SelfHostedProcess<StackBitSize> {
         let root_slot = cap_transfer_slots.alloc_strong().map_err(|e| match e {
             CNodeSlotsError::NotEnoughSlots => ProcessSetupError::NotEnoughCNodeSlots,
         })?;
-        let child_vspace = vspace.for_child(
+        let child_vspace = vspace.handoff_to_child(
             parent_cnode,
             root_slot,
             cap_transfer_slots,
@@ -153,8 +154,8 @@ impl<StackBitSize: Unsigned> SelfHostedProcess<StackBitSize> {
 
         local_stack_pages.flush()?;
 
-        registers.sp = stack_pointer;
-        registers.pc = self_hosted_run::<T> as usize;
+        set_thread_stack_pointer(&mut registers, stack_pointer);
+        set_thread_program_counter(&mut registers, self_hosted_run::<T> as usize);
 
         // TODO - Probably ought to suspend or destroy the thread
         // instead of endlessly yielding
@@ -182,6 +183,15 @@ impl<StackBitSize: Unsigned> SelfHostedProcess<StackBitSize> {
         })
     }
 
+    /// Place the process' thread in the scheduling domain `domain`.
+    pub fn set_domain(
+        &mut self,
+        domain_control: &LocalCap<DomainControl>,
+        domain: Domain,
+    ) -> Result<(), SeL4Error> {
+        domain_control.set_domain(&self.tcb, domain)
+    }
+
     pub fn start(self) -> Result<(), SeL4Error> {
         unsafe { seL4_TCB_Resume(self.tcb.cptr) }
             .as_result()
@@ -0,0 +1,357 @@
+//! Starting threads from within a process.
+//!
+//! `Thread::new` is for the root task, setting up a thread in a CSpace
+//! of its own. A process with a `VSpace` of its own, e.g. a
+//! `SelfHostedProcess`, can start threads sharing its CSpace and VSpace
+//! with a `ThreadAuthority` granted by whoever set it up, and the root
+//! task can start threads sharing its own with
+//! `ThreadAuthority::for_root_task`. The threads' stacks come out of a
+//! `ThreadStacks` region the process has mapped, and each returns its
+//! result through a `JoinHandle`.
+use core::mem::{align_of, size_of, MaybeUninit};
+use core::ops::Sub;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use arrayvec::ArrayVec;
+use selfe_sys::*;
+use typenum::*;
+
+use crate::arch::{NotificationBits, PageBits, TCBBits};
+use crate::cap::{
+    page_state, role, CNode, CNodeRole, Cap, ChildCNode, ChildCNodeSlot, ChildCap, InternalASID,
+    LocalCNode, LocalCNodeSlot, LocalCNodeSlots, LocalCap, Notification, Page, ThreadControlBlock,
+    ThreadPriorityAuthority, Untyped,
+};
+use crate::error::{ErrorExt, SeL4Error};
+use crate::pow::{Pow, _Pow};
+use crate::userland::CapRights;
+use crate::vspace::{shared_status, MappedMemoryRegion, VSpace, WeakMappedMemoryRegion};
+
+use super::tls::TlsImage;
+use super::*;
+
+/// The most stacks a `ThreadStacks` hands out
+pub const MAX_THREAD_STACKS: usize = 32;
+
+/// What a process needs, besides its own `VSpace`, to start threads: a
+/// reference to its own CNode, for the threads to share, and the
+/// authority to set their priority.
+pub struct ThreadAuthority<Role: CNodeRole> {
+    cnode: Cap<ChildCNode, Role>,
+    priority_authority: Cap<ThreadPriorityAuthority, Role>,
+}
+
+impl ThreadAuthority<role::Child> {
+    /// Grant the process whose CNode `self_reference` refers to, see
+    /// `generate_self_reference`, the authority to start threads, copying
+    /// `priority_authority` into `slot` of its CNode.
+    pub fn grant(
+        self_reference: ChildCap<ChildCNode>,
+        priority_authority: &LocalCap<ThreadPriorityAuthority>,
+        parent_cnode: &LocalCap<LocalCNode>,
+        slot: ChildCNodeSlot,
+    ) -> Result<Self, SeL4Error> {
+        Ok(ThreadAuthority {
+            cnode: self_reference,
+            priority_authority: priority_authority.copy(parent_cnode, slot, CapRights::RW)?,
+        })
+    }
+}
+
+impl ThreadAuthority<role::Local> {
+    /// The authority for the root task to start threads of its own,
+    /// sharing `root_cnode`, copying `priority_authority` into `slot`.
+    pub fn for_root_task(
+        root_cnode: &LocalCap<LocalCNode>,
+        priority_authority: &LocalCap<ThreadPriorityAuthority>,
+        slot: LocalCNodeSlot,
+    ) -> Result<Self, SeL4Error> {
+        Ok(ThreadAuthority {
+            cnode: Cap {
+                cptr: root_cnode.cptr,
+                cap_data: CNode {
+                    radix: root_cnode.cap_data.radix,
+                    _role: PhantomData,
+                },
+                _role: PhantomData,
+            },
+            priority_authority: priority_authority.copy(root_cnode, slot, CapRights::RW)?,
+        })
+    }
+}
+
+/// A region of memory carved into `1 << StackBitSize` byte thread stacks.
+///
+/// The lowest page of each stack is unmapped as a guard page, so a thread
+/// overflowing its stack faults rather than running into the next one;
+/// each thread has `1 << StackBitSize` bytes less a page of stack.
+pub struct ThreadStacks<StackBitSize: Unsigned> {
+    vaddr: usize,
+    asid: InternalASID,
+    free: ArrayVec<[usize; MAX_THREAD_STACKS]>,
+    _region: WeakMappedMemoryRegion<shared_status::Exclusive>,
+    _stack_bit_size: PhantomData<StackBitSize>,
+}
+
+/// A stack checked out of `ThreadStacks`, given back when its thread is
+/// joined.
+pub struct ThreadStack<StackBitSize: Unsigned> {
+    index: usize,
+    vaddr: usize,
+    _stack_bit_size: PhantomData<StackBitSize>,
+}
+
+impl<StackBitSize: Unsigned> ThreadStacks<StackBitSize> {
+    /// Carve `region`, mapped in the process' own `VSpace`, into stacks,
+    /// unmapping their guard pages. At most `MAX_THREAD_STACKS` are used.
+    pub fn new<RegionBits: Unsigned>(
+        region: MappedMemoryRegion<RegionBits, shared_status::Exclusive>,
+    ) -> Result<Self, SeL4Error>
+    where
+        // Room for the guard page and at least a page of stack
+        StackBitSize: IsGreater<PageBits, Output = True>,
+        RegionBits: IsGreaterOrEqual<StackBitSize, Output = True>,
+        RegionBits: IsGreaterOrEqual<PageBits>,
+        RegionBits: Sub<PageBits>,
+        <RegionBits as Sub<PageBits>>::Output: Unsigned,
+        <RegionBits as Sub<PageBits>>::Output: _Pow,
+        Pow<<RegionBits as Sub<PageBits>>::Output>: Unsigned,
+    {
+        let count = core::cmp::min(
+            1 << (RegionBits::USIZE - StackBitSize::USIZE),
+            MAX_THREAD_STACKS,
+        );
+        region.unmap_guard_pages(1 << (StackBitSize::USIZE - PageBits::USIZE))?;
+        Ok(ThreadStacks {
+            vaddr: region.vaddr(),
+            asid: region.asid(),
+            free: (0..count).rev().collect(),
+            _region: region.weaken(),
+            _stack_bit_size: PhantomData,
+        })
+    }
+
+    /// How many stacks are free
+    pub fn available(&self) -> usize {
+        self.free.len()
+    }
+
+    fn checkout(&mut self) -> Option<ThreadStack<StackBitSize>> {
+        let index = self.free.pop()?;
+        Some(ThreadStack {
+            index,
+            vaddr: self.vaddr + (index << StackBitSize::USIZE),
+            _stack_bit_size: PhantomData,
+        })
+    }
+
+    fn give_back(&mut self, stack: ThreadStack<StackBitSize>) {
+        // Can't overflow, the stack was checked out of this pool
+        self.free.push(stack.index);
+    }
+}
+
+/// Where a spawned thread leaves its result, at the top of its stack
+struct Completion<R> {
+    done: AtomicBool,
+    value: MaybeUninit<R>,
+}
+
+struct SpawnArgs<T, R> {
+    param: T,
+    entry: fn(T) -> R,
+    completion: *mut Completion<R>,
+    notification: usize,
+    tcb: usize,
+    ipc_buffer: usize,
+}
+
+#[allow(improper_ctypes_definitions)] // Not FFI-safe, see #5
+extern "C" fn run_spawned<T, R>(args: SpawnArgs<T, R>) {
+    let SpawnArgs {
+        param,
+        entry,
+        completion,
+        notification,
+        tcb,
+        ipc_buffer,
+    } = args;
+    // The thread pointer is set to a TLS block of the thread's own, see
+    // `TlsImage`, for libsel4 to keep the IPC buffer address in
+    unsafe { seL4_SetIPCBuffer(ipc_buffer as *mut seL4_IPCBuffer) };
+    let value = entry(param);
+    unsafe {
+        (*completion).value.as_mut_ptr().write(value);
+        (*completion).done.store(true, Ordering::Release);
+        seL4_Signal(notification);
+        seL4_TCB_Suspend(tcb);
+    }
+}
+
+impl ThreadAuthority<role::Local> {
+    /// Start a thread running `entry(param)` in this process, with a
+    /// stack from `stacks` and `ipc_buffer` as its IPC buffer, both mapped
+    /// in `vspace`, this process' own.
+    ///
+    /// The thread's TLS block, a copy of the process' own TLS segment,
+    /// goes at the top of its stack.
+    pub fn spawn<T: Send, R: Send, StackBitSize: Unsigned>(
+        &self,
+        vspace: &VSpace,
+        stacks: &mut ThreadStacks<StackBitSize>,
+        entry: fn(T) -> R,
+        param: T,
+        ipc_buffer: MappedMemoryRegion<PageBits, shared_status::Exclusive>,
+        tcb_ut: LocalCap<Untyped<TCBBits>>,
+        notification_ut: LocalCap<Untyped<NotificationBits>>,
+        slots: LocalCNodeSlots<U2>,
+    ) -> Result<JoinHandle<R, StackBitSize>, ThreadSetupError> {
+        if ipc_buffer.asid() != vspace.asid() || stacks.asid != vspace.asid() {
+            return Err(ThreadSetupError::StackRegionASIDMustMatchIPCBufferASID);
+        }
+        let tls = TlsImage::current().map_err(ThreadSetupError::ElfParseError)?;
+        let stack_bytes = 1 << StackBitSize::USIZE;
+        // Leave at least a page above the guard page for the thread to
+        // run in
+        if size_of::<Completion<R>>()
+            + align_of::<Completion<R>>()
+            + tls.area_bytes()
+            + size_of::<SpawnArgs<T, R>>()
+            > stack_bytes.saturating_sub(2 << PageBits::USIZE)
+        {
+            return Err(ThreadSetupError::ThreadParameterTooBigForStack);
+        }
+        if stacks.available() == 0 {
+            return Err(ThreadSetupError::NoThreadStackAvailable);
+        }
+
+        let (tcb_slot, notification_slot) = slots.alloc::<U1>();
+        let mut tcb: LocalCap<ThreadControlBlock> = tcb_ut.retype(tcb_slot)?;
+        let notification: LocalCap<Notification> = notification_ut.retype(notification_slot)?;
+        let stack = stacks
+            .checkout()
+            .ok_or(ThreadSetupError::NoThreadStackAvailable)?;
+
+        let stack_top = stack.vaddr + stack_bytes;
+        let completion = ((stack_top - size_of::<Completion<R>>())
+            & !(align_of::<Completion<R>>() - 1)) as *mut Completion<R>;
+        unsafe {
+            completion.write(Completion {
+                done: AtomicBool::new(false),
+                value: MaybeUninit::uninit(),
+            });
+        }
+        // The stack is mapped here as well as for the thread, they're the
+        // same address space
+        let tls_area = completion as usize - tls.area_bytes();
+        let thread_pointer = unsafe { tls.write(tls_area) };
+        let args = SpawnArgs {
+            param,
+            entry,
+            completion,
+            notification: notification.cptr,
+            tcb: tcb.cptr,
+            ipc_buffer: ipc_buffer.vaddr(),
+        };
+
+        let args_top = tls_area & !0xf;
+        let (mut registers, param_size_on_stack) = unsafe {
+            setup_initial_stack_and_regs(
+                &args as *const SpawnArgs<T, R> as *const usize,
+                size_of::<SpawnArgs<T, R>>(),
+                args_top as *mut usize,
+                args_top,
+            )
+        };
+        // The thread owns its parameter now
+        core::mem::forget(args);
+        set_thread_stack_pointer(&mut registers, args_top - param_size_on_stack);
+        set_thread_program_counter(&mut registers, run_spawned::<T, R> as usize);
+        set_thread_link_register(&mut registers, yield_forever);
+        set_thread_tls_base(&mut registers, thread_pointer);
+
+        let ipc_buffer = ipc_buffer.to_page();
+        tcb.configure(
+            Cap {
+                cptr: self.cnode.cptr,
+                cap_data: CNode {
+                    radix: self.cnode.cap_data.radix,
+                    _role: PhantomData,
+                },
+                _role: PhantomData,
+            },
+            None,
+            vspace.root(),
+            Some(&ipc_buffer),
+        )?;
+        unsafe {
+            seL4_TCB_WriteRegisters(
+                tcb.cptr,
+                0,
+                0,
+                // all the regs
+                size_of::<seL4_UserContext>() / size_of::<usize>(),
+                &mut registers,
+            )
+            .as_result()
+            .map_err(|e| ThreadSetupError::SeL4Error(SeL4Error::TCBWriteRegisters(e)))?;
+        }
+        tcb.set_priority(&self.priority_authority, 255)?;
+        unsafe { seL4_TCB_Resume(tcb.cptr) }
+            .as_result()
+            .map_err(|e| ThreadSetupError::SeL4Error(SeL4Error::TCBResume(e)))?;
+
+        Ok(JoinHandle {
+            completion,
+            notification,
+            stack,
+            _tcb: tcb,
+            _ipc_buffer: ipc_buffer,
+        })
+    }
+}
+
+/// A thread started by `ThreadAuthority::spawn`, for collecting its
+/// result.
+///
+/// Joining gives the thread's stack back, but its TCB, notification and
+/// IPC buffer stay allocated.
+pub struct JoinHandle<R, StackBitSize: Unsigned> {
+    completion: *mut Completion<R>,
+    notification: LocalCap<Notification>,
+    stack: ThreadStack<StackBitSize>,
+    _tcb: LocalCap<ThreadControlBlock>,
+    _ipc_buffer: LocalCap<Page<page_state::Mapped>>,
+}
+
+impl<R, StackBitSize: Unsigned> JoinHandle<R, StackBitSize> {
+    /// Whether the thread has returned
+    pub fn is_finished(&self) -> bool {
+        unsafe { (*self.completion).done.load(Ordering::Acquire) }
+    }
+
+    /// Wait for the thread to return, and take its result, giving its
+    /// stack back to `stacks`.
+    pub fn join(self, stacks: &mut ThreadStacks<StackBitSize>) -> R {
+        while !self.is_finished() {
+            self.notification.wait();
+        }
+        self.take(stacks)
+    }
+
+    /// Take the thread's result if it has returned, otherwise hand the
+    /// handle back.
+    pub fn try_join(self, stacks: &mut ThreadStacks<StackBitSize>) -> Result<R, Self> {
+        if self.is_finished() {
+            Ok(self.take(stacks))
+        } else {
+            Err(self)
+        }
+    }
+
+    fn take(self, stacks: &mut ThreadStacks<StackBitSize>) -> R {
+        let value = unsafe { (*self.completion).value.as_ptr().read() };
+        stacks.give_back(self.stack);
+        value
+    }
+}
@@ -2,6 +2,7 @@ use crate::arch::{self, *};
 use crate::cap::*;
 use crate::pow::{Pow, _Pow};
 use crate::userland::rights::CapRights;
+use crate::userland::LazyStack;
 use crate::vspace::*;
 use core::ops::{Add, Sub};
 
@@ -22,9 +23,51 @@ use super::*;
 ///  * An IPC buffer and CSpace and fault handler associated with that TCB.
 pub struct StandardProcess<StackBitSize: Unsigned = DefaultStackBitSize> {
     tcb: LocalCap<ThreadControlBlock>,
+    ipc_buffer: LocalCap<Page<page_state::Mapped>>,
+    resources: SetupResources,
+    /// Whether `start` has ever been called
+    started: bool,
     _stack_bit_size: PhantomData<StackBitSize>,
 }
 
+/// Where the resources a process was made from went, for taking them
+/// back when the process is stopped
+struct SetupResources {
+    cspace: LocalCap<ChildCNode>,
+    /// The parent's mapping of the stack
+    parent_stack: WeakMappedMemoryRegion<shared_status::Shared>,
+    ipc_buffer_ut: usize,
+    /// Whether the thread's been moved off the IPC buffer made from
+    /// `ipc_buffer_ut`, which then stays mapped until the untyped's revoked
+    ipc_buffer_replaced: bool,
+    tcb_ut: usize,
+    /// The start of the slots given to `new`
+    slots_offset: usize,
+}
+
+/// The resources a stopped `StandardProcess` was made from, the same as
+/// were given to `StandardProcess::new` and ready to make another
+pub struct ReclaimedProcess<StackBitSize: Unsigned>
+where
+    StackBitSize: IsGreaterOrEqual<PageBits>,
+    StackBitSize: Sub<PageBits>,
+    <StackBitSize as Sub<PageBits>>::Output: Unsigned,
+    <StackBitSize as Sub<PageBits>>::Output: _Pow,
+    Pow<<StackBitSize as Sub<PageBits>>::Output>: Unsigned,
+    NumPages<StackBitSize>: Add<U2>,
+    Sum<NumPages<StackBitSize>, U2>: Unsigned,
+{
+    /// Emptied of everything the process had in it
+    pub cspace: LocalCap<ChildCNode>,
+    pub parent_mapped_region: MappedMemoryRegion<StackBitSize, shared_status::Exclusive>,
+    pub ipc_buffer_ut: LocalCap<Untyped<PageBits>>,
+    /// The IPC buffer last given to `set_ipc_buffer`, if there was one,
+    /// still mapped into the process' vspace
+    pub ipc_buffer: Option<MappedMemoryRegion<PageBits, shared_status::Exclusive>>,
+    pub tcb_ut: LocalCap<Untyped<<ThreadControlBlock as DirectRetype>::SizeBits>>,
+    pub slots: LocalCNodeSlots<Sum<NumPages<StackBitSize>, U2>>,
+}
+
 pub enum EntryPoint<'a, T> {
     Fork(extern "C" fn(T) -> ()),
     Elf(&'a [u8]),
@@ -48,6 +91,9 @@ impl<'a, T> From<&'a [u8]> for EntryPoint<'a, T> {
 }
 
 impl<StackBitSize: Unsigned> StandardProcess<StackBitSize> {
+    /// The priority every standard process runs at
+    pub const PRIORITY: usize = 255;
+
     pub fn new<'a, T: RetypeForSetup, EP: Into<EntryPoint<'a, T>>>(
         vspace: &mut VSpace,
         cspace: LocalCap<ChildCNode>,
@@ -69,6 +115,106 @@ impl<StackBitSize: Unsigned> StandardProcess<StackBitSize> {
         Diff<Sum<NumPages<StackBitSize>, U2>, U2>: Unsigned,
         Diff<Sum<NumPages<StackBitSize>, U2>, U2>: IsEqual<NumPages<StackBitSize>, Output = True>,
 
+        StackBitSize: IsGreaterOrEqual<PageBits>,
+        StackBitSize: Sub<PageBits>,
+        <StackBitSize as Sub<PageBits>>::Output: Unsigned,
+        <StackBitSize as Sub<PageBits>>::Output: _Pow,
+        Pow<<StackBitSize as Sub<PageBits>>::Output>: Unsigned,
+    {
+        Self::setup(
+            vspace,
+            cspace,
+            parent_mapped_region,
+            parent_cnode,
+            entry_point,
+            process_parameter,
+            ipc_buffer_ut,
+            tcb_ut,
+            slots,
+            priority_authority,
+            fault_source,
+            None,
+        )
+        .map(|(process, _)| process)
+    }
+
+    /// Set up a process as `new` does, but with `parent_mapped_region` as
+    /// only the top of its stack. Below it, `2^LazyStackBitSize` bytes of
+    /// address space are left unmapped for a `StackPager` handling the
+    /// process' faults to map pages into as the stack grows.
+    pub fn new_with_lazy_stack<
+        'a,
+        T: RetypeForSetup,
+        EP: Into<EntryPoint<'a, T>>,
+        LazyStackBitSize: Unsigned,
+    >(
+        vspace: &mut VSpace,
+        cspace: LocalCap<ChildCNode>,
+        parent_mapped_region: MappedMemoryRegion<StackBitSize, shared_status::Exclusive>,
+        parent_cnode: &LocalCap<LocalCNode>,
+        entry_point: EP,
+        process_parameter: SetupVer<T>,
+        ipc_buffer_ut: LocalCap<Untyped<PageBits>>,
+        tcb_ut: LocalCap<Untyped<<ThreadControlBlock as DirectRetype>::SizeBits>>,
+        slots: LocalCNodeSlots<Sum<NumPages<StackBitSize>, U2>>,
+        priority_authority: &LocalCap<ThreadPriorityAuthority>,
+        fault_source: Option<crate::userland::FaultSource<role::Child>>,
+    ) -> Result<(StandardProcess<StackBitSize>, LazyStack), ProcessSetupError>
+    where
+        LazyStackBitSize: IsGreaterOrEqual<PageBits, Output = True>,
+        NumPages<StackBitSize>: Add<U2>,
+        Sum<NumPages<StackBitSize>, U2>: Unsigned,
+
+        Sum<NumPages<StackBitSize>, U2>: Sub<U2>,
+        Diff<Sum<NumPages<StackBitSize>, U2>, U2>: Unsigned,
+        Diff<Sum<NumPages<StackBitSize>, U2>, U2>: IsEqual<NumPages<StackBitSize>, Output = True>,
+
+        StackBitSize: IsGreaterOrEqual<PageBits>,
+        StackBitSize: Sub<PageBits>,
+        <StackBitSize as Sub<PageBits>>::Output: Unsigned,
+        <StackBitSize as Sub<PageBits>>::Output: _Pow,
+        Pow<<StackBitSize as Sub<PageBits>>::Output>: Unsigned,
+    {
+        let (process, lazy_stack) = Self::setup(
+            vspace,
+            cspace,
+            parent_mapped_region,
+            parent_cnode,
+            entry_point,
+            process_parameter,
+            ipc_buffer_ut,
+            tcb_ut,
+            slots,
+            priority_authority,
+            fault_source,
+            Some(LazyStackBitSize::U8),
+        )?;
+        // Always set up when asked for
+        Ok((process, lazy_stack.unwrap()))
+    }
+
+    fn setup<'a, T: RetypeForSetup, EP: Into<EntryPoint<'a, T>>>(
+        vspace: &mut VSpace,
+        cspace: LocalCap<ChildCNode>,
+        parent_mapped_region: MappedMemoryRegion<StackBitSize, shared_status::Exclusive>,
+        parent_cnode: &LocalCap<LocalCNode>,
+        entry_point: EP,
+        process_parameter: SetupVer<T>,
+        ipc_buffer_ut: LocalCap<Untyped<PageBits>>,
+        tcb_ut: LocalCap<Untyped<<ThreadControlBlock as DirectRetype>::SizeBits>>,
+        slots: LocalCNodeSlots<Sum<NumPages<StackBitSize>, U2>>,
+        priority_authority: &LocalCap<ThreadPriorityAuthority>,
+        fault_source: Option<crate::userland::FaultSource<role::Child>>,
+        lazy_stack_bits: Option<u8>,
+    ) -> Result<(StandardProcess<StackBitSize>, Option<LazyStack>), ProcessSetupError>
+    where
+        NumPages<StackBitSize>: Add<U2>,
+        Sum<NumPages<StackBitSize>, U2>: Unsigned,
+
+        Sum<NumPages<StackBitSize>, U2>: Sub<U2>,
+        Diff<Sum<NumPages<StackBitSize>, U2>, U2>: Unsigned,
+        Diff<Sum<NumPages<StackBitSize>, U2>, U2>: IsEqual<NumPages<StackBitSize>, Output = True>,
+
         StackBitSize: IsGreaterOrEqual<PageBits>,
         StackBitSize: Sub<PageBits>,
         <StackBitSize as Sub<PageBits>>::Output: Unsigned,
@@ -77,12 +223,27 @@ impl<StackBitSize: Unsigned> StandardProcess<StackBitSize> {
     {
         let entry_point = entry_point.into();
 
+        // A child built against a different version of its parameter
+        // type would read garbage out of it
+        if let EntryPoint::Elf(elf_data) = &entry_point {
+            let elf = xmas_elf::ElfFile::new(elf_data).map_err(ProcessSetupError::ElfParseError)?;
+            if let Some(found) = elf_params_layout_hash(&elf)? {
+                if found != T::PARAMS_LAYOUT_HASH {
+                    return Err(ProcessSetupError::ParamsLayoutMismatch {
+                        expected: T::PARAMS_LAYOUT_HASH,
+                        found,
+                    });
+                }
+            }
+        }
+
         if parent_mapped_region.asid() == vspace.asid() {
             return Err(
                 ProcessSetupError::ParentMappedMemoryRegionASIDShouldNotMatchChildVSpaceASID,
             );
         }
 
+        let slots_offset = slots.cap_data.offset;
         let (misc_slots, stack_slots) = slots.alloc::<U2>();
         // TODO - lift these checks to compile-time, as static assertions
         // Note - This comparison is conservative because technically
@@ -96,6 +257,10 @@ impl<StackBitSize: Unsigned> StandardProcess<StackBitSize> {
 
         // Reserve a guard page before the stack
         vspace.skip_pages(1)?;
+        let lazy_stack_bottom = match lazy_stack_bits {
+            Some(bits) => Some(vspace.skip_range(bits)?),
+            None => None,
+        };
 
         // Map the stack to the target address space
         let stack_top = parent_mapped_region.vaddr() + parent_mapped_region.size_bytes();
@@ -107,6 +272,22 @@ impl<StackBitSize: Unsigned> StandardProcess<StackBitSize> {
             arch::vm_attributes::DEFAULT | arch::vm_attributes::EXECUTE_NEVER,
         )?;
 
+        // The stack has to carry on growing straight down into the lazy
+        // part
+        let lazy_stack = match (lazy_stack_bits, lazy_stack_bottom) {
+            (Some(bits), Some(bottom)) => {
+                if bottom + (1 << bits) != mapped_stack_pages.vaddr() {
+                    return Err(ProcessSetupError::LazyStackNotBelowMappedStack);
+                }
+                Some(LazyStack {
+                    bottom,
+                    top: mapped_stack_pages.vaddr(),
+                    asid: vspace.asid(),
+                })
+            }
+            _ => None,
+        };
+
         // map the child stack into local memory so we can copy the contents
         // of the process params into it
         let (mut registers, param_size_on_stack) = unsafe {
@@ -119,20 +300,22 @@ impl<StackBitSize: Unsigned> StandardProcess<StackBitSize> {
         };
 
         local_stack_pages.flush()?;
+        let parent_stack = local_stack_pages.weaken();
 
         let stack_pointer =
             mapped_stack_pages.vaddr() + mapped_stack_pages.size_bytes() - param_size_on_stack;
 
-        registers.sp = stack_pointer;
+        set_thread_stack_pointer(&mut registers, stack_pointer);
 
-        registers.pc = match entry_point {
+        let program_counter = match entry_point {
             EntryPoint::Fork(f) => f as usize,
             EntryPoint::Elf(elf_data) => {
                 let elf =
                     xmas_elf::ElfFile::new(elf_data).map_err(ProcessSetupError::ElfParseError)?;
-                elf.header.pt2.entry_point() as usize
+                elf_entry_point(&elf)
             }
         };
+        set_thread_program_counter(&mut registers, program_counter);
 
         // TODO - Probably ought to suspend or destroy the thread instead of endlessly
         // yielding
@@ -146,6 +329,7 @@ impl<StackBitSize: Unsigned> StandardProcess<StackBitSize> {
 
         // Allocate and map the ipc buffer
         let (ipc_slots, misc_slots) = misc_slots.alloc();
+        let ipc_buffer_ut_cptr = ipc_buffer_ut.cptr;
         let ipc_buffer = ipc_buffer_ut.retype(ipc_slots)?;
         let ipc_buffer = vspace.map_region(
             ipc_buffer.to_region(),
@@ -155,14 +339,20 @@ impl<StackBitSize: Unsigned> StandardProcess<StackBitSize> {
 
         //// allocate the thread control block
         let (tcb_slots, _slots) = misc_slots.alloc();
+        let tcb_ut_cptr = tcb_ut.cptr;
         let mut tcb = tcb_ut.retype(tcb_slots)?;
 
-        tcb.configure(
-            cspace,
-            fault_source,
-            vspace.root(),
-            Some(ipc_buffer.to_page()),
-        )?;
+        let ipc_buffer = ipc_buffer.to_page();
+        // The TCB takes its own copy of the cspace root
+        let cspace_cap = Cap {
+            cptr: cspace.cptr,
+            cap_data: CNode {
+                radix: cspace.cap_data.radix,
+                _role: PhantomData,
+            },
+            _role: PhantomData,
+        };
+        tcb.configure(cspace, fault_source, vspace.root(), Some(&ipc_buffer))?;
         unsafe {
             seL4_TCB_WriteRegisters(
                 tcb.cptr,
@@ -177,12 +367,82 @@ impl<StackBitSize: Unsigned> StandardProcess<StackBitSize> {
 
             // TODO - priority management could be exposed once we
             // plan on actually using it
-            tcb.set_priority(priority_authority, 255)?;
+            tcb.set_priority(priority_authority, Self::PRIORITY)?;
         }
-        Ok(StandardProcess {
-            tcb,
-            _stack_bit_size: PhantomData,
-        })
+        Ok((
+            StandardProcess {
+                tcb,
+                ipc_buffer,
+                resources: SetupResources {
+                    cspace: cspace_cap,
+                    parent_stack,
+                    ipc_buffer_ut: ipc_buffer_ut_cptr,
+                    ipc_buffer_replaced: false,
+                    tcb_ut: tcb_ut_cptr,
+                    slots_offset,
+                },
+                started: false,
+                _stack_bit_size: PhantomData,
+            },
+            lazy_stack,
+        ))
+    }
+
+    /// Set up a process as `new` does, to run on `sched_context` once it's
+    /// started. On an MCS kernel a process without a scheduling context
+    /// never runs.
+    #[cfg(KernelIsMCS)]
+    pub fn new_with_sched_context<
+        'a,
+        T: RetypeForSetup,
+        EP: Into<EntryPoint<'a, T>>,
+        BudgetMicros: Unsigned,
+        PeriodMicros: Unsigned,
+    >(
+        vspace: &mut VSpace,
+        cspace: LocalCap<ChildCNode>,
+        parent_mapped_region: MappedMemoryRegion<StackBitSize, shared_status::Exclusive>,
+        parent_cnode: &LocalCap<LocalCNode>,
+        entry_point: EP,
+        process_parameter: SetupVer<T>,
+        ipc_buffer_ut: LocalCap<Untyped<PageBits>>,
+        tcb_ut: LocalCap<Untyped<<ThreadControlBlock as DirectRetype>::SizeBits>>,
+        slots: LocalCNodeSlots<Sum<NumPages<StackBitSize>, U2>>,
+        priority_authority: &LocalCap<ThreadPriorityAuthority>,
+        fault_source: Option<crate::userland::FaultSource<role::Child>>,
+        sched_context: &LocalCap<
+            SchedContext<sched_context_state::Configured<BudgetMicros, PeriodMicros>>,
+        >,
+    ) -> Result<StandardProcess<StackBitSize>, ProcessSetupError>
+    where
+        NumPages<StackBitSize>: Add<U2>,
+        Sum<NumPages<StackBitSize>, U2>: Unsigned,
+
+        Sum<NumPages<StackBitSize>, U2>: Sub<U2>,
+        Diff<Sum<NumPages<StackBitSize>, U2>, U2>: Unsigned,
+        Diff<Sum<NumPages<StackBitSize>, U2>, U2>: IsEqual<NumPages<StackBitSize>, Output = True>,
+
+        StackBitSize: IsGreaterOrEqual<PageBits>,
+        StackBitSize: Sub<PageBits>,
+        <StackBitSize as Sub<PageBits>>::Output: Unsigned,
+        <StackBitSize as Sub<PageBits>>::Output: _Pow,
+        Pow<<StackBitSize as Sub<PageBits>>::Output>: Unsigned,
+    {
+        let process = Self::new(
+            vspace,
+            cspace,
+            parent_mapped_region,
+            parent_cnode,
+            entry_point,
+            process_parameter,
+            ipc_buffer_ut,
+            tcb_ut,
+            slots,
+            priority_authority,
+            fault_source,
+        )?;
+        sched_context.bind_tcb(&process.tcb)?;
+        Ok(process)
     }
 
     pub fn set_name(&mut self, name: &str) {
@@ -205,10 +465,173 @@ impl<StackBitSize: Unsigned> StandardProcess<StackBitSize> {
             .map_err(SeL4Error::TCBBindNotification)
     }
 
+    /// Move the process' thread to a different IPC buffer, mapped into
+    /// the process' vspace, handing back the buffer it replaces so it can
+    /// be unmapped or reused.
+    ///
+    /// The buffer made by `new` isn't handed back, as it's destroyed along
+    /// with its untyped when the process is reclaimed; it stays with the
+    /// process until then.
+    ///
+    /// Only a process that hasn't been started can be moved: once it's
+    /// running, libsel4 in the process has the old buffer's address, and
+    /// nothing would tell it about the new one.
+    pub fn set_ipc_buffer(
+        &mut self,
+        ipc_buffer: MappedMemoryRegion<PageBits, shared_status::Exclusive>,
+    ) -> Result<Option<MappedMemoryRegion<PageBits, shared_status::Exclusive>>, ProcessSetupError>
+    {
+        if self.started {
+            return Err(ProcessSetupError::IPCBufferMovedAfterStart);
+        }
+        if ipc_buffer.asid() != self.ipc_buffer.cap_data.state.asid {
+            return Err(ProcessSetupError::IPCBufferASIDMustMatchProcessVSpaceASID);
+        }
+        let ipc_buffer = ipc_buffer.to_page();
+        self.tcb.set_ipc_buffer(&ipc_buffer)?;
+        let replaced = core::mem::replace(&mut self.ipc_buffer, ipc_buffer);
+        if self.resources.ipc_buffer_replaced {
+            Ok(Some(replaced.to_region()))
+        } else {
+            self.resources.ipc_buffer_replaced = true;
+            Ok(None)
+        }
+    }
+
+    /// Place the process' thread in the scheduling domain `domain`.
+    pub fn set_domain(
+        &mut self,
+        domain_control: &LocalCap<DomainControl>,
+        domain: Domain,
+    ) -> Result<(), SeL4Error> {
+        domain_control.set_domain(&self.tcb, domain)
+    }
+
     pub fn start(&mut self) -> Result<(), SeL4Error> {
         unsafe { seL4_TCB_Resume(self.tcb.cptr) }
             .as_result()
-            .map_err(SeL4Error::TCBResume)
+            .map_err(SeL4Error::TCBResume)?;
+        self.started = true;
+        Ok(())
+    }
+
+    pub fn stop(&mut self) -> Result<(), SeL4Error> {
+        unsafe { seL4_TCB_Suspend(self.tcb.cptr) }
+            .as_result()
+            .map_err(SeL4Error::TCBSuspend)
+    }
+
+    /// Stop the process for good and take back what it was made from, so
+    /// that a supervisor can start it afresh, e.g. after it crashed.
+    ///
+    /// The thread is suspended and destroyed along with its IPC buffer,
+    /// every cap in its cspace is deleted, and its copies of the stack
+    /// pages are deleted, unmapping them from its vspace. Anything else
+    /// mapped into the vspace, the code image included, stays mapped;
+    /// a restart that needs a fresh image needs a fresh vspace as well.
+    ///
+    /// An IPC buffer given to `set_ipc_buffer` isn't the process' to
+    /// destroy, the one in use when it's stopped is handed back in the
+    /// `ReclaimedProcess`, still mapped.
+    pub fn stop_and_reclaim(
+        self,
+        parent_cnode: &LocalCap<LocalCNode>,
+    ) -> Result<ReclaimedProcess<StackBitSize>, ProcessSetupError>
+    where
+        StackBitSize: IsGreaterOrEqual<PageBits>,
+        StackBitSize: Sub<PageBits>,
+        <StackBitSize as Sub<PageBits>>::Output: Unsigned,
+        <StackBitSize as Sub<PageBits>>::Output: _Pow,
+        Pow<<StackBitSize as Sub<PageBits>>::Output>: Unsigned,
+        NumPages<StackBitSize>: Add<U2>,
+        Sum<NumPages<StackBitSize>, U2>: Unsigned,
+    {
+        self.reclaim(parent_cnode, true)
+    }
+
+    /// As `stop_and_reclaim`, optionally leaving the cspace as it is for
+    /// a restart to pick up with the same caps
+    pub(crate) fn reclaim(
+        mut self,
+        parent_cnode: &LocalCap<LocalCNode>,
+        empty_cspace: bool,
+    ) -> Result<ReclaimedProcess<StackBitSize>, ProcessSetupError>
+    where
+        StackBitSize: IsGreaterOrEqual<PageBits>,
+        StackBitSize: Sub<PageBits>,
+        <StackBitSize as Sub<PageBits>>::Output: Unsigned,
+        <StackBitSize as Sub<PageBits>>::Output: _Pow,
+        Pow<<StackBitSize as Sub<PageBits>>::Output>: Unsigned,
+        NumPages<StackBitSize>: Add<U2>,
+        Sum<NumPages<StackBitSize>, U2>: Unsigned,
+    {
+        self.stop()?;
+        let SetupResources {
+            cspace,
+            parent_stack,
+            ipc_buffer_ut,
+            ipc_buffer_replaced,
+            tcb_ut,
+            slots_offset,
+        } = self.resources;
+        let ipc_buffer = if ipc_buffer_replaced {
+            Some(self.ipc_buffer.to_region())
+        } else {
+            None
+        };
+
+        // Retyped objects only ever live in the slots given to `new`, so
+        // revoking the untypeds empties the first two of them
+        for ut in [tcb_ut, ipc_buffer_ut].iter() {
+            unsafe {
+                seL4_CNode_Revoke(
+                    parent_cnode.cptr,   // _service
+                    *ut,                 // index
+                    seL4_WordBits as u8, // depth
+                )
+            }
+            .as_result()
+            .map_err(SeL4Error::CNodeRevoke)?;
+        }
+
+        // and the rest hold the process' copies of the stack pages
+        for offset in slots_offset + 2..slots_offset + 2 + <NumPages<StackBitSize>>::USIZE {
+            unsafe {
+                seL4_CNode_Delete(
+                    parent_cnode.cptr,   // _service
+                    offset,              // index
+                    seL4_WordBits as u8, // depth
+                )
+            }
+            .as_result()
+            .map_err(SeL4Error::CNodeDelete)?;
+        }
+
+        let cspace_slots = if empty_cspace {
+            1usize << cspace.cap_data.radix
+        } else {
+            0
+        };
+        for slot in 0..cspace_slots {
+            unsafe {
+                seL4_CNode_Delete(
+                    cspace.cptr,           // _service
+                    slot,                  // index
+                    cspace.cap_data.radix, // depth
+                )
+            }
+            .as_result()
+            .map_err(SeL4Error::CNodeDelete)?;
+        }
+
+        Ok(ReclaimedProcess {
+            cspace,
+            parent_mapped_region: parent_stack.unchecked_to_exclusive().as_strong()?,
+            ipc_buffer_ut: Cap::wrap_cptr(ipc_buffer_ut),
+            ipc_buffer,
+            tcb_ut: Cap::wrap_cptr(tcb_ut),
+            slots: Cap::internal_new(parent_cnode.cptr, slots_offset),
+        })
     }
 
     pub fn elim(self) -> usize {
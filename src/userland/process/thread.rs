@@ -14,17 +14,25 @@ use super::*;
 /// A thread in Ferros is a TCB associated with a parent VSpace
 /// that has:
 ///  * A usable code image mapped/written into it.
-///  * A mapped stack.
+///  * A mapped stack, with a guard page at its bottom.
 ///  * Initial process state (e.g. parameter data) written into a
 ///    `seL4_UserContext` and/or its stack.
 ///  * Said seL4_UserContext written into the TCB.
 ///  * An IPC buffer and CSpace and fault handler associated with that TCB.
 pub struct Thread<StackBitSize: Unsigned = DefaultStackBitSize> {
     tcb: LocalCap<ThreadControlBlock>,
+    ipc_buffer: LocalCap<Page<page_state::Mapped>>,
     _stack_bit_size: PhantomData<StackBitSize>,
 }
 
 impl<StackBitSize: Unsigned> Thread<StackBitSize> {
+    /// Set up a thread running `function_descriptor(process_parameter)`
+    /// on `stack_region`.
+    ///
+    /// The stack region's lowest page is unmapped to serve as a guard
+    /// page, so a thread overflowing its stack faults instead of running
+    /// into whatever is mapped below it; the thread has
+    /// `2^StackBitSize` bytes less a page of stack.
     pub fn new<T: RetypeForSetup>(
         virtual_address_space_root: &LocalCap<crate::arch::PagingRoot>,
         cspace: LocalCap<ChildCNode>,
@@ -38,6 +46,8 @@ impl<StackBitSize: Unsigned> Thread<StackBitSize> {
         fault_source: Option<crate::userland::FaultSource<role::Child>>,
     ) -> Result<Thread<StackBitSize>, ThreadSetupError>
     where
+        // Room for the guard page and at least a page of stack
+        StackBitSize: IsGreater<PageBits, Output = True>,
         StackBitSize: IsGreaterOrEqual<PageBits>,
         StackBitSize: Sub<PageBits>,
         <StackBitSize as Sub<PageBits>>::Output: Unsigned,
@@ -50,13 +60,17 @@ impl<StackBitSize: Unsigned> Thread<StackBitSize> {
         // TODO - lift these checks to compile-time, as static assertions
         // Note - This comparison is conservative because technically
         // we can fit some of the params into available registers.
-        if core::mem::size_of::<SetupVer<T>>() > 2usize.pow(StackBitSize::U32) {
+        if core::mem::size_of::<SetupVer<T>>()
+            > 2usize.pow(StackBitSize::U32) - 2usize.pow(PageBits::U32)
+        {
             return Err(ThreadSetupError::ThreadParameterTooBigForStack);
         }
         if core::mem::size_of::<SetupVer<T>>() != core::mem::size_of::<T>() {
             return Err(ThreadSetupError::ThreadParameterHandoffSizeMismatch);
         }
 
+        stack_region.unmap_guard_pages(NumPages::<StackBitSize>::USIZE)?;
+
         // Map the stack to the target address space
         let stack_top = stack_region.vaddr() + stack_region.size_bytes();
         let mapped_stack_pages = stack_region;
@@ -75,8 +89,8 @@ impl<StackBitSize: Unsigned> Thread<StackBitSize> {
         let stack_pointer =
             mapped_stack_pages.vaddr() + mapped_stack_pages.size_bytes() - param_size_on_stack;
 
-        registers.sp = stack_pointer;
-        registers.pc = function_descriptor as usize;
+        set_thread_stack_pointer(&mut registers, stack_pointer);
+        set_thread_program_counter(&mut registers, function_descriptor as usize);
 
         // TODO - Probably ought to suspend or destroy the thread instead of endlessly
         // yielding
@@ -86,11 +100,12 @@ impl<StackBitSize: Unsigned> Thread<StackBitSize> {
         let (tcb_slots, _slots) = slots.alloc();
         let mut tcb = tcb_ut.retype(tcb_slots)?;
 
+        let ipc_buffer = ipc_buffer.to_page();
         tcb.configure(
             cspace,
             fault_source,
             virtual_address_space_root,
-            Some(ipc_buffer.to_page()),
+            Some(&ipc_buffer),
         )?;
         unsafe {
             seL4_TCB_WriteRegisters(
@@ -110,10 +125,38 @@ impl<StackBitSize: Unsigned> Thread<StackBitSize> {
         }
         Ok(Thread {
             tcb,
+            ipc_buffer,
             _stack_bit_size: PhantomData,
         })
     }
 
+    /// Move the thread to a different IPC buffer, mapped into the same
+    /// address space as the current one, handing back the buffer it
+    /// replaces.
+    ///
+    /// As `start` consumes the thread, this can only be done before it
+    /// runs; a running thread's libsel4 wouldn't know about the move.
+    pub fn set_ipc_buffer(
+        &mut self,
+        ipc_buffer: MappedMemoryRegion<PageBits, shared_status::Exclusive>,
+    ) -> Result<MappedMemoryRegion<PageBits, shared_status::Exclusive>, ThreadSetupError> {
+        if ipc_buffer.asid() != self.ipc_buffer.cap_data.state.asid {
+            return Err(ThreadSetupError::IPCBufferASIDMustMatchThreadASID);
+        }
+        let ipc_buffer = ipc_buffer.to_page();
+        self.tcb.set_ipc_buffer(&ipc_buffer)?;
+        Ok(core::mem::replace(&mut self.ipc_buffer, ipc_buffer).to_region())
+    }
+
+    /// Place the thread in the scheduling domain `domain`.
+    pub fn set_domain(
+        &mut self,
+        domain_control: &LocalCap<DomainControl>,
+        domain: Domain,
+    ) -> Result<(), SeL4Error> {
+        domain_control.set_domain(&self.tcb, domain)
+    }
+
     pub fn start(self) -> Result<(), SeL4Error> {
         unsafe { seL4_TCB_Resume(self.tcb.cptr) }
             .as_result()
@@ -125,6 +168,12 @@ pub enum ThreadSetupError {
     ThreadParameterTooBigForStack,
     ThreadParameterHandoffSizeMismatch,
     StackRegionASIDMustMatchIPCBufferASID,
+    IPCBufferASIDMustMatchThreadASID,
+    /// Every stack in the `ThreadStacks` is in use
+    NoThreadStackAvailable,
+    /// The running image's own program headers, which its TLS segment is
+    /// found through, couldn't be parsed
+    ElfParseError(&'static str),
     SeL4Error(SeL4Error),
 }
 
@@ -0,0 +1,125 @@
+//! Static thread-local storage for threads started with
+//! `ThreadAuthority::spawn`.
+//!
+//! libsel4 keeps the calling thread's IPC buffer address in a thread-local,
+//! so a thread needs a TLS block of its own, and its thread pointer set to
+//! it, before it can make any call that goes through its IPC buffer. Each
+//! spawned thread gets a fresh copy of the running image's TLS segment,
+//! found through the image's own program headers.
+use core::mem::size_of;
+use core::ptr;
+use core::slice;
+
+use xmas_elf::header;
+use xmas_elf::program::Type;
+use xmas_elf::ElfFile;
+
+extern "C" {
+    /// The running image's ELF header, placed by the linker
+    static __ehdr_start: u8;
+}
+
+const ELF_HEADER_BYTES: usize = if size_of::<usize>() == 8 { 64 } else { 52 };
+
+/// The block the thread pointer points at, ahead of the TLS block (TLS
+/// variant I)
+#[cfg(any(target_arch = "arm", target_arch = "aarch64"))]
+const THREAD_CONTROL_BLOCK_BYTES: usize = 2 * size_of::<usize>();
+
+fn align_up(addr: usize, align: usize) -> usize {
+    (addr + align - 1) & !(align - 1)
+}
+
+/// The running image's TLS segment, copied into each new thread's TLS
+/// block
+pub(crate) struct TlsImage {
+    /// The initialized part, the rest of the block is zeroed
+    data: &'static [u8],
+    size: usize,
+    align: usize,
+}
+
+impl TlsImage {
+    /// The running image's TLS segment, empty if it hasn't got one
+    pub(crate) fn current() -> Result<Self, &'static str> {
+        let ehdr = unsafe { &__ehdr_start as *const u8 };
+        let header =
+            header::parse_header(unsafe { slice::from_raw_parts(ehdr, ELF_HEADER_BYTES) })?;
+        let headers_end = header.pt2.ph_offset() as usize
+            + header.pt2.ph_count() as usize * header.pt2.ph_entry_size() as usize;
+        let elf = ElfFile::new(unsafe { slice::from_raw_parts(ehdr, headers_end) })?;
+
+        // The header is at the start of the segment loaded from the start
+        // of the file, wherever the image was loaded
+        let first_load = elf
+            .program_iter()
+            .find(|h| h.get_type() == Ok(Type::Load))
+            .ok_or("Image has no loadable segment")?;
+        let load_bias = ehdr as usize - (first_load.virtual_addr() - first_load.offset()) as usize;
+
+        let tls = match elf.program_iter().find(|h| h.get_type() == Ok(Type::Tls)) {
+            Some(tls) => tls,
+            None => {
+                return Ok(TlsImage {
+                    data: &[],
+                    size: 0,
+                    align: size_of::<usize>(),
+                })
+            }
+        };
+        Ok(TlsImage {
+            data: unsafe {
+                slice::from_raw_parts(
+                    (tls.virtual_addr() as usize + load_bias) as *const u8,
+                    tls.file_size() as usize,
+                )
+            },
+            size: tls.mem_size() as usize,
+            align: core::cmp::max(tls.align() as usize, size_of::<usize>()),
+        })
+    }
+
+    /// How many bytes `write` needs
+    #[cfg(any(target_arch = "arm", target_arch = "aarch64"))]
+    pub(crate) fn area_bytes(&self) -> usize {
+        self.align - 1 + align_up(THREAD_CONTROL_BLOCK_BYTES, self.align) + self.size
+    }
+
+    /// Lay out a thread's TLS in the `area_bytes` at `area`, returning
+    /// its thread pointer.
+    #[cfg(any(target_arch = "arm", target_arch = "aarch64"))]
+    pub(crate) unsafe fn write(&self, area: usize) -> usize {
+        let thread_pointer = align_up(area, self.align);
+        ptr::write_bytes(thread_pointer as *mut u8, 0, THREAD_CONTROL_BLOCK_BYTES);
+        self.copy_to(thread_pointer + align_up(THREAD_CONTROL_BLOCK_BYTES, self.align));
+        thread_pointer
+    }
+
+    /// How many bytes `write` needs
+    #[cfg(target_arch = "x86_64")]
+    pub(crate) fn area_bytes(&self) -> usize {
+        self.align - 1 + align_up(self.size, self.align) + size_of::<usize>()
+    }
+
+    /// Lay out a thread's TLS in the `area_bytes` at `area`, returning
+    /// its thread pointer.
+    #[cfg(target_arch = "x86_64")]
+    pub(crate) unsafe fn write(&self, area: usize) -> usize {
+        // The TLS block ends at the thread pointer (TLS variant II), which
+        // points at itself
+        let block = align_up(area, self.align);
+        let thread_pointer = block + align_up(self.size, self.align);
+        self.copy_to(block);
+        (thread_pointer as *mut usize).write(thread_pointer);
+        thread_pointer
+    }
+
+    unsafe fn copy_to(&self, block: usize) {
+        ptr::copy_nonoverlapping(self.data.as_ptr(), block as *mut u8, self.data.len());
+        ptr::write_bytes(
+            (block + self.data.len()) as *mut u8,
+            0,
+            self.size - self.data.len(),
+        );
+    }
+}
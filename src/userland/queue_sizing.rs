@@ -0,0 +1,124 @@
+//! Worst case queue depth sizing, checked at compile time.
+//!
+//! Each producer's worst case arrivals are declared as a type, in whatever
+//! unit of time ("ticks") the system settles on, along with the longest a
+//! consumer may go between draining its queue. `assert_queue_depth!` then
+//! fails to compile if the queue's depth could be exceeded by everything
+//! the producers are allowed to send within that latency.
+//!
+//! ```ignore
+//! // Up to 4 requests back to back, 10 per 100 ms sustained, from one
+//! // producer, and a single element at a time from another. The consumer
+//! // handles its queue at least every 20 ms.
+//! type Producers = (TokenBucket<U4, U10, U100>, Burst<U1>);
+//! assert_queue_depth!(U8, Producers, U20);
+//! ```
+use core::marker::PhantomData;
+use core::ops::{Add, Div, Mul, Sub};
+
+use typenum::*;
+
+/// A producer that sends at most `N` elements altogether, all at once in
+/// the worst case
+pub struct Burst<N: Unsigned> {
+    _n: PhantomData<N>,
+}
+
+/// A producer that sends up to `Burst` elements back to back, and at most
+/// `Count` elements every `Ticks` sustained
+pub struct TokenBucket<Burst: Unsigned, Count: Unsigned, Ticks: Unsigned + NonZero> {
+    _burst: PhantomData<Burst>,
+    _count: PhantomData<Count>,
+    _ticks: PhantomData<Ticks>,
+}
+
+/// The most elements a producer (or a tuple of them) can send within a
+/// window of `Window` ticks
+pub trait ArrivalBound<Window: Unsigned> {
+    type Max: Unsigned;
+}
+
+impl<N: Unsigned, Window: Unsigned> ArrivalBound<Window> for Burst<N> {
+    type Max = N;
+}
+
+/// `Burst + ceil(Count * Window / Ticks)`
+impl<B, C, T, Window> ArrivalBound<Window> for TokenBucket<B, C, T>
+where
+    B: Unsigned,
+    C: Unsigned + Mul<Window>,
+    T: Unsigned + NonZero,
+    Window: Unsigned,
+    Prod<C, Window>: Add<T>,
+    Sum<Prod<C, Window>, T>: Sub<B1>,
+    Sub1<Sum<Prod<C, Window>, T>>: Div<T>,
+    B: Add<Quot<Sub1<Sum<Prod<C, Window>, T>>, T>>,
+    Sum<B, Quot<Sub1<Sum<Prod<C, Window>, T>>, T>>: Unsigned,
+{
+    type Max = Sum<B, Quot<Sub1<Sum<Prod<C, Window>, T>>, T>>;
+}
+
+impl<P1, P2, Window> ArrivalBound<Window> for (P1, P2)
+where
+    Window: Unsigned,
+    P1: ArrivalBound<Window>,
+    P2: ArrivalBound<Window>,
+    P1::Max: Add<P2::Max>,
+    Sum<P1::Max, P2::Max>: Unsigned,
+{
+    type Max = Sum<P1::Max, P2::Max>;
+}
+
+impl<P1, P2, P3, Window> ArrivalBound<Window> for (P1, P2, P3)
+where
+    Window: Unsigned,
+    (P1, P2): ArrivalBound<Window>,
+    P3: ArrivalBound<Window>,
+    <(P1, P2) as ArrivalBound<Window>>::Max: Add<P3::Max>,
+    Sum<<(P1, P2) as ArrivalBound<Window>>::Max, P3::Max>: Unsigned,
+{
+    type Max = Sum<<(P1, P2) as ArrivalBound<Window>>::Max, P3::Max>;
+}
+
+impl<P1, P2, P3, P4, Window> ArrivalBound<Window> for (P1, P2, P3, P4)
+where
+    Window: Unsigned,
+    (P1, P2, P3): ArrivalBound<Window>,
+    P4: ArrivalBound<Window>,
+    <(P1, P2, P3) as ArrivalBound<Window>>::Max: Add<P4::Max>,
+    Sum<<(P1, P2, P3) as ArrivalBound<Window>>::Max, P4::Max>: Unsigned,
+{
+    type Max = Sum<<(P1, P2, P3) as ArrivalBound<Window>>::Max, P4::Max>;
+}
+
+/// The depth a queue needs so that `Producers` can't overflow it when the
+/// consumer drains it at least every `MaxLatency` ticks
+pub type RequiredQueueDepth<Producers, MaxLatency> = <Producers as ArrivalBound<MaxLatency>>::Max;
+
+/// Implemented by the queue depths sufficient for `Producers` and a
+/// consumer latency of `MaxLatency` ticks
+pub trait SufficientQueueDepth<Producers, MaxLatency> {}
+
+impl<Depth, Producers, MaxLatency> SufficientQueueDepth<Producers, MaxLatency> for Depth
+where
+    MaxLatency: Unsigned,
+    Producers: ArrivalBound<MaxLatency>,
+    Depth: Unsigned + IsGreaterOrEqual<RequiredQueueDepth<Producers, MaxLatency>, Output = True>,
+{
+}
+
+/// Fails to compile unless a queue of depth `$depth` can hold everything
+/// `$producers` may send within `$max_latency` ticks
+#[macro_export]
+macro_rules! assert_queue_depth {
+    ($depth:ty, $producers:ty, $max_latency:ty) => {
+        const _: fn() = || {
+            fn sufficient<D, P, L>()
+            where
+                D: $crate::userland::SufficientQueueDepth<P, L>,
+            {
+            }
+            sufficient::<$depth, $producers, $max_latency>();
+        };
+    };
+}
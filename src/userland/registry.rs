@@ -0,0 +1,412 @@
+//! A name registry, for processes to find each other's endpoints at
+//! runtime rather than having the root task wire up every channel.
+//!
+//! The registry is served by a broker process of its own, with
+//! `RegistryBroker::serve`. The processes handed a `RegistryClient`
+//! register endpoints with it under a name, and look names up to get a
+//! copy of the endpoint registered under them, the endpoints moving along
+//! with the messages as with `CapMessage`.
+//!
+//! A name is registered along with the sizes of the request and response
+//! types its service handles, and looking it up for types of other sizes
+//! fails, which catches most clients that disagree with a service about
+//! what it handles.
+//!
+//! ```ignore
+//! // Root task
+//! let (registry_setup, broker) = registry(ut, &root_cnode, slot, broker_slot)?;
+//! let storage_registry = registry_setup.create_client(storage_slot)?;
+//! let console_registry = registry_setup.create_client(console_slot)?;
+//!
+//! // Broker
+//! broker.serve(slots, receive_slot, &cnode)?;
+//!
+//! // Storage driver
+//! let responder = registry.register_service::<Request, Response>("storage", ut, slot)?;
+//!
+//! // Console
+//! let storage: Caller<Request, Response, _> = registry.lookup("storage", slot)?;
+//! ```
+use core::fmt;
+
+use arrayvec::ArrayVec;
+use selfe_sys::*;
+use typenum::*;
+
+use crate::cap::{
+    role, CNodeRole, CNodeSlot, Cap, DirectRetype, Endpoint, LocalCNode, LocalCNodeSlot,
+    LocalCNodeSlots, LocalCap, Untyped,
+};
+use crate::error::SeL4Error;
+use crate::userland::cap_transfer::{
+    cap_message_info, clear_receive_slot, offer_cap, received_cap, set_receive_cptr,
+    set_receive_slot,
+};
+use crate::userland::correlation::{current_correlation_id, CorrelationId};
+use crate::userland::ipc::{labeled_type_length_message_info, type_length_in_words, IPCBuffer};
+use crate::userland::{Caller, CapRights, IPCError, MessageInfo, Responder};
+
+/// The longest name a service can be registered under, in bytes
+pub const SERVICE_NAME_MAX_LEN: usize = 32;
+
+/// The most names the registry holds at once, and so the slots the broker
+/// keeps their endpoints in
+pub type RegistryCapacity = U32;
+
+#[derive(Debug)]
+pub enum RegistryError {
+    /// The name is longer than `SERVICE_NAME_MAX_LEN`
+    NameTooLong,
+    /// Another endpoint is already registered under the name
+    NameTaken,
+    /// The registry has no room for another name
+    RegistryFull,
+    NotFound,
+    /// The service registered under the name handles requests or
+    /// responses of other sizes than the ones looked up
+    MessageSizeMismatch,
+    IPCError(IPCError),
+}
+
+impl From<IPCError> for RegistryError {
+    fn from(e: IPCError) -> Self {
+        RegistryError::IPCError(e)
+    }
+}
+
+impl From<SeL4Error> for RegistryError {
+    fn from(e: SeL4Error) -> Self {
+        RegistryError::IPCError(IPCError::SeL4Error(e))
+    }
+}
+
+/// A service's name, a string of up to `SERVICE_NAME_MAX_LEN` bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ServiceName {
+    len: u8,
+    bytes: [u8; SERVICE_NAME_MAX_LEN],
+}
+
+impl ServiceName {
+    pub fn new(name: &str) -> Result<Self, RegistryError> {
+        if name.len() > SERVICE_NAME_MAX_LEN {
+            return Err(RegistryError::NameTooLong);
+        }
+        let mut bytes = [0; SERVICE_NAME_MAX_LEN];
+        bytes[..name.len()].copy_from_slice(name.as_bytes());
+        Ok(ServiceName {
+            len: name.len() as u8,
+            bytes,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.bytes
+            .get(..self.len as usize)
+            .and_then(|bytes| core::str::from_utf8(bytes).ok())
+            .unwrap_or("")
+    }
+}
+
+impl fmt::Display for ServiceName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+/// A name along with the sizes of the messages its service handles
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ServiceSignature {
+    name: ServiceName,
+    request_size: usize,
+    response_size: usize,
+}
+
+impl ServiceSignature {
+    fn of<Req, Rsp>(name: &str) -> Result<Self, RegistryError> {
+        Ok(ServiceSignature {
+            name: ServiceName::new(name)?,
+            request_size: core::mem::size_of::<Req>(),
+            response_size: core::mem::size_of::<Rsp>(),
+        })
+    }
+}
+
+/// A register request comes with the endpoint to register, and a found
+/// response with the endpoint looked up
+#[derive(Debug, Clone, Copy)]
+enum RegistryRequest {
+    Register(ServiceSignature),
+    Lookup(ServiceSignature),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RegistryResponse {
+    Registered,
+    Found,
+    NameTaken,
+    RegistryFull,
+    NotFound,
+    MessageSizeMismatch,
+    /// A register request came without its endpoint
+    CapNotTransferred,
+}
+
+impl RegistryResponse {
+    fn into_result(self) -> Result<(), RegistryError> {
+        match self {
+            RegistryResponse::Registered | RegistryResponse::Found => Ok(()),
+            RegistryResponse::NameTaken => Err(RegistryError::NameTaken),
+            RegistryResponse::RegistryFull => Err(RegistryError::RegistryFull),
+            RegistryResponse::NotFound => Err(RegistryError::NotFound),
+            RegistryResponse::MessageSizeMismatch => Err(RegistryError::MessageSizeMismatch),
+            RegistryResponse::CapNotTransferred => {
+                Err(RegistryError::IPCError(IPCError::CapNotTransferred))
+            }
+        }
+    }
+}
+
+/// Create the registry's endpoint, copying it into the broker's CNode at
+/// `broker_slot`, and return a `RegistrySetup` to create its clients with
+pub fn registry<BrokerRole: CNodeRole>(
+    untyped: LocalCap<Untyped<<Endpoint as DirectRetype>::SizeBits>>,
+    local_cnode: &LocalCap<LocalCNode>,
+    local_slot: LocalCNodeSlot,
+    broker_slot: CNodeSlot<BrokerRole>,
+) -> Result<(RegistrySetup, RegistryBroker<BrokerRole>), IPCError> {
+    let _ = IPCBuffer::<RegistryRequest, RegistryResponse>::new()?; // Check buffer fits both
+    let local_endpoint: LocalCap<Endpoint> = untyped.retype(local_slot)?;
+    // Replying with an endpoint takes the grant right
+    let broker_endpoint = local_endpoint.copy(local_cnode, broker_slot, CapRights::RWG)?;
+
+    Ok((
+        RegistrySetup {
+            endpoint: local_endpoint,
+            endpoint_cnode: local_cnode,
+        },
+        RegistryBroker {
+            endpoint: broker_endpoint,
+        },
+    ))
+}
+
+pub struct RegistrySetup<'a> {
+    endpoint: LocalCap<Endpoint>,
+    endpoint_cnode: &'a LocalCap<LocalCNode>,
+}
+
+impl<'a> RegistrySetup<'a> {
+    pub fn create_client<Role: CNodeRole>(
+        &self,
+        client_slot: CNodeSlot<Role>,
+    ) -> Result<RegistryClient<Role>, IPCError> {
+        let client_endpoint =
+            self.endpoint
+                .copy(self.endpoint_cnode, client_slot, CapRights::RWG)?;
+        Ok(RegistryClient {
+            endpoint: client_endpoint,
+        })
+    }
+}
+
+/// A registered endpoint, kept by the broker
+struct Registration {
+    signature: ServiceSignature,
+    endpoint: LocalCap<Endpoint>,
+}
+
+#[derive(Debug)]
+pub struct RegistryBroker<Role: CNodeRole> {
+    endpoint: Cap<Endpoint, Role>,
+}
+
+impl RegistryBroker<role::Local> {
+    /// Serve registrations and lookups, keeping each endpoint registered
+    /// in one of `slots`. Endpoints being registered arrive in
+    /// `receive_slot`, and are moved out of it once they're accepted.
+    ///
+    /// Only returns if a received endpoint can't be moved or deleted.
+    pub fn serve(
+        self,
+        slots: LocalCNodeSlots<RegistryCapacity>,
+        receive_slot: LocalCNodeSlot,
+        local_cnode: &LocalCap<LocalCNode>,
+    ) -> Result<(), IPCError> {
+        let mut free_slots = slots.iter();
+        let mut registrations: ArrayVec<[Registration; RegistryCapacity::USIZE]> = ArrayVec::new();
+        let (receive_cnode, receive_offset, _) = receive_slot.elim();
+
+        // Sizes were checked when the registry was set up
+        let mut ipc_buffer: IPCBuffer<RegistryRequest, RegistryResponse> =
+            unsafe { IPCBuffer::unchecked_new() };
+        let mut sender_badge: usize = 0;
+        let mut msg_info: MessageInfo = unsafe {
+            set_receive_cptr(receive_cnode, receive_offset);
+            seL4_Recv(self.endpoint.cptr, &mut sender_badge as *mut usize)
+        }
+        .into();
+        loop {
+            let received = received_cap::<Endpoint>(&msg_info, receive_offset).ok();
+            if msg_info.length_words() != type_length_in_words::<RegistryRequest>() {
+                // Not knowing what the message is, drop it, leaving its
+                // caller blocked, as `Responder` does
+                debug_println!(
+                    "Registry request of {} words does not match its expected size.",
+                    msg_info.length_words()
+                );
+                if let Some(endpoint) = received {
+                    endpoint.delete(local_cnode)?;
+                }
+                msg_info =
+                    unsafe { seL4_Recv(self.endpoint.cptr, &mut sender_badge as *mut usize) }
+                        .into();
+                continue;
+            }
+
+            let mut found = None;
+            let response = match ipc_buffer.copy_req_from_buffer() {
+                RegistryRequest::Register(signature) => match received {
+                    None => RegistryResponse::CapNotTransferred,
+                    Some(endpoint) => {
+                        if registrations
+                            .iter()
+                            .any(|r| r.signature.name == signature.name)
+                        {
+                            endpoint.delete(local_cnode)?;
+                            RegistryResponse::NameTaken
+                        } else if let Some(slot) = free_slots.next() {
+                            registrations.push(Registration {
+                                signature,
+                                endpoint: endpoint.move_to_slot(local_cnode, slot)?,
+                            });
+                            RegistryResponse::Registered
+                        } else {
+                            endpoint.delete(local_cnode)?;
+                            RegistryResponse::RegistryFull
+                        }
+                    }
+                },
+                RegistryRequest::Lookup(signature) => {
+                    if let Some(endpoint) = received {
+                        endpoint.delete(local_cnode)?;
+                    }
+                    match registrations
+                        .iter()
+                        .find(|r| r.signature.name == signature.name)
+                    {
+                        None => RegistryResponse::NotFound,
+                        Some(r) if r.signature != signature => {
+                            RegistryResponse::MessageSizeMismatch
+                        }
+                        Some(r) => {
+                            found = Some(&r.endpoint);
+                            RegistryResponse::Found
+                        }
+                    }
+                }
+            };
+
+            let label = msg_info.label();
+            unsafe { ipc_buffer.unchecked_copy_into_buffer(&response) };
+            let reply_info = match found {
+                Some(endpoint) => unsafe {
+                    offer_cap(endpoint);
+                    cap_message_info::<RegistryResponse>(label)
+                },
+                None => labeled_type_length_message_info::<RegistryResponse>(label),
+            };
+            msg_info = unsafe {
+                set_receive_cptr(receive_cnode, receive_offset);
+                seL4_ReplyRecv(
+                    self.endpoint.cptr,
+                    reply_info,
+                    &mut sender_badge as *mut usize,
+                )
+            }
+            .into();
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct RegistryClient<Role: CNodeRole> {
+    endpoint: Cap<Endpoint, Role>,
+}
+
+impl RegistryClient<role::Local> {
+    /// Register `endpoint` under `name`, for a service handling `Req`s
+    /// with `Rsp`s. The registry gets a copy, `endpoint` itself is kept.
+    pub fn register<Req, Rsp>(
+        &self,
+        name: &str,
+        endpoint: &LocalCap<Endpoint>,
+    ) -> Result<(), RegistryError> {
+        let request = RegistryRequest::Register(ServiceSignature::of::<Req, Rsp>(name)?);
+        let (response, _) = unsafe {
+            offer_cap(endpoint);
+            self.call(&request, cap_message_info::<RegistryRequest>)
+        }?;
+        response.into_result()
+    }
+
+    /// Create an endpoint and register it under `name`, returning the
+    /// responder to serve the service with
+    pub fn register_service<Req: Send + Sync, Rsp: Send + Sync>(
+        &self,
+        name: &str,
+        untyped: LocalCap<Untyped<<Endpoint as DirectRetype>::SizeBits>>,
+        slot: LocalCNodeSlot,
+    ) -> Result<Responder<Req, Rsp, role::Local>, RegistryError> {
+        let _ = IPCBuffer::<Req, Rsp>::new()?; // Check buffer fits Req and Rsp
+        let endpoint: LocalCap<Endpoint> = untyped.retype(slot).map_err(IPCError::from)?;
+        self.register::<Req, Rsp>(name, &endpoint)?;
+        Ok(Responder::wrap_cptr(endpoint.cptr))
+    }
+
+    /// Look up the service registered under `name`, receiving a copy of
+    /// its endpoint into `slot`. The slot is used up whether or not the
+    /// name is found.
+    pub fn lookup<Req: Send + Sync, Rsp: Send + Sync>(
+        &self,
+        name: &str,
+        slot: LocalCNodeSlot,
+    ) -> Result<Caller<Req, Rsp, role::Local>, RegistryError> {
+        let _ = IPCBuffer::<Req, Rsp>::new()?; // Check buffer fits Req and Rsp
+        let request = RegistryRequest::Lookup(ServiceSignature::of::<Req, Rsp>(name)?);
+        let (result, receive_cptr) = unsafe {
+            let receive_cptr = set_receive_slot(slot);
+            let result = self.call(
+                &request,
+                labeled_type_length_message_info::<RegistryRequest>,
+            );
+            clear_receive_slot();
+            (result, receive_cptr)
+        };
+        let (response, msg_info) = result?;
+        response.into_result()?;
+        let endpoint = received_cap::<Endpoint>(&msg_info, receive_cptr)?;
+        Ok(Caller::wrap_cptr(endpoint.cptr))
+    }
+
+    /// Call the broker with `request`, with whatever capability has been
+    /// offered
+    unsafe fn call(
+        &self,
+        request: &RegistryRequest,
+        message_info: fn(usize) -> seL4_MessageInfo_t,
+    ) -> Result<(RegistryResponse, MessageInfo), IPCError> {
+        let mut ipc_buffer: IPCBuffer<RegistryRequest, RegistryResponse> =
+            IPCBuffer::unchecked_new();
+        ipc_buffer.copy_req_into_buffer(request);
+        let msg_info: MessageInfo = seL4_Call(
+            self.endpoint.cptr,
+            message_info(CorrelationId::into_label(current_correlation_id())),
+        )
+        .into();
+        if msg_info.length_words() != type_length_in_words::<RegistryResponse>() {
+            return Err(IPCError::ResponseSizeMismatch);
+        }
+        Ok((ipc_buffer.unchecked_copy_from_buffer(), msg_info))
+    }
+}
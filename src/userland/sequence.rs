@@ -0,0 +1,52 @@
+//! A system-wide event sequence, stamped from a counter in a page of
+//! memory shared between processes.
+//!
+//! Every stamp taken from the counter is unique and later than any taken
+//! before it, whichever process took it. Stamping emitted events or log
+//! records gives a total order of interleaved traces from several
+//! processes, without needing their clocks to agree.
+use core::fmt;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use crate::arch::PageBits;
+use crate::vspace::{shared_status, MappedMemoryRegion};
+
+/// Size of the shared region backing a `SequenceCounter`
+pub type SequenceCounterSizeBits = PageBits;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SequenceNumber(pub u32);
+
+impl fmt::Display for SequenceNumber {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// The counter, laid out at the start of the shared page
+#[repr(C)]
+pub struct SequenceCounter {
+    next: AtomicU32,
+}
+
+impl SequenceCounter {
+    /// Use the counter in a mapping of the shared page.
+    ///
+    /// The page needs no initialization, retyped memory starts out
+    /// zeroed, and the mapping is kept for the rest of the process' life.
+    pub fn from_region(
+        region: MappedMemoryRegion<SequenceCounterSizeBits, shared_status::Shared>,
+    ) -> &'static SequenceCounter {
+        unsafe { &*(region.vaddr() as *const SequenceCounter) }
+    }
+
+    /// Take the next number in the sequence
+    pub fn stamp(&self) -> SequenceNumber {
+        SequenceNumber(self.next.fetch_add(1, Ordering::AcqRel))
+    }
+
+    /// The number the next stamp will be given
+    pub fn peek(&self) -> SequenceNumber {
+        SequenceNumber(self.next.load(Ordering::Acquire))
+    }
+}
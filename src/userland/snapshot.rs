@@ -0,0 +1,115 @@
+//! Consistent snapshots of stats written lock-free to shared memory.
+//!
+//! A producer publishing several related fields, e.g. a counter and the
+//! time it was last bumped, can't update them all at once, and a reader
+//! copying them out field by field now and then sees some from before an
+//! update and some from after. A `SeqLock` wraps the whole record in a
+//! sequence counter: the producer makes it odd while it writes and even
+//! again once it's done, and a reader's copy only counts if the counter
+//! was even and unchanged from before the copy to after.
+//!
+//! Readers never block the producer; a reader that keeps losing the race
+//! gives up after a bounded number of retries rather than spinning on.
+use core::cell::UnsafeCell;
+use core::mem;
+use core::ptr;
+use core::sync::atomic::{self, AtomicU32, Ordering};
+
+use typenum::Unsigned;
+
+use crate::arch::{PageBits, PageBytes};
+use crate::vspace::{shared_status, MappedMemoryRegion};
+
+/// Size of the shared region backing a `SeqLock` made with `from_region`
+pub type SeqLockSizeBits = PageBits;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotError {
+    /// Every attempt overlapped a write
+    Contended { attempts: usize },
+}
+
+/// A record of type `T` along with its sequence counter, laid out at the
+/// start of the shared page
+#[repr(C)]
+pub struct SeqLock<T: Copy> {
+    sequence: AtomicU32,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T: Copy + Send> Sync for SeqLock<T> {}
+
+impl<T: Copy> SeqLock<T> {
+    /// Use the record in a mapping of the shared page, or `None` if it
+    /// doesn't fit in one.
+    ///
+    /// Retyped memory starts out zeroed, so `T` should be a type for
+    /// which all-zero is a valid value.
+    pub fn from_region(
+        region: MappedMemoryRegion<SeqLockSizeBits, shared_status::Shared>,
+    ) -> Option<&'static SeqLock<T>> {
+        if mem::size_of::<SeqLock<T>>() > PageBytes::USIZE {
+            return None;
+        }
+        Some(unsafe { &*(region.vaddr() as *const SeqLock<T>) })
+    }
+
+    /// Update the record in place.
+    ///
+    /// # Safety
+    ///
+    /// Only one thread may ever write to the record, in any process it's
+    /// shared with.
+    pub unsafe fn write<F: FnOnce(&mut T)>(&self, f: F) {
+        let sequence = self.sequence.load(Ordering::Relaxed);
+        self.sequence
+            .store(sequence.wrapping_add(1), Ordering::Relaxed);
+        atomic::fence(Ordering::Release);
+
+        f(&mut *self.value.get());
+
+        self.sequence
+            .store(sequence.wrapping_add(2), Ordering::Release);
+    }
+
+    /// One attempt at a copy, `None` if a write overlapped it
+    fn try_snapshot(&self) -> Option<T> {
+        let before = self.sequence.load(Ordering::Acquire);
+        if before % 2 == 1 {
+            return None;
+        }
+        let value = unsafe { ptr::read_volatile(self.value.get()) };
+        atomic::fence(Ordering::Acquire);
+        if self.sequence.load(Ordering::Relaxed) == before {
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    /// A consistent copy of the record, trying up to `max_attempts` times
+    pub fn snapshot(&self, max_attempts: usize) -> Result<T, SnapshotError> {
+        for _ in 0..max_attempts {
+            if let Some(value) = self.try_snapshot() {
+                return Ok(value);
+            }
+            core::hint::spin_loop();
+        }
+        Err(SnapshotError::Contended {
+            attempts: max_attempts,
+        })
+    }
+
+    /// Copy the record into `buf`, e.g. an aggregator's own copy of the
+    /// page, leaving `buf` as it was if every attempt fails
+    pub fn snapshot_into(&self, buf: &mut T, max_attempts: usize) -> Result<(), SnapshotError> {
+        *buf = self.snapshot(max_attempts)?;
+        Ok(())
+    }
+
+    /// How many writes have completed, for readers checking whether
+    /// there's anything new since their last snapshot
+    pub fn generation(&self) -> u32 {
+        self.sequence.load(Ordering::Acquire) / 2
+    }
+}
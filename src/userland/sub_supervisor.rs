@@ -0,0 +1,237 @@
+//! Handing a child what it needs to spawn processes of its own.
+//!
+//! A `SubSupervisorKit` bundles a share of its supervisor's slots,
+//! untypeds and ASIDs along with a scratch region, the user image and the
+//! authority to set thread priorities, all moved into the child's CNode.
+//! Passed to the child as part of its process parameters, it's all the
+//! child needs to spawn children of its own, with the weakly-typed
+//! allocators sizing each one at runtime, and nothing it spawns can use
+//! more than it was given.
+//!
+//! ```ignore
+//! // Supervisor
+//! let kit = SubSupervisorKit::new(
+//!     &child_cnode, child_slots.weaken(), weak_ut_buddy(ut.weaken()), &mut asid_pool, 2,
+//!     scratch.weaken(), &mut child_vspace, &user_image, &tpa, &root_cnode,
+//! )?;
+//! let params = ChildParams { kit, .. };
+//!
+//! // Child
+//! let mut vspace = kit.vspace(1024, 15)?;
+//! let (grandkid_cnode, grandkid_slots) =
+//!     retype_cnode::<U8>(kit.alloc_untyped()?, kit.alloc_slots()?)?;
+//! let stack = kit.take_scratch::<U17>()?;
+//! ```
+use core::marker::PhantomData;
+use core::ops::Sub;
+
+use typenum::*;
+
+use crate::alloc::ut_buddy::{UTBuddyError, WUTBuddy};
+use crate::arch::{self, PageBits, PagingRoot};
+use crate::bootstrap::UserImage;
+use crate::cap::{
+    role, ASIDPoolError, CNode, CNodeRole, CNodeSlotsError, Cap, ChildCNode, DirectRetype,
+    LocalCNode, LocalCNodeSlots, LocalCap, RetypeError, ThreadPriorityAuthority, Untyped,
+    WASIDPool, WCNodeSlotsData,
+};
+use crate::error::SeL4Error;
+use crate::pow::{Pow, _Pow};
+use crate::userland::CapRights;
+use crate::vspace::{
+    shared_status, MappedMemoryRegion, ProcessCodeImageConfig, VSpace, VSpaceError,
+    WeakMappedMemoryRegion, WeakUnmappedMemoryRegion,
+};
+
+#[derive(Debug)]
+pub enum SubSupervisorKitError {
+    NotEnoughSlots,
+    /// The kit's scratch region has already been taken
+    ScratchTaken,
+    /// The kit's scratch region isn't the size asked for
+    ScratchSizeMismatch {
+        size_bits: u8,
+    },
+    ASIDPoolError(ASIDPoolError),
+    UTBuddyError(UTBuddyError),
+    RetypeError(RetypeError),
+    VSpaceError(VSpaceError),
+    SeL4Error(SeL4Error),
+}
+
+impl From<CNodeSlotsError> for SubSupervisorKitError {
+    fn from(_: CNodeSlotsError) -> Self {
+        SubSupervisorKitError::NotEnoughSlots
+    }
+}
+
+impl From<ASIDPoolError> for SubSupervisorKitError {
+    fn from(e: ASIDPoolError) -> Self {
+        SubSupervisorKitError::ASIDPoolError(e)
+    }
+}
+
+impl From<UTBuddyError> for SubSupervisorKitError {
+    fn from(e: UTBuddyError) -> Self {
+        SubSupervisorKitError::UTBuddyError(e)
+    }
+}
+
+impl From<RetypeError> for SubSupervisorKitError {
+    fn from(e: RetypeError) -> Self {
+        SubSupervisorKitError::RetypeError(e)
+    }
+}
+
+impl From<VSpaceError> for SubSupervisorKitError {
+    fn from(e: VSpaceError) -> Self {
+        SubSupervisorKitError::VSpaceError(e)
+    }
+}
+
+impl From<SeL4Error> for SubSupervisorKitError {
+    fn from(e: SeL4Error) -> Self {
+        SubSupervisorKitError::SeL4Error(e)
+    }
+}
+
+pub struct SubSupervisorKit<Role: CNodeRole> {
+    /// The child's own CNode, for copying and moving caps within it
+    pub cnode: Cap<CNode<Role>, Role>,
+    pub slots: Cap<WCNodeSlotsData<Role>, Role>,
+    pub untypeds: WUTBuddy<Role>,
+    pub asid_pool: Cap<WASIDPool, Role>,
+    pub user_image: UserImage<Role>,
+    pub thread_priority_authority: Cap<ThreadPriorityAuthority, Role>,
+    /// Mapped in the child's VSpace, for setting up a process' stack
+    scratch: Option<WeakMappedMemoryRegion<shared_status::Exclusive>>,
+}
+
+impl SubSupervisorKit<role::Child> {
+    /// Move a share of the supervisor's resources into `child_cnode`
+    /// for the child to spawn processes of its own with: `asid_count` of
+    /// the ASIDs in `asid_pool`, all of `untypeds`, and `scratch`, mapped
+    /// into `child_vspace`. The kit's own caps take the first of
+    /// `child_slots`, and the rest go in the kit as the child's to
+    /// allocate from.
+    pub fn new(
+        child_cnode: &LocalCap<ChildCNode>,
+        mut child_slots: LocalCap<WCNodeSlotsData<role::Child>>,
+        untypeds: WUTBuddy,
+        asid_pool: &mut LocalCap<WASIDPool>,
+        asid_count: usize,
+        scratch: WeakUnmappedMemoryRegion<shared_status::Exclusive>,
+        child_vspace: &mut VSpace,
+        user_image: &UserImage<role::Local>,
+        thread_priority_authority: &LocalCap<ThreadPriorityAuthority>,
+        local_cnode: &LocalCap<LocalCNode>,
+    ) -> Result<Self, SubSupervisorKitError> {
+        let (cnode, _) =
+            child_cnode.generate_self_reference::<U0>(local_cnode, child_slots.alloc_strong()?)?;
+        let asid_pool =
+            asid_pool.split_into(asid_count, child_slots.alloc_strong()?, local_cnode)?;
+        let user_image = user_image.copy(local_cnode, child_slots.alloc_strong()?)?;
+        let thread_priority_authority = thread_priority_authority.copy(
+            local_cnode,
+            child_slots.alloc_strong()?,
+            CapRights::RWG,
+        )?;
+        let untypeds = untypeds.move_to_child(local_cnode, &mut child_slots)?;
+        let scratch = child_vspace.weak_map_region_and_move(
+            scratch,
+            CapRights::RW,
+            arch::vm_attributes::DEFAULT,
+            local_cnode,
+            &mut child_slots,
+        )?;
+
+        // What's left, addressed through the child's reference to its
+        // own CNode
+        let slots = Cap {
+            cptr: cnode.cptr,
+            cap_data: WCNodeSlotsData {
+                offset: child_slots.cap_data.offset,
+                size: child_slots.cap_data.size,
+                _role: PhantomData,
+            },
+            _role: PhantomData,
+        };
+
+        Ok(SubSupervisorKit {
+            cnode,
+            slots,
+            untypeds,
+            asid_pool,
+            user_image,
+            thread_priority_authority,
+            scratch: Some(scratch),
+        })
+    }
+}
+
+impl SubSupervisorKit<role::Local> {
+    /// Allocate `Count` slots from the kit
+    pub fn alloc_slots<Count: Unsigned>(
+        &mut self,
+    ) -> Result<LocalCNodeSlots<Count>, SubSupervisorKitError> {
+        Ok(self.slots.alloc_strong()?)
+    }
+
+    /// Allocate an untyped of `SizeBits` bits from the kit, splitting a
+    /// bigger one if need be
+    pub fn alloc_untyped<SizeBits: Unsigned>(
+        &mut self,
+    ) -> Result<LocalCap<Untyped<SizeBits>>, SubSupervisorKitError> {
+        Ok(self.untypeds.alloc_strong(&mut self.slots)?)
+    }
+
+    /// A VSpace for a new process, with one of the kit's ASIDs, and
+    /// `slot_count` of its slots and an untyped of `untyped_bits` bits
+    /// for its paging structures
+    pub fn vspace(
+        &mut self,
+        slot_count: usize,
+        untyped_bits: u8,
+    ) -> Result<VSpace, SubSupervisorKitError> {
+        let asid = self.asid_pool.alloc()?;
+        let root: LocalCap<PagingRoot> = self
+            .untypeds
+            .alloc(&mut self.slots, <PagingRoot as DirectRetype>::SizeBits::U8)?
+            .retype(&mut self.slots)?;
+        let slots = self.slots.alloc(slot_count)?;
+        let untyped = self.untypeds.alloc(&mut self.slots, untyped_bits)?;
+        Ok(VSpace::new(
+            root,
+            asid,
+            slots,
+            untyped,
+            ProcessCodeImageConfig::ReadOnly,
+            &self.user_image,
+            &self.cnode,
+        )?)
+    }
+
+    /// Take the scratch region, e.g. to set up the stack of a process
+    /// with. There's only the one, so split it first to spawn more than
+    /// one process.
+    pub fn take_scratch<SizeBits: Unsigned>(
+        &mut self,
+    ) -> Result<MappedMemoryRegion<SizeBits, shared_status::Exclusive>, SubSupervisorKitError>
+    where
+        SizeBits: IsGreaterOrEqual<PageBits>,
+        SizeBits: Sub<PageBits>,
+        <SizeBits as Sub<PageBits>>::Output: Unsigned,
+        <SizeBits as Sub<PageBits>>::Output: _Pow,
+        Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+    {
+        match self.scratch.take() {
+            None => Err(SubSupervisorKitError::ScratchTaken),
+            Some(scratch) if scratch.size_bits() != SizeBits::U8 => {
+                let size_bits = scratch.size_bits();
+                self.scratch = Some(scratch);
+                Err(SubSupervisorKitError::ScratchSizeMismatch { size_bits })
+            }
+            Some(scratch) => Ok(scratch.as_strong()?),
+        }
+    }
+}
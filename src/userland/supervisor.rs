@@ -0,0 +1,512 @@
+//! Restarting child processes that fault.
+//!
+//! A `Supervisor` starts a process from its elf image and receives its
+//! faults, along with any messages it sends, on the handler side of a
+//! `fault_or_message_channel`. When the process faults, the supervisor
+//! stops it, takes back the untypeds and slots it and its vspace were made
+//! from, and depending on its `RestartPolicy` starts it again in a vspace
+//! loaded afresh from the same image, with the same process parameters and
+//! cspace.
+//!
+//! ```ignore
+//! let (fault_source, sender, handler) =
+//!     fault_or_message_channel(&root_cnode, ut, slot, child_slot, slot)?;
+//! let params = ProcParams { heartbeat: sender, .. };
+//! let mut supervisor = unsafe {
+//!     Supervisor::new(
+//!         handler,
+//!         fault_source,
+//!         vspace_resources,
+//!         elf_data,
+//!         params,
+//!         resources,
+//!         RestartPolicy::AlwaysRestart,
+//!         &user_image,
+//!         &mut scratch,
+//!         &root_cnode,
+//!         &tpa,
+//!     )?
+//! };
+//! loop {
+//!     match supervisor.next_event(&user_image, &mut scratch, &root_cnode, &tpa)? { .. }
+//! }
+//! ```
+use core::ops::{Add, Sub};
+use core::ptr;
+
+use core::marker::PhantomData;
+
+use selfe_sys::*;
+use typenum::*;
+
+use crate::arch::{self, fault::Fault, PageBits, PagingRoot};
+use crate::bootstrap::UserImage;
+use crate::cap::{
+    memory_kind, role, CNodeSlotsData, Cap, DirectRetype, LocalCNode, LocalCNodeSlot, LocalCap,
+    ThreadPriorityAuthority, UnassignedASID, Untyped, WCNodeSlots, WCNodeSlotsData, WUntyped,
+};
+use crate::error::{ErrorExt, SeL4Error};
+use crate::pow::{Pow, _Pow};
+use crate::userland::{
+    DefaultStackBitSize, FaultOrMessage, FaultOrMessageHandler, FaultSource, IPCError,
+    ProcessSetupError, ReclaimedProcess, RetypeForSetup, SetupVer, StandardProcess,
+};
+use crate::vspace::{NumPages, ScratchRegion, VSpace, VSpaceError};
+
+/// What to do with a supervised process once it faults
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestartPolicy {
+    /// Leave it stopped
+    OneShot,
+    /// Restart it straight away, every time
+    AlwaysRestart,
+    /// Restart it after `initial_cycles`, doubling the wait with each
+    /// restart up to `max_cycles`, and leave it stopped once it's been
+    /// restarted `max_restarts` times
+    Backoff {
+        initial_cycles: usize,
+        max_cycles: usize,
+        max_restarts: usize,
+    },
+}
+
+impl RestartPolicy {
+    /// How long to wait before the next restart, after `restarts` so far,
+    /// or `None` to leave the process stopped
+    fn restart_delay(&self, restarts: usize) -> Option<usize> {
+        match *self {
+            RestartPolicy::OneShot => None,
+            RestartPolicy::AlwaysRestart => Some(0),
+            RestartPolicy::Backoff {
+                initial_cycles,
+                max_cycles,
+                max_restarts,
+            } => {
+                if restarts >= max_restarts {
+                    return None;
+                }
+                let factor = 1usize.checked_shl(restarts as u32).unwrap_or(usize::MAX);
+                Some(initial_cycles.saturating_mul(factor).min(max_cycles))
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SupervisorEvent<Msg> {
+    /// A message the process sent on the channel
+    Message(Msg),
+    /// The process faulted and was started again, this being restart
+    /// number `restarts`
+    Restarted { fault: Fault, restarts: usize },
+    /// The process faulted and the policy says to leave it stopped
+    Stopped { fault: Fault, restarts: usize },
+}
+
+#[derive(Debug)]
+pub enum SupervisorError {
+    /// A fault arrived with no process running, so there's nothing to
+    /// restart
+    NotRunning,
+    ProcessSetupError(ProcessSetupError),
+    IPCError(IPCError),
+    VSpaceError(VSpaceError),
+    SeL4Error(SeL4Error),
+}
+
+impl From<ProcessSetupError> for SupervisorError {
+    fn from(e: ProcessSetupError) -> Self {
+        SupervisorError::ProcessSetupError(e)
+    }
+}
+
+impl From<IPCError> for SupervisorError {
+    fn from(e: IPCError) -> Self {
+        SupervisorError::IPCError(e)
+    }
+}
+
+impl From<VSpaceError> for SupervisorError {
+    fn from(e: VSpaceError) -> Self {
+        SupervisorError::VSpaceError(e)
+    }
+}
+
+impl From<SeL4Error> for SupervisorError {
+    fn from(e: SeL4Error) -> Self {
+        SupervisorError::SeL4Error(e)
+    }
+}
+
+/// What a supervised process' vspace is made from, the same as
+/// `VSpace::new_from_elf_weak` takes. The supervisor loads the vspace
+/// afresh from these for each start of the process, and destroys it again
+/// once the process has stopped.
+pub struct SupervisedVSpaceResources {
+    pub paging_root_ut: LocalCap<Untyped<<PagingRoot as DirectRetype>::SizeBits>>,
+    pub paging_root_slot: LocalCNodeSlot,
+    pub asid: LocalCap<UnassignedASID>,
+    pub slots: WCNodeSlots,
+    pub paging_untyped: LocalCap<WUntyped<memory_kind::General>>,
+    pub page_slots: WCNodeSlots,
+    pub elf_writable_mem: LocalCap<WUntyped<memory_kind::General>>,
+}
+
+impl SupervisedVSpaceResources {
+    /// Another handle on the same caps, for loading a vspace from while
+    /// the supervisor keeps hold of these
+    fn alias(&self) -> Self {
+        SupervisedVSpaceResources {
+            paging_root_ut: Cap {
+                cptr: self.paging_root_ut.cptr,
+                cap_data: Untyped {
+                    kind: memory_kind::General,
+                    _bit_size: PhantomData,
+                },
+                _role: PhantomData,
+            },
+            paging_root_slot: Cap {
+                cptr: self.paging_root_slot.cptr,
+                cap_data: CNodeSlotsData {
+                    offset: self.paging_root_slot.cap_data.offset,
+                    _size: PhantomData,
+                    _role: PhantomData,
+                },
+                _role: PhantomData,
+            },
+            asid: Cap {
+                cptr: self.asid.cptr,
+                cap_data: UnassignedASID {
+                    asid: self.asid.cap_data.asid,
+                },
+                _role: PhantomData,
+            },
+            slots: alias_slots(&self.slots),
+            paging_untyped: alias_untyped(&self.paging_untyped),
+            page_slots: alias_slots(&self.page_slots),
+            elf_writable_mem: alias_untyped(&self.elf_writable_mem),
+        }
+    }
+
+    /// Load a vspace from `elf_data`
+    fn load(
+        &self,
+        elf_data: &[u8],
+        user_image: &UserImage<role::Local>,
+        local_vspace_scratch: &mut ScratchRegion,
+        parent_cnode: &LocalCap<LocalCNode>,
+    ) -> Result<VSpace, SupervisorError> {
+        let resources = self.alias();
+        let paging_root = resources
+            .paging_root_ut
+            .retype(resources.paging_root_slot)?;
+        Ok(VSpace::new_from_elf_weak(
+            paging_root,
+            resources.asid,
+            resources.slots,
+            resources.paging_untyped,
+            elf_data,
+            resources.page_slots,
+            resources.elf_writable_mem,
+            user_image,
+            parent_cnode,
+            local_vspace_scratch,
+        )?)
+    }
+
+    /// Destroy a vspace loaded by `load`, which unmaps everything in it,
+    /// leaving these as they were before it was loaded
+    fn destroy(
+        &self,
+        _vspace: VSpace,
+        parent_cnode: &LocalCap<LocalCNode>,
+    ) -> Result<(), SeL4Error> {
+        // The paging structures and writable pages are all retyped from
+        // these, the root included
+        for ut in [
+            self.paging_root_ut.cptr,
+            self.paging_untyped.cptr,
+            self.elf_writable_mem.cptr,
+        ]
+        .iter()
+        {
+            unsafe {
+                seL4_CNode_Revoke(
+                    parent_cnode.cptr,   // _service
+                    *ut,                 // index
+                    seL4_WordBits as u8, // depth
+                )
+            }
+            .as_result()
+            .map_err(SeL4Error::CNodeRevoke)?;
+        }
+
+        // and the read-only pages are copies of the image's pages
+        let page_slots = &self.page_slots.cap_data;
+        for offset in page_slots.offset..page_slots.offset + page_slots.size {
+            unsafe {
+                seL4_CNode_Delete(
+                    parent_cnode.cptr,   // _service
+                    offset,              // index
+                    seL4_WordBits as u8, // depth
+                )
+            }
+            .as_result()
+            .map_err(SeL4Error::CNodeDelete)?;
+        }
+        Ok(())
+    }
+}
+
+fn alias_slots(slots: &WCNodeSlots) -> WCNodeSlots {
+    Cap {
+        cptr: slots.cptr,
+        cap_data: WCNodeSlotsData {
+            offset: slots.cap_data.offset,
+            size: slots.cap_data.size,
+            _role: PhantomData,
+        },
+        _role: PhantomData,
+    }
+}
+
+fn alias_untyped(
+    ut: &LocalCap<WUntyped<memory_kind::General>>,
+) -> LocalCap<WUntyped<memory_kind::General>> {
+    Cap {
+        cptr: ut.cptr,
+        cap_data: WUntyped {
+            kind: memory_kind::General,
+            size_bits: ut.cap_data.size_bits,
+        },
+        _role: PhantomData,
+    }
+}
+
+pub struct Supervisor<Msg: Sized, T: RetypeForSetup, StackBitSize: Unsigned = DefaultStackBitSize>
+where
+    StackBitSize: IsGreaterOrEqual<PageBits>,
+    StackBitSize: Sub<PageBits>,
+    <StackBitSize as Sub<PageBits>>::Output: Unsigned,
+    <StackBitSize as Sub<PageBits>>::Output: _Pow,
+    Pow<<StackBitSize as Sub<PageBits>>::Output>: Unsigned,
+    NumPages<StackBitSize>: Add<U2>,
+    Sum<NumPages<StackBitSize>, U2>: Unsigned,
+{
+    handler: FaultOrMessageHandler<Msg, role::Local>,
+    /// The process' own cptr for its fault endpoint, in the cspace kept
+    /// across restarts
+    fault_source_cptr: usize,
+    vspace_resources: SupervisedVSpaceResources,
+    /// Loaded from the image while the process is running
+    vspace: Option<VSpace>,
+    elf_data: &'static [u8],
+    /// Handed to each start of the process as it was first given
+    params: SetupVer<T>,
+    policy: RestartPolicy,
+    process: Option<StandardProcess<StackBitSize>>,
+    /// What the process was made from, while it's stopped
+    resources: Option<ReclaimedProcess<StackBitSize>>,
+    restarts: usize,
+}
+
+impl<Msg: Sized, T: RetypeForSetup, StackBitSize: Unsigned> Supervisor<Msg, T, StackBitSize>
+where
+    StackBitSize: IsGreaterOrEqual<PageBits>,
+    StackBitSize: Sub<PageBits>,
+    <StackBitSize as Sub<PageBits>>::Output: Unsigned,
+    <StackBitSize as Sub<PageBits>>::Output: _Pow,
+    Pow<<StackBitSize as Sub<PageBits>>::Output>: Unsigned,
+    NumPages<StackBitSize>: Add<U2>,
+    Sum<NumPages<StackBitSize>, U2>: Unsigned,
+    Sum<NumPages<StackBitSize>, U2>: Sub<U2>,
+    Diff<Sum<NumPages<StackBitSize>, U2>, U2>: Unsigned,
+    Diff<Sum<NumPages<StackBitSize>, U2>, U2>: IsEqual<NumPages<StackBitSize>, Output = True>,
+{
+    /// Start the process in a vspace loaded from `elf_data` and made from
+    /// `vspace_resources`, with `resources`, the same as
+    /// `StandardProcess::new` takes. The process parameters and the caps in
+    /// the cspace are kept for each restart.
+    ///
+    /// Each restart loads the vspace afresh, so the image's writable memory
+    /// starts out as the image has it every time. The vspace only ever has
+    /// the image, stack and IPC buffer in it; anything else the process
+    /// uses has to reach it by way of its cspace.
+    ///
+    /// # Safety
+    ///
+    /// With a `Backoff` policy, the kernel must export the cycle counter to
+    /// user level, see `arch::enable_cycle_counter`.
+    pub unsafe fn new(
+        handler: FaultOrMessageHandler<Msg, role::Local>,
+        fault_source: FaultSource<role::Child>,
+        vspace_resources: SupervisedVSpaceResources,
+        elf_data: &'static [u8],
+        params: SetupVer<T>,
+        resources: ReclaimedProcess<StackBitSize>,
+        policy: RestartPolicy,
+        user_image: &UserImage<role::Local>,
+        local_vspace_scratch: &mut ScratchRegion,
+        parent_cnode: &LocalCap<LocalCNode>,
+        priority_authority: &LocalCap<ThreadPriorityAuthority>,
+    ) -> Result<Self, SupervisorError> {
+        if let RestartPolicy::Backoff { .. } = policy {
+            arch::enable_cycle_counter();
+        }
+        let mut supervisor = Supervisor {
+            handler,
+            fault_source_cptr: fault_source.endpoint.cptr,
+            vspace_resources,
+            vspace: None,
+            elf_data,
+            params,
+            policy,
+            process: None,
+            resources: None,
+            restarts: 0,
+        };
+        supervisor.start(
+            resources,
+            user_image,
+            local_vspace_scratch,
+            parent_cnode,
+            priority_authority,
+        )?;
+        Ok(supervisor)
+    }
+
+    fn start(
+        &mut self,
+        resources: ReclaimedProcess<StackBitSize>,
+        user_image: &UserImage<role::Local>,
+        local_vspace_scratch: &mut ScratchRegion,
+        parent_cnode: &LocalCap<LocalCNode>,
+        priority_authority: &LocalCap<ThreadPriorityAuthority>,
+    ) -> Result<(), SupervisorError> {
+        let mut vspace = self.vspace_resources.load(
+            self.elf_data,
+            user_image,
+            local_vspace_scratch,
+            parent_cnode,
+        )?;
+        // Caps are only ever named by their cptrs, which every start of
+        // the process sees the same
+        let params = unsafe { ptr::read(&self.params) };
+        let fault_source = FaultSource {
+            endpoint: Cap::wrap_cptr(self.fault_source_cptr),
+        };
+        let mut process = StandardProcess::new::<T, _>(
+            &mut vspace,
+            resources.cspace,
+            resources.parent_mapped_region,
+            parent_cnode,
+            self.elf_data,
+            params,
+            resources.ipc_buffer_ut,
+            resources.tcb_ut,
+            resources.slots,
+            priority_authority,
+            Some(fault_source),
+        )?;
+        process.start().map_err(ProcessSetupError::SeL4Error)?;
+        self.vspace = Some(vspace);
+        self.process = Some(process);
+        Ok(())
+    }
+
+    /// Stop the process and destroy its vspace, taking back what they
+    /// were made from
+    fn stop(
+        &mut self,
+        process: StandardProcess<StackBitSize>,
+        empty_cspace: bool,
+        parent_cnode: &LocalCap<LocalCNode>,
+    ) -> Result<ReclaimedProcess<StackBitSize>, SupervisorError> {
+        let resources = process.reclaim(parent_cnode, empty_cspace)?;
+        if let Some(vspace) = self.vspace.take() {
+            self.vspace_resources.destroy(vspace, parent_cnode)?;
+        }
+        Ok(resources)
+    }
+
+    /// Wait for the process to send a message or fault, handling a fault
+    /// according to the policy.
+    pub fn next_event(
+        &mut self,
+        user_image: &UserImage<role::Local>,
+        local_vspace_scratch: &mut ScratchRegion,
+        parent_cnode: &LocalCap<LocalCNode>,
+        priority_authority: &LocalCap<ThreadPriorityAuthority>,
+    ) -> Result<SupervisorEvent<Msg>, SupervisorError> {
+        let fault = match self.handler.await_message()? {
+            FaultOrMessage::Message(msg) => return Ok(SupervisorEvent::Message(msg)),
+            FaultOrMessage::Fault(fault) => fault,
+        };
+
+        let process = self.process.take().ok_or(SupervisorError::NotRunning)?;
+        let resources = self.stop(process, false, parent_cnode)?;
+        let delay = match self.policy.restart_delay(self.restarts) {
+            Some(delay) => delay,
+            None => {
+                self.resources = Some(resources);
+                return Ok(SupervisorEvent::Stopped {
+                    fault,
+                    restarts: self.restarts,
+                });
+            }
+        };
+
+        wait_cycles(delay);
+        self.restarts += 1;
+        self.start(
+            resources,
+            user_image,
+            local_vspace_scratch,
+            parent_cnode,
+            priority_authority,
+        )?;
+        Ok(SupervisorEvent::Restarted {
+            fault,
+            restarts: self.restarts,
+        })
+    }
+
+    /// The running process, e.g. to name it or bind a notification to it,
+    /// which has to be done again after each restart
+    pub fn process_mut(&mut self) -> Option<&mut StandardProcess<StackBitSize>> {
+        self.process.as_mut()
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.process.is_some()
+    }
+
+    pub fn restarts(&self) -> usize {
+        self.restarts
+    }
+
+    /// Give up on the process, handing back what it and its vspace were
+    /// made from, its cspace emptied
+    pub fn into_resources(
+        mut self,
+        parent_cnode: &LocalCap<LocalCNode>,
+    ) -> Result<(SupervisedVSpaceResources, ReclaimedProcess<StackBitSize>), SupervisorError> {
+        let resources = match (self.process.take(), self.resources.take()) {
+            (Some(process), _) => self.stop(process, true, parent_cnode)?,
+            (None, Some(resources)) => resources,
+            (None, None) => return Err(SupervisorError::NotRunning),
+        };
+        Ok((self.vspace_resources, resources))
+    }
+}
+
+/// Yield until at least `cycles` have passed
+fn wait_cycles(cycles: usize) {
+    if cycles == 0 {
+        return;
+    }
+    let start = unsafe { arch::cycle_count() };
+    while unsafe { arch::cycle_count() }.wrapping_sub(start) < cycles {
+        unsafe { seL4_Yield() };
+    }
+}
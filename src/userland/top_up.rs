@@ -0,0 +1,239 @@
+//! Topping up a running child's slots and untypeds.
+//!
+//! A child that allocates as it goes would otherwise have to be given the
+//! most it could ever need when it's spawned. A `top_up_channel` lets its
+//! supervisor hand it more later instead: the `TopUpDonor` holds back a
+//! reserve of the child's CNode slots, and gives the child some of them,
+//! or moves an untyped into one, telling the child with a `TopUp`
+//! message. The child's `TopUpReceiver` splices what it's told about into
+//! its own `WCNodeSlots` and `WUTBuddy`.
+//!
+//! Nothing goes through the kernel's cap transfer: the supervisor already
+//! has the child's CNode, so it moves caps straight into the reserved
+//! slots, and only where they are crosses the channel.
+//!
+//! ```ignore
+//! // Supervisor, with a reserve directly after the child's own slots
+//! donor.donate_slots(16)?;
+//! donor.donate_untyped(ut.weaken(), &root_cnode)?;
+//!
+//! // Child
+//! receiver.receive(&mut slots, &mut ut_buddy)?;
+//! ```
+use core::marker::PhantomData;
+
+use crate::alloc::ut_buddy::{UTBuddyError, WUTBuddy};
+use crate::cap::{
+    memory_kind, role, CNodeRole, CNodeSlot, Cap, DirectRetype, Endpoint, LocalCNode,
+    LocalCNodeSlot, LocalCap, Untyped, WCNodeSlots, WCNodeSlotsData, WUntyped,
+};
+use crate::error::SeL4Error;
+use crate::userland::{CapRights, IPCError};
+
+const SLOTS_LABEL: usize = 1;
+const UNTYPED_LABEL: usize = 2;
+
+/// What a child was given, as the child sees it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TopUp {
+    /// `size` free slots of the child's CNode, starting at `offset`
+    Slots { offset: usize, size: usize },
+    /// A general untyped of `size_bits` bits, in the child's slot `cptr`
+    Untyped { cptr: usize, size_bits: u8 },
+}
+
+#[derive(Debug)]
+pub enum TopUpError {
+    /// The donor's reserve of the child's slots has run out
+    NotEnoughSlots,
+    /// The donated slots don't follow on from the child's own, which it
+    /// hasn't used up yet; they're handed back to be kept separately
+    SlotsNotAdjacent(WCNodeSlots),
+    /// The message wasn't a top-up
+    UnexpectedLabel(usize),
+    UTBuddyError(UTBuddyError),
+    IPCError(IPCError),
+    SeL4Error(SeL4Error),
+}
+
+impl From<UTBuddyError> for TopUpError {
+    fn from(e: UTBuddyError) -> Self {
+        TopUpError::UTBuddyError(e)
+    }
+}
+
+impl From<IPCError> for TopUpError {
+    fn from(e: IPCError) -> Self {
+        TopUpError::IPCError(e)
+    }
+}
+
+impl From<SeL4Error> for TopUpError {
+    fn from(e: SeL4Error) -> Self {
+        TopUpError::SeL4Error(e)
+    }
+}
+
+/// Make a top-up channel. `reserve` is the slots of the child's CNode
+/// held back for donating.
+///
+/// For donated slots to extend the child's own, `reserve` should start
+/// directly after the slots the child was given, as when both are split
+/// from the same range with the child's first. Untypeds are moved into
+/// the end of the reserve, so they don't get in the way.
+pub fn top_up_channel<ReceiverRole: CNodeRole>(
+    untyped: LocalCap<Untyped<<Endpoint as DirectRetype>::SizeBits>>,
+    local_cnode: &LocalCap<LocalCNode>,
+    local_slot: LocalCNodeSlot,
+    receiver_slot: CNodeSlot<ReceiverRole>,
+    reserve: LocalCap<WCNodeSlotsData<role::Child>>,
+) -> Result<(TopUpDonor, TopUpReceiver<ReceiverRole>), IPCError> {
+    let local_endpoint: LocalCap<Endpoint> = untyped.retype(local_slot)?;
+    let receiver_endpoint = local_endpoint.copy(local_cnode, receiver_slot, CapRights::RW)?;
+    Ok((
+        TopUpDonor {
+            endpoint: local_endpoint,
+            reserve,
+        },
+        TopUpReceiver {
+            endpoint: receiver_endpoint,
+        },
+    ))
+}
+
+/// The supervisor's side of a top-up channel
+pub struct TopUpDonor {
+    endpoint: LocalCap<Endpoint>,
+    reserve: LocalCap<WCNodeSlotsData<role::Child>>,
+}
+
+impl TopUpDonor {
+    /// How many of the child's slots are left to donate, or move untypeds
+    /// into
+    pub fn reserved_slots(&self) -> usize {
+        self.reserve.size()
+    }
+
+    /// Give the child `count` more free slots. This blocks until the
+    /// child receives them.
+    pub fn donate_slots(&mut self, count: usize) -> Result<(), TopUpError> {
+        let slots = self
+            .reserve
+            .alloc(count)
+            .map_err(|_| TopUpError::NotEnoughSlots)?;
+        self.send(TopUp::Slots {
+            offset: slots.cap_data.offset,
+            size: slots.cap_data.size,
+        })
+    }
+
+    /// Move `ut` into the child. This blocks until the child receives
+    /// it.
+    pub fn donate_untyped(
+        &mut self,
+        ut: LocalCap<WUntyped<memory_kind::General>>,
+        local_cnode: &LocalCap<LocalCNode>,
+    ) -> Result<(), TopUpError> {
+        if self.reserve.cap_data.size == 0 {
+            return Err(TopUpError::NotEnoughSlots);
+        }
+        // Take the slot from the end, keeping the rest of the reserve
+        // contiguous with the child's slots
+        self.reserve.cap_data.size -= 1;
+        let slot: CNodeSlot<role::Child> = Cap::internal_new(
+            self.reserve.cptr,
+            self.reserve.cap_data.offset + self.reserve.cap_data.size,
+        );
+        let size_bits = ut.size_bits();
+        let child_ut = ut.move_to_slot(local_cnode, slot)?;
+        self.send(TopUp::Untyped {
+            cptr: child_ut.cptr,
+            size_bits,
+        })
+    }
+
+    fn send(&self, top_up: TopUp) -> Result<(), TopUpError> {
+        let (label, words) = match top_up {
+            TopUp::Slots { offset, size } => (SLOTS_LABEL, [offset, size]),
+            TopUp::Untyped { cptr, size_bits } => (UNTYPED_LABEL, [cptr, usize::from(size_bits)]),
+        };
+        self.endpoint.send(label, &words)?;
+        Ok(())
+    }
+}
+
+/// The child's side of a top-up channel
+#[derive(Debug)]
+pub struct TopUpReceiver<Role: CNodeRole> {
+    endpoint: Cap<Endpoint, Role>,
+}
+
+impl TopUpReceiver<role::Local> {
+    /// Wait for the next top-up, and splice it into `slots` or
+    /// `ut_buddy`.
+    pub fn receive(
+        &self,
+        slots: &mut WCNodeSlots,
+        ut_buddy: &mut WUTBuddy,
+    ) -> Result<TopUp, TopUpError> {
+        let mut words = [0; 2];
+        let (info, _) = self.endpoint.recv(&mut words)?;
+        splice(info.label(), words, slots, ut_buddy)
+    }
+
+    /// Splice in a top-up if the donor's already waiting to give one.
+    pub fn try_receive(
+        &self,
+        slots: &mut WCNodeSlots,
+        ut_buddy: &mut WUTBuddy,
+    ) -> Result<Option<TopUp>, TopUpError> {
+        let mut words = [0; 2];
+        match self.endpoint.nb_recv(&mut words)? {
+            Some((info, _)) => splice(info.label(), words, slots, ut_buddy).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+fn splice(
+    label: usize,
+    words: [usize; 2],
+    slots: &mut WCNodeSlots,
+    ut_buddy: &mut WUTBuddy,
+) -> Result<TopUp, TopUpError> {
+    match label {
+        SLOTS_LABEL => {
+            let (offset, size) = (words[0], words[1]);
+            if slots.cap_data.size == 0 {
+                slots.cap_data.offset = offset;
+                slots.cap_data.size = size;
+            } else if slots.cap_data.offset + slots.cap_data.size == offset {
+                slots.cap_data.size += size;
+            } else {
+                return Err(TopUpError::SlotsNotAdjacent(Cap {
+                    cptr: slots.cptr,
+                    cap_data: WCNodeSlotsData {
+                        offset,
+                        size,
+                        _role: PhantomData,
+                    },
+                    _role: PhantomData,
+                }));
+            }
+            Ok(TopUp::Slots { offset, size })
+        }
+        UNTYPED_LABEL => {
+            let (cptr, size_bits) = (words[0], words[1] as u8);
+            ut_buddy.add(Cap {
+                cptr,
+                cap_data: WUntyped {
+                    size_bits,
+                    kind: memory_kind::General,
+                },
+                _role: PhantomData,
+            })?;
+            Ok(TopUp::Untyped { cptr, size_bits })
+        }
+        label => Err(TopUpError::UnexpectedLabel(label)),
+    }
+}
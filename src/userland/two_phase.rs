@@ -0,0 +1,250 @@
+//! Two-phase commit of a change across several processes.
+//!
+//! A coordinator that needs several services to take on a change
+//! together, e.g. a new IP address both the TCP/IP driver and the console
+//! have to agree on, first asks each of them to prepare it. A participant
+//! checks the change and holds on to it without applying it yet,
+//! answering `Prepared`, or refuses it with its reason. Only once every
+//! participant has prepared does the coordinator tell them all to commit;
+//! if any refuses, the ones that had prepared are told to abort instead.
+//!
+//! Participants are reached over a `call_channel` each, carrying
+//! `TwoPhaseRequest`s and `TwoPhaseResponse`s, and answer with a
+//! `ParticipantLog` keeping track of the change they've prepared.
+//!
+//! ```ignore
+//! // Coordinator
+//! let mut coordinator = Coordinator::new();
+//! coordinator.run(&[&tcpip, &console], NewAddress(addr))?;
+//!
+//! // Participant
+//! responder.reply_recv_with_state(ParticipantLog::new(), |req, mut log| {
+//!     (log.handle(&mut driver, req), log)
+//! })
+//! ```
+use core::fmt;
+
+use crate::cap::role;
+use crate::userland::{Caller, IPCError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TransactionId(pub u32);
+
+impl fmt::Display for TransactionId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "txn-{}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TwoPhaseRequest<C> {
+    /// Check the change and hold on to it, without applying it yet
+    Prepare(TransactionId, C),
+    /// Apply the change prepared for the transaction
+    Commit(TransactionId),
+    /// Drop the change prepared for the transaction
+    Abort(TransactionId),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TwoPhaseResponse<R> {
+    Prepared,
+    Refused(R),
+    Committed,
+    Aborted,
+    /// A commit or abort of a transaction other than the one prepared
+    UnknownTransaction,
+}
+
+/// The coordinator's end of a participant's `call_channel`
+pub type Participant<C, R, Role> = Caller<TwoPhaseRequest<C>, TwoPhaseResponse<R>, Role>;
+
+#[derive(Debug)]
+pub enum ParticipantError {
+    IPCError(IPCError),
+    /// An answer that doesn't go with the request
+    UnexpectedResponse,
+}
+
+/// Why a change didn't go through, with the participant responsible
+/// given by its index in the participants it was run across
+#[derive(Debug)]
+pub enum TwoPhaseError<R> {
+    /// The participant refused the change, so it was aborted by the
+    /// others
+    Refused { participant: usize, reason: R },
+    /// The participant couldn't be asked to prepare the change, so it was
+    /// aborted by the others
+    PrepareFailed {
+        participant: usize,
+        error: ParticipantError,
+    },
+    /// Every participant prepared the change, and all of them were told
+    /// to commit it, but this one, the first of any, didn't acknowledge
+    /// that it had
+    CommitIncomplete {
+        participant: usize,
+        error: ParticipantError,
+    },
+}
+
+/// Runs changes across participants, numbering the transactions
+#[derive(Debug)]
+pub struct Coordinator {
+    next_transaction: u32,
+}
+
+impl Default for Coordinator {
+    fn default() -> Self {
+        Coordinator::new()
+    }
+}
+
+impl Coordinator {
+    pub fn new() -> Self {
+        Coordinator {
+            next_transaction: 1,
+        }
+    }
+
+    /// Prepare `change` with every participant in turn, then commit it
+    /// with all of them, or abort it with those that prepared it as soon
+    /// as one doesn't.
+    pub fn run<C: Copy, R>(
+        &mut self,
+        participants: &[&Participant<C, R, role::Local>],
+        change: C,
+    ) -> Result<TransactionId, TwoPhaseError<R>> {
+        let txn = TransactionId(self.next_transaction);
+        self.next_transaction = self.next_transaction.wrapping_add(1);
+
+        for (idx, participant) in participants.iter().enumerate() {
+            let failure = match participant.blocking_call(&TwoPhaseRequest::Prepare(txn, change)) {
+                Ok(TwoPhaseResponse::Prepared) => continue,
+                Ok(TwoPhaseResponse::Refused(reason)) => TwoPhaseError::Refused {
+                    participant: idx,
+                    reason,
+                },
+                Ok(_) => TwoPhaseError::PrepareFailed {
+                    participant: idx,
+                    error: ParticipantError::UnexpectedResponse,
+                },
+                Err(e) => TwoPhaseError::PrepareFailed {
+                    participant: idx,
+                    error: ParticipantError::IPCError(e),
+                },
+            };
+            // There's nothing more to do about a participant that fails
+            // to abort, the next change it prepares replaces this one
+            for prepared in &participants[..idx] {
+                let _ = prepared.blocking_call(&TwoPhaseRequest::Abort(txn));
+            }
+            return Err(failure);
+        }
+
+        // Once every participant has prepared, the change is committed
+        // with all of them, whatever the first failure
+        let mut incomplete = None;
+        for (idx, participant) in participants.iter().enumerate() {
+            let error = match participant.blocking_call(&TwoPhaseRequest::Commit(txn)) {
+                Ok(TwoPhaseResponse::Committed) => continue,
+                Ok(_) => ParticipantError::UnexpectedResponse,
+                Err(e) => ParticipantError::IPCError(e),
+            };
+            if incomplete.is_none() {
+                incomplete = Some(TwoPhaseError::CommitIncomplete {
+                    participant: idx,
+                    error,
+                });
+            }
+        }
+        match incomplete {
+            Some(e) => Err(e),
+            None => Ok(txn),
+        }
+    }
+}
+
+/// A participant's side of changes, applied by way of a `ParticipantLog`
+pub trait TwoPhaseParticipant<C, R> {
+    /// Check `change` can be applied, setting aside whatever applying it
+    /// takes, but without applying it yet
+    fn prepare(&mut self, change: &C) -> Result<(), R>;
+
+    /// Apply a change that was prepared
+    fn commit(&mut self, change: C);
+
+    /// Give back whatever preparing `change` set aside
+    fn abort(&mut self, change: C);
+}
+
+/// The change a participant has prepared, if any
+#[derive(Debug)]
+pub struct ParticipantLog<C> {
+    prepared: Option<(TransactionId, C)>,
+}
+
+impl<C> Default for ParticipantLog<C> {
+    fn default() -> Self {
+        ParticipantLog::new()
+    }
+}
+
+impl<C> ParticipantLog<C> {
+    pub fn new() -> Self {
+        ParticipantLog { prepared: None }
+    }
+
+    pub fn prepared(&self) -> Option<TransactionId> {
+        self.prepared.as_ref().map(|(txn, _)| *txn)
+    }
+
+    /// Answer a coordinator's request, calling on `participant` to
+    /// prepare, commit or abort the change
+    pub fn handle<R, P: TwoPhaseParticipant<C, R>>(
+        &mut self,
+        participant: &mut P,
+        request: TwoPhaseRequest<C>,
+    ) -> TwoPhaseResponse<R> {
+        match request {
+            TwoPhaseRequest::Prepare(txn, change) => {
+                // A coordinator that's moved on never finishes the
+                // transaction it left prepared
+                if let Some((_, stale)) = self.prepared.take() {
+                    participant.abort(stale);
+                }
+                match participant.prepare(&change) {
+                    Ok(()) => {
+                        self.prepared = Some((txn, change));
+                        TwoPhaseResponse::Prepared
+                    }
+                    Err(reason) => TwoPhaseResponse::Refused(reason),
+                }
+            }
+            TwoPhaseRequest::Commit(txn) => match self.take(txn) {
+                Some(change) => {
+                    participant.commit(change);
+                    TwoPhaseResponse::Committed
+                }
+                None => TwoPhaseResponse::UnknownTransaction,
+            },
+            TwoPhaseRequest::Abort(txn) => match self.take(txn) {
+                Some(change) => {
+                    participant.abort(change);
+                    TwoPhaseResponse::Aborted
+                }
+                None => TwoPhaseResponse::UnknownTransaction,
+            },
+        }
+    }
+
+    fn take(&mut self, txn: TransactionId) -> Option<C> {
+        match self.prepared.take() {
+            Some((prepared, change)) if prepared == txn => Some(change),
+            other => {
+                self.prepared = other;
+                None
+            }
+        }
+    }
+}
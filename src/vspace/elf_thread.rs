@@ -0,0 +1,180 @@
+//! Loading an ELF image into a new `VSpace` on a thread of the root
+//! task's own.
+//!
+//! `VSpace::new_from_elf` copies every writable page of the image before
+//! it returns, which for a few large images is most of the root task's
+//! boot time. `VSpace::new_from_elf_on_thread` does the same on a thread
+//! started with a `ThreadAuthority` from `ThreadAuthority::for_root_task`
+//! and returns straight away, so the root task can carry on setting up
+//! the other processes. The thread signals the returned `JoinHandle`'s
+//! notification once the image is loaded, and joining it hands back the
+//! new `VSpace` along with the scratch region the thread loaded it
+//! through.
+//!
+//! ```ignore
+//! let thread_authority = ThreadAuthority::for_root_task(&root_cnode, &tpa, slots)?;
+//! let mut stacks: ThreadStacks<U16> = ThreadStacks::new(root_vspace.map_region(
+//!     UnmappedMemoryRegion::new(ut, slots)?,
+//!     CapRights::RW,
+//!     arch::vm_attributes::DEFAULT | arch::vm_attributes::EXECUTE_NEVER,
+//! )?)?;
+//! let tcpip_loading = VSpace::new_from_elf_on_thread::<resources::TcpIp, _>(
+//!     retype(ut, slots)?, // paging_root
+//!     asid,
+//!     vspace_slots.weaken(), // slots
+//!     vspace_ut.weaken(),    // paging_untyped
+//!     tcpip_elf_data,
+//!     slots, // page_slots
+//!     ut,    // elf_writable_mem
+//!     &user_image,
+//!     &root_cnode,
+//!     loader_scratch,
+//!     &thread_authority,
+//!     &root_vspace,
+//!     &mut stacks,
+//!     loader_ipc_buffer,
+//!     ut, // tcb_ut
+//!     ut, // notification_ut
+//!     slots,
+//! )?;
+//! // ...set up the other processes...
+//! let (mut tcpip_vspace, loader_scratch) = tcpip_loading.join(&mut stacks)?;
+//! ```
+use core::marker::PhantomData;
+
+use typenum::*;
+
+use crate::arch::{NotificationBits, PageBits, PagingRoot, TCBBits};
+use crate::bootstrap::UserImage;
+use crate::cap::{
+    memory_kind, role, CNode, Cap, LocalCNode, LocalCNodeSlots, LocalCap, UnassignedASID, Untyped,
+    WCNodeSlots, WUntyped,
+};
+use crate::userland::{JoinHandle, ThreadAuthority, ThreadSetupError, ThreadStacks};
+
+use super::{
+    shared_status, vspace_state, ElfProc, MappedMemoryRegion, ScratchRegion, VSpace, VSpaceError,
+};
+
+/// What `VSpace::new_from_elf_on_thread`'s thread hands back: the new
+/// `VSpace`, and the scratch region it was loaded through
+pub type ElfLoadResult = Result<(VSpace, ScratchRegion), VSpaceError>;
+
+/// Everything the loading thread needs, owned so it can be moved onto
+/// the thread's stack
+struct ElfLoadJob {
+    paging_root: LocalCap<PagingRoot>,
+    asid: LocalCap<UnassignedASID>,
+    slots: WCNodeSlots,
+    paging_untyped: LocalCap<WUntyped<memory_kind::General>>,
+    elf_data: &'static [u8],
+    page_slots: WCNodeSlots,
+    elf_writable_mem: LocalCap<WUntyped<memory_kind::General>>,
+    user_image: UserImage<role::Local>,
+    parent_cnode: LocalCap<LocalCNode>,
+    scratch: ScratchRegion,
+    segment_hashes: &'static [u64],
+    segment_page_counts: &'static [usize],
+    reject_shared_pages: bool,
+}
+
+fn load_elf(job: ElfLoadJob) -> ElfLoadResult {
+    let ElfLoadJob {
+        paging_root,
+        asid,
+        slots,
+        paging_untyped,
+        elf_data,
+        page_slots,
+        elf_writable_mem,
+        user_image,
+        parent_cnode,
+        mut scratch,
+        segment_hashes,
+        segment_page_counts,
+        reject_shared_pages,
+    } = job;
+    let vspace = VSpace::new_from_elf_internal(
+        paging_root,
+        asid,
+        slots,
+        paging_untyped,
+        elf_data,
+        page_slots,
+        elf_writable_mem,
+        &user_image,
+        &parent_cnode,
+        &mut scratch,
+        segment_hashes,
+        segment_page_counts,
+        reject_shared_pages,
+        &mut |_| (),
+    )?;
+    Ok((vspace, scratch))
+}
+
+impl VSpace<vspace_state::Imaged, role::Local> {
+    /// Like `new_from_elf`, loading the image on a thread of the root
+    /// task's own, started with `thread_authority` in `root_vspace` with a
+    /// stack from `stacks`.
+    ///
+    /// The thread loads the image through `local_vspace_scratch`, which
+    /// it has to itself until it's joined. Errors loading the image come
+    /// back from the join.
+    pub fn new_from_elf_on_thread<E: ElfProc, StackBitSize: Unsigned>(
+        paging_root: LocalCap<PagingRoot>,
+        asid: LocalCap<UnassignedASID>,
+        slots: WCNodeSlots,
+        paging_untyped: LocalCap<WUntyped<memory_kind::General>>,
+        // Things relating to user image code
+        elf_data: &'static [u8],
+        page_slots: LocalCNodeSlots<E::RequiredPages>,
+        elf_writable_mem: LocalCap<Untyped<E::RequiredMemoryBits>>,
+        user_image: &UserImage<role::Local>,
+        parent_cnode: &LocalCap<LocalCNode>,
+        local_vspace_scratch: ScratchRegion,
+        // Things relating to the loading thread
+        thread_authority: &ThreadAuthority<role::Local>,
+        root_vspace: &VSpace,
+        stacks: &mut ThreadStacks<StackBitSize>,
+        ipc_buffer: MappedMemoryRegion<PageBits, shared_status::Exclusive>,
+        tcb_ut: LocalCap<Untyped<TCBBits>>,
+        notification_ut: LocalCap<Untyped<NotificationBits>>,
+        thread_slots: LocalCNodeSlots<U2>,
+    ) -> Result<JoinHandle<ElfLoadResult, StackBitSize>, ThreadSetupError> {
+        let job = ElfLoadJob {
+            paging_root,
+            asid,
+            slots,
+            paging_untyped,
+            elf_data,
+            // As long as the elf binary agrees with the types in E (which were
+            // extracted from the elf binary), we're good for resource capacity.
+            page_slots: page_slots.weaken(),
+            elf_writable_mem: elf_writable_mem.weaken(),
+            user_image: user_image.alias(),
+            parent_cnode: Cap {
+                cptr: parent_cnode.cptr,
+                cap_data: CNode {
+                    radix: parent_cnode.cap_data.radix,
+                    _role: PhantomData,
+                },
+                _role: PhantomData,
+            },
+            scratch: local_vspace_scratch,
+            segment_hashes: E::SEGMENT_HASHES,
+            segment_page_counts: E::SEGMENT_PAGE_COUNTS,
+            reject_shared_pages: E::REJECT_SHARED_SEGMENT_PAGES,
+        };
+        thread_authority.spawn(
+            root_vspace,
+            stacks,
+            load_elf,
+            job,
+            ipc_buffer,
+            tcb_ut,
+            notification_ut,
+            thread_slots,
+        )
+    }
+}
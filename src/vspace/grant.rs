@@ -0,0 +1,155 @@
+//! Lending part of a shared region to another process, without copying.
+//!
+//! A process holding a shared region, e.g. a driver's receive buffers,
+//! can hand a window of it to another process for that process to work on
+//! in place: `lend` maps copies of the pages of a sub-range into the
+//! borrower's vspace with just the rights given, read-only or read-write,
+//! and `SharedMemoryGrant::revoke` unmaps and deletes them again.
+//!
+//! A grant borrows the region it was lent from, so until every grant of
+//! it has been revoked the region can't be unmapped or moved, nor written
+//! through `as_mut_slice`.
+use core::marker::PhantomData;
+use core::ops::Sub;
+
+use typenum::*;
+
+use crate::arch::{self, PageBits, PageBytes};
+use crate::cap::{
+    page_state, role, CapRange, InternalASID, LocalCNode, LocalCNodeSlots, LocalCap, Page,
+};
+use crate::pow::{Pow, _Pow};
+use crate::userland::Rights;
+
+use super::{
+    shared_status, CacheStatus, MappedMemoryRegion, NumPages, UnmappedMemoryRegion, VSpace,
+    VSpaceError,
+};
+
+/// A sub-range of a shared region, mapped into a borrower's vspace with
+/// `GrantRights`, until it's revoked
+pub struct SharedMemoryGrant<'a, GrantSizeBits: Unsigned, GrantRights: Rights>
+where
+    GrantSizeBits: IsGreaterOrEqual<PageBits>,
+    GrantSizeBits: Sub<PageBits>,
+    <GrantSizeBits as Sub<PageBits>>::Output: Unsigned,
+    <GrantSizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<GrantSizeBits as Sub<PageBits>>::Output>: Unsigned,
+{
+    /// The borrower's mapping of the lent pages
+    borrowed: MappedMemoryRegion<GrantSizeBits, shared_status::Shared>,
+    /// Where the lent pages start in the lender's mapping
+    lender_vaddr: usize,
+    _lender: PhantomData<&'a ()>,
+    _rights: PhantomData<GrantRights>,
+}
+
+impl<SizeBits: Unsigned, CS: CacheStatus>
+    MappedMemoryRegion<SizeBits, shared_status::Shared, role::Local, CS>
+where
+    SizeBits: IsGreaterOrEqual<PageBits>,
+    SizeBits: Sub<PageBits>,
+    <SizeBits as Sub<PageBits>>::Output: Unsigned,
+    <SizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+{
+    /// Lend the `2^GrantSizeBits` bytes starting `page_offset` pages into
+    /// this region to the process with `borrower_vspace`, with only
+    /// `GrantRights` to them. The borrower's copies of the page caps go in
+    /// `slots`.
+    pub fn lend<GrantSizeBits: Unsigned, GrantRights: Rights>(
+        &self,
+        page_offset: usize,
+        borrower_vspace: &mut VSpace,
+        vm_attributes: arch::VMAttributes,
+        slots: LocalCNodeSlots<NumPages<GrantSizeBits>>,
+        cnode: &LocalCap<LocalCNode>,
+    ) -> Result<SharedMemoryGrant<'_, GrantSizeBits, GrantRights>, VSpaceError>
+    where
+        GrantSizeBits: IsGreaterOrEqual<PageBits>,
+        GrantSizeBits: Sub<PageBits>,
+        <GrantSizeBits as Sub<PageBits>>::Output: Unsigned,
+        <GrantSizeBits as Sub<PageBits>>::Output: _Pow,
+        Pow<<GrantSizeBits as Sub<PageBits>>::Output>: Unsigned,
+    {
+        let grant_pages = <NumPages<GrantSizeBits>>::USIZE;
+        if page_offset
+            .checked_add(grant_pages)
+            .map_or(true, |end| end > <NumPages<SizeBits>>::USIZE)
+        {
+            return Err(VSpaceError::GrantOutOfRange);
+        }
+
+        let lender_vaddr = self.vaddr() + page_offset * PageBytes::USIZE;
+        let lent_pages: CapRange<Page<page_state::Mapped>, role::Local, NumPages<GrantSizeBits>> =
+            CapRange::new(
+                self.caps.start_cptr + page_offset,
+                Page {
+                    state: page_state::Mapped {
+                        vaddr: lender_vaddr,
+                        asid: self.asid(),
+                        rights: self.rights(),
+                    },
+                },
+            );
+        let rights = GrantRights::as_caprights();
+        let copies = lent_pages.copy(cnode, slots, rights)?;
+        let borrowed = borrower_vspace.map_region_internal(
+            UnmappedMemoryRegion::<GrantSizeBits, shared_status::Shared>::from_caps(
+                copies, self.kind,
+            ),
+            rights,
+            vm_attributes,
+        )?;
+
+        Ok(SharedMemoryGrant {
+            borrowed,
+            lender_vaddr,
+            _lender: PhantomData,
+            _rights: PhantomData,
+        })
+    }
+}
+
+impl<'a, GrantSizeBits: Unsigned, GrantRights: Rights>
+    SharedMemoryGrant<'a, GrantSizeBits, GrantRights>
+where
+    GrantSizeBits: IsGreaterOrEqual<PageBits>,
+    GrantSizeBits: Sub<PageBits>,
+    <GrantSizeBits as Sub<PageBits>>::Output: Unsigned,
+    <GrantSizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<GrantSizeBits as Sub<PageBits>>::Output>: Unsigned,
+{
+    /// Where the lent pages are mapped in the borrower's vspace, for
+    /// telling the borrower about them
+    pub fn vaddr(&self) -> usize {
+        self.borrowed.vaddr()
+    }
+
+    /// Where the lent pages are in the lender's mapping of the region
+    pub fn lender_vaddr(&self) -> usize {
+        self.lender_vaddr
+    }
+
+    pub fn size_bytes(&self) -> usize {
+        self.borrowed.size_bytes()
+    }
+
+    pub(crate) fn borrower_asid(&self) -> InternalASID {
+        self.borrowed.asid()
+    }
+
+    /// Take the pages back from the borrower, unmapping and deleting its
+    /// copies of them, and hand back the slots the copies were in
+    pub fn revoke(
+        self,
+        borrower_vspace: &mut VSpace,
+        cnode: &LocalCap<LocalCNode>,
+    ) -> Result<LocalCNodeSlots<NumPages<GrantSizeBits>>, VSpaceError> {
+        if self.borrower_asid() != borrower_vspace.asid() {
+            return Err(VSpaceError::ASIDMismatch);
+        }
+        let unmapped = borrower_vspace.reclaim_region(self.borrowed)?;
+        Ok(unmapped.delete_caps(cnode)?)
+    }
+}
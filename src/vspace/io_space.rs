@@ -0,0 +1,200 @@
+//! Confining a device's DMA to an IOMMU address space.
+//!
+//! With an IOMMU, the addresses a device uses for DMA are translated much
+//! like a process's virtual addresses, so the device can only reach what's
+//! been mapped into its IOSpace, rather than any physical address it's
+//! programmed with. An `IOSpace` maps regions the way a `VSpace` does,
+//! picking the IO addresses itself and retyping the IOMMU's translation
+//! tables as they turn out to be needed.
+//!
+//! This covers the x86 IOMMU. A device behind an ARM SMMU translates
+//! through an ordinary vspace instead.
+use core::ops::Sub;
+
+use typenum::*;
+
+use crate::alloc::ut_buddy::{UTBuddyError, WUTBuddy};
+use crate::arch::{self, PageBits, PageBytes};
+use crate::cap::{
+    page_state, LocalCNode, LocalCNodeSlots, LocalCap, Page, RetypeError, WCNodeSlots,
+};
+use crate::error::{KernelError, SeL4Error};
+use crate::pow::{Pow, _Pow};
+use crate::userland::CapRights;
+
+use super::{shared_status, NumPages, UnmappedMemoryRegion};
+
+/// More levels than any IOMMU's translation tables have
+const MAX_IO_PAGE_TABLE_LEVELS: usize = 6;
+
+#[derive(Debug)]
+pub enum IOSpaceError {
+    /// Mapping a page still failed after mapping as many levels of
+    /// translation tables as any IOMMU has
+    TooManyPageTableLevels,
+    /// The mapping would have run past the end of the IO address space
+    ExceededAddressableSpace,
+    UTBuddyError(UTBuddyError),
+    RetypeError(RetypeError),
+    SeL4Error(SeL4Error),
+}
+
+impl From<UTBuddyError> for IOSpaceError {
+    fn from(e: UTBuddyError) -> Self {
+        IOSpaceError::UTBuddyError(e)
+    }
+}
+
+impl From<RetypeError> for IOSpaceError {
+    fn from(e: RetypeError) -> Self {
+        IOSpaceError::RetypeError(e)
+    }
+}
+
+impl From<SeL4Error> for IOSpaceError {
+    fn from(e: SeL4Error) -> Self {
+        IOSpaceError::SeL4Error(e)
+    }
+}
+
+/// The IO addresses one device's DMA goes through
+pub struct IOSpace {
+    io_space: LocalCap<arch::cap::IOSpace>,
+    untyped: WUTBuddy,
+    slots: WCNodeSlots,
+    next_ioaddr: usize,
+}
+
+impl IOSpace {
+    /// `io_space` should be minted for the device, see
+    /// `arch::cap::IOSpace::for_device`. The translation tables are
+    /// retyped from `untyped` into `slots`.
+    pub fn new(
+        io_space: LocalCap<arch::cap::IOSpace>,
+        untyped: WUTBuddy,
+        slots: WCNodeSlots,
+    ) -> Self {
+        IOSpace {
+            io_space,
+            untyped,
+            slots,
+            // Leave the first page unmapped, so that a device DMAing to a
+            // zeroed address faults
+            next_ioaddr: PageBytes::USIZE,
+        }
+    }
+
+    /// Map a shared region for the device, at some IO address, I don't
+    /// care where. Like `VSpace::map_shared_region`, the region's caps are
+    /// copied into `slots` and the region itself is only borrowed, so it
+    /// can be mapped into the driver's vspace as well.
+    ///
+    /// IO addresses aren't reused once the region's unmapped.
+    pub fn map_region<SizeBits: Unsigned>(
+        &mut self,
+        region: &UnmappedMemoryRegion<SizeBits, shared_status::Shared>,
+        rights: CapRights,
+        slots: LocalCNodeSlots<NumPages<SizeBits>>,
+        cnode: &LocalCap<LocalCNode>,
+    ) -> Result<IOMappedMemoryRegion<SizeBits>, IOSpaceError>
+    where
+        SizeBits: IsGreaterOrEqual<PageBits>,
+        SizeBits: Sub<PageBits>,
+        <SizeBits as Sub<PageBits>>::Output: Unsigned,
+        <SizeBits as Sub<PageBits>>::Output: _Pow,
+        Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+    {
+        let ioaddr = self.next_ioaddr;
+        let end = ioaddr
+            .checked_add(region.size_bytes())
+            .ok_or(IOSpaceError::ExceededAddressableSpace)?;
+        let caps = region.caps.copy(cnode, slots, rights)?;
+
+        let mut mapped = 0;
+        let result = caps.for_each(|page| {
+            self.map_page(page, rights, ioaddr + mapped * PageBytes::USIZE)?;
+            mapped += 1;
+            Ok(())
+        });
+        if let Err(e) = result {
+            let _ = caps.for_each(|page| {
+                if mapped == 0 {
+                    return Err(());
+                }
+                mapped -= 1;
+                let _ = page.unmap_io();
+                Ok(())
+            });
+            return Err(e);
+        }
+
+        self.next_ioaddr = end;
+        Ok(IOMappedMemoryRegion {
+            region: UnmappedMemoryRegion::from_caps(caps, region.kind),
+            ioaddr,
+        })
+    }
+
+    fn map_page(
+        &mut self,
+        page: &LocalCap<Page<page_state::Unmapped>>,
+        rights: CapRights,
+        ioaddr: usize,
+    ) -> Result<(), IOSpaceError> {
+        for _ in 0..MAX_IO_PAGE_TABLE_LEVELS {
+            match page.map_io(&self.io_space, rights, ioaddr) {
+                Ok(()) => return Ok(()),
+                Err(KernelError::FailedLookup) => {
+                    let ut = self
+                        .untyped
+                        .alloc(&mut self.slots, arch::IOPageTableBits::U8)?;
+                    let table: LocalCap<arch::cap::IOPageTable> = ut.retype(&mut self.slots)?;
+                    table.map_io(&self.io_space, ioaddr)?;
+                }
+                Err(e) => return Err(SeL4Error::PageMapIO(e).into()),
+            }
+        }
+        Err(IOSpaceError::TooManyPageTableLevels)
+    }
+}
+
+/// A region mapped into an `IOSpace`, at the address the device should
+/// be given for it
+pub struct IOMappedMemoryRegion<SizeBits: Unsigned>
+where
+    SizeBits: IsGreaterOrEqual<PageBits>,
+    SizeBits: Sub<PageBits>,
+    <SizeBits as Sub<PageBits>>::Output: Unsigned,
+    <SizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+{
+    // N.B. The page caps are typed unmapped, since they aren't mapped in
+    // any vspace, but they're in use by the IOSpace until unmapped
+    region: UnmappedMemoryRegion<SizeBits, shared_status::Shared>,
+    ioaddr: usize,
+}
+
+impl<SizeBits: Unsigned> IOMappedMemoryRegion<SizeBits>
+where
+    SizeBits: IsGreaterOrEqual<PageBits>,
+    SizeBits: Sub<PageBits>,
+    <SizeBits as Sub<PageBits>>::Output: Unsigned,
+    <SizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+{
+    /// The address the device sees the region at
+    pub fn ioaddr(&self) -> usize {
+        self.ioaddr
+    }
+
+    pub fn size_bytes(&self) -> usize {
+        self.region.size_bytes()
+    }
+
+    /// Take the region back out of the IOSpace, once the device has been
+    /// told to stop using it.
+    pub fn unmap(self) -> Result<UnmappedMemoryRegion<SizeBits, shared_status::Shared>, SeL4Error> {
+        self.region.caps.for_each(|page| page.unmap_io())?;
+        Ok(self.region)
+    }
+}
@@ -4,9 +4,12 @@
 //! This architecture-independent realization of that concept uses
 //! memory _regions_ rather than expose the granules that each layer
 //! in the addressing structures is responsible for mapping.
+use core::cell::Cell;
 use core::marker::PhantomData;
 use core::ops::Sub;
 
+use arrayvec::ArrayVec;
+
 use typenum::*;
 
 use crate::alloc::ut_buddy::{self, UTBuddyError, WUTBuddy};
@@ -14,15 +17,39 @@ use crate::arch::{self, AddressSpace, PageBits, PageBytes, PagingRoot, PagingRoo
 use crate::bootstrap::UserImage;
 use crate::cap::{
     memory_kind, page_state, role, AssignedASID, CNodeRole, CNodeSlots, Cap, CapRange, CapType,
-    ChildCNodeSlot, DirectRetype, InternalASID, LocalCNode, LocalCNodeSlots, LocalCap, Page,
-    PhantomCap, RetypeError, UnassignedASID, Untyped, WCNodeSlots, WCNodeSlotsData, WUntyped,
-    WeakCapRange, WeakCopyError,
+    DirectRetype, InternalASID, LocalCNode, LocalCNodeSlots, LocalCap, Page, PhantomCap,
+    RetypeError, UnassignedASID, Untyped, WCNodeSlots, WCNodeSlotsData, WUntyped, WeakCapRange,
+    WeakCopyError,
 };
 use crate::error::SeL4Error;
 use crate::pow::{Pow, _Pow};
 use crate::userland::CapRights;
+mod elf_thread;
+mod grant;
+#[cfg(all(target_arch = "x86_64", KernelIOMMU))]
+mod io_space;
+mod pinned;
 mod region;
+mod region_registry;
+mod relocation;
+mod scratch;
+mod self_hosted;
+mod window;
+pub use elf_thread::*;
+pub use grant::*;
+#[cfg(all(target_arch = "x86_64", KernelIOMMU))]
+pub use io_space::*;
+pub use pinned::*;
 pub use region::*;
+pub use region_registry::*;
+pub use relocation::{elf_entry_point, elf_load_bias, PIE_LOAD_BIAS};
+pub use scratch::*;
+pub use self_hosted::*;
+pub use window::*;
+
+use relocation::{check_relocations, relocate_page};
+use scratch::map_temporarily;
+use window::AddressWindows;
 
 include!(concat!(env!("OUT_DIR"), "/KERNEL_RETYPE_FAN_OUT_LIMIT"));
 
@@ -44,6 +71,40 @@ pub trait ElfProc: Sized {
 
     /// How much memory is needed for the process stack, as a bitsize.
     type StackSizeBits: Unsigned;
+
+    /// The `elf_segment_hash` of each loadable segment's file contents,
+    /// in program header order, checked as the image is loaded. Empty to
+    /// skip the check.
+    const SEGMENT_HASHES: &'static [u64] = &[];
+
+    /// How many pages each loadable segment is mapped with, in program
+    /// header order, checked before the image is loaded. Empty to skip
+    /// the check.
+    const SEGMENT_PAGE_COUNTS: &'static [usize] = &[];
+
+    /// Whether to refuse to load the image if segments share a page,
+    /// rather than mapping the page with the rights of all of them. A page
+    /// shared by a writable and an executable segment is always refused.
+    const REJECT_SHARED_SEGMENT_PAGES: bool = false;
+}
+
+/// The hash of an ELF segment's contents, 64-bit FNV-1a.
+///
+/// This only guards against corruption of the image, e.g. a damaged
+/// archive, not tampering.
+pub fn elf_segment_hash(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// How far along loading an ELF image is, as reported to the progress
+/// callback of `VSpace::new_from_elf_with_progress`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ElfLoadProgress {
+    /// Pages copied or mapped into the new vspace so far
+    pub pages_loaded: usize,
+    pub total_pages: usize,
 }
 
 pub trait VSpaceState: private::SealedVSpaceState {}
@@ -156,6 +217,68 @@ pub enum VSpaceError {
     InvalidRegionSize,
     ElfParseError(&'static str),
     InsufficientResourcesForElf,
+    /// A loadable segment's contents didn't match the hash recorded for it
+    /// at build time, or the image has a different number of segments.
+    ElfSegmentHashMismatch {
+        segment: usize,
+    },
+    /// A loadable segment is both writable and executable
+    ElfSegmentWritableAndExecutable {
+        segment: usize,
+    },
+    /// A loadable segment shares a page with the one before it, and one
+    /// of them is writable and the other executable, the image refuses
+    /// shared pages, or both are read-only with different contents for
+    /// the page
+    ElfSegmentsSharePage {
+        segment: usize,
+    },
+    /// A loadable segment needs a different number of pages than was
+    /// recorded for it at build time, or the image has a different
+    /// number of segments.
+    ElfSegmentPageCountMismatch {
+        segment: usize,
+    },
+    /// A position-independent image has a relocation other than a
+    /// relative one, which would need a dynamic linker
+    ElfUnsupportedRelocation {
+        kind: u32,
+    },
+    /// A relocation would patch a read-only segment, which is mapped
+    /// straight from the root task's image and can't be written
+    ElfRelocationOutsideWritableSegment {
+        offset: usize,
+    },
+    /// There's no address window reserved by the name given
+    UnknownAddressWindow,
+    /// An address window by that name is already reserved
+    DuplicateAddressWindow,
+    /// As many ranges of reclaimed addresses as a vspace keeps track of
+    /// are already kept, see `MAX_RECLAIMED_RANGES`
+    TooManyReclaimedRanges,
+    /// As many address windows as a vspace keeps track of are already
+    /// reserved, see `MAX_ADDRESS_WINDOWS`
+    TooManyAddressWindows,
+    /// The address window hasn't enough room left for the region
+    AddressWindowFull,
+    /// A mapping at an explicit address would have overlapped a reserved
+    /// address window
+    OverlapsAddressWindow,
+    /// Part of the range to lend would have been past the end of the
+    /// region
+    GrantOutOfRange,
+    /// The scratch region is already checked out, see
+    /// `ScratchRegion::checkout`
+    ScratchRegionInUse,
+    /// The region has more pages than the scratch region has room for
+    ScratchRegionTooSmall,
+    /// Every scratch region in the pool is checked out
+    ScratchPoolExhausted,
+    /// The pool already holds `MAX_SCRATCH_POOL_REGIONS` scratch regions
+    ScratchPoolFull,
+    /// There wasn't untyped memory to make a region from, see
+    /// `SelfHostedVSpace::new_region`
+    UTBuddyError(UTBuddyError),
 }
 
 impl From<RetypeError> for VSpaceError {
@@ -411,11 +534,24 @@ fn iterate_by_page(start: usize, end: usize) -> impl Iterator<Item = (usize, usi
     ByPageIterator { next: start, end }
 }
 
+/// The range of addresses an ELF segment is loaded into. Writable
+/// segments are loaded in full, the rest only as far as the file has
+/// contents for them.
+fn loaded_range(h: &xmas_elf::program::ProgramHeader) -> (usize, usize) {
+    let start = h.virtual_addr() as usize;
+    let size = if h.flags().is_write() {
+        h.mem_size()
+    } else {
+        h.file_size()
+    };
+    (start, start + size as usize)
+}
+
 impl VSpace<vspace_state::Imaged, role::Local> {
     /// Unmap a region.
-    pub fn unmap_region<SizeBits: Unsigned, SS: SharedStatus>(
+    pub fn unmap_region<SizeBits: Unsigned, SS: SharedStatus, CS: CacheStatus>(
         &mut self,
-        region: MappedMemoryRegion<SizeBits, SS>,
+        region: MappedMemoryRegion<SizeBits, SS, role::Local, CS>,
     ) -> Result<UnmappedMemoryRegion<SizeBits, SS>, VSpaceError>
     where
         SizeBits: IsGreaterOrEqual<PageBits>,
@@ -425,7 +561,7 @@ impl VSpace<vspace_state::Imaged, role::Local> {
         Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
     {
         self.weak_unmap_region(region.weaken())
-            .and_then(|r| r.as_strong::<SizeBits>())
+            .and_then(|r| r.as_strong::<SizeBits, _>())
     }
     /// Unmap a weak region.
     pub fn weak_unmap_region<SS: SharedStatus>(
@@ -448,6 +584,57 @@ impl VSpace<vspace_state::Imaged, role::Local> {
         ))
     }
 
+    /// Unmap a region and give its addresses back to the vspace, so that
+    /// regions mapped later, e.g. for a restarted process, can reuse them.
+    ///
+    /// The unmapped region holds the same page caps, for mapping again or
+    /// revoking to recover the memory. The paging structures created for
+    /// the mapping stay in place for whatever is mapped there next.
+    pub fn reclaim_region<SizeBits: Unsigned, SS: SharedStatus, CS: CacheStatus>(
+        &mut self,
+        region: MappedMemoryRegion<SizeBits, SS, role::Local, CS>,
+    ) -> Result<UnmappedMemoryRegion<SizeBits, SS>, VSpaceError>
+    where
+        SizeBits: IsGreaterOrEqual<PageBits>,
+        SizeBits: Sub<PageBits>,
+        <SizeBits as Sub<PageBits>>::Output: Unsigned,
+        <SizeBits as Sub<PageBits>>::Output: _Pow,
+        Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+    {
+        self.weak_reclaim_region(region.weaken())
+            .and_then(|r| r.as_strong::<SizeBits, _>())
+    }
+    /// Unmap a weak region and give its addresses back to the vspace.
+    pub fn weak_reclaim_region<SS: SharedStatus>(
+        &mut self,
+        region: WeakMappedMemoryRegion<SS>,
+    ) -> Result<WeakUnmappedMemoryRegion<SS>, VSpaceError> {
+        let vaddr = region.vaddr();
+        let size_bits = region.size_bits();
+        // Addresses in a window stay in the window, any others have to be
+        // kept track of to be reused
+        let size_bytes = bytes_from_size_bits(size_bits);
+        if self
+            .available_address_range
+            .windows
+            .overlapping(vaddr, size_bytes)
+            .is_none()
+        {
+            self.available_address_range
+                .check_unmapping(vaddr, size_bits)?;
+        }
+        let unmapped = self.weak_unmap_region(region)?;
+        if !self
+            .available_address_range
+            .windows
+            .release(vaddr, size_bytes)
+        {
+            self.available_address_range
+                .observe_unmapping(vaddr, size_bits);
+        }
+        Ok(unmapped)
+    }
+
     fn unmap_page(
         &mut self,
         page: LocalCap<Page<page_state::Mapped>>,
@@ -455,46 +642,37 @@ impl VSpace<vspace_state::Imaged, role::Local> {
         page.unmap()
     }
 
-    // This function will move the caps into the child's CSpace so
-    // that it may use it.
-    pub(crate) fn for_child(
-        self,
-        src_cnode: &LocalCap<LocalCNode>,
-        child_root_slot: ChildCNodeSlot,
-        mut ut_transfer_slots: LocalCap<WCNodeSlotsData<role::Child>>,
-        child_paging_slots: Cap<WCNodeSlotsData<role::Child>, role::Child>,
-    ) -> Result<VSpace<vspace_state::Imaged, role::Child>, VSpaceError> {
-        let VSpace {
-            root,
-            asid,
-            layers,
-            untyped,
-            slots: _,
-            available_address_range,
-            ..
-        } = self;
-        let child_root = root.move_to_slot(src_cnode, child_root_slot)?;
-        let child_untyped = untyped
-            .move_to_child(src_cnode, &mut ut_transfer_slots)
-            .map_err(|e| match e {
-                UTBuddyError::NotEnoughSlots => VSpaceError::InsufficientCNodeSlots,
-                UTBuddyError::SeL4Error(se) => VSpaceError::SeL4Error(se),
-                _ => unreachable!(
-                    "All other UTBuddyError variants are irrelevant for the move_to_child call"
-                ),
-            })?;
-        Ok(VSpace {
-            root: child_root,
+    pub fn new_from_elf<E: ElfProc>(
+        paging_root: LocalCap<PagingRoot>,
+        asid: LocalCap<UnassignedASID>,
+        slots: WCNodeSlots,
+        paging_untyped: LocalCap<WUntyped<memory_kind::General>>,
+        // Things relating to user image code
+        elf_data: &[u8],
+        page_slots: LocalCNodeSlots<E::RequiredPages>,
+        elf_writable_mem: LocalCap<Untyped<E::RequiredMemoryBits>>,
+        user_image: &UserImage<role::Local>,
+        parent_cnode: &LocalCap<LocalCNode>,
+        local_vspace_scratch: &mut ScratchRegion,
+    ) -> Result<Self, VSpaceError> {
+        Self::new_from_elf_with_progress::<E, _>(
+            paging_root,
             asid,
-            layers,
-            untyped: child_untyped,
-            slots: child_paging_slots,
-            available_address_range,
-            _state: PhantomData,
-        })
+            slots,
+            paging_untyped,
+            elf_data,
+            page_slots,
+            elf_writable_mem,
+            user_image,
+            parent_cnode,
+            local_vspace_scratch,
+            |_| (),
+        )
     }
 
-    pub fn new_from_elf<E: ElfProc>(
+    /// Like `new_from_elf`, calling `progress` after each page is loaded,
+    /// e.g. to show boot progress for large images.
+    pub fn new_from_elf_with_progress<E: ElfProc, F: FnMut(ElfLoadProgress)>(
         paging_root: LocalCap<PagingRoot>,
         asid: LocalCap<UnassignedASID>,
         slots: WCNodeSlots,
@@ -506,8 +684,9 @@ impl VSpace<vspace_state::Imaged, role::Local> {
         user_image: &UserImage<role::Local>,
         parent_cnode: &LocalCap<LocalCNode>,
         local_vspace_scratch: &mut ScratchRegion,
+        mut progress: F,
     ) -> Result<Self, VSpaceError> {
-        Self::new_from_elf_weak(
+        Self::new_from_elf_internal(
             paging_root,
             asid,
             slots,
@@ -520,6 +699,10 @@ impl VSpace<vspace_state::Imaged, role::Local> {
             user_image,
             parent_cnode,
             local_vspace_scratch,
+            E::SEGMENT_HASHES,
+            E::SEGMENT_PAGE_COUNTS,
+            E::REJECT_SHARED_SEGMENT_PAGES,
+            &mut progress,
         )
     }
 
@@ -536,28 +719,201 @@ impl VSpace<vspace_state::Imaged, role::Local> {
         parent_cnode: &LocalCap<LocalCNode>,
         local_vspace_scratch: &mut ScratchRegion,
     ) -> Result<Self, VSpaceError> {
-        let mut vspace =
-            VSpace::<vspace_state::Empty>::new(paging_root, asid, slots, paging_untyped)?;
+        Self::new_from_elf_internal(
+            paging_root,
+            asid,
+            slots,
+            paging_untyped,
+            elf_data,
+            page_slots,
+            elf_writable_mem,
+            user_image,
+            parent_cnode,
+            local_vspace_scratch,
+            &[],
+            &[],
+            false,
+            &mut |_| (),
+        )
+    }
 
+    fn new_from_elf_internal(
+        paging_root: LocalCap<PagingRoot>,
+        asid: LocalCap<UnassignedASID>,
+        slots: WCNodeSlots,
+        paging_untyped: LocalCap<WUntyped<memory_kind::General>>,
+        // Things relating to user image code
+        elf_data: &[u8],
+        mut page_slots: WCNodeSlots,
+        elf_writable_mem: LocalCap<WUntyped<memory_kind::General>>,
+        user_image: &UserImage<role::Local>,
+        parent_cnode: &LocalCap<LocalCNode>,
+        local_vspace_scratch: &mut ScratchRegion,
+        segment_hashes: &[u64],
+        segment_page_counts: &[usize],
+        reject_shared_pages: bool,
+        progress: &mut dyn FnMut(ElfLoadProgress),
+    ) -> Result<Self, VSpaceError> {
         let elf = xmas_elf::ElfFile::new(elf_data).map_err(VSpaceError::ElfParseError)?;
+        // Position-independent images are loaded above where they were
+        // linked, see `elf_load_bias`
+        let load_bias = elf_load_bias(&elf);
+        let load_headers = || {
+            elf.program_iter()
+                .filter(|h| h.get_type() == Ok(xmas_elf::program::Type::Load))
+        };
+
+        // Check the whole image before loading any of it
+        if !segment_hashes.is_empty() {
+            let mut segment_count = 0;
+            for (segment, program_header) in load_headers().enumerate() {
+                let start = program_header.offset() as usize;
+                let end = start + program_header.file_size() as usize;
+                let contents = elf_data.get(start..end).ok_or(VSpaceError::ElfParseError(
+                    "Segment extends past end of file",
+                ))?;
+                if segment_hashes.get(segment) != Some(&elf_segment_hash(contents)) {
+                    return Err(VSpaceError::ElfSegmentHashMismatch { segment });
+                }
+                segment_count += 1;
+            }
+            if segment_count != segment_hashes.len() {
+                return Err(VSpaceError::ElfSegmentHashMismatch {
+                    segment: segment_count,
+                });
+            }
+        }
+
+        // No segment may be both writable and executable. A page shared
+        // between segments gets the rights of all of them, so no page may
+        // be shared by a writable and an executable segment either.
+        let mut previous: Option<(usize, bool, bool, u64)> = None;
+        for (segment, program_header) in load_headers().enumerate() {
+            let flags = program_header.flags();
+            if flags.is_write() && flags.is_execute() {
+                return Err(VSpaceError::ElfSegmentWritableAndExecutable { segment });
+            }
+            let (start, end) = loaded_range(&program_header);
+            if start == end {
+                continue;
+            }
+            // Read-only pages are mapped straight from the image, so two
+            // read-only segments can only share a page that's the same
+            // page of the file for both
+            let file_displacement = program_header
+                .virtual_addr()
+                .wrapping_sub(program_header.offset());
+            if let Some((
+                previous_end_page,
+                previous_is_write,
+                previous_is_execute,
+                previous_displacement,
+            )) = previous
+            {
+                let writable_and_executable = (flags.is_write() && previous_is_execute)
+                    || (flags.is_execute() && previous_is_write);
+                let different_file_pages = !flags.is_write()
+                    && !previous_is_write
+                    && file_displacement != previous_displacement;
+                if start & !PAGE_MASK < previous_end_page
+                    && (reject_shared_pages || writable_and_executable || different_file_pages)
+                {
+                    return Err(VSpaceError::ElfSegmentsSharePage { segment });
+                }
+            }
+            previous = Some((
+                (end + PAGE_MASK) & !PAGE_MASK,
+                flags.is_write(),
+                flags.is_execute(),
+                file_displacement,
+            ));
+        }
+
+        if !segment_page_counts.is_empty() {
+            let mut segment_count = 0;
+            for (segment, program_header) in load_headers().enumerate() {
+                let (start, end) = loaded_range(&program_header);
+                if segment_page_counts.get(segment) != Some(&iterate_by_page(start, end).count()) {
+                    return Err(VSpaceError::ElfSegmentPageCountMismatch { segment });
+                }
+                segment_count += 1;
+            }
+            if segment_count != segment_page_counts.len() {
+                return Err(VSpaceError::ElfSegmentPageCountMismatch {
+                    segment: segment_count,
+                });
+            }
+        }
+
+        check_relocations(&elf)?;
+
+        // Pages wholly inside a RELRO segment only need writing while
+        // they're loaded, and are mapped read-only
+        let is_relro_page = |page_vaddr: usize| {
+            elf.program_iter()
+                .filter(|h| h.get_type() == Ok(xmas_elf::program::Type::GnuRelro))
+                .any(|h| {
+                    let start = h.virtual_addr() as usize + load_bias;
+                    let end = start + h.mem_size() as usize;
+                    start <= page_vaddr && page_vaddr + arch::PageBytes::USIZE <= end
+                })
+        };
+
+        // The segments with anything loaded into the page at `page_vaddr`
+        let segments_in_page = |page_vaddr: usize| {
+            load_headers().filter(move |h| {
+                let (start, end) = loaded_range(h);
+                start + load_bias < page_vaddr + arch::PageBytes::USIZE
+                    && page_vaddr < end + load_bias
+            })
+        };
+        let vm_attributes_for_page = |page_vaddr: usize| {
+            if segments_in_page(page_vaddr).any(|h| h.flags().is_execute()) {
+                arch::vm_attributes::PROGRAM_CODE
+            } else {
+                arch::vm_attributes::PROGRAM_DATA
+            }
+        };
+
+        let total_pages = load_headers()
+            .map(|h| {
+                let (start, end) = loaded_range(&h);
+                iterate_by_page(start, end).count()
+            })
+            .sum();
+        let mut pages_loaded = 0;
+        let mut page_loaded = || {
+            pages_loaded += 1;
+            progress(ElfLoadProgress {
+                pages_loaded,
+                total_pages,
+            });
+        };
+
+        let mut vspace =
+            VSpace::<vspace_state::Empty>::new(paging_root, asid, slots, paging_untyped)?;
 
         let mut writable_segment_pages_iter =
             elf_writable_mem.retype_pages(&mut page_slots)?.into_iter();
 
-        for program_header in elf
-            .program_iter()
-            .filter(|h| h.get_type() == Ok(xmas_elf::program::Type::Load))
-        {
-            let target_vaddr = program_header.virtual_addr() as usize;
+        for program_header in load_headers() {
+            let target_vaddr = program_header.virtual_addr() as usize + load_bias;
             let offset = program_header.offset();
 
             let file_size = program_header.file_size() as usize;
             let flags = program_header.flags();
 
-            let vm_attrs = if flags.is_execute() {
-                arch::vm_attributes::PROGRAM_CODE
-            } else {
-                arch::vm_attributes::PROGRAM_DATA
+            // Whether the page at `page_vaddr` was loaded along with an
+            // earlier segment, or will be with a writable one
+            let loaded_elsewhere = |page_vaddr: usize| {
+                segments_in_page(page_vaddr).any(|h| {
+                    let earlier = h.virtual_addr() < program_header.virtual_addr();
+                    if flags.is_write() {
+                        earlier && h.flags().is_write()
+                    } else {
+                        earlier || h.flags().is_write()
+                    }
+                })
             };
 
             if flags.is_write() {
@@ -569,14 +925,15 @@ impl VSpace<vspace_state::Imaged, role::Local> {
                 // things like the BSS section. This memory is zeroed out
                 // below.
                 let mem_size = program_header.mem_size() as usize;
-                let src_offset = program_header.offset() as usize;
 
-                for (target_vaddr_start, target_vaddr_end) in
+                for (target_vaddr_start, _) in
                     iterate_by_page(target_vaddr, target_vaddr + mem_size)
                 {
                     let curr_page_vaddr = target_vaddr_start & !PAGE_MASK;
-                    // debug_println!("-- setting up writable range {:X}-{:X}", target_vaddr_start,
-                    // target_vaddr_end);
+                    if loaded_elsewhere(curr_page_vaddr) {
+                        page_loaded();
+                        continue;
+                    }
 
                     // if this fails, it means that we weren't given enough
                     // resources to map all the pages.This shouldn't happen, as
@@ -597,40 +954,47 @@ impl VSpace<vspace_state::Imaged, role::Local> {
                                 *dest = 0;
                             }
 
-                            // if this overlaps with any file-provided data, copy it over
-                            if target_vaddr_start < (target_vaddr + file_size) {
-                                let src_start = src_offset + (target_vaddr_start - target_vaddr);
-                                let src_end = core::cmp::min(
-                                    src_offset + (target_vaddr_end - target_vaddr),
-                                    src_offset + file_size,
+                            // copy over whatever the file provides for the page,
+                            // from this segment and any other sharing the page
+                            for h in segments_in_page(curr_page_vaddr) {
+                                let segment_start = h.virtual_addr() as usize + load_bias;
+                                let copy_start = core::cmp::max(segment_start, curr_page_vaddr);
+                                let copy_end = core::cmp::min(
+                                    segment_start + h.file_size() as usize,
+                                    curr_page_vaddr + arch::PageBytes::USIZE,
                                 );
-
-                                let data_start_in_page = src_start & PAGE_MASK;
-                                let data_end_in_page = src_end & PAGE_MASK;
-                                let data_end_in_page = if data_end_in_page == 0 {
-                                    arch::PageBytes::USIZE
-                                } else {
-                                    data_end_in_page
-                                };
-                                let dest_slice =
-                                    &mut dest_mem[data_start_in_page..data_end_in_page];
-                                dest_slice.copy_from_slice(&elf_data[src_start..src_end]);
+                                if copy_start < copy_end {
+                                    let src_start =
+                                        h.offset() as usize + (copy_start - segment_start);
+                                    let src_end = src_start + (copy_end - copy_start);
+                                    dest_mem
+                                        [copy_start - curr_page_vaddr..copy_end - curr_page_vaddr]
+                                        .copy_from_slice(&elf_data[src_start..src_end]);
+                                }
                             }
 
+                            relocate_page(&elf, load_bias, curr_page_vaddr, dest_mem);
+
                             temp_mapped_region.flush().unwrap();
                         },
                     );
 
+                    let rights = if is_relro_page(curr_page_vaddr) {
+                        CapRights::R
+                    } else {
+                        CapRights::RW
+                    };
                     let _ = vspace.map_page_at_addr_without_watermarking(
                         unmapped_region.to_page(),
                         curr_page_vaddr,
-                        CapRights::RW,
-                        vm_attrs,
+                        rights,
+                        vm_attributes_for_page(curr_page_vaddr),
                     )?;
 
                     vspace
                         .available_address_range
                         .observe_mapping(curr_page_vaddr, PageBits::U8)?;
+                    page_loaded();
                 }
             } else {
                 // If the elf headers say to map something as read only, we can map in the pages
@@ -653,6 +1017,10 @@ impl VSpace<vspace_state::Imaged, role::Local> {
                     let page_vaddr_here = user_image_page.cap_data.state.vaddr;
                     let page_offset = page_vaddr_here - start_page_vaddr_here;
                     let child_vaddr = target_vaddr + page_offset;
+                    if loaded_elsewhere(child_vaddr) {
+                        page_loaded();
+                        continue;
+                    }
 
                     let copied_page_cap = user_image_page.copy(
                         parent_cnode,
@@ -670,11 +1038,12 @@ impl VSpace<vspace_state::Imaged, role::Local> {
                         copied_page_cap,
                         child_vaddr,
                         CapRights::R,
-                        vm_attrs,
+                        vm_attributes_for_page(child_vaddr),
                     )?;
                     vspace
                         .available_address_range
                         .observe_mapping(child_vaddr, arch::PageBits::U8)?;
+                    page_loaded();
                 }
             }
         }
@@ -742,7 +1111,7 @@ impl VSpace<vspace_state::Imaged, role::Local> {
                     Page<page_state::Unmapped>,
                     role::Local,
                     arch::CodePageCount,
-                > = code_pages_ut.retype_multi(code_pages_slots)?;
+                > = code_pages_ut.retype_bulk(code_pages_slots)?;
                 // Then, zip up the pages with the user image pages
                 for (user_image_page, fresh_page) in
                     user_image.pages_iter().zip(fresh_pages.into_iter())
@@ -835,8 +1204,8 @@ impl VSpace<vspace_state::Imaged, role::Local> {
         Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
     {
         match self.weak_map_region_at_addr(region.weaken(), vaddr, rights, vm_attributes) {
-            Ok(r) => Ok(r.as_strong::<SizeBits>().map_err(|e| (e, None))?),
-            Err((e, r)) => Err((e, r.as_strong::<SizeBits>().ok())),
+            Ok(r) => Ok(r.as_strong::<SizeBits, _>().map_err(|e| (e, None))?),
+            Err((e, r)) => Err((e, r.as_strong::<SizeBits, _>().ok())),
         }
     }
 
@@ -846,6 +1215,24 @@ impl VSpace<vspace_state::Imaged, role::Local> {
         vaddr: usize,
         rights: CapRights,
         vm_attributes: arch::VMAttributes,
+    ) -> Result<WeakMappedMemoryRegion<SS>, (VSpaceError, WeakUnmappedMemoryRegion<SS>)> {
+        if self
+            .available_address_range
+            .windows
+            .overlapping(vaddr, region.size_bytes())
+            .is_some()
+        {
+            return Err((VSpaceError::OverlapsAddressWindow, region));
+        }
+        self.weak_map_region_at_addr_internal(region, vaddr, rights, vm_attributes)
+    }
+
+    fn weak_map_region_at_addr_internal<SS: SharedStatus>(
+        &mut self,
+        region: WeakUnmappedMemoryRegion<SS>,
+        vaddr: usize,
+        rights: CapRights,
+        vm_attributes: arch::VMAttributes,
     ) -> Result<WeakMappedMemoryRegion<SS>, (VSpaceError, WeakUnmappedMemoryRegion<SS>)> {
         if region.size_bits() < PageBits::U8 {
             return Err((VSpaceError::InvalidRegionSize, region));
@@ -1019,8 +1406,56 @@ impl VSpace<vspace_state::Imaged, role::Local> {
             src_cnode,
             &mut dest_slots.weaken(),
         )
-        .and_then(|r| r.as_strong::<SizeBits>())
+        .and_then(|r| r.as_strong::<SizeBits, _>())
+    }
+    /// Map a region of memory at some address, with the attributes of
+    /// the cache status `CS` rather than caller-provided ones, so the
+    /// mapped region can be typed with that status.
+    pub fn map_region_with_cache_status<SizeBits: Unsigned, CS: CacheStatus>(
+        &mut self,
+        region: UnmappedMemoryRegion<SizeBits, shared_status::Exclusive>,
+        rights: CapRights,
+    ) -> Result<MappedMemoryRegion<SizeBits, shared_status::Exclusive, role::Local, CS>, VSpaceError>
+    where
+        SizeBits: IsGreaterOrEqual<PageBits>,
+        SizeBits: Sub<PageBits>,
+        <SizeBits as Sub<PageBits>>::Output: Unsigned,
+        <SizeBits as Sub<PageBits>>::Output: _Pow,
+        Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+    {
+        self.map_region_internal(region, rights, CS::VM_ATTRIBUTES)
     }
+
+    /// Map a region of memory at some address with the attributes of the
+    /// cache status `CS`, then move it to a different cspace.
+    pub fn map_region_and_move_with_cache_status<
+        SizeBits: Unsigned,
+        Role: CNodeRole,
+        CS: CacheStatus,
+    >(
+        &mut self,
+        region: UnmappedMemoryRegion<SizeBits, shared_status::Exclusive>,
+        rights: CapRights,
+        src_cnode: &LocalCap<LocalCNode>,
+        dest_slots: CNodeSlots<NumPages<SizeBits>, Role>,
+    ) -> Result<MappedMemoryRegion<SizeBits, shared_status::Exclusive, role::Local, CS>, VSpaceError>
+    where
+        SizeBits: IsGreaterOrEqual<PageBits>,
+        SizeBits: Sub<PageBits>,
+        <SizeBits as Sub<PageBits>>::Output: Unsigned,
+        <SizeBits as Sub<PageBits>>::Output: _Pow,
+        Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+    {
+        self.weak_map_region_and_move(
+            region.weaken(),
+            rights,
+            CS::VM_ATTRIBUTES,
+            src_cnode,
+            &mut dest_slots.weaken(),
+        )
+        .and_then(|r| r.as_strong::<SizeBits, _>())
+    }
+
     /// Map a weak region of memory at some address, then move it to a
     /// different cspace.
     pub fn weak_map_region_and_move<Role: CNodeRole>(
@@ -1115,6 +1550,42 @@ impl VSpace<vspace_state::Imaged, role::Local> {
         self.weak_map_region_internal(unmapped_sr, rights, vm_attributes)
     }
 
+    /// Map a read-only region somewhere, as `map_shared_region` does,
+    /// always without write rights.
+    pub fn map_read_only_region<SizeBits: Unsigned>(
+        &mut self,
+        region: &ReadOnlyRegion<SizeBits>,
+        vm_attributes: arch::VMAttributes,
+        slots: LocalCNodeSlots<NumPages<SizeBits>>,
+        cnode: &LocalCap<LocalCNode>,
+    ) -> Result<MappedMemoryRegion<SizeBits, shared_status::Shared>, VSpaceError>
+    where
+        SizeBits: IsGreaterOrEqual<PageBits>,
+        SizeBits: Sub<PageBits>,
+        <SizeBits as Sub<PageBits>>::Output: Unsigned,
+        <SizeBits as Sub<PageBits>>::Output: _Pow,
+        Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+    {
+        self.map_shared_region(&region.region, CapRights::R, vm_attributes, slots, cnode)
+    }
+
+    /// Map a read-only region somewhere, consuming it, as
+    /// `map_shared_region_and_consume` does, always without write rights.
+    pub fn map_read_only_region_and_consume<SizeBits: Unsigned>(
+        &mut self,
+        region: ReadOnlyRegion<SizeBits>,
+        vm_attributes: arch::VMAttributes,
+    ) -> Result<MappedMemoryRegion<SizeBits, shared_status::Shared>, VSpaceError>
+    where
+        SizeBits: IsGreaterOrEqual<PageBits>,
+        SizeBits: Sub<PageBits>,
+        <SizeBits as Sub<PageBits>>::Output: Unsigned,
+        <SizeBits as Sub<PageBits>>::Output: _Pow,
+        Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+    {
+        self.map_region_internal(region.region, CapRights::R, vm_attributes)
+    }
+
     /// For cases when one does not want to continue to duplicate the
     /// region's constituent caps—meaning that there is only one final
     /// address space in which this region will be mapped—that
@@ -1136,12 +1607,17 @@ impl VSpace<vspace_state::Imaged, role::Local> {
         self.map_region_internal(region, rights, vm_attributes)
     }
 
-    fn map_region_internal<SizeBits: Unsigned, SSIn: SharedStatus, SSOut: SharedStatus>(
+    fn map_region_internal<
+        SizeBits: Unsigned,
+        SSIn: SharedStatus,
+        SSOut: SharedStatus,
+        CS: CacheStatus,
+    >(
         &mut self,
         region: UnmappedMemoryRegion<SizeBits, SSIn>,
         rights: CapRights,
         vm_attributes: arch::VMAttributes,
-    ) -> Result<MappedMemoryRegion<SizeBits, SSOut>, VSpaceError>
+    ) -> Result<MappedMemoryRegion<SizeBits, SSOut, role::Local, CS>, VSpaceError>
     where
         SizeBits: IsGreaterOrEqual<PageBits>,
         SizeBits: Sub<PageBits>,
@@ -1150,7 +1626,7 @@ impl VSpace<vspace_state::Imaged, role::Local> {
         Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
     {
         self.weak_map_region_internal(region.weaken(), rights, vm_attributes)
-            .and_then(|r| r.as_strong::<SizeBits>())
+            .and_then(|r| r.as_strong::<SizeBits, _>())
     }
     fn weak_map_region_internal<SSIn: SharedStatus, SSOut: SharedStatus>(
         &mut self,
@@ -1217,6 +1693,18 @@ impl VSpace<vspace_state::Imaged, role::Local> {
         Ok(())
     }
 
+    /// Set aside `2^size_bits` bytes of address space, leaving it
+    /// unmapped, and return where it starts.
+    pub(crate) fn skip_range(&mut self, size_bits: u8) -> Result<usize, VSpaceError> {
+        let start = self
+            .available_address_range
+            .auto_propose_region_start(size_bits)
+            .map_err(|_| VSpaceError::ExceededAddressableSpace)?;
+        self.available_address_range
+            .observe_mapping(start, size_bits)?;
+        Ok(start)
+    }
+
     pub fn reserve<PageCount: Unsigned>(
         &mut self,
         sacrificial_page: LocalCap<Page<page_state::Unmapped>>,
@@ -1226,6 +1714,87 @@ impl VSpace<vspace_state::Imaged, role::Local> {
     {
         ReservedRegion::new(self, sacrificial_page)
     }
+
+    /// Set aside a window of `2^SizeBits` bytes of address space by name,
+    /// for regions to be mapped into with `map_region_in_window`
+    pub fn reserve_window<SizeBits: Unsigned>(
+        &mut self,
+        name: &'static str,
+    ) -> Result<AddressWindow, VSpaceError>
+    where
+        SizeBits: IsGreaterOrEqual<PageBits, Output = True>,
+    {
+        self.available_address_range.windows.check_available(name)?;
+        let start = self
+            .available_address_range
+            .auto_propose_region_start(SizeBits::U8)
+            .map_err(|_| VSpaceError::InsufficientAddressSpaceAvailableToMapRegion)?;
+        self.available_address_range
+            .observe_mapping(start, SizeBits::U8)?;
+        let window = AddressWindow {
+            name,
+            start,
+            size_bytes: bytes_from_size_bits(SizeBits::U8),
+            used_bytes: 0,
+        };
+        self.available_address_range.windows.insert(window)?;
+        Ok(window)
+    }
+
+    /// Map a region at the next free address in the window reserved as
+    /// `name`
+    pub fn map_region_in_window<SizeBits: Unsigned, SS: SharedStatus>(
+        &mut self,
+        name: &str,
+        region: UnmappedMemoryRegion<SizeBits, SS>,
+        rights: CapRights,
+        vm_attributes: arch::VMAttributes,
+    ) -> Result<
+        MappedMemoryRegion<SizeBits, SS>,
+        (VSpaceError, Option<UnmappedMemoryRegion<SizeBits, SS>>),
+    >
+    where
+        SizeBits: IsGreaterOrEqual<PageBits>,
+        SizeBits: Sub<PageBits>,
+        <SizeBits as Sub<PageBits>>::Output: Unsigned,
+        <SizeBits as Sub<PageBits>>::Output: _Pow,
+        Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+    {
+        match self.weak_map_region_in_window(name, region.weaken(), rights, vm_attributes) {
+            Ok(r) => Ok(r.as_strong::<SizeBits, _>().map_err(|e| (e, None))?),
+            Err((e, r)) => Err((e, r.as_strong::<SizeBits, _>().ok())),
+        }
+    }
+
+    pub fn weak_map_region_in_window<SS: SharedStatus>(
+        &mut self,
+        name: &str,
+        region: WeakUnmappedMemoryRegion<SS>,
+        rights: CapRights,
+        vm_attributes: arch::VMAttributes,
+    ) -> Result<WeakMappedMemoryRegion<SS>, (VSpaceError, WeakUnmappedMemoryRegion<SS>)> {
+        let size_bytes = region.size_bytes();
+        let vaddr = match self.available_address_range.windows.claim(name, size_bytes) {
+            Ok(vaddr) => vaddr,
+            Err(e) => return Err((e, region)),
+        };
+        self.weak_map_region_at_addr_internal(region, vaddr, rights, vm_attributes)
+            .map_err(|(e, region)| {
+                self.available_address_range
+                    .windows
+                    .release(vaddr, size_bytes);
+                (e, region)
+            })
+    }
+
+    /// The window reserved as `name`, with how much of it is used so far
+    pub fn window(&self, name: &str) -> Option<&AddressWindow> {
+        self.available_address_range.windows.find(name)
+    }
+
+    pub fn windows(&self) -> impl Iterator<Item = &AddressWindow> {
+        self.available_address_range.windows.iter()
+    }
 }
 
 /// A region of memory in a VSpace that has been reserved
@@ -1296,6 +1865,8 @@ where
 pub struct ScratchRegion<PageCount: Unsigned = crate::userland::process::DefaultStackPageCount> {
     reserved_region: ReservedRegion<PageCount>,
     paging_root: LocalCap<PagingRoot>,
+    /// Whether a `ScratchGuard` is out for the region
+    checked_out: Cell<bool>,
 }
 
 impl<PageCount: Unsigned> ScratchRegion<PageCount> {
@@ -1308,6 +1879,7 @@ impl<PageCount: Unsigned> ScratchRegion<PageCount> {
                     cap_data: PagingRoot::phantom_instance(),
                     _role: PhantomData,
                 },
+                checked_out: Cell::new(false),
             })
         } else {
             Err(VSpaceError::ASIDMismatch)
@@ -1338,47 +1910,21 @@ impl<PageCount: Unsigned> ScratchRegion<PageCount> {
         Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
         F: Fn(&mut MappedMemoryRegion<SizeBits, shared_status::Exclusive>) -> Out,
     {
-        let start_vaddr = self.reserved_region.vaddr;
-        let mut next_addr = start_vaddr;
-
-        let res: Result<(), SeL4Error> = region.caps.for_each::<SeL4Error, _>(|page| {
-            unsafe {
-                page.unchecked_page_map(
-                    next_addr,
-                    &mut self.paging_root,
-                    CapRights::RW,
-                    arch::vm_attributes::DEFAULT,
-                )?;
-            }
-            next_addr += arch::PageBytes::USIZE;
-            let res: Result<(), SeL4Error> = Ok(());
-            res
-        });
-
-        res?;
-
-        // synthesize a MappedMemoryRegion to pass to the callback
-        let mut mapped_region = MemoryRegion::unchecked_new(
-            region.caps.start_cptr,
-            page_state::Mapped {
-                vaddr: start_vaddr,
-                asid: self.reserved_region.asid,
-                rights: CapRights::RW,
-            },
-            region.kind,
-        );
-
-        let res = f(&mut mapped_region);
-
-        // unmap everything
-        for page in mapped_region.caps.into_iter() {
-            page.unmap()?;
-        }
-
-        Ok(res)
+        map_temporarily(
+            self.reserved_region.vaddr,
+            self.reserved_region.asid,
+            PageCount::USIZE,
+            &mut self.paging_root,
+            region,
+            f,
+        )
     }
 }
 
+/// How many ranges of reclaimed addresses outside the middle-region
+/// a vspace keeps for reuse. Adjacent ranges are merged into one.
+pub const MAX_RECLAIMED_RANGES: usize = 16;
+
 /// A dual-cursor address range tracker that maintains
 /// watermarks tracking an unallocated middle-region.
 #[derive(Debug, Clone)]
@@ -1387,6 +1933,18 @@ struct AvailableAddressRange {
     bottom: usize,
     /// Watermark for the highest ending address available
     top: usize,
+    /// Unmapped ranges below `bottom` or above `top`, made available
+    /// again by reclaiming the regions mapped there
+    reclaimed: ArrayVec<[ReclaimedRange; MAX_RECLAIMED_RANGES]>,
+    /// Ranges taken out of the middle-region up front, for regions to be
+    /// mapped into by name
+    windows: AddressWindows,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ReclaimedRange {
+    start: usize,
+    end: usize,
 }
 
 impl Default for AvailableAddressRange {
@@ -1394,6 +1952,8 @@ impl Default for AvailableAddressRange {
         AvailableAddressRange {
             bottom: 0,
             top: core::usize::MAX,
+            reclaimed: ArrayVec::new(),
+            windows: AddressWindows::default(),
         }
     }
 }
@@ -1404,6 +1964,7 @@ impl AvailableAddressRange {
         let end = start
             .checked_add(size_bytes)
             .ok_or(VSpaceError::ExceededAddressableSpace)?;
+        self.observe_reclaimed_mapping(start, end)?;
         if end < self.bottom || start > self.top {
             return Ok(());
         }
@@ -1418,11 +1979,98 @@ impl AvailableAddressRange {
         Ok(())
     }
 
+    /// Take a mapping out of any reclaimed ranges it overlaps. Fails,
+    /// changing nothing, if that would split a range in two and there's
+    /// no room for the second.
+    fn observe_reclaimed_mapping(&mut self, start: usize, end: usize) -> Result<(), VSpaceError> {
+        let splits = self
+            .reclaimed
+            .iter()
+            .filter(|r| r.start < start && end < r.end)
+            .count();
+        if self.reclaimed.len() + splits > MAX_RECLAIMED_RANGES {
+            return Err(VSpaceError::TooManyReclaimedRanges);
+        }
+
+        let mut i = 0;
+        while i < self.reclaimed.len() {
+            let range = self.reclaimed[i];
+            if end <= range.start || start >= range.end {
+                i += 1;
+                continue;
+            }
+            self.reclaimed.swap_remove(i);
+            // Can't overflow, there's room for the splits checked above
+            if range.start < start {
+                self.reclaimed.push(ReclaimedRange {
+                    start: range.start,
+                    end: start,
+                });
+            }
+            if end < range.end {
+                self.reclaimed.push(ReclaimedRange {
+                    start: end,
+                    end: range.end,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether `observe_unmapping` has room to keep the range, which it
+    /// does if the range merges with another or with the middle-region
+    fn check_unmapping(&self, start: usize, size_bits: u8) -> Result<(), VSpaceError> {
+        let end = start.saturating_add(bytes_from_size_bits(size_bits));
+        let merges = end == self.bottom
+            || start == self.top
+            || self
+                .reclaimed
+                .iter()
+                .any(|r| r.end == start || r.start == end);
+        if merges || !self.reclaimed.is_full() {
+            Ok(())
+        } else {
+            Err(VSpaceError::TooManyReclaimedRanges)
+        }
+    }
+
+    /// Make a previously mapped range available again, merging it with
+    /// any it's adjacent to. There has to be room for it, see
+    /// `check_unmapping`.
+    fn observe_unmapping(&mut self, start: usize, size_bits: u8) {
+        let mut start = start;
+        let mut end = start.saturating_add(bytes_from_size_bits(size_bits));
+        while let Some(i) = self
+            .reclaimed
+            .iter()
+            .position(|r| r.end == start || r.start == end)
+        {
+            let range = self.reclaimed.swap_remove(i);
+            start = core::cmp::min(start, range.start);
+            end = core::cmp::max(end, range.end);
+        }
+
+        if end == self.bottom {
+            self.bottom = start;
+        } else if start == self.top {
+            self.top = end;
+        } else {
+            self.reclaimed.push(ReclaimedRange { start, end });
+        }
+    }
+
     fn auto_propose_region_start(&self, size_bits: u8) -> Result<usize, CouldNotAllocateRegion> {
+        let size_bytes = bytes_from_size_bits(size_bits);
+        if let Some(range) = self
+            .reclaimed
+            .iter()
+            .find(|r| r.end - r.start >= size_bytes)
+        {
+            return Ok(range.start);
+        }
         if self.bottom > self.top {
             return Err(CouldNotAllocateRegion);
         }
-        let size_bytes = bytes_from_size_bits(size_bits);
         let proposed_start = self.bottom;
         let proposed_end = proposed_start
             .checked_add(size_bytes)
@@ -0,0 +1,148 @@
+//! Regions pinned in place for a device to use.
+//!
+//! Once a device has been handed a region's physical address for DMA it
+//! goes on reading and writing there, whatever happens to the mapping.
+//! A `DmaRegion` only borrows its region for as long as the token is
+//! around, but the device holds on to the address for as long as it's
+//! programmed with it. Pinning a mapped region wraps it so that it can't
+//! be given to anything that unmaps, remaps or splits regions: there's no
+//! way back to the `MappedMemoryRegion` short of the unsafe `unpin`, by
+//! which point the device should have been told to let go.
+use core::ops::Sub;
+
+use typenum::*;
+
+use crate::arch::PageBits;
+use crate::cap::role;
+use crate::error::SeL4Error;
+use crate::pow::{Pow, _Pow};
+
+use super::{
+    cache_status, CacheFlushed, CacheStatus, DmaCoherent, DmaRegion, MappedMemoryRegion,
+    SharedStatus,
+};
+
+/// A mapped region that stays mapped where it is until it's unpinned, see
+/// `MappedMemoryRegion::pin`.
+pub struct PinnedMemoryRegion<
+    SizeBits: Unsigned,
+    SS: SharedStatus,
+    CS: CacheStatus = cache_status::Cached,
+> where
+    SizeBits: IsGreaterOrEqual<PageBits>,
+    SizeBits: Sub<PageBits>,
+    <SizeBits as Sub<PageBits>>::Output: Unsigned,
+    <SizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+{
+    region: MappedMemoryRegion<SizeBits, SS, role::Local, CS>,
+    paddr: usize,
+}
+
+impl<SizeBits: Unsigned, SS: SharedStatus, CS: CacheStatus>
+    MappedMemoryRegion<SizeBits, SS, role::Local, CS>
+where
+    SizeBits: IsGreaterOrEqual<PageBits>,
+    SizeBits: Sub<PageBits>,
+    <SizeBits as Sub<PageBits>>::Output: Unsigned,
+    <SizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+{
+    /// Pin the region where it's mapped, for handing to a device.
+    pub fn pin(self) -> Result<PinnedMemoryRegion<SizeBits, SS, CS>, SeL4Error> {
+        let paddr = self.paddr()?;
+        Ok(PinnedMemoryRegion {
+            region: self,
+            paddr,
+        })
+    }
+}
+
+impl<SizeBits: Unsigned, SS: SharedStatus, CS: CacheStatus> PinnedMemoryRegion<SizeBits, SS, CS>
+where
+    SizeBits: IsGreaterOrEqual<PageBits>,
+    SizeBits: Sub<PageBits>,
+    <SizeBits as Sub<PageBits>>::Output: Unsigned,
+    <SizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+{
+    pub fn vaddr(&self) -> usize {
+        self.region.vaddr()
+    }
+
+    pub fn paddr(&self) -> usize {
+        self.paddr
+    }
+
+    pub fn size_bytes(&self) -> usize {
+        self.region.size_bytes()
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        self.region.as_slice()
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        self.region.as_mut_slice()
+    }
+
+    pub fn flush(&self) -> Result<(), SeL4Error> {
+        self.region.flush()
+    }
+
+    pub fn flush_range(&self, vaddr: usize, size: usize) -> Result<(), SeL4Error> {
+        self.region.flush_range(vaddr, size)
+    }
+
+    /// Hand the region back for unmapping or remapping.
+    ///
+    /// # Safety
+    ///
+    /// No device may still be using the region's physical address.
+    pub unsafe fn unpin(self) -> MappedMemoryRegion<SizeBits, SS, role::Local, CS> {
+        self.region
+    }
+}
+
+impl<SizeBits: Unsigned, SS: SharedStatus> PinnedMemoryRegion<SizeBits, SS>
+where
+    SizeBits: IsGreaterOrEqual<PageBits>,
+    SizeBits: Sub<PageBits>,
+    <SizeBits as Sub<PageBits>>::Output: Unsigned,
+    <SizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+{
+    /// Flush the region so it can be handed to a device, see
+    /// `MappedMemoryRegion::flush_for_dma`.
+    pub fn flush_for_dma(&self) -> Result<CacheFlushed<'_>, SeL4Error> {
+        self.region.flush_for_dma()
+    }
+
+    /// See `MappedMemoryRegion::clean_for_dma`.
+    pub fn clean_for_dma(&self) -> Result<CacheFlushed<'_>, SeL4Error> {
+        self.region.clean_for_dma()
+    }
+
+    pub fn clean(&self) -> Result<(), SeL4Error> {
+        self.region.clean()
+    }
+
+    /// See `MappedMemoryRegion::invalidate`.
+    pub fn invalidate(&mut self) -> Result<(), SeL4Error> {
+        self.region.invalidate()
+    }
+}
+
+impl<SizeBits: Unsigned, SS: SharedStatus, CS: DmaCoherent> PinnedMemoryRegion<SizeBits, SS, CS>
+where
+    SizeBits: IsGreaterOrEqual<PageBits>,
+    SizeBits: Sub<PageBits>,
+    <SizeBits as Sub<PageBits>>::Output: Unsigned,
+    <SizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+{
+    /// The region as handed to a device, no flush needed.
+    pub fn dma_region(&self) -> Result<DmaRegion<'_>, SeL4Error> {
+        self.region.dma_region()
+    }
+}
@@ -2,16 +2,17 @@ use core::cmp;
 use core::marker::PhantomData;
 use core::ops::Sub;
 
+use selfe_sys::{seL4_CNode_Delete, seL4_WordBits};
 use typenum::*;
 
-use super::{KernelRetypeFanOutLimit, NumPages, VSpaceError};
+use super::{KernelRetypeFanOutLimit, NumPages, VSpace, VSpaceError};
 use crate::arch::{self, PageBits, PageBytes};
 use crate::cap::{
-    memory_kind, page_state, role, CNode, CNodeRole, CNodeSlots, Cap, CapRange, InternalASID,
-    LocalCNodeSlots, LocalCap, MemoryKind, Page, PageState, RetypeError, Untyped, WCNodeSlots,
-    WUntyped, WeakCapRange, WeakMemoryKind,
+    memory_kind, page_state, role, CNode, CNodeRole, CNodeSlots, Cap, CapRange,
+    CapRangeDataReconstruction, InternalASID, LocalCNode, LocalCNodeSlots, LocalCap, MemoryKind,
+    Page, PageState, RetypeError, Untyped, WCNodeSlots, WUntyped, WeakCapRange, WeakMemoryKind,
 };
-use crate::error::SeL4Error;
+use crate::error::{ErrorExt, SeL4Error};
 
 use crate::pow::{Pow, _Pow};
 use crate::userland::CapRights;
@@ -28,11 +29,61 @@ pub mod shared_status {
     impl SharedStatus for Exclusive {}
 }
 
+/// How the CPU caches a mapped region, and so what has to happen before
+/// a device can be handed the region for DMA.
+pub trait CacheStatus: private::SealedCacheStatus {
+    /// The attributes a region with this status is mapped with
+    const VM_ATTRIBUTES: arch::VMAttributes;
+}
+
+/// Cache statuses for which the CPU's view of the memory is always the
+/// device's view; no cache maintenance is needed around DMA.
+pub trait DmaCoherent: CacheStatus {}
+
+pub mod cache_status {
+    use super::{CacheStatus, DmaCoherent};
+    use crate::arch::{self, vm_attributes};
+
+    /// The region may be cached, the cache has to be flushed before a
+    /// device reads from it. Regions mapped with caller-provided
+    /// attributes are always treated as cached, since nothing checks
+    /// what those attributes are.
+    pub struct Cached;
+    impl CacheStatus for Cached {
+        const VM_ATTRIBUTES: arch::VMAttributes = vm_attributes::DEFAULT;
+    }
+
+    /// The region is mapped non-cacheable.
+    pub struct Uncached;
+    impl CacheStatus for Uncached {
+        const VM_ATTRIBUTES: arch::VMAttributes = vm_attributes::UNCACHED;
+    }
+    impl DmaCoherent for Uncached {}
+
+    /// The region is mapped for write-combining, e.g. a frame buffer.
+    /// seL4 doesn't expose a separate write-combining attribute on ARM,
+    /// so there it's mapped non-cacheable like `Uncached`; the distinct
+    /// status records what the region is for. x86 maps it write-combining.
+    ///
+    /// Writes can sit in the write-combining buffers until a fence, so
+    /// this isn't `DmaCoherent`; see `fence_for_dma`.
+    pub struct WriteCombining;
+    impl CacheStatus for WriteCombining {
+        const VM_ATTRIBUTES: arch::VMAttributes = vm_attributes::WRITE_COMBINING;
+    }
+}
+
 mod private {
+    use super::cache_status::{Cached, Uncached, WriteCombining};
     use super::shared_status::{Exclusive, Shared};
     pub trait SealedSharedStatus {}
     impl SealedSharedStatus for Shared {}
     impl SealedSharedStatus for Exclusive {}
+
+    pub trait SealedCacheStatus {}
+    impl SealedCacheStatus for Cached {}
+    impl SealedCacheStatus for Uncached {}
+    impl SealedCacheStatus for WriteCombining {}
 }
 /// A `1 << SizeBits` bytes region of unmapped memory. It can be
 /// shared or owned exclusively. The ramifications of its shared
@@ -43,10 +94,14 @@ pub type UnmappedMemoryRegion<SizeBits, ShStatus, CapRole: CNodeRole = role::Loc
     MemoryRegion<page_state::Unmapped, SizeBits, ShStatus, CapRole>;
 /// A memory region which is mapped into an address space, meaning it
 /// has a virtual address and an associated asid in which that virtual
-/// address is valid.
+/// address is valid. Its cache status is described in `CacheStatus`.
 #[allow(type_alias_bounds)]
-pub type MappedMemoryRegion<SizeBits, ShStatus, CapRole: CNodeRole = role::Local> =
-    MemoryRegion<page_state::Mapped, SizeBits, ShStatus, CapRole>;
+pub type MappedMemoryRegion<
+    SizeBits,
+    ShStatus,
+    CapRole: CNodeRole = role::Local,
+    CS: CacheStatus = cache_status::Cached,
+> = MemoryRegion<page_state::Mapped, SizeBits, ShStatus, CapRole, CS>;
 #[allow(type_alias_bounds)]
 pub type WeakUnmappedMemoryRegion<ShStatus, CapRole: CNodeRole = role::Local> =
     WeakMemoryRegion<page_state::Unmapped, ShStatus, CapRole>;
@@ -63,6 +118,7 @@ pub struct MemoryRegion<
     SizeBits: Unsigned,
     SS: SharedStatus,
     CapRole: CNodeRole = role::Local,
+    CS: CacheStatus = cache_status::Cached,
 > where
     // Forces regions to be page-aligned.
     SizeBits: IsGreaterOrEqual<PageBits>,
@@ -75,10 +131,16 @@ pub struct MemoryRegion<
     pub(super) kind: WeakMemoryKind,
     _size_bits: PhantomData<SizeBits>,
     _shared_status: PhantomData<SS>,
+    _cache_status: PhantomData<CS>,
 }
 
-impl<State: PageState, SizeBits: Unsigned, SS: SharedStatus, CapRole: CNodeRole>
-    MemoryRegion<State, SizeBits, SS, CapRole>
+impl<
+        State: PageState,
+        SizeBits: Unsigned,
+        SS: SharedStatus,
+        CapRole: CNodeRole,
+        CS: CacheStatus,
+    > MemoryRegion<State, SizeBits, SS, CapRole, CS>
 where
     SizeBits: IsGreaterOrEqual<PageBits>,
     SizeBits: Sub<PageBits>,
@@ -101,12 +163,13 @@ where
     pub(super) fn from_caps(
         caps: CapRange<Page<State>, CapRole, NumPages<SizeBits>>,
         kind: WeakMemoryKind,
-    ) -> MemoryRegion<State, SizeBits, SS, CapRole> {
+    ) -> MemoryRegion<State, SizeBits, SS, CapRole, CS> {
         MemoryRegion {
             caps,
             kind,
             _size_bits: PhantomData,
             _shared_status: PhantomData,
+            _cache_status: PhantomData,
         }
     }
 
@@ -120,6 +183,7 @@ where
             kind,
             _size_bits: PhantomData,
             _shared_status: PhantomData,
+            _cache_status: PhantomData,
         }
     }
     pub fn weaken(self) -> WeakMemoryRegion<State, SS, CapRole> {
@@ -160,7 +224,7 @@ where
     ) -> Result<
         (
             MemoryRegion<page_state::Unmapped, SizeBits, shared_status::Shared, DestRole>,
-            MemoryRegion<State, SizeBits, shared_status::Shared, CapRole>,
+            MemoryRegion<State, SizeBits, shared_status::Shared, CapRole, CS>,
         ),
         VSpaceError,
     >
@@ -189,6 +253,75 @@ where
     }
 }
 
+impl<
+        State: PageState,
+        SizeBits: Unsigned,
+        SS: SharedStatus,
+        CapRole: CNodeRole,
+        CS: CacheStatus,
+    > MemoryRegion<State, SizeBits, SS, CapRole, CS>
+where
+    SizeBits: IsGreaterOrEqual<PageBits>,
+    SizeBits: Sub<PageBits>,
+    <SizeBits as Sub<PageBits>>::Output: Unsigned,
+    <SizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+{
+    /// Like `share`, but the copy can only ever be mapped read-only, e.g.
+    /// for publishing a configuration table to many processes. This
+    /// memory region comes back marked as shared, and as writable as it
+    /// was.
+    pub fn share_read_only<CNodeSlotCount: Unsigned, DestRole: CNodeRole>(
+        self,
+        slots: CNodeSlots<CNodeSlotCount, DestRole>,
+        cnode: &LocalCap<CNode<CapRole>>,
+    ) -> Result<
+        (
+            ReadOnlyRegion<SizeBits, DestRole>,
+            MemoryRegion<State, SizeBits, shared_status::Shared, CapRole, CS>,
+        ),
+        VSpaceError,
+    >
+    where
+        CNodeSlotCount: IsEqual<NumPages<SizeBits>, Output = True>,
+    {
+        // The copied page caps lack write rights too, so the kernel won't
+        // map them writable whatever's asked for
+        let (region, original) = self.share(slots, cnode, CapRights::R)?;
+        Ok((ReadOnlyRegion { region }, original))
+    }
+}
+
+/// A shared, unmapped region that can only be mapped read-only, see
+/// `MemoryRegion::share_read_only` and `VSpace::map_read_only_region`.
+pub struct ReadOnlyRegion<SizeBits: Unsigned, CapRole: CNodeRole = role::Local>
+where
+    SizeBits: IsGreaterOrEqual<PageBits>,
+    SizeBits: Sub<PageBits>,
+    <SizeBits as Sub<PageBits>>::Output: Unsigned,
+    <SizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+{
+    pub(super) region: UnmappedMemoryRegion<SizeBits, shared_status::Shared, CapRole>,
+}
+
+impl<SizeBits: Unsigned, CapRole: CNodeRole> ReadOnlyRegion<SizeBits, CapRole>
+where
+    SizeBits: IsGreaterOrEqual<PageBits>,
+    SizeBits: Sub<PageBits>,
+    <SizeBits as Sub<PageBits>>::Output: Unsigned,
+    <SizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+{
+    pub fn size_bits(&self) -> u8 {
+        SizeBits::U8
+    }
+
+    pub fn size_bytes(&self) -> usize {
+        self.region.size_bytes()
+    }
+}
+
 impl LocalCap<Page<page_state::Unmapped>> {
     /// N.B. until MemoryKind tracking is added to Page, this is a lossy
     /// conversion that will assume the Page was for General memory
@@ -199,6 +332,14 @@ impl LocalCap<Page<page_state::Unmapped>> {
     }
 }
 
+impl LocalCap<Page<page_state::Mapped>> {
+    /// N.B. until MemoryKind tracking is added to Page, this is a lossy
+    /// conversion that will assume the Page was for General memory
+    pub(crate) fn to_region(self) -> MappedMemoryRegion<PageBits, shared_status::Exclusive> {
+        MemoryRegion::unchecked_new(self.cptr, self.cap_data.state, WeakMemoryKind::General)
+    }
+}
+
 impl<SizeBits: Unsigned> UnmappedMemoryRegion<SizeBits, shared_status::Exclusive>
 where
     SizeBits: IsGreaterOrEqual<PageBits>,
@@ -241,9 +382,49 @@ where
     pub fn to_shared(self) -> UnmappedMemoryRegion<SizeBits, shared_status::Shared> {
         UnmappedMemoryRegion::from_caps(self.caps, self.kind)
     }
+
+    /// Delete the region's page caps, handing back the slots they were in.
+    ///
+    /// The memory goes back to the untyped it was retyped from, to be
+    /// retyped again once that untyped has been revoked.
+    pub fn delete(
+        self,
+        cnode: &LocalCap<LocalCNode>,
+    ) -> Result<LocalCNodeSlots<NumPages<SizeBits>>, SeL4Error> {
+        self.delete_caps(cnode)
+    }
 }
 
-impl<SizeBits: Unsigned, SS: SharedStatus> MappedMemoryRegion<SizeBits, SS>
+impl<SizeBits: Unsigned, SS: SharedStatus> UnmappedMemoryRegion<SizeBits, SS>
+where
+    SizeBits: IsGreaterOrEqual<PageBits>,
+    SizeBits: Sub<PageBits>,
+    <SizeBits as Sub<PageBits>>::Output: Unsigned,
+    <SizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+{
+    pub(crate) fn delete_caps(
+        self,
+        cnode: &LocalCap<LocalCNode>,
+    ) -> Result<LocalCNodeSlots<NumPages<SizeBits>>, SeL4Error> {
+        let start_cptr = self.caps.start_cptr;
+        for cptr in start_cptr..start_cptr + <NumPages<SizeBits>>::USIZE {
+            unsafe {
+                seL4_CNode_Delete(
+                    cnode.cptr,          // _service
+                    cptr,                // index
+                    seL4_WordBits as u8, // depth
+                )
+            }
+            .as_result()
+            .map_err(SeL4Error::CNodeDelete)?;
+        }
+        Ok(Cap::internal_new(cnode.cptr, start_cptr))
+    }
+}
+
+impl<SizeBits: Unsigned, SS: SharedStatus, CS: CacheStatus>
+    MappedMemoryRegion<SizeBits, SS, role::Local, CS>
 where
     SizeBits: IsGreaterOrEqual<PageBits>,
     SizeBits: Sub<PageBits>,
@@ -263,6 +444,16 @@ where
         self.caps.start_cap_data.state.rights
     }
 
+    /// Unmap the region from `vspace`, the vspace it's mapped in, and give
+    /// its addresses back for mapping something else there. The unmapped
+    /// region can be mapped again, or deleted to reuse its memory.
+    pub fn unmap(
+        self,
+        vspace: &mut VSpace,
+    ) -> Result<UnmappedMemoryRegion<SizeBits, SS>, VSpaceError> {
+        vspace.reclaim_region(self)
+    }
+
     pub fn as_slice(&self) -> &[u8] {
         unsafe { core::slice::from_raw_parts(self.vaddr() as *const u8, self.size_bytes()) }
     }
@@ -271,6 +462,24 @@ where
         unsafe { core::slice::from_raw_parts_mut(self.vaddr() as *mut u8, self.size_bytes()) }
     }
 
+    /// Unmap every `stride_pages`th page, starting with the lowest,
+    /// leaving holes that fault when touched, e.g. guard pages below the
+    /// stacks carved out of the region.
+    ///
+    /// The region keeps its type, so this is only for regions that are
+    /// never unmapped as a whole.
+    pub(crate) fn unmap_guard_pages(&self, stride_pages: usize) -> Result<(), SeL4Error> {
+        for index in (0..self.caps.len()).step_by(stride_pages) {
+            let page: LocalCap<Page<page_state::Mapped>> = Cap {
+                cptr: self.caps.start_cptr + index,
+                cap_data: CapRangeDataReconstruction::reconstruct(index, &self.caps.start_cap_data),
+                _role: PhantomData,
+            };
+            page.unmap()?;
+        }
+        Ok(())
+    }
+
     pub fn flush(&self) -> Result<(), SeL4Error> {
         self.caps.for_each::<SeL4Error, _>(|cap| {
             unsafe {
@@ -301,7 +510,7 @@ where
     #[cfg(feature = "test_support")]
     /// Super dangerous copy-aliasing
     pub(crate) unsafe fn dangerous_internal_alias(&mut self) -> Self {
-        MappedMemoryRegion::unchecked_new(
+        Self::unchecked_new(
             self.caps.start_cptr,
             page_state::Mapped {
                 vaddr: self.vaddr(),
@@ -317,8 +526,8 @@ where
         self,
     ) -> Result<
         (
-            MappedMemoryRegion<op!(SizeBits - U1), SS>,
-            MappedMemoryRegion<op!(SizeBits - U1), SS>,
+            MappedMemoryRegion<op!(SizeBits - U1), SS, role::Local, CS>,
+            MappedMemoryRegion<op!(SizeBits - U1), SS, role::Local, CS>,
         ),
         VSpaceError,
     >
@@ -357,6 +566,7 @@ where
                 kind: self.kind,
                 _size_bits: PhantomData,
                 _shared_status: PhantomData,
+                _cache_status: PhantomData,
             },
             MappedMemoryRegion {
                 caps: CapRange::new(
@@ -372,6 +582,7 @@ where
                 kind: self.kind,
                 _size_bits: PhantomData,
                 _shared_status: PhantomData,
+                _cache_status: PhantomData,
             },
         ))
     }
@@ -394,8 +605,8 @@ where
         self,
     ) -> Result<
         (
-            MappedMemoryRegion<TargetSize, SS>,
-            MappedMemoryRegion<op!(SizeBits - U1), SS>,
+            MappedMemoryRegion<TargetSize, SS, role::Local, CS>,
+            MappedMemoryRegion<op!(SizeBits - U1), SS, role::Local, CS>,
         ),
         VSpaceError,
     >
@@ -431,12 +642,211 @@ where
                 kind: a.kind,
                 _size_bits: PhantomData,
                 _shared_status: PhantomData,
+                _cache_status: PhantomData,
             },
             b,
         ))
     }
 }
 
+impl<SizeBits: Unsigned, SS: SharedStatus> MappedMemoryRegion<SizeBits, SS>
+where
+    SizeBits: IsGreaterOrEqual<PageBits>,
+    SizeBits: Sub<PageBits>,
+    <SizeBits as Sub<PageBits>>::Output: Unsigned,
+    <SizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+{
+    /// Flush the region so it can be handed to a device. The returned
+    /// token borrows the region, so it can't be written through
+    /// `as_mut_slice` (and dirty the cache again) until the device is done
+    /// with it.
+    pub fn flush_for_dma(&self) -> Result<CacheFlushed<'_>, SeL4Error> {
+        self.flush()?;
+        self.cache_flushed()
+    }
+
+    /// Like `flush_for_dma`, but only writing the cache back, for regions
+    /// the device only reads.
+    pub fn clean_for_dma(&self) -> Result<CacheFlushed<'_>, SeL4Error> {
+        self.clean()?;
+        self.cache_flushed()
+    }
+
+    /// Write back whatever the CPU has cached of the region, so that a
+    /// device reading it sees the CPU's writes.
+    pub fn clean(&self) -> Result<(), SeL4Error> {
+        self.caps.for_each::<SeL4Error, _>(|cap| {
+            unsafe {
+                arch::clean_page(cap.cptr)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Drop whatever the CPU has cached of the region, so that the CPU
+    /// reads what a device wrote there. This borrows the region mutably,
+    /// so the device has to be done with it: no `DmaRegion` of it can
+    /// still be around. Anything the CPU wrote since the region was last
+    /// cleaned or flushed is lost.
+    pub fn invalidate(&mut self) -> Result<(), SeL4Error> {
+        self.caps.for_each::<SeL4Error, _>(|cap| {
+            unsafe {
+                arch::invalidate_page(cap.cptr)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn cache_flushed(&self) -> Result<CacheFlushed<'_>, SeL4Error> {
+        Ok(CacheFlushed {
+            region: DmaRegion {
+                vaddr: self.vaddr(),
+                paddr: self.paddr()?,
+                size_bytes: self.size_bytes(),
+                _region: PhantomData,
+            },
+        })
+    }
+}
+
+impl<SizeBits: Unsigned, SS: SharedStatus, CS: DmaCoherent>
+    MappedMemoryRegion<SizeBits, SS, role::Local, CS>
+where
+    SizeBits: IsGreaterOrEqual<PageBits>,
+    SizeBits: Sub<PageBits>,
+    <SizeBits as Sub<PageBits>>::Output: Unsigned,
+    <SizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+{
+    /// The region as handed to a device, no flush needed.
+    pub fn dma_region(&self) -> Result<DmaRegion<'_>, SeL4Error> {
+        Ok(DmaRegion {
+            vaddr: self.vaddr(),
+            paddr: self.paddr()?,
+            size_bytes: self.size_bytes(),
+            _region: PhantomData,
+        })
+    }
+}
+
+impl<SizeBits: Unsigned, SS: SharedStatus>
+    MappedMemoryRegion<SizeBits, SS, role::Local, cache_status::WriteCombining>
+where
+    SizeBits: IsGreaterOrEqual<PageBits>,
+    SizeBits: Sub<PageBits>,
+    <SizeBits as Sub<PageBits>>::Output: Unsigned,
+    <SizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+{
+    /// Drain the write-combining buffers so the region can be handed to
+    /// a device. As with `flush_for_dma`, the returned token borrows the
+    /// region, so it can't be written through again until the device is
+    /// done with it.
+    pub fn fence_for_dma(&self) -> Result<CacheFlushed<'_>, SeL4Error> {
+        arch::drain_write_combining();
+        Ok(CacheFlushed {
+            region: DmaRegion {
+                vaddr: self.vaddr(),
+                paddr: self.paddr()?,
+                size_bytes: self.size_bytes(),
+                _region: PhantomData,
+            },
+        })
+    }
+}
+
+/// Proof that a cached region was flushed or cleaned, see `flush_for_dma`
+/// and `clean_for_dma`, or that a write-combining region was fenced, see
+/// `fence_for_dma`.
+pub struct CacheFlushed<'a> {
+    region: DmaRegion<'a>,
+}
+
+impl<'a> CacheFlushed<'a> {
+    pub fn dma_region(self) -> DmaRegion<'a> {
+        self.region
+    }
+}
+
+/// A physically contiguous, mapped region that's safe to hand to a device
+/// for DMA: either it isn't cached, or it was flushed and hasn't been
+/// written through since. It can only be had from a `DmaCoherent` region
+/// or a `CacheFlushed` token, so APIs programming a device with memory
+/// should take one of these rather than addresses.
+///
+/// It isn't `Clone`, so splitting it is the only way to have two, and
+/// they never overlap.
+#[derive(Debug)]
+pub struct DmaRegion<'a> {
+    vaddr: usize,
+    paddr: usize,
+    size_bytes: usize,
+    _region: PhantomData<&'a ()>,
+}
+
+impl<'a> DmaRegion<'a> {
+    pub fn vaddr(&self) -> usize {
+        self.vaddr
+    }
+
+    pub fn paddr(&self) -> usize {
+        self.paddr
+    }
+
+    pub fn size_bytes(&self) -> usize {
+        self.size_bytes
+    }
+
+    /// Split the region at byte `at`, keeping `[0, at)` and returning
+    /// `[at, size_bytes)`, e.g. to carve a device's descriptor rings and
+    /// buffers out of one region.
+    pub fn split_off(&mut self, at: usize) -> Result<Self, DmaRegionError> {
+        if at >= self.size_bytes {
+            return Err(DmaRegionError::InvalidSize);
+        }
+        let tail = DmaRegion {
+            vaddr: self.vaddr + at,
+            paddr: self.paddr + at,
+            size_bytes: self.size_bytes - at,
+            _region: PhantomData,
+        };
+        self.size_bytes = at;
+        Ok(tail)
+    }
+
+    /// Split the region at byte `at`, keeping `[at, size_bytes)` and
+    /// returning `[0, at)`.
+    pub fn split(&mut self, at: usize) -> Result<Self, DmaRegionError> {
+        let tail = self.split_off(at)?;
+        Ok(core::mem::replace(self, tail))
+    }
+
+    pub fn shrink_to(&mut self, size_bytes: usize) -> Result<(), DmaRegionError> {
+        if size_bytes > self.size_bytes {
+            return Err(DmaRegionError::InvalidSize);
+        }
+        self.size_bytes = size_bytes;
+        Ok(())
+    }
+
+    pub fn as_ptr<T>(&self) -> *const T {
+        debug_assert!(self.size_bytes >= core::mem::size_of::<T>());
+        self.vaddr as *const T
+    }
+
+    pub fn as_mut_ptr<T>(&mut self) -> *mut T {
+        debug_assert!(self.size_bytes >= core::mem::size_of::<T>());
+        self.vaddr as *mut T
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DmaRegionError {
+    /// The split or shrink would leave a region empty or grow it
+    InvalidSize,
+}
+
 pub struct WeakMemoryRegion<State: PageState, SS: SharedStatus, CapRole: CNodeRole = role::Local> {
     pub(super) caps: WeakCapRange<Page<State>, CapRole>,
     pub(super) kind: WeakMemoryKind,
@@ -503,9 +913,9 @@ impl<State: PageState, SS: SharedStatus, CapRole: CNodeRole> WeakMemoryRegion<St
         })
     }
 
-    pub(super) fn as_strong<SizeBits: Unsigned>(
+    pub(crate) fn as_strong<SizeBits: Unsigned, CS: CacheStatus>(
         self,
-    ) -> Result<MemoryRegion<State, SizeBits, SS, CapRole>, VSpaceError>
+    ) -> Result<MemoryRegion<State, SizeBits, SS, CapRole, CS>, VSpaceError>
     where
         // Forces regions to be page-aligned.
         SizeBits: IsGreaterOrEqual<PageBits>,
@@ -531,6 +941,19 @@ impl<State: PageState, SS: SharedStatus, CapRole: CNodeRole> WeakMemoryRegion<St
             _shared_status: PhantomData,
         }
     }
+
+    /// Mark a shared region exclusive again, for when every copy it was
+    /// shared through is known to be gone.
+    pub(crate) fn unchecked_to_exclusive(
+        self,
+    ) -> WeakMemoryRegion<State, shared_status::Exclusive, CapRole> {
+        WeakMemoryRegion {
+            caps: self.caps,
+            kind: self.kind,
+            size_bits: self.size_bits,
+            _shared_status: PhantomData,
+        }
+    }
 }
 
 impl<SS: SharedStatus, CapRole: CNodeRole> WeakMappedMemoryRegion<SS, CapRole> {
@@ -0,0 +1,163 @@
+//! Labels for memory regions, for crash dumps and memory reports.
+//!
+//! Regions don't carry any description of what they're for, so by the
+//! time one shows up in a fault or a memory report it's an anonymous
+//! range of addresses. The root task can tag regions as it creates them
+//! in a `RegionRegistry`, with a static string and the process that owns
+//! them, and look them up by address later on.
+use core::fmt;
+use core::ops::Sub;
+
+use arrayvec::ArrayVec;
+use typenum::*;
+
+use crate::arch::PageBits;
+use crate::cap::{page_state, role, PageState};
+use crate::error::SeL4Error;
+use crate::pow::{Pow, _Pow};
+use crate::vspace::{CacheStatus, MemoryRegion, SharedStatus};
+
+/// The most regions a `RegionRegistry` keeps track of
+pub const MAX_TAGGED_REGIONS: usize = 64;
+
+/// An identifier for the process owning a region, as assigned by the root
+/// task
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProcessId(pub u16);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RegionRecord {
+    pub tag: &'static str,
+    pub owner: Option<ProcessId>,
+    pub paddr: usize,
+    pub size_bytes: usize,
+    /// Where the region was mapped when it was tagged, if it was
+    pub vaddr: Option<usize>,
+}
+
+impl RegionRecord {
+    pub fn contains_paddr(&self, paddr: usize) -> bool {
+        self.paddr <= paddr && paddr - self.paddr < self.size_bytes
+    }
+
+    pub fn contains_vaddr(&self, vaddr: usize) -> bool {
+        self.vaddr.map_or(false, |start| {
+            start <= vaddr && vaddr - start < self.size_bytes
+        })
+    }
+}
+
+impl fmt::Display for RegionRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "'{}' paddr {:#010x}..{:#010x}",
+            self.tag,
+            self.paddr,
+            self.paddr + self.size_bytes
+        )?;
+        if let Some(vaddr) = self.vaddr {
+            write!(f, " vaddr {:#010x}", vaddr)?;
+        }
+        if let Some(owner) = self.owner {
+            write!(f, " owner {}", owner.0)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RegionRegistryError {
+    RegistryFull,
+    SeL4Error(SeL4Error),
+}
+
+impl From<SeL4Error> for RegionRegistryError {
+    fn from(e: SeL4Error) -> Self {
+        RegionRegistryError::SeL4Error(e)
+    }
+}
+
+#[derive(Default)]
+pub struct RegionRegistry {
+    records: ArrayVec<[RegionRecord; MAX_TAGGED_REGIONS]>,
+}
+
+impl RegionRegistry {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Tag a region by its physical address, so that the tag applies to
+    /// it wherever it's later mapped or moved to
+    pub fn tag<State: PageState, SizeBits: Unsigned, SS: SharedStatus, CS: CacheStatus>(
+        &mut self,
+        region: &MemoryRegion<State, SizeBits, SS, role::Local, CS>,
+        tag: &'static str,
+        owner: Option<ProcessId>,
+    ) -> Result<(), RegionRegistryError>
+    where
+        SizeBits: IsGreaterOrEqual<PageBits>,
+        SizeBits: Sub<PageBits>,
+        <SizeBits as Sub<PageBits>>::Output: Unsigned,
+        <SizeBits as Sub<PageBits>>::Output: _Pow,
+        Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+    {
+        self.push(RegionRecord {
+            tag,
+            owner,
+            paddr: region.paddr()?,
+            size_bytes: region.size_bytes(),
+            vaddr: None,
+        })
+    }
+
+    /// Tag a mapped region, recording where it's mapped as well
+    pub fn tag_mapped<SizeBits: Unsigned, SS: SharedStatus, CS: CacheStatus>(
+        &mut self,
+        region: &MemoryRegion<page_state::Mapped, SizeBits, SS, role::Local, CS>,
+        tag: &'static str,
+        owner: Option<ProcessId>,
+    ) -> Result<(), RegionRegistryError>
+    where
+        SizeBits: IsGreaterOrEqual<PageBits>,
+        SizeBits: Sub<PageBits>,
+        <SizeBits as Sub<PageBits>>::Output: Unsigned,
+        <SizeBits as Sub<PageBits>>::Output: _Pow,
+        Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+    {
+        self.push(RegionRecord {
+            tag,
+            owner,
+            paddr: region.paddr()?,
+            size_bytes: region.size_bytes(),
+            vaddr: Some(region.vaddr()),
+        })
+    }
+
+    fn push(&mut self, record: RegionRecord) -> Result<(), RegionRegistryError> {
+        self.records
+            .try_push(record)
+            .map_err(|_| RegionRegistryError::RegistryFull)
+    }
+
+    /// The tagged region containing a physical address
+    pub fn find_paddr(&self, paddr: usize) -> Option<&RegionRecord> {
+        self.records.iter().find(|r| r.contains_paddr(paddr))
+    }
+
+    /// The tagged region a process has mapped at a virtual address
+    pub fn find_vaddr(&self, owner: ProcessId, vaddr: usize) -> Option<&RegionRecord> {
+        self.records
+            .iter()
+            .find(|r| r.owner == Some(owner) && r.contains_vaddr(vaddr))
+    }
+
+    pub fn owned_by(&self, owner: ProcessId) -> impl Iterator<Item = &RegionRecord> {
+        self.records.iter().filter(move |r| r.owner == Some(owner))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &RegionRecord> {
+        self.records.iter()
+    }
+}
@@ -0,0 +1,166 @@
+//! Loading position-independent (ET_DYN) ELF images.
+//!
+//! A PIE image is linked as if it were loaded at address zero. ferros
+//! loads it `PIE_LOAD_BIAS` bytes higher, and fixes up the words the
+//! image's dynamic relocations point at. There's no dynamic linker, so
+//! only relative relocations, which need nothing but the bias, are
+//! supported, and they may only target writable segments; the read-only
+//! ones are mapped straight out of the root task's image.
+
+use xmas_elf::header;
+use xmas_elf::sections::{SectionData, ShType, SHF_ALLOC};
+use xmas_elf::ElfFile;
+
+use crate::arch;
+
+use super::{loaded_range, VSpaceError, PAGE_MASK};
+
+/// Where a position-independent image is loaded, page aligned and clear
+/// of the null page.
+pub const PIE_LOAD_BIAS: usize = 0x40_0000;
+
+const WORD_BYTES: usize = core::mem::size_of::<usize>();
+
+/// How far an ELF image is moved from the addresses it was linked at:
+/// `PIE_LOAD_BIAS` for position-independent images, zero otherwise.
+pub fn elf_load_bias(elf: &ElfFile) -> usize {
+    if is_position_independent(elf) {
+        PIE_LOAD_BIAS
+    } else {
+        0
+    }
+}
+
+/// The address a process loaded from `elf` starts at, rebased by
+/// `elf_load_bias`.
+pub fn elf_entry_point(elf: &ElfFile) -> usize {
+    elf.header.pt2.entry_point() as usize + elf_load_bias(elf)
+}
+
+fn is_position_independent(elf: &ElfFile) -> bool {
+    elf.header.pt2.type_().as_type() == header::Type::SharedObject
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Relocation {
+    /// The unbiased address of the word to fix up
+    offset: usize,
+    kind: u32,
+    /// Only `Rela` relocations carry an addend, `Rel` ones use the word
+    /// already in place
+    addend: Option<u64>,
+}
+
+/// Call `f` with each of the dynamic relocations of a position-independent
+/// image, and none for any other.
+pub(super) fn for_each_relocation<F: FnMut(Relocation) -> Result<(), VSpaceError>>(
+    elf: &ElfFile,
+    mut f: F,
+) -> Result<(), VSpaceError> {
+    if !is_position_independent(elf) {
+        return Ok(());
+    }
+    for section in elf.section_iter() {
+        match section.get_type() {
+            Ok(ShType::Rela) | Ok(ShType::Rel) => (),
+            _ => continue,
+        }
+        // Relocations not loaded with the image are for a static linker
+        if section.flags() & SHF_ALLOC == 0 {
+            continue;
+        }
+        match section.get_data(elf).map_err(VSpaceError::ElfParseError)? {
+            SectionData::Rela32(relas) => {
+                for r in relas {
+                    f(Relocation {
+                        offset: r.get_offset() as usize,
+                        kind: u32::from(r.get_type()),
+                        addend: Some(u64::from(r.get_addend())),
+                    })?;
+                }
+            }
+            SectionData::Rela64(relas) => {
+                for r in relas {
+                    f(Relocation {
+                        offset: r.get_offset() as usize,
+                        kind: r.get_type(),
+                        addend: Some(r.get_addend()),
+                    })?;
+                }
+            }
+            SectionData::Rel32(rels) => {
+                for r in rels {
+                    f(Relocation {
+                        offset: r.get_offset() as usize,
+                        kind: u32::from(r.get_type()),
+                        addend: None,
+                    })?;
+                }
+            }
+            SectionData::Rel64(rels) => {
+                for r in rels {
+                    f(Relocation {
+                        offset: r.get_offset() as usize,
+                        kind: r.get_type(),
+                        addend: None,
+                    })?;
+                }
+            }
+            _ => return Err(VSpaceError::ElfParseError("Malformed relocation section")),
+        }
+    }
+    Ok(())
+}
+
+/// Check every relocation can be applied before loading any of the image.
+pub(super) fn check_relocations(elf: &ElfFile) -> Result<(), VSpaceError> {
+    for_each_relocation(elf, |r| {
+        if r.kind == 0 {
+            // R_*_NONE
+            return Ok(());
+        }
+        if r.kind != arch::ELF_RELATIVE_RELOCATION {
+            return Err(VSpaceError::ElfUnsupportedRelocation { kind: r.kind });
+        }
+        // Aligned words never straddle a page
+        if r.offset % WORD_BYTES != 0 {
+            return Err(VSpaceError::ElfParseError("Misaligned relocation"));
+        }
+        let in_writable_segment = elf
+            .program_iter()
+            .filter(|h| h.get_type() == Ok(xmas_elf::program::Type::Load))
+            .filter(|h| h.flags().is_write())
+            .any(|h| {
+                let (start, end) = loaded_range(&h);
+                start <= r.offset && r.offset + WORD_BYTES <= end
+            });
+        if !in_writable_segment {
+            return Err(VSpaceError::ElfRelocationOutsideWritableSegment { offset: r.offset });
+        }
+        Ok(())
+    })
+}
+
+/// Apply the relocations that land in the page loaded at `page_vaddr`
+/// (biased) to its contents, `page`. The relocations must have passed
+/// `check_relocations`.
+pub(super) fn relocate_page(elf: &ElfFile, load_bias: usize, page_vaddr: usize, page: &mut [u8]) {
+    let _ = for_each_relocation(elf, |r| {
+        let vaddr = r.offset + load_bias;
+        if r.kind != arch::ELF_RELATIVE_RELOCATION || vaddr & !PAGE_MASK != page_vaddr {
+            return Ok(());
+        }
+        let at = vaddr & PAGE_MASK;
+        let word = &mut page[at..at + WORD_BYTES];
+        let value = match r.addend {
+            Some(addend) => load_bias.wrapping_add(addend as usize),
+            None => {
+                let mut bytes = [0; WORD_BYTES];
+                bytes.copy_from_slice(word);
+                usize::from_ne_bytes(bytes).wrapping_add(load_bias)
+            }
+        };
+        word.copy_from_slice(&value.to_ne_bytes());
+        Ok(())
+    });
+}
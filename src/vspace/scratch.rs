@@ -0,0 +1,409 @@
+//! Checking scratch regions out for temporary mappings.
+//!
+//! `ScratchRegion::temporarily_map_region` takes the region by `&mut`,
+//! so setup code that needs scratch space in several places at once has
+//! to thread a single mutable borrow through all of them. A scratch
+//! region can instead be shared by `&` reference and checked out with
+//! `ScratchRegion::checkout` for as long as the returned `ScratchGuard`
+//! is around. Checking out a region that's already out is an error
+//! rather than two mappings landing on the same addresses. Code that
+//! really does need scratch space in two places at the same time can
+//! reserve as many scratch regions as it likes with
+//! `VSpace::reserve_scratch`, each backed by its own sacrificial page,
+//! and gather them into a `ScratchPool` that checks out whichever one is
+//! free.
+//!
+//! Rather than mapping a region only for the length of a closure,
+//! `ScratchGuard::map` and `ScratchPool::map` hand back a
+//! `ScratchMapping` that keeps the region mapped until it's dropped.
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut, Sub};
+
+use arrayvec::ArrayVec;
+use typenum::*;
+
+use crate::arch::{self, PageBits, PagingRoot};
+use crate::cap::{page_state, Cap, InternalASID, LocalCap, Page, PhantomCap};
+use crate::error::SeL4Error;
+use crate::pow::{Pow, _Pow};
+use crate::userland::CapRights;
+
+use super::{
+    shared_status, MappedMemoryRegion, MemoryRegion, ScratchRegion, UnmappedMemoryRegion, VSpace,
+    VSpaceError,
+};
+
+impl VSpace {
+    /// Reserve a scratch region of `PageCount` pages, using
+    /// `sacrificial_page` to instantiate its paging structures.
+    pub fn reserve_scratch<PageCount: Unsigned>(
+        &mut self,
+        sacrificial_page: LocalCap<Page<page_state::Unmapped>>,
+    ) -> Result<ScratchRegion<PageCount>, VSpaceError>
+    where
+        PageCount: IsGreaterOrEqual<U1, Output = True>,
+    {
+        self.reserve::<PageCount>(sacrificial_page)?.as_scratch(self)
+    }
+}
+
+impl<PageCount: Unsigned> ScratchRegion<PageCount> {
+    /// Check the region out for temporary mappings until the returned
+    /// guard is dropped.
+    pub fn checkout(&self) -> Result<ScratchGuard<PageCount>, VSpaceError> {
+        if self.checked_out.replace(true) {
+            return Err(VSpaceError::ScratchRegionInUse);
+        }
+        Ok(ScratchGuard {
+            scratch: self,
+            paging_root: Cap {
+                cptr: self.paging_root.cptr,
+                cap_data: PagingRoot::phantom_instance(),
+                _role: PhantomData,
+            },
+        })
+    }
+
+    pub fn is_checked_out(&self) -> bool {
+        self.checked_out.get()
+    }
+
+    pub fn vaddr(&self) -> usize {
+        self.reserved_region.vaddr
+    }
+
+    pub fn size_bytes(&self) -> usize {
+        PageCount::USIZE * arch::PageBytes::USIZE
+    }
+}
+
+/// A scratch region checked out with `ScratchRegion::checkout`, handed
+/// back when dropped.
+pub struct ScratchGuard<'s, PageCount: Unsigned> {
+    scratch: &'s ScratchRegion<PageCount>,
+    paging_root: LocalCap<PagingRoot>,
+}
+
+impl<'s, PageCount: Unsigned> ScratchGuard<'s, PageCount> {
+    /// Map a region temporarily and do with it as thou wilt with `f`,
+    /// as with `ScratchRegion::temporarily_map_region`.
+    pub fn temporarily_map_region<SizeBits: Unsigned, F, Out>(
+        &mut self,
+        region: &mut UnmappedMemoryRegion<SizeBits, shared_status::Exclusive>,
+        f: F,
+    ) -> Result<Out, VSpaceError>
+    where
+        SizeBits: IsGreaterOrEqual<PageBits>,
+        SizeBits: Sub<PageBits>,
+        <SizeBits as Sub<PageBits>>::Output: Unsigned,
+        <SizeBits as Sub<PageBits>>::Output: _Pow,
+        Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+        F: Fn(&mut MappedMemoryRegion<SizeBits, shared_status::Exclusive>) -> Out,
+    {
+        map_temporarily(
+            self.scratch.reserved_region.vaddr,
+            self.scratch.reserved_region.asid,
+            PageCount::USIZE,
+            &mut self.paging_root,
+            region,
+            f,
+        )
+    }
+
+    /// Map a region for as long as the returned `ScratchMapping` is
+    /// around, holding on to the checkout until then.
+    pub fn map<'r, SizeBits: Unsigned>(
+        mut self,
+        region: &'r mut UnmappedMemoryRegion<SizeBits, shared_status::Exclusive>,
+    ) -> Result<ScratchMapping<'s, 'r, SizeBits, PageCount>, VSpaceError>
+    where
+        SizeBits: IsGreaterOrEqual<PageBits>,
+        SizeBits: Sub<PageBits>,
+        <SizeBits as Sub<PageBits>>::Output: Unsigned,
+        <SizeBits as Sub<PageBits>>::Output: _Pow,
+        Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+    {
+        let mapped = map_at(
+            self.scratch.reserved_region.vaddr,
+            self.scratch.reserved_region.asid,
+            PageCount::USIZE,
+            &mut self.paging_root,
+            region,
+        )?;
+        Ok(ScratchMapping {
+            mapped: Some(mapped),
+            _guard: self,
+            _region: PhantomData,
+        })
+    }
+
+    pub fn vaddr(&self) -> usize {
+        self.scratch.vaddr()
+    }
+}
+
+impl<'s, PageCount: Unsigned> Drop for ScratchGuard<'s, PageCount> {
+    fn drop(&mut self) {
+        self.scratch.checked_out.set(false);
+    }
+}
+
+/// A region mapped into a scratch region with `ScratchGuard::map` or
+/// `ScratchPool::map`. It's unmapped, and the scratch region handed
+/// back, when this is dropped.
+pub struct ScratchMapping<'s, 'r, SizeBits: Unsigned, PageCount: Unsigned>
+where
+    SizeBits: IsGreaterOrEqual<PageBits>,
+    SizeBits: Sub<PageBits>,
+    <SizeBits as Sub<PageBits>>::Output: Unsigned,
+    <SizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+{
+    // Only `None` once unmapped
+    mapped: Option<MappedMemoryRegion<SizeBits, shared_status::Exclusive>>,
+    // Dropped after the region's unmapped
+    _guard: ScratchGuard<'s, PageCount>,
+    _region: PhantomData<&'r mut UnmappedMemoryRegion<SizeBits, shared_status::Exclusive>>,
+}
+
+impl<'s, 'r, SizeBits: Unsigned, PageCount: Unsigned> ScratchMapping<'s, 'r, SizeBits, PageCount>
+where
+    SizeBits: IsGreaterOrEqual<PageBits>,
+    SizeBits: Sub<PageBits>,
+    <SizeBits as Sub<PageBits>>::Output: Unsigned,
+    <SizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+{
+    /// Unmap the region, for when unmapping errors matter. Dropping the
+    /// mapping unmaps it too, but can only ignore them.
+    pub fn unmap(mut self) -> Result<(), VSpaceError> {
+        match self.mapped.take() {
+            Some(mapped) => unmap(mapped),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<'s, 'r, SizeBits: Unsigned, PageCount: Unsigned> Deref
+    for ScratchMapping<'s, 'r, SizeBits, PageCount>
+where
+    SizeBits: IsGreaterOrEqual<PageBits>,
+    SizeBits: Sub<PageBits>,
+    <SizeBits as Sub<PageBits>>::Output: Unsigned,
+    <SizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+{
+    type Target = MappedMemoryRegion<SizeBits, shared_status::Exclusive>;
+
+    fn deref(&self) -> &Self::Target {
+        self.mapped
+            .as_ref()
+            .expect("A scratch mapping is only unmapped when it's consumed")
+    }
+}
+
+impl<'s, 'r, SizeBits: Unsigned, PageCount: Unsigned> DerefMut
+    for ScratchMapping<'s, 'r, SizeBits, PageCount>
+where
+    SizeBits: IsGreaterOrEqual<PageBits>,
+    SizeBits: Sub<PageBits>,
+    <SizeBits as Sub<PageBits>>::Output: Unsigned,
+    <SizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.mapped
+            .as_mut()
+            .expect("A scratch mapping is only unmapped when it's consumed")
+    }
+}
+
+impl<'s, 'r, SizeBits: Unsigned, PageCount: Unsigned> Drop
+    for ScratchMapping<'s, 'r, SizeBits, PageCount>
+where
+    SizeBits: IsGreaterOrEqual<PageBits>,
+    SizeBits: Sub<PageBits>,
+    <SizeBits as Sub<PageBits>>::Output: Unsigned,
+    <SizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+{
+    fn drop(&mut self) {
+        if let Some(mapped) = self.mapped.take() {
+            let _ = unmap(mapped);
+        }
+    }
+}
+
+/// The most scratch regions a single `ScratchPool` can hold
+pub const MAX_SCRATCH_POOL_REGIONS: usize = 8;
+
+/// Several scratch regions, handed out to whichever setup code asks for
+/// one next, so that code paths needing scratch space at the same time
+/// (e.g. building several vspaces from ELFs) don't have to take turns
+/// with a single region.
+pub struct ScratchPool<PageCount: Unsigned = crate::userland::process::DefaultStackPageCount> {
+    regions: ArrayVec<[ScratchRegion<PageCount>; MAX_SCRATCH_POOL_REGIONS]>,
+}
+
+impl<PageCount: Unsigned> Default for ScratchPool<PageCount> {
+    fn default() -> Self {
+        ScratchPool {
+            regions: ArrayVec::new(),
+        }
+    }
+}
+
+impl<PageCount: Unsigned> ScratchPool<PageCount> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a scratch region to the pool
+    pub fn add(&mut self, region: ScratchRegion<PageCount>) -> Result<(), VSpaceError> {
+        self.regions
+            .try_push(region)
+            .map_err(|_| VSpaceError::ScratchPoolFull)
+    }
+
+    /// Reserve a scratch region in `vspace` with `sacrificial_page`, as
+    /// with `VSpace::reserve_scratch`, and add it to the pool
+    pub fn reserve(
+        &mut self,
+        vspace: &mut VSpace,
+        sacrificial_page: LocalCap<Page<page_state::Unmapped>>,
+    ) -> Result<(), VSpaceError>
+    where
+        PageCount: IsGreaterOrEqual<U1, Output = True>,
+    {
+        if self.regions.len() == self.regions.capacity() {
+            return Err(VSpaceError::ScratchPoolFull);
+        }
+        self.add(vspace.reserve_scratch(sacrificial_page)?)
+    }
+
+    /// How many scratch regions the pool holds
+    pub fn len(&self) -> usize {
+        self.regions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+
+    /// How many of the pool's scratch regions aren't checked out
+    pub fn available(&self) -> usize {
+        self.regions.iter().filter(|r| !r.is_checked_out()).count()
+    }
+
+    /// Check out whichever scratch region is free
+    pub fn checkout(&self) -> Result<ScratchGuard<PageCount>, VSpaceError> {
+        self.regions
+            .iter()
+            .find_map(|r| r.checkout().ok())
+            .ok_or(VSpaceError::ScratchPoolExhausted)
+    }
+
+    /// Map `region` into whichever scratch region is free, for as long
+    /// as the returned `ScratchMapping` is around
+    pub fn map<'r, SizeBits: Unsigned>(
+        &self,
+        region: &'r mut UnmappedMemoryRegion<SizeBits, shared_status::Exclusive>,
+    ) -> Result<ScratchMapping<'_, 'r, SizeBits, PageCount>, VSpaceError>
+    where
+        SizeBits: IsGreaterOrEqual<PageBits>,
+        SizeBits: Sub<PageBits>,
+        <SizeBits as Sub<PageBits>>::Output: Unsigned,
+        <SizeBits as Sub<PageBits>>::Output: _Pow,
+        Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+    {
+        self.checkout()?.map(region)
+    }
+}
+
+/// Map `region` at `vaddr`, call `f` with it, and unmap it again
+pub(super) fn map_temporarily<SizeBits: Unsigned, F, Out>(
+    vaddr: usize,
+    asid: InternalASID,
+    page_count: usize,
+    paging_root: &mut LocalCap<PagingRoot>,
+    region: &mut UnmappedMemoryRegion<SizeBits, shared_status::Exclusive>,
+    f: F,
+) -> Result<Out, VSpaceError>
+where
+    SizeBits: IsGreaterOrEqual<PageBits>,
+    SizeBits: Sub<PageBits>,
+    <SizeBits as Sub<PageBits>>::Output: Unsigned,
+    <SizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+    F: Fn(&mut MappedMemoryRegion<SizeBits, shared_status::Exclusive>) -> Out,
+{
+    let mut mapped_region = map_at(vaddr, asid, page_count, paging_root, region)?;
+    let res = f(&mut mapped_region);
+    unmap(mapped_region)?;
+    Ok(res)
+}
+
+/// Map the pages of `region` at `vaddr`, handing back a
+/// `MappedMemoryRegion` for them that aliases `region`'s caps.
+fn map_at<SizeBits: Unsigned>(
+    vaddr: usize,
+    asid: InternalASID,
+    page_count: usize,
+    paging_root: &mut LocalCap<PagingRoot>,
+    region: &UnmappedMemoryRegion<SizeBits, shared_status::Exclusive>,
+) -> Result<MappedMemoryRegion<SizeBits, shared_status::Exclusive>, VSpaceError>
+where
+    SizeBits: IsGreaterOrEqual<PageBits>,
+    SizeBits: Sub<PageBits>,
+    <SizeBits as Sub<PageBits>>::Output: Unsigned,
+    <SizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+{
+    if region.size_bytes() > page_count * arch::PageBytes::USIZE {
+        return Err(VSpaceError::ScratchRegionTooSmall);
+    }
+    let mut next_addr = vaddr;
+
+    let res: Result<(), SeL4Error> = region.caps.for_each::<SeL4Error, _>(|page| {
+        unsafe {
+            page.unchecked_page_map(
+                next_addr,
+                paging_root,
+                CapRights::RW,
+                arch::vm_attributes::DEFAULT,
+            )?;
+        }
+        next_addr += arch::PageBytes::USIZE;
+        let res: Result<(), SeL4Error> = Ok(());
+        res
+    });
+
+    res?;
+
+    // synthesize a MappedMemoryRegion to pass along
+    Ok(MemoryRegion::unchecked_new(
+        region.caps.start_cptr,
+        page_state::Mapped {
+            vaddr,
+            asid,
+            rights: CapRights::RW,
+        },
+        region.kind,
+    ))
+}
+
+fn unmap<SizeBits: Unsigned>(
+    mapped_region: MappedMemoryRegion<SizeBits, shared_status::Exclusive>,
+) -> Result<(), VSpaceError>
+where
+    SizeBits: IsGreaterOrEqual<PageBits>,
+    SizeBits: Sub<PageBits>,
+    <SizeBits as Sub<PageBits>>::Output: Unsigned,
+    <SizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+{
+    for page in mapped_region.caps.into_iter() {
+        page.unmap()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,169 @@
+//! A vspace handed off to the process running in it.
+//!
+//! `VSpace::handoff_to_child` moves a vspace's root, along with the
+//! untyped memory and slots it builds out paging structures with, into
+//! the child's CSpace. The child, holding the resulting
+//! `SelfHostedVSpace`, can then make, map and unmap regions of its own
+//! memory at runtime without asking the root task, until the vspace's
+//! untyped memory or slots run out.
+//!
+//! ```ignore
+//! pub extern "C" fn child_main(mut vspace: SelfHostedVSpace, params: ProcParams<role::Local>) {
+//!     let region = vspace.new_region::<U12>().expect("out of memory");
+//!     let mapped = vspace
+//!         .map_region(region, CapRights::RW, arch::vm_attributes::DEFAULT)
+//!         .expect("mapping failed");
+//!     ...
+//!     let region = vspace.reclaim_region(mapped).expect("unmapping failed");
+//! }
+//! ```
+use core::marker::PhantomData;
+use core::ops::Sub;
+
+use typenum::*;
+
+use crate::alloc::ut_buddy::UTBuddyError;
+use crate::arch::{self, PageBits};
+use crate::cap::{role, CNodeRole, ChildCNodeSlot, LocalCNode, LocalCap, WCNodeSlotsData};
+use crate::pow::{Pow, _Pow};
+use crate::userland::CapRights;
+
+use super::*;
+
+/// A vspace whose caps are held by the process running in it.
+pub struct SelfHostedVSpace<CapRole: CNodeRole = role::Local> {
+    vspace: VSpace<vspace_state::Imaged, CapRole>,
+}
+
+impl VSpace<vspace_state::Imaged, role::Local> {
+    /// Move the vspace's root, untyped memory and slots into a child's
+    /// CSpace for it to manage its own memory with.
+    ///
+    /// The root is moved into `child_root_slot` and the untyped memory
+    /// into `ut_transfer_slots`, which needs a slot per untyped the
+    /// vspace holds. The vspace's own slots stay behind, and
+    /// `child_paging_slots` takes their place, for the paging structures
+    /// and page caps the child makes from then on.
+    pub fn handoff_to_child(
+        self,
+        src_cnode: &LocalCap<LocalCNode>,
+        child_root_slot: ChildCNodeSlot,
+        mut ut_transfer_slots: LocalCap<WCNodeSlotsData<role::Child>>,
+        child_paging_slots: Cap<WCNodeSlotsData<role::Child>, role::Child>,
+    ) -> Result<SelfHostedVSpace<role::Child>, VSpaceError> {
+        let VSpace {
+            root,
+            asid,
+            layers,
+            untyped,
+            slots: _,
+            available_address_range,
+            ..
+        } = self;
+        let child_root = root.move_to_slot(src_cnode, child_root_slot)?;
+        let child_untyped = untyped
+            .move_to_child(src_cnode, &mut ut_transfer_slots)
+            .map_err(|e| match e {
+                UTBuddyError::NotEnoughSlots => VSpaceError::InsufficientCNodeSlots,
+                UTBuddyError::SeL4Error(se) => VSpaceError::SeL4Error(se),
+                _ => unreachable!(
+                    "All other UTBuddyError variants are irrelevant for the move_to_child call"
+                ),
+            })?;
+        Ok(SelfHostedVSpace {
+            vspace: VSpace {
+                root: child_root,
+                asid,
+                layers,
+                untyped: child_untyped,
+                slots: child_paging_slots,
+                available_address_range,
+                _state: PhantomData,
+            },
+        })
+    }
+}
+
+impl SelfHostedVSpace<role::Local> {
+    /// Make a region of `SizeBits` from the vspace's untyped memory, with
+    /// page caps in its slots.
+    pub fn new_region<SizeBits: Unsigned>(
+        &mut self,
+    ) -> Result<UnmappedMemoryRegion<SizeBits, shared_status::Exclusive>, VSpaceError>
+    where
+        SizeBits: IsGreaterOrEqual<PageBits>,
+        SizeBits: Sub<PageBits>,
+        <SizeBits as Sub<PageBits>>::Output: Unsigned,
+        <SizeBits as Sub<PageBits>>::Output: _Pow,
+        Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+    {
+        let VSpace { untyped, slots, .. } = &mut self.vspace;
+        let ut = untyped
+            .alloc(slots, SizeBits::U8)
+            .map_err(VSpaceError::UTBuddyError)?;
+        WeakUnmappedMemoryRegion::new(ut, slots)?.as_strong()
+    }
+
+    /// Map a region of memory at some address, see `VSpace::map_region`.
+    pub fn map_region<SizeBits: Unsigned>(
+        &mut self,
+        region: UnmappedMemoryRegion<SizeBits, shared_status::Exclusive>,
+        rights: CapRights,
+        vm_attributes: arch::VMAttributes,
+    ) -> Result<MappedMemoryRegion<SizeBits, shared_status::Exclusive>, VSpaceError>
+    where
+        SizeBits: IsGreaterOrEqual<PageBits>,
+        SizeBits: Sub<PageBits>,
+        <SizeBits as Sub<PageBits>>::Output: Unsigned,
+        <SizeBits as Sub<PageBits>>::Output: _Pow,
+        Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+    {
+        self.vspace.map_region(region, rights, vm_attributes)
+    }
+
+    /// Unmap a region, see `VSpace::unmap_region`.
+    pub fn unmap_region<SizeBits: Unsigned, SS: SharedStatus, CS: CacheStatus>(
+        &mut self,
+        region: MappedMemoryRegion<SizeBits, SS, role::Local, CS>,
+    ) -> Result<UnmappedMemoryRegion<SizeBits, SS>, VSpaceError>
+    where
+        SizeBits: IsGreaterOrEqual<PageBits>,
+        SizeBits: Sub<PageBits>,
+        <SizeBits as Sub<PageBits>>::Output: Unsigned,
+        <SizeBits as Sub<PageBits>>::Output: _Pow,
+        Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+    {
+        self.vspace.unmap_region(region)
+    }
+
+    /// Unmap a region and give its addresses back to the vspace, see
+    /// `VSpace::reclaim_region`.
+    pub fn reclaim_region<SizeBits: Unsigned, SS: SharedStatus, CS: CacheStatus>(
+        &mut self,
+        region: MappedMemoryRegion<SizeBits, SS, role::Local, CS>,
+    ) -> Result<UnmappedMemoryRegion<SizeBits, SS>, VSpaceError>
+    where
+        SizeBits: IsGreaterOrEqual<PageBits>,
+        SizeBits: Sub<PageBits>,
+        <SizeBits as Sub<PageBits>>::Output: Unsigned,
+        <SizeBits as Sub<PageBits>>::Output: _Pow,
+        Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+    {
+        self.vspace.reclaim_region(region)
+    }
+
+    /// Bytes of untyped memory left for regions and paging structures
+    pub fn free_bytes(&self) -> usize {
+        self.vspace.untyped.free_bytes()
+    }
+
+    /// Slots left for page caps and paging structures
+    pub fn free_slots(&self) -> usize {
+        self.vspace.slots.cap_data.size
+    }
+
+    /// The vspace itself, for the rest of its API
+    pub fn vspace(&mut self) -> &mut VSpace {
+        &mut self.vspace
+    }
+}
@@ -0,0 +1,128 @@
+//! Named windows of a vspace's addresses, set aside for particular uses.
+//!
+//! `map_region` puts a region wherever there's room, so where a child's
+//! DMA buffers or shared pages end up depends on everything mapped before
+//! them. Reserving a window by name with `VSpace::reserve_window` sets a
+//! range of addresses aside up front, and regions mapped into it with
+//! `VSpace::map_region_in_window` are laid out one after another from its
+//! start. Nothing else gets mapped over a window's addresses, by
+//! `map_region_at_addr` included, so the layout of a child's address space
+//! comes out the same however the mapping calls around it change.
+use core::fmt;
+
+use arrayvec::ArrayVec;
+
+use super::VSpaceError;
+
+/// The most windows a single vspace can have reserved
+pub const MAX_ADDRESS_WINDOWS: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AddressWindow {
+    pub name: &'static str,
+    pub start: usize,
+    pub size_bytes: usize,
+    /// How far into the window, from its start, regions have been mapped
+    pub used_bytes: usize,
+}
+
+impl AddressWindow {
+    pub fn end(&self) -> usize {
+        self.start + self.size_bytes
+    }
+
+    pub fn remaining_bytes(&self) -> usize {
+        self.size_bytes - self.used_bytes
+    }
+
+    pub fn contains(&self, vaddr: usize) -> bool {
+        self.start <= vaddr && vaddr < self.end()
+    }
+
+    fn overlaps(&self, start: usize, size_bytes: usize) -> bool {
+        start < self.end() && self.start < start.saturating_add(size_bytes)
+    }
+}
+
+impl fmt::Display for AddressWindow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "'{}' {:#010x}..{:#010x} ({} of {} bytes used)",
+            self.name,
+            self.start,
+            self.end(),
+            self.used_bytes,
+            self.size_bytes
+        )
+    }
+}
+
+/// The windows reserved in a vspace
+#[derive(Debug, Clone, Default)]
+pub(super) struct AddressWindows {
+    windows: ArrayVec<[AddressWindow; MAX_ADDRESS_WINDOWS]>,
+}
+
+impl AddressWindows {
+    pub(super) fn check_available(&self, name: &'static str) -> Result<(), VSpaceError> {
+        if self.find(name).is_some() {
+            return Err(VSpaceError::DuplicateAddressWindow);
+        }
+        if self.windows.len() == self.windows.capacity() {
+            return Err(VSpaceError::TooManyAddressWindows);
+        }
+        Ok(())
+    }
+
+    pub(super) fn insert(&mut self, window: AddressWindow) -> Result<(), VSpaceError> {
+        self.check_available(window.name)?;
+        self.windows.push(window);
+        Ok(())
+    }
+
+    pub(super) fn find(&self, name: &str) -> Option<&AddressWindow> {
+        self.windows.iter().find(|w| w.name == name)
+    }
+
+    pub(super) fn overlapping(&self, start: usize, size_bytes: usize) -> Option<&AddressWindow> {
+        self.windows.iter().find(|w| w.overlaps(start, size_bytes))
+    }
+
+    /// Take the next `size_bytes` of a window, returning where they start
+    pub(super) fn claim(&mut self, name: &str, size_bytes: usize) -> Result<usize, VSpaceError> {
+        let window = self
+            .windows
+            .iter_mut()
+            .find(|w| w.name == name)
+            .ok_or(VSpaceError::UnknownAddressWindow)?;
+        if size_bytes > window.remaining_bytes() {
+            return Err(VSpaceError::AddressWindowFull);
+        }
+        let start = window.start + window.used_bytes;
+        window.used_bytes += size_bytes;
+        Ok(start)
+    }
+
+    /// Give back a range taken from a window, if it was the last one
+    /// taken. Returns whether the range was in a window at all.
+    pub(super) fn release(&mut self, start: usize, size_bytes: usize) -> bool {
+        match self
+            .windows
+            .iter_mut()
+            .find(|w| w.overlaps(start, size_bytes))
+        {
+            Some(window) => {
+                if window.start + window.used_bytes == start + size_bytes {
+                    window.used_bytes -= size_bytes;
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub(super) fn iter(&self) -> impl Iterator<Item = &AddressWindow> {
+        self.windows.iter()
+    }
+}